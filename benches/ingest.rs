@@ -0,0 +1,28 @@
+//! Ingesting the entries of an initial sync on one thread, like the arbiter
+//! of the `DataRouter` did, and in batches on the verifier threads.
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use risq::testkit;
+
+fn ingest(c: &mut Criterion) {
+    let entries = testkit::signed_entries(5_000);
+    let mut group = c.benchmark_group("ingest/5000");
+    group.sample_size(10);
+    group.bench_function("one_thread", |b| {
+        b.iter_batched(
+            || entries.clone(),
+            testkit::ingest_on_this_thread,
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("pool", |b| {
+        b.iter_batched(
+            || entries.clone(),
+            testkit::ingest_on_pool,
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, ingest);
+criterion_main!(benches);
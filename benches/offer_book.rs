@@ -0,0 +1,78 @@
+//! Lookups of the offers of one market, maker or payment method in a big
+//! offer book, through the `OfferIndex` and by scanning the whole book.
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use risq::{testkit, OpenOffer};
+use std::collections::HashMap;
+
+fn book(size: usize) -> HashMap<String, OpenOffer> {
+    (0..size)
+        .map(testkit::open_offer)
+        .map(|offer| (String::from(offer.id.clone()), offer))
+        .collect()
+}
+
+fn market_lookup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("offers_of_market");
+    for size in [1_000, 5_000, 20_000].iter() {
+        let offers: Vec<OpenOffer> = (0..*size).map(testkit::open_offer).collect();
+        let by_hash: HashMap<_, _> = offers
+            .iter()
+            .map(|offer| (offer.bisq_hash, offer))
+            .collect();
+        let index = testkit::OfferIndex::new(offers.iter());
+        let pair = offers[0].market.pair.clone();
+        group.bench_with_input(BenchmarkId::new("scan", size), &pair, |b, pair| {
+            b.iter(|| {
+                by_hash
+                    .values()
+                    .filter(|offer| offer.market.pair == *pair)
+                    .count()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("index", size), &pair, |b, pair| {
+            b.iter(|| {
+                index
+                    .market(pair)
+                    .filter_map(|hash| by_hash.get(hash))
+                    .count()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn maker_lookup(c: &mut Criterion) {
+    let offers = book(20_000);
+    let index = testkit::OfferIndex::new(offers.values());
+    let maker = offers
+        .values()
+        .next()
+        .and_then(|offer| offer.payload.owner_node_address.clone())
+        .unwrap();
+    c.bench_function("offers_of_maker/index/20000", |b| {
+        b.iter(|| index.maker(black_box(&maker)).count())
+    });
+    c.bench_function("offers_of_maker/scan/20000", |b| {
+        b.iter(|| {
+            offers
+                .values()
+                .filter(|offer| offer.payload.owner_node_address.as_ref() == Some(&maker))
+                .count()
+        })
+    });
+}
+
+fn index_maintenance(c: &mut Criterion) {
+    let offers: Vec<OpenOffer> = (0..5_000).map(testkit::open_offer).collect();
+    let mut index = testkit::OfferIndex::new(offers.iter());
+    let offer = testkit::open_offer(5_000);
+    c.bench_function("index/insert_and_remove", |b| {
+        b.iter(|| {
+            index.insert(black_box(&offer));
+            index.remove(black_box(&offer));
+        })
+    });
+}
+
+criterion_group!(benches, market_lookup, maker_lookup, index_maintenance);
+criterion_main!(benches);
@@ -0,0 +1,164 @@
+// Fills the dashboard from the api of the node it is served by. Paths are
+// relative, so /mainnet/ shows the mainnet node of a process running several
+// networks.
+(function () {
+  'use strict';
+
+  var STATUS_INTERVAL = 5000;
+  var ALERTS_INTERVAL = 60000;
+  // Offer changes arrive in bursts, the counts are fetched once they settle
+  var MARKETS_DELAY = 2000;
+  var MAX_CHANGES = 50;
+
+  var marketsTimer = null;
+
+  function $(id) {
+    return document.getElementById(id);
+  }
+
+  function get(path) {
+    return fetch(path, { credentials: 'same-origin' }).then(function (res) {
+      if (res.status === 401) {
+        $('login').hidden = false;
+        throw new Error('Unauthorized');
+      }
+      if (!res.ok) {
+        throw new Error(path + ': ' + res.status);
+      }
+      return res.json();
+    });
+  }
+
+  function row(cells) {
+    var tr = document.createElement('tr');
+    cells.forEach(function (cell) {
+      var td = document.createElement('td');
+      td.textContent = cell;
+      tr.appendChild(td);
+    });
+    return tr;
+  }
+
+  function replace(parent, children) {
+    while (parent.firstChild) {
+      parent.removeChild(parent.firstChild);
+    }
+    children.forEach(function (child) {
+      parent.appendChild(child);
+    });
+  }
+
+  function duration(secs) {
+    var days = Math.floor(secs / 86400);
+    var hours = Math.floor((secs % 86400) / 3600);
+    var minutes = Math.floor((secs % 3600) / 60);
+    return (days ? days + 'd ' : '') + hours + 'h ' + minutes + 'm';
+  }
+
+  function showStatus(status) {
+    $('address').textContent = status.address || '';
+    $('state').textContent = status.state;
+    $('sync').textContent = status.sync;
+    $('progress').value = status.progress;
+    $('uptime').textContent = duration(status.uptime_secs);
+    $('message-version').textContent = status.message_version;
+    $('tor').textContent = status.tor
+      ? (status.tor.reachable ? (status.tor.summary || 'reachable') : 'unreachable')
+      : 'not used';
+    var peers = Object.keys(status.connections).map(function (id) {
+      return status.connections[id];
+    });
+    peers.sort(function (a, b) {
+      return (a.addr || '').localeCompare(b.addr || '');
+    });
+    $('peer-count').textContent = peers.length;
+    replace($('peers'), peers.map(function (peer) {
+      return row([
+        peer.addr || 'inbound',
+        peer.kind,
+        peer.round_trip_ms === null ? '-' : peer.round_trip_ms + ' ms'
+      ]);
+    }));
+  }
+
+  function showMarkets(markets) {
+    var total = markets.reduce(function (sum, market) {
+      return sum + market.offers;
+    }, 0);
+    $('offer-count').textContent = total;
+    replace($('markets'), markets.map(function (market) {
+      return row([market.market, market.offers, market.buy, market.sell]);
+    }));
+  }
+
+  function showAlerts(alerts) {
+    $('alerts-section').hidden = alerts.length === 0;
+    replace($('alerts'), alerts.map(function (alert) {
+      var li = document.createElement('li');
+      li.textContent = alert.message + ' (' + alert.version + ')' +
+        (alert.requires_update ? ', update required' : '');
+      return li;
+    }));
+  }
+
+  function showChange(change) {
+    var li = document.createElement('li');
+    li.textContent = new Date().toLocaleTimeString() + ' ' +
+      change.event.replace('offer_', '') + ' ' + change.market + ' ' + change.id;
+    var changes = $('changes');
+    changes.insertBefore(li, changes.firstChild);
+    while (changes.childNodes.length > MAX_CHANGES) {
+      changes.removeChild(changes.lastChild);
+    }
+  }
+
+  function refreshStatus() {
+    get('status').then(showStatus).catch(console.warn);
+  }
+
+  function refreshMarkets() {
+    marketsTimer = null;
+    get('markets').then(showMarkets).catch(console.warn);
+  }
+
+  function refreshAlerts() {
+    get('alerts').then(showAlerts).catch(console.warn);
+  }
+
+  function connect() {
+    var url = new URL('ws', window.location.href);
+    url.protocol = url.protocol === 'https:' ? 'wss:' : 'ws:';
+    var socket = new WebSocket(url.href);
+    socket.onopen = function () {
+      $('live').textContent = 'live';
+      $('live').classList.add('live');
+    };
+    socket.onmessage = function (msg) {
+      var change = JSON.parse(msg.data);
+      showChange(change);
+      if (change.event !== 'offer_refreshed' && marketsTimer === null) {
+        marketsTimer = setTimeout(refreshMarkets, MARKETS_DELAY);
+      }
+    };
+    socket.onclose = function () {
+      $('live').textContent = 'offline';
+      $('live').classList.remove('live');
+      setTimeout(connect, STATUS_INTERVAL);
+    };
+  }
+
+  $('login').addEventListener('submit', function (event) {
+    event.preventDefault();
+    // Sent with every request, the WebSocket included
+    document.cookie = 'risq-token=' + encodeURIComponent($('token').value) +
+      '; path=/; SameSite=Strict';
+    window.location.reload();
+  });
+
+  refreshStatus();
+  refreshMarkets();
+  refreshAlerts();
+  setInterval(refreshStatus, STATUS_INTERVAL);
+  setInterval(refreshAlerts, ALERTS_INTERVAL);
+  connect();
+})();
@@ -7,6 +7,12 @@ fn main() {
 }
 
 fn generate_protocols() {
+    // Writes an empty io.bisq.protobuffer.rs for the imported bisq messages,
+    // so it has to run before they are generated
+    prost_build::Config::new()
+        .extern_path(".io.bisq.protobuffer", "crate::bisq::payload")
+        .compile_protos(&["proto/risq/risq.proto".to_string()], &protocol_includes())
+        .expect("Error compiling risq api definitions");
     let mut config = prost_build::Config::new();
     config
         .type_attribute("NodeAddress", "#[derive(Eq, Hash)]")
@@ -49,7 +55,11 @@ fn generated_files() -> Vec<PathBuf> {
 }
 
 fn protocol_includes() -> Vec<String> {
-    vec!["proto/custom".to_string(), "proto/bisq".to_string()]
+    vec![
+        "proto/custom".to_string(),
+        "proto/bisq".to_string(),
+        "proto/risq".to_string(),
+    ]
 }
 
 fn protocol_files() -> Vec<String> {
@@ -0,0 +1,45 @@
+/*
+ * Embedding risq from C, build the library with `make build-ffi`.
+ *
+ * Strings passed to risq are NUL terminated UTF-8, strings returned by risq
+ * must be released with risq_string_free.
+ */
+#ifndef RISQ_H
+#define RISQ_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct RisqNode RisqNode;
+
+/* Called from a thread of risq with an event as JSON, eg.
+ * {"event":"offer_added","id":..}. The string is only valid during the call. */
+typedef void (*RisqEventCallback)(const char *event, void *user_data);
+
+/* Starts a node of `network` ("BtcMainnet", "BtcTestnet" or "BtcRegtest")
+ * keeping its data in `risq_home`, without tor when `tor_proxy_port` is 0.
+ * Returns NULL if the node couldn't be started. */
+RisqNode *risq_start(const char *network, const char *risq_home, uint16_t api_port,
+                     uint16_t server_port, uint16_t tor_proxy_port);
+
+/* The open offers as JSON array, of one market (eg. "btc_eur") or of all
+ * markets when `market` is NULL. */
+char *risq_offers(const RisqNode *node, const char *market);
+
+/* Calls `callback` with every event of the node until risq_stop.
+ * Returns 0 on success. */
+int32_t risq_subscribe(RisqNode *node, RisqEventCallback callback, void *user_data);
+
+/* Stops and frees the node, no callbacks are running once it returns. */
+void risq_stop(RisqNode *node);
+
+void risq_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
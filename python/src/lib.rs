@@ -0,0 +1,166 @@
+// The functions generated by pyo3 convert the PyErr they return into itself
+#![allow(clippy::useless_conversion)]
+
+use pyo3::{
+    exceptions::{PyConnectionError, PyValueError},
+    prelude::*,
+    types::PyDict,
+};
+use reqwest::{Client as HttpClient, RequestBuilder, Url};
+use risq::cli::decode::{decode as decode_bytes, input_bytes, DecodeKind, Decoded};
+use std::str::FromStr;
+
+/// Captured data as bytes or as hex / base64 like `risq decode` takes it.
+#[derive(FromPyObject)]
+enum Input {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+fn decode_input(input: Input, kind: &str) -> PyResult<Decoded> {
+    let kind = DecodeKind::from_str(kind)
+        .map_err(|e| PyValueError::new_err(format!("kind must be one of {}", e)))?;
+    let bytes = match input {
+        Input::Text(text) => input_bytes(&text).map_err(PyValueError::new_err)?,
+        Input::Bytes(bytes) => bytes,
+    };
+    decode_bytes(kind, &bytes).map_err(PyValueError::new_err)
+}
+
+fn to_python(py: Python, json: &str) -> PyResult<PyObject> {
+    Ok(py
+        .import_bound("json")?
+        .call_method1("loads", (json,))?
+        .unbind())
+}
+
+/// Decodes a `NetworkEnvelope` or `StoragePayload` into a dict with the
+/// same fields as `risq decode --json`.
+#[pyfunction]
+#[pyo3(signature = (data, kind = "envelope"))]
+fn decode(py: Python, data: Input, kind: &str) -> PyResult<PyObject> {
+    let decoded = decode_input(data, kind)?;
+    let json = serde_json::to_string(&decoded).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    to_python(py, &json)
+}
+
+/// Whether the envelope carries storage entries and all of them are signed
+/// by their owner.
+#[pyfunction]
+#[pyo3(signature = (data, kind = "envelope"))]
+fn verify(data: Input, kind: &str) -> PyResult<bool> {
+    Ok(decode_input(data, kind)?.signatures_valid())
+}
+
+/// Thin client for the api of a running `risq daemon`, responses are
+/// returned as parsed JSON.
+#[pyclass]
+struct Client {
+    url: Url,
+    client: HttpClient,
+}
+
+#[pymethods]
+impl Client {
+    #[new]
+    #[pyo3(signature = (api_port = 7477))]
+    fn new(api_port: u16) -> Self {
+        Self {
+            url: format!("http://127.0.0.1:{}", api_port)
+                .parse()
+                .expect("Couldn't build api url"),
+            client: HttpClient::new(),
+        }
+    }
+
+    /// `GET` any path of the api, eg. `/trades/mine`.
+    fn get(&self, py: Python, path: &str) -> PyResult<PyObject> {
+        let url = self.join(path)?;
+        self.send(py, self.client.get(url))
+    }
+
+    /// `POST` a dict (or anything `json.dumps` takes) to a path of the api.
+    #[pyo3(signature = (path, body = None))]
+    fn post(&self, py: Python, path: &str, body: Option<Bound<PyAny>>) -> PyResult<PyObject> {
+        let url = self.join(path)?;
+        let mut request = self.client.post(url);
+        if let Some(body) = body {
+            let body: String = py
+                .import_bound("json")?
+                .call_method1("dumps", (body,))?
+                .extract()?;
+            request = request
+                .header("Content-Type", "application/json")
+                .body(body);
+        }
+        self.send(py, request)
+    }
+
+    /// Runs a query against `/graphql` and returns its `data`.
+    #[pyo3(signature = (query, variables = None))]
+    fn graphql(
+        &self,
+        py: Python,
+        query: &str,
+        variables: Option<Bound<PyDict>>,
+    ) -> PyResult<PyObject> {
+        let body = PyDict::new_bound(py);
+        body.set_item("query", query)?;
+        body.set_item(
+            "variables",
+            variables.unwrap_or_else(|| PyDict::new_bound(py)),
+        )?;
+        let response = self.post(py, "/graphql", Some(body.into_any()))?;
+        response.bind(py).get_item("data").map(Bound::unbind)
+    }
+
+    fn status(&self, py: Python) -> PyResult<PyObject> {
+        self.get(py, "/status")
+    }
+
+    fn trades(&self, py: Python) -> PyResult<PyObject> {
+        self.get(py, "/trades")
+    }
+
+    fn my_trades(&self, py: Python) -> PyResult<PyObject> {
+        self.get(py, "/trades/mine")
+    }
+
+    fn fees(&self, py: Python) -> PyResult<PyObject> {
+        self.get(py, "/fees")
+    }
+
+    fn offer_anomalies(&self, py: Python) -> PyResult<PyObject> {
+        self.get(py, "/offers/anomalies")
+    }
+}
+
+impl Client {
+    fn join(&self, path: &str) -> PyResult<Url> {
+        self.url
+            .join(path)
+            .map_err(|e| PyValueError::new_err(format!("Invalid path {}: {}", path, e)))
+    }
+
+    fn send(&self, py: Python, request: RequestBuilder) -> PyResult<PyObject> {
+        let text = py
+            .allow_threads(|| {
+                request
+                    .send()
+                    .and_then(|response| response.error_for_status())
+                    .and_then(|mut response| response.text())
+            })
+            .map_err(|e| PyConnectionError::new_err(e.to_string()))?;
+        to_python(py, &text)
+    }
+}
+
+/// Work with captured bisq data and a running risq daemon from python.
+#[pymodule]
+#[pyo3(name = "risq")]
+fn risq_python(module: &Bound<PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(decode, module)?)?;
+    module.add_function(wrap_pyfunction!(verify, module)?)?;
+    module.add_class::<Client>()?;
+    Ok(())
+}
@@ -0,0 +1,327 @@
+use actix_web::http::{
+    header::{self, HeaderMap, HeaderValue},
+    Method,
+};
+use percent_encoding::percent_decode_str;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+const COOKIE_TOKEN_LEN: usize = 32;
+/// The name of the HTTP cookie a browser can send the token in
+const TOKEN_COOKIE: &str = "risq-token";
+
+/// Who may use the api. Requests that change something, and everything
+/// under `/admin`, need a token even from localhost: the one of the cookie
+/// file the cli reads or the one configured. With a configured token every
+/// request but `/ping` and the dashboard needs one.
+#[derive(Clone)]
+pub struct ApiAuth {
+    cookie: String,
+    /// Changes when the config file is reloaded
+    token: Arc<RwLock<Option<String>>>,
+    /// `*` allows every origin
+    cors_origins: Arc<Vec<String>>,
+}
+impl ApiAuth {
+    /// Writes a new random token to `cookie_path`, only readable by us.
+    pub fn new(
+        token: Option<String>,
+        cors_origins: Vec<String>,
+        cookie_path: &Path,
+    ) -> io::Result<Self> {
+        let cookie: String = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(COOKIE_TOKEN_LEN)
+            .collect();
+        if let Some(dir) = cookie_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        write_cookie(cookie_path, &cookie)?;
+        Ok(Self {
+            cookie,
+            token: Arc::new(RwLock::new(token)),
+            cors_origins: Arc::new(cors_origins),
+        })
+    }
+
+    /// An empty token only leaves the one of the cookie file.
+    pub fn set_token(&self, token: String) {
+        *self.token.write().expect("Corrupted lock in api auth") =
+            Some(token).filter(|token| !token.is_empty());
+    }
+
+    pub fn authorized(&self, method: &Method, path: &str, headers: &HeaderMap) -> bool {
+        let configured = self.token.read().expect("Corrupted lock in api auth");
+        let needs_token = if configured.is_some() {
+            !public(path)
+        } else {
+            changes_state(method, path)
+        };
+        // Preflight requests don't carry credentials
+        if !needs_token || *method == Method::OPTIONS {
+            return true;
+        }
+        match request_token(headers) {
+            Some(token) => {
+                same_token(&token, &self.cookie)
+                    || configured
+                        .as_ref()
+                        .map(|configured| same_token(&token, configured))
+                        .unwrap_or(false)
+            }
+            None => false,
+        }
+    }
+
+    /// The `Access-Control-Allow-Origin` of a request from another origin,
+    /// `*` when every origin is allowed.
+    pub fn allowed_origin(&self, headers: &HeaderMap) -> Option<HeaderValue> {
+        let origin = headers.get(header::ORIGIN)?;
+        if self.cors_origins.iter().any(|allowed| allowed == "*") {
+            Some(HeaderValue::from_static("*"))
+        } else if self
+            .cors_origins
+            .iter()
+            .any(|allowed| origin.as_bytes() == allowed.as_bytes())
+        {
+            Some(origin.clone())
+        } else {
+            None
+        }
+    }
+}
+
+/// Adds the CORS headers to the response to an allowed origin, `preflight`
+/// ones only to the answers of `OPTIONS` requests. Any origin may only send
+/// the token as a header, cookies are reserved to the listed ones.
+pub fn allow_origin(headers: &mut HeaderMap, origin: HeaderValue, preflight: bool) {
+    if origin != "*" {
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+            HeaderValue::from_static("true"),
+        );
+        headers.insert(header::VARY, HeaderValue::from_static("Origin"));
+    }
+    headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    if preflight {
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_METHODS,
+            HeaderValue::from_static("GET, POST, PUT, DELETE"),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_HEADERS,
+            HeaderValue::from_static("Authorization, Content-Type"),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_MAX_AGE,
+            HeaderValue::from_static("3600"),
+        );
+    }
+}
+
+/// The path of a request to the routes under the prefix of a network, like
+/// the same request to the root.
+pub fn unprefixed<'a>(path: &'a str, prefix: &str) -> &'a str {
+    if path.starts_with(prefix) && path[prefix.len()..].starts_with('/') {
+        &path[prefix.len()..]
+    } else {
+        path
+    }
+}
+
+// The dashboard asks for the token itself, its files hold nothing of the node
+fn public(path: &str) -> bool {
+    path == "/ping" || path == "/" || path.starts_with("/dashboard/")
+}
+
+fn changes_state(method: &Method, path: &str) -> bool {
+    let reads = match *method {
+        Method::GET | Method::HEAD | Method::OPTIONS => true,
+        // Queries to /graphql are sent with POST but don't change anything
+        Method::POST => path == "/graphql",
+        _ => false,
+    };
+    !reads || path.starts_with("/admin/") || path == "/shutdown"
+}
+
+fn request_token(headers: &HeaderMap) -> Option<String> {
+    if let Some(authorization) = headers.get(header::AUTHORIZATION) {
+        let mut parts = authorization.to_str().ok()?.splitn(2, ' ');
+        if let (Some("Bearer"), Some(token)) = (parts.next(), parts.next()) {
+            return Some(token.trim().to_string());
+        }
+    }
+    headers
+        .get_all(header::COOKIE)
+        .filter_map(|cookies| cookies.to_str().ok())
+        .flat_map(|cookies| cookies.split(';'))
+        .filter_map(|cookie| {
+            let mut parts = cookie.trim().splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                // The dashboard percent-encodes what it writes to the cookie
+                (Some(TOKEN_COOKIE), Some(token)) => percent_decode_str(token)
+                    .decode_utf8()
+                    .ok()
+                    .map(String::from),
+                _ => None,
+            }
+        })
+        .next()
+}
+
+// Compared in constant time so the token can't be guessed byte by byte
+fn same_token(given: &str, token: &str) -> bool {
+    given.len() == token.len() && openssl::memcmp::eq(given.as_bytes(), token.as_bytes())
+}
+
+// Created anew with its permissions, so the token is never readable by others
+fn write_cookie(path: &Path, cookie: &str) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => (),
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(cookie.as_bytes())
+}
+
+/// The token of the cookie file of a running daemon.
+pub fn read_cookie(path: &Path) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|cookie| cookie.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    // Every test writes a cookie file of its own, they run in parallel
+    fn api_auth(test: &str, token: Option<&str>) -> ApiAuth {
+        let path = env::temp_dir().join(format!("risq-api-cookie-{}-{}", test, process::id()));
+        let auth = ApiAuth::new(
+            token.map(String::from),
+            vec!["https://risq.example".into()],
+            &path,
+        )
+        .unwrap();
+        assert_eq!(read_cookie(&path).as_ref(), Some(&auth.cookie));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        fs::remove_file(path).unwrap();
+        auth
+    }
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {}", token)).unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn mutating_requests_need_a_token() {
+        let auth = api_auth("mutating", None);
+        let none = HeaderMap::new();
+        assert!(auth.authorized(&Method::GET, "/offers", &none));
+        assert!(auth.authorized(&Method::POST, "/graphql", &none));
+        assert!(!auth.authorized(&Method::POST, "/offers", &none));
+        assert!(!auth.authorized(&Method::GET, "/admin/log", &none));
+        assert!(!auth.authorized(&Method::DELETE, "/offers/1", &bearer("wrong")));
+        assert!(auth.authorized(&Method::DELETE, "/offers/1", &bearer(&auth.cookie)));
+    }
+
+    #[test]
+    fn takes_the_token_from_a_cookie() {
+        let auth = api_auth("cookie", None);
+        let mut cookie = HeaderMap::new();
+        cookie.insert(
+            header::COOKIE,
+            HeaderValue::from_str(&format!("theme=dark; risq-token={}", auth.cookie)).unwrap(),
+        );
+        assert!(auth.authorized(&Method::POST, "/shutdown", &cookie));
+
+        let auth = api_auth("cookie-encoded", Some("s3cret;=%"));
+        cookie.insert(
+            header::COOKIE,
+            HeaderValue::from_static("risq-token=s3cret%3B%3D%25"),
+        );
+        assert!(auth.authorized(&Method::POST, "/shutdown", &cookie));
+    }
+
+    #[test]
+    fn a_configured_token_guards_all_but_public_paths() {
+        let auth = api_auth("configured", Some("secret"));
+        let none = HeaderMap::new();
+        assert!(!auth.authorized(&Method::GET, "/offers", &none));
+        assert!(auth.authorized(&Method::GET, "/ping", &none));
+        assert!(auth.authorized(&Method::GET, "/dashboard/dashboard.js", &none));
+        assert!(auth.authorized(&Method::GET, "/offers", &bearer("secret")));
+        assert!(auth.authorized(&Method::POST, "/offers", &bearer("secret")));
+    }
+
+    #[test]
+    fn strips_the_network_prefix_of_paths() {
+        assert_eq!(unprefixed("/mainnet/ping", "/mainnet"), "/ping");
+        assert_eq!(unprefixed("/mainnetx/ping", "/mainnet"), "/mainnetx/ping");
+        assert_eq!(
+            unprefixed("/testnet/admin/log", "/mainnet"),
+            "/testnet/admin/log"
+        );
+    }
+
+    #[test]
+    fn rotates_the_token() {
+        let auth = api_auth("rotation", Some("secret"));
+        let none = HeaderMap::new();
+        auth.set_token("rotated".into());
+        assert!(!auth.authorized(&Method::GET, "/offers", &bearer("secret")));
+        assert!(auth.authorized(&Method::GET, "/offers", &bearer("rotated")));
+        auth.set_token(String::new());
+        assert!(auth.authorized(&Method::GET, "/offers", &none));
+    }
+
+    #[test]
+    fn allows_the_configured_origins() {
+        let auth = api_auth("cors", None);
+        let mut origin = HeaderMap::new();
+        origin.insert(
+            header::ORIGIN,
+            HeaderValue::from_static("https://evil.example"),
+        );
+        assert_eq!(auth.allowed_origin(&origin), None);
+        origin.insert(
+            header::ORIGIN,
+            HeaderValue::from_static("https://risq.example"),
+        );
+        assert!(auth.allowed_origin(&origin).is_some());
+
+        let path = env::temp_dir().join(format!("risq-api-cookie-any-{}", process::id()));
+        let any = ApiAuth::new(None, vec!["*".into()], &path).unwrap();
+        fs::remove_file(path).unwrap();
+        let allowed = any.allowed_origin(&origin).unwrap();
+        assert_eq!(allowed, "*");
+        let mut headers = HeaderMap::new();
+        allow_origin(&mut headers, allowed, false);
+        assert!(headers
+            .get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+            .is_none());
+    }
+}
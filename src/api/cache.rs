@@ -0,0 +1,146 @@
+//! Conditional requests for the responses clients poll the most. Their
+//! ETag is made of the versions of the state they are computed from, so a
+//! client that has the current one gets a `304` and everybody else the body
+//! serialized by the first request after the state changed.
+use actix_web::{http::header, HttpRequest, HttpResponse};
+use bytes::Bytes;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Bodies kept at most, one per path and query.
+const MAX_ENTRIES: usize = 512;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ETag(String);
+impl ETag {
+    pub fn new(versions: &[u64]) -> Self {
+        let versions: Vec<_> = versions.iter().map(|v| format!("{:x}", v)).collect();
+        ETag(format!("\"{}\"", versions.join("-")))
+    }
+
+    /// Whether the client already has the response, it names the tag in
+    /// `If-None-Match`.
+    pub fn matches(&self, req: &HttpRequest) -> bool {
+        req.headers()
+            .get_all(header::IF_NONE_MATCH)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.trim_start_matches("W/") == self.0)
+    }
+}
+
+/// The number of `period`s since the epoch, for responses that also change
+/// with time, like the statistics of the last 24 hours.
+pub fn time_bucket(period: Duration) -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        / period.as_secs().max(1)
+}
+
+#[derive(Clone, Default)]
+pub struct ResponseCache {
+    bodies: Arc<Mutex<HashMap<String, (ETag, Bytes)>>>,
+}
+impl ResponseCache {
+    /// A `304` when the client is up to date, the cached body when it was
+    /// made from the same state. `None` when the body has to be computed.
+    pub fn lookup(&self, req: &HttpRequest, etag: &ETag) -> Option<HttpResponse> {
+        if etag.matches(req) {
+            return Some(not_modified(etag));
+        }
+        match self
+            .bodies
+            .lock()
+            .expect("Corrupted lock in response cache")
+            .get(&key(req))
+        {
+            Some((cached, body)) if cached == etag => Some(ok(etag, body.clone())),
+            _ => None,
+        }
+    }
+
+    /// Serializes `body` and keeps it for the requests to come until
+    /// `etag` changes.
+    pub fn respond(&self, req: &HttpRequest, etag: ETag, body: &impl Serialize) -> HttpResponse {
+        let body = match serde_json::to_vec(body) {
+            Ok(body) => Bytes::from(body),
+            Err(e) => {
+                return HttpResponse::InternalServerError()
+                    .json(serde_json::json!({ "error": e.to_string() }))
+            }
+        };
+        let mut bodies = self
+            .bodies
+            .lock()
+            .expect("Corrupted lock in response cache");
+        if bodies.len() >= MAX_ENTRIES {
+            bodies.retain(|_, (cached, _)| *cached == etag);
+            if bodies.len() >= MAX_ENTRIES {
+                bodies.clear();
+            }
+        }
+        bodies.insert(key(req), (etag.clone(), body.clone()));
+        ok(&etag, body)
+    }
+}
+
+fn key(req: &HttpRequest) -> String {
+    req.uri().to_string()
+}
+
+fn ok(etag: &ETag, body: Bytes) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .header(header::ETAG, etag.0.as_str())
+        .body(body)
+}
+
+fn not_modified(etag: &ETag) -> HttpResponse {
+    HttpResponse::NotModified()
+        .header(header::ETAG, etag.0.as_str())
+        .finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test::TestRequest};
+
+    #[test]
+    fn answers_from_the_cache_until_the_state_changes() {
+        let cache = ResponseCache::default();
+        let etag = ETag::new(&[10, 2]);
+        assert_eq!(etag, ETag("\"a-2\"".into()));
+        let req = TestRequest::with_uri("/offers?currency=EUR").to_http_request();
+        assert!(cache.lookup(&req, &etag).is_none());
+        let response = cache.respond(&req, etag.clone(), &vec!["offer"]);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get(header::ETAG).unwrap(), "\"a-2\"");
+
+        assert_eq!(
+            cache.lookup(&req, &etag).map(|response| response.status()),
+            Some(StatusCode::OK)
+        );
+        let other_query = TestRequest::with_uri("/offers?currency=USD").to_http_request();
+        assert!(cache.lookup(&other_query, &etag).is_none());
+        assert!(cache.lookup(&req, &ETag::new(&[11, 2])).is_none());
+
+        let conditional = TestRequest::with_uri("/offers?currency=EUR")
+            .header(header::IF_NONE_MATCH, "\"9-2\", W/\"a-2\"")
+            .to_http_request();
+        assert_eq!(
+            cache
+                .lookup(&conditional, &etag)
+                .map(|response| response.status()),
+            Some(StatusCode::NOT_MODIFIED)
+        );
+        assert!(cache.lookup(&conditional, &ETag::new(&[11, 2])).is_none());
+    }
+}
@@ -32,12 +32,22 @@ impl GraphQLQuery {
 }
 
 impl GrqphQLClient {
-    pub fn new(api_port: u16) -> Self {
+    /// `token` is sent with every request when the api requires one.
+    pub fn new(api_port: u16, token: Option<String>) -> Self {
+        let mut headers = header::HeaderMap::new();
+        if let Some(value) =
+            token.and_then(|token| header::HeaderValue::from_str(&format!("Bearer {}", token)).ok())
+        {
+            headers.insert(header::AUTHORIZATION, value);
+        }
         Self {
             url: format!("http://127.0.0.1:{}/graphql", api_port)
                 .parse()
                 .unwrap(),
-            client: Client::new(),
+            client: Client::builder()
+                .default_headers(headers)
+                .build()
+                .expect("Couldn't build api client"),
         }
     }
     pub fn query<T: WithQueryFields>(&self, variables: HashMap<String, String>) -> Result<T> {
@@ -49,4 +59,150 @@ impl GrqphQLClient {
             .json()?;
         Ok(response.data)
     }
+    pub fn status<T: DeserializeOwned>(&self) -> Result<T> {
+        let url = self.url.join("/status").expect("Couldn't build status url");
+        self.client.get(url).send()?.json()
+    }
+    pub fn log_filters<T: DeserializeOwned>(&self) -> Result<T> {
+        let url = self.url.join("/admin/log").expect("Couldn't build log url");
+        self.client.get(url).send()?.error_for_status()?.json()
+    }
+    pub fn set_log_filters<B: Serialize, T: DeserializeOwned>(&self, filters: &B) -> Result<T> {
+        let url = self.url.join("/admin/log").expect("Couldn't build log url");
+        self.client
+            .put(url)
+            .json(filters)
+            .send()?
+            .error_for_status()?
+            .json()
+    }
+    pub fn trades<T: DeserializeOwned>(&self) -> Result<T> {
+        let url = self.url.join("/trades").expect("Couldn't build trades url");
+        self.client.get(url).send()?.error_for_status()?.json()
+    }
+    pub fn my_trades<T: DeserializeOwned>(&self) -> Result<T> {
+        let url = self
+            .url
+            .join("/trades/mine")
+            .expect("Couldn't build trade history url");
+        self.client.get(url).send()?.error_for_status()?.json()
+    }
+    pub fn my_trades_csv(&self) -> Result<String> {
+        let url = self
+            .url
+            .join("/trades/mine?format=csv")
+            .expect("Couldn't build trade history url");
+        self.client.get(url).send()?.error_for_status()?.text()
+    }
+    pub fn wallet<T: DeserializeOwned>(&self) -> Result<T> {
+        let url = self.url.join("/wallet").expect("Couldn't build wallet url");
+        self.client.get(url).send()?.json()
+    }
+    pub fn fees<T: DeserializeOwned>(&self) -> Result<T> {
+        let url = self.url.join("/fees").expect("Couldn't build fees url");
+        self.client.get(url).send()?.json()
+    }
+    pub fn trade_fees<T: DeserializeOwned>(&self, amount: &str) -> Result<T> {
+        let url = self
+            .url
+            .join(&format!("/fees/trade?amount={}", amount))
+            .expect("Couldn't build trade fees url");
+        self.client.get(url).send()?.json()
+    }
+    pub fn confirm_payment<T: DeserializeOwned>(&self, trade_id: &str) -> Result<T> {
+        let url = self
+            .url
+            .join(&format!("/trades/{}/confirm", trade_id))
+            .expect("Couldn't build confirm url");
+        self.client.post(url).send()?.json()
+    }
+    pub fn accept_mediation<T: DeserializeOwned>(&self, trade_id: &str) -> Result<T> {
+        let url = self
+            .url
+            .join(&format!("/trades/{}/mediation", trade_id))
+            .expect("Couldn't build mediation url");
+        self.client.post(url).send()?.json()
+    }
+    pub fn publish_delayed_payout<T: DeserializeOwned>(&self, trade_id: &str) -> Result<T> {
+        let url = self
+            .url
+            .join(&format!("/trades/{}/refund", trade_id))
+            .expect("Couldn't build refund url");
+        self.client.post(url).send()?.json()
+    }
+    pub fn payment_accounts<T: DeserializeOwned>(&self) -> Result<T> {
+        let url = self
+            .url
+            .join("/payment_accounts")
+            .expect("Couldn't build payment accounts url");
+        self.client.get(url).send()?.error_for_status()?.json()
+    }
+    pub fn create_payment_account<B: Serialize, T: DeserializeOwned>(
+        &self,
+        request: &B,
+    ) -> Result<T> {
+        let url = self
+            .url
+            .join("/payment_accounts")
+            .expect("Couldn't build payment accounts url");
+        self.client.post(url).json(request).send()?.json()
+    }
+    pub fn dao_status<T: DeserializeOwned>(&self) -> Result<T> {
+        let url = self
+            .url
+            .join("/dao/status")
+            .expect("Couldn't build dao status url");
+        self.client.get(url).send()?.json()
+    }
+    pub fn bsq_supply<T: DeserializeOwned>(&self) -> Result<T> {
+        let url = self
+            .url
+            .join("/dao/supply")
+            .expect("Couldn't build bsq supply url");
+        self.client.get(url).send()?.json()
+    }
+    pub fn bsq_balance<T: DeserializeOwned>(&self, address: &str) -> Result<T> {
+        let url = self
+            .url
+            .join(&format!("/dao/balance/{}", address))
+            .expect("Couldn't build bsq balance url");
+        self.client.get(url).send()?.json()
+    }
+    pub fn dao_cycles<T: DeserializeOwned>(&self) -> Result<T> {
+        let url = self
+            .url
+            .join("/dao/cycles")
+            .expect("Couldn't build dao cycles url");
+        self.client.get(url).send()?.json()
+    }
+    pub fn offer_anomalies<T: DeserializeOwned>(&self) -> Result<T> {
+        let url = self
+            .url
+            .join("/offers/anomalies")
+            .expect("Couldn't build offer anomalies url");
+        self.client.get(url).send()?.json()
+    }
+    pub fn chat<T: DeserializeOwned>(&self, trade_id: &str) -> Result<T> {
+        let url = self
+            .url
+            .join(&format!("/trades/{}/chat", trade_id))
+            .expect("Couldn't build chat url");
+        self.client.get(url).send()?.json()
+    }
+    pub fn send_chat<B: Serialize, T: DeserializeOwned>(
+        &self,
+        trade_id: &str,
+        request: &B,
+    ) -> Result<T> {
+        let url = self
+            .url
+            .join(&format!("/trades/{}/chat", trade_id))
+            .expect("Couldn't build chat url");
+        self.client.post(url).json(request).send()?.json()
+    }
+    // Rejected requests still carry an `{"error": ..}` body worth showing
+    pub fn take_offer<B: Serialize, T: DeserializeOwned>(&self, request: &B) -> Result<T> {
+        let url = self.url.join("/trades").expect("Couldn't build trades url");
+        self.client.post(url).json(request).send()?.json()
+    }
 }
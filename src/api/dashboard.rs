@@ -0,0 +1,52 @@
+//! The dashboard at `/`, one page showing the sync state, peers, offers per
+//! market and alerts of the node. It only uses the json endpoints and `/ws`
+//! like any other client, its files are built into the binary from
+//! `dashboard/`.
+use actix_web::{http::header, web, HttpResponse};
+use include_dir::{include_dir, Dir};
+
+static ASSETS: Dir = include_dir!("dashboard");
+
+pub fn index() -> HttpResponse {
+    serve("index.html")
+}
+
+pub fn asset(file: web::Path<String>) -> HttpResponse {
+    serve(&file)
+}
+
+fn serve(path: &str) -> HttpResponse {
+    match ASSETS.get_file(path) {
+        Some(file) => HttpResponse::Ok()
+            .content_type(content_type(path))
+            // They change with the binary only
+            .header(header::CACHE_CONTROL, "no-cache")
+            .body(file.contents()),
+        None => HttpResponse::NotFound()
+            .json(serde_json::json!({ "error": format!("No dashboard file {}", path) })),
+    }
+}
+
+fn content_type(path: &str) -> &'static str {
+    match path.rsplit('.').next() {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js") => "application/javascript; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serves_the_embedded_files() {
+        assert!(ASSETS.get_file("index.html").is_some());
+        assert!(ASSETS.get_file("dashboard.js").is_some());
+        assert!(ASSETS.get_file("missing.js").is_none());
+        assert_eq!(content_type("dashboard.css"), "text/css; charset=utf-8");
+        assert_eq!(content_type("risq.toml"), "application/octet-stream");
+    }
+}
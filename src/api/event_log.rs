@@ -0,0 +1,115 @@
+use crate::{
+    events::{Event, Events},
+    rotating_file::{RotatingFile, RotationConfig},
+};
+use serde_json::Value;
+use std::{
+    io,
+    sync::mpsc::Receiver,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+
+pub type EventLogConfig = RotationConfig;
+
+/// Appends every event as a line of JSON to the log, with the time it
+/// was published in milliseconds under `timestamp`. While the file can't be
+/// written events are kept and written once it can again.
+pub fn publish(config: EventLogConfig, events: &Events) {
+    let events = events.subscribe();
+    thread::spawn(move || {
+        info!("Writing events to {}", config.path.display());
+        let mut pending = None;
+        loop {
+            match EventLog::open(config.clone()).and_then(|log| log.write(&events, &mut pending)) {
+                Ok(()) => return,
+                Err(e) => warn!("Couldn't write events to {}: {}", config.path.display(), e),
+            }
+            thread::sleep(RETRY_DELAY);
+        }
+    });
+}
+
+struct EventLog(RotatingFile);
+impl EventLog {
+    fn open(config: EventLogConfig) -> io::Result<Self> {
+        RotatingFile::open(config).map(EventLog)
+    }
+
+    // Returns once the daemon stops publishing, `pending` holds the line
+    // that failed to be written.
+    fn write(mut self, events: &Receiver<Event>, pending: &mut Option<Vec<u8>>) -> io::Result<()> {
+        loop {
+            let line = match pending.take() {
+                Some(line) => line,
+                None => match events.recv() {
+                    Ok(event) => line(&event, SystemTime::now())?,
+                    Err(_) => return Ok(()),
+                },
+            };
+            if let Err(e) = self.append(&line) {
+                *pending = Some(line);
+                return Err(e);
+            }
+        }
+    }
+
+    fn append(&mut self, line: &[u8]) -> io::Result<()> {
+        self.0.append(line)
+    }
+}
+
+fn line(event: &Event, at: SystemTime) -> io::Result<Vec<u8>> {
+    let mut value = serde_json::to_value(event)?;
+    if let Value::Object(fields) = &mut value {
+        let timestamp = at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        fields.insert("timestamp".into(), timestamp.into());
+    }
+    let mut line = serde_json::to_vec(&value)?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, process};
+
+    #[test]
+    fn rotates_full_logs() {
+        let dir = env::temp_dir().join(format!("risq-event-log-{}", process::id()));
+        let config = EventLogConfig {
+            path: dir.join("events.jsonl"),
+            max_size: 200,
+            max_files: 2,
+        };
+        let mut log = EventLog::open(config).unwrap();
+        for n in 0..8 {
+            let event = Event::OfferRemoved {
+                id: format!("offer-{}", n),
+                market: "btc_eur".into(),
+                reason: "expired".into(),
+            };
+            let line = line(&event, UNIX_EPOCH + Duration::from_secs(1)).unwrap();
+            assert_eq!(
+                String::from_utf8(line.clone()).unwrap(),
+                format!(
+                    "{{\"event\":\"offer_removed\",\"id\":\"offer-{}\",\"market\":\"btc_eur\",\"reason\":\"expired\",\"timestamp\":1000}}\n",
+                    n
+                )
+            );
+            log.append(&line).unwrap();
+        }
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert!(read("events.jsonl").contains("offer-7"));
+        assert!(read("events.jsonl.1").contains("offer-5"));
+        assert!(read("events.jsonl.2").contains("offer-3"));
+        assert!(!dir.join("events.jsonl.3").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,182 @@
+use crate::events::{Event, Events};
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// How many of the latest offers the feed shows.
+const FEED_SIZE: usize = 100;
+
+struct FeedEntry {
+    id: String,
+    market: String,
+    direction: String,
+    price: String,
+    amount: String,
+    payment_method: String,
+    created_at: u64,
+}
+
+/// The latest offers from the event bus, rendered as Atom feed for
+/// `/feeds/offers.atom`.
+#[derive(Clone, Default)]
+pub struct OfferFeed {
+    entries: Arc<Mutex<VecDeque<FeedEntry>>>,
+}
+impl OfferFeed {
+    pub fn follow(events: &Events) -> Self {
+        let feed = Self::default();
+        let events = events.subscribe();
+        let following = feed.clone();
+        thread::spawn(move || {
+            for event in events {
+                following.add(event);
+            }
+        });
+        feed
+    }
+
+    fn add(&self, event: Event) {
+        if let Event::OfferAdded {
+            id,
+            market,
+            direction,
+            price,
+            amount,
+            payment_method,
+            created_at,
+            ..
+        } = event
+        {
+            let mut entries = self.entries.lock().expect("Corrupted lock in offer feed");
+            // The offer book arrives unordered while bootstrapping
+            let idx = entries
+                .iter()
+                .position(|entry| entry.created_at <= created_at)
+                .unwrap_or_else(|| entries.len());
+            entries.insert(
+                idx,
+                FeedEntry {
+                    id,
+                    market,
+                    direction,
+                    price,
+                    amount,
+                    payment_method,
+                    created_at,
+                },
+            );
+            entries.truncate(FEED_SIZE);
+        }
+    }
+
+    /// Newest offers first, of one market (eg. "btc_eur") or of all markets.
+    pub fn atom(&self, market: Option<&str>) -> String {
+        let entries = self.entries.lock().expect("Corrupted lock in offer feed");
+        let entries: Vec<_> = entries
+            .iter()
+            .filter(|entry| market.map(|m| m == entry.market).unwrap_or(true))
+            .collect();
+        let updated = entries
+            .iter()
+            .map(|entry| entry.created_at)
+            .max()
+            .map(timestamp)
+            .unwrap_or_else(|| rfc3339(SystemTime::now()));
+        let scope = market.unwrap_or("all");
+
+        let mut feed = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        feed.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+        feed.push_str(&format!("<title>risq offers ({})</title>\n", escape(scope)));
+        feed.push_str(&format!("<id>urn:risq:offers:{}</id>\n", escape(scope)));
+        feed.push_str(&format!("<updated>{}</updated>\n", updated));
+        feed.push_str("<author><name>risq</name></author>\n");
+        for entry in entries {
+            let title = format!(
+                "{} {} BTC at {} {} ({})",
+                entry.direction,
+                entry.amount,
+                entry.price,
+                entry.market.to_uppercase(),
+                entry.payment_method
+            );
+            feed.push_str("<entry>\n");
+            feed.push_str(&format!("<title>{}</title>\n", escape(&title)));
+            feed.push_str(&format!("<id>urn:risq:offer:{}</id>\n", escape(&entry.id)));
+            feed.push_str(&format!(
+                "<updated>{}</updated>\n",
+                timestamp(entry.created_at)
+            ));
+            feed.push_str(&format!("<category term=\"{}\"/>\n", escape(&entry.market)));
+            feed.push_str(&format!(
+                "<summary>Offer {}: {}</summary>\n",
+                escape(&entry.id),
+                escape(&title)
+            ));
+            feed.push_str("</entry>\n");
+        }
+        feed.push_str("</feed>\n");
+        feed
+    }
+}
+
+fn timestamp(secs: u64) -> String {
+    rfc3339(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+fn rfc3339(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offer(id: &str, market: &str, created_at: u64) -> Event {
+        Event::OfferAdded {
+            id: id.into(),
+            market: market.into(),
+            direction: "SELL".into(),
+            price: "9000".into(),
+            premium: None,
+            amount: "0.1".into(),
+            min_amount: "0.05".into(),
+            payment_method: "SEPA".into(),
+            created_at,
+            maker_fee: "0.0001".into(),
+            maker_fee_currency: "BTC".into(),
+            maker_fee_tx_id: "fee".into(),
+            maker: None,
+            maker_age_witness_hash: None,
+        }
+    }
+
+    #[test]
+    fn renders_newest_offers_of_market() {
+        let feed = OfferFeed::default();
+        feed.add(offer("c", "btc_eur", 180));
+        feed.add(offer("a", "btc_eur", 60));
+        feed.add(offer("b<", "btc_usd", 120));
+
+        let atom = feed.atom(Some("btc_eur"));
+        assert!(atom.contains("<id>urn:risq:offers:btc_eur</id>"));
+        assert!(atom.contains("<updated>1970-01-01T00:03:00Z</updated>"));
+        assert!(atom.contains("<title>SELL 0.1 BTC at 9000 BTC_EUR (SEPA)</title>"));
+        assert!(!atom.contains("btc_usd"));
+        let c = atom.find("urn:risq:offer:c").unwrap();
+        let a = atom.find("urn:risq:offer:a").unwrap();
+        assert!(c < a);
+
+        assert!(feed.atom(None).contains("urn:risq:offer:b&lt;"));
+    }
+}
@@ -1,18 +1,26 @@
-mod btc_offer;
+// The schema macro returns unit expressions
+#![allow(clippy::unused_unit)]
 
 use crate::{
-    bisq::SequencedMessageHash,
+    bisq::{NodeAddress, SequencedMessageHash},
     domain::{
+        amount::NumberWithPrecision,
         currency::{self, Currency},
+        filter::FilterStore,
+        format::{self, Locale, BSQ_PRECISION, BTC_PRECISION},
         market::{self, Market},
-        offer::{message::GetOpenOffers, OfferBook, OfferDirection, OpenOffer},
+        offer::{
+            message::GetOpenOffers, BtcOffer, Depth, DepthLevel, OfferBook, OfferDirection,
+            OpenOffer,
+        },
+        payment_method::{self, PaymentMethodInfo},
+        price_feed::{GetCurrentPrices, PriceData},
         statistics::*,
     },
     p2p::{BootstrapState, Status},
     prelude::*,
 };
 use actix_web::{web, Error, HttpResponse};
-use btc_offer::BtcOffer;
 use chrono::{DateTime, TimeZone, Utc};
 use juniper::{
     self,
@@ -47,7 +55,7 @@ pub fn graphql(
             .and_then(|context| {
                 web::block(move || {
                     let res = request.execute(&schema, &context);
-                    Ok::<_, serde_json::error::Error>(serde_json::to_string(&res)?)
+                    serde_json::to_string(&res)
                 })
                 .map_err(Error::from)
             })
@@ -58,8 +66,9 @@ pub fn graphql(
             }),
     )
 }
-pub fn graphiql(port: web::Data<u16>) -> HttpResponse {
-    let html = graphiql_source(&format!("http://localhost:{}/graphql", port.to_string()));
+// Relative, as the api can be bound to another address than localhost
+pub fn graphiql() -> HttpResponse {
+    let html = graphiql_source("/graphql");
     HttpResponse::Ok()
         .content_type("text/html; charset=utf-8")
         .body(html)
@@ -70,31 +79,45 @@ pub struct GraphQLContextWrapper {
     #[cfg(feature = "statistics")]
     pub stats_cache: StatsCache,
     pub offer_book: Addr<OfferBook>,
+    pub get_prices: Recipient<GetCurrentPrices>,
+    pub filters: FilterStore,
 }
 impl GraphQLContextWrapper {
     #[cfg(feature = "statistics")]
     pub fn get(&self) -> impl Future<Item = GraphQLContext, Error = Error> {
-        Future::join(
+        let filters = self.filters.clone();
+        Future::join3(
             self.stats_cache.inner().map_err(Error::from),
             self.offer_book.send(GetOpenOffers).map_err(Error::from),
+            self.get_prices.send(GetCurrentPrices).map_err(Error::from),
         )
-        .map(|(stats_cache, open_offers)| GraphQLContext {
+        .map(|(stats_cache, open_offers, prices)| GraphQLContext {
             stats_cache,
             open_offers,
+            prices,
+            filters,
         })
     }
     #[cfg(not(feature = "statistics"))]
     pub fn get(&self) -> impl Future<Item = GraphQLContext, Error = Error> {
-        self.offer_book
-            .send(GetOpenOffers)
-            .map_err(Error::from)
-            .map(|open_offers| GraphQLContext { open_offers })
+        let filters = self.filters.clone();
+        Future::join(
+            self.offer_book.send(GetOpenOffers).map_err(Error::from),
+            self.get_prices.send(GetCurrentPrices).map_err(Error::from),
+        )
+        .map(|(open_offers, prices)| GraphQLContext {
+            open_offers,
+            prices,
+            filters,
+        })
     }
 }
 pub struct GraphQLContext {
     #[cfg(feature = "statistics")]
     stats_cache: locks::RwLockReadGuard<StatsCacheInner>,
     open_offers: Arc<HashMap<SequencedMessageHash, OpenOffer>>,
+    prices: Arc<HashMap<&'static str, PriceData>>,
+    filters: FilterStore,
 }
 impl juniper::Context for GraphQLContext {}
 
@@ -149,27 +172,61 @@ impl QueryFields for Query {
             .filter(|o| direction.is_none() || o.direction == direction.unwrap())
             .cloned()
             .collect();
-        offers.sort_unstable_by(|a, b| a.display_price.cmp(&b.display_price));
+        offers.sort_unstable_by_key(|o| o.display_price);
         Ok(Offers {
             market: market.unwrap_or_else(|| MarketPair(ALL_MARKETS.to_string())),
             offers,
         })
     }
 
+    fn field_depth(
+        &self,
+        executor: &juniper::Executor<'_, GraphQLContext>,
+        _trail: &QueryTrail<'_, Depth, juniper_from_schema::Walked>,
+        MarketPair(market): MarketPair,
+    ) -> FieldResult<Depth> {
+        let market = Market::from_pair(&market)
+            .ok_or_else(|| format!("MarketPair '{}' does not exist", market))?;
+        Ok(Depth::new(
+            market,
+            executor
+                .context()
+                .open_offers
+                .values()
+                .filter(|o| !o.is_expired()),
+        ))
+    }
+
     fn field_markets(
         &self,
-        _executor: &juniper::Executor<'_, GraphQLContext>,
+        executor: &juniper::Executor<'_, GraphQLContext>,
         _trail: &QueryTrail<'_, Market, juniper_from_schema::Walked>,
-    ) -> FieldResult<&Vec<Market>> {
-        Ok(&market::ALL)
+    ) -> FieldResult<Vec<&Market>> {
+        let filters = &executor.context().filters;
+        Ok(market::ALL
+            .iter()
+            .filter(|market| filters.allows_currency(&market.non_btc_side().code))
+            .collect())
     }
 
-    fn field_currencies(
+    fn field_payment_methods(
         &self,
         _executor: &juniper::Executor<'_, GraphQLContext>,
+        _trail: &QueryTrail<'_, PaymentMethodInfo, juniper_from_schema::Walked>,
+    ) -> FieldResult<Vec<&PaymentMethodInfo>> {
+        Ok(payment_method::ALL.iter().collect())
+    }
+
+    fn field_currencies(
+        &self,
+        executor: &juniper::Executor<'_, GraphQLContext>,
         _trail: &QueryTrail<'_, Currency, juniper_from_schema::Walked>,
-    ) -> FieldResult<&Vec<Currency>> {
-        Ok(&currency::ALL)
+    ) -> FieldResult<Vec<&Currency>> {
+        let filters = &executor.context().filters;
+        Ok(currency::ALL
+            .iter()
+            .filter(|currency| filters.allows_currency(&currency.code))
+            .collect())
     }
 
     #[cfg(not(feature = "statistics"))]
@@ -575,6 +632,55 @@ impl MarketFields for Market {
     }
 }
 
+impl PaymentMethodInfoFields for PaymentMethodInfo {
+    fn field_id(&self, _executor: &juniper::Executor<'_, GraphQLContext>) -> FieldResult<String> {
+        Ok(self.id.to_string())
+    }
+
+    fn field_formatted_max_trade_limit(
+        &self,
+        _executor: &juniper::Executor<'_, GraphQLContext>,
+    ) -> FieldResult<String> {
+        Ok(format::btc(
+            NumberWithPrecision::new(self.max_trade_limit(), BTC_PRECISION),
+            Locale::RAW,
+        ))
+    }
+
+    fn field_max_trade_period_hours(
+        &self,
+        _executor: &juniper::Executor<'_, GraphQLContext>,
+    ) -> FieldResult<i32> {
+        Ok((self.max_trade_period.as_secs() / 3600) as i32)
+    }
+
+    fn field_chargeback_risk(
+        &self,
+        _executor: &juniper::Executor<'_, GraphQLContext>,
+    ) -> FieldResult<String> {
+        Ok(self.risk.to_lowercase().to_string())
+    }
+
+    fn field_has_chargeback_risk(
+        &self,
+        _executor: &juniper::Executor<'_, GraphQLContext>,
+    ) -> FieldResult<bool> {
+        Ok(self.has_chargeback_risk)
+    }
+
+    fn field_currencies(
+        &self,
+        _executor: &juniper::Executor<'_, GraphQLContext>,
+    ) -> FieldResult<Vec<String>> {
+        Ok(self
+            .currencies
+            .names()
+            .into_iter()
+            .map(String::from)
+            .collect())
+    }
+}
+
 impl OpenOfferFields for OpenOffer {
     fn field_market_pair(
         &self,
@@ -605,8 +711,15 @@ impl OpenOfferFields for OpenOffer {
     fn field_payment_method_id(
         &self,
         _executor: &juniper::Executor<'_, GraphQLContext>,
-    ) -> FieldResult<&String> {
-        Ok(&self.payment_method_id)
+    ) -> FieldResult<String> {
+        Ok(self.payment_method.to_string())
+    }
+    fn field_payment_method(
+        &self,
+        _executor: &juniper::Executor<'_, GraphQLContext>,
+        _trail: &QueryTrail<'_, PaymentMethodInfo, juniper_from_schema::Walked>,
+    ) -> FieldResult<Option<&PaymentMethodInfo>> {
+        Ok(self.payment_method.info())
     }
     fn field_offer_fee_tx_id(
         &self,
@@ -624,7 +737,7 @@ impl OpenOfferFields for OpenOffer {
         &self,
         _executor: &juniper::Executor<'_, GraphQLContext>,
     ) -> FieldResult<String> {
-        Ok(self.amount.total.format(TARGET_PRECISION))
+        Ok(self.amount.total.number().format(TARGET_PRECISION))
     }
 
     fn field_formatted_btc_amount(
@@ -638,7 +751,7 @@ impl OpenOfferFields for OpenOffer {
         &self,
         _executor: &juniper::Executor<'_, GraphQLContext>,
     ) -> FieldResult<String> {
-        Ok(self.amount.min.format(TARGET_PRECISION))
+        Ok(self.amount.min.number().format(TARGET_PRECISION))
     }
     fn field_formatted_price(
         &self,
@@ -650,7 +763,7 @@ impl OpenOfferFields for OpenOffer {
         &self,
         _executor: &juniper::Executor<'_, GraphQLContext>,
     ) -> FieldResult<String> {
-        let display_volume = self.display_price * self.amount.total;
+        let display_volume = self.display_price * self.amount.total.number();
         Ok(display_volume.format(TARGET_PRECISION))
     }
 
@@ -661,6 +774,137 @@ impl OpenOfferFields for OpenOffer {
         let btc_offer = BtcOffer::new(self);
         Ok(btc_offer.volume().format(TARGET_PRECISION))
     }
+
+    fn field_formatted_market_price_margin(
+        &self,
+        _executor: &juniper::Executor<'_, GraphQLContext>,
+    ) -> FieldResult<Option<String>> {
+        Ok(self
+            .market_price_margin()
+            .map(|margin| format::percentage(margin, Locale::RAW)))
+    }
+
+    fn field_formatted_premium(
+        &self,
+        executor: &juniper::Executor<'_, GraphQLContext>,
+    ) -> FieldResult<Option<String>> {
+        Ok(self
+            .premium(&executor.context().prices)
+            .map(|premium| format::percentage(premium, Locale::RAW)))
+    }
+
+    fn field_maker_node_address(
+        &self,
+        _executor: &juniper::Executor<'_, GraphQLContext>,
+    ) -> FieldResult<Option<String>> {
+        Ok(self
+            .payload
+            .owner_node_address
+            .as_ref()
+            .map(NodeAddress::to_string))
+    }
+
+    fn field_formatted_maker_fee(
+        &self,
+        _executor: &juniper::Executor<'_, GraphQLContext>,
+    ) -> FieldResult<String> {
+        let fee = self.payload.maker_fee as u64;
+        Ok(if self.payload.is_currency_for_maker_fee_btc {
+            format::btc(NumberWithPrecision::new(fee, BTC_PRECISION), Locale::RAW)
+        } else {
+            format::bsq(NumberWithPrecision::new(fee, BSQ_PRECISION), Locale::RAW)
+        })
+    }
+
+    fn field_maker_fee_currency(
+        &self,
+        _executor: &juniper::Executor<'_, GraphQLContext>,
+    ) -> FieldResult<String> {
+        Ok(if self.payload.is_currency_for_maker_fee_btc {
+            "BTC".into()
+        } else {
+            "BSQ".into()
+        })
+    }
+
+    fn field_formatted_buyer_security_deposit(
+        &self,
+        _executor: &juniper::Executor<'_, GraphQLContext>,
+    ) -> FieldResult<String> {
+        Ok(format::btc(
+            NumberWithPrecision::new(self.payload.buyer_security_deposit as u64, BTC_PRECISION),
+            Locale::RAW,
+        ))
+    }
+
+    fn field_formatted_seller_security_deposit(
+        &self,
+        _executor: &juniper::Executor<'_, GraphQLContext>,
+    ) -> FieldResult<String> {
+        Ok(format::btc(
+            NumberWithPrecision::new(self.payload.seller_security_deposit as u64, BTC_PRECISION),
+            Locale::RAW,
+        ))
+    }
+
+    fn field_accepted_country_codes(
+        &self,
+        _executor: &juniper::Executor<'_, GraphQLContext>,
+    ) -> FieldResult<&Vec<String>> {
+        Ok(&self.payload.accepted_country_codes)
+    }
+}
+
+impl DepthFields for Depth {
+    fn field_market_pair(
+        &self,
+        _executor: &juniper::Executor<'_, GraphQLContext>,
+    ) -> FieldResult<MarketPair> {
+        Ok(MarketPair(self.market.pair.clone()))
+    }
+
+    fn field_buys(
+        &self,
+        _executor: &juniper::Executor<'_, GraphQLContext>,
+        _trail: &QueryTrail<'_, DepthLevel, juniper_from_schema::Walked>,
+    ) -> FieldResult<&Vec<DepthLevel>> {
+        Ok(&self.buys)
+    }
+
+    fn field_sells(
+        &self,
+        _executor: &juniper::Executor<'_, GraphQLContext>,
+        _trail: &QueryTrail<'_, DepthLevel, juniper_from_schema::Walked>,
+    ) -> FieldResult<&Vec<DepthLevel>> {
+        Ok(&self.sells)
+    }
+}
+
+impl DepthLevelFields for DepthLevel {
+    fn field_formatted_price(
+        &self,
+        _executor: &juniper::Executor<'_, GraphQLContext>,
+    ) -> FieldResult<String> {
+        Ok(self.price.format(TARGET_PRECISION))
+    }
+    fn field_formatted_amount(
+        &self,
+        _executor: &juniper::Executor<'_, GraphQLContext>,
+    ) -> FieldResult<String> {
+        Ok(self.amount.format(TARGET_PRECISION))
+    }
+    fn field_formatted_cumulative_amount(
+        &self,
+        _executor: &juniper::Executor<'_, GraphQLContext>,
+    ) -> FieldResult<String> {
+        Ok(self.cumulative_amount.format(TARGET_PRECISION))
+    }
+    fn field_num_offers(
+        &self,
+        _executor: &juniper::Executor<'_, GraphQLContext>,
+    ) -> FieldResult<i32> {
+        Ok(self.num_offers as i32)
+    }
 }
 
 impl TickerFields for Ticker {
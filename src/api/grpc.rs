@@ -0,0 +1,319 @@
+use crate::{
+    domain::{market::Market, offer::message::GetOpenOffers},
+    p2p::Status,
+    prelude::{net::TcpListener, *},
+};
+use h2::server;
+use http::{header::HeaderValue, HeaderMap, Response};
+use prost::Message;
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[allow(dead_code)]
+mod proto {
+    include!("../generated/risq.api.rs");
+}
+
+// gRPC (https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-HTTP2.md) over
+// HTTP/2 without TLS, unary calls of uncompressed messages only.
+//
+// Not tonic: every release of it runs on tokio 0.2 or later with std futures
+// and generates its messages with prost 0.6 or later. We're on tokio 0.1 and
+// futures 0.1 under actix 0.8, and the bisq messages the services return are
+// prost 0.5 types. Until the daemon moves to a newer actix, tonic would need
+// a second runtime and a second copy of the bisq protobuf, so the framing is
+// done here over the h2 crate actix-web already depends on.
+const CONTENT_TYPE: &str = "application/grpc";
+const PREFIX_SIZE: usize = 5;
+
+/// Codes of https://github.com/grpc/grpc/blob/master/doc/statuscodes.md
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Code {
+    Ok = 0,
+    InvalidArgument = 3,
+    Unimplemented = 12,
+    Internal = 13,
+    Unavailable = 14,
+}
+
+#[derive(Debug, PartialEq)]
+struct CallError(Code, String);
+
+type CallResult = Box<dyn Future<Item = Vec<u8>, Error = CallError>>;
+
+/// Serves the `Offers`, `Peers` and `Node` services of proto/risq/risq.proto
+/// on localhost.
+pub fn serve(
+    port: u16,
+    get_open_offers: Recipient<GetOpenOffers>,
+    p2p_status: Status,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(&SocketAddr::from((Ipv4Addr::LOCALHOST, port)))?;
+    info!("Serving gRPC on 127.0.0.1:{}", port);
+    let services = Services {
+        get_open_offers,
+        p2p_status,
+    };
+    Arbiter::spawn(
+        listener
+            .incoming()
+            .map_err(|e| error!("Couldn't accept gRPC connection: {}", e))
+            .for_each(move |socket| {
+                let services = services.clone();
+                Arbiter::spawn(
+                    server::handshake(socket)
+                        .and_then(move |connection| {
+                            connection.for_each(move |(request, mut respond)| {
+                                let path = request.uri().path().to_string();
+                                let mut body = request.into_body();
+                                let mut release = body.release_capacity().clone();
+                                let services = services.clone();
+                                Arbiter::spawn(
+                                    body.fold(Vec::new(), move |mut received, chunk| {
+                                        received.extend_from_slice(&chunk);
+                                        release.release_capacity(chunk.len()).map(|_| received)
+                                    })
+                                    .map_err(|e| CallError(Code::Internal, e.to_string()))
+                                    .and_then(move |received| {
+                                        let message = unframe(&received)?;
+                                        Ok(services.call(&path, message))
+                                    })
+                                    .flatten()
+                                    .then(move |result| {
+                                        let sent = match result {
+                                            Ok(reply) => respond
+                                                .send_response(response(), false)
+                                                .and_then(|mut stream| {
+                                                    stream
+                                                        .send_data(frame(&reply).into(), false)?;
+                                                    stream.send_trailers(trailers(Code::Ok, ""))
+                                                }),
+                                            Err(CallError(code, message)) => {
+                                                let mut response = response();
+                                                response
+                                                    .headers_mut()
+                                                    .extend(trailers(code, &message));
+                                                respond.send_response(response, true).map(|_| ())
+                                            }
+                                        };
+                                        if let Err(e) = sent {
+                                            debug!("Couldn't send gRPC response: {}", e);
+                                        }
+                                        Ok(())
+                                    }),
+                                );
+                                Ok(())
+                            })
+                        })
+                        .map_err(|e| debug!("gRPC connection failed: {}", e)),
+                );
+                Ok(())
+            }),
+    );
+    Ok(())
+}
+
+#[derive(Clone)]
+struct Services {
+    get_open_offers: Recipient<GetOpenOffers>,
+    p2p_status: Status,
+}
+impl Services {
+    fn call(&self, path: &str, message: &[u8]) -> CallResult {
+        match path {
+            "/risq.api.Offers/ListOffers" => match proto::ListOffersRequest::decode(message) {
+                Ok(request) => self.list_offers(request),
+                Err(e) => Box::new(future::err(invalid(e))),
+            },
+            "/risq.api.Peers/ListPeers" => Box::new(future::ok(self.list_peers())),
+            "/risq.api.Node/GetStatus" => Box::new(future::ok(self.status())),
+            _ => Box::new(future::err(CallError(
+                Code::Unimplemented,
+                format!("Unknown method {}", path),
+            ))),
+        }
+    }
+
+    fn list_offers(&self, request: proto::ListOffersRequest) -> CallResult {
+        let market = if request.market.is_empty() {
+            None
+        } else {
+            match Market::from_pair(&request.market) {
+                Some(market) => Some(market),
+                None => {
+                    return Box::new(future::err(CallError(
+                        Code::InvalidArgument,
+                        format!("Unknown market {}", request.market),
+                    )))
+                }
+            }
+        };
+        Box::new(
+            self.get_open_offers
+                .send(GetOpenOffers)
+                .map_err(|_| CallError(Code::Unavailable, "Offer book is gone".into()))
+                .map(move |offers| {
+                    let mut offers: Vec<_> = offers
+                        .values()
+                        .filter(|offer| market.map(|m| m.pair == offer.market.pair).unwrap_or(true))
+                        .collect();
+                    offers.sort_by_key(|offer| offer.created_at);
+                    encode(&proto::ListOffersResponse {
+                        offers: offers
+                            .into_iter()
+                            .map(|offer| proto::Offer {
+                                payload: Some((*offer.payload).clone()),
+                                market: offer.market.pair.clone(),
+                                price: offer
+                                    .price()
+                                    .map(|price| {
+                                        price
+                                            .with_precision(
+                                                offer
+                                                    .market
+                                                    .non_btc_side()
+                                                    .bisq_internal_precision(),
+                                            )
+                                            .base_amount()
+                                            as i64
+                                    })
+                                    .unwrap_or(0),
+                            })
+                            .collect(),
+                    })
+                }),
+        )
+    }
+
+    fn list_peers(&self) -> Vec<u8> {
+        encode(&proto::ListPeersResponse {
+            peers: self
+                .p2p_status
+                .connections()
+                .iter()
+                .map(|(id, status)| proto::Peer {
+                    connection_id: String::from(*id),
+                    address: status.addr.clone(),
+                    alive_at: unix_millis(status.alive_at),
+                    round_trip_ms: status
+                        .round_trip_time
+                        .map_or(0, |rtt| rtt.as_millis() as u32),
+                })
+                .collect(),
+        })
+    }
+
+    fn status(&self) -> Vec<u8> {
+        encode(&proto::NodeStatus {
+            state: self.p2p_status.bootstrap_state().to_string(),
+            progress: self.p2p_status.bootstrap_progress() as u32,
+            phases: self
+                .p2p_status
+                .bootstrap_phases()
+                .into_iter()
+                .map(|(phase, reached)| proto::BootstrapPhase {
+                    name: phase.to_string(),
+                    reached,
+                })
+                .collect(),
+            local_address: self.p2p_status.local_addr(),
+        })
+    }
+}
+
+fn invalid(e: impl ToString) -> CallError {
+    CallError(Code::InvalidArgument, e.to_string())
+}
+
+fn response() -> Response<()> {
+    Response::builder()
+        .header("content-type", CONTENT_TYPE)
+        .body(())
+        .expect("Invalid gRPC response")
+}
+
+fn trailers(code: Code, message: &str) -> HeaderMap {
+    let mut trailers = HeaderMap::new();
+    trailers.insert("grpc-status", (code as u16).into());
+    if let (false, Ok(message)) = (message.is_empty(), HeaderValue::from_str(message)) {
+        trailers.insert("grpc-message", message);
+    }
+    trailers
+}
+
+fn encode(message: &impl Message) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(message.encoded_len());
+    message
+        .encode(&mut encoded)
+        .expect("Could not encode gRPC message");
+    encoded
+}
+
+fn frame(message: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(PREFIX_SIZE + message.len());
+    frame.push(0);
+    frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    frame.extend_from_slice(message);
+    frame
+}
+
+/// The message of a request, which must be exactly one uncompressed frame.
+fn unframe(received: &[u8]) -> Result<&[u8], CallError> {
+    if received.len() < PREFIX_SIZE {
+        return Err(CallError(Code::Internal, "Incomplete message".into()));
+    }
+    if received[0] != 0 {
+        return Err(CallError(
+            Code::Unimplemented,
+            "Compressed messages aren't supported".into(),
+        ));
+    }
+    let mut len = [0; 4];
+    len.copy_from_slice(&received[1..PREFIX_SIZE]);
+    if received.len() != PREFIX_SIZE + u32::from_be_bytes(len) as usize {
+        return Err(CallError(
+            Code::Internal,
+            "Expected a single message".into(),
+        ));
+    }
+    Ok(&received[PREFIX_SIZE..])
+}
+
+fn unix_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_messages() {
+        let request = encode(&proto::ListOffersRequest {
+            market: "btc_eur".into(),
+        });
+        let framed = frame(&request);
+        assert_eq!(&framed[..PREFIX_SIZE], &[0, 0, 0, 0, 9]);
+        assert_eq!(unframe(&framed), Ok(&request[..]));
+        assert_eq!(
+            proto::ListOffersRequest::decode(unframe(&framed).unwrap())
+                .unwrap()
+                .market,
+            "btc_eur"
+        );
+
+        assert_eq!(unframe(&framed[..7]).unwrap_err().0, Code::Internal);
+        let mut compressed = framed.clone();
+        compressed[0] = 1;
+        assert_eq!(unframe(&compressed).unwrap_err().0, Code::Unimplemented);
+
+        let trailers = trailers(Code::Unavailable, "Offer book is gone");
+        assert_eq!(trailers["grpc-status"], "14");
+        assert_eq!(trailers["grpc-message"], "Offer book is gone");
+    }
+}
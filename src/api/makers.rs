@@ -0,0 +1,240 @@
+//! What is known about the maker of offers, joined from the open offers,
+//! the account age witnesses and, with `--offer-archive`, the offers it
+//! made before.
+use super::offer_archive::{MakerHistory, OfferArchive};
+use crate::{
+    bisq::NodeAddress,
+    domain::{
+        offer::{compliance, message::QueryOffers},
+        payment_account::{AccountAgeWitnesses, SignedWitnesses},
+    },
+    prelude::*,
+};
+use actix_web::{web, Error};
+use serde::Serialize;
+use std::{
+    collections::BTreeSet,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// An account age witness as published in the network.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct AccountAge {
+    pub hash: String,
+    pub date: u64,
+    pub age_days: u64,
+    pub signed_at: Option<u64>,
+    /// Whether the account was signed long enough to sign others
+    pub signer: bool,
+}
+impl AccountAge {
+    /// `None` for a witness that isn't known, or `hash` that isn't hex.
+    pub fn lookup(
+        hash: &str,
+        account_age_witnesses: &AccountAgeWitnesses,
+        signed_witnesses: &SignedWitnesses,
+        now: SystemTime,
+    ) -> Option<Self> {
+        let bytes = Vec::<u8>::from_hex(hash).ok()?;
+        let date = account_age_witnesses.date(&bytes)?;
+        Some(AccountAge {
+            hash: hash.into(),
+            date: unix_secs(date),
+            age_days: now
+                .duration_since(date)
+                .map(|age| age.as_secs() / (24 * 60 * 60))
+                .unwrap_or_default(),
+            signed_at: signed_witnesses.signed_since(&bytes).map(unix_secs),
+            signer: signed_witnesses.is_signer(&bytes, now),
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct MakerSummary {
+    /// Onion address, eg. `abc.onion:9999`
+    pub maker: String,
+    pub open_offers: usize,
+    /// Of its oldest account
+    pub age_days: Option<u64>,
+    /// `signer`, `signed` or `unsigned`, the best of its accounts
+    pub signing: &'static str,
+    /// When its first offer was seen, unix seconds. `None` without an offer
+    /// archive
+    pub first_seen: Option<u64>,
+    /// Offers seen over all, `None` without an offer archive
+    pub offers_seen: Option<u64>,
+    /// The accounts its offers were made with, oldest first
+    pub accounts: Vec<AccountAge>,
+}
+
+#[derive(Clone)]
+pub struct Makers {
+    query_offers: Recipient<QueryOffers>,
+    account_age_witnesses: AccountAgeWitnesses,
+    signed_witnesses: SignedWitnesses,
+    offer_archive: Option<OfferArchive>,
+}
+impl Makers {
+    pub fn new(
+        query_offers: Recipient<QueryOffers>,
+        account_age_witnesses: AccountAgeWitnesses,
+        signed_witnesses: SignedWitnesses,
+        offer_archive: Option<OfferArchive>,
+    ) -> Self {
+        Makers {
+            query_offers,
+            account_age_witnesses,
+            signed_witnesses,
+            offer_archive,
+        }
+    }
+
+    /// `None` for a maker without open offers that the archive didn't see
+    /// either.
+    pub fn summary(
+        &self,
+        maker: NodeAddress,
+    ) -> impl Future<Item = Option<MakerSummary>, Error = Error> {
+        let address = maker.to_string();
+        let history = match self.offer_archive.clone() {
+            Some(archive) => {
+                let address = address.clone();
+                future::Either::A(web::block(move || archive.maker(&address)).from_err())
+            }
+            None => future::Either::B(future::ok(None)),
+        };
+        let open = self
+            .query_offers
+            .send(QueryOffers {
+                maker: Some(maker),
+                ..Default::default()
+            })
+            .from_err();
+        let makers = self.clone();
+        open.join(history).map(move |(page, history)| {
+            let witness_hashes = page
+                .offers
+                .iter()
+                .filter_map(compliance::maker_witness_hash)
+                .map(|hash| hash.to_hex())
+                .collect();
+            summarize(
+                address,
+                page.offers.len(),
+                witness_hashes,
+                history,
+                &makers.account_age_witnesses,
+                &makers.signed_witnesses,
+                SystemTime::now(),
+            )
+        })
+    }
+}
+
+fn summarize(
+    maker: String,
+    open_offers: usize,
+    mut witness_hashes: BTreeSet<String>,
+    history: Option<MakerHistory>,
+    account_age_witnesses: &AccountAgeWitnesses,
+    signed_witnesses: &SignedWitnesses,
+    now: SystemTime,
+) -> Option<MakerSummary> {
+    if open_offers == 0 && history.is_none() {
+        return None;
+    }
+    if let Some(history) = history.as_ref() {
+        witness_hashes.extend(history.witness_hashes.iter().cloned());
+    }
+    let mut accounts: Vec<_> = witness_hashes
+        .iter()
+        .filter_map(|hash| AccountAge::lookup(hash, account_age_witnesses, signed_witnesses, now))
+        .collect();
+    accounts.sort_by_key(|account| account.date);
+    let signing = if accounts.iter().any(|account| account.signer) {
+        "signer"
+    } else if accounts.iter().any(|account| account.signed_at.is_some()) {
+        "signed"
+    } else {
+        "unsigned"
+    };
+    Some(MakerSummary {
+        maker,
+        open_offers,
+        age_days: accounts.first().map(|account| account.age_days),
+        signing,
+        first_seen: history.as_ref().map(|history| history.first_seen),
+        offers_seen: history.as_ref().map(|history| history.offers),
+        accounts,
+    })
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bisq::{constants::BaseCurrencyNetwork, payload::AccountAgeWitness};
+    use std::time::Duration;
+
+    #[test]
+    fn summarizes_the_accounts_and_history_of_a_maker() {
+        let account_age_witnesses = AccountAgeWitnesses::default();
+        let signed_witnesses = SignedWitnesses::new(BaseCurrencyNetwork::BtcRegtest);
+        let day = Duration::from_secs(24 * 60 * 60);
+        for (hash, days) in &[(vec![0xab], 30), (vec![0xcd], 10)] {
+            account_age_witnesses.add(&AccountAgeWitness {
+                hash: hash.clone(),
+                date: (day * *days).as_millis() as i64,
+            });
+        }
+        let now = UNIX_EPOCH + day * 40;
+        let summary = summarize(
+            "maker.onion:9999".into(),
+            2,
+            vec!["cd".to_string(), "ef".to_string()]
+                .into_iter()
+                .collect(),
+            Some(MakerHistory {
+                first_seen: 100,
+                offers: 5,
+                witness_hashes: vec!["ab".into()],
+            }),
+            &account_age_witnesses,
+            &signed_witnesses,
+            now,
+        )
+        .unwrap();
+        assert_eq!(summary.open_offers, 2);
+        // The oldest account counts, the unknown witness is left out
+        assert_eq!(summary.age_days, Some(30));
+        assert_eq!(summary.signing, "unsigned");
+        assert_eq!(summary.first_seen, Some(100));
+        assert_eq!(summary.offers_seen, Some(5));
+        assert_eq!(
+            summary
+                .accounts
+                .iter()
+                .map(|account| account.hash.as_str())
+                .collect::<Vec<_>>(),
+            vec!["cd", "ab"]
+        );
+        assert_eq!(
+            summarize(
+                "gone.onion:9999".into(),
+                0,
+                BTreeSet::new(),
+                None,
+                &account_age_witnesses,
+                &signed_witnesses,
+                now
+            ),
+            None
+        );
+    }
+}
@@ -1,10 +1,31 @@
+mod auth;
+mod cache;
 #[cfg(not(target_os = "android"))]
 mod client;
+mod dashboard;
+mod event_log;
+mod feed;
 mod graphql;
+mod grpc;
+mod makers;
+mod mqtt;
+mod offer_archive;
+mod postgres;
+pub mod responses;
 mod server;
+mod ws;
+mod zmq;
 
+pub use auth::{read_cookie as read_api_cookie, ApiAuth};
 #[cfg(not(target_os = "android"))]
 pub use client::GrqphQLClient as Client;
 #[cfg(not(target_os = "android"))]
 pub use client::WithQueryFields;
+pub use event_log::{publish as publish_event_log, EventLogConfig};
+pub use feed::OfferFeed;
+pub use grpc::serve as serve_grpc;
+pub use mqtt::{publish as publish_mqtt, MqttConfig};
+pub use offer_archive::OfferArchive;
+pub use postgres::{publish as publish_postgres, PostgresConfig};
 pub use server::listen;
+pub use zmq::publish as publish_zmq;
@@ -0,0 +1,205 @@
+use crate::events::{Event, Events};
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    sync::mpsc::{Receiver, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
+use uuid::Uuid;
+
+// MQTT 3.1.1 (http://docs.oasis-open.org/mqtt/mqtt/v3.1.1/mqtt-v3.1.1.html),
+// publishing with QoS 0 is all we need.
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const PINGREQ: u8 = 0xC0;
+const CLEAN_SESSION: u8 = 0x02;
+const PASSWORD: u8 = 0x40;
+const USER_NAME: u8 = 0x80;
+const KEEP_ALIVE: Duration = Duration::from_secs(60);
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+const DEFAULT_PORT: u16 = 1883;
+
+#[derive(Clone)]
+pub struct MqttConfig {
+    /// `host[:port]` of the broker
+    pub broker: String,
+    /// Offer events go to `<topic>/offers/<market>`, trade events to
+    /// `<topic>/trades/<trade_id>`
+    pub topic: String,
+    pub user: Option<String>,
+    pub password: Option<String>,
+}
+
+/// Publishes offer and trade events as JSON to an MQTT broker. Events that
+/// happen while the broker is unreachable are dropped.
+pub fn publish(config: MqttConfig, events: &Events) {
+    let events = events.subscribe();
+    thread::spawn(move || loop {
+        match connect(&config) {
+            Ok(stream) => {
+                info!("Publishing events to MQTT broker {}", config.broker);
+                if let Err(e) = forward(stream, &config.topic, &events) {
+                    warn!("Lost MQTT broker {}: {}", config.broker, e);
+                } else {
+                    return;
+                }
+            }
+            Err(e) => warn!("Couldn't connect to MQTT broker {}: {}", config.broker, e),
+        }
+        thread::sleep(RECONNECT_DELAY);
+        // Everything queued up meanwhile is outdated
+        while events.try_recv().is_ok() {}
+    });
+}
+
+fn connect(config: &MqttConfig) -> io::Result<TcpStream> {
+    let mut stream = if config.broker.contains(':') {
+        TcpStream::connect(config.broker.as_str())?
+    } else {
+        TcpStream::connect((config.broker.as_str(), DEFAULT_PORT))?
+    };
+    let client_id = format!("risq-{}", &Uuid::new_v4().to_simple().to_string()[..12]);
+    let mut flags = CLEAN_SESSION;
+    let mut payload = string(&client_id);
+    if let Some(user) = config.user.as_ref() {
+        flags |= USER_NAME;
+        payload.extend(string(user));
+        if let Some(password) = config.password.as_ref() {
+            flags |= PASSWORD;
+            payload.extend(string(password));
+        }
+    }
+    let mut body = string("MQTT");
+    body.push(4);
+    body.push(flags);
+    body.extend_from_slice(&(KEEP_ALIVE.as_secs() as u16).to_be_bytes());
+    body.extend(payload);
+    stream.write_all(&packet(CONNECT, &body))?;
+
+    let mut connack = [0; 4];
+    stream.read_exact(&mut connack)?;
+    match connack {
+        [CONNACK, 2, _, 0] => Ok(stream),
+        [CONNACK, 2, _, 4] | [CONNACK, 2, _, 5] => Err(refused("not authorized")),
+        [CONNACK, 2, _, code] => Err(refused(&format!("return code {}", code))),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "expected CONNACK",
+        )),
+    }
+}
+
+fn forward(mut stream: TcpStream, prefix: &str, events: &Receiver<Event>) -> io::Result<()> {
+    // The broker only answers our pings, EOF means it is gone
+    let mut responses = stream.try_clone()?;
+    thread::spawn(move || io::copy(&mut responses, &mut io::sink()));
+    loop {
+        let event = match events.recv_timeout(KEEP_ALIVE / 2) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => {
+                stream.write_all(&packet(PINGREQ, &[]))?;
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        };
+        let topic = match topic(prefix, &event) {
+            Some(topic) => topic,
+            None => continue,
+        };
+        let mut body = string(&topic);
+        body.extend(serde_json::to_vec(&event)?);
+        stream.write_all(&packet(PUBLISH, &body))?;
+    }
+}
+
+fn topic(prefix: &str, event: &Event) -> Option<String> {
+    match event {
+        Event::OfferAdded { market, .. } | Event::OfferRemoved { market, .. } => {
+            Some(format!("{}/offers/{}", prefix, market))
+        }
+        Event::TradeChanged { id, .. } => Some(format!("{}/trades/{}", prefix, id)),
+        _ => None,
+    }
+}
+
+fn packet(packet_type: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![packet_type];
+    let mut remaining = body.len();
+    loop {
+        let byte = (remaining % 128) as u8;
+        remaining /= 128;
+        if remaining > 0 {
+            packet.push(byte | 0x80);
+        } else {
+            packet.push(byte);
+            break;
+        }
+    }
+    packet.extend_from_slice(body);
+    packet
+}
+
+fn string(s: &str) -> Vec<u8> {
+    let mut ret = (s.len() as u16).to_be_bytes().to_vec();
+    ret.extend_from_slice(s.as_bytes());
+    ret
+}
+
+fn refused(reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::ConnectionRefused,
+        format!("Broker refused connection: {}", reason),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, TcpListener};
+
+    #[test]
+    fn publishes_to_broker() {
+        assert_eq!(&packet(PUBLISH, &[0; 321])[..3], &[PUBLISH, 0xC1, 0x02]);
+
+        let broker = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let events = Events::default();
+        publish(
+            MqttConfig {
+                broker: broker.local_addr().unwrap().to_string(),
+                topic: "risq".into(),
+                user: Some("user".into()),
+                password: Some("secret".into()),
+            },
+            &events,
+        );
+        let (mut conn, _) = broker.accept().unwrap();
+        let mut connect = [0; 14];
+        conn.read_exact(&mut connect).unwrap();
+        assert_eq!(connect[0], CONNECT);
+        assert_eq!(&connect[4..12], b"MQTT\x04\xC2\0\x3C");
+        let mut payload = vec![0; connect[1] as usize - 12];
+        conn.read_exact(&mut payload).unwrap();
+        assert!(payload.ends_with(b"\0\x04user\0\x06secret"));
+        conn.write_all(&[CONNACK, 2, 0, 0]).unwrap();
+
+        events.publish(Event::PeerConnected {
+            addr: "peer.onion:9999".into(),
+        });
+        events.publish(Event::OfferRemoved {
+            id: "offer".into(),
+            market: "btc_eur".into(),
+            reason: "expired".into(),
+        });
+        let mut publish = [0; 2];
+        conn.read_exact(&mut publish).unwrap();
+        assert_eq!(publish[0], PUBLISH);
+        let mut body = vec![0; publish[1] as usize];
+        conn.read_exact(&mut body).unwrap();
+        assert_eq!(
+            String::from_utf8(body).unwrap(),
+            "\0\x13risq/offers/btc_eur{\"event\":\"offer_removed\",\"id\":\"offer\",\"market\":\"btc_eur\",\"reason\":\"expired\"}"
+        );
+    }
+}
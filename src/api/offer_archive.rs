@@ -0,0 +1,416 @@
+use crate::{
+    domain::{dao::bsq::BsqFee, market::Market},
+    events::{Event, Events},
+};
+use rusqlite::{params, Connection, NO_PARAMS};
+use serde::Serialize;
+use std::{
+    path::Path,
+    sync::{mpsc::Receiver, Arc, Mutex},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Events recorded in one transaction at most, bootstrapping brings lots of
+/// offers at once.
+const MAX_BATCH: usize = 500;
+const MAX_LIMIT: usize = 10_000;
+
+/// The schema, applied in order and counted in `user_version`. Append new
+/// migrations, never change applied ones.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE offers (
+        id TEXT PRIMARY KEY,
+        market TEXT NOT NULL,
+        direction TEXT NOT NULL,
+        price TEXT NOT NULL,
+        premium REAL,
+        amount TEXT NOT NULL,
+        min_amount TEXT NOT NULL,
+        payment_method TEXT NOT NULL,
+        created_at INTEGER NOT NULL,
+        first_seen INTEGER NOT NULL,
+        refreshes INTEGER NOT NULL DEFAULT 0,
+        last_refreshed_at INTEGER,
+        removed_at INTEGER,
+        state TEXT NOT NULL
+    );
+    CREATE INDEX offers_market ON offers (market, first_seen);
+    CREATE INDEX offers_first_seen ON offers (first_seen);",
+    "ALTER TABLE offers ADD COLUMN maker_fee TEXT;
+    ALTER TABLE offers ADD COLUMN maker_fee_currency TEXT;
+    ALTER TABLE offers ADD COLUMN maker_fee_tx_id TEXT;",
+    "ALTER TABLE offers ADD COLUMN maker TEXT;
+    ALTER TABLE offers ADD COLUMN maker_age_witness_hash TEXT;
+    CREATE INDEX offers_maker ON offers (maker);",
+];
+
+/// The lifecycle of every offer the daemon saw, kept in a SQLite database
+/// beyond the live offer book.
+#[derive(Clone)]
+pub struct OfferArchive {
+    conn: Arc<Mutex<Connection>>,
+}
+
+/// An offer as recorded, times in seconds since the epoch.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ArchivedOffer {
+    pub id: String,
+    pub market: String,
+    pub direction: String,
+    pub price: String,
+    pub premium: Option<f64>,
+    pub amount: String,
+    pub min_amount: String,
+    pub payment_method: String,
+    pub created_at: u64,
+    pub first_seen: u64,
+    pub refreshes: u64,
+    pub last_refreshed_at: Option<u64>,
+    pub removed_at: Option<u64>,
+    /// `open` or why it left the offer book: `removed`, `expired`, `banned`,
+    /// `evicted` or `untracked` when the daemon stopped while it was open
+    pub state: String,
+    /// Missing for offers recorded by older versions
+    pub maker_fee: Option<String>,
+    /// `BTC` or `BSQ`
+    pub maker_fee_currency: Option<String>,
+    pub maker_fee_tx_id: Option<String>,
+    /// What the fee tx of a BSQ fee burnt, once the DAO state has it
+    pub bsq_fee: Option<BsqFee>,
+    /// Onion address of the maker, missing for offers recorded by older
+    /// versions
+    pub maker: Option<String>,
+    pub maker_age_witness_hash: Option<String>,
+}
+
+/// What the archive saw of one maker.
+#[derive(Debug, PartialEq)]
+pub struct MakerHistory {
+    /// When the first of its offers was seen, in seconds since the epoch
+    pub first_seen: u64,
+    pub offers: u64,
+    /// Hex of the account age witnesses of all its offers
+    pub witness_hashes: Vec<String>,
+}
+
+/// Offers that were open at some time between `from` and `to`.
+#[derive(Debug, Default)]
+pub struct HistoryQuery {
+    pub market: Option<&'static Market>,
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+impl OfferArchive {
+    /// Opens or creates the database and migrates it. Offers that were
+    /// still open when the daemon stopped are marked `untracked`, they are
+    /// reopened if they show up again.
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        let mut conn = Connection::open(path)?;
+        let version: i64 = conn.query_row("PRAGMA user_version", NO_PARAMS, |row| row.get(0))?;
+        let tx = conn.transaction()?;
+        for migration in MIGRATIONS.iter().skip(version as usize) {
+            tx.execute_batch(migration)?;
+        }
+        tx.execute_batch(&format!("PRAGMA user_version = {}", MIGRATIONS.len()))?;
+        tx.execute(
+            "UPDATE offers SET state = 'untracked', removed_at = ?1 WHERE removed_at IS NULL",
+            params![unix_secs(SystemTime::now())],
+        )?;
+        tx.commit()?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Records the offer events of the daemon from now on.
+    pub fn follow(&self, events: &Events) {
+        let archive = self.clone();
+        let events = events.subscribe();
+        thread::spawn(move || {
+            if let Err(e) = archive.record_all(&events) {
+                error!("Stopped archiving offers: {}", e);
+            }
+        });
+    }
+
+    fn record_all(&self, events: &Receiver<Event>) -> rusqlite::Result<()> {
+        for event in events {
+            let mut conn = self.conn.lock().expect("Corrupted lock in offer archive");
+            let tx = conn.transaction()?;
+            let now = unix_secs(SystemTime::now());
+            for event in Some(event)
+                .into_iter()
+                .chain(events.try_iter().take(MAX_BATCH))
+            {
+                record(&tx, &event, now)?;
+            }
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Ordered by when the offers were first seen.
+    pub fn offers(&self, query: &HistoryQuery) -> rusqlite::Result<Vec<ArchivedOffer>> {
+        let conn = self.conn.lock().expect("Corrupted lock in offer archive");
+        let mut statement = conn.prepare(
+            "SELECT id, market, direction, price, premium, amount, min_amount, payment_method,
+                created_at, first_seen, refreshes, last_refreshed_at, removed_at, state,
+                maker_fee, maker_fee_currency, maker_fee_tx_id, maker, maker_age_witness_hash
+             FROM offers
+             WHERE (?1 IS NULL OR market = ?1)
+               AND (?2 IS NULL OR removed_at IS NULL OR removed_at >= ?2)
+               AND (?3 IS NULL OR first_seen <= ?3)
+             ORDER BY first_seen, id
+             LIMIT ?4 OFFSET ?5",
+        )?;
+        let rows = statement.query_map(
+            params![
+                query.market.map(|market| market.pair.as_str()),
+                query.from.map(|from| from as i64),
+                query.to.map(|to| to as i64),
+                query.limit.min(MAX_LIMIT) as i64,
+                query.offset as i64
+            ],
+            |row| {
+                Ok(ArchivedOffer {
+                    id: row.get(0)?,
+                    market: row.get(1)?,
+                    direction: row.get(2)?,
+                    price: row.get(3)?,
+                    premium: row.get(4)?,
+                    amount: row.get(5)?,
+                    min_amount: row.get(6)?,
+                    payment_method: row.get(7)?,
+                    created_at: row.get::<_, i64>(8)? as u64,
+                    first_seen: row.get::<_, i64>(9)? as u64,
+                    refreshes: row.get::<_, i64>(10)? as u64,
+                    last_refreshed_at: row.get::<_, Option<i64>>(11)?.map(|secs| secs as u64),
+                    removed_at: row.get::<_, Option<i64>>(12)?.map(|secs| secs as u64),
+                    state: row.get(13)?,
+                    maker_fee: row.get(14)?,
+                    maker_fee_currency: row.get(15)?,
+                    maker_fee_tx_id: row.get(16)?,
+                    bsq_fee: None,
+                    maker: row.get(17)?,
+                    maker_age_witness_hash: row.get(18)?,
+                })
+            },
+        )?;
+        rows.collect()
+    }
+
+    /// `None` when no offer of `maker` was seen.
+    pub fn maker(&self, maker: &str) -> rusqlite::Result<Option<MakerHistory>> {
+        let conn = self.conn.lock().expect("Corrupted lock in offer archive");
+        let (first_seen, offers) = conn.query_row(
+            "SELECT MIN(first_seen), COUNT(*) FROM offers WHERE maker = ?1",
+            params![maker],
+            |row| Ok((row.get::<_, Option<i64>>(0)?, row.get::<_, i64>(1)?)),
+        )?;
+        let first_seen = match first_seen {
+            Some(first_seen) => first_seen as u64,
+            None => return Ok(None),
+        };
+        let mut statement = conn.prepare(
+            "SELECT DISTINCT maker_age_witness_hash FROM offers
+             WHERE maker = ?1 AND maker_age_witness_hash IS NOT NULL
+             ORDER BY maker_age_witness_hash",
+        )?;
+        let witness_hashes = statement
+            .query_map(params![maker], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(Some(MakerHistory {
+            first_seen,
+            offers: offers as u64,
+            witness_hashes,
+        }))
+    }
+}
+
+fn record(conn: &Connection, event: &Event, now: i64) -> rusqlite::Result<()> {
+    match event {
+        Event::OfferAdded {
+            id,
+            market,
+            direction,
+            price,
+            premium,
+            amount,
+            min_amount,
+            payment_method,
+            created_at,
+            maker_fee,
+            maker_fee_currency,
+            maker_fee_tx_id,
+            maker,
+            maker_age_witness_hash,
+        } => conn.execute(
+            "INSERT INTO offers (id, market, direction, price, premium, amount, min_amount,
+                payment_method, created_at, first_seen, state, maker_fee, maker_fee_currency,
+                maker_fee_tx_id, maker, maker_age_witness_hash)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, 'open', ?11, ?12, ?13, ?14, ?15)
+             ON CONFLICT (id) DO UPDATE SET price = excluded.price,
+                premium = excluded.premium, amount = excluded.amount,
+                min_amount = excluded.min_amount, removed_at = NULL, state = 'open',
+                maker = excluded.maker, maker_age_witness_hash = excluded.maker_age_witness_hash",
+            params![
+                id,
+                market,
+                direction,
+                price,
+                premium.filter(|premium| premium.is_finite()),
+                amount,
+                min_amount,
+                payment_method,
+                *created_at as i64,
+                now,
+                maker_fee,
+                maker_fee_currency,
+                maker_fee_tx_id,
+                maker,
+                maker_age_witness_hash
+            ],
+        ),
+        Event::OfferRefreshed { id, .. } => conn.execute(
+            "UPDATE offers SET refreshes = refreshes + 1, last_refreshed_at = ?2
+             WHERE id = ?1 AND removed_at IS NULL",
+            params![id, now],
+        ),
+        Event::OfferRemoved { id, reason, .. } => conn.execute(
+            "UPDATE offers SET removed_at = ?2, state = ?3 WHERE id = ?1 AND removed_at IS NULL",
+            params![id, now, reason],
+        ),
+        _ => Ok(0),
+    }
+    .map(|_| ())
+}
+
+fn unix_secs(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, process};
+
+    fn added(id: &str, market: &str) -> Event {
+        Event::OfferAdded {
+            id: id.into(),
+            market: market.into(),
+            direction: "BUY".into(),
+            price: "9000".into(),
+            premium: Some(0.01),
+            amount: "0.1".into(),
+            min_amount: "0.05".into(),
+            payment_method: "SEPA".into(),
+            created_at: 90,
+            maker_fee: "0.0001".into(),
+            maker_fee_currency: "BTC".into(),
+            maker_fee_tx_id: "fee".into(),
+            maker: None,
+            maker_age_witness_hash: None,
+        }
+    }
+
+    #[test]
+    fn records_offer_lifecycles() {
+        let path = env::temp_dir().join(format!("risq-offer-archive-{}", process::id()));
+        let archive = OfferArchive::open(&path).unwrap();
+        {
+            let conn = archive.conn.lock().unwrap();
+            record(&conn, &added("a", "btc_eur"), 100).unwrap();
+            record(&conn, &added("b", "btc_usd"), 110).unwrap();
+            for secs in &[120, 130] {
+                let refreshed = Event::OfferRefreshed {
+                    id: "a".into(),
+                    market: "btc_eur".into(),
+                };
+                record(&conn, &refreshed, *secs).unwrap();
+            }
+            let removed = Event::OfferRemoved {
+                id: "a".into(),
+                market: "btc_eur".into(),
+                reason: "expired".into(),
+            };
+            record(&conn, &removed, 140).unwrap();
+            record(&conn, &added("c", "btc_eur"), 150).unwrap();
+            let mut by_maker = added("d", "btc_usd");
+            if let Event::OfferAdded {
+                maker,
+                maker_age_witness_hash,
+                ..
+            } = &mut by_maker
+            {
+                *maker = Some("maker.onion:9999".into());
+                *maker_age_witness_hash = Some("ab".into());
+            }
+            record(&conn, &by_maker, 160).unwrap();
+        }
+        assert_eq!(
+            archive.maker("maker.onion:9999").unwrap(),
+            Some(MakerHistory {
+                first_seen: 160,
+                offers: 1,
+                witness_hashes: vec!["ab".into()],
+            })
+        );
+        assert_eq!(archive.maker("other.onion:9999").unwrap(), None);
+        let query = |market: Option<&str>, from: Option<u64>, to: Option<u64>| {
+            archive
+                .offers(&HistoryQuery {
+                    market: market.and_then(Market::from_pair),
+                    from,
+                    to,
+                    limit: 100,
+                    ..Default::default()
+                })
+                .unwrap()
+                .into_iter()
+                .map(|offer| offer.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(query(None, None, None), vec!["a", "b", "c", "d"]);
+        assert_eq!(query(Some("btc_eur"), None, None), vec!["a", "c"]);
+        assert_eq!(query(None, Some(145), None), vec!["b", "c", "d"]);
+        assert_eq!(query(None, None, Some(105)), vec!["a"]);
+
+        let a = archive
+            .offers(&HistoryQuery {
+                limit: 1,
+                ..Default::default()
+            })
+            .unwrap()
+            .remove(0);
+        assert_eq!(a.refreshes, 2);
+        assert_eq!(a.last_refreshed_at, Some(130));
+        assert_eq!(a.removed_at, Some(140));
+        assert_eq!(a.state, "expired");
+        assert_eq!(a.maker_fee.as_deref(), Some("0.0001"));
+        assert_eq!(a.maker_fee_currency.as_deref(), Some("BTC"));
+        assert_eq!(a.maker_fee_tx_id.as_deref(), Some("fee"));
+
+        drop(archive);
+        let reopened = OfferArchive::open(&path).unwrap();
+        let states: Vec<_> = reopened
+            .offers(&HistoryQuery {
+                limit: 100,
+                ..Default::default()
+            })
+            .unwrap()
+            .into_iter()
+            .map(|offer| offer.state)
+            .collect();
+        assert_eq!(
+            states,
+            vec!["expired", "untracked", "untracked", "untracked"]
+        );
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,116 @@
+//! The bodies of the REST api that are also read by `risq::client`, so the
+//! server and its Rust consumers share one definition of them.
+pub use crate::node::Offer;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// The filters of `GET /offers`, all of them optional.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct OffersQuery {
+    /// `buy` or `sell`
+    pub direction: Option<String>,
+    /// The non btc side of the market, eg. `EUR`
+    pub currency: Option<String>,
+    pub payment_method: Option<String>,
+    /// Onion address of the maker, eg. `abc.onion:9999`
+    pub maker: Option<String>,
+    /// BTC, eg. `0.1`
+    pub min_amount: Option<String>,
+    /// `created_at`, `price`, `amount` or `premium`, `-` in front to reverse
+    pub sort: Option<String>,
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+    /// The `version` of an earlier response, to get only what changed since
+    pub since: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OffersResponse {
+    /// How many offers passed the filters, regardless of offset and limit
+    pub total: usize,
+    pub offers: Vec<Offer>,
+    pub version: u64,
+}
+
+/// Offers that match the filters, removed ones by id only.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OfferChangesResponse {
+    pub version: u64,
+    pub added: Vec<Offer>,
+    pub refreshed: Vec<Offer>,
+    pub removed: Vec<String>,
+}
+
+/// A change of the offer book as pushed by `/ws`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event")]
+pub enum OfferEvent {
+    #[serde(rename = "offer_added")]
+    Added(Offer),
+    #[serde(rename = "offer_refreshed")]
+    Refreshed(Offer),
+    #[serde(rename = "offer_removed")]
+    Removed { id: String, market: String },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ConnInfo {
+    pub addr: Option<String>,
+    /// `seed`, `reported` or `direct`
+    pub kind: String,
+    pub alive_at: u64,
+    /// Of the last ping, `null` until the peer answered one
+    pub round_trip_ms: Option<u64>,
+}
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TorInfo {
+    /// Whether the tor control port answers
+    pub reachable: bool,
+    /// How far tor got connecting to the tor network, 100 once it's done
+    pub progress: Option<u8>,
+    pub summary: Option<String>,
+    /// Our onion address, while the hidden service is published
+    pub hidden_service: Option<String>,
+}
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PhaseInfo {
+    pub phase: String,
+    pub reached: bool,
+}
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EvictionInfo {
+    pub offers: usize,
+    pub dedup_entries: usize,
+    pub trades: usize,
+}
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClockSkewInfo {
+    pub offset_ms: Option<i64>,
+    pub samples: usize,
+    pub exceeded: bool,
+}
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StatusResponse {
+    pub uptime_secs: u64,
+    /// Our onion address, once the hidden service is published
+    pub address: Option<String>,
+    pub message_version: i32,
+    pub state: String,
+    /// `pre-sync`, `syncing` or `synced`
+    pub sync: String,
+    pub progress: usize,
+    pub phases: Vec<PhaseInfo>,
+    /// `null` without tor
+    pub tor: Option<TorInfo>,
+    pub connections: HashMap<String, ConnInfo>,
+    /// Number of connections by kind
+    pub peers: BTreeMap<String, usize>,
+    pub evictions: EvictionInfo,
+    pub clock_skew: ClockSkewInfo,
+}
+
+/// The body of the responses to invalid requests.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub error: String,
+}
@@ -1,70 +1,520 @@
-use super::graphql::*;
+use super::{
+    auth::{self, ApiAuth},
+    cache::{self, ETag, ResponseCache},
+    dashboard,
+    feed::OfferFeed,
+    graphql::*,
+    makers::{AccountAge, Makers},
+    offer_archive::{HistoryQuery, OfferArchive},
+    responses::*,
+    ws,
+};
 use crate::{
-    bisq::NodeAddress,
-    domain::{offer::OfferBook, statistics::*},
-    p2p::Status,
+    bisq::{constants::BaseCurrencyNetwork, payload::AvailabilityResult, NodeAddress},
+    domain::{
+        alert::Alerts,
+        amount::NumberWithPrecision,
+        budget::Evictions,
+        clock::ClockSkew,
+        currency::Currency,
+        dao::{
+            bsq::BsqFees,
+            message::{DaoStatus, GetBsqBalance, GetBsqSupply, GetDaoCycles, GetDaoStatus},
+            voting::{BlindVoteInfo, CycleResult, DaoPayloads, ProposalInfo, ProposalResult},
+        },
+        dispute::{Arbitrators, DisputeAgentKind, DisputeAgents},
+        filter::FilterStore,
+        format::{self, Locale, BSQ_PRECISION, BTC_PRECISION},
+        market::Market,
+        offer::{
+            compliance,
+            message::{
+                GetDepth, GetOfferBookVersion, GetOfferChanges, GetOpenOffers, OfferBookVersion,
+                OfferPage, QueryOffers, SubscribeOfferBook,
+            },
+            DepthLevel, OfferAmount, OfferBook, OfferChanges, OfferDirection, OfferId, OfferPrice,
+            OpenOffer,
+        },
+        payment_account::{
+            message::{CreatePaymentAccount, GetPaymentAccounts, PaymentAccountInfo},
+            AccountAgeWitnesses, PaymentAccountDetails, SignedWitnesses,
+        },
+        payment_method::{self, PaymentMethod, PaymentMethodInfo},
+        price_feed::GetCurrentPrices,
+        statistics::*,
+        trade::{
+            history,
+            message::{
+                AcceptMediation, CheckAvailability, ConfirmPayment, CreateOffer, GetFees,
+                GetTrades, GetWallet, PublishDelayedPayout, RemoveMyOffer, SendChatMessage,
+                TakeOffer,
+            },
+            ChatLine, ChatParty, MediatedPayout, Trade,
+        },
+        trade_fee::{self, FeeCurrency},
+    },
+    logging, metrics,
+    notifier::{AlertRules, WebhookRule},
+    p2p::{BanList, Status, Traffic, TrafficReport},
     prelude::*,
+    reload, scheduler, shutdown,
+};
+use actix_web::{
+    dev::Service, http::Method, middleware::Logger, web, App, Error, HttpRequest, HttpResponse,
+    HttpServer, Result,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
-use actix_web::{middleware::Logger, web, App, HttpResponse, HttpServer, Result};
-use std::{collections::HashMap, io, time::UNIX_EPOCH};
+
+const DEFAULT_HISTORY_LIMIT: usize = 1_000;
 
 #[allow(unused_variables)]
 pub fn listen(
+    bind: &str,
     port: u16,
+    network: BaseCurrencyNetwork,
     offer_book: Addr<OfferBook>,
     p2p_status: Status,
     stats_cache: Option<StatsCache>,
+    evictions: Evictions,
+    clock_skew: ClockSkew,
+    signed_witnesses: SignedWitnesses,
+    account_age_witnesses: AccountAgeWitnesses,
+    filters: FilterStore,
+    ban_list: BanList,
+    auth: ApiAuth,
+    alerts: Alerts,
+    alert_rules: AlertRules,
+    dispute_agents: DisputeAgents,
+    arbitrators: Arbitrators,
+    dao_payloads: DaoPayloads,
+    bsq_fees: BsqFees,
+    offer_feed: OfferFeed,
+    offer_archive: Option<OfferArchive>,
+    take_offer: Recipient<TakeOffer>,
+    check_availability: Recipient<CheckAvailability>,
+    create_offer: Recipient<CreateOffer>,
+    remove_offer: Recipient<RemoveMyOffer>,
+    get_trades: Recipient<GetTrades>,
+    confirm_payment: Recipient<ConfirmPayment>,
+    accept_mediation: Recipient<AcceptMediation>,
+    publish_delayed_payout: Recipient<PublishDelayedPayout>,
+    send_chat_message: Recipient<SendChatMessage>,
+    get_wallet: Recipient<GetWallet>,
+    get_fees: Recipient<GetFees>,
+    get_prices: Recipient<GetCurrentPrices>,
+    create_payment_account: Recipient<CreatePaymentAccount>,
+    get_payment_accounts: Recipient<GetPaymentAccounts>,
+    get_dao_status: Recipient<GetDaoStatus>,
+    get_bsq_supply: Recipient<GetBsqSupply>,
+    get_bsq_balance: Recipient<GetBsqBalance>,
+    get_dao_cycles: Recipient<GetDaoCycles>,
 ) -> Result<(), io::Error> {
+    let get_open_offers = offer_book.clone().recipient();
+    let query_offers = offer_book.clone().recipient();
+    let subscribe_offer_book = offer_book.clone().recipient();
+    let get_depth = offer_book.clone().recipient();
+    let get_offer_changes = offer_book.clone().recipient();
+    let get_offer_book_version = offer_book.clone().recipient();
     let gql_context = GraphQLContextWrapper {
         #[cfg(feature = "statistics")]
-        stats_cache: stats_cache.unwrap(),
+        stats_cache: stats_cache.clone().unwrap(),
         offer_book,
+        get_prices: get_prices.clone(),
+        filters: filters.clone(),
     };
-    listen_with_context(port, p2p_status, gql_context)
+    listen_with_context(
+        bind,
+        port,
+        network,
+        p2p_status,
+        stats_cache,
+        evictions,
+        clock_skew,
+        signed_witnesses,
+        account_age_witnesses,
+        filters,
+        ban_list,
+        auth,
+        alerts,
+        alert_rules,
+        dispute_agents,
+        arbitrators,
+        dao_payloads,
+        bsq_fees,
+        offer_feed,
+        offer_archive,
+        get_open_offers,
+        query_offers,
+        subscribe_offer_book,
+        get_depth,
+        get_offer_changes,
+        get_offer_book_version,
+        take_offer,
+        check_availability,
+        create_offer,
+        remove_offer,
+        get_trades,
+        confirm_payment,
+        accept_mediation,
+        publish_delayed_payout,
+        send_chat_message,
+        get_wallet,
+        get_fees,
+        get_prices,
+        create_payment_account,
+        get_payment_accounts,
+        get_dao_status,
+        get_bsq_supply,
+        get_bsq_balance,
+        get_dao_cycles,
+        gql_context,
+    )
 }
 
 fn listen_with_context(
+    bind: &str,
     port: u16,
+    network: BaseCurrencyNetwork,
     p2p_status: Status,
+    stats_cache: Option<StatsCache>,
+    evictions: Evictions,
+    clock_skew: ClockSkew,
+    signed_witnesses: SignedWitnesses,
+    account_age_witnesses: AccountAgeWitnesses,
+    filters: FilterStore,
+    ban_list: BanList,
+    auth: ApiAuth,
+    alerts: Alerts,
+    alert_rules: AlertRules,
+    dispute_agents: DisputeAgents,
+    arbitrators: Arbitrators,
+    dao_payloads: DaoPayloads,
+    bsq_fees: BsqFees,
+    offer_feed: OfferFeed,
+    offer_archive: Option<OfferArchive>,
+    get_open_offers: Recipient<GetOpenOffers>,
+    query_offers: Recipient<QueryOffers>,
+    subscribe_offer_book: Recipient<SubscribeOfferBook>,
+    get_depth: Recipient<GetDepth>,
+    get_offer_changes: Recipient<GetOfferChanges>,
+    get_offer_book_version: Recipient<GetOfferBookVersion>,
+    take_offer: Recipient<TakeOffer>,
+    check_availability: Recipient<CheckAvailability>,
+    create_offer: Recipient<CreateOffer>,
+    remove_offer: Recipient<RemoveMyOffer>,
+    get_trades: Recipient<GetTrades>,
+    confirm_payment: Recipient<ConfirmPayment>,
+    accept_mediation: Recipient<AcceptMediation>,
+    publish_delayed_payout: Recipient<PublishDelayedPayout>,
+    send_chat_message: Recipient<SendChatMessage>,
+    get_wallet: Recipient<GetWallet>,
+    get_fees: Recipient<GetFees>,
+    get_prices: Recipient<GetCurrentPrices>,
+    create_payment_account: Recipient<CreatePaymentAccount>,
+    get_payment_accounts: Recipient<GetPaymentAccounts>,
+    get_dao_status: Recipient<GetDaoStatus>,
+    get_bsq_supply: Recipient<GetBsqSupply>,
+    get_bsq_balance: Recipient<GetBsqBalance>,
+    get_dao_cycles: Recipient<GetDaoCycles>,
     gql_context: GraphQLContextWrapper,
 ) -> Result<(), io::Error> {
     let schema = std::sync::Arc::new(create_schema());
+    let response_cache = ResponseCache::default();
+    let makers = Makers::new(
+        query_offers.clone(),
+        account_age_witnesses.clone(),
+        signed_witnesses.clone(),
+        offer_archive.clone(),
+    );
+    let prefix = format!("/{}", network.short_name());
 
     HttpServer::new(move || {
+        let auth = auth.clone();
+        let path_prefix = prefix.clone();
+        // Served at the root and under the prefix of the network, eg. /mainnet/offers
+        let routes = |cfg: &mut web::ServiceConfig| {
+            cfg.route("/ping", web::get().to(|| "pong"))
+                .service(web::resource("/").route(web::get().to(dashboard::index)))
+                .service(web::resource("/dashboard/{file}").route(web::get().to(dashboard::asset)))
+                .service(web::resource("/status").route(web::get().to(status)))
+                .service(web::resource("/node/status").route(web::get().to(status)))
+                .service(
+                    web::resource("/admin/log")
+                        .route(web::get().to(log_filters))
+                        .route(web::put().to(set_log_filters)),
+                )
+                .service(
+                    web::resource("/admin/ban")
+                        .data(ban_list.clone())
+                        .route(web::get().to(bans))
+                        .route(web::post().to(ban_peer)),
+                )
+                .service(
+                    web::resource("/admin/ban/{onion}")
+                        .data(ban_list.clone())
+                        .route(web::delete().to(unban_peer)),
+                )
+                .service(web::resource("/admin/reload").route(web::post().to(reload_config)))
+                .service(web::resource("/admin/tasks").route(web::get().to(tasks)))
+                .service(web::resource("/shutdown").route(web::post().to(shutdown_node)))
+                .service(web::resource("/metrics").route(web::get().to(metrics)))
+                .service(web::resource("/debug/traffic").route(web::get().to(traffic)))
+                .service(
+                    web::resource("/alerts")
+                        .data(alerts.clone())
+                        .route(web::get().to(alerts_info)),
+                )
+                .service(
+                    web::resource("/alerts/rules")
+                        .data(alert_rules.clone())
+                        .route(web::get().to(alert_rules_info))
+                        .route(web::put().to(set_alert_rules)),
+                )
+                .service(
+                    web::resource("/dispute-agents")
+                        .data(dispute_agents.clone())
+                        .route(web::get().to(dispute_agents_info)),
+                )
+                .service(
+                    web::resource("/arbitrators")
+                        .data(arbitrators.clone())
+                        .route(web::get().to(arbitrators_info)),
+                )
+                .service(
+                    web::resource("/offers")
+                        .data(query_offers.clone())
+                        .data(get_offer_changes.clone())
+                        .data(get_offer_book_version.clone())
+                        .data(create_offer.clone())
+                        .data(signed_witnesses.clone())
+                        .data(response_cache.clone())
+                        .route(web::get().to_async(offers))
+                        .route(web::post().to_async(new_offer)),
+                )
+                .service(
+                    web::resource("/offers/anomalies")
+                        .data(get_open_offers.clone())
+                        .data(signed_witnesses.clone())
+                        .data(arbitrators.clone())
+                        .data(filters.clone())
+                        .route(web::get().to_async(offer_anomalies)),
+                )
+                .service(
+                    web::resource("/makers/{id}")
+                        .data(makers.clone())
+                        .route(web::get().to_async(maker)),
+                )
+                .service(
+                    web::resource("/account-age/{hash}")
+                        .data(account_age_witnesses.clone())
+                        .data(signed_witnesses.clone())
+                        .route(web::get().to(account_age)),
+                )
+                .service(
+                    web::resource("/offers/{id}")
+                        .data(remove_offer.clone())
+                        .route(web::delete().to_async(delete_offer)),
+                )
+                .service(
+                    web::resource("/offers/{id}/availability")
+                        .data(check_availability.clone())
+                        .route(web::post().to_async(offer_availability)),
+                )
+                .service(
+                    web::resource("/ws")
+                        .data(subscribe_offer_book.clone())
+                        .data(signed_witnesses.clone())
+                        .route(web::get().to(ws::offer_stream)),
+                )
+                .service(
+                    web::resource("/feeds/offers.atom")
+                        .data(offer_feed.clone())
+                        .route(web::get().to(offers_feed)),
+                )
+                .service(
+                    web::resource("/history/offers")
+                        .data(offer_archive.clone())
+                        .data(bsq_fees.clone())
+                        .route(web::get().to_async(offer_history)),
+                )
+                .service(
+                    web::resource("/statistics")
+                        .data(stats_cache.clone())
+                        .data(response_cache.clone())
+                        .route(web::get().to_async(statistics)),
+                )
+                .service(
+                    web::resource("/statistics/export")
+                        .data(stats_cache.clone())
+                        .route(web::get().to(statistics_export)),
+                )
+                .service(
+                    web::resource("/markets")
+                        .data(get_open_offers.clone())
+                        .route(web::get().to_async(markets)),
+                )
+                .service(
+                    web::resource("/markets/{market}/depth")
+                        .data(get_depth.clone())
+                        .route(web::get().to_async(market_depth)),
+                )
+                .service(
+                    web::resource("/markets/{market}/candles")
+                        .data(stats_cache.clone())
+                        .data(response_cache.clone())
+                        .route(web::get().to_async(market_candles)),
+                )
+                .service(
+                    web::resource("/statistics/{market}")
+                        .data(stats_cache.clone())
+                        .data(response_cache.clone())
+                        .route(web::get().to_async(market_statistics)),
+                )
+                .service(
+                    web::resource("/trades")
+                        .data(take_offer.clone())
+                        .data(get_trades.clone())
+                        .route(web::get().to_async(trades))
+                        .route(web::post().to_async(take)),
+                )
+                .service(
+                    web::resource("/trades/mine")
+                        .data(get_trades.clone())
+                        .route(web::get().to_async(my_trades)),
+                )
+                .service(
+                    web::resource("/trades/{id}/confirm")
+                        .data(confirm_payment.clone())
+                        .route(web::post().to_async(confirm)),
+                )
+                .service(
+                    web::resource("/trades/{id}/mediation")
+                        .data(accept_mediation.clone())
+                        .route(web::post().to_async(mediation)),
+                )
+                .service(
+                    web::resource("/trades/{id}/refund")
+                        .data(publish_delayed_payout.clone())
+                        .route(web::post().to_async(refund)),
+                )
+                .service(
+                    web::resource("/trades/{id}/chat")
+                        .data(get_trades.clone())
+                        .data(send_chat_message.clone())
+                        .route(web::get().to_async(chat))
+                        .route(web::post().to_async(send_chat)),
+                )
+                .service(
+                    web::resource("/wallet")
+                        .data(get_wallet.clone())
+                        .route(web::get().to_async(wallet)),
+                )
+                .service(
+                    web::resource("/fees")
+                        .data(get_fees.clone())
+                        .route(web::get().to_async(fees)),
+                )
+                .service(
+                    web::resource("/fees/trade")
+                        .data(get_prices.clone())
+                        .route(web::get().to_async(trade_fees)),
+                )
+                .service(web::resource("/payment_methods").route(web::get().to(payment_methods)))
+                .service(
+                    web::resource("/payment_accounts")
+                        .data(create_payment_account.clone())
+                        .data(get_payment_accounts.clone())
+                        .route(web::get().to_async(payment_accounts))
+                        .route(web::post().to_async(create_account)),
+                )
+                .service(
+                    web::resource("/dao/status")
+                        .data(get_dao_status.clone())
+                        .route(web::get().to_async(dao_status)),
+                )
+                .service(
+                    web::resource("/dao/supply")
+                        .data(get_bsq_supply.clone())
+                        .route(web::get().to_async(bsq_supply)),
+                )
+                .service(
+                    web::resource("/dao/balance/{address}")
+                        .data(get_bsq_balance.clone())
+                        .route(web::get().to_async(bsq_balance)),
+                )
+                .service(
+                    web::resource("/dao/cycles")
+                        .data(get_dao_cycles.clone())
+                        .route(web::get().to_async(dao_cycles)),
+                )
+                .service(
+                    web::resource("/dao/proposals")
+                        .data(dao_payloads.clone())
+                        .route(web::get().to(dao_proposals)),
+                )
+                .service(
+                    web::resource("/dao/blind-votes")
+                        .data(dao_payloads.clone())
+                        .route(web::get().to(dao_blind_votes)),
+                )
+                .service(
+                    web::resource("/graphql")
+                        .data(schema.clone())
+                        .data(gql_context.clone())
+                        .route(web::post().to_async(graphql)),
+                )
+                .service(web::resource("/graphiql").route(web::get().to(graphiql)));
+        };
         App::new()
+            .wrap_fn(move |req, srv| {
+                let origin = auth.allowed_origin(req.headers());
+                if *req.method() == Method::OPTIONS {
+                    if let Some(origin) = origin {
+                        let mut res = HttpResponse::NoContent().finish();
+                        auth::allow_origin(res.headers_mut(), origin, true);
+                        return future::Either::A(future::ok(req.into_response(res)));
+                    }
+                }
+                let path = auth::unprefixed(req.path(), &path_prefix);
+                if !auth.authorized(req.method(), path, req.headers()) {
+                    let mut res = HttpResponse::Unauthorized()
+                        .header("WWW-Authenticate", "Bearer")
+                        .json(serde_json::json!({ "error": "Missing or wrong api token" }));
+                    if let Some(origin) = origin {
+                        auth::allow_origin(res.headers_mut(), origin, false);
+                    }
+                    return future::Either::A(future::ok(req.into_response(res)));
+                }
+                future::Either::B(srv.call(req).map(move |mut res| {
+                    if let Some(origin) = origin {
+                        auth::allow_origin(res.headers_mut(), origin, false);
+                    }
+                    res
+                }))
+            })
             .wrap(Logger::default())
-            .route("/ping", web::get().to(|| "pong"))
             .data(p2p_status.clone())
-            .service(web::resource("/status").route(web::get().to(status)))
-            .service(
-                web::resource("/graphql")
-                    .data(schema.clone())
-                    .data(gql_context.clone())
-                    .route(web::post().to_async(graphql)),
-            )
-            .service(
-                web::resource("/graphiql")
-                    .data(port)
-                    .route(web::get().to(graphiql)),
-            )
+            .data(evictions.clone())
+            .data(clock_skew.clone())
+            .service(web::scope(&prefix).configure(routes))
+            .configure(routes)
     })
-    .bind(("127.0.0.1", port))?
+    .bind((bind, port))?
+    // SIGINT and SIGTERM run the shutdown of the whole node
+    .disable_signals()
     .start();
     Ok(())
 }
 
-#[derive(serde::Serialize)]
-struct ConnInfo {
-    addr: Option<String>,
-    alive_at: u64,
-}
-#[derive(serde::Serialize)]
-struct StatusResponse {
-    state: String,
-    connections: HashMap<String, ConnInfo>,
-}
-
-fn status(status: web::Data<Status>) -> HttpResponse {
+fn status(
+    status: web::Data<Status>,
+    evictions: web::Data<Evictions>,
+    clock_skew: web::Data<ClockSkew>,
+) -> HttpResponse {
     let connections: HashMap<String, ConnInfo> = status
         .connections()
         .iter()
@@ -73,17 +523,2067 @@ fn status(status: web::Data<Status>) -> HttpResponse {
                 String::from(*id),
                 ConnInfo {
                     addr: status.addr.as_ref().map(NodeAddress::to_string),
+                    kind: status.kind.to_string(),
                     alive_at: status
                         .alive_at
                         .duration_since(UNIX_EPOCH)
                         .expect("Time reversed")
                         .as_secs(),
+                    round_trip_ms: status.round_trip_time.map(|rtt| rtt.as_millis() as u64),
                 },
             )
         })
         .collect();
+    let mut peers = BTreeMap::new();
+    for conn in connections.values() {
+        *peers.entry(conn.kind.clone()).or_insert(0) += 1;
+    }
     HttpResponse::Ok().json(StatusResponse {
+        uptime_secs: status
+            .started_at()
+            .elapsed()
+            .map(|uptime| uptime.as_secs())
+            .unwrap_or_default(),
+        address: status.local_addr().as_ref().map(NodeAddress::to_string),
+        message_version: status.message_version().into(),
         state: status.bootstrap_state().to_string(),
+        sync: status.sync_state().to_string(),
+        progress: status.bootstrap_progress(),
+        phases: status
+            .bootstrap_phases()
+            .into_iter()
+            .map(|(phase, reached)| PhaseInfo {
+                phase: phase.to_string(),
+                reached,
+            })
+            .collect(),
+        tor: status.tor().map(|tor| TorInfo {
+            reachable: tor.reachable,
+            progress: tor.bootstrap.as_ref().map(|bootstrap| bootstrap.progress),
+            summary: tor.bootstrap.map(|bootstrap| bootstrap.summary),
+            hidden_service: tor.hidden_service.as_ref().map(NodeAddress::to_string),
+        }),
         connections,
+        peers,
+        evictions: EvictionInfo {
+            offers: evictions.offers(),
+            dedup_entries: evictions.dedup_entries(),
+            trades: evictions.trades(),
+        },
+        clock_skew: ClockSkewInfo {
+            offset_ms: clock_skew.offset_millis(),
+            samples: clock_skew.samples(),
+            exceeded: clock_skew.exceeded(),
+        },
+    })
+}
+
+fn shutdown_node() -> HttpResponse {
+    arbiter_spawn!(shutdown::run());
+    HttpResponse::Accepted().finish()
+}
+
+fn reload_config() -> HttpResponse {
+    match reload::run() {
+        Ok(update) => HttpResponse::Ok().json(serde_json::json!({ "reloaded": update.settings() })),
+        Err(error) => HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+    }
+}
+
+fn tasks() -> HttpResponse {
+    HttpResponse::Ok().json(scheduler::stats())
+}
+
+fn metrics(status: web::Data<Status>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics::render(&status))
+}
+
+#[derive(serde::Serialize)]
+struct ConnectionTraffic {
+    id: String,
+    addr: Option<String>,
+    kind: String,
+    #[serde(flatten)]
+    traffic: TrafficReport,
+}
+#[derive(serde::Serialize, Default)]
+struct TrafficTotals {
+    received_bytes: u64,
+    sent_bytes: u64,
+    received_bytes_per_sec: f64,
+    sent_bytes_per_sec: f64,
+}
+#[derive(serde::Serialize)]
+struct TrafficResponse {
+    totals: TrafficTotals,
+    /// Busiest first, by the bytes received over the last minute
+    connections: Vec<ConnectionTraffic>,
+}
+
+fn traffic(status: web::Data<Status>) -> HttpResponse {
+    let mut connections: Vec<ConnectionTraffic> = status
+        .connections()
+        .iter()
+        .filter_map(|(id, status)| {
+            Some(ConnectionTraffic {
+                id: String::from(*id),
+                addr: status.addr.as_ref().map(NodeAddress::to_string),
+                kind: status.kind.to_string(),
+                traffic: Traffic::report(id)?,
+            })
+        })
+        .collect();
+    connections.sort_by(|a, b| {
+        b.traffic
+            .received
+            .bytes_per_sec
+            .partial_cmp(&a.traffic.received.bytes_per_sec)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    let mut totals = TrafficTotals::default();
+    for conn in connections.iter() {
+        totals.received_bytes += conn.traffic.received.bytes;
+        totals.sent_bytes += conn.traffic.sent.bytes;
+        totals.received_bytes_per_sec += conn.traffic.received.bytes_per_sec;
+        totals.sent_bytes_per_sec += conn.traffic.sent.bytes_per_sec;
+    }
+    HttpResponse::Ok().json(TrafficResponse {
+        totals,
+        connections,
+    })
+}
+
+#[derive(serde::Serialize)]
+struct AlertInfo {
+    message: String,
+    version: String,
+    update_info: bool,
+    requires_update: bool,
+}
+
+#[derive(serde::Serialize)]
+struct DisputeAgentInfo {
+    /// `mediator` or `refund_agent`
+    kind: &'static str,
+    address: String,
+    language_codes: Vec<String>,
+    /// Unix seconds
+    registered_at: u64,
+    email_address: String,
+    info: String,
+    /// How many of the latest 100 trades in the network it was assigned to
+    recent_trades: usize,
+}
+
+fn dispute_agents_info(dispute_agents: web::Data<DisputeAgents>) -> HttpResponse {
+    let mediator_usage = dispute_agents.usage(DisputeAgentKind::Mediator);
+    let refund_agent_usage = dispute_agents.usage(DisputeAgentKind::RefundAgent);
+    let mut agents: Vec<_> = dispute_agents
+        .list()
+        .into_iter()
+        .map(|(kind, agent)| {
+            let usage = match kind {
+                DisputeAgentKind::Mediator => &mediator_usage,
+                DisputeAgentKind::RefundAgent => &refund_agent_usage,
+            };
+            DisputeAgentInfo {
+                kind: kind.to_lowercase(),
+                recent_trades: usage.get(&agent.address).cloned().unwrap_or(0),
+                address: agent.address.to_string(),
+                language_codes: agent.language_codes,
+                registered_at: agent
+                    .registration_date
+                    .duration_since(UNIX_EPOCH)
+                    .map(|age| age.as_secs())
+                    .unwrap_or(0),
+                email_address: agent.email_address,
+                info: agent.info,
+            }
+        })
+        .collect();
+    agents.sort_by(|a, b| (a.kind, &a.address).cmp(&(b.kind, &b.address)));
+    HttpResponse::Ok().json(agents)
+}
+
+#[derive(serde::Serialize)]
+struct ArbitratorInfo {
+    address: String,
+    language_codes: Vec<String>,
+    /// Unix seconds
+    registered_at: u64,
+    btc_address: String,
+    email_address: String,
+    info: String,
+}
+
+fn arbitrators_info(arbitrators: web::Data<Arbitrators>) -> HttpResponse {
+    let mut arbitrators: Vec<_> = arbitrators
+        .list()
+        .into_iter()
+        .map(|arbitrator| ArbitratorInfo {
+            address: arbitrator.address.to_string(),
+            language_codes: arbitrator.language_codes,
+            registered_at: arbitrator
+                .registration_date
+                .duration_since(UNIX_EPOCH)
+                .map(|age| age.as_secs())
+                .unwrap_or(0),
+            btc_address: arbitrator.btc_address,
+            email_address: arbitrator.email_address,
+            info: arbitrator.info,
+        })
+        .collect();
+    arbitrators.sort_by(|a, b| a.address.cmp(&b.address));
+    HttpResponse::Ok().json(arbitrators)
+}
+
+fn alerts_info(alerts: web::Data<Alerts>) -> HttpResponse {
+    HttpResponse::Ok().json(
+        alerts
+            .current()
+            .into_iter()
+            .map(|alert| AlertInfo {
+                requires_update: alert.requires_update(),
+                message: alert.message,
+                version: alert.version,
+                update_info: alert.update_info,
+            })
+            .collect::<Vec<_>>(),
+    )
+}
+
+fn alert_rules_info(alert_rules: web::Data<AlertRules>) -> HttpResponse {
+    HttpResponse::Ok().json(alert_rules.list())
+}
+
+fn set_alert_rules(
+    alert_rules: web::Data<AlertRules>,
+    body: web::Json<Vec<WebhookRule>>,
+) -> HttpResponse {
+    let rules = body.into_inner();
+    let count = rules.len();
+    match alert_rules.set(rules) {
+        Ok(()) => {
+            info!("Set {} alert rules", count);
+            HttpResponse::Ok().json(alert_rules.list())
+        }
+        Err(error) => HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct LogFilters {
+    filters: String,
+}
+
+fn log_filters() -> HttpResponse {
+    HttpResponse::Ok().json(LogFilters {
+        filters: logging::filters(),
+    })
+}
+
+fn set_log_filters(body: web::Json<LogFilters>) -> HttpResponse {
+    match logging::set_filters(&body.filters) {
+        Ok(()) => {
+            info!("Log filters changed to '{}'", body.filters);
+            HttpResponse::Ok().json(LogFilters {
+                filters: logging::filters(),
+            })
+        }
+        Err(error) => HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct BanRequest {
+    /// With or without a port, the peer is banned on all
+    onion: String,
+    #[serde(default)]
+    reason: String,
+}
+
+fn bans(ban_list: web::Data<BanList>) -> HttpResponse {
+    HttpResponse::Ok().json(ban_list.list())
+}
+
+fn ban_peer(ban_list: web::Data<BanList>, body: web::Json<BanRequest>) -> HttpResponse {
+    let BanRequest { onion, reason } = body.into_inner();
+    match ban_list.ban(&onion, reason) {
+        Ok(ban) => {
+            info!("Banned {} ({})", ban.host, ban.reason);
+            HttpResponse::Ok().json(ban)
+        }
+        Err(error) => HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+    }
+}
+
+fn unban_peer(ban_list: web::Data<BanList>, onion: web::Path<String>) -> HttpResponse {
+    match ban_list.unban(&onion) {
+        Ok(true) => {
+            info!("Unbanned {}", onion);
+            HttpResponse::NoContent().finish()
+        }
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(error) => HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+    }
+}
+
+#[derive(serde::Serialize)]
+struct TradeInfo {
+    id: String,
+    market: String,
+    role: String,
+    direction: String,
+    amount: String,
+    price: String,
+    state: String,
+    peer: String,
+    taker_fee_tx_id: Option<String>,
+    deposit_tx_id: Option<String>,
+    payout_tx_id: Option<String>,
+    mediated_payout: Option<MediatedPayoutInfo>,
+    delayed_payout_tx_id: Option<String>,
+    refund_payout: Option<RefundPayoutInfo>,
+    error: Option<String>,
+    created_at: u64,
+}
+#[derive(serde::Serialize)]
+struct MediatedPayoutInfo {
+    buyer_payout: String,
+    seller_payout: String,
+    accepted: bool,
+}
+#[derive(serde::Serialize)]
+struct RefundPayoutInfo {
+    buyer_payout: String,
+    seller_payout: String,
+}
+impl From<&Trade> for TradeInfo {
+    fn from(trade: &Trade) -> Self {
+        Self {
+            id: trade.id().clone().into(),
+            market: trade.offer.market.pair.clone(),
+            role: trade.role.to_string(),
+            direction: format!("{:?}", trade.direction()).to_uppercase(),
+            amount: format::btc(trade.amount, Locale::RAW),
+            price: format::price(trade.price, trade.offer.market, Locale::RAW),
+            state: trade.state().to_string(),
+            peer: trade.peer.to_string(),
+            taker_fee_tx_id: trade.taker_fee_tx_id.clone(),
+            deposit_tx_id: trade.deposit_tx_id.clone(),
+            payout_tx_id: trade.payout_tx_id.clone(),
+            mediated_payout: trade.mediated_payout.map(
+                |MediatedPayout {
+                     buyer_payout,
+                     seller_payout,
+                 }| MediatedPayoutInfo {
+                    buyer_payout: format::btc(
+                        NumberWithPrecision::new(buyer_payout, BTC_PRECISION),
+                        Locale::RAW,
+                    ),
+                    seller_payout: format::btc(
+                        NumberWithPrecision::new(seller_payout, BTC_PRECISION),
+                        Locale::RAW,
+                    ),
+                    accepted: trade.mediated_payout_signature.is_some(),
+                },
+            ),
+            delayed_payout_tx_id: trade.delayed_payout_tx_id(),
+            refund_payout: trade.refund_payout.map(
+                |MediatedPayout {
+                     buyer_payout,
+                     seller_payout,
+                 }| RefundPayoutInfo {
+                    buyer_payout: format::btc(
+                        NumberWithPrecision::new(buyer_payout, BTC_PRECISION),
+                        Locale::RAW,
+                    ),
+                    seller_payout: format::btc(
+                        NumberWithPrecision::new(seller_payout, BTC_PRECISION),
+                        Locale::RAW,
+                    ),
+                },
+            ),
+            error: trade.error().map(String::from),
+            created_at: trade
+                .created_at
+                .duration_since(UNIX_EPOCH)
+                .expect("Time reversed")
+                .as_secs(),
+        }
+    }
+}
+
+fn confirm(
+    id: web::Path<String>,
+    confirm_payment: web::Data<Recipient<ConfirmPayment>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    confirm_payment
+        .send(ConfirmPayment {
+            trade_id: OfferId::from(id.into_inner()),
+        })
+        .map(|result| match result {
+            Ok(trade) => HttpResponse::Ok().json(TradeInfo::from(&trade)),
+            Err(error) => HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+        })
+        .from_err()
+}
+
+fn mediation(
+    id: web::Path<String>,
+    accept_mediation: web::Data<Recipient<AcceptMediation>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    accept_mediation
+        .send(AcceptMediation {
+            trade_id: OfferId::from(id.into_inner()),
+        })
+        .map(|result| match result {
+            Ok(trade) => HttpResponse::Ok().json(TradeInfo::from(&trade)),
+            Err(error) => HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+        })
+        .from_err()
+}
+
+fn refund(
+    id: web::Path<String>,
+    publish_delayed_payout: web::Data<Recipient<PublishDelayedPayout>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    publish_delayed_payout
+        .send(PublishDelayedPayout {
+            trade_id: OfferId::from(id.into_inner()),
+        })
+        .map(|result| match result {
+            Ok(trade) => HttpResponse::Ok().json(TradeInfo::from(&trade)),
+            Err(error) => HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+        })
+        .from_err()
+}
+
+/// Offers whose fees or deposits differ from what the bisq client creates, or
+/// that list arbitrators which aren't registered.
+#[derive(serde::Serialize)]
+struct OfferAnomaliesResponse {
+    offers: usize,
+    signed_makers: usize,
+    flagged: Vec<FlaggedOffer>,
+}
+#[derive(serde::Serialize)]
+struct MarketInfo {
+    market: String,
+    offers: usize,
+    buy: usize,
+    sell: usize,
+}
+
+/// The markets with open offers, most offers first.
+fn markets(
+    get_open_offers: web::Data<Recipient<GetOpenOffers>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    get_open_offers
+        .send(GetOpenOffers)
+        .map(|offers| {
+            let mut markets: HashMap<&str, MarketInfo> = HashMap::new();
+            for offer in offers.values() {
+                let market = markets
+                    .entry(offer.market.pair.as_str())
+                    .or_insert_with(|| MarketInfo {
+                        market: offer.market.pair.clone(),
+                        offers: 0,
+                        buy: 0,
+                        sell: 0,
+                    });
+                market.offers += 1;
+                match offer.direction {
+                    OfferDirection::Buy => market.buy += 1,
+                    OfferDirection::Sell => market.sell += 1,
+                }
+            }
+            let mut markets: Vec<_> = markets.into_values().collect();
+            markets.sort_by(|a, b| b.offers.cmp(&a.offers).then(a.market.cmp(&b.market)));
+            HttpResponse::Ok().json(markets)
+        })
+        .from_err()
+}
+
+#[derive(serde::Serialize)]
+struct FlaggedOffer {
+    id: String,
+    market: String,
+    direction: String,
+    maker_signed: bool,
+    anomalies: Vec<String>,
+}
+
+fn offer_anomalies(
+    get_open_offers: web::Data<Recipient<GetOpenOffers>>,
+    signed_witnesses: web::Data<SignedWitnesses>,
+    arbitrators: web::Data<Arbitrators>,
+    filters: web::Data<FilterStore>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    get_open_offers
+        .send(GetOpenOffers)
+        .map(move |offers| {
+            let mut signed_makers = 0;
+            let mut flagged = Vec::new();
+            for offer in offers.values() {
+                let maker_signed = compliance::maker_witness_hash(offer)
+                    .map(|hash| signed_witnesses.is_signed(&hash))
+                    .unwrap_or(false);
+                if maker_signed {
+                    signed_makers += 1;
+                }
+                let mut anomalies = compliance::check(offer);
+                anomalies.extend(compliance::check_arbitrators(offer, &arbitrators));
+                anomalies.extend(filters.delayed_payout_anomalies(offer));
+                if anomalies.is_empty() {
+                    continue;
+                }
+                flagged.push(FlaggedOffer {
+                    id: offer.id.clone().into(),
+                    market: offer.market.pair.clone(),
+                    direction: format!("{:?}", offer.direction).to_uppercase(),
+                    maker_signed,
+                    anomalies: anomalies.iter().map(ToString::to_string).collect(),
+                });
+            }
+            flagged.sort_by(|a, b| a.id.cmp(&b.id));
+            HttpResponse::Ok().json(OfferAnomaliesResponse {
+                offers: offers.len(),
+                signed_makers,
+                flagged,
+            })
+        })
+        .from_err()
+}
+
+fn account_age(
+    hash: web::Path<String>,
+    account_age_witnesses: web::Data<AccountAgeWitnesses>,
+    signed_witnesses: web::Data<SignedWitnesses>,
+) -> HttpResponse {
+    if Vec::<u8>::from_hex(&hash).is_err() {
+        return HttpResponse::BadRequest()
+            .json(serde_json::json!({ "error": "The hash must be hex" }));
+    }
+    match AccountAge::lookup(
+        &hash,
+        &account_age_witnesses,
+        &signed_witnesses,
+        SystemTime::now(),
+    ) {
+        Some(account) => HttpResponse::Ok().json(account),
+        None => HttpResponse::NotFound()
+            .json(serde_json::json!({ "error": "Unknown account age witness" })),
+    }
+}
+
+fn maker(
+    maker: web::Path<String>,
+    makers: web::Data<Makers>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let address: NodeAddress = match maker.parse() {
+        Ok(address) => address,
+        Err(e) => {
+            return future::Either::A(future::ok(HttpResponse::BadRequest().json(ErrorResponse {
+                error: format!("Invalid maker '{}': {}", maker.into_inner(), e),
+            })))
+        }
+    };
+    future::Either::B(makers.summary(address).map(|summary| match summary {
+        Some(summary) => HttpResponse::Ok().json(summary),
+        None => HttpResponse::NotFound().json(ErrorResponse {
+            error: "No offers of this maker were seen".into(),
+        }),
+    }))
+}
+
+#[derive(serde::Serialize)]
+struct ChatLineInfo {
+    uid: String,
+    party: String,
+    sent: bool,
+    text: String,
+    date: u64,
+    delivery: Option<String>,
+}
+impl From<&ChatLine> for ChatLineInfo {
+    fn from(line: &ChatLine) -> Self {
+        Self {
+            uid: line.uid.clone(),
+            party: line.party.to_string(),
+            sent: line.sent,
+            text: line.text.clone(),
+            date: line
+                .date
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            delivery: if line.sent {
+                Some(line.delivery.to_string())
+            } else {
+                None
+            },
+        }
+    }
+}
+
+fn chat(
+    id: web::Path<String>,
+    get_trades: web::Data<Recipient<GetTrades>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let id = OfferId::from(id.into_inner());
+    get_trades
+        .send(GetTrades)
+        .map(
+            move |trades| match trades.iter().find(|trade| trade.id() == &id) {
+                Some(trade) => HttpResponse::Ok().json(
+                    trade
+                        .chat
+                        .iter()
+                        .map(ChatLineInfo::from)
+                        .collect::<Vec<_>>(),
+                ),
+                None => HttpResponse::BadRequest().json(
+                    serde_json::json!({ "error": format!("Unknown trade {}", String::from(id)) }),
+                ),
+            },
+        )
+        .from_err()
+}
+
+#[derive(serde::Deserialize)]
+struct SendChatRequest {
+    party: Option<String>,
+    text: String,
+}
+
+fn send_chat(
+    id: web::Path<String>,
+    body: web::Json<SendChatRequest>,
+    send_chat_message: web::Data<Recipient<SendChatMessage>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let party = match body.party.as_ref().map(|party| party.parse::<ChatParty>()) {
+        Some(Ok(party)) => party,
+        None => ChatParty::Peer,
+        Some(Err(error)) => {
+            return future::Either::A(future::ok(
+                HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+            ))
+        }
+    };
+    future::Either::B(
+        send_chat_message
+            .send(SendChatMessage {
+                trade_id: OfferId::from(id.into_inner()),
+                party,
+                text: body.into_inner().text,
+            })
+            .map(|result| match result {
+                Ok(line) => HttpResponse::Ok().json(ChatLineInfo::from(&line)),
+                Err(error) => {
+                    HttpResponse::BadRequest().json(serde_json::json!({ "error": error }))
+                }
+            })
+            .from_err(),
+    )
+}
+
+#[derive(serde::Serialize)]
+struct WalletResponse {
+    balance: String,
+    receive_address: String,
+}
+
+fn wallet(
+    get_wallet: web::Data<Recipient<GetWallet>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    get_wallet
+        .send(GetWallet)
+        .map(|result| match result {
+            Ok(wallet) => HttpResponse::Ok().json(WalletResponse {
+                balance: format::btc(wallet.balance, Locale::RAW),
+                receive_address: wallet.receive_address,
+            }),
+            Err(error) => HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+        })
+        .from_err()
+}
+
+#[derive(serde::Serialize)]
+struct FeeServiceResponse {
+    sats_per_vbyte: u64,
+    estimated_at: u64,
+}
+/// Fee rates in sat/vB.
+#[derive(serde::Serialize)]
+struct FeesResponse {
+    fee_service: Option<FeeServiceResponse>,
+    trade_sats_per_vbyte: Option<u64>,
+}
+
+fn fees(
+    get_fees: web::Data<Recipient<GetFees>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    get_fees
+        .send(GetFees)
+        .map(|result| match result {
+            Ok(fees) => HttpResponse::Ok().json(FeesResponse {
+                fee_service: fees.service.map(|rate| FeeServiceResponse {
+                    sats_per_vbyte: rate.sats_per_vbyte,
+                    estimated_at: rate
+                        .estimated_at
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_millis() as u64,
+                }),
+                trade_sats_per_vbyte: fees.trade,
+            }),
+            Err(error) => HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+        })
+        .from_err()
+}
+
+#[derive(serde::Deserialize)]
+struct TradeFeesQuery {
+    /// BTC amount, eg. `0.01`
+    amount: String,
+}
+
+#[derive(serde::Serialize)]
+struct TradeFeesResponse {
+    amount: String,
+    maker: TradeFeeResponse,
+    taker: TradeFeeResponse,
+}
+#[derive(serde::Serialize)]
+struct TradeFeeResponse {
+    btc: String,
+    bsq: String,
+    /// The BSQ fee at the BSQ market price, unknown without a price
+    bsq_in_btc: Option<String>,
+}
+impl TradeFeeResponse {
+    fn new(fee: impl Fn(FeeCurrency) -> u64, btc_per_bsq: Option<f64>) -> Self {
+        let bsq = fee(FeeCurrency::Bsq);
+        Self {
+            btc: format::btc(
+                NumberWithPrecision::new(fee(FeeCurrency::Btc), BTC_PRECISION),
+                Locale::RAW,
+            ),
+            bsq: format::bsq(NumberWithPrecision::new(bsq, BSQ_PRECISION), Locale::RAW),
+            bsq_in_btc: btc_per_bsq.map(|price| {
+                format::btc(
+                    NumberWithPrecision::new(trade_fee::bsq_fee_in_btc(bsq, price), BTC_PRECISION),
+                    Locale::RAW,
+                )
+            }),
+        }
+    }
+}
+
+fn trade_fees(
+    query: web::Query<TradeFeesQuery>,
+    get_prices: web::Data<Recipient<GetCurrentPrices>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let amount = match query.amount.parse::<NumberWithPrecision>() {
+        Ok(amount) => amount.with_precision(BTC_PRECISION).base_amount(),
+        Err(error) => {
+            return future::Either::A(future::ok(
+                HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+            ))
+        }
+    };
+    future::Either::B(
+        get_prices
+            .send(GetCurrentPrices)
+            .map(move |prices| {
+                let btc_per_bsq = prices.get("BSQ").map(|data| data.price);
+                HttpResponse::Ok().json(TradeFeesResponse {
+                    amount: format::btc(
+                        NumberWithPrecision::new(amount, BTC_PRECISION),
+                        Locale::RAW,
+                    ),
+                    maker: TradeFeeResponse::new(
+                        |currency| trade_fee::maker_fee(amount, currency),
+                        btc_per_bsq,
+                    ),
+                    taker: TradeFeeResponse::new(
+                        |currency| trade_fee::taker_fee(amount, currency),
+                        btc_per_bsq,
+                    ),
+                })
+            })
+            .from_err(),
+    )
+}
+
+#[derive(serde::Serialize)]
+struct PaymentAccountResponse {
+    id: String,
+    name: String,
+    payment_method: String,
+    currency: String,
+    details: BTreeMap<&'static str, String>,
+    age_witness_hash: String,
+    age_days: u64,
+    created_at: u64,
+}
+impl From<PaymentAccountInfo> for PaymentAccountResponse {
+    fn from(info: PaymentAccountInfo) -> Self {
+        let account = info.account;
+        let details = match account.details.clone() {
+            PaymentAccountDetails::Sepa {
+                holder_name,
+                iban,
+                bic,
+            } => vec![("holder_name", holder_name), ("iban", iban), ("bic", bic)],
+            PaymentAccountDetails::Revolut { account_id } => vec![("account_id", account_id)],
+            PaymentAccountDetails::F2f {
+                country_code,
+                contact,
+                city,
+                extra_info,
+            } => vec![
+                ("country_code", country_code),
+                ("contact", contact),
+                ("city", city),
+                ("extra_info", extra_info),
+            ],
+            PaymentAccountDetails::Crypto { address } => vec![("address", address)],
+        };
+        Self {
+            payment_method: account.payment_method().to_string(),
+            currency: account.currency.code.clone(),
+            details: details.into_iter().collect(),
+            age_witness_hash: info.age_witness_hash.to_hex(),
+            age_days: SystemTime::now()
+                .duration_since(info.age_witness_date)
+                .map(|age| age.as_secs() / (24 * 60 * 60))
+                .unwrap_or_default(),
+            created_at: account
+                .created_at
+                .duration_since(UNIX_EPOCH)
+                .expect("Time reversed")
+                .as_secs(),
+            id: account.id,
+            name: account.name,
+        }
+    }
+}
+
+/// The fields of the payment method's details are required, except
+/// `extra_info` of F2F accounts.
+#[derive(serde::Deserialize)]
+struct CreatePaymentAccountRequest {
+    name: String,
+    payment_method: String,
+    currency: String,
+    holder_name: Option<String>,
+    iban: Option<String>,
+    bic: Option<String>,
+    account_id: Option<String>,
+    country_code: Option<String>,
+    contact: Option<String>,
+    city: Option<String>,
+    extra_info: Option<String>,
+    address: Option<String>,
+}
+impl CreatePaymentAccountRequest {
+    fn into_message(self) -> Result<CreatePaymentAccount, String> {
+        fn required(field: Option<String>, name: &str) -> Result<String, String> {
+            field.ok_or_else(|| format!("{} is missing", name))
+        }
+        let currency = Currency::from_code(&self.currency.to_uppercase())
+            .ok_or_else(|| format!("Unknown currency {}", self.currency))?;
+        let details = match self.payment_method.parse()? {
+            PaymentMethod::Sepa => PaymentAccountDetails::Sepa {
+                holder_name: required(self.holder_name, "holder_name")?,
+                iban: required(self.iban, "iban")?,
+                bic: required(self.bic, "bic")?,
+            },
+            PaymentMethod::Revolut => PaymentAccountDetails::Revolut {
+                account_id: required(self.account_id, "account_id")?,
+            },
+            PaymentMethod::F2f => PaymentAccountDetails::F2f {
+                country_code: required(self.country_code, "country_code")?,
+                contact: required(self.contact, "contact")?,
+                city: required(self.city, "city")?,
+                extra_info: self.extra_info.unwrap_or_default(),
+            },
+            PaymentMethod::Blockchains => PaymentAccountDetails::Crypto {
+                address: required(self.address, "address")?,
+            },
+        };
+        Ok(CreatePaymentAccount {
+            name: self.name,
+            currency,
+            details,
+        })
+    }
+}
+
+#[derive(serde::Serialize)]
+struct PaymentMethodResponse {
+    id: &'static str,
+    /// BTC
+    max_trade_limit: String,
+    max_trade_period_hours: u64,
+    /// `very_low`, `low`, `mid` or `high`, the trade limit shrinks with the risk
+    chargeback_risk: &'static str,
+    /// Only accounts of these methods get signed
+    has_chargeback_risk: bool,
+    /// `fiat`, `crypto` or the codes of the currencies
+    currencies: Vec<&'static str>,
+}
+impl From<&'static PaymentMethodInfo> for PaymentMethodResponse {
+    fn from(method: &'static PaymentMethodInfo) -> Self {
+        PaymentMethodResponse {
+            id: method.id,
+            max_trade_limit: format::btc(
+                NumberWithPrecision::new(method.max_trade_limit(), BTC_PRECISION),
+                Locale::RAW,
+            ),
+            max_trade_period_hours: method.max_trade_period.as_secs() / 3600,
+            chargeback_risk: method.risk.to_lowercase(),
+            has_chargeback_risk: method.has_chargeback_risk,
+            currencies: method.currencies.names(),
+        }
+    }
+}
+
+fn payment_methods() -> HttpResponse {
+    HttpResponse::Ok().json(
+        payment_method::ALL
+            .iter()
+            .map(PaymentMethodResponse::from)
+            .collect::<Vec<_>>(),
+    )
+}
+
+fn payment_accounts(
+    get_payment_accounts: web::Data<Recipient<GetPaymentAccounts>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    get_payment_accounts
+        .send(GetPaymentAccounts)
+        .map(|accounts| {
+            HttpResponse::Ok().json(
+                accounts
+                    .into_iter()
+                    .map(PaymentAccountResponse::from)
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .from_err()
+}
+
+fn create_account(
+    body: web::Json<CreatePaymentAccountRequest>,
+    create_payment_account: web::Data<Recipient<CreatePaymentAccount>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let message = match body.into_inner().into_message() {
+        Ok(message) => message,
+        Err(error) => {
+            return future::Either::A(future::ok(
+                HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+            ))
+        }
+    };
+    future::Either::B(
+        create_payment_account
+            .send(message)
+            .map(|result| match result {
+                Ok(info) => HttpResponse::Ok().json(PaymentAccountResponse::from(info)),
+                Err(error) => {
+                    HttpResponse::BadRequest().json(serde_json::json!({ "error": error }))
+                }
+            })
+            .from_err(),
+    )
+}
+
+#[derive(serde::Serialize)]
+struct DaoStatusResponse {
+    genesis_tx_id: String,
+    height: Option<u32>,
+    bsq_txs: usize,
+    unspent_outputs: usize,
+    cycle: Option<u32>,
+    phase: Option<String>,
+}
+impl From<DaoStatus> for DaoStatusResponse {
+    fn from(status: DaoStatus) -> Self {
+        Self {
+            genesis_tx_id: status.genesis_tx_id,
+            height: status.height,
+            bsq_txs: status.bsq_txs,
+            unspent_outputs: status.unspent_outputs,
+            cycle: status.phase.map(|(cycle, _)| cycle),
+            phase: status.phase.map(|(_, phase)| phase.to_string()),
+        }
+    }
+}
+
+fn dao_status(
+    get_dao_status: web::Data<Recipient<GetDaoStatus>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    get_dao_status
+        .send(GetDaoStatus)
+        .map(|result| match result {
+            Ok(status) => HttpResponse::Ok().json(DaoStatusResponse::from(status)),
+            Err(error) => HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+        })
+        .from_err()
+}
+
+/// BSQ amounts are rendered with 2 decimals, eg. `2500000.00`.
+#[derive(serde::Serialize)]
+struct BsqSupplyResponse {
+    genesis: String,
+    burnt: String,
+    circulating: String,
+}
+
+fn bsq_supply(
+    get_bsq_supply: web::Data<Recipient<GetBsqSupply>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    get_bsq_supply
+        .send(GetBsqSupply)
+        .map(|result| match result {
+            Ok(supply) => HttpResponse::Ok().json(BsqSupplyResponse {
+                genesis: bsq(supply.genesis),
+                burnt: bsq(supply.burnt),
+                circulating: bsq(supply.circulating),
+            }),
+            Err(error) => HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+        })
+        .from_err()
+}
+
+#[derive(serde::Serialize)]
+struct BsqBalanceResponse {
+    address: String,
+    balance: String,
+    unspent_outputs: usize,
+}
+
+fn bsq_balance(
+    address: web::Path<String>,
+    get_bsq_balance: web::Data<Recipient<GetBsqBalance>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let address = address.into_inner();
+    get_bsq_balance
+        .send(GetBsqBalance {
+            address: address.clone(),
+        })
+        .map(move |result| match result {
+            Ok(balance) => HttpResponse::Ok().json(BsqBalanceResponse {
+                address,
+                balance: bsq(balance.balance),
+                unspent_outputs: balance.unspent_outputs,
+            }),
+            Err(error) => HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+        })
+        .from_err()
+}
+
+#[derive(serde::Serialize)]
+struct ProposalResultResponse {
+    tx_id: String,
+    name: String,
+    link: String,
+    kind: &'static str,
+    requested_bsq: Option<String>,
+    stake_of_accepted_votes: String,
+    stake_of_rejected_votes: String,
+    num_accepted_votes: usize,
+    num_rejected_votes: usize,
+    num_ignored_votes: usize,
+    accepted: bool,
+}
+impl From<ProposalResult> for ProposalResultResponse {
+    fn from(result: ProposalResult) -> Self {
+        Self {
+            tx_id: result.tx_id,
+            name: result.name,
+            link: result.link,
+            kind: result.kind,
+            requested_bsq: result.requested_bsq.map(bsq),
+            stake_of_accepted_votes: bsq(result.stake_of_accepted_votes),
+            stake_of_rejected_votes: bsq(result.stake_of_rejected_votes),
+            num_accepted_votes: result.num_accepted_votes,
+            num_rejected_votes: result.num_rejected_votes,
+            num_ignored_votes: result.num_ignored_votes,
+            accepted: result.accepted,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct CycleResultResponse {
+    index: u32,
+    first_block: u32,
+    last_block: u32,
+    blind_votes: usize,
+    vote_reveals: usize,
+    issued: String,
+    proposals: Vec<ProposalResultResponse>,
+}
+impl From<CycleResult> for CycleResultResponse {
+    fn from(cycle: CycleResult) -> Self {
+        Self {
+            index: cycle.index,
+            first_block: cycle.blocks.start,
+            last_block: cycle.blocks.end - 1,
+            blind_votes: cycle.blind_votes,
+            vote_reveals: cycle.vote_reveals,
+            issued: bsq(cycle.issued),
+            proposals: cycle
+                .proposals
+                .into_iter()
+                .map(ProposalResultResponse::from)
+                .collect(),
+        }
+    }
+}
+
+fn dao_cycles(
+    get_dao_cycles: web::Data<Recipient<GetDaoCycles>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    get_dao_cycles
+        .send(GetDaoCycles)
+        .map(|result| match result {
+            Ok(cycles) => HttpResponse::Ok().json(
+                cycles
+                    .into_iter()
+                    .map(CycleResultResponse::from)
+                    .collect::<Vec<_>>(),
+            ),
+            Err(error) => HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+        })
+        .from_err()
+}
+
+#[derive(serde::Serialize)]
+struct ProposalResponse {
+    tx_id: String,
+    name: String,
+    link: String,
+    kind: &'static str,
+    requested_bsq: Option<String>,
+    created_at: u64,
+    /// Not yet persisted by the network, as during the proposal phase
+    temporary: bool,
+}
+impl From<ProposalInfo> for ProposalResponse {
+    fn from(proposal: ProposalInfo) -> Self {
+        Self {
+            tx_id: proposal.tx_id,
+            name: proposal.name,
+            link: proposal.link,
+            kind: proposal.kind,
+            requested_bsq: proposal.requested_bsq.map(bsq),
+            created_at: unix_secs(proposal.created_at),
+            temporary: proposal.temporary,
+        }
+    }
+}
+
+fn dao_proposals(dao_payloads: web::Data<DaoPayloads>) -> HttpResponse {
+    HttpResponse::Ok().json(
+        dao_payloads
+            .proposals()
+            .into_iter()
+            .map(ProposalResponse::from)
+            .collect::<Vec<_>>(),
+    )
+}
+
+#[derive(serde::Serialize)]
+struct BlindVoteResponse {
+    tx_id: String,
+    stake: String,
+    date: u64,
+}
+
+fn dao_blind_votes(dao_payloads: web::Data<DaoPayloads>) -> HttpResponse {
+    HttpResponse::Ok().json(
+        dao_payloads
+            .blind_votes()
+            .into_iter()
+            .map(|vote: BlindVoteInfo| BlindVoteResponse {
+                tx_id: vote.tx_id,
+                stake: bsq(vote.stake),
+                date: unix_secs(vote.date),
+            })
+            .collect::<Vec<_>>(),
+    )
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn bsq(satoshis: u64) -> String {
+    format::bsq(
+        NumberWithPrecision::new(satoshis, BSQ_PRECISION),
+        Locale::RAW,
+    )
+}
+
+/// A finished trade, as kept for accounting.
+#[derive(serde::Serialize)]
+struct TradeRecordInfo {
+    id: String,
+    market: String,
+    role: String,
+    direction: String,
+    amount: String,
+    price: String,
+    payment_method: String,
+    state: String,
+    peer: String,
+    fee: Option<String>,
+    fee_currency: Option<String>,
+    fee_tx_id: Option<String>,
+    deposit_tx_id: Option<String>,
+    payout_tx_id: Option<String>,
+    delayed_payout_tx_id: Option<String>,
+    error: Option<String>,
+    created_at: u64,
+    completed_at: u64,
+}
+impl TradeRecordInfo {
+    fn new(trade: &Trade, completed_at: SystemTime) -> Self {
+        let secs = |time: SystemTime| {
+            time.duration_since(UNIX_EPOCH)
+                .expect("Time reversed")
+                .as_secs()
+        };
+        let fee = history::trade_fee(trade);
+        Self {
+            id: trade.id().clone().into(),
+            market: trade.offer.market.pair.clone(),
+            role: trade.role.to_string(),
+            direction: format!("{:?}", trade.direction()).to_uppercase(),
+            amount: format::btc(trade.amount, Locale::RAW),
+            price: format::price(trade.price, trade.offer.market, Locale::RAW),
+            payment_method: trade.offer.payment_method.to_string(),
+            state: trade.state().to_string(),
+            peer: trade.peer.to_string(),
+            fee: fee.as_ref().map(|(amount, _)| amount.clone()),
+            fee_currency: fee.map(|(_, currency)| currency.to_string()),
+            fee_tx_id: history::fee_tx_id(trade).map(String::from),
+            deposit_tx_id: trade.deposit_tx_id.clone(),
+            payout_tx_id: trade.payout_tx_id.clone(),
+            delayed_payout_tx_id: trade.delayed_payout_tx_id(),
+            error: trade.error().map(String::from),
+            created_at: secs(trade.created_at),
+            completed_at: secs(completed_at),
+        }
+    }
+}
+
+impl OffersQuery {
+    fn parse(&self) -> Result<QueryOffers, String> {
+        Ok(QueryOffers {
+            direction: match self.direction.as_ref().map(|d| d.to_lowercase()) {
+                None => None,
+                Some(ref d) if d == "buy" => Some(OfferDirection::Buy),
+                Some(ref d) if d == "sell" => Some(OfferDirection::Sell),
+                Some(d) => return Err(format!("Invalid direction '{}'", d)),
+            },
+            currency: self
+                .currency
+                .as_ref()
+                .map(|code| {
+                    Currency::from_code(&code.to_uppercase())
+                        .ok_or_else(|| format!("Unknown currency '{}'", code))
+                })
+                .transpose()?,
+            payment_method: self.payment_method.clone(),
+            maker: self
+                .maker
+                .as_ref()
+                .map(|maker| {
+                    maker
+                        .parse()
+                        .map_err(|e| format!("Invalid maker '{}': {}", maker, e))
+                })
+                .transpose()?,
+            min_amount: self
+                .min_amount
+                .as_ref()
+                .map(|amount| amount.parse())
+                .transpose()?,
+            sort: self
+                .sort
+                .as_ref()
+                .map(|sort| sort.parse())
+                .transpose()?
+                .unwrap_or_default(),
+            offset: self.offset.unwrap_or(0),
+            limit: self.limit,
+        })
+    }
+}
+
+impl OffersResponse {
+    fn new(page: OfferPage, signed_witnesses: &SignedWitnesses) -> Self {
+        OffersResponse {
+            total: page.total,
+            version: page.version,
+            offers: page
+                .offers
+                .iter()
+                .map(|offer| Offer::new(offer, &page.price_data, signed_witnesses))
+                .collect(),
+        }
+    }
+}
+
+impl OfferChangesResponse {
+    fn new(changes: OfferChanges, query: &QueryOffers, signed_witnesses: &SignedWitnesses) -> Self {
+        let OfferChanges {
+            version,
+            added,
+            refreshed,
+            removed,
+            price_data,
+        } = changes;
+        let offers = |offers: Vec<OpenOffer>| {
+            offers
+                .iter()
+                .filter(|offer| query.matches(offer))
+                .map(|offer| Offer::new(offer, &price_data, signed_witnesses))
+                .collect()
+        };
+        OfferChangesResponse {
+            version,
+            added: offers(added),
+            refreshed: offers(refreshed),
+            removed: removed.into_iter().map(String::from).collect(),
+        }
+    }
+}
+
+// The makers' accounts count as signed once their signatures are old enough
+const SIGNED_SINCE_PERIOD: Duration = Duration::from_secs(60 * 60);
+
+fn offers_etag(version: OfferBookVersion, signed_witnesses: &SignedWitnesses) -> ETag {
+    ETag::new(&[
+        version.offers,
+        version.prices,
+        signed_witnesses.version(),
+        cache::time_bucket(SIGNED_SINCE_PERIOD),
+    ])
+}
+
+fn offers(
+    req: HttpRequest,
+    query: web::Query<OffersQuery>,
+    query_offers: web::Data<Recipient<QueryOffers>>,
+    get_offer_changes: web::Data<Recipient<GetOfferChanges>>,
+    get_offer_book_version: web::Data<Recipient<GetOfferBookVersion>>,
+    signed_witnesses: web::Data<SignedWitnesses>,
+    response_cache: web::Data<ResponseCache>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let since = query.since;
+    let query = match query.parse() {
+        Ok(query) => query,
+        Err(error) => {
+            return future::Either::A(future::ok(
+                HttpResponse::BadRequest().json(ErrorResponse { error }),
+            ))
+        }
+    };
+    match since {
+        Some(since) => future::Either::B(future::Either::A(
+            get_offer_changes
+                .send(GetOfferChanges(since))
+                .map(move |changes| match changes {
+                    Some(changes) => HttpResponse::Ok().json(OfferChangesResponse::new(
+                        changes,
+                        &query,
+                        &signed_witnesses,
+                    )),
+                    None => HttpResponse::Gone().json(ErrorResponse {
+                        error: format!("Version {} is unknown, get all offers again", since),
+                    }),
+                })
+                .from_err(),
+        )),
+        // Only the whole book is cached, the changes are small anyway
+        None => future::Either::B(future::Either::B(
+            get_offer_book_version
+                .send(GetOfferBookVersion)
+                .from_err()
+                .and_then(move |version| {
+                    let etag = offers_etag(version, &signed_witnesses);
+                    if let Some(response) = response_cache.lookup(&req, &etag) {
+                        return future::Either::A(future::ok(response));
+                    }
+                    future::Either::B(
+                        query_offers
+                            .send(query)
+                            .map(move |page| {
+                                let version = OfferBookVersion {
+                                    offers: page.version,
+                                    prices: page.prices_version,
+                                };
+                                response_cache.respond(
+                                    &req,
+                                    offers_etag(version, &signed_witnesses),
+                                    &OffersResponse::new(page, &signed_witnesses),
+                                )
+                            })
+                            .from_err(),
+                    )
+                }),
+        )),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct NewOfferRequest {
+    /// eg. `btc_eur`
+    market: String,
+    /// `buy` or `sell` BTC
+    direction: String,
+    /// Fixed price, eg. `9000.5`
+    price: Option<String>,
+    /// Follows the market price instead, eg. `0.02` to sell 2% above it
+    market_price_margin: Option<f64>,
+    /// BTC, eg. `0.1`
+    amount: String,
+    /// BTC, the full amount when missing
+    min_amount: Option<String>,
+    payment_account_id: String,
+}
+impl NewOfferRequest {
+    fn parse(&self) -> Result<CreateOffer, String> {
+        let market = Market::from_pair(&self.market.to_lowercase())
+            .ok_or_else(|| format!("Unknown market '{}'", self.market))?;
+        let total = self.amount.parse()?;
+        Ok(CreateOffer {
+            market,
+            direction: match self.direction.to_lowercase().as_str() {
+                "buy" => OfferDirection::Buy,
+                "sell" => OfferDirection::Sell,
+                d => return Err(format!("Invalid direction '{}'", d)),
+            },
+            price: match (&self.price, self.market_price_margin) {
+                (Some(price), None) => OfferPrice::Fixed(price.parse()?),
+                (None, Some(margin)) => OfferPrice::MarketWithMargin(margin),
+                _ => return Err("Either price or market_price_margin is required".into()),
+            },
+            amount: OfferAmount {
+                total,
+                min: self
+                    .min_amount
+                    .as_ref()
+                    .map(|amount| amount.parse())
+                    .transpose()?
+                    .unwrap_or(total),
+            },
+            payment_account_id: self.payment_account_id.clone(),
+        })
+    }
+}
+
+fn new_offer(
+    body: web::Json<NewOfferRequest>,
+    create_offer: web::Data<Recipient<CreateOffer>>,
+    signed_witnesses: web::Data<SignedWitnesses>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let create = match body.parse() {
+        Ok(create) => create,
+        Err(error) => {
+            return future::Either::A(future::ok(
+                HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+            ))
+        }
+    };
+    future::Either::B(
+        create_offer
+            .send(create)
+            .map(move |result| match result {
+                Ok(offer) => {
+                    HttpResponse::Ok().json(Offer::new(&offer, &HashMap::new(), &signed_witnesses))
+                }
+                Err(error) => {
+                    HttpResponse::BadRequest().json(serde_json::json!({ "error": error }))
+                }
+            })
+            .from_err(),
+    )
+}
+
+fn delete_offer(
+    id: web::Path<String>,
+    remove_offer: web::Data<Recipient<RemoveMyOffer>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    remove_offer
+        .send(RemoveMyOffer(OfferId::from(id.into_inner())))
+        .map(|result| match result {
+            Ok(()) => HttpResponse::NoContent().finish(),
+            Err(error) => HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+        })
+        .from_err()
+}
+
+fn offer_availability(
+    id: web::Path<String>,
+    check_availability: web::Data<Recipient<CheckAvailability>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let id = id.into_inner();
+    check_availability
+        .send(CheckAvailability(OfferId::from(id.clone())))
+        .map(move |result| match result {
+            Ok(result) => HttpResponse::Ok().json(serde_json::json!({
+                "offer_id": id,
+                "available": result == AvailabilityResult::Available,
+                "result": format!("{:?}", result),
+            })),
+            Err(error) => HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+        })
+        .from_err()
+}
+
+#[derive(serde::Deserialize)]
+struct FeedQuery {
+    market: Option<String>,
+}
+
+fn offers_feed(query: web::Query<FeedQuery>, offer_feed: web::Data<OfferFeed>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("application/atom+xml; charset=utf-8")
+        .body(offer_feed.atom(query.market.as_deref()))
+}
+
+#[derive(serde::Deserialize)]
+struct OfferHistoryQuery {
+    /// eg. `btc_eur`
+    market: Option<String>,
+    /// Unix time in seconds
+    from: Option<u64>,
+    to: Option<u64>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+}
+
+fn offer_history(
+    query: web::Query<OfferHistoryQuery>,
+    offer_archive: web::Data<Option<OfferArchive>>,
+    bsq_fees: web::Data<BsqFees>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let archive = match offer_archive.get_ref() {
+        Some(archive) => archive.clone(),
+        None => {
+            return future::Either::A(future::ok(HttpResponse::NotFound().json(
+                serde_json::json!({ "error": "Start the daemon with --offer-archive to keep the offer history" }),
+            )))
+        }
+    };
+    let market = match query.market.as_ref() {
+        Some(pair) => match Market::from_pair(pair) {
+            Some(market) => Some(market),
+            None => {
+                return future::Either::A(future::ok(
+                    HttpResponse::BadRequest()
+                        .json(serde_json::json!({ "error": format!("Unknown market {}", pair) })),
+                ))
+            }
+        },
+        None => None,
+    };
+    let query = HistoryQuery {
+        market,
+        from: query.from,
+        to: query.to,
+        offset: query.offset.unwrap_or(0),
+        limit: query.limit.unwrap_or(DEFAULT_HISTORY_LIMIT),
+    };
+    future::Either::B(
+        web::block(move || archive.offers(&query))
+            .map(move |mut offers| {
+                for offer in offers.iter_mut() {
+                    if offer.maker_fee_currency.as_deref() == Some("BSQ") {
+                        offer.bsq_fee = offer
+                            .maker_fee_tx_id
+                            .as_ref()
+                            .and_then(|tx_id| bsq_fees.get(tx_id));
+                    }
+                }
+                HttpResponse::Ok().json(offers)
+            })
+            .from_err(),
+    )
+}
+
+#[derive(serde::Deserialize)]
+struct TradeHistoryQuery {
+    /// `csv` for a spreadsheet, json otherwise
+    format: Option<String>,
+}
+
+#[cfg(feature = "statistics")]
+#[derive(serde::Serialize)]
+struct SummaryInfo {
+    trades: usize,
+    amount: String,
+    volume: String,
+    median_price: Option<String>,
+}
+#[cfg(feature = "statistics")]
+impl SummaryInfo {
+    fn new(summary: &Summary, market: &Market) -> Self {
+        SummaryInfo {
+            trades: summary.num_trades,
+            amount: format::btc(summary.amount, Locale::RAW),
+            volume: format::amount(summary.volume, market.non_btc_side(), Locale::RAW),
+            median_price: summary
+                .median_price
+                .map(|price| format::price(price, market, Locale::RAW)),
+        }
+    }
+}
+#[cfg(feature = "statistics")]
+#[derive(serde::Serialize)]
+struct WindowInfo {
+    window: &'static str,
+    #[serde(flatten)]
+    summary: SummaryInfo,
+}
+#[cfg(feature = "statistics")]
+#[derive(serde::Serialize)]
+struct MarketSummaryInfo {
+    market: String,
+    #[serde(flatten)]
+    all_time: SummaryInfo,
+    windows: Vec<WindowInfo>,
+}
+#[cfg(feature = "statistics")]
+impl From<&MarketSummary> for MarketSummaryInfo {
+    fn from(summary: &MarketSummary) -> Self {
+        MarketSummaryInfo {
+            market: summary.market.pair.clone(),
+            all_time: SummaryInfo::new(&summary.all_time, summary.market),
+            windows: summary
+                .windows
+                .iter()
+                .map(|(window, window_summary)| WindowInfo {
+                    window,
+                    summary: SummaryInfo::new(window_summary, summary.market),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct DepthLevelInfo {
+    price: String,
+    amount: String,
+    /// Amount of this level and all levels with a better price
+    cumulative_amount: String,
+    num_offers: usize,
+}
+
+#[derive(serde::Serialize)]
+struct DepthResponse {
+    market: String,
+    /// Highest price first
+    buys: Vec<DepthLevelInfo>,
+    /// Lowest price first
+    sells: Vec<DepthLevelInfo>,
+}
+
+/// Amounts are in terms of the left side of the market, like the GraphQL
+/// `depth`.
+fn market_depth(
+    market: web::Path<String>,
+    get_depth: web::Data<Recipient<GetDepth>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let market =
+        match Market::from_pair(&market.to_lowercase()) {
+            Some(market) => market,
+            None => return future::Either::A(future::ok(HttpResponse::NotFound().json(
+                serde_json::json!({ "error": format!("Unknown market {}", market.into_inner()) }),
+            ))),
+        };
+    let levels = move |levels: &[DepthLevel]| -> Vec<DepthLevelInfo> {
+        levels
+            .iter()
+            .map(|level| DepthLevelInfo {
+                price: format::price(level.price, market, Locale::RAW),
+                amount: format::amount(level.amount, market.left, Locale::RAW),
+                cumulative_amount: format::amount(
+                    level.cumulative_amount,
+                    market.left,
+                    Locale::RAW,
+                ),
+                num_offers: level.num_offers,
+            })
+            .collect()
+    };
+    future::Either::B(
+        get_depth
+            .send(GetDepth(market))
+            .map(move |depth| {
+                HttpResponse::Ok().json(DepthResponse {
+                    market: market.pair.clone(),
+                    buys: levels(&depth.buys),
+                    sells: levels(&depth.sells),
+                })
+            })
+            .from_err(),
+    )
+}
+
+#[derive(serde::Deserialize)]
+struct CandlesQuery {
+    /// `1m`, `30m`, `1h`, `12h`, `1d`, `1w`, `1M` or `1y`, defaults to `1d`
+    interval: Option<String>,
+    /// Unix time in seconds, `to` is excluded
+    from: Option<u64>,
+    to: Option<u64>,
+}
+
+#[cfg(feature = "statistics")]
+#[derive(serde::Serialize)]
+struct CandleInfo {
+    period_start: u64,
+    open: String,
+    high: String,
+    low: String,
+    close: String,
+    /// Traded amount of the left side of the market
+    volume_left: String,
+    volume_right: String,
+    trades: u32,
+}
+#[cfg(feature = "statistics")]
+impl CandleInfo {
+    fn new(candle: &candles::Candle, market: &'static Market) -> Self {
+        CandleInfo {
+            period_start: unix_secs(candle.period_start),
+            open: format::price(candle.open, market, Locale::RAW),
+            high: format::price(candle.high, market, Locale::RAW),
+            low: format::price(candle.low, market, Locale::RAW),
+            close: format::price(candle.close, market, Locale::RAW),
+            volume_left: format::amount(candle.volume_left, market.left, Locale::RAW),
+            volume_right: format::amount(candle.volume_right, market.right, Locale::RAW),
+            trades: candle.num_trades,
+        }
+    }
+}
+#[cfg(feature = "statistics")]
+#[derive(serde::Serialize)]
+struct CandlesResponse {
+    market: String,
+    interval: String,
+    /// Oldest first, periods without trades are left out
+    candles: Vec<CandleInfo>,
+}
+
+// The candles are kept up to date by the StatsCache as trades arrive, only
+// the requested range is serialized.
+#[cfg(feature = "statistics")]
+fn market_candles(
+    req: HttpRequest,
+    market: web::Path<String>,
+    query: web::Query<CandlesQuery>,
+    stats_cache: web::Data<Option<StatsCache>>,
+    response_cache: web::Data<ResponseCache>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let market = match Market::from_pair(&market.to_lowercase()) {
+        Some(market) => market,
+        None => {
+            return future::Either::A(future::ok(HttpResponse::NotFound().json(ErrorResponse {
+                error: format!("Unknown market {}", market.into_inner()),
+            })))
+        }
+    };
+    let interval_name = query.interval.clone().unwrap_or_else(|| "1d".into());
+    let interval: interval::Interval = match interval_name.parse() {
+        Ok(interval) => interval,
+        Err(error) => {
+            return future::Either::A(future::ok(
+                HttpResponse::BadRequest().json(ErrorResponse { error }),
+            ))
+        }
+    };
+    let from = query
+        .from
+        .map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+    let to = query.to.map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+    let stats_cache = stats_cache
+        .get_ref()
+        .clone()
+        .expect("StatsCache is always set with statistics");
+    let response_cache = response_cache.get_ref().clone();
+    future::Either::B(
+        stats_cache
+            .inner()
+            .map(move |stats| {
+                let etag = ETag::new(&[stats.version()]);
+                response_cache.lookup(&req, &etag).unwrap_or_else(|| {
+                    response_cache.respond(
+                        &req,
+                        etag,
+                        &CandlesResponse {
+                            market: market.pair.clone(),
+                            interval: interval_name,
+                            candles: stats
+                                .candles(market, interval, from, to)
+                                .into_iter()
+                                .map(|candle| CandleInfo::new(candle, market))
+                                .collect(),
+                        },
+                    )
+                })
+            })
+            .from_err(),
+    )
+}
+#[cfg(not(feature = "statistics"))]
+fn market_candles(
+    _req: HttpRequest,
+    _market: web::Path<String>,
+    _query: web::Query<CandlesQuery>,
+    _stats_cache: web::Data<Option<StatsCache>>,
+    _response_cache: web::Data<ResponseCache>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    future::ok(
+        HttpResponse::NotFound()
+            .json(serde_json::json!({ "error": "risq was built without the statistics feature" })),
+    )
+}
+
+fn statistics(
+    req: HttpRequest,
+    stats_cache: web::Data<Option<StatsCache>>,
+    response_cache: web::Data<ResponseCache>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    summaries(req, &stats_cache, &response_cache, None)
+}
+
+#[derive(serde::Deserialize)]
+struct StatisticsExportQuery {
+    /// `csv` or `json` for one object per line
+    format: Option<String>,
+    /// eg. `btc_eur`
+    market: Option<String>,
+    /// Unix time in seconds, `to` is excluded
+    from: Option<u64>,
+    to: Option<u64>,
+}
+
+// Streamed in chunks, the full history is too large for one response.
+#[cfg(feature = "statistics")]
+fn statistics_export(
+    query: web::Query<StatisticsExportQuery>,
+    stats_cache: web::Data<Option<StatsCache>>,
+) -> HttpResponse {
+    use crate::domain::statistics::export::{ExportCursor, ExportFormat, ExportQuery};
+    use actix_web::error;
+    use bytes::Bytes;
+    let format: ExportFormat = match query.format.as_ref().map(|format| format.parse()) {
+        None => ExportFormat::Csv,
+        Some(Ok(format)) => format,
+        Some(Err(error)) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": error }))
+        }
+    };
+    let market = match query.market.as_ref() {
+        Some(pair) => match Market::from_pair(pair) {
+            Some(market) => Some(market),
+            None => {
+                return HttpResponse::BadRequest()
+                    .json(serde_json::json!({ "error": format!("Unknown market {}", pair) }))
+            }
+        },
+        None => None,
+    };
+    let export = ExportQuery {
+        format,
+        market,
+        from: query
+            .from
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(secs)),
+        to: query.to.map(|secs| UNIX_EPOCH + Duration::from_secs(secs)),
+    };
+    let stats_cache = stats_cache
+        .get_ref()
+        .clone()
+        .expect("StatsCache is always set with statistics");
+    let chunks = stream::unfold(Some(ExportCursor::default()), move |cursor| {
+        let mut cursor = cursor?;
+        let export = export.clone();
+        Some(stats_cache.inner().map(move |stats| {
+            let (chunk, done) = stats.export(&export, &mut cursor);
+            (Bytes::from(chunk), if done { None } else { Some(cursor) })
+        }))
     })
+    .map_err(|_| error::ErrorInternalServerError("Statistics are unavailable"));
+    HttpResponse::Ok()
+        .content_type(format.content_type())
+        .streaming(
+            stream::once(Ok(Bytes::from(format.header())))
+                .chain(chunks)
+                .filter(|chunk| !chunk.is_empty()),
+        )
+}
+#[cfg(not(feature = "statistics"))]
+fn statistics_export(
+    _query: web::Query<StatisticsExportQuery>,
+    _stats_cache: web::Data<Option<StatsCache>>,
+) -> HttpResponse {
+    HttpResponse::NotFound()
+        .json(serde_json::json!({ "error": "risq was built without the statistics feature" }))
+}
+
+fn market_statistics(
+    req: HttpRequest,
+    market: web::Path<String>,
+    stats_cache: web::Data<Option<StatsCache>>,
+    response_cache: web::Data<ResponseCache>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    match Market::from_pair(&market) {
+        Some(market) => {
+            future::Either::A(summaries(req, &stats_cache, &response_cache, Some(market)))
+        }
+        None => future::Either::B(future::ok(HttpResponse::BadRequest().json(
+            serde_json::json!({ "error": format!("Unknown market {}", market.into_inner()) }),
+        ))),
+    }
+}
+
+// The summaries cover the last 24 hours, they are computed again every minute
+#[cfg(feature = "statistics")]
+fn summaries(
+    req: HttpRequest,
+    stats_cache: &Option<StatsCache>,
+    response_cache: &ResponseCache,
+    market: Option<&'static Market>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let stats_cache = stats_cache
+        .as_ref()
+        .expect("StatsCache is always set with statistics")
+        .clone();
+    let response_cache = response_cache.clone();
+    stats_cache
+        .inner()
+        .map(move |stats| {
+            let etag = ETag::new(&[stats.version(), cache::time_bucket(Duration::from_secs(60))]);
+            response_cache.lookup(&req, &etag).unwrap_or_else(|| {
+                response_cache.respond(
+                    &req,
+                    etag,
+                    &stats
+                        .summaries(market)
+                        .iter()
+                        .map(MarketSummaryInfo::from)
+                        .collect::<Vec<_>>(),
+                )
+            })
+        })
+        .from_err()
+}
+#[cfg(not(feature = "statistics"))]
+fn summaries(
+    _req: HttpRequest,
+    _stats_cache: &Option<StatsCache>,
+    _response_cache: &ResponseCache,
+    _market: Option<&'static Market>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    future::ok(
+        HttpResponse::NotFound()
+            .json(serde_json::json!({ "error": "risq was built without the statistics feature" })),
+    )
+}
+
+fn my_trades(
+    query: web::Query<TradeHistoryQuery>,
+    get_trades: web::Data<Recipient<GetTrades>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let csv = query
+        .format
+        .as_ref()
+        .map(|format| format == "csv")
+        .unwrap_or(false);
+    get_trades
+        .send(GetTrades)
+        .map(move |trades| {
+            let finished = trades
+                .iter()
+                .filter_map(|trade| history::completed_at(trade).map(|at| (trade, at)));
+            if csv {
+                HttpResponse::Ok()
+                    .content_type("text/csv; charset=utf-8")
+                    .body(history::csv(finished.map(|(trade, _)| trade)))
+            } else {
+                HttpResponse::Ok().json(
+                    finished
+                        .map(|(trade, at)| TradeRecordInfo::new(trade, at))
+                        .collect::<Vec<_>>(),
+                )
+            }
+        })
+        .from_err()
+}
+
+#[derive(serde::Deserialize)]
+struct TakeOfferRequest {
+    offer_id: String,
+    /// BTC amount, eg. `0.01`. Takes the full offer when missing.
+    amount: Option<String>,
+}
+
+fn trades(
+    get_trades: web::Data<Recipient<GetTrades>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    get_trades
+        .send(GetTrades)
+        .map(|trades| {
+            HttpResponse::Ok().json(trades.iter().map(TradeInfo::from).collect::<Vec<_>>())
+        })
+        .from_err()
+}
+
+fn take(
+    body: web::Json<TakeOfferRequest>,
+    take_offer: web::Data<Recipient<TakeOffer>>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let amount = match body
+        .amount
+        .as_ref()
+        .map(|amount| amount.parse())
+        .transpose()
+    {
+        Ok(amount) => amount,
+        Err(error) => {
+            return future::Either::A(future::ok(
+                HttpResponse::BadRequest().json(serde_json::json!({ "error": error })),
+            ))
+        }
+    };
+    future::Either::B(
+        take_offer
+            .send(TakeOffer {
+                offer_id: OfferId::from(body.offer_id.clone()),
+                amount,
+            })
+            .map(|result| match result {
+                Ok(trade) => HttpResponse::Ok().json(TradeInfo::from(&trade)),
+                Err(error) => {
+                    HttpResponse::BadRequest().json(serde_json::json!({ "error": error }))
+                }
+            })
+            .from_err(),
+    )
 }
@@ -0,0 +1,218 @@
+use super::responses::{Offer, OfferEvent};
+use crate::{
+    domain::{
+        offer::message::{OfferBookChange, SubscribeOfferBook},
+        payment_account::SignedWitnesses,
+    },
+    prelude::*,
+};
+use actix_web::{
+    error::{ErrorInternalServerError, PayloadError},
+    http::{header, StatusCode},
+    web::{self, Bytes},
+    HttpRequest, HttpResponse,
+};
+use futures::sync::mpsc::{self, UnboundedSender};
+use openssl::sha::sha1;
+use std::collections::HashMap;
+
+// WebSocket (RFC 6455) as far as pushing text to browsers needs it: the
+// handshake, unfragmented frames from us and control frames from clients.
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const FIN: u8 = 0x80;
+const MASKED: u8 = 0x80;
+const TEXT: u8 = 0x1;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xA;
+/// Clients only send control frames, which can't be longer.
+const MAX_CLIENT_FRAME: usize = 125;
+const PROTOCOL_ERROR: u16 = 1002;
+
+/// Upgrades the request to a WebSocket that receives every change of the
+/// offer book as JSON, tagged with `offer_added`, `offer_refreshed` or
+/// `offer_removed` under `event`.
+pub fn offer_stream(
+    req: HttpRequest,
+    payload: web::Payload,
+    subscribe: web::Data<Recipient<SubscribeOfferBook>>,
+    signed_witnesses: web::Data<SignedWitnesses>,
+) -> HttpResponse {
+    let key = match handshake_key(&req) {
+        Some(key) => key,
+        None => {
+            return HttpResponse::BadRequest()
+                .json(serde_json::json!({ "error": "Expected a WebSocket upgrade" }))
+        }
+    };
+    let (sender, receiver) = mpsc::unbounded();
+    let subscribe = subscribe.get_ref().clone();
+    let signed_witnesses = signed_witnesses.get_ref().clone();
+    OfferStream::create(move |ctx| {
+        ctx.add_stream(payload);
+        if subscribe
+            .do_send(SubscribeOfferBook(ctx.address().recipient()))
+            .is_err()
+        {
+            ctx.stop();
+        }
+        OfferStream {
+            out: sender,
+            received: Vec::new(),
+            signed_witnesses,
+        }
+    });
+    HttpResponse::build(StatusCode::SWITCHING_PROTOCOLS)
+        .upgrade("websocket")
+        .header(header::TRANSFER_ENCODING, "chunked")
+        .header(header::SEC_WEBSOCKET_ACCEPT, accept_key(&key))
+        .streaming(receiver.map_err(|_| ErrorInternalServerError("Offer stream failed")))
+}
+
+fn handshake_key(req: &HttpRequest) -> Option<String> {
+    let header = |name| {
+        req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_lowercase)
+    };
+    if !header(header::UPGRADE)?.contains("websocket")
+        || header(header::SEC_WEBSOCKET_VERSION)? != "13"
+    {
+        return None;
+    }
+    req.headers()
+        .get(header::SEC_WEBSOCKET_KEY)
+        .and_then(|key| key.to_str().ok())
+        .map(String::from)
+}
+
+fn accept_key(key: &str) -> String {
+    base64::encode(&sha1(format!("{}{}", key, GUID).as_bytes()))
+}
+
+fn push(change: OfferBookChange, signed_witnesses: &SignedWitnesses) -> OfferEvent {
+    let offer = |offer, premium| Offer {
+        premium,
+        ..Offer::new(&offer, &HashMap::new(), signed_witnesses)
+    };
+    match change {
+        OfferBookChange::Added { offer: o, premium } => OfferEvent::Added(offer(o, premium)),
+        OfferBookChange::Refreshed { offer: o, premium } => {
+            OfferEvent::Refreshed(offer(o, premium))
+        }
+        OfferBookChange::Removed(offer) => OfferEvent::Removed {
+            id: offer.id.into(),
+            market: offer.market.pair.clone(),
+        },
+    }
+}
+
+/// One per connection, lives until the client closes it.
+struct OfferStream {
+    out: UnboundedSender<Bytes>,
+    received: Vec<u8>,
+    signed_witnesses: SignedWitnesses,
+}
+impl Actor for OfferStream {
+    type Context = Context<Self>;
+}
+impl OfferStream {
+    fn send(&mut self, opcode: u8, payload: &[u8], ctx: &mut Context<Self>) {
+        if self.out.unbounded_send(frame(opcode, payload)).is_err() {
+            ctx.stop();
+        }
+    }
+
+    fn close(&mut self, code: u16, ctx: &mut Context<Self>) {
+        self.send(CLOSE, &code.to_be_bytes(), ctx);
+        ctx.stop();
+    }
+}
+impl Handler<OfferBookChange> for OfferStream {
+    type Result = ();
+    fn handle(&mut self, change: OfferBookChange, ctx: &mut Self::Context) {
+        match serde_json::to_vec(&push(change, &self.signed_witnesses)) {
+            Ok(json) => self.send(TEXT, &json, ctx),
+            Err(e) => warn!("Couldn't serialize offer book change: {}", e),
+        }
+    }
+}
+impl StreamHandler<Bytes, PayloadError> for OfferStream {
+    fn handle(&mut self, chunk: Bytes, ctx: &mut Self::Context) {
+        self.received.extend_from_slice(&chunk);
+        loop {
+            match next_frame(&mut self.received) {
+                Ok(Some((PING, payload))) => self.send(PONG, &payload, ctx),
+                Ok(Some((CLOSE, _))) => return self.close(1000, ctx),
+                Ok(Some(_)) => (),
+                Ok(None) => return,
+                Err(()) => return self.close(PROTOCOL_ERROR, ctx),
+            }
+        }
+    }
+}
+
+fn frame(opcode: u8, payload: &[u8]) -> Bytes {
+    let mut frame = vec![FIN | opcode];
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= 0xFFFF => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    Bytes::from(frame)
+}
+
+/// Takes the next complete frame from `received`, `Err` for anything a
+/// client mustn't send.
+fn next_frame(received: &mut Vec<u8>) -> Result<Option<(u8, Vec<u8>)>, ()> {
+    if received.len() < 2 {
+        return Ok(None);
+    }
+    let opcode = received[0] & 0x0F;
+    let len = (received[1] & 0x7F) as usize;
+    if received[1] & MASKED == 0 || len > MAX_CLIENT_FRAME {
+        return Err(());
+    }
+    if received.len() < 6 + len {
+        return Ok(None);
+    }
+    let frame: Vec<u8> = received.drain(..6 + len).collect();
+    let mask = &frame[2..6];
+    let payload = frame[6..]
+        .iter()
+        .enumerate()
+        .map(|(i, byte)| byte ^ mask[i % 4])
+        .collect();
+    Ok(Some((opcode, payload)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handshake_and_frames() {
+        // Example of RFC 6455 section 1.3
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+
+        assert_eq!(&frame(TEXT, b"Hi")[..], &[0x81, 2, b'H', b'i']);
+        assert_eq!(&frame(TEXT, &[0; 200])[..4], &[0x81, 126, 0, 200]);
+
+        let mut received = vec![0x89, 0x82, 1, 2, 3, 4, b'o' ^ 1, b'k' ^ 2, 0x88];
+        assert_eq!(next_frame(&mut received), Ok(Some((PING, b"ok".to_vec()))));
+        assert_eq!(next_frame(&mut received), Ok(None));
+        received.push(0x02);
+        assert_eq!(next_frame(&mut received), Err(()));
+    }
+}
@@ -0,0 +1,234 @@
+use crate::events::{Event, Events};
+use std::{
+    io::{self, Read, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    sync::{mpsc::Receiver, Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+// ZMTP 3.0 (https://rfc.zeromq.org/spec/23/) with the NULL mechanism, just
+// enough to be a PUB socket for libzmq's SUB and XSUB sockets.
+const GREETING_SIZE: usize = 64;
+const MORE: u8 = 0x01;
+const LONG: u8 = 0x02;
+const COMMAND: u8 = 0x04;
+const SUBSCRIBE: u8 = 0x01;
+const UNSUBSCRIBE: u8 = 0x00;
+/// Subscribers that can't keep up are dropped instead of stalling the others.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+type Subscribers = Arc<Mutex<Vec<Subscriber>>>;
+
+struct Subscriber {
+    stream: TcpStream,
+    prefixes: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+impl Subscriber {
+    fn wants(&self, topic: &str) -> bool {
+        self.prefixes
+            .lock()
+            .expect("Corrupted lock in zmq subscriber")
+            .iter()
+            .any(|prefix| topic.as_bytes().starts_with(prefix))
+    }
+}
+
+/// Publishes the events of the daemon on a zmq PUB socket on localhost,
+/// the topic frame is followed by the event as JSON.
+pub fn publish(port: u16, events: &Events) -> io::Result<()> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+    info!("Publishing events on tcp://127.0.0.1:{}", port);
+    serve(listener, events.subscribe());
+    Ok(())
+}
+
+fn serve(listener: TcpListener, events: Receiver<Event>) {
+    let subscribers = Subscribers::default();
+    let accepting = subscribers.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let subscribers = accepting.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = subscribe(stream, subscribers) {
+                            debug!("zmq subscriber is gone: {}", e);
+                        }
+                    });
+                }
+                Err(e) => warn!("Couldn't accept zmq subscriber: {}", e),
+            }
+        }
+    });
+    thread::spawn(move || {
+        for event in events {
+            let body = match serde_json::to_vec(&event) {
+                Ok(body) => body,
+                Err(e) => {
+                    error!("Couldn't serialize {:?}: {}", event, e);
+                    continue;
+                }
+            };
+            let topic = event.topic();
+            let mut message = frame(MORE, topic.as_bytes());
+            message.extend(frame(0, &body));
+            subscribers
+                .lock()
+                .expect("Corrupted lock in zmq subscribers")
+                .retain(|subscriber| {
+                    !subscriber.wants(topic) || (&subscriber.stream).write_all(&message).is_ok()
+                });
+        }
+    });
+}
+
+// Handshakes, then tracks the subscriptions until the subscriber disconnects.
+fn subscribe(mut stream: TcpStream, subscribers: Subscribers) -> io::Result<()> {
+    stream.write_all(&greeting())?;
+    let mut peer_greeting = [0; GREETING_SIZE];
+    stream.read_exact(&mut peer_greeting)?;
+    if peer_greeting[0] != 0xFF || peer_greeting[9] != 0x7F || peer_greeting[10] < 3 {
+        return Err(invalid("not a ZMTP 3 peer"));
+    }
+    if !peer_greeting[12..32].starts_with(b"NULL\0") {
+        return Err(invalid("only the NULL mechanism is supported"));
+    }
+    stream.write_all(&frame(COMMAND, &ready()))?;
+    let (flags, ready) = read_frame(&mut stream)?;
+    if flags & COMMAND == 0 || !ready.starts_with(b"\x05READY") {
+        return Err(invalid("expected READY"));
+    }
+
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let prefixes = Arc::new(Mutex::new(Vec::new()));
+    subscribers
+        .lock()
+        .expect("Corrupted lock in zmq subscribers")
+        .push(Subscriber {
+            stream: stream.try_clone()?,
+            prefixes: prefixes.clone(),
+        });
+    loop {
+        let (flags, body) = read_frame(&mut stream)?;
+        // ZMTP 3.0 subscribes with messages, 3.1 with commands
+        let (subscribe, prefix) = match (flags & COMMAND != 0, body.split_first()) {
+            (false, Some((&SUBSCRIBE, prefix))) => (true, prefix),
+            (false, Some((&UNSUBSCRIBE, prefix))) => (false, prefix),
+            (true, _) if body.starts_with(b"\x09SUBSCRIBE") => (true, &body[10..]),
+            (true, _) if body.starts_with(b"\x06CANCEL") => (false, &body[7..]),
+            _ => continue,
+        };
+        let mut prefixes = prefixes.lock().expect("Corrupted lock in zmq subscriber");
+        if subscribe {
+            prefixes.push(prefix.to_vec());
+        } else if let Some(idx) = prefixes.iter().position(|p| p.as_slice() == prefix) {
+            prefixes.remove(idx);
+        }
+    }
+}
+
+fn greeting() -> [u8; GREETING_SIZE] {
+    let mut greeting = [0; GREETING_SIZE];
+    greeting[0] = 0xFF;
+    greeting[8] = 0x01;
+    greeting[9] = 0x7F;
+    greeting[10] = 3;
+    greeting[12..16].copy_from_slice(b"NULL");
+    greeting
+}
+
+fn ready() -> Vec<u8> {
+    let mut ready = b"\x05READY\x0bSocket-Type".to_vec();
+    ready.extend_from_slice(&3u32.to_be_bytes());
+    ready.extend_from_slice(b"PUB");
+    ready
+}
+
+fn frame(flags: u8, body: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(body.len() + 9);
+    if body.len() > 255 {
+        frame.push(flags | LONG);
+        frame.extend_from_slice(&(body.len() as u64).to_be_bytes());
+    } else {
+        frame.push(flags);
+        frame.push(body.len() as u8);
+    }
+    frame.extend_from_slice(body);
+    frame
+}
+
+fn read_frame(stream: &mut TcpStream) -> io::Result<(u8, Vec<u8>)> {
+    let mut flags = [0];
+    stream.read_exact(&mut flags)?;
+    let size = if flags[0] & LONG != 0 {
+        let mut size = [0; 8];
+        stream.read_exact(&mut size)?;
+        u64::from_be_bytes(size)
+    } else {
+        let mut size = [0];
+        stream.read_exact(&mut size)?;
+        u64::from(size[0])
+    };
+    // Subscriptions are short, anything big isn't from a SUB socket
+    if size > 1024 {
+        return Err(invalid("frame too large"));
+    }
+    let mut body = vec![0; size as usize];
+    stream.read_exact(&mut body)?;
+    Ok((flags[0], body))
+}
+
+fn invalid(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn publishes_subscribed_topics() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let events = Events::default();
+        serve(listener, events.subscribe());
+
+        let mut sub = TcpStream::connect(addr).unwrap();
+        sub.write_all(&greeting()).unwrap();
+        let mut greeting = [0; GREETING_SIZE];
+        sub.read_exact(&mut greeting).unwrap();
+        assert_eq!(&greeting[12..16], b"NULL");
+        let (flags, ready) = read_frame(&mut sub).unwrap();
+        assert_eq!(flags, COMMAND);
+        assert!(ready.ends_with(b"Socket-Type\0\0\0\x03PUB"));
+        let mut ready = b"\x05READY\x0bSocket-Type".to_vec();
+        ready.extend_from_slice(b"\0\0\0\x03SUB");
+        sub.write_all(&frame(COMMAND, &ready)).unwrap();
+        sub.write_all(&frame(0, b"\x01trades")).unwrap();
+
+        // Until the subscription arrived, both events are dropped
+        thread::spawn(move || {
+            for _ in 0..100 {
+                events.publish(Event::PeerDisconnected {
+                    addr: "peer.onion:9999".into(),
+                });
+                events.publish(Event::TradeChanged {
+                    id: "trade".into(),
+                    market: "btc_eur".into(),
+                    role: "Taker".into(),
+                    state: "Failed".into(),
+                    error: None,
+                });
+                thread::sleep(Duration::from_millis(20));
+            }
+        });
+        assert_eq!(read_frame(&mut sub).unwrap(), (MORE, b"trades".to_vec()));
+        let (flags, body) = read_frame(&mut sub).unwrap();
+        assert_eq!(flags, 0);
+        assert_eq!(
+            String::from_utf8(body).unwrap(),
+            r#"{"event":"trade_changed","id":"trade","market":"btc_eur","role":"Taker","state":"Failed","error":null}"#
+        );
+    }
+}
@@ -1,8 +1,13 @@
 use super::payload::NodeAddress;
 use lazy_static::lazy_static;
-use std::{convert::TryFrom, str::FromStr};
+use std::{
+    convert::TryFrom,
+    fmt,
+    str::FromStr,
+    sync::atomic::{AtomicI32, Ordering},
+};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum BaseCurrencyNetwork {
     BtcMainnet,
     BtcTestnet,
@@ -21,14 +26,53 @@ impl FromStr for BaseCurrencyNetwork {
     }
 }
 
-pub(super) const P2P_NETWORK_VERSION: i32 = 1;
+impl BaseCurrencyNetwork {
+    /// Lowercase without the currency, eg. `mainnet`
+    pub fn short_name(self) -> &'static str {
+        match self {
+            BaseCurrencyNetwork::BtcMainnet => "mainnet",
+            BaseCurrencyNetwork::BtcTestnet => "testnet",
+            BaseCurrencyNetwork::BtcRegtest => "regtest",
+        }
+    }
+}
+
+/// bisq's `Version.P2P_NETWORK_VERSION`, the tens of the message version
+pub const DEFAULT_P2P_NETWORK_VERSION: i32 = 1;
+// By `BaseCurrencyNetwork`
+static P2P_NETWORK_VERSIONS: [AtomicI32; 3] = [
+    AtomicI32::new(DEFAULT_P2P_NETWORK_VERSION),
+    AtomicI32::new(DEFAULT_P2P_NETWORK_VERSION),
+    AtomicI32::new(DEFAULT_P2P_NETWORK_VERSION),
+];
+
+/// Follows a protocol bump of bisq on `network`, connections opened from
+/// now on send `version`.
+pub fn set_p2p_network_version(network: BaseCurrencyNetwork, version: i32) {
+    P2P_NETWORK_VERSIONS[network as usize].store(version, Ordering::Relaxed);
+}
+
+pub fn p2p_network_version(network: BaseCurrencyNetwork) -> i32 {
+    P2P_NETWORK_VERSIONS[network as usize].load(Ordering::Relaxed)
+}
 
 pub fn seed_nodes(network: BaseCurrencyNetwork) -> Vec<NodeAddress> {
     match network {
-        BaseCurrencyNetwork::BtcRegtest => vec![NodeAddress {
-            host_name: "127.0.0.1".to_string(),
-            port: 4002,
-        }],
+        // The seed nodes bisq runs on regtest and `risq dummy-seed`
+        BaseCurrencyNetwork::BtcRegtest => vec![
+            NodeAddress {
+                host_name: "localhost".to_string(),
+                port: 2002,
+            },
+            NodeAddress {
+                host_name: "localhost".to_string(),
+                port: 3002,
+            },
+            NodeAddress {
+                host_name: "127.0.0.1".to_string(),
+                port: 4002,
+            },
+        ],
         BaseCurrencyNetwork::BtcTestnet => vec![NodeAddress {
             host_name: "m5izk3fvjsjbmkqi.onion".to_string(),
             port: 8001,
@@ -40,6 +84,68 @@ pub fn seed_nodes(network: BaseCurrencyNetwork) -> Vec<NodeAddress> {
     }
 }
 
+/// Receiver of trade fees paid in BTC. These are the defaults of bisq's
+/// `Param.RECIPIENT_BTC_ADDRESS`, the DAO can vote for a different one.
+pub fn btc_fee_receiver(network: BaseCurrencyNetwork) -> &'static str {
+    match network {
+        BaseCurrencyNetwork::BtcMainnet => "1BVxNn3T12veSK6DgqwU4Hdn7QHcDDRag7",
+        BaseCurrencyNetwork::BtcTestnet => "2N4mVTpUZAnhm9phnxB7VrHB4aBhnWrcUrV",
+        BaseCurrencyNetwork::BtcRegtest => "2MzBNTJDjjXgViKBGnatDU3yWkJ8pJkEg9w",
+    }
+}
+
+/// bisq's `DevEnv.DEV_PRIVILEGE_PUB_KEY`, which signs filters and alerts
+/// outside of mainnet
+const DEV_PRIVILEGE_PUB_KEY: &str =
+    "027a381b5333a56e1cc3d90d3a7d07f26509adf7029ed06fc997c656621f8da1ee";
+
+/// Keys of bisq's `FilterManager`, one of them signs the filter.
+pub fn filter_pub_keys(network: BaseCurrencyNetwork) -> Vec<&'static str> {
+    match network {
+        BaseCurrencyNetwork::BtcMainnet => vec![
+            "0358d47858acdc41910325fce266571540681ef83a0d6fedce312bef9810793a27",
+            "029340c3e7d4bb0f9e651b5f590b434fecb6175aeaa57145c7804ff05d210e534f",
+            "034dc7530bf66ffd9580aa98031ea9a18ac2d269f7c56c0e71eca06105b9ed69f9",
+        ],
+        _ => vec![DEV_PRIVILEGE_PUB_KEY],
+    }
+}
+
+/// Keys of bisq's `ArbitratorManager`. An account age witness an arbitrator
+/// signed with one of them starts a chain of signed accounts.
+pub fn arbitrator_pub_keys(network: BaseCurrencyNetwork) -> Vec<&'static str> {
+    match network {
+        BaseCurrencyNetwork::BtcMainnet => vec![
+            "0365c6af94681dbee69de1851f98d4684063bf5c2d64b1c73ed5d90434f375a054",
+            "031c502a60f9dbdb5ae5e438a79819e4e1f417211dd537ac12c9bc23246534c4bd",
+            "02c1e5a242387b6d5319ce27246cea6edaaf51c3550591b528d2578a4753c56c2c",
+            "025c319faf7067d9299590dd6c97fe7e56cd4dac61205ccee1cd1fc390142390a2",
+            "038f6e24c2bfe5d51d0a290f20a9a657c270b94ef2b9c12cd15ca3725fa798fc55",
+            "0255256ff7fb615278c4544a9bbd3f5298b903b8a011cd7889be19b6b1c45cbefe",
+            "024a3a37289f08c910fbd925ebc72b946f33feaeff451a4738ee82037b4cda2e95",
+            "02a88b75e9f0f8afba1467ab26799dcc38fd7a6468fb2795444b425eb43e2c10bd",
+            "02349a51512c1c04c67118386f4d27d768c5195a83247c150a4b722d161722ba81",
+            "03f718a2e0dc672c7cdec0113e72c3322efc70412bb95870750d25c32cd98de17d",
+            "028ff47ee2c56e66313928975c58fa4f1b19a0f81f3a96c4e9c9c3c6768075509e",
+            "02b517c0cbc3a49548f448ddf004ed695c5a1c52ec110be1bfd65fa0ca0761c94b",
+            "03df837a3a0f3d858e82f3356b71d1285327f101f7c10b404abed2abc1c94e7169",
+            "0203a90fb2ab698e524a5286f317a183a84327b8f8c3f7fa4a98fec9e1cefd6b72",
+            "023c99cc073b851c892d8c43329ca3beb5d2213ee87111af49884e3ce66cbd5ba5",
+        ],
+        _ => vec![DEV_PRIVILEGE_PUB_KEY],
+    }
+}
+
+/// Key of bisq's `AlertManager`, which signs alerts.
+pub fn alert_pub_keys(network: BaseCurrencyNetwork) -> Vec<&'static str> {
+    match network {
+        BaseCurrencyNetwork::BtcMainnet => {
+            vec!["036d8a1dfcb406886037d2381da006358722823e1940acc2598c844bbc0fd1026f"]
+        }
+        _ => vec![DEV_PRIVILEGE_PUB_KEY],
+    }
+}
+
 pub fn price_nodes(use_tor_proxy: bool) -> Vec<&'static str> {
     if use_tor_proxy {
         vec![
@@ -63,7 +169,7 @@ lazy_static! {
         vec
     };
 }
-static SUPPORTED_CAPABILITIES: [Capability; 12] = [
+static SUPPORTED_CAPABILITIES: [Capability; 13] = [
     Capability::TradeStatistics,
     Capability::TradeStatistics2,
     Capability::AccountAgeWitness,
@@ -75,10 +181,11 @@ static SUPPORTED_CAPABILITIES: [Capability; 12] = [
     Capability::BundleOfEnvelopes,
     Capability::SignedAccountAgeWitness,
     Capability::Mediation,
+    Capability::RefundAgent,
     Capability::TradeStatisticsHashUpdate,
 ];
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Capability {
     TradeStatistics, // Not required anymore as no old clients out there not having that support
     TradeStatistics2, // Not required anymore as no old clients out there not having that support
@@ -123,6 +230,53 @@ impl TryFrom<i32> for Capability {
     }
 }
 
+/// The capabilities a node announced, in `GetPeersRequest` and the like or as
+/// the comma separated `capabilities` in the `extra_data` of its offers.
+/// Ordinals of capabilities newer than ours are ignored.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Capabilities(u32);
+impl Capabilities {
+    pub fn contains(self, capability: Capability) -> bool {
+        self.0 & (1 << capability as u32) != 0
+    }
+    pub fn contains_all(self, capabilities: &[Capability]) -> bool {
+        capabilities
+            .iter()
+            .all(|capability| self.contains(*capability))
+    }
+}
+impl From<&[i32]> for Capabilities {
+    fn from(ordinals: &[i32]) -> Self {
+        Capabilities(
+            ordinals
+                .iter()
+                .filter_map(|n| Capability::try_from(*n).ok())
+                .fold(0, |bits, capability| bits | 1 << capability as u32),
+        )
+    }
+}
+impl FromStr for Capabilities {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let ordinals = s
+            .split(',')
+            .map(str::trim)
+            .filter(|n| !n.is_empty())
+            .map(|n| n.parse().map_err(|_| format!("Invalid capability '{}'", n)))
+            .collect::<Result<Vec<i32>, _>>()?;
+        Ok(Capabilities::from(&ordinals[..]))
+    }
+}
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ordinals: Vec<String> = (0..32)
+            .filter(|n| self.0 & (1 << n) != 0)
+            .map(|n| n.to_string())
+            .collect();
+        write!(f, "{}", ordinals.join(", "))
+    }
+}
+
 pub enum CloseConnectionReason {
     SocketClosed,
     Reset,
@@ -131,3 +131,14 @@ impl ResponseExtractor for Ping {
         .expect("Msg was not the extpected response type")
     }
 }
+impl ResponseExtractor for GetBlocksRequest {
+    type Response = GetBlocksResponse;
+
+    fn extract(msg: Message) -> Self::Response {
+        match msg {
+            Message::GetBlocksResponse(response) => Some(response),
+            _ => None,
+        }
+        .expect("Msg was not the extpected response type")
+    }
+}
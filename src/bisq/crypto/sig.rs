@@ -0,0 +1,155 @@
+// The keys bisq signs payloads with. Older payloads are signed with DSA
+// keys, newer ones with secp256k1 keys, either as DER encoded java keys or
+// as the plain points bitcoinj serializes.
+use crate::prelude::{sha256, Hash};
+use bitcoin::secp256k1::{self, PublicKey, Secp256k1, Signature, VerifyOnly};
+use lazy_static::lazy_static;
+use openssl::{
+    bn::BigNumContext,
+    ec::PointConversionForm,
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::{Id, PKey, Public},
+    sign::Verifier,
+};
+
+lazy_static! {
+    static ref SECP: Secp256k1<VerifyOnly> = Secp256k1::verification_only();
+}
+
+pub enum SigKey {
+    Dsa(PKey<Public>),
+    Ec(PublicKey),
+}
+impl SigKey {
+    /// Detects the kind of key from its encoding, `None` for keys of other
+    /// algorithms or curves.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if let Ok(key) = PublicKey::from_slice(bytes) {
+            return Some(SigKey::Ec(key));
+        }
+        let key = PKey::public_key_from_der(bytes).ok()?;
+        match key.id() {
+            Id::DSA => Some(SigKey::Dsa(key)),
+            Id::EC => {
+                let ec = key.ec_key().ok()?;
+                if ec.group().curve_name() != Some(Nid::SECP256K1) {
+                    return None;
+                }
+                let mut ctx = BigNumContext::new().ok()?;
+                let point = ec
+                    .public_key()
+                    .to_bytes(ec.group(), PointConversionForm::COMPRESSED, &mut ctx)
+                    .ok()?;
+                PublicKey::from_slice(&point).ok().map(SigKey::Ec)
+            }
+            _ => None,
+        }
+    }
+
+    /// Checks a DER encoded `SHA256withDSA` or `SHA256withECDSA` signature
+    /// of `data`, the algorithms bisq's `Sig` signs with.
+    pub fn verify(&self, signature: &[u8], data: &[u8]) -> bool {
+        match self {
+            SigKey::Dsa(key) => Verifier::new(MessageDigest::sha256(), key)
+                .and_then(|mut verifier| {
+                    verifier.update(data)?;
+                    verifier.verify(signature)
+                })
+                .unwrap_or(false),
+            SigKey::Ec(key) => {
+                let mut signature = match Signature::from_der_lax(signature) {
+                    Ok(signature) => signature,
+                    Err(_) => return false,
+                };
+                // Java doesn't produce low S signatures like bitcoin does
+                signature.normalize_s();
+                let message = secp256k1::Message::from_slice(&sha256::Hash::hash(data)[..])
+                    .expect("Hash has the message length");
+                SECP.verify(&message, &signature, key).is_ok()
+            }
+        }
+    }
+}
+impl PartialEq for SigKey {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (SigKey::Dsa(key), SigKey::Dsa(other)) => key.public_eq(other),
+            (SigKey::Ec(key), SigKey::Ec(other)) => key == other,
+            _ => false,
+        }
+    }
+}
+
+/// Checks a signature of `data` by an encoded key, `None` if the key can't
+/// be parsed.
+pub fn verify(pub_key: &[u8], signature: &[u8], data: &[u8]) -> Option<bool> {
+    SigKey::from_bytes(pub_key).map(|key| key.verify(signature, data))
+}
+
+/// Whether both encode the same key, a secp256k1 key may come as DER or as
+/// a plain point.
+pub fn same_key(key: &[u8], other: &[u8]) -> bool {
+    key == other
+        || match (SigKey::from_bytes(key), SigKey::from_bytes(other)) {
+            (Some(key), Some(other)) => key == other,
+            _ => false,
+        }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::{
+        dsa::Dsa,
+        ec::{EcGroup, EcKey},
+        pkey::Private,
+        sign::Signer,
+    };
+
+    fn sign(key: &PKey<Private>, data: &[u8]) -> Vec<u8> {
+        let mut signer = Signer::new(MessageDigest::sha256(), key).unwrap();
+        signer.update(data).unwrap();
+        signer.sign_to_vec().unwrap()
+    }
+
+    fn generate_ec(curve: Nid) -> PKey<Private> {
+        let group = EcGroup::from_curve_name(curve).unwrap();
+        PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn verifies_dsa_and_ec_signatures() {
+        let dsa = PKey::from_dsa(Dsa::generate(1024).unwrap()).unwrap();
+        let dsa_pub = dsa.public_key_to_der().unwrap();
+        let signature = sign(&dsa, b"payload");
+        assert_eq!(verify(&dsa_pub, &signature, b"payload"), Some(true));
+        assert_eq!(verify(&dsa_pub, &signature, b"other"), Some(false));
+        assert_eq!(verify(&dsa_pub, b"garbage", b"payload"), Some(false));
+
+        let ec = generate_ec(Nid::SECP256K1);
+        let ec_pub = ec.public_key_to_der().unwrap();
+        let signature = sign(&ec, b"payload");
+        assert_eq!(verify(&ec_pub, &signature, b"payload"), Some(true));
+        assert_eq!(verify(&ec_pub, &signature, b"other"), Some(false));
+        assert_eq!(verify(&dsa_pub, &signature, b"payload"), Some(false));
+
+        let ec_key = ec.ec_key().unwrap();
+        let point = ec_key
+            .public_key()
+            .to_bytes(
+                ec_key.group(),
+                PointConversionForm::COMPRESSED,
+                &mut BigNumContext::new().unwrap(),
+            )
+            .unwrap();
+        assert_eq!(verify(&point, &signature, b"payload"), Some(true));
+        assert!(same_key(&point, &ec_pub));
+        assert!(!same_key(&point, &dsa_pub));
+
+        let p256 = generate_ec(Nid::X9_62_PRIME256V1);
+        let p256_pub = p256.public_key_to_der().unwrap();
+        assert_eq!(verify(&p256_pub, &sign(&p256, b"x"), b"x"), None);
+        assert_eq!(verify(&dsa_pub[1..], &signature, b"payload"), None);
+    }
+}
@@ -0,0 +1,229 @@
+// DSA signature verification without openssl for targets that can't link it
+// (wasm32). Only as much bignum arithmetic as checking a bisq signature needs,
+// constant time doesn't matter as everything involved is public.
+use std::cmp::Ordering;
+
+/// Checks a DER encoded DSA signature of `digest` like openssl's
+/// `Verifier::new_without_digest` does, `None` if the key or signature
+/// can't be parsed.
+pub fn verify(pub_key: &[u8], signature: &[u8], digest: &[u8]) -> Option<bool> {
+    let (p, q, g, y) = parse_pub_key(pub_key)?;
+    let mut sig = Der::sequence(signature)?;
+    let (r, s) = (sig.integer()?, sig.integer()?);
+    if r.is_zero()
+        || s.is_zero()
+        || r.compare(&q) != Ordering::Less
+        || s.compare(&q) != Ordering::Less
+    {
+        return Some(false);
+    }
+    // Leftmost bytes of the digest when it is longer than q
+    let digest = &digest[..digest.len().min(q.bits() / 8)];
+    let z = BigUint::from_be_bytes(digest);
+
+    // q is prime, so s^(q-2) is the inverse of s
+    let w = s.mod_pow(&q.sub_small(2), &q);
+    let u1 = z.mul(&w).rem(&q);
+    let u2 = r.mul(&w).rem(&q);
+    let v = g.mod_pow(&u1, &p).mul(&y.mod_pow(&u2, &p)).rem(&p).rem(&q);
+    Some(v == r)
+}
+
+// SubjectPublicKeyInfo { { dsa oid, { p, q, g } }, BIT STRING of y }
+fn parse_pub_key(der: &[u8]) -> Option<(BigUint, BigUint, BigUint, BigUint)> {
+    let mut info = Der::sequence(der)?;
+    let mut algorithm = Der(info.next(SEQUENCE)?);
+    if algorithm.next(OBJECT_IDENTIFIER)? != DSA_OID {
+        return None;
+    }
+    let mut params = Der(algorithm.next(SEQUENCE)?);
+    let (p, q, g) = (params.integer()?, params.integer()?, params.integer()?);
+    let key = info.next(BIT_STRING)?;
+    let y = Der(key.get(1..)?).integer()?;
+    Some((p, q, g, y))
+}
+
+const INTEGER: u8 = 0x02;
+const BIT_STRING: u8 = 0x03;
+const OBJECT_IDENTIFIER: u8 = 0x06;
+const SEQUENCE: u8 = 0x30;
+/// 1.2.840.10040.4.1
+const DSA_OID: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x38, 0x04, 0x01];
+
+struct Der<'a>(&'a [u8]);
+impl<'a> Der<'a> {
+    fn sequence(input: &'a [u8]) -> Option<Self> {
+        Der(input).next(SEQUENCE).map(Der)
+    }
+
+    fn next(&mut self, tag: u8) -> Option<&'a [u8]> {
+        let (&found, rest) = self.0.split_first()?;
+        let (&first, rest) = rest.split_first()?;
+        let (len, rest) = if first < 0x80 {
+            (first as usize, rest)
+        } else {
+            let bytes = (first & 0x7F) as usize;
+            if bytes > 4 || rest.len() < bytes {
+                return None;
+            }
+            let (len, rest) = rest.split_at(bytes);
+            (
+                len.iter().fold(0, |len, byte| len << 8 | *byte as usize),
+                rest,
+            )
+        };
+        if found != tag || rest.len() < len {
+            return None;
+        }
+        let (content, rest) = rest.split_at(len);
+        self.0 = rest;
+        Some(content)
+    }
+
+    fn integer(&mut self) -> Option<BigUint> {
+        self.next(INTEGER).map(BigUint::from_be_bytes)
+    }
+}
+
+/// Little endian 32 bit limbs without leading zeros.
+#[derive(Clone, Debug, PartialEq)]
+struct BigUint(Vec<u32>);
+impl BigUint {
+    fn from_be_bytes(bytes: &[u8]) -> Self {
+        let limbs = bytes
+            .rchunks(4)
+            .map(|chunk| chunk.iter().fold(0, |limb, byte| limb << 8 | *byte as u32))
+            .collect();
+        Self::normalized(limbs)
+    }
+
+    fn normalized(mut limbs: Vec<u32>) -> Self {
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        BigUint(limbs)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn bits(&self) -> usize {
+        match self.0.last() {
+            Some(top) => self.0.len() * 32 - top.leading_zeros() as usize,
+            None => 0,
+        }
+    }
+
+    fn bit(&self, i: usize) -> bool {
+        self.0[i / 32] >> (i % 32) & 1 == 1
+    }
+
+    fn compare(&self, other: &Self) -> Ordering {
+        self.0
+            .len()
+            .cmp(&other.0.len())
+            .then_with(|| self.0.iter().rev().cmp(other.0.iter().rev()))
+    }
+
+    fn sub_small(&self, n: u32) -> Self {
+        let mut limbs = self.0.clone();
+        let mut borrow = n;
+        for limb in limbs.iter_mut() {
+            let (diff, overflow) = limb.overflowing_sub(borrow);
+            *limb = diff;
+            borrow = overflow as u32;
+            if borrow == 0 {
+                break;
+            }
+        }
+        Self::normalized(limbs)
+    }
+
+    fn mul(&self, other: &Self) -> Self {
+        let mut limbs = vec![0u32; self.0.len() + other.0.len()];
+        for (i, a) in self.0.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, b) in other.0.iter().enumerate() {
+                let t = *a as u64 * *b as u64 + limbs[i + j] as u64 + carry;
+                limbs[i + j] = t as u32;
+                carry = t >> 32;
+            }
+            limbs[i + other.0.len()] = carry as u32;
+        }
+        Self::normalized(limbs)
+    }
+
+    // Shift and subtract, one bit at a time
+    fn rem(&self, modulus: &Self) -> Self {
+        let mut rem = BigUint(Vec::with_capacity(modulus.0.len() + 1));
+        for i in (0..self.bits()).rev() {
+            rem.shl1(self.bit(i));
+            if rem.compare(modulus) != Ordering::Less {
+                rem.sub_assign(modulus);
+            }
+        }
+        rem
+    }
+
+    fn shl1(&mut self, bit: bool) {
+        let mut carry = bit as u32;
+        for limb in self.0.iter_mut() {
+            let next = *limb >> 31;
+            *limb = *limb << 1 | carry;
+            carry = next;
+        }
+        if carry != 0 {
+            self.0.push(carry);
+        }
+    }
+
+    // Requires self >= other
+    fn sub_assign(&mut self, other: &Self) {
+        let mut borrow = false;
+        for (i, limb) in self.0.iter_mut().enumerate() {
+            let sub = other.0.get(i).cloned().unwrap_or(0);
+            let (diff, overflow1) = limb.overflowing_sub(sub);
+            let (diff, overflow2) = diff.overflowing_sub(borrow as u32);
+            *limb = diff;
+            borrow = overflow1 || overflow2;
+        }
+        while self.0.last() == Some(&0) {
+            self.0.pop();
+        }
+    }
+
+    fn mod_pow(&self, exp: &Self, modulus: &Self) -> Self {
+        let base = self.rem(modulus);
+        let mut result = BigUint(vec![1]).rem(modulus);
+        for i in (0..exp.bits()).rev() {
+            result = result.mul(&result).rem(modulus);
+            if exp.bit(i) {
+                result = result.mul(&base).rem(modulus);
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::{sha256, Hash};
+    use openssl::{dsa::Dsa, hash::MessageDigest, pkey::PKey, sign::Signer};
+
+    #[test]
+    fn verifies_like_openssl() {
+        let key = PKey::from_dsa(Dsa::generate(1024).unwrap()).unwrap();
+        let pub_key = key.public_key_to_der().unwrap();
+        let mut signer = Signer::new(MessageDigest::sha256(), &key).unwrap();
+        signer.update(b"payload").unwrap();
+        let signature = signer.sign_to_vec().unwrap();
+
+        let digest = sha256::Hash::hash(b"payload").into_inner();
+        assert_eq!(verify(&pub_key, &signature, &digest), Some(true));
+        let other = sha256::Hash::hash(b"other").into_inner();
+        assert_eq!(verify(&pub_key, &signature, &other), Some(false));
+        assert_eq!(verify(&pub_key[1..], &signature, &digest), None);
+    }
+}
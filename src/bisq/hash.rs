@@ -14,12 +14,16 @@ impl From<PersistentMessageHash> for Vec<u8> {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 pub struct SequencedMessageHash(sha256::Hash);
 impl SequencedMessageHash {
     pub fn new(inner: sha256::Hash) -> Self {
         SequencedMessageHash(inner)
     }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0[..]
+    }
 }
 impl From<SequencedMessageHash> for Vec<u8> {
     fn from(hash: SequencedMessageHash) -> Vec<u8> {
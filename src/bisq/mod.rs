@@ -1,9 +1,14 @@
+#[cfg(any(target_arch = "wasm32", test))]
+mod dsa;
 mod hash;
 
 pub mod constants;
 #[macro_use]
 pub mod payload;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod correlation;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod crypto;
 
 pub use hash::*;
 pub use payload::NodeAddress;
@@ -4,6 +4,19 @@ use prost::{encoding, DecodeError, Message};
 #[cfg_attr(feature = "dummy-seed", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone, Default, PartialEq)]
 pub struct JavaStringMapEntry(String, String);
+impl JavaStringMapEntry {
+    pub fn new(key: String, value: String) -> Self {
+        JavaStringMapEntry(key, value)
+    }
+
+    pub fn key(&self) -> &str {
+        &self.0
+    }
+
+    pub fn value(&self) -> &str {
+        &self.1
+    }
+}
 
 // Java protobuf lib always serializes key and value in map fields
 // Prost skips serializing value if it == the default ("" for string)
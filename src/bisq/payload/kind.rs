@@ -1,6 +1,7 @@
-use crate::bisq::payload::*;
+use crate::bisq::{constants::Capability, payload::*};
+use std::{fmt, time::Duration};
 
-#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub enum StoragePayloadKind {
     Alert,
     Arbitrator,
@@ -12,11 +13,50 @@ pub enum StoragePayloadKind {
     MailboxStoragePayload,
     OfferPayload,
     TempProposalPayload,
+    RefundAgent,
+    #[default]
     Unknown,
 }
-impl Default for StoragePayloadKind {
-    fn default() -> Self {
-        Self::Unknown
+impl StoragePayloadKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Alert => "Alert",
+            Self::Arbitrator => "Arbitrator",
+            Self::Mediator => "Mediator",
+            Self::Filter => "Filter",
+            Self::TradeStatistics => "TradeStatistics",
+            Self::MailboxStoragePayload => "MailboxStoragePayload",
+            Self::OfferPayload => "OfferPayload",
+            Self::TempProposalPayload => "TempProposalPayload",
+            Self::RefundAgent => "RefundAgent",
+            Self::Unknown => "Unknown",
+        }
+    }
+    /// How long an entry lives after its creation or last refresh, as in
+    /// the `getTTL()` of bisq's payloads.
+    pub fn ttl(self) -> Duration {
+        const MINUTE: u64 = 60;
+        const DAY: u64 = 24 * 60 * MINUTE;
+        Duration::from_secs(match self {
+            Self::Alert => 90 * DAY,
+            Self::Arbitrator | Self::Mediator | Self::RefundAgent => 10 * DAY,
+            Self::Filter => 180 * DAY,
+            Self::TradeStatistics | Self::TempProposalPayload => 30 * DAY,
+            Self::MailboxStoragePayload => 15 * DAY,
+            Self::OfferPayload => 9 * MINUTE,
+            Self::Unknown => 10 * DAY,
+        })
+    }
+    /// What a peer must support to receive such entries, as in the
+    /// `getRequiredCapabilities()` of bisq's payloads.
+    pub fn required_capabilities(self) -> &'static [Capability] {
+        match self {
+            Self::Mediator => &[Capability::Mediation],
+            Self::RefundAgent => &[Capability::RefundAgent],
+            Self::TradeStatistics => &[Capability::TradeStatistics],
+            Self::TempProposalPayload => &[Capability::Proposal],
+            _ => &[],
+        }
     }
 }
 
@@ -26,38 +66,134 @@ impl From<&ProtectedStorageEntry> for StoragePayloadKind {
             .as_ref()
             .and_then(|p| p.message.as_ref())
             .map(|m| match m {
+                storage_payload::Message::Alert(_) => StoragePayloadKind::Alert,
+                storage_payload::Message::Arbitrator(_) => StoragePayloadKind::Arbitrator,
+                storage_payload::Message::Mediator(_) => StoragePayloadKind::Mediator,
+                storage_payload::Message::Filter(_) => StoragePayloadKind::Filter,
+                storage_payload::Message::TradeStatistics(_) => StoragePayloadKind::TradeStatistics,
+                storage_payload::Message::MailboxStoragePayload(_) => {
+                    StoragePayloadKind::MailboxStoragePayload
+                }
                 storage_payload::Message::OfferPayload(_) => StoragePayloadKind::OfferPayload,
-                _ => StoragePayloadKind::Unknown,
+                storage_payload::Message::TempProposalPayload(_) => {
+                    StoragePayloadKind::TempProposalPayload
+                }
+                storage_payload::Message::RefundAgent(_) => StoragePayloadKind::RefundAgent,
             })
             .unwrap_or_default()
     }
 }
 
-#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub enum PersistableNetworkPayloadKind {
     AccountAgeWitness,
     TradeStatistics2,
     ProposalPayload,
     BlindVotePayload,
     SignedWitness,
+    #[default]
     Unknown,
 }
-impl Default for PersistableNetworkPayloadKind {
-    fn default() -> Self {
-        Self::Unknown
+impl PersistableNetworkPayloadKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::AccountAgeWitness => "AccountAgeWitness",
+            Self::TradeStatistics2 => "TradeStatistics2",
+            Self::ProposalPayload => "ProposalPayload",
+            Self::BlindVotePayload => "BlindVotePayload",
+            Self::SignedWitness => "SignedWitness",
+            Self::Unknown => "Unknown",
+        }
+    }
+    /// What a peer must support to receive such payloads, as in the
+    /// `getRequiredCapabilities()` of bisq's payloads.
+    pub fn required_capabilities(self) -> &'static [Capability] {
+        match self {
+            Self::AccountAgeWitness => &[Capability::AccountAgeWitness],
+            Self::TradeStatistics2 => &[Capability::TradeStatistics2],
+            Self::ProposalPayload => &[Capability::Proposal],
+            Self::BlindVotePayload => &[Capability::BlindVote],
+            Self::SignedWitness => &[Capability::SignedAccountAgeWitness],
+            Self::Unknown => &[],
+        }
     }
 }
+
 impl From<&PersistableNetworkPayload> for PersistableNetworkPayloadKind {
     fn from(payload: &PersistableNetworkPayload) -> Self {
         payload
             .message
             .as_ref()
             .map(|m| match m {
+                persistable_network_payload::Message::AccountAgeWitness(_) => {
+                    PersistableNetworkPayloadKind::AccountAgeWitness
+                }
                 persistable_network_payload::Message::TradeStatistics2(_) => {
                     PersistableNetworkPayloadKind::TradeStatistics2
                 }
-                _ => PersistableNetworkPayloadKind::Unknown,
+                persistable_network_payload::Message::ProposalPayload(_) => {
+                    PersistableNetworkPayloadKind::ProposalPayload
+                }
+                persistable_network_payload::Message::BlindVotePayload(_) => {
+                    PersistableNetworkPayloadKind::BlindVotePayload
+                }
+                persistable_network_payload::Message::SignedWitness(_) => {
+                    PersistableNetworkPayloadKind::SignedWitness
+                }
             })
             .unwrap_or_default()
     }
 }
+
+/// What a message is about, the traffic of connections is accounted by it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MessageKind {
+    pub message: &'static str,
+    /// Of the entry or payload a message adds, refreshes or removes
+    pub payload: Option<&'static str>,
+}
+impl From<&network_envelope::Message> for MessageKind {
+    fn from(msg: &network_envelope::Message) -> Self {
+        let payload = match msg {
+            network_envelope::Message::AddDataMessage(msg) => msg
+                .entry
+                .as_ref()
+                .and_then(|wrapper| match wrapper.message.as_ref()? {
+                    storage_entry_wrapper::Message::ProtectedStorageEntry(entry) => {
+                        Some(StoragePayloadKind::from(entry))
+                    }
+                    storage_entry_wrapper::Message::ProtectedMailboxStorageEntry(_) => {
+                        Some(StoragePayloadKind::MailboxStoragePayload)
+                    }
+                })
+                .map(StoragePayloadKind::name),
+            network_envelope::Message::RemoveDataMessage(msg) => msg
+                .protected_storage_entry
+                .as_ref()
+                .map(|entry| StoragePayloadKind::from(entry).name()),
+            network_envelope::Message::RemoveMailboxDataMessage(_) => {
+                Some(StoragePayloadKind::MailboxStoragePayload.name())
+            }
+            network_envelope::Message::RefreshOfferMessage(_) => {
+                Some(StoragePayloadKind::OfferPayload.name())
+            }
+            network_envelope::Message::AddPersistableNetworkPayloadMessage(msg) => msg
+                .payload
+                .as_ref()
+                .map(|payload| PersistableNetworkPayloadKind::from(payload).name()),
+            _ => None,
+        };
+        MessageKind {
+            message: msg.name(),
+            payload,
+        }
+    }
+}
+impl fmt::Display for MessageKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.payload {
+            Some(payload) => write!(f, "{}/{}", self.message, payload),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
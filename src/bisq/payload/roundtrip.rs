@@ -0,0 +1,403 @@
+// Payloads are hashed and signed over their encoding, so decoding and
+// encoding again has to give the bytes a bisq node sent, not just an equal
+// message.
+use super::*;
+use proptest::{collection::vec, option, prelude::*};
+use prost::Message;
+
+fn text() -> impl Strategy<Value = String> {
+    ".{0,12}"
+}
+
+fn bytes() -> impl Strategy<Value = Vec<u8>> {
+    vec(any::<u8>(), 0..48)
+}
+
+fn extra_data() -> impl Strategy<Value = Vec<JavaStringMapEntry>> {
+    vec(
+        (text(), text()).prop_map(|(key, value)| JavaStringMapEntry::new(key, value)),
+        0..3,
+    )
+}
+
+fn node_address() -> impl Strategy<Value = NodeAddress> {
+    (text(), any::<i32>()).prop_map(|(host_name, port)| NodeAddress { host_name, port })
+}
+
+fn pub_key_ring() -> impl Strategy<Value = PubKeyRing> {
+    (bytes(), bytes()).prop_map(
+        |(signature_pub_key_bytes, encryption_pub_key_bytes)| PubKeyRing {
+            signature_pub_key_bytes,
+            encryption_pub_key_bytes,
+        },
+    )
+}
+
+fn offer_payload() -> impl Strategy<Value = OfferPayload> {
+    (
+        (
+            text(),
+            any::<i64>(),
+            option::of(node_address()),
+            option::of(pub_key_ring()),
+            any::<i32>(),
+            any::<i64>(),
+            -1.0f64..1.0,
+            any::<bool>(),
+        ),
+        (
+            any::<i64>(),
+            any::<i64>(),
+            text(),
+            text(),
+            vec(node_address(), 0..3),
+            vec(node_address(), 0..3),
+        ),
+        (
+            text(),
+            text(),
+            text(),
+            text(),
+            vec(text(), 0..3),
+            text(),
+            vec(text(), 0..3),
+            text(),
+        ),
+        (
+            any::<[i64; 9]>(),
+            any::<[bool; 4]>(),
+            text(),
+            extra_data(),
+            any::<i32>(),
+        ),
+    )
+        .prop_map(
+            |(
+                (
+                    id,
+                    date,
+                    owner_node_address,
+                    pub_key_ring,
+                    direction,
+                    price,
+                    market_price_margin,
+                    use_market_based_price,
+                ),
+                (
+                    amount,
+                    min_amount,
+                    base_currency_code,
+                    counter_currency_code,
+                    arbitrator_node_addresses,
+                    mediator_node_addresses,
+                ),
+                (
+                    payment_method_id,
+                    maker_payment_account_id,
+                    offer_fee_payment_tx_id,
+                    country_code,
+                    accepted_country_codes,
+                    bank_id,
+                    accepted_bank_ids,
+                    version_nr,
+                ),
+                (numbers, flags, hash_of_challenge, extra_data, protocol_version),
+            )| OfferPayload {
+                id,
+                date,
+                owner_node_address,
+                pub_key_ring,
+                direction,
+                price,
+                market_price_margin,
+                use_market_based_price,
+                amount,
+                min_amount,
+                base_currency_code,
+                counter_currency_code,
+                arbitrator_node_addresses,
+                mediator_node_addresses,
+                payment_method_id,
+                maker_payment_account_id,
+                offer_fee_payment_tx_id,
+                country_code,
+                accepted_country_codes,
+                bank_id,
+                accepted_bank_ids,
+                version_nr,
+                block_height_at_offer_creation: numbers[0],
+                tx_fee: numbers[1],
+                maker_fee: numbers[2],
+                is_currency_for_maker_fee_btc: flags[0],
+                buyer_security_deposit: numbers[3],
+                seller_security_deposit: numbers[4],
+                max_trade_limit: numbers[5],
+                max_trade_period: numbers[6],
+                use_auto_close: flags[1],
+                use_re_open_after_auto_close: flags[2],
+                lower_close_price: numbers[7],
+                upper_close_price: numbers[8],
+                is_private_offer: flags[3],
+                hash_of_challenge,
+                extra_data,
+                protocol_version,
+            },
+        )
+}
+
+fn alert() -> impl Strategy<Value = Alert> {
+    (text(), text(), any::<bool>(), text(), bytes(), extra_data()).prop_map(
+        |(
+            message,
+            version,
+            is_update_info,
+            signature_as_base64,
+            owner_pub_key_bytes,
+            extra_data,
+        )| {
+            Alert {
+                message,
+                version,
+                is_update_info,
+                signature_as_base64,
+                owner_pub_key_bytes,
+                extra_data,
+            }
+        },
+    )
+}
+
+fn storage_payload() -> impl Strategy<Value = StoragePayload> {
+    prop_oneof![
+        offer_payload().prop_map(storage_payload::Message::OfferPayload),
+        alert().prop_map(storage_payload::Message::Alert),
+    ]
+    .prop_map(|message| StoragePayload {
+        message: Some(message),
+    })
+}
+
+fn trade_statistics2() -> impl Strategy<Value = TradeStatistics2> {
+    (
+        (text(), text(), any::<i32>(), any::<[i64; 6]>(), text()),
+        (
+            any::<bool>(),
+            -1.0f64..1.0,
+            text(),
+            text(),
+            bytes(),
+            extra_data(),
+        ),
+    )
+        .prop_map(
+            |(
+                (base_currency, counter_currency, direction, numbers, payment_method_id),
+                (
+                    offer_use_market_based_price,
+                    offer_market_price_margin,
+                    offer_id,
+                    deposit_tx_id,
+                    hash,
+                    extra_data,
+                ),
+            )| TradeStatistics2 {
+                base_currency,
+                counter_currency,
+                direction,
+                trade_price: numbers[0],
+                trade_amount: numbers[1],
+                trade_date: numbers[2],
+                payment_method_id,
+                offer_date: numbers[3],
+                offer_use_market_based_price,
+                offer_market_price_margin,
+                offer_amount: numbers[4],
+                offer_min_amount: numbers[5],
+                offer_id,
+                deposit_tx_id,
+                hash,
+                extra_data,
+            },
+        )
+}
+
+fn persistable_payload() -> impl Strategy<Value = PersistableNetworkPayload> {
+    prop_oneof![
+        (bytes(), any::<i64>()).prop_map(|(hash, date)| {
+            persistable_network_payload::Message::AccountAgeWitness(AccountAgeWitness {
+                hash,
+                date,
+            })
+        }),
+        trade_statistics2().prop_map(persistable_network_payload::Message::TradeStatistics2),
+    ]
+    .prop_map(|message| PersistableNetworkPayload {
+        message: Some(message),
+    })
+}
+
+fn storage_entry() -> impl Strategy<Value = StorageEntryWrapper> {
+    (
+        storage_payload(),
+        bytes(),
+        any::<i32>(),
+        bytes(),
+        any::<i64>(),
+    )
+        .prop_map(
+            |(payload, owner_pub_key_bytes, sequence_number, signature, creation_time_stamp)| {
+                StorageEntryWrapper {
+                    message: Some(storage_entry_wrapper::Message::ProtectedStorageEntry(
+                        ProtectedStorageEntry {
+                            storage_payload: Some(payload),
+                            owner_pub_key_bytes,
+                            sequence_number,
+                            signature,
+                            creation_time_stamp,
+                        },
+                    )),
+                }
+            },
+        )
+}
+
+fn envelope() -> impl Strategy<Value = NetworkEnvelope> {
+    let message = prop_oneof![
+        (any::<i32>(), any::<i32>()).prop_map(|(nonce, last_round_trip_time)| {
+            network_envelope::Message::Ping(Ping {
+                nonce,
+                last_round_trip_time,
+            })
+        }),
+        any::<i32>()
+            .prop_map(|request_nonce| network_envelope::Message::Pong(Pong { request_nonce })),
+        (bytes(), bytes(), bytes(), any::<i32>()).prop_map(
+            |(hash_of_data_and_seq_nr, signature, hash_of_payload, sequence_number)| {
+                network_envelope::Message::RefreshOfferMessage(RefreshOfferMessage {
+                    hash_of_data_and_seq_nr,
+                    signature,
+                    hash_of_payload,
+                    sequence_number,
+                })
+            }
+        ),
+        option::of(storage_entry())
+            .prop_map(|entry| network_envelope::Message::AddDataMessage(AddDataMessage { entry })),
+        option::of(persistable_payload()).prop_map(|payload| {
+            network_envelope::Message::AddPersistableNetworkPayloadMessage(
+                AddPersistableNetworkPayloadMessage { payload },
+            )
+        }),
+        (
+            any::<i32>(),
+            any::<bool>(),
+            vec(storage_entry(), 0..3),
+            vec(any::<i32>(), 0..4),
+            vec(persistable_payload(), 0..3),
+        )
+            .prop_map(
+                |(
+                    request_nonce,
+                    is_get_updated_data_response,
+                    data_set,
+                    supported_capabilities,
+                    persistable_network_payload_items,
+                )| {
+                    network_envelope::Message::GetDataResponse(GetDataResponse {
+                        request_nonce,
+                        is_get_updated_data_response,
+                        data_set,
+                        supported_capabilities,
+                        persistable_network_payload_items,
+                    })
+                }
+            ),
+    ];
+    (any::<i32>(), message).prop_map(|(message_version, message)| NetworkEnvelope {
+        message_version,
+        message: Some(message),
+    })
+}
+
+fn encode<M: Message>(msg: &M) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(msg.encoded_len());
+    msg.encode(&mut encoded).expect("Could not encode message");
+    encoded
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn storage_payload_round_trip(payload in storage_payload()) {
+        let encoded = encode(&payload);
+        let decoded = StoragePayload::decode(&encoded[..]).unwrap();
+        prop_assert_eq!(encode(&decoded), encoded);
+        prop_assert_eq!(decoded.bisq_hash(), payload.bisq_hash());
+        prop_assert_eq!(decoded, payload);
+    }
+
+    #[test]
+    fn envelope_round_trip(envelope in envelope()) {
+        let encoded = encode(&envelope);
+        let decoded = NetworkEnvelope::decode(&encoded[..]).unwrap();
+        prop_assert_eq!(encode(&decoded), encoded);
+        prop_assert_eq!(&decoded, &envelope);
+
+        // As sent over a connection
+        let mut delimited = Vec::new();
+        envelope.encode_length_delimited(&mut delimited).unwrap();
+        prop_assert_eq!(NetworkEnvelope::decode_length_delimited(&delimited[..]).unwrap(), envelope);
+    }
+
+    #[test]
+    fn map_entries_keep_empty_values(key in text()) {
+        // Like java, unlike prost's own maps
+        let entry = JavaStringMapEntry::new(key, String::new());
+        let encoded = encode(&entry);
+        prop_assert!(encoded.ends_with(&[0x12, 0]));
+        prop_assert_eq!(JavaStringMapEntry::decode(&encoded[..]).unwrap(), entry);
+    }
+}
+
+/// Messages captured from java bisq nodes under `test/golden`, see the
+/// README there. Decoding and encoding them has to give the same bytes.
+/// Without the feature `payload::tests` still checks the offer payload.
+#[cfg(feature = "golden-vectors")]
+#[test]
+fn golden_vectors() {
+    use crate::prelude::FromHex;
+    use std::{fs, path::Path};
+
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("test/golden");
+    let mut checked = 0;
+    for file in fs::read_dir(&dir).expect("Couldn't read golden vectors") {
+        let path = file.unwrap().path();
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        if !name.ends_with(".hex") {
+            continue;
+        }
+        let hex = fs::read_to_string(&path).unwrap();
+        let bytes = Vec::<u8>::from_hex(&hex.split_whitespace().collect::<String>())
+            .unwrap_or_else(|_| panic!("{} isn't hex", name));
+        let encoded = if name.starts_with("envelope-") {
+            let envelope = NetworkEnvelope::decode(&bytes[..])
+                .unwrap_or_else(|e| panic!("Couldn't decode {}: {}", name, e));
+            assert!(envelope.message.is_some(), "Unknown message in {}", name);
+            encode(&envelope)
+        } else if name.starts_with("storage-payload-") {
+            let payload = StoragePayload::decode(&bytes[..])
+                .unwrap_or_else(|e| panic!("Couldn't decode {}: {}", name, e));
+            assert!(payload.message.is_some(), "Unknown payload in {}", name);
+            encode(&payload)
+        } else {
+            panic!(
+                "{} is neither envelope-*.hex nor storage-payload-*.hex",
+                name
+            )
+        };
+        assert!(encoded == bytes, "{} encodes differently", name);
+        checked += 1;
+    }
+    assert!(checked > 0, "No golden vectors in {:?}", dir);
+}
@@ -1,8 +1,15 @@
+mod crawler;
+mod monitor;
+
+pub use crawler::{crawl, CrawlConfig};
+pub use monitor::{monitor, MonitorConfig};
+
 use crate::{
     bisq::{constants::BaseCurrencyNetwork, payload::*},
-    p2p::{dispatch::*, Connection, ConnectionId, Request},
+    p2p::{dispatch::*, transport, Connection, ConnectionId, Request},
     prelude::*,
 };
+use serde_json::json;
 use std::{process, time::SystemTime};
 
 #[derive(Debug, Clone, Copy)]
@@ -13,19 +20,16 @@ impl Dispatcher for DummyDispatcher {
     }
 }
 
-pub fn check_node(network: BaseCurrencyNetwork, addr: NodeAddress, proxy_port: u16) {
+pub fn check_node(network: BaseCurrencyNetwork, addr: NodeAddress, proxy_port: u16, json: bool) {
     let _ = System::run(move || {
         Arbiter::spawn(
             Connection::open(
                 addr.clone(),
                 network.into(),
                 DummyDispatcher,
-                Some(proxy_port),
+                &transport::Socks5::new(proxy_port),
             )
-            .map_err(|_| {
-                eprintln!("CRITICAL - Unable to connect to node");
-                process::exit(2);
-            })
+            .map_err(move |_| critical(json, "Unable to connect to node"))
             .and_then(move |(_id, conn)| {
                 let ping = Ping {
                     nonce: gen_nonce(),
@@ -33,28 +37,32 @@ pub fn check_node(network: BaseCurrencyNetwork, addr: NodeAddress, proxy_port: u
                 };
                 let send_time = SystemTime::now();
                 conn.send(Request(ping))
-                    .map_err(|_| {
-                        eprintln!("CRITICAL - Unable to send ping");
-                        process::exit(2)
-                    })
+                    .map_err(move |_| critical(json, "Unable to send ping"))
                     .map(move |res| match res {
                         Ok(_) => {
-                            let res_time = SystemTime::now();
-                            println!(
-                                "OK - PONG|time={}ms",
-                                res_time
-                                    .duration_since(send_time)
-                                    .expect("Pong before Ping")
-                                    .as_millis()
-                            );
+                            let time = SystemTime::now()
+                                .duration_since(send_time)
+                                .expect("Pong before Ping")
+                                .as_millis();
+                            if json {
+                                println!("{}", json!({ "status": "OK", "time_ms": time as u64 }));
+                            } else {
+                                println!("OK - PONG|time={}ms", time);
+                            }
                             process::exit(0)
                         }
-                        Err(_) => {
-                            eprintln!("CRITICAL - No response from host");
-                            process::exit(2)
-                        }
+                        Err(_) => critical(json, "No response from host"),
                     })
             }),
         )
     });
 }
+
+fn critical(json: bool, message: &str) -> ! {
+    if json {
+        println!("{}", json!({ "status": "CRITICAL", "message": message }));
+    } else {
+        eprintln!("CRITICAL - {}", message);
+    }
+    process::exit(2)
+}
@@ -0,0 +1,533 @@
+use super::monitor::{store_sizes, DataBatches};
+use crate::{
+    bisq::{
+        constants::{BaseCurrencyNetwork, Capability, CloseConnectionReason, LOCAL_CAPABILITIES},
+        payload::*,
+    },
+    metrics,
+    p2p::{dispatch::*, transport, Connection, ConnectionId, Request, Shutdown, Transport},
+    prelude::{future::Either, *},
+};
+use actix_web::{web, App, HttpResponse, HttpServer};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    convert::TryFrom,
+    fs, io,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+const VISIT_TIMEOUT: Duration = Duration::from_secs(120);
+
+pub struct CrawlConfig {
+    pub network: BaseCurrencyNetwork,
+    pub seeds: Vec<NodeAddress>,
+    pub proxy_port: Option<u16>,
+    /// Announced as the sender of the peer requests
+    pub sender_address: Option<NodeAddress>,
+    /// Peers visited at most per crawl
+    pub max_peers: usize,
+    /// Peers visited at the same time
+    pub parallel: usize,
+    /// Whether to ask every peer for its data to count it
+    pub data: bool,
+    pub output: Option<PathBuf>,
+    /// Crawl again after this long instead of exiting
+    pub interval: Option<Duration>,
+    pub metrics_port: Option<u16>,
+}
+
+/// What a crawl found out about the peers of the network.
+#[derive(Serialize)]
+pub struct CrawlReport {
+    pub network: String,
+    /// Unix time in seconds
+    pub started_at: u64,
+    pub duration_secs: u64,
+    pub discovered: usize,
+    pub reachable: usize,
+    pub peers: Vec<PeerReport>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct PeerReport {
+    pub address: String,
+    pub reachable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connect_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ping_ms: Option<u64>,
+    /// Bisq doesn't announce the version of the app, the capabilities tell
+    /// how recent it is
+    pub capabilities: Vec<String>,
+    /// How many peers it reported to us
+    pub reports_peers: usize,
+    /// How many of the visited peers reported it
+    pub reported_by: usize,
+    /// Entries and payloads it holds per kind
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub data: BTreeMap<String, u64>,
+}
+
+pub fn crawl(config: CrawlConfig) {
+    let _ = System::run(move || {
+        let rendered = Arc::new(RwLock::new(String::new()));
+        if let Some(port) = config.metrics_port {
+            if let Err(e) = serve_metrics(port, rendered.clone()) {
+                error!("Couldn't serve metrics on port {}: {}", port, e);
+                System::current().stop();
+                return;
+            }
+        }
+        Crawler {
+            transport: transport::for_proxy(config.proxy_port),
+            config,
+            rendered,
+            data_batches: DataBatches::default(),
+            round: Round::default(),
+        }
+        .start();
+    });
+}
+
+#[derive(Default)]
+struct Round {
+    started_at: Option<SystemTime>,
+    queue: VecDeque<NodeAddress>,
+    seen: HashSet<NodeAddress>,
+    reports: HashMap<NodeAddress, PeerReport>,
+    reported_by: HashMap<NodeAddress, usize>,
+    visiting: usize,
+}
+impl Round {
+    fn discover(&mut self, addr: NodeAddress, max_peers: usize) {
+        if self.seen.len() < max_peers && self.seen.insert(addr.clone()) {
+            self.queue.push_back(addr);
+        }
+    }
+
+    fn report(&self, network: BaseCurrencyNetwork) -> CrawlReport {
+        let started_at = self.started_at.unwrap_or_else(SystemTime::now);
+        let mut peers: Vec<PeerReport> = self
+            .reports
+            .iter()
+            .map(|(addr, report)| PeerReport {
+                reported_by: self.reported_by.get(addr).cloned().unwrap_or(0),
+                ..report.clone()
+            })
+            .collect();
+        peers.sort_by(|a, b| a.address.cmp(&b.address));
+        CrawlReport {
+            network: format!("{:?}", network),
+            started_at: started_at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            duration_secs: started_at.elapsed().unwrap_or_default().as_secs(),
+            discovered: peers.len(),
+            reachable: peers.iter().filter(|peer| peer.reachable).count(),
+            peers,
+        }
+    }
+}
+
+struct Crawler {
+    config: CrawlConfig,
+    transport: Arc<dyn Transport>,
+    rendered: Arc<RwLock<String>>,
+    data_batches: DataBatches,
+    round: Round,
+}
+impl Actor for Crawler {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.start_round(ctx);
+    }
+}
+impl Crawler {
+    fn start_round(&mut self, ctx: &mut Context<Self>) {
+        info!(
+            "Crawling the {:?} network from {} seed nodes",
+            self.config.network,
+            self.config.seeds.len()
+        );
+        self.round = Round {
+            started_at: Some(SystemTime::now()),
+            ..Round::default()
+        };
+        for seed in self.config.seeds.clone() {
+            self.round.discover(seed, self.config.max_peers);
+        }
+        self.visit_next(ctx);
+    }
+
+    fn visit_next(&mut self, ctx: &mut Context<Self>) {
+        while self.round.visiting < self.config.parallel.max(1) {
+            let addr = match self.round.queue.pop_front() {
+                Some(addr) => addr,
+                None => break,
+            };
+            self.round.visiting += 1;
+            let visited = addr.clone();
+            ctx.spawn(
+                fut::wrap_future(visit(
+                    addr,
+                    self.config.network,
+                    self.config.sender_address.clone(),
+                    self.config.data,
+                    &*self.transport,
+                    self.data_batches.clone(),
+                ))
+                .then(move |visit, crawler: &mut Self, ctx| {
+                    crawler.visited(visited, visit, ctx);
+                    fut::ok(())
+                }),
+            );
+        }
+        if self.round.visiting == 0 && self.round.queue.is_empty() {
+            self.finish_round(ctx);
+        }
+    }
+
+    fn visited(
+        &mut self,
+        addr: NodeAddress,
+        visit: Result<Visit, String>,
+        ctx: &mut Context<Self>,
+    ) {
+        self.round.visiting -= 1;
+        let mut data = self.data_batches.take(&addr);
+        let report = match visit {
+            Ok(visit) => {
+                for peer in visit.reported_peers.iter() {
+                    *self.round.reported_by.entry(peer.clone()).or_insert(0) += 1;
+                }
+                let reports_peers = visit.reported_peers.len();
+                for peer in visit.reported_peers {
+                    self.round.discover(peer, self.config.max_peers);
+                }
+                data.extend(visit.data);
+                PeerReport {
+                    address: addr.to_string(),
+                    reachable: true,
+                    connect_ms: Some(as_millis(visit.connect_time)),
+                    ping_ms: Some(as_millis(visit.round_trip_time)),
+                    capabilities: capability_names(&visit.capabilities),
+                    reports_peers,
+                    data: data_counts(data),
+                    ..PeerReport::default()
+                }
+            }
+            Err(e) => {
+                debug!("Couldn't visit {}: {}", addr, e);
+                PeerReport {
+                    address: addr.to_string(),
+                    error: Some(e),
+                    ..PeerReport::default()
+                }
+            }
+        };
+        self.round.reports.insert(addr, report);
+        self.visit_next(ctx);
+    }
+
+    fn finish_round(&mut self, ctx: &mut Context<Self>) {
+        let report = self.round.report(self.config.network);
+        info!(
+            "Crawled {} peers of which {} are reachable",
+            report.discovered, report.reachable
+        );
+        *self.rendered.write().expect("Corrupted lock in crawler") = render(&report);
+        let json = serde_json::to_string_pretty(&report).expect("Couldn't serialize report");
+        match self.config.output.as_ref() {
+            Some(path) => {
+                if let Err(e) = write_report(path, &json) {
+                    error!("Couldn't write the report to {}: {}", path.display(), e);
+                }
+            }
+            None => println!("{}", json),
+        }
+        match self.config.interval {
+            Some(interval) => {
+                ctx.run_later(interval, |crawler, ctx| crawler.start_round(ctx));
+            }
+            None => System::current().stop(),
+        }
+    }
+}
+
+struct Visit {
+    connect_time: Duration,
+    round_trip_time: Duration,
+    capabilities: Vec<i32>,
+    reported_peers: Vec<NodeAddress>,
+    data: Vec<(String, u64)>,
+}
+
+// Pings the peer, asks it for its peers and, if wanted, its data, then
+// closes the connection.
+fn visit(
+    addr: NodeAddress,
+    network: BaseCurrencyNetwork,
+    sender_address: Option<NodeAddress>,
+    data: bool,
+    transport: &dyn Transport,
+    data_batches: DataBatches,
+) -> impl Future<Item = Visit, Error = String> {
+    let started = Instant::now();
+    Connection::open(
+        addr.clone(),
+        network.into(),
+        Collector {
+            peer: addr,
+            data_batches,
+        },
+        transport,
+    )
+    .timeout(VISIT_TIMEOUT)
+    .map_err(|_| "Couldn't connect".to_string())
+    .and_then(move |(_id, conn)| {
+        let connect_time = started.elapsed();
+        let ping_sent = Instant::now();
+        conn.send(Request(Ping {
+            nonce: gen_nonce(),
+            last_round_trip_time: 0,
+        }))
+        .flatten()
+        .timeout(VISIT_TIMEOUT)
+        .map_err(|_| "No pong".to_string())
+        .and_then({
+            let conn = conn.clone();
+            move |_pong| {
+                let round_trip_time = ping_sent.elapsed();
+                conn.send(Request(GetPeersRequest {
+                    sender_node_address: sender_address,
+                    nonce: gen_nonce(),
+                    supported_capabilities: LOCAL_CAPABILITIES.clone(),
+                    reported_peers: Vec::new(),
+                }))
+                .flatten()
+                .timeout(VISIT_TIMEOUT)
+                .map_err(|_| "No peers response".to_string())
+                .map(move |response| (connect_time, round_trip_time, response))
+            }
+        })
+        .and_then({
+            let conn = conn.clone();
+            move |(connect_time, round_trip_time, response)| {
+                let sizes = if data {
+                    Either::A(
+                        conn.send(Request(PreliminaryGetDataRequest {
+                            nonce: gen_nonce(),
+                            excluded_keys: Vec::new(),
+                            supported_capabilities: LOCAL_CAPABILITIES.clone(),
+                        }))
+                        .flatten()
+                        .timeout(VISIT_TIMEOUT)
+                        .map_err(|_| "No data response".to_string())
+                        .map(|response| store_sizes(&response)),
+                    )
+                } else {
+                    Either::B(future::ok(Vec::new()))
+                };
+                sizes.map(move |data| Visit {
+                    connect_time,
+                    round_trip_time,
+                    capabilities: response.supported_capabilities,
+                    reported_peers: response
+                        .reported_peers
+                        .into_iter()
+                        .filter_map(|peer| peer.node_address)
+                        .collect(),
+                    data,
+                })
+            }
+        })
+        .then(move |visit| {
+            conn.send(Shutdown(CloseConnectionReason::CloseRequestedByPeer))
+                .then(|_| visit)
+        })
+    })
+}
+
+// Counts the batches large data responses arrive in before the response
+// to the request.
+#[derive(Clone)]
+struct Collector {
+    peer: NodeAddress,
+    data_batches: DataBatches,
+}
+impl Dispatcher for Collector {
+    fn dispatch(&self, _conn: ConnectionId, msg: network_envelope::Message) -> Dispatch {
+        if let network_envelope::Message::GetDataResponse(batch) = &msg {
+            self.data_batches.add(&self.peer, batch);
+        }
+        Dispatch::Consumed
+    }
+}
+
+fn capability_names(ordinals: &[i32]) -> Vec<String> {
+    ordinals
+        .iter()
+        .map(|n| match Capability::try_from(*n) {
+            Ok(capability) => format!("{:?}", capability),
+            Err(()) => n.to_string(),
+        })
+        .collect()
+}
+
+fn data_counts(sizes: Vec<(String, u64)>) -> BTreeMap<String, u64> {
+    let mut counts = BTreeMap::new();
+    for (name, count) in sizes {
+        *counts
+            .entry(name.trim_start_matches("data.").to_string())
+            .or_insert(0) += count;
+    }
+    counts
+}
+
+fn as_millis(duration: Duration) -> u64 {
+    duration.as_millis() as u64
+}
+
+fn write_report(path: &PathBuf, json: &str) -> io::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, json)?;
+    fs::rename(tmp_path, path)
+}
+
+fn render(report: &CrawlReport) -> String {
+    let mut out = String::new();
+    metrics::metric(
+        &mut out,
+        "risq_crawl_peers",
+        "gauge",
+        "Peers discovered by the last crawl",
+        &[
+            (Some("state=\"reachable\"".into()), report.reachable as f64),
+            (
+                Some("state=\"unreachable\"".into()),
+                (report.discovered - report.reachable) as f64,
+            ),
+        ],
+    );
+    let mut capabilities: BTreeMap<&str, u64> = BTreeMap::new();
+    for capability in report
+        .peers
+        .iter()
+        .flat_map(|peer| peer.capabilities.iter())
+    {
+        *capabilities.entry(capability.as_str()).or_insert(0) += 1;
+    }
+    metrics::metric(
+        &mut out,
+        "risq_crawl_peers_with_capability",
+        "gauge",
+        "Reachable peers announcing a capability",
+        &capabilities
+            .iter()
+            .map(|(capability, count)| {
+                (
+                    Some(format!("capability=\"{}\"", capability)),
+                    *count as f64,
+                )
+            })
+            .collect::<Vec<_>>(),
+    );
+    metrics::metric(
+        &mut out,
+        "risq_crawl_duration_seconds",
+        "gauge",
+        "How long the last crawl took",
+        &[(None, report.duration_secs as f64)],
+    );
+    out
+}
+
+fn serve_metrics(port: u16, rendered: Arc<RwLock<String>>) -> io::Result<()> {
+    HttpServer::new(move || {
+        App::new()
+            .data(rendered.clone())
+            .service(web::resource("/metrics").route(web::get().to(metrics)))
+    })
+    .bind(("127.0.0.1", port))?
+    .start();
+    Ok(())
+}
+
+fn metrics(rendered: web::Data<Arc<RwLock<String>>>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(rendered.read().expect("Corrupted lock in crawler").clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(host: &str) -> NodeAddress {
+        NodeAddress {
+            host_name: host.into(),
+            port: 9999,
+        }
+    }
+
+    #[test]
+    fn reports_the_peers_of_a_round() {
+        let mut round = Round::default();
+        round.discover(addr("a.onion"), 2);
+        round.discover(addr("b.onion"), 2);
+        round.discover(addr("a.onion"), 2);
+        round.discover(addr("c.onion"), 2);
+        assert_eq!(round.queue, vec![addr("a.onion"), addr("b.onion")]);
+
+        round.reports.insert(
+            addr("b.onion"),
+            PeerReport {
+                address: "b.onion:9999".into(),
+                error: Some("Couldn't connect".into()),
+                ..PeerReport::default()
+            },
+        );
+        round.reports.insert(
+            addr("a.onion"),
+            PeerReport {
+                address: "a.onion:9999".into(),
+                reachable: true,
+                capabilities: capability_names(&[0, 3, 99]),
+                ..PeerReport::default()
+            },
+        );
+        round.reported_by.insert(addr("b.onion"), 1);
+        let report = round.report(BaseCurrencyNetwork::BtcRegtest);
+        assert_eq!((report.discovered, report.reachable), (2, 1));
+        assert_eq!(
+            report.peers[0].capabilities,
+            vec!["TradeStatistics", "SeedNode", "99"]
+        );
+        assert_eq!(report.peers[1].reported_by, 1);
+
+        let rendered = render(&report);
+        assert!(rendered.contains("risq_crawl_peers{state=\"unreachable\"} 1\n"));
+        assert!(rendered.contains("risq_crawl_peers_with_capability{capability=\"SeedNode\"} 1\n"));
+    }
+
+    #[test]
+    fn counts_data_per_kind() {
+        assert_eq!(
+            data_counts(vec![
+                ("data.OfferPayload".into(), 2),
+                ("data.AccountAgeWitness".into(), 5),
+                ("data.OfferPayload".into(), 1),
+            ])
+            .into_iter()
+            .collect::<Vec<_>>(),
+            vec![("AccountAgeWitness".into(), 5), ("OfferPayload".into(), 3)]
+        );
+    }
+}
@@ -0,0 +1,422 @@
+use crate::{
+    bisq::{
+        constants::{BaseCurrencyNetwork, LOCAL_CAPABILITIES},
+        payload::{kind::*, *},
+        Sha256,
+    },
+    p2p::{dispatch::*, transport, Connection, ConnectionId, Payload, Request, Transport},
+    prelude::{future::Either, *},
+    scheduler::{Schedule, Task},
+};
+use reqwest::{r#async::Client, Url};
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    net::ToSocketAddrs,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
+pub struct MonitorConfig {
+    pub network: BaseCurrencyNetwork,
+    pub seeds: Vec<NodeAddress>,
+    pub proxy_port: Option<u16>,
+    pub interval: Duration,
+    pub prefix: String,
+    pub graphite: Option<String>,
+    pub influx_url: Option<Url>,
+    pub json: bool,
+}
+
+pub fn monitor(config: MonitorConfig) {
+    let _ = System::run(move || {
+        Monitor {
+            client: Client::new(),
+            transport: transport::for_proxy(config.proxy_port),
+            config,
+            data_batches: DataBatches::default(),
+            connections: HashMap::new(),
+            observations: HashMap::new(),
+        }
+        .start();
+    });
+}
+
+pub(super) type Metrics = Vec<(String, u64)>;
+
+struct Observation {
+    first_seen: Instant,
+    seen_by: HashMap<NodeAddress, Instant>,
+}
+
+struct Monitor {
+    config: MonitorConfig,
+    client: Client,
+    transport: Arc<dyn Transport>,
+    data_batches: DataBatches,
+    connections: HashMap<NodeAddress, Addr<Connection>>,
+    observations: HashMap<sha256::Hash, Observation>,
+}
+impl Actor for Monitor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        info!(
+            "Monitoring {} seed nodes every {:?}",
+            self.config.seeds.len(),
+            self.config.interval
+        );
+        self.measure_seeds(ctx);
+        ctx.schedule(
+            Task::new("monitor", self.config.interval),
+            |monitor, ctx| {
+                monitor.report_propagation();
+                monitor.measure_seeds(ctx);
+            },
+        );
+    }
+}
+impl Monitor {
+    fn measure_seeds(&mut self, ctx: &mut Context<Self>) {
+        for seed in self.config.seeds.clone() {
+            ctx.spawn(self.measure_seed(seed, ctx.address()));
+        }
+    }
+
+    // Reuses the connection of the last round so relayed messages can be observed in between.
+    fn measure_seed(
+        &self,
+        seed: NodeAddress,
+        addr: Addr<Monitor>,
+    ) -> impl ActorFuture<Item = (), Error = (), Actor = Self> {
+        let connect = match self.connections.get(&seed).filter(|conn| conn.connected()) {
+            Some(conn) => Either::A(future::ok((conn.clone(), None))),
+            None => {
+                let started = Instant::now();
+                Either::B(
+                    Connection::open(
+                        seed.clone(),
+                        self.config.network.into(),
+                        Observer {
+                            seed: seed.clone(),
+                            monitor: addr,
+                            data_batches: self.data_batches.clone(),
+                        },
+                        &*self.transport,
+                    )
+                    .timeout(REQUEST_TIMEOUT)
+                    .map(move |(_id, conn)| (conn, Some(started.elapsed())))
+                    .map_err(|_| ()),
+                )
+            }
+        };
+        fut::wrap_future(connect)
+            .and_then(|(conn, connect_time), _: &mut Monitor, _| {
+                fut::wrap_future(probe(conn.clone(), connect_time))
+                    .map(move |metrics, _: &mut Monitor, _| (conn, metrics))
+            })
+            .then(move |result, monitor: &mut Monitor, _| {
+                match result {
+                    Ok((conn, metrics)) => {
+                        monitor.connections.insert(seed.clone(), conn);
+                        let batches = monitor.data_batches.take(&seed);
+                        monitor.report(&seed, add_metrics(metrics, batches));
+                    }
+                    Err(_) => {
+                        monitor.data_batches.take(&seed);
+                        warn!("Seed {} is not responding", seed);
+                        monitor.connections.remove(&seed);
+                        monitor.report(&seed, vec![("up".into(), 0)]);
+                    }
+                }
+                fut::ok(())
+            })
+    }
+
+    fn report_propagation(&mut self) {
+        let observations: Vec<Observation> =
+            self.observations.drain().map(|(_, obs)| obs).collect();
+        for seed in self.config.seeds.clone() {
+            let delays: Vec<Duration> = observations
+                .iter()
+                .filter_map(|obs| obs.seen_by.get(&seed).map(|seen| *seen - obs.first_seen))
+                .collect();
+            let mut metrics = vec![
+                ("messages.received".to_string(), delays.len() as u64),
+                (
+                    "messages.missed".to_string(),
+                    (observations.len() - delays.len()) as u64,
+                ),
+            ];
+            if !delays.is_empty() {
+                let total: Duration = delays.iter().sum();
+                metrics.push((
+                    "propagation_delay_ms".into(),
+                    as_millis(total / delays.len() as u32),
+                ));
+            }
+            self.report(&seed, metrics);
+        }
+    }
+
+    fn report(&self, seed: &NodeAddress, metrics: Metrics) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards");
+        let graphite = graphite_lines(&self.config, seed, &metrics, timestamp);
+        if let Some(graphite_addr) = self.config.graphite.as_ref() {
+            match graphite_addr
+                .to_socket_addrs()
+                .ok()
+                .and_then(|mut a| a.next())
+            {
+                Some(socket_addr) => arbiter_spawn!(net::TcpStream::connect(&socket_addr)
+                    .and_then(move |stream| io::write_all(stream, graphite))
+                    .map_err(|e| warn!("Couldn't write to graphite: {}", e))),
+                None => warn!("Couldn't resolve graphite address {}", graphite_addr),
+            }
+        } else if self.config.influx_url.is_none() {
+            if self.config.json {
+                let metrics: HashMap<&String, &u64> =
+                    metrics.iter().map(|(name, value)| (name, value)).collect();
+                println!(
+                    "{}",
+                    json!({
+                        "timestamp": timestamp.as_secs(),
+                        "network": format!("{:?}", self.config.network),
+                        "seed": seed.to_string(),
+                        "metrics": metrics,
+                    })
+                );
+            } else {
+                print!("{}", graphite);
+            }
+        }
+        if let Some(url) = self.config.influx_url.as_ref() {
+            arbiter_spawn!(self
+                .client
+                .post(url.clone())
+                .body(influx_line(&self.config, seed, &metrics, timestamp))
+                .send()
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| warn!("Couldn't write to influx: {}", e)));
+        }
+    }
+}
+
+fn probe(
+    conn: Addr<Connection>,
+    connect_time: Option<Duration>,
+) -> impl Future<Item = Metrics, Error = ()> {
+    let ping = Ping {
+        nonce: gen_nonce(),
+        last_round_trip_time: 0,
+    };
+    let ping_sent = Instant::now();
+    conn.send(Request(ping))
+        .flatten()
+        .timeout(REQUEST_TIMEOUT)
+        .map_err(|_| ())
+        .and_then(move |_pong| {
+            let round_trip_time = ping_sent.elapsed();
+            let request = PreliminaryGetDataRequest {
+                nonce: gen_nonce(),
+                excluded_keys: Vec::new(),
+                supported_capabilities: LOCAL_CAPABILITIES.clone(),
+            };
+            let request_sent = Instant::now();
+            conn.send(Request(request))
+                .flatten()
+                .timeout(REQUEST_TIMEOUT)
+                .map_err(|_| ())
+                .map(move |response| (round_trip_time, request_sent.elapsed(), response))
+        })
+        .map(move |(round_trip_time, get_data_time, response)| {
+            let mut metrics = vec![
+                ("up".to_string(), 1),
+                ("ping_ms".to_string(), as_millis(round_trip_time)),
+                ("get_data_ms".to_string(), as_millis(get_data_time)),
+            ];
+            if let Some(connect_time) = connect_time {
+                metrics.push(("connect_ms".into(), as_millis(connect_time)));
+            }
+            metrics.extend(store_sizes(&response));
+            metrics
+        })
+}
+
+pub(super) fn store_sizes(response: &GetDataResponse) -> Metrics {
+    let mut entries: HashMap<StoragePayloadKind, u64> = HashMap::new();
+    for entry in response
+        .data_set
+        .iter()
+        .filter_map(|w| match w.message.as_ref()? {
+            storage_entry_wrapper::Message::ProtectedStorageEntry(entry) => Some(entry),
+            storage_entry_wrapper::Message::ProtectedMailboxStorageEntry(mailbox_entry) => {
+                mailbox_entry.entry.as_ref()
+            }
+        })
+    {
+        *entries.entry(entry.into()).or_default() += 1;
+    }
+    let mut payloads: HashMap<PersistableNetworkPayloadKind, u64> = HashMap::new();
+    for payload in response.persistable_network_payload_items.iter() {
+        *payloads.entry(payload.into()).or_default() += 1;
+    }
+    entries
+        .into_iter()
+        .map(|(kind, count)| (format!("data.{:?}", kind), count))
+        .chain(
+            payloads
+                .into_iter()
+                .map(|(kind, count)| (format!("data.{:?}", kind), count)),
+        )
+        .collect()
+}
+
+fn add_metrics(mut metrics: Metrics, more: Metrics) -> Metrics {
+    for (name, value) in more {
+        match metrics.iter_mut().find(|(existing, _)| *existing == name) {
+            Some((_, total)) => *total += value,
+            None => metrics.push((name, value)),
+        }
+    }
+    metrics
+}
+
+fn as_millis(duration: Duration) -> u64 {
+    duration.as_millis() as u64
+}
+
+fn graphite_lines(
+    config: &MonitorConfig,
+    seed: &NodeAddress,
+    metrics: &[(String, u64)],
+    timestamp: Duration,
+) -> String {
+    let seed = format!("{}_{}", seed.host_name.replace('.', "_"), seed.port);
+    metrics
+        .iter()
+        .map(|(name, value)| {
+            format!(
+                "{}.{:?}.{}.{} {} {}\n",
+                config.prefix,
+                config.network,
+                seed,
+                name,
+                value,
+                timestamp.as_secs()
+            )
+        })
+        .collect()
+}
+
+fn influx_line(
+    config: &MonitorConfig,
+    seed: &NodeAddress,
+    metrics: &[(String, u64)],
+    timestamp: Duration,
+) -> String {
+    let fields: Vec<String> = metrics
+        .iter()
+        .map(|(name, value)| format!("{}={}i", name, value))
+        .collect();
+    format!(
+        "{},network={:?},seed={} {} {}\n",
+        config.prefix,
+        config.network,
+        seed,
+        fields.join(","),
+        timestamp.as_nanos()
+    )
+}
+
+struct Observed(NodeAddress, sha256::Hash, Instant);
+impl Message for Observed {
+    type Result = ();
+}
+impl Handler<Observed> for Monitor {
+    type Result = ();
+    fn handle(&mut self, Observed(seed, hash, at): Observed, _: &mut Self::Context) {
+        self.observations
+            .entry(hash)
+            .or_insert_with(|| Observation {
+                first_seen: at,
+                seen_by: HashMap::new(),
+            })
+            .seen_by
+            .entry(seed)
+            .or_insert(at);
+    }
+}
+struct PingReceived(NodeAddress, i32);
+impl Message for PingReceived {
+    type Result = ();
+}
+impl Handler<PingReceived> for Monitor {
+    type Result = ();
+    fn handle(&mut self, PingReceived(seed, nonce): PingReceived, _: &mut Self::Context) {
+        if let Some(conn) = self.connections.get(&seed) {
+            arbiter_spawn!(conn.send(Payload(Pong {
+                request_nonce: nonce
+            })));
+        }
+    }
+}
+
+/// Store sizes of the batches large data responses arrive in, until the
+/// rest of the response answers the probe.
+#[derive(Clone, Default)]
+pub(super) struct DataBatches(Arc<Mutex<HashMap<NodeAddress, Metrics>>>);
+impl DataBatches {
+    pub(super) fn add(&self, seed: &NodeAddress, response: &GetDataResponse) {
+        let mut batches = self.0.lock().expect("Corrupted lock in monitor");
+        let sizes = batches.remove(seed).unwrap_or_default();
+        batches.insert(seed.clone(), add_metrics(sizes, store_sizes(response)));
+    }
+    pub(super) fn take(&self, seed: &NodeAddress) -> Metrics {
+        self.0
+            .lock()
+            .expect("Corrupted lock in monitor")
+            .remove(seed)
+            .unwrap_or_default()
+    }
+}
+
+// Records when relayed data arrives from a seed to measure the propagation delay.
+#[derive(Clone)]
+struct Observer {
+    seed: NodeAddress,
+    monitor: Addr<Monitor>,
+    data_batches: DataBatches,
+}
+impl Dispatcher for Observer {
+    fn dispatch(&self, _conn: ConnectionId, msg: network_envelope::Message) -> Dispatch {
+        let now = Instant::now();
+        let hash = match &msg {
+            network_envelope::Message::AddDataMessage(msg) => Some(msg.sha256()),
+            network_envelope::Message::RemoveDataMessage(msg) => Some(msg.sha256()),
+            network_envelope::Message::RefreshOfferMessage(msg) => Some(msg.sha256()),
+            network_envelope::Message::AddPersistableNetworkPayloadMessage(msg) => {
+                Some(msg.sha256())
+            }
+            network_envelope::Message::GetDataResponse(batch) => {
+                self.data_batches.add(&self.seed, batch);
+                None
+            }
+            network_envelope::Message::Ping(ping) => {
+                arbiter_spawn!(self
+                    .monitor
+                    .send(PingReceived(self.seed.clone(), ping.nonce)));
+                None
+            }
+            _ => None,
+        };
+        if let Some(hash) = hash {
+            arbiter_spawn!(self.monitor.send(Observed(self.seed.clone(), hash, now)));
+        }
+        Dispatch::Consumed
+    }
+}
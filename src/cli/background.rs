@@ -0,0 +1,107 @@
+use std::{
+    fs::{self, OpenOptions},
+    io,
+    os::unix::io::AsRawFd,
+    path::Path,
+    process, thread,
+    time::{Duration, Instant},
+};
+
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Forks the process and detaches the child from the terminal.
+/// Returns the pid of the child in the parent and `None` in the child,
+/// whose stdout and stderr are appended to `log_file`.
+pub fn daemonize(log_file: &Path) -> io::Result<Option<libc::pid_t>> {
+    if let Some(dir) = log_file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_file)?;
+    let dev_null = OpenOptions::new().read(true).open("/dev/null")?;
+
+    match unsafe { libc::fork() } {
+        -1 => return Err(io::Error::last_os_error()),
+        0 => (),
+        pid => return Ok(Some(pid)),
+    }
+    unsafe {
+        if libc::setsid() == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        if libc::dup2(dev_null.as_raw_fd(), libc::STDIN_FILENO) == -1
+            || libc::dup2(log.as_raw_fd(), libc::STDOUT_FILENO) == -1
+            || libc::dup2(log.as_raw_fd(), libc::STDERR_FILENO) == -1
+        {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(None)
+}
+
+fn read_pid(pid_file: &Path) -> Option<libc::pid_t> {
+    fs::read_to_string(pid_file).ok()?.trim().parse().ok()
+}
+
+fn is_running(pid: libc::pid_t) -> bool {
+    unsafe { libc::kill(pid, 0) == 0 }
+}
+
+/// The pid of the daemon in `pid_file` if it's still running.
+pub fn running(pid_file: &Path) -> Option<libc::pid_t> {
+    read_pid(pid_file).filter(|pid| is_running(*pid))
+}
+
+pub fn write_pid_file(pid_file: &Path) -> Result<(), String> {
+    if let Some(pid) = read_pid(pid_file) {
+        if is_running(pid) {
+            return Err(format!(
+                "risq is already running with pid {} ({})",
+                pid,
+                pid_file.display()
+            ));
+        }
+    }
+    if let Some(dir) = pid_file.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    fs::write(pid_file, process::id().to_string())
+        .map_err(|e| format!("Couldn't write pid file {}: {}", pid_file.display(), e))
+}
+
+pub fn remove_pid_file(pid_file: &Path) {
+    if read_pid(pid_file) == Some(process::id() as libc::pid_t) {
+        let _ = fs::remove_file(pid_file);
+    }
+}
+
+/// Sends SIGTERM to the process in `pid_file` and waits for it to exit.
+pub fn stop(pid_file: &Path, timeout: Duration) -> Result<libc::pid_t, String> {
+    let pid = read_pid(pid_file)
+        .ok_or_else(|| format!("Couldn't read pid from {}", pid_file.display()))?;
+    if !is_running(pid) {
+        let _ = fs::remove_file(pid_file);
+        return Err(format!("risq is not running (stale pid {})", pid));
+    }
+    if unsafe { libc::kill(pid, libc::SIGTERM) } == -1 {
+        return Err(format!(
+            "Couldn't signal pid {}: {}",
+            pid,
+            io::Error::last_os_error()
+        ));
+    }
+    let started = Instant::now();
+    while is_running(pid) {
+        if started.elapsed() > timeout {
+            return Err(format!(
+                "risq (pid {}) didn't exit within {:?}",
+                pid, timeout
+            ));
+        }
+        thread::sleep(STOP_POLL_INTERVAL);
+    }
+    let _ = fs::remove_file(pid_file);
+    Ok(pid)
+}
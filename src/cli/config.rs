@@ -0,0 +1,300 @@
+//! The config file, `$RISQ_HOME/risq.toml` unless `$RISQ_CONFIG` is set.
+//! Its settings are named like the flags of `risq daemon`, eg.
+//! `api-port = 7477`, and sections like `[BtcTestnet]` hold the settings
+//! of one network only:
+//!
+//! ```toml
+//! network = ["BtcMainnet", "BtcTestnet"]
+//! no-tor = true
+//!
+//! [BtcTestnet]
+//! api-port = 7577
+//! force-seed = "localhost:2002"
+//! ```
+use crate::bisq::constants::BaseCurrencyNetwork;
+use std::{collections::HashMap, env, fs, io, path::Path, str::FromStr};
+
+/// What the section of a network can set, everything else is shared by
+/// all networks.
+const NETWORK_SETTINGS: &[&str] = &[
+    "api-port",
+    "api-bind",
+    "p2p-port",
+    "force-seed",
+    "clearnet-address",
+    "max-connections",
+    "p2p-network-version",
+    "data-dir",
+    "tor-control-port",
+    "tor-hidden-service-port",
+    "tor-socks-port",
+];
+
+#[derive(Clone, Debug, Default)]
+pub struct ConfigFile {
+    settings: Vec<(String, String)>,
+    networks: Vec<(BaseCurrencyNetwork, HashMap<String, String>)>,
+    exported: Vec<String>,
+}
+impl ConfigFile {
+    /// An empty config when there is no file at `path`.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(content) => Self::parse(&content).map_err(|e| format!("{}: {}", path.display(), e)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("Couldn't read {}: {}", path.display(), e)),
+        }
+    }
+
+    /// Supports the part of TOML a flat list of settings needs: strings,
+    /// numbers, booleans, arrays of them and `#` comments.
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+        for (idx, line) in content.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let fail = |message: &str| format!("line {}: {}", idx + 1, message);
+            if line.starts_with('[') {
+                if !line.ends_with(']') {
+                    return Err(fail("Unterminated section"));
+                }
+                let name = line[1..line.len() - 1].trim();
+                let network = BaseCurrencyNetwork::from_str(name).map_err(|_| {
+                    fail("Sections are networks (BtcMainnet|BtcTestnet|BtcRegtest)")
+                })?;
+                if config.networks.iter().any(|(n, _)| *n == network) {
+                    return Err(fail("Network section given more than once"));
+                }
+                config.networks.push((network, HashMap::new()));
+                continue;
+            }
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap_or_default().trim().replace('_', "-");
+            let value = parts.next().ok_or_else(|| fail("Expected key = value"))?;
+            if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+                return Err(fail("Invalid key"));
+            }
+            let value = parse_value(value.trim()).map_err(|e| fail(&e))?;
+            match config.networks.last_mut() {
+                Some((_, settings)) => {
+                    if !NETWORK_SETTINGS.contains(&key.as_str()) {
+                        return Err(fail(&format!(
+                            "{} can't be set per network, only {}",
+                            key,
+                            NETWORK_SETTINGS.join(", ")
+                        )));
+                    }
+                    settings.insert(key, value);
+                }
+                None => config.settings.push((key, value)),
+            }
+        }
+        Ok(config)
+    }
+
+    /// Sets the env var of each setting that isn't set already, so that
+    /// flags override env vars and env vars override the file.
+    pub fn export(&mut self) {
+        for (key, value) in self.settings.iter() {
+            let var = env_var(key);
+            if env::var_os(&var).is_none() {
+                env::set_var(&var, value);
+                self.exported.push(var);
+            }
+        }
+    }
+
+    /// The file at `path` as it is now, for a running daemon. Its settings
+    /// apply where those of this file did, no env var is set again.
+    pub fn reload(&self, path: &Path) -> Result<Self, String> {
+        Ok(Self {
+            exported: self.exported.clone(),
+            ..Self::load(path)?
+        })
+    }
+
+    /// A setting shared by all networks, with the precedence of
+    /// `network_setting`.
+    pub fn setting(&self, key: &str, given: bool) -> Option<&str> {
+        if self.overridden(key, given) {
+            return None;
+        }
+        self.settings
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The setting in the section of `network`. It doesn't apply when the
+    /// setting was given as flag (`given`) or as env var.
+    pub fn network_setting(
+        &self,
+        network: BaseCurrencyNetwork,
+        key: &str,
+        given: bool,
+    ) -> Option<&str> {
+        if self.overridden(key, given) {
+            return None;
+        }
+        self.networks
+            .iter()
+            .find(|(n, _)| *n == network)
+            .and_then(|(_, settings)| settings.get(key))
+            .map(String::as_str)
+    }
+
+    fn overridden(&self, key: &str, given: bool) -> bool {
+        let var = env_var(key);
+        given || (env::var_os(&var).is_some() && !self.exported.contains(&var))
+    }
+}
+
+fn env_var(key: &str) -> String {
+    format!("RISQ_{}", key.to_uppercase().replace('-', "_"))
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (idx, c) in line.char_indices() {
+        match c {
+            '\\' if quoted && !escaped => {
+                escaped = true;
+                continue;
+            }
+            '"' if !escaped => quoted = !quoted,
+            '#' if !quoted => return &line[..idx],
+            _ => (),
+        }
+        escaped = false;
+    }
+    line
+}
+
+// Arrays become a comma separated list, like clap splits env vars of flags
+// that can be given several times.
+fn parse_value(value: &str) -> Result<String, String> {
+    if value.starts_with('[') {
+        if !value.ends_with(']') {
+            return Err("Unterminated array".into());
+        }
+        let inner = value[1..value.len() - 1].trim();
+        let mut items = Vec::new();
+        let mut rest = inner;
+        while !rest.is_empty() {
+            let (item, remaining) = split_item(rest)?;
+            items.push(parse_value(item)?);
+            rest = remaining;
+        }
+        return Ok(items.join(","));
+    }
+    if value.starts_with('"') {
+        if value.len() < 2 || !value.ends_with('"') {
+            return Err("Unterminated string".into());
+        }
+        return unescape(&value[1..value.len() - 1]);
+    }
+    if value == "true" || value == "false" || value.parse::<f64>().is_ok() {
+        return Ok(value.into());
+    }
+    Err(format!("Invalid value {}, strings need quotes", value))
+}
+
+// The first item of an array and what follows its comma
+fn split_item(items: &str) -> Result<(&str, &str), String> {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (idx, c) in items.char_indices() {
+        match c {
+            '\\' if quoted && !escaped => {
+                escaped = true;
+                continue;
+            }
+            '"' if !escaped => quoted = !quoted,
+            ',' if !quoted => return Ok((items[..idx].trim(), items[idx + 1..].trim())),
+            _ => (),
+        }
+        escaped = false;
+    }
+    if quoted {
+        return Err("Unterminated string".into());
+    }
+    Ok((items.trim(), ""))
+}
+
+fn unescape(string: &str) -> Result<String, String> {
+    let mut unescaped = String::with_capacity(string.len());
+    let mut chars = string.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('"') => unescaped.push('"'),
+            Some('\\') => unescaped.push('\\'),
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            _ => return Err("Invalid escape in string".into()),
+        }
+    }
+    Ok(unescaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_settings_and_network_sections() {
+        let config = ConfigFile::parse(
+            r#"
+            # risq.toml
+            network = ["BtcMainnet", "BtcTestnet"] # both
+            no_tor = true
+            max-offers = 1000
+            notify-webhook = "https://example.com/#hook"
+
+            [BtcTestnet]
+            api-port = 7577
+            force-seed = "localhost:2002"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.settings,
+            vec![
+                ("network".to_string(), "BtcMainnet,BtcTestnet".to_string()),
+                ("no-tor".to_string(), "true".to_string()),
+                ("max-offers".to_string(), "1000".to_string()),
+                (
+                    "notify-webhook".to_string(),
+                    "https://example.com/#hook".to_string()
+                ),
+            ]
+        );
+        assert_eq!(
+            config.network_setting(BaseCurrencyNetwork::BtcTestnet, "api-port", false),
+            Some("7577")
+        );
+        assert_eq!(
+            config.network_setting(BaseCurrencyNetwork::BtcTestnet, "api-port", true),
+            None
+        );
+        assert_eq!(
+            config.network_setting(BaseCurrencyNetwork::BtcMainnet, "api-port", false),
+            None
+        );
+        assert_eq!(config.setting("max-offers", false), Some("1000"));
+        assert_eq!(config.setting("max-offers", true), None);
+        assert_eq!(config.setting("api-port", false), None);
+        assert_eq!(env_var("tor-socks-port"), "RISQ_TOR_SOCKS_PORT");
+
+        assert!(ConfigFile::parse("[BtcTestnet]\nmax-offers = 10").is_err());
+        assert!(ConfigFile::parse("[Litecoin]").is_err());
+        assert!(ConfigFile::parse("network = BtcMainnet").is_err());
+        assert!(ConfigFile::parse("api-port").is_err());
+    }
+}
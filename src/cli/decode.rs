@@ -0,0 +1,201 @@
+use crate::{
+    bisq::{payload::*, SequencedMessageHash},
+    prelude::*,
+};
+use prost::Message;
+use serde::Serialize;
+use std::{fmt, str::FromStr};
+
+pub enum DecodeKind {
+    Envelope,
+    StoragePayload,
+}
+impl FromStr for DecodeKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "envelope" => Ok(DecodeKind::Envelope),
+            "storage-payload" => Ok(DecodeKind::StoragePayload),
+            _ => Err("(envelope|storage-payload)".to_string()),
+        }
+    }
+}
+
+pub fn input_bytes(input: &str) -> Result<Vec<u8>, String> {
+    let input = input.trim();
+    Vec::<u8>::from_hex(input)
+        .or_else(|_| base64::decode(input))
+        .map_err(|_| "Input is neither valid hex nor base64".to_string())
+}
+
+/// Summary of a decoded message. Field names are part of the `--json` output.
+#[derive(Serialize)]
+pub struct Decoded {
+    kind: &'static str,
+    message_version: Option<i32>,
+    message: Option<String>,
+    payload_hash: Option<String>,
+    storage_entries: Vec<StorageEntrySummary>,
+    persistable_payloads: Vec<String>,
+    content: String,
+}
+#[derive(Serialize)]
+pub struct StorageEntrySummary {
+    hash: Option<String>,
+    signature_valid: bool,
+}
+
+pub fn decode(kind: DecodeKind, bytes: &[u8]) -> Result<Decoded, String> {
+    match kind {
+        DecodeKind::Envelope => {
+            let envelope = NetworkEnvelope::decode(bytes)
+                .or_else(|_| NetworkEnvelope::decode_length_delimited(bytes))
+                .map_err(|e| format!("Couldn't decode NetworkEnvelope: {}", e))?;
+            let mut decoded = Decoded {
+                kind: "envelope",
+                message_version: Some(envelope.message_version),
+                message: envelope.message.as_ref().map(message_name),
+                payload_hash: None,
+                storage_entries: Vec::new(),
+                persistable_payloads: Vec::new(),
+                content: format!("{:#?}", envelope),
+            };
+            summarize_envelope(&envelope, &mut decoded);
+            Ok(decoded)
+        }
+        DecodeKind::StoragePayload => {
+            let payload = StoragePayload::decode(bytes)
+                .map_err(|e| format!("Couldn't decode StoragePayload: {}", e))?;
+            Ok(Decoded {
+                kind: "storage-payload",
+                message_version: None,
+                message: None,
+                payload_hash: Some(sequenced_hash(payload.bisq_hash())),
+                storage_entries: Vec::new(),
+                persistable_payloads: Vec::new(),
+                content: format!("{:#?}", payload),
+            })
+        }
+    }
+}
+
+impl Decoded {
+    /// Whether there were storage entries and all of them are signed by
+    /// their owner.
+    pub fn signatures_valid(&self) -> bool {
+        !self.storage_entries.is_empty()
+            && self
+                .storage_entries
+                .iter()
+                .all(|entry| entry.signature_valid)
+    }
+}
+
+impl fmt::Display for Decoded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.content)?;
+        if let Some(version) = self.message_version {
+            writeln!(f, "message version: {}", version)?;
+            if self.message.is_none() {
+                writeln!(f, "envelope has no message attached")?;
+            }
+        }
+        for entry in self.storage_entries.iter() {
+            match entry.hash.as_ref() {
+                Some(hash) => writeln!(
+                    f,
+                    "storage payload {} - signature {}",
+                    hash,
+                    if entry.signature_valid {
+                        "VALID"
+                    } else {
+                        "INVALID"
+                    }
+                )?,
+                None => writeln!(f, "protected storage entry has no payload attached")?,
+            }
+        }
+        for hash in self.persistable_payloads.iter() {
+            writeln!(f, "persistable payload {}", hash)?;
+        }
+        match (self.kind, self.payload_hash.as_ref()) {
+            ("storage-payload", Some(hash)) => writeln!(f, "bisq hash: {}", hash),
+            (_, Some(hash)) => writeln!(f, "payload hash: {}", hash),
+            _ => Ok(()),
+        }
+    }
+}
+
+// The oneof variant, eg. "AddDataMessage"
+fn message_name(message: &network_envelope::Message) -> String {
+    let debug = format!("{:?}", message);
+    debug.split('(').next().unwrap_or_default().to_string()
+}
+
+fn summarize_envelope(envelope: &NetworkEnvelope, decoded: &mut Decoded) {
+    match envelope.message.as_ref() {
+        Some(network_envelope::Message::AddDataMessage(msg)) => {
+            if let Some(entry) = msg.entry.as_ref() {
+                summarize_storage_entry(entry, decoded);
+            }
+        }
+        Some(network_envelope::Message::RemoveDataMessage(msg)) => {
+            if let Some(entry) = msg.protected_storage_entry.as_ref() {
+                summarize_protected_entry(entry, decoded);
+            }
+        }
+        Some(network_envelope::Message::RefreshOfferMessage(msg)) => {
+            decoded.payload_hash = msg.payload_hash().ok().map(sequenced_hash);
+        }
+        Some(network_envelope::Message::AddPersistableNetworkPayloadMessage(msg)) => {
+            if let Some(payload) = msg.payload.as_ref() {
+                summarize_persistable_payload(payload, decoded);
+            }
+        }
+        Some(network_envelope::Message::GetDataResponse(response)) => {
+            for entry in response.data_set.iter() {
+                summarize_storage_entry(entry, decoded);
+            }
+            for payload in response.persistable_network_payload_items.iter() {
+                summarize_persistable_payload(payload, decoded);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn summarize_storage_entry(entry: &StorageEntryWrapper, decoded: &mut Decoded) {
+    match entry.message.as_ref() {
+        Some(storage_entry_wrapper::Message::ProtectedStorageEntry(entry)) => {
+            summarize_protected_entry(entry, decoded)
+        }
+        Some(storage_entry_wrapper::Message::ProtectedMailboxStorageEntry(mailbox)) => {
+            if let Some(entry) = mailbox.entry.as_ref() {
+                summarize_protected_entry(entry, decoded)
+            }
+        }
+        None => (),
+    }
+}
+
+fn summarize_protected_entry(entry: &ProtectedStorageEntry, decoded: &mut Decoded) {
+    decoded.storage_entries.push(StorageEntrySummary {
+        hash: entry
+            .storage_payload
+            .as_ref()
+            .map(|payload| sequenced_hash(payload.bisq_hash())),
+        signature_valid: entry.verify().is_some(),
+    });
+}
+
+fn summarize_persistable_payload(payload: &PersistableNetworkPayload, decoded: &mut Decoded) {
+    if let Ok(hash) = payload.bisq_hash() {
+        let hash: Vec<u8> = hash.into();
+        decoded.persistable_payloads.push(hash.to_hex());
+    }
+}
+
+fn sequenced_hash(hash: SequencedMessageHash) -> String {
+    let hash: Vec<u8> = hash.into();
+    hash.to_hex()
+}
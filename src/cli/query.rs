@@ -1,6 +1,21 @@
-use crate::{api::WithQueryFields, domain::market::Market};
-use serde::Deserialize;
-use std::{collections::HashMap, fmt, iter::Chain, vec::IntoIter};
+use super::table::Table;
+use crate::{
+    api::WithQueryFields,
+    domain::{
+        amount::NumberWithPrecision,
+        format::{self, Locale},
+        market::Market,
+    },
+};
+use chrono::{TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    iter::Chain,
+    time::{SystemTime, UNIX_EPOCH},
+    vec::IntoIter,
+};
 
 #[derive(Deserialize)]
 pub struct Offers {
@@ -31,7 +46,7 @@ impl IntoIterator for Offers {
 
     fn into_iter(self) -> Self::IntoIter {
         let BuysAndSells { buys, sells } = self.buys_and_sells;
-        buys.into_iter().chain(sells.into_iter())
+        buys.into_iter().chain(sells)
     }
 }
 impl WithQueryFields for Offers {
@@ -48,7 +63,7 @@ impl WithQueryFields for Offers {
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct Offer {
     pub id: String,
     pub market: String,
@@ -59,19 +74,55 @@ pub struct Offer {
     pub volume: String,
     pub payment_method: String,
 }
-impl fmt::Display for Offer {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{} - {} {} {}({}) {} {}",
-            self.id,
-            self.direction,
-            self.price,
-            self.amount,
-            self.min_amount,
-            self.volume,
-            self.payment_method
-        )
+impl Offer {
+    /// Reformats the raw numbers of the api for display.
+    pub fn localized(self, locale: Locale) -> Self {
+        let market = match Market::from_pair(&self.market) {
+            Some(market) => market,
+            None => return self,
+        };
+        let reformat = |raw: String, fmt: &dyn Fn(NumberWithPrecision) -> String| match raw.parse()
+        {
+            Ok(n) => fmt(n),
+            Err(_) => raw,
+        };
+        let btc = |n| format::btc(n, locale);
+        Self {
+            price: reformat(self.price, &|n| format::price(n, market, locale)),
+            amount: reformat(self.amount, &btc),
+            min_amount: reformat(self.min_amount, &btc),
+            volume: reformat(self.volume, &|n| {
+                format::amount(n, market.non_btc_side(), locale)
+            }),
+            ..self
+        }
+    }
+
+    pub fn table(offers: impl IntoIterator<Item = Offer>) -> Table {
+        let mut table = Table::new(&[
+            "ID",
+            "MARKET",
+            "DIRECTION",
+            "PRICE",
+            "AMOUNT",
+            "MIN",
+            "VOLUME",
+            "METHOD",
+        ])
+        .align_right(&[3, 4, 5, 6]);
+        for offer in offers {
+            table.row(vec![
+                offer.id,
+                offer.market,
+                offer.direction,
+                offer.price,
+                offer.amount,
+                offer.min_amount,
+                offer.volume,
+                offer.payment_method,
+            ]);
+        }
+        table
     }
 }
 
@@ -89,3 +140,560 @@ impl WithQueryFields for Offer {
         .to_string()
     }
 }
+
+#[derive(Deserialize, Serialize)]
+pub struct Status {
+    pub state: String,
+    pub progress: usize,
+    pub phases: Vec<Phase>,
+    #[serde(default)]
+    pub tor: Option<Tor>,
+    pub connections: HashMap<String, Connection>,
+    #[serde(default)]
+    pub clock_skew: Option<ClockSkew>,
+}
+impl Status {
+    pub fn is_bootstrapped(&self) -> bool {
+        self.state == "Running"
+    }
+    pub fn identified_peers(&self) -> usize {
+        self.connections
+            .values()
+            .filter(|conn| conn.addr.is_some())
+            .count()
+    }
+
+    /// The connections by kind and address.
+    pub fn peers_table(&self) -> Table {
+        let mut connections: Vec<_> = self.connections.values().collect();
+        connections.sort_by(|a, b| (&a.kind, &a.addr).cmp(&(&b.kind, &b.addr)));
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_secs())
+            .unwrap_or(0);
+        let mut table = Table::new(&["ADDRESS", "KIND", "LAST SEEN", "PING"]).align_right(&[2, 3]);
+        for conn in connections {
+            table.row(vec![
+                conn.addr.clone().unwrap_or_else(|| "<unidentified>".into()),
+                conn.kind.clone(),
+                format!("{}s ago", now.saturating_sub(conn.alive_at)),
+                conn.round_trip_ms
+                    .map(|ms| format!("{}ms", ms))
+                    .unwrap_or_else(|| "-".into()),
+            ]);
+        }
+        table
+    }
+}
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "STATE: {} ({}%)", self.state, self.progress)?;
+        for phase in self.phases.iter() {
+            writeln!(
+                f,
+                "  [{}] {}",
+                if phase.reached { "x" } else { " " },
+                phase.phase
+            )?;
+        }
+        if let Some(tor) = self.tor.as_ref() {
+            match (&tor.hidden_service, tor.progress) {
+                (Some(addr), _) => writeln!(f, "TOR: published {}", addr)?,
+                (None, Some(progress)) => writeln!(
+                    f,
+                    "TOR: {}% - {}",
+                    progress,
+                    tor.summary.as_deref().unwrap_or_default()
+                )?,
+                (None, None) if tor.reachable => writeln!(f, "TOR: connected")?,
+                (None, None) => writeln!(f, "TOR: control port not reachable")?,
+            }
+        }
+        write!(f, "PEERS: {}", self.identified_peers())?;
+        match self.clock_skew.as_ref() {
+            Some(ClockSkew {
+                offset_ms: Some(offset_ms),
+                exceeded: true,
+            }) => write!(
+                f,
+                "\nWARNING: local clock is off by {}s compared to the network",
+                offset_ms / 1000
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+#[derive(Deserialize, Serialize)]
+pub struct ClockSkew {
+    pub offset_ms: Option<i64>,
+    pub exceeded: bool,
+}
+#[derive(Deserialize, Serialize)]
+pub struct Tor {
+    pub reachable: bool,
+    pub progress: Option<u8>,
+    pub summary: Option<String>,
+    pub hidden_service: Option<String>,
+}
+#[derive(Deserialize, Serialize)]
+pub struct Phase {
+    pub phase: String,
+    pub reached: bool,
+}
+#[derive(Deserialize, Serialize)]
+pub struct Connection {
+    pub addr: Option<String>,
+    /// `seed`, `reported` or `direct`
+    #[serde(default)]
+    pub kind: String,
+    #[serde(default)]
+    pub alive_at: u64,
+    #[serde(default)]
+    pub round_trip_ms: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct LogFilters {
+    pub filters: String,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum ApiResult<T> {
+    Ok(T),
+    Err { error: String },
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TakeOfferRequest {
+    pub offer_id: String,
+    pub amount: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct Trade {
+    pub id: String,
+    pub market: String,
+    pub role: String,
+    pub direction: String,
+    pub amount: String,
+    pub price: String,
+    pub state: String,
+    pub peer: String,
+    pub taker_fee_tx_id: Option<String>,
+    pub deposit_tx_id: Option<String>,
+    pub payout_tx_id: Option<String>,
+    pub mediated_payout: Option<MediatedPayout>,
+    pub delayed_payout_tx_id: Option<String>,
+    pub refund_payout: Option<RefundPayout>,
+    pub error: Option<String>,
+    pub created_at: u64,
+}
+#[derive(Deserialize, Serialize)]
+pub struct MediatedPayout {
+    pub buyer_payout: String,
+    pub seller_payout: String,
+    pub accepted: bool,
+}
+#[derive(Deserialize, Serialize)]
+pub struct RefundPayout {
+    pub buyer_payout: String,
+    pub seller_payout: String,
+}
+impl fmt::Display for Trade {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} BTC @ {} {} with {} - {}",
+            self.id,
+            self.role,
+            self.direction,
+            self.amount,
+            self.price,
+            self.market,
+            self.peer,
+            self.state
+        )?;
+        if let Some(tx_id) = self.deposit_tx_id.as_ref() {
+            write!(f, " (deposit tx {})", tx_id)?;
+        }
+        if let Some(tx_id) = self.payout_tx_id.as_ref() {
+            write!(f, " (payout tx {})", tx_id)?;
+        }
+        if let Some(payout) = self.mediated_payout.as_ref() {
+            write!(
+                f,
+                " (mediator proposes {} BTC to the buyer, {} BTC to the seller{})",
+                payout.buyer_payout,
+                payout.seller_payout,
+                if payout.accepted { ", accepted" } else { "" }
+            )?;
+        }
+        if let (Some(tx_id), "DelayedPayoutPublished") =
+            (self.delayed_payout_tx_id.as_ref(), self.state.as_str())
+        {
+            write!(f, " (delayed payout tx {})", tx_id)?;
+        }
+        if let Some(payout) = self.refund_payout.as_ref() {
+            write!(
+                f,
+                " (refund agent pays {} BTC to the buyer, {} BTC to the seller)",
+                payout.buyer_payout, payout.seller_payout
+            )?;
+        }
+        match self.error.as_ref() {
+            Some(error) => write!(f, ": {}", error),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TradeRecord {
+    pub id: String,
+    pub market: String,
+    pub role: String,
+    pub direction: String,
+    pub amount: String,
+    pub price: String,
+    pub payment_method: String,
+    pub state: String,
+    pub peer: String,
+    pub fee: Option<String>,
+    pub fee_currency: Option<String>,
+    pub fee_tx_id: Option<String>,
+    pub deposit_tx_id: Option<String>,
+    pub payout_tx_id: Option<String>,
+    pub delayed_payout_tx_id: Option<String>,
+    pub error: Option<String>,
+    pub created_at: u64,
+    pub completed_at: u64,
+}
+impl fmt::Display for TradeRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {} BTC @ {} {} via {} with {} - {}",
+            Utc.timestamp(self.completed_at as i64, 0)
+                .format("%Y-%m-%d"),
+            self.id,
+            self.role,
+            self.direction,
+            self.amount,
+            self.price,
+            self.market,
+            self.payment_method,
+            self.peer,
+            self.state
+        )?;
+        if let (Some(fee), Some(currency)) = (self.fee.as_ref(), self.fee_currency.as_ref()) {
+            write!(f, " (fee {} {})", fee, currency)?;
+        }
+        if let Some(tx_id) = self.payout_tx_id.as_ref() {
+            write!(f, " (payout tx {})", tx_id)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct Wallet {
+    pub balance: String,
+    pub receive_address: String,
+}
+impl fmt::Display for Wallet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Balance: {} BTC", self.balance)?;
+        write!(f, "Receive address: {}", self.receive_address)
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FeeServiceRate {
+    pub sats_per_vbyte: u64,
+    pub estimated_at: u64,
+}
+#[derive(Deserialize, Serialize)]
+pub struct Fees {
+    pub fee_service: Option<FeeServiceRate>,
+    pub trade_sats_per_vbyte: Option<u64>,
+}
+impl fmt::Display for Fees {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.fee_service.as_ref() {
+            Some(rate) => writeln!(f, "Fee service: {} sat/vB", rate.sats_per_vbyte)?,
+            None => writeln!(f, "Fee service: <no recent estimate>")?,
+        }
+        match self.trade_sats_per_vbyte {
+            Some(rate) => write!(f, "Trade txs: {} sat/vB", rate),
+            None => write!(f, "Trade txs: <no wallet configured>"),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TradeFees {
+    pub amount: String,
+    pub maker: TradeFee,
+    pub taker: TradeFee,
+}
+#[derive(Deserialize, Serialize)]
+pub struct TradeFee {
+    pub btc: String,
+    pub bsq: String,
+    pub bsq_in_btc: Option<String>,
+}
+impl fmt::Display for TradeFees {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Trade fees for {} BTC", self.amount)?;
+        writeln!(f, "Maker: {}", self.maker)?;
+        write!(f, "Taker: {}", self.taker)
+    }
+}
+impl fmt::Display for TradeFee {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} BTC or {} BSQ", self.btc, self.bsq)?;
+        match self.bsq_in_btc.as_ref() {
+            Some(btc) => write!(f, " (~{} BTC)", btc),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct OfferAnomalies {
+    pub offers: usize,
+    pub signed_makers: usize,
+    pub flagged: Vec<FlaggedOffer>,
+}
+#[derive(Deserialize, Serialize)]
+pub struct FlaggedOffer {
+    pub id: String,
+    pub market: String,
+    pub direction: String,
+    pub maker_signed: bool,
+    pub anomalies: Vec<String>,
+}
+impl fmt::Display for OfferAnomalies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "OFFER ANOMALIES")?;
+        write!(
+            f,
+            "{} offers, {} of signed makers",
+            self.offers, self.signed_makers
+        )?;
+        if self.flagged.is_empty() {
+            return write!(f, "\n<no anomalies found>");
+        }
+        for offer in self.flagged.iter() {
+            write!(
+                f,
+                "\n{} {} {}{}: {}",
+                offer.id,
+                offer.market,
+                offer.direction,
+                if offer.maker_signed {
+                    " (signed maker)"
+                } else {
+                    ""
+                },
+                offer.anomalies.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct SendChatRequest {
+    pub party: Option<String>,
+    pub text: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ChatLine {
+    pub uid: String,
+    pub party: String,
+    pub sent: bool,
+    pub text: String,
+    pub date: u64,
+    pub delivery: Option<String>,
+}
+impl fmt::Display for ChatLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.sent {
+            return write!(f, "{}: {}", self.party, self.text);
+        }
+        write!(f, "me: {}", self.text)?;
+        match self.delivery.as_ref() {
+            Some(delivery) => write!(f, " ({})", delivery),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct CreatePaymentAccountRequest {
+    pub name: String,
+    pub payment_method: String,
+    pub currency: String,
+    pub holder_name: Option<String>,
+    pub iban: Option<String>,
+    pub bic: Option<String>,
+    pub account_id: Option<String>,
+    pub country_code: Option<String>,
+    pub contact: Option<String>,
+    pub city: Option<String>,
+    pub extra_info: Option<String>,
+    pub address: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct PaymentAccount {
+    pub id: String,
+    pub name: String,
+    pub payment_method: String,
+    pub currency: String,
+    pub details: BTreeMap<String, String>,
+    pub age_witness_hash: String,
+    pub age_days: u64,
+    pub created_at: u64,
+}
+impl fmt::Display for PaymentAccount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} - {} days old",
+            self.id, self.name, self.payment_method, self.currency, self.age_days
+        )?;
+        for (key, value) in self.details.iter().filter(|(_, value)| !value.is_empty()) {
+            write!(f, ", {}: {}", key, value)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct DaoStatus {
+    pub genesis_tx_id: String,
+    pub height: Option<u32>,
+    pub bsq_txs: usize,
+    pub unspent_outputs: usize,
+    pub cycle: Option<u32>,
+    pub phase: Option<String>,
+}
+impl fmt::Display for DaoStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.height {
+            Some(height) => writeln!(f, "Synced blocks up to: {}", height)?,
+            None => writeln!(f, "Synced blocks up to: <waiting for genesis block>")?,
+        }
+        if let (Some(cycle), Some(phase)) = (self.cycle, self.phase.as_ref()) {
+            writeln!(f, "Cycle: {} in phase {}", cycle + 1, phase)?;
+        }
+        writeln!(f, "Genesis tx: {}", self.genesis_tx_id)?;
+        writeln!(f, "BSQ txs: {}", self.bsq_txs)?;
+        write!(f, "Unspent BSQ outputs: {}", self.unspent_outputs)
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct BsqSupply {
+    pub genesis: String,
+    pub burnt: String,
+    pub circulating: String,
+}
+impl fmt::Display for BsqSupply {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Genesis: {} BSQ", self.genesis)?;
+        writeln!(f, "Burnt: {} BSQ", self.burnt)?;
+        write!(f, "Circulating: {} BSQ", self.circulating)
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct BsqBalance {
+    pub address: String,
+    pub balance: String,
+    pub unspent_outputs: usize,
+}
+impl fmt::Display for BsqBalance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} BSQ in {} unspent outputs",
+            self.address, self.balance, self.unspent_outputs
+        )
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ProposalResult {
+    pub tx_id: String,
+    pub name: String,
+    pub link: String,
+    pub kind: String,
+    pub requested_bsq: Option<String>,
+    pub stake_of_accepted_votes: String,
+    pub stake_of_rejected_votes: String,
+    pub num_accepted_votes: usize,
+    pub num_rejected_votes: usize,
+    pub num_ignored_votes: usize,
+    pub accepted: bool,
+}
+impl fmt::Display for ProposalResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} ",
+            if self.accepted {
+                "ACCEPTED"
+            } else {
+                "REJECTED"
+            },
+            self.kind,
+            self.name
+        )?;
+        if let Some(requested_bsq) = self.requested_bsq.as_ref() {
+            write!(f, "({} BSQ) ", requested_bsq)?;
+        }
+        write!(
+            f,
+            "- {} BSQ in {} votes for, {} BSQ in {} votes against, {} ignored",
+            self.stake_of_accepted_votes,
+            self.num_accepted_votes,
+            self.stake_of_rejected_votes,
+            self.num_rejected_votes,
+            self.num_ignored_votes
+        )
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct CycleResult {
+    pub index: u32,
+    pub first_block: u32,
+    pub last_block: u32,
+    pub blind_votes: usize,
+    pub vote_reveals: usize,
+    pub issued: String,
+    pub proposals: Vec<ProposalResult>,
+}
+impl fmt::Display for CycleResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Cycle {} (blocks {} - {}): {} of {} blind votes revealed, {} BSQ issued",
+            self.index + 1,
+            self.first_block,
+            self.last_block,
+            self.vote_reveals,
+            self.blind_votes,
+            self.issued
+        )?;
+        for proposal in self.proposals.iter() {
+            write!(f, "\n  {}", proposal)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+use std::fmt;
+
+/// Columns of text padded to their widest cell, numbers aligned to the right.
+pub struct Table {
+    headers: Vec<&'static str>,
+    right_aligned: Vec<bool>,
+    rows: Vec<Vec<String>>,
+}
+impl Table {
+    pub fn new(headers: &[&'static str]) -> Self {
+        Table {
+            headers: headers.to_vec(),
+            right_aligned: vec![false; headers.len()],
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn align_right(mut self, columns: &[usize]) -> Self {
+        for column in columns {
+            self.right_aligned[*column] = true;
+        }
+        self
+    }
+
+    pub fn row(&mut self, cells: Vec<String>) {
+        debug_assert_eq!(cells.len(), self.headers.len());
+        self.rows.push(cells);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+}
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let widths: Vec<usize> = self
+            .headers
+            .iter()
+            .enumerate()
+            .map(|(column, header)| {
+                self.rows
+                    .iter()
+                    .map(|row| row[column].chars().count())
+                    .fold(header.len(), usize::max)
+            })
+            .collect();
+        let headers: Vec<String> = self
+            .headers
+            .iter()
+            .map(|header| header.to_string())
+            .collect();
+        for (idx, row) in Some(&headers).into_iter().chain(&self.rows).enumerate() {
+            if idx > 0 {
+                writeln!(f)?;
+            }
+            let mut line = String::new();
+            for (column, cell) in row.iter().enumerate() {
+                let padding = " ".repeat(widths[column] - cell.chars().count());
+                if column > 0 {
+                    line.push_str("  ");
+                }
+                if self.right_aligned[column] {
+                    line.push_str(&padding);
+                    line.push_str(cell);
+                } else {
+                    line.push_str(cell);
+                    line.push_str(&padding);
+                }
+            }
+            write!(f, "{}", line.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pads_columns() {
+        let mut table = Table::new(&["ID", "PRICE", "METHOD"]).align_right(&[1]);
+        assert!(table.is_empty());
+        table.row(vec!["a".into(), "9000.5".into(), "SEPA".into()]);
+        table.row(vec!["bcd".into(), "10".into(), "F2F".into()]);
+        assert_eq!(
+            table.to_string(),
+            "ID    PRICE  METHOD\n\
+             a    9000.5  SEPA\n\
+             bcd      10  F2F"
+        );
+    }
+}
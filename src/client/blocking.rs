@@ -0,0 +1,123 @@
+//! The client for scripts, every call waits for the response.
+use super::{
+    api_error, headers, parse_url, subscription_query, Error, OfferChangesResponse, OfferEvent,
+    OffersQuery, OffersResponse, StatusResponse, Tracker,
+};
+use reqwest::{Client as HttpClient, Response, Url};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{collections::VecDeque, thread, time::Duration};
+
+pub struct Client {
+    url: Url,
+    client: HttpClient,
+}
+impl Client {
+    /// `url` of the api, eg. `http://127.0.0.1:7477`. `token` is sent with
+    /// every request when the api requires one.
+    pub fn new(url: &str, token: Option<String>) -> Result<Self, Error> {
+        Ok(Self {
+            url: parse_url(url)?,
+            client: HttpClient::builder()
+                .default_headers(headers(token))
+                .build()?,
+        })
+    }
+
+    pub fn get_status(&self) -> Result<StatusResponse, Error> {
+        self.get("/status", &())
+    }
+
+    /// The offers matching `query`, whatever its `since`.
+    pub fn get_offers(&self, query: &OffersQuery) -> Result<OffersResponse, Error> {
+        self.get(
+            "/offers",
+            &OffersQuery {
+                since: None,
+                ..query.clone()
+            },
+        )
+    }
+
+    /// What changed since `version`, an `Error::Api` with status `410` when
+    /// the daemon doesn't know it anymore.
+    pub fn get_offer_changes(
+        &self,
+        query: &OffersQuery,
+        version: u64,
+    ) -> Result<OfferChangesResponse, Error> {
+        self.get(
+            "/offers",
+            &OffersQuery {
+                since: Some(version),
+                ..query.clone()
+            },
+        )
+    }
+
+    /// The changes of the offers matching `query` from now on, asked for
+    /// every `interval`. Unlike the async stream it goes on after errors.
+    pub fn subscribe_events(&self, query: OffersQuery, interval: Duration) -> Events<'_> {
+        Events {
+            client: self,
+            query: subscription_query(query),
+            interval,
+            tracker: Tracker::default(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn get<Q: Serialize, T: DeserializeOwned>(&self, path: &str, query: &Q) -> Result<T, Error> {
+        let url = self.url.join(path).expect("Couldn't build api url");
+        json(self.client.get(url).query(query).send()?)
+    }
+}
+
+fn json<T: DeserializeOwned>(mut response: Response) -> Result<T, Error> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response.json()?);
+    }
+    Err(api_error(status, response.json().ok()))
+}
+
+pub struct Events<'a> {
+    client: &'a Client,
+    query: OffersQuery,
+    interval: Duration,
+    tracker: Tracker,
+    pending: VecDeque<OfferEvent>,
+}
+impl Events<'_> {
+    fn poll(&mut self) -> Result<Vec<OfferEvent>, Error> {
+        let version = match self.tracker.version {
+            Some(version) => version,
+            None => {
+                let offers = self.client.get_offers(&self.query)?;
+                return Ok(self.tracker.book(offers));
+            }
+        };
+        thread::sleep(self.interval);
+        match self.client.get_offer_changes(&self.query, version) {
+            Ok(changes) => Ok(self.tracker.changes(changes)),
+            Err(Error::Api(410, _)) => {
+                let offers = self.client.get_offers(&self.query)?;
+                Ok(self.tracker.book(offers))
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+impl Iterator for Events<'_> {
+    type Item = Result<OfferEvent, Error>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(Ok(event));
+            }
+            match self.poll() {
+                Ok(events) => self.pending.extend(events),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
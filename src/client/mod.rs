@@ -0,0 +1,356 @@
+//! Typed access to the REST api of a running daemon, with the bodies the
+//! server itself serializes. `blocking` offers the same for scripts without
+//! an event loop.
+pub mod blocking;
+
+pub use crate::api::responses::*;
+use futures::{future, stream, Future, Stream};
+use reqwest::{
+    header::{self, HeaderMap, HeaderValue},
+    r#async::{Client as HttpClient, Response},
+    StatusCode, Url,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::HashMap,
+    fmt,
+    time::{Duration, Instant},
+};
+use tokio::timer::Delay;
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidUrl(String),
+    Http(reqwest::Error),
+    /// The api refused the request, with its status and the error it gave
+    Api(u16, String),
+    Timer(tokio::timer::Error),
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidUrl(url) => write!(f, "Invalid api url '{}'", url),
+            Error::Http(e) => write!(f, "Request to the api failed: {}", e),
+            Error::Api(status, error) => write!(f, "Api answered {}: {}", status, error),
+            Error::Timer(e) => write!(f, "Timer failed: {}", e),
+        }
+    }
+}
+impl std::error::Error for Error {}
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        Error::Http(err)
+    }
+}
+impl From<tokio::timer::Error> for Error {
+    fn from(err: tokio::timer::Error) -> Self {
+        Error::Timer(err)
+    }
+}
+
+pub type ClientFuture<T> = Box<dyn Future<Item = T, Error = Error> + Send>;
+
+#[derive(Clone)]
+pub struct Client {
+    url: Url,
+    client: HttpClient,
+}
+impl Client {
+    /// `url` of the api, eg. `http://127.0.0.1:7477`. `token` is sent with
+    /// every request when the api requires one.
+    pub fn new(url: &str, token: Option<String>) -> Result<Self, Error> {
+        Ok(Self {
+            url: parse_url(url)?,
+            client: HttpClient::builder()
+                .default_headers(headers(token))
+                .build()?,
+        })
+    }
+
+    pub fn get_status(&self) -> ClientFuture<StatusResponse> {
+        self.get("/status", &())
+    }
+
+    /// The offers matching `query`, whatever its `since`.
+    pub fn get_offers(&self, query: &OffersQuery) -> ClientFuture<OffersResponse> {
+        self.get(
+            "/offers",
+            &OffersQuery {
+                since: None,
+                ..query.clone()
+            },
+        )
+    }
+
+    /// What changed since `version`, an `Error::Api` with status `410` when
+    /// the daemon doesn't know it anymore.
+    pub fn get_offer_changes(
+        &self,
+        query: &OffersQuery,
+        version: u64,
+    ) -> ClientFuture<OfferChangesResponse> {
+        self.get(
+            "/offers",
+            &OffersQuery {
+                since: Some(version),
+                ..query.clone()
+            },
+        )
+    }
+
+    /// The changes of the offers matching `query` from now on, the same
+    /// `/ws` pushes. It asks for the changes every `interval` and, when the
+    /// daemon lost track of its version, for all offers to tell the
+    /// difference. The stream ends after the first error.
+    pub fn subscribe_events(
+        &self,
+        query: OffersQuery,
+        interval: Duration,
+    ) -> impl Stream<Item = OfferEvent, Error = Error> {
+        let client = self.clone();
+        let query = subscription_query(query);
+        stream::unfold(Tracker::default(), move |mut tracker| {
+            let client = client.clone();
+            let query = query.clone();
+            let poll: ClientFuture<(Vec<OfferEvent>, Tracker)> = match tracker.version {
+                None => Box::new(client.get_offers(&query).map(move |offers| {
+                    let events = tracker.book(offers);
+                    (events, tracker)
+                })),
+                Some(version) => Box::new(
+                    Delay::new(Instant::now() + interval)
+                        .from_err()
+                        .and_then(move |_| {
+                            client
+                                .get_offer_changes(&query, version)
+                                .then(move |changes| match changes {
+                                    Ok(changes) => {
+                                        let events = tracker.changes(changes);
+                                        future::Either::A(future::ok((events, tracker)))
+                                    }
+                                    Err(Error::Api(410, _)) => future::Either::B(
+                                        client.get_offers(&query).map(move |offers| {
+                                            let events = tracker.book(offers);
+                                            (events, tracker)
+                                        }),
+                                    ),
+                                    Err(e) => future::Either::A(future::err(e)),
+                                })
+                        }),
+                ),
+            };
+            Some(poll)
+        })
+        .map(stream::iter_ok::<_, Error>)
+        .flatten()
+    }
+
+    fn get<Q: Serialize, T: DeserializeOwned + Send + 'static>(
+        &self,
+        path: &str,
+        query: &Q,
+    ) -> ClientFuture<T> {
+        let url = self.url.join(path).expect("Couldn't build api url");
+        Box::new(
+            self.client
+                .get(url)
+                .query(query)
+                .send()
+                .from_err()
+                .and_then(json::<T>),
+        )
+    }
+}
+
+fn json<T: DeserializeOwned + Send + 'static>(mut response: Response) -> ClientFuture<T> {
+    let status = response.status();
+    if status.is_success() {
+        return Box::new(response.json().from_err());
+    }
+    Box::new(
+        response
+            .json::<ErrorResponse>()
+            .then(move |body| Err::<T, _>(api_error(status, body.ok()))),
+    )
+}
+
+fn api_error(status: StatusCode, body: Option<ErrorResponse>) -> Error {
+    Error::Api(
+        status.as_u16(),
+        body.map(|body| body.error)
+            .unwrap_or_else(|| status.to_string()),
+    )
+}
+
+fn parse_url(url: &str) -> Result<Url, Error> {
+    url.parse().map_err(|_| Error::InvalidUrl(url.into()))
+}
+
+fn headers(token: Option<String>) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if let Some(value) =
+        token.and_then(|token| HeaderValue::from_str(&format!("Bearer {}", token)).ok())
+    {
+        headers.insert(header::AUTHORIZATION, value);
+    }
+    headers
+}
+
+// A subscription follows the whole book matching the filters, not a page.
+fn subscription_query(query: OffersQuery) -> OffersQuery {
+    OffersQuery {
+        offset: None,
+        limit: None,
+        since: None,
+        ..query
+    }
+}
+
+/// The offers a subscription knows of, by id with their market, to tell
+/// what changed.
+#[derive(Default)]
+struct Tracker {
+    version: Option<u64>,
+    markets: HashMap<String, String>,
+}
+impl Tracker {
+    /// Takes all offers, the difference to those known before. The first
+    /// time nothing changed.
+    fn book(&mut self, response: OffersResponse) -> Vec<OfferEvent> {
+        let known = self.version.is_some();
+        self.version = Some(response.version);
+        let current: HashMap<String, String> = response
+            .offers
+            .iter()
+            .map(|offer| (offer.id.clone(), offer.market.clone()))
+            .collect();
+        let mut events = Vec::new();
+        if known {
+            events.extend(
+                self.markets
+                    .iter()
+                    .filter(|(id, _)| !current.contains_key(*id))
+                    .map(|(id, market)| OfferEvent::Removed {
+                        id: id.clone(),
+                        market: market.clone(),
+                    }),
+            );
+            let markets = &self.markets;
+            events.extend(
+                response
+                    .offers
+                    .into_iter()
+                    .filter(|offer| !markets.contains_key(&offer.id))
+                    .map(OfferEvent::Added),
+            );
+        }
+        self.markets = current;
+        events
+    }
+
+    fn changes(&mut self, changes: OfferChangesResponse) -> Vec<OfferEvent> {
+        self.version = Some(changes.version);
+        let mut events = Vec::new();
+        for id in changes.removed {
+            // Those that didn't match the filters aren't known
+            if let Some(market) = self.markets.remove(&id) {
+                events.push(OfferEvent::Removed { id, market });
+            }
+        }
+        for offer in changes.added {
+            self.markets.insert(offer.id.clone(), offer.market.clone());
+            events.push(OfferEvent::Added(offer));
+        }
+        for offer in changes.refreshed {
+            self.markets.insert(offer.id.clone(), offer.market.clone());
+            events.push(OfferEvent::Refreshed(offer));
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offer(id: &str) -> Offer {
+        Offer {
+            id: id.into(),
+            market: "btc_eur".into(),
+            direction: "SELL".into(),
+            price: Some("9000.0000".into()),
+            market_price_margin: None,
+            premium: None,
+            amount: "0.10000000".into(),
+            min_amount: "0.10000000".into(),
+            payment_method: "SEPA".into(),
+            max_trade_limit: None,
+            maker_age_witness_hash: None,
+            maker_signed_since: None,
+            created_at: 0,
+        }
+    }
+
+    fn book(version: u64, ids: &[&str]) -> OffersResponse {
+        OffersResponse {
+            total: ids.len(),
+            offers: ids.iter().map(|id| offer(id)).collect(),
+            version,
+        }
+    }
+
+    #[test]
+    fn tells_the_changes_of_the_offer_book() {
+        let mut tracker = Tracker::default();
+        assert!(tracker.book(book(1, &["a", "b"])).is_empty());
+        assert_eq!(
+            tracker.changes(OfferChangesResponse {
+                version: 3,
+                added: vec![offer("c")],
+                refreshed: vec![offer("a")],
+                removed: vec!["b".into(), "unknown".into()],
+            }),
+            vec![
+                OfferEvent::Removed {
+                    id: "b".into(),
+                    market: "btc_eur".into()
+                },
+                OfferEvent::Added(offer("c")),
+                OfferEvent::Refreshed(offer("a")),
+            ]
+        );
+        assert_eq!(tracker.version, Some(3));
+
+        // After a 410 the whole book is compared
+        assert_eq!(
+            tracker.book(book(9, &["c", "d"])),
+            vec![
+                OfferEvent::Removed {
+                    id: "a".into(),
+                    market: "btc_eur".into()
+                },
+                OfferEvent::Added(offer("d")),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_the_bodies_of_the_api() {
+        let event: OfferEvent =
+            serde_json::from_str(r#"{"event":"offer_removed","id":"a","market":"btc_eur"}"#)
+                .unwrap();
+        assert_eq!(
+            event,
+            OfferEvent::Removed {
+                id: "a".into(),
+                market: "btc_eur".into()
+            }
+        );
+        let json = serde_json::to_string(&OfferEvent::Added(offer("a"))).unwrap();
+        assert!(json.starts_with(r#"{"event":"offer_added","id":"a""#));
+        assert_eq!(
+            serde_json::from_str::<OfferEvent>(&json).unwrap(),
+            OfferEvent::Added(offer("a"))
+        );
+    }
+}
@@ -0,0 +1,116 @@
+// PanicInfo is called PanicHookInfo in newer rust, the old name still builds with older ones
+#![allow(deprecated)]
+use crate::{bisq::constants::BaseCurrencyNetwork, logging, p2p::Status};
+use backtrace::Backtrace;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use std::{
+    fs,
+    panic::{self, PanicInfo},
+    path::PathBuf,
+    process,
+    sync::Mutex,
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const CRASH_EXIT_CODE: i32 = 101;
+
+lazy_static! {
+    static ref NODE_STATUS: Mutex<Vec<(BaseCurrencyNetwork, Status)>> = Mutex::new(Vec::new());
+}
+
+pub fn register_status(network: BaseCurrencyNetwork, status: Status) {
+    NODE_STATUS
+        .lock()
+        .expect("Corrupted lock in crash")
+        .push((network, status));
+}
+
+#[derive(Serialize)]
+struct NodeStatus {
+    network: String,
+    state: String,
+    connections: usize,
+}
+#[derive(Serialize)]
+struct CrashReport {
+    version: &'static str,
+    timestamp: u64,
+    thread: Option<String>,
+    message: String,
+    location: Option<String>,
+    backtrace: String,
+    recent_logs: Vec<String>,
+    nodes: Vec<NodeStatus>,
+}
+impl CrashReport {
+    fn new(info: &PanicInfo) -> Self {
+        let message = match info.payload().downcast_ref::<&str>() {
+            Some(msg) => (*msg).to_string(),
+            None => match info.payload().downcast_ref::<String>() {
+                Some(msg) => msg.clone(),
+                None => "Box<Any>".to_string(),
+            },
+        };
+        let recent_logs = logging::recent_logs();
+        let nodes = NODE_STATUS
+            .try_lock()
+            .map(|nodes| {
+                nodes
+                    .iter()
+                    .map(|(network, status)| NodeStatus {
+                        network: format!("{:?}", network),
+                        state: status.bootstrap_state().to_string(),
+                        connections: status.connections().len(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time reversed")
+                .as_secs(),
+            thread: thread::current().name().map(String::from),
+            message,
+            location: info
+                .location()
+                .map(|loc| format!("{}:{}", loc.file(), loc.line())),
+            backtrace: format!("{:?}", Backtrace::new()),
+            recent_logs,
+            nodes,
+        }
+    }
+}
+
+/// Writes a crash report to `report_dir` (and optionally posts it to `report_url`)
+/// before exiting the process on any panic.
+pub fn install_hook(report_dir: PathBuf, report_url: Option<reqwest::Url>) {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        let report = CrashReport::new(info);
+        let path = report_dir.join(format!("crash-{}.json", report.timestamp));
+        match fs::create_dir_all(&report_dir).and_then(|_| {
+            fs::write(
+                &path,
+                serde_json::to_vec_pretty(&report).expect("Couldn't serialize crash report"),
+            )
+        }) {
+            Ok(_) => eprintln!("Crash report written to {}", path.display()),
+            Err(e) => eprintln!("Couldn't write crash report: {}", e),
+        }
+        if let Some(url) = report_url.as_ref() {
+            if let Err(e) = reqwest::Client::new()
+                .post(url.clone())
+                .json(&report)
+                .send()
+            {
+                eprintln!("Couldn't post crash report: {}", e);
+            }
+        }
+        process::exit(CRASH_EXIT_CODE);
+    }));
+}
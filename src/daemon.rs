@@ -1,46 +1,251 @@
+mod account;
 mod convert;
+mod dao;
 mod data_router;
+mod data_store;
+mod direct_messages;
+mod identity;
+mod sequenced_infos;
+mod trade;
+mod verifier;
 
 use crate::{
     api,
-    bisq::{constants::BaseCurrencyNetwork, NodeAddress},
-    domain::{offer::*, price_feed::PriceFeed, statistics::StatsCache},
+    bisq::{
+        constants::{set_p2p_network_version, BaseCurrencyNetwork},
+        crypto::KeyRing,
+        payload::{NewBlockBroadcastMessage, PrefixedSealedAndSignedMessage},
+        NodeAddress,
+    },
+    crash,
+    domain::{
+        alert::Alerts,
+        budget::{Evictions, MemoryBudget},
+        clock::ClockSkew,
+        dao::{
+            bsq::{BsqFees, BsqMakerFee},
+            voting::DaoPayloads,
+            Genesis,
+        },
+        dispute::{Arbitrators, DisputeAgents},
+        fees::FeeRates,
+        filter::FilterStore,
+        mailbox::Mailbox,
+        offer::{
+            compliance::{DelayedPayout, DelayedPayoutPolicy},
+            validation::OfferValidators,
+            *,
+        },
+        payment_account::{AccountAgeWitnesses, PaymentAccounts, SignedWitnesses},
+        price_feed::PriceFeed,
+        statistics::StatsCache,
+    },
+    events::Events,
+    notifier::{self, AlertRules, NotifierConfig, Rule},
     p2p::{
-        dispatch::ActorDispatcher, server, Bootstrap, BootstrapState, Broadcaster, Peers, Status,
-        TorConfig,
+        self,
+        dispatch::{self, ActorDispatcher},
+        server, BanList, Bootstrap, BootstrapState, Broadcaster, PeerStore, Peers,
+        ReportMisbehavior, Status, TorConfig, DEFAULT_MAX_CONNECTIONS,
     },
     prelude::*,
+    reload, shutdown,
+    wallet::{self, WalletConfig},
 };
+use account::{AccountManager, AccountStore};
+use dao::{BlockStore, DaoManager};
 use data_router::*;
-use std::{fs, path::PathBuf};
+pub use data_store::Compaction;
+use data_store::{DataStore, StoredData};
+use direct_messages::DirectMessages;
+use identity::PreviousIdentity;
+pub use identity::{export_identity, import_identity, rotate_identity};
+#[cfg(feature = "testkit")]
+pub(crate) use sequenced_infos::SequencedInfos;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use trade::{OfferStore, TradeManager, TradeStore};
+#[cfg(feature = "testkit")]
+pub(crate) use verifier::{ingest_on_pool, ingest_on_this_thread, signed_entries, Ingested};
 
 pub struct DaemonConfig {
     pub api_port: u16,
+    pub api_bind: String,
+    /// Required for every request but /ping when set
+    pub api_token: Option<String>,
+    /// Origins of web pages that may use the api, `*` for all
+    pub api_cors_origins: Vec<String>,
+    pub zmq_port: Option<u16>,
+    pub grpc_port: Option<u16>,
+    pub mqtt: Option<api::MqttConfig>,
+    pub postgres: Option<api::PostgresConfig>,
+    pub event_log: Option<api::EventLogConfig>,
+    /// Keep the lifecycle of every offer in a SQLite database
+    pub offer_archive: bool,
+    pub notifiers: Vec<NotifierConfig>,
+    pub notification_rules: Vec<Rule>,
     pub server_port: u16,
     pub network: BaseCurrencyNetwork,
     pub force_seed: Option<NodeAddress>,
+    /// Listened on next to the hidden service and told to clearnet peers
+    pub clearnet_address: Option<NodeAddress>,
     pub risq_home: PathBuf,
     pub tor_control_port: Option<u16>,
     pub tor_proxy_port: Option<u16>,
     pub hidden_service_port: Option<u16>,
+    /// Launch this tor binary instead of using a running tor
+    pub tor_binary: Option<PathBuf>,
+    pub memory_budget: MemoryBudget,
+    pub seed_mode: bool,
+    /// Defaults to more connections in seed mode
+    pub max_connections: Option<usize>,
+    /// Defaults to bisq's 10 MB, shared by all networks of the process
+    pub max_message_size: Option<usize>,
+    /// Bytes per second written to all peers, shared by all networks of the
+    /// process
+    pub max_upload_rate: Option<usize>,
+    /// The tens of the message version, bisq's by default
+    pub p2p_network_version: Option<i32>,
+    pub halt_offers_on_alert: bool,
+    pub delayed_payout_policy: DelayedPayoutPolicy,
+    /// Offers from the network that violate one of them aren't listed
+    pub offer_validators: OfferValidators,
+    pub wallet: Option<WalletConfig>,
+    pub key_ring_password: Option<String>,
+    pub dao_genesis: Option<Genesis>,
 }
 
 const SERIVCE_PRIVATE_KEY_PATH: &str = "tor/service.key";
+const TOR_DATA_PATH: &str = "tor/data";
+const KEY_RING_PATH: &str = "keys";
+const TRADES_PATH: &str = "trades";
+const OFFERS_PATH: &str = "offers";
+const PEERS_PATH: &str = "peers";
+const BANS_PATH: &str = "bans";
+const ALERT_RULES_PATH: &str = "alert_rules.json";
+const API_COOKIE_PATH: &str = "api.cookie";
+const PAYMENT_ACCOUNTS_PATH: &str = "payment_accounts";
+const DAO_BLOCKS_PATH: &str = "dao_blocks";
+const NETWORK_DATA_PATH: &str = "network_data";
+const OFFER_ARCHIVE_PATH: &str = "offer_archive.sqlite";
+const SEED_MODE_MAX_CONNECTIONS: usize = 200;
+
+/// Handles to a running network for applications that embed risq.
+#[derive(Clone)]
+pub struct NetworkHandles {
+    pub offer_book: Addr<OfferBook>,
+    pub price_feed: Addr<PriceFeed>,
+    #[cfg_attr(not(feature = "statistics"), allow(dead_code))]
+    pub stats_cache: Option<StatsCache>,
+    pub signed_witnesses: SignedWitnesses,
+    pub events: Events,
+    pub p2p_status: Status,
+}
+
+pub fn run(configs: Vec<DaemonConfig>) {
+    let sys = System::new("risq");
+    shutdown::on_signals();
+    reload::on_signals();
+    start(configs);
+    let _ = sys.run();
+}
+
+/// Rewrites the network data snapshot in `risq_home` with its delta log
+/// applied, for `risq compact`. The daemon must not be running.
+pub fn compact_network_data(risq_home: &Path) -> io::Result<Compaction> {
+    DataStore::new(risq_home.join(NETWORK_DATA_PATH)).compact()
+}
 
-pub fn run(
+/// Starts the networks on the current actix system.
+pub fn start(configs: Vec<DaemonConfig>) -> Vec<NetworkHandles> {
+    // Domain Thread
+    let clock_skew = ClockSkew::default();
+    let fee_rates = FeeRates::default();
+    let price_feed = PriceFeed::start(
+        configs.first().and_then(|c| c.tor_proxy_port),
+        clock_skew.clone(),
+        fee_rates.clone(),
+    );
+    configs
+        .into_iter()
+        .map(|config| {
+            start_network(
+                config,
+                price_feed.clone(),
+                clock_skew.clone(),
+                fee_rates.clone(),
+            )
+        })
+        .collect()
+}
+
+fn start_network(
     DaemonConfig {
         api_port,
+        api_bind,
+        api_token,
+        api_cors_origins,
+        zmq_port,
+        grpc_port,
+        mqtt,
+        postgres,
+        event_log,
+        offer_archive,
+        notifiers,
+        notification_rules,
         server_port,
         network,
         force_seed,
+        clearnet_address,
         risq_home,
         tor_control_port,
         tor_proxy_port,
         hidden_service_port,
+        tor_binary,
+        memory_budget,
+        seed_mode,
+        max_connections,
+        max_message_size,
+        max_upload_rate,
+        p2p_network_version,
+        halt_offers_on_alert,
+        delayed_payout_policy,
+        offer_validators,
+        wallet,
+        key_ring_password,
+        dao_genesis,
     }: DaemonConfig,
-) {
+    price_feed: Addr<PriceFeed>,
+    clock_skew: ClockSkew,
+    fee_rates: FeeRates,
+) -> NetworkHandles {
     let private_key_path = risq_home.join(SERIVCE_PRIVATE_KEY_PATH);
     fs::create_dir_all(private_key_path.parent().unwrap()).expect("Couldn't create risq dir");
+    if let (Some(binary), Some(proxy_port), Some(control_port)) =
+        (tor_binary, tor_proxy_port, tor_control_port)
+    {
+        // The server connects once tor listens on the control port
+        if let Err(e) = p2p::launch_tor(
+            &binary,
+            &risq_home.join(TOR_DATA_PATH),
+            proxy_port,
+            control_port,
+        ) {
+            error!("Couldn't launch {}: {}", binary.display(), e);
+        }
+    }
+    if let Some(max_size) = max_message_size {
+        p2p::limit_message_size(max_size);
+    }
+    if let Some(rate) = max_upload_rate {
+        p2p::limit_upload_rate(rate);
+    }
+    if let Some(version) = p2p_network_version {
+        set_p2p_network_version(network, version);
+    }
     let tor_config = match (tor_control_port, hidden_service_port) {
         (Some(tc_port), Some(hidden_service_port)) => Some(TorConfig {
             hidden_service_port,
@@ -49,47 +254,315 @@ pub fn run(
         }),
         _ => None,
     };
+    let key_ring = Arc::new(
+        KeyRing::load_or_generate(&risq_home.join(KEY_RING_PATH), key_ring_password.as_deref())
+            .expect("Couldn't load key ring"),
+    );
+    let previous_identity = PreviousIdentity::load(&risq_home, key_ring_password.as_deref())
+        .expect("Couldn't load previous identity");
+    let trade_store = TradeStore::new(risq_home.join(TRADES_PATH));
+    let offer_store = OfferStore::new(risq_home.join(OFFERS_PATH));
+    let account_store = AccountStore::new(risq_home.join(PAYMENT_ACCOUNTS_PATH));
+    let payment_accounts = PaymentAccounts::new(
+        account_store
+            .load()
+            .expect("Couldn't load payment accounts"),
+    );
 
-    let sys = System::new("risq");
+    let block_store = BlockStore::new(risq_home.join(DAO_BLOCKS_PATH));
+    let data_store = DataStore::new(risq_home.join(NETWORK_DATA_PATH));
+    let stored_data = data_store.load().unwrap_or_else(|e| {
+        warn!("Couldn't load network data: {}", e);
+        StoredData::default()
+    });
+    let known_keys = stored_data.payload_keys();
+    let peer_store = PeerStore::new(risq_home.join(PEERS_PATH));
+    let known_peers = peer_store.load().unwrap_or_else(|e| {
+        warn!("Couldn't load peers: {}", e);
+        Vec::new()
+    });
+    let ban_list = BanList::load(risq_home.join(BANS_PATH)).expect("Couldn't load ban list");
+    let bootstrap_peers = known_peers
+        .iter()
+        .filter_map(|known| known.peer.as_ref()?.node_address.clone())
+        .filter(|addr| !ban_list.is_banned(addr))
+        .collect();
+    let mailbox = Mailbox::new(key_ring.pub_key_ring().signature_pub_key_bytes);
+    let filters = FilterStore::new(network).with_delayed_payout_policy(delayed_payout_policy);
+    let alerts = Alerts::new(network, halt_offers_on_alert);
 
-    // Domain Thread
-    let price_feed = PriceFeed::start(tor_proxy_port);
-    let offer_book = OfferBook::start(price_feed);
+    let evictions = Evictions::default();
+    let events = Events::default();
+    if let Some(port) = zmq_port {
+        if let Err(e) = api::publish_zmq(port, &events) {
+            error!("Couldn't publish events on zmq port {}: {}", port, e);
+        }
+    }
+    if let Some(config) = mqtt {
+        api::publish_mqtt(config, &events);
+    }
+    if let Some(config) = postgres {
+        api::publish_postgres(config, network, &events);
+    }
+    if let Some(config) = event_log {
+        api::publish_event_log(config, &events);
+    }
+    let offer_archive = if offer_archive {
+        let path = risq_home.join(OFFER_ARCHIVE_PATH);
+        match api::OfferArchive::open(&path) {
+            Ok(archive) => {
+                archive.follow(&events);
+                Some(archive)
+            }
+            Err(e) => {
+                error!("Couldn't open offer archive {}: {}", path.display(), e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let offer_feed = api::OfferFeed::follow(&events);
+    let offer_book = OfferBook::start(
+        price_feed.clone(),
+        memory_budget.max_offers,
+        evictions.clone(),
+        events.clone(),
+        filters.clone(),
+    );
+    if let Err(e) = notifier::start(
+        notifiers,
+        notification_rules,
+        &events,
+        price_feed.clone(),
+        offer_book.clone(),
+        tor_proxy_port,
+    ) {
+        error!("Couldn't start notifications: {}", e);
+    }
+    let alert_rules =
+        AlertRules::load(risq_home.join(ALERT_RULES_PATH)).expect("Couldn't load alert rules");
+    notifier::watch_alert_rules(alert_rules.clone(), &events, tor_proxy_port);
+    let stats_cache = StatsCache::new(memory_budget.max_trades, evictions.clone());
+    let signed_witnesses = SignedWitnesses::new(network);
+    let bootstrap_state = BootstrapState::init();
+    let p2p_status = Status::new(network, bootstrap_state.clone(), events.clone());
+    let handles = NetworkHandles {
+        offer_book: offer_book.clone(),
+        price_feed: price_feed.clone(),
+        stats_cache: stats_cache.clone(),
+        signed_witnesses: signed_witnesses.clone(),
+        events: events.clone(),
+        p2p_status: p2p_status.clone(),
+    };
 
     Arbiter::new().exec_fn(move || {
         // Daemon Thread
-        let stats_cache = StatsCache::new();
         let broadcaster = Broadcaster::start();
-        let data_router =
-            DataRouter::start(offer_book.clone(), broadcaster.clone(), stats_cache.clone());
+        let dispute_agents = DisputeAgents::default();
+        let arbitrators = Arbitrators::default();
+        let account_age_witnesses = AccountAgeWitnesses::default();
+        let dao_payloads = DaoPayloads::default();
+        let bsq_fees = BsqFees::default();
+        let data_router = DataRouter::start(
+            offer_book.clone(),
+            broadcaster.clone(),
+            stats_cache.clone(),
+            memory_budget.max_dedup_entries,
+            evictions.clone(),
+            clock_skew.clone(),
+            dispute_agents.clone(),
+            arbitrators.clone(),
+            account_age_witnesses.clone(),
+            signed_witnesses.clone(),
+            dao_payloads.clone(),
+            mailbox.clone(),
+            filters.clone(),
+            alerts.clone(),
+            events.clone(),
+            match delayed_payout_policy {
+                DelayedPayoutPolicy::Flag => offer_validators,
+                DelayedPayoutPolicy::Hide => offer_validators.add(DelayedPayout(filters.clone())),
+            }
+            .add(BsqMakerFee(bsq_fees.clone())),
+            seed_mode,
+            data_store,
+            stored_data,
+        );
 
         Arbiter::new().exec_fn(move || {
             // P2P Thread
-            let dispatcher = ActorDispatcher::<DataRouter, DataRouterDispatch>::new(data_router);
-            let bootstrap_state = BootstrapState::init();
+            crash::register_status(network, p2p_status.clone());
+            // Shared so peers keep their tor circuit whichever actor connects
+            let transport = if clearnet_address.is_some() {
+                p2p::transport::dual_stack(tor_proxy_port)
+            } else {
+                p2p::transport::for_proxy(tor_proxy_port)
+            };
 
-            let p2p_status = Status::new(bootstrap_state.clone());
+            let account_manager = AccountManager::start(
+                key_ring.clone(),
+                payment_accounts.clone(),
+                account_age_witnesses.clone(),
+                account_store,
+            );
+            let direct_messages = DirectMessages::start(
+                network,
+                key_ring.clone(),
+                p2p_status.clone(),
+                transport.clone(),
+                broadcaster.clone(),
+                data_router.clone().recipient(),
+                mailbox,
+            );
+            let trade_manager = TradeManager::start(
+                network,
+                key_ring,
+                previous_identity,
+                offer_book.clone(),
+                wallet.map(|config| {
+                    wallet::open(config, network, tor_proxy_port, fee_rates.clone())
+                        .expect("Couldn't open wallet")
+                }),
+                p2p_status.clone(),
+                direct_messages.clone(),
+                trade_store,
+                dispute_agents.clone(),
+                payment_accounts,
+                fee_rates,
+                broadcaster.clone(),
+                data_router.clone().recipient(),
+                offer_store,
+                signed_witnesses.clone(),
+                alerts.clone(),
+                events,
+            );
+            let dao_manager = DaoManager::start(
+                network,
+                dao_genesis,
+                dao_payloads.clone(),
+                bsq_fees.clone(),
+                block_store,
+                p2p_status.clone(),
+                transport.clone(),
+                force_seed.clone(),
+            );
+            let dispatcher = dispatch::chain(
+                ActorDispatcher::<DataRouter, DataRouterDispatch>::new(data_router.clone()),
+            )
+            .forward_to(ActorDispatcher::<
+                DirectMessages,
+                PrefixedSealedAndSignedMessage,
+            >::new(direct_messages))
+            .forward_to(
+                ActorDispatcher::<DaoManager, NewBlockBroadcastMessage>::new(dao_manager.clone()),
+            );
             let peers = Peers::start(
                 network,
                 broadcaster,
                 p2p_status.clone(),
                 dispatcher.clone(),
-                tor_proxy_port,
+                transport.clone(),
+                max_connections.unwrap_or(if seed_mode {
+                    SEED_MODE_MAX_CONNECTIONS
+                } else {
+                    DEFAULT_MAX_CONNECTIONS
+                }),
+                known_peers,
+                Some(peer_store),
+                filters.clone(),
+                ban_list.clone(),
+                Some(data_router.clone().recipient()),
             );
+            data_router.do_send(ReportMisbehavior(peers.clone().recipient()));
+            reload::subscribe(peers.clone().recipient());
             let bootstrap = Bootstrap::start(
                 network,
                 bootstrap_state,
                 peers.clone(),
                 dispatcher,
-                tor_proxy_port,
+                transport,
                 force_seed,
+                bootstrap_peers,
+                p2p_status.clone(),
+                known_keys,
+            );
+            let server = server::start(
+                server_port,
+                peers.clone(),
+                Some(bootstrap),
+                tor_config,
+                clearnet_address,
+                p2p_status.clone(),
             );
-            server::start(server_port, peers, Some(bootstrap), tor_config);
+            // Stop listening before the connections are closed and the
+            // network data is saved
+            shutdown::register(server.recipient());
+            shutdown::register(peers.recipient());
+            shutdown::register(data_router.recipient());
 
             // Api Thread
-            let _ = api::listen(api_port, offer_book, p2p_status, stats_cache);
+            if let Some(port) = grpc_port {
+                if let Err(e) =
+                    api::serve_grpc(port, offer_book.clone().recipient(), p2p_status.clone())
+                {
+                    error!("Couldn't serve gRPC on port {}: {}", port, e);
+                }
+            }
+            let auth = api::ApiAuth::new(
+                api_token,
+                api_cors_origins,
+                &risq_home.join(API_COOKIE_PATH),
+            )
+            .expect("Couldn't write api cookie");
+            let reloaded_auth = auth.clone();
+            reload::on_update(move |update| {
+                if let Some(token) = update.api_token.as_ref() {
+                    reloaded_auth.set_token(token.clone());
+                }
+            });
+            let _ = api::listen(
+                &api_bind,
+                api_port,
+                network,
+                offer_book,
+                p2p_status,
+                stats_cache,
+                evictions,
+                clock_skew,
+                signed_witnesses,
+                account_age_witnesses,
+                filters,
+                ban_list,
+                auth,
+                alerts,
+                alert_rules,
+                dispute_agents,
+                arbitrators,
+                dao_payloads,
+                bsq_fees,
+                offer_feed,
+                offer_archive,
+                trade_manager.clone().recipient(),
+                trade_manager.clone().recipient(),
+                trade_manager.clone().recipient(),
+                trade_manager.clone().recipient(),
+                trade_manager.clone().recipient(),
+                trade_manager.clone().recipient(),
+                trade_manager.clone().recipient(),
+                trade_manager.clone().recipient(),
+                trade_manager.clone().recipient(),
+                trade_manager.clone().recipient(),
+                trade_manager.recipient(),
+                price_feed.recipient(),
+                account_manager.clone().recipient(),
+                account_manager.recipient(),
+                dao_manager.clone().recipient(),
+                dao_manager.clone().recipient(),
+                dao_manager.clone().recipient(),
+                dao_manager.recipient(),
+            );
         });
     });
-
-    let _ = sys.run();
+    handles
 }
@@ -0,0 +1,95 @@
+mod store;
+
+pub use store::AccountStore;
+
+use crate::{
+    bisq::crypto::KeyRing,
+    domain::payment_account::{message::*, AccountAgeWitnesses, PaymentAccount, PaymentAccounts},
+    prelude::*,
+};
+use std::sync::Arc;
+
+/// Creates and persists the payment accounts of this node. The accounts
+/// are shared with the `TradeManager` that sends their payloads to peers.
+pub struct AccountManager {
+    key_ring: Arc<KeyRing>,
+    accounts: PaymentAccounts,
+    witnesses: AccountAgeWitnesses,
+    store: AccountStore,
+}
+impl Actor for AccountManager {
+    type Context = Context<Self>;
+}
+
+impl AccountManager {
+    pub fn start(
+        key_ring: Arc<KeyRing>,
+        accounts: PaymentAccounts,
+        witnesses: AccountAgeWitnesses,
+        store: AccountStore,
+    ) -> Addr<Self> {
+        Self {
+            key_ring,
+            accounts,
+            witnesses,
+            store,
+        }
+        .start()
+    }
+
+    fn info(&self, account: PaymentAccount) -> PaymentAccountInfo {
+        let pub_key_ring = self.key_ring.pub_key_ring();
+        PaymentAccountInfo {
+            age_witness_hash: account.age_witness_hash(&pub_key_ring),
+            age_witness_date: account.age_witness_date(&pub_key_ring, &self.witnesses),
+            account,
+        }
+    }
+}
+
+impl Handler<CreatePaymentAccount> for AccountManager {
+    type Result = Result<PaymentAccountInfo, String>;
+    fn handle(
+        &mut self,
+        CreatePaymentAccount {
+            name,
+            currency,
+            details,
+        }: CreatePaymentAccount,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let account = PaymentAccount::new(name, currency, details)?;
+        let mut accounts = self.accounts.all();
+        if accounts
+            .iter()
+            .any(|existing| existing.name == account.name)
+        {
+            return Err(format!("Account {} already exists", account.name));
+        }
+        accounts.push(account.clone());
+        self.store
+            .save(accounts.iter())
+            .map_err(|e| format!("Couldn't persist payment accounts: {}", e))?;
+        info!(
+            "Created {} account {}",
+            account.payment_method(),
+            account.name
+        );
+        self.accounts.add(account.clone());
+        Ok(self.info(account))
+    }
+}
+
+impl Handler<GetPaymentAccounts> for AccountManager {
+    type Result = MessageResult<GetPaymentAccounts>;
+    fn handle(&mut self, _: GetPaymentAccounts, _: &mut Self::Context) -> Self::Result {
+        let mut accounts = self.accounts.all();
+        accounts.sort_by_key(|account| account.created_at);
+        MessageResult(
+            accounts
+                .into_iter()
+                .map(|account| self.info(account))
+                .collect(),
+        )
+    }
+}
@@ -0,0 +1,172 @@
+use crate::{
+    bisq::payload::*,
+    domain::{
+        currency::Currency,
+        payment_account::{PaymentAccount, PaymentAccountDetails},
+    },
+};
+use prost::Message;
+use std::{
+    fs, io,
+    path::PathBuf,
+    time::{Duration, UNIX_EPOCH},
+};
+
+#[derive(Clone, PartialEq, Message)]
+struct StoredPaymentAccounts {
+    #[prost(message, repeated, tag = "1")]
+    accounts: Vec<StoredPaymentAccount>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct StoredPaymentAccount {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(string, tag = "2")]
+    currency_code: String,
+    #[prost(message, optional, tag = "3")]
+    payload: Option<PaymentAccountPayload>,
+    #[prost(bytes, tag = "4")]
+    salt: Vec<u8>,
+    #[prost(uint64, tag = "5")]
+    created_at: u64,
+}
+
+/// Keeps the payment accounts in a single protobuf file, the details are
+/// stored as the payload that is sent to trading peers.
+pub struct AccountStore {
+    path: PathBuf,
+}
+impl AccountStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn load(&self) -> io::Result<Vec<PaymentAccount>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let stored = StoredPaymentAccounts::decode(&fs::read(&self.path)?[..])
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        stored
+            .accounts
+            .into_iter()
+            .map(|account| {
+                restore(account).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            })
+            .collect()
+    }
+
+    pub fn save<'a>(&self, accounts: impl Iterator<Item = &'a PaymentAccount>) -> io::Result<()> {
+        let stored = StoredPaymentAccounts {
+            accounts: accounts.map(store).collect(),
+        };
+        let mut serialized = Vec::with_capacity(stored.encoded_len());
+        stored
+            .encode(&mut serialized)
+            .expect("Could not encode payment accounts");
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, serialized)?;
+        fs::rename(tmp_path, &self.path)
+    }
+}
+
+fn store(account: &PaymentAccount) -> StoredPaymentAccount {
+    StoredPaymentAccount {
+        name: account.name.clone(),
+        currency_code: account.currency.code.clone(),
+        payload: Some(account.payload()),
+        salt: account.salt.clone(),
+        created_at: account
+            .created_at
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_millis() as u64,
+    }
+}
+
+fn restore(stored: StoredPaymentAccount) -> Result<PaymentAccount, String> {
+    let currency = Currency::from_code(&stored.currency_code)
+        .ok_or_else(|| format!("Unknown currency {}", stored.currency_code))?;
+    let payload = stored.payload.ok_or("Payment account without payload")?;
+    let details = match payload.message.ok_or("Payment account without details")? {
+        payment_account_payload::Message::CountryBasedPaymentAccountPayload(
+            CountryBasedPaymentAccountPayload {
+                country_code,
+                message,
+            },
+        ) => match message.ok_or("Payment account without details")? {
+            country_based_payment_account_payload::Message::SepaAccountPayload(sepa) => {
+                PaymentAccountDetails::Sepa {
+                    holder_name: sepa.holder_name,
+                    iban: sepa.iban,
+                    bic: sepa.bic,
+                }
+            }
+            country_based_payment_account_payload::Message::F2fAccountPayload(f2f) => {
+                PaymentAccountDetails::F2f {
+                    country_code,
+                    contact: f2f.contact,
+                    city: f2f.city,
+                    extra_info: f2f.extra_info,
+                }
+            }
+            _ => return Err("Unsupported payment account".into()),
+        },
+        payment_account_payload::Message::RevolutAccountPayload(revolut) => {
+            PaymentAccountDetails::Revolut {
+                account_id: revolut.account_id,
+            }
+        }
+        payment_account_payload::Message::CryptoCurrencyAccountPayload(crypto) => {
+            PaymentAccountDetails::Crypto {
+                address: crypto.address,
+            }
+        }
+        _ => return Err("Unsupported payment account".into()),
+    };
+    Ok(PaymentAccount {
+        id: payload.id,
+        name: stored.name,
+        currency,
+        details,
+        salt: stored.salt,
+        created_at: UNIX_EPOCH + Duration::from_millis(stored.created_at),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    #[test]
+    fn save_and_load() {
+        let account = PaymentAccount::new(
+            "cash".into(),
+            Currency::from_code("USD").unwrap(),
+            PaymentAccountDetails::F2f {
+                country_code: "US".into(),
+                contact: "satoshi@example.com".into(),
+                city: "Austin".into(),
+                extra_info: String::new(),
+            },
+        )
+        .unwrap();
+
+        let path = env::temp_dir().join(format!("risq-accounts-{}", process::id()));
+        let store = AccountStore::new(path.clone());
+        store.save(vec![&account].into_iter()).unwrap();
+        let loaded = store.load().unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(loaded.len(), 1);
+        let loaded = &loaded[0];
+        assert_eq!(loaded.id, account.id);
+        assert_eq!(loaded.name, account.name);
+        assert_eq!(loaded.currency, account.currency);
+        assert_eq!(loaded.details, account.details);
+        assert_eq!(loaded.salt, account.salt);
+        assert_eq!(loaded.payload(), account.payload());
+    }
+}
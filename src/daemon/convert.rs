@@ -1,11 +1,15 @@
 use crate::{
     bisq::{
-        payload::{offer_payload, storage_payload, ProtectedStorageEntry, RefreshOfferMessage},
+        payload::{
+            offer_payload, persistable_network_payload, storage_payload, NodeAddress,
+            PersistableNetworkPayload, ProtectedStorageEntry, RefreshOfferMessage,
+        },
         SequencedMessageHash,
     },
     domain::{
-        amount::NumberWithPrecision,
+        amount::{BtcAmount, NumberWithPrecision},
         currency::Currency,
+        dispute::{Arbitrator, DisputeAgent, DisputeAgentKind},
         market::Market,
         offer::{message::*, *},
     },
@@ -13,9 +17,14 @@ use crate::{
 };
 use std::{
     convert::TryFrom,
-    time::{Duration, SystemTime},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+// Keys of `TradeStatistics2.extra_data` in bisq
+const MEDIATOR_ADDRESS_KEY: &str = "medAddr";
+const REFUND_AGENT_ADDRESS_KEY: &str = "refAddr";
+
 impl TryFrom<offer_payload::Direction> for OfferDirection {
     type Error = ();
     fn try_from(direction: offer_payload::Direction) -> Result<OfferDirection, Self::Error> {
@@ -73,32 +82,111 @@ pub fn open_offer(entry: ProtectedStorageEntry, hash: SequencedMessageHash) -> O
             ))
         };
         let market = Market::from_currency_pair(base, counter)?;
+        let amount = match (
+            u64::try_from(payload.amount),
+            u64::try_from(payload.min_amount),
+        ) {
+            (Ok(total), Ok(min)) => OfferAmount {
+                total: BtcAmount::from_sats(total),
+                min: BtcAmount::from_sats(min),
+            },
+            _ => {
+                warn!("Negative amount in offer '{}'", payload.id);
+                return None;
+            }
+        };
+        let original = Arc::new(payload.clone());
         Some(OpenOffer::new(
             hash,
             market,
             payload.id.into(),
             direction,
             price,
-            OfferAmount {
-                total: NumberWithPrecision::new(
-                    payload.amount as u64,
-                    base.bisq_internal_precision(),
-                ),
-                min: NumberWithPrecision::new(
-                    payload.min_amount as u64,
-                    base.bisq_internal_precision(),
-                ),
-            },
-            payload.payment_method_id,
+            amount,
+            payload.payment_method_id.as_str().into(),
             payload.offer_fee_payment_tx_id,
             created_at,
             entry.sequence_number.into(),
+            original,
         ))
     } else {
         None
     }
 }
 
+pub fn dispute_agent(entry: &ProtectedStorageEntry) -> Option<(DisputeAgentKind, DisputeAgent)> {
+    // Mediators and refund agents share their fields in bisq's `DisputeAgent`
+    macro_rules! agent {
+        ($kind:expr, $agent:expr) => {
+            (
+                $kind,
+                DisputeAgent {
+                    address: $agent.node_address.clone()?,
+                    pub_key_ring: $agent.pub_key_ring.clone()?,
+                    language_codes: $agent.language_codes.clone(),
+                    registration_date: UNIX_EPOCH
+                        + Duration::from_millis($agent.registration_date.max(0) as u64),
+                    email_address: $agent.email_address.clone(),
+                    info: $agent.info.clone(),
+                    registration_pub_key: $agent.registration_pub_key.clone(),
+                    registration_signature: $agent.registration_signature.clone(),
+                },
+            )
+        };
+    }
+    match entry.storage_payload.as_ref()?.message.as_ref()? {
+        storage_payload::Message::Mediator(mediator) => {
+            Some(agent!(DisputeAgentKind::Mediator, mediator))
+        }
+        storage_payload::Message::RefundAgent(agent) => {
+            Some(agent!(DisputeAgentKind::RefundAgent, agent))
+        }
+        _ => None,
+    }
+}
+
+pub fn arbitrator(entry: &ProtectedStorageEntry) -> Option<Arbitrator> {
+    match entry.storage_payload.as_ref()?.message.as_ref()? {
+        storage_payload::Message::Arbitrator(arbitrator) => Some(Arbitrator {
+            address: arbitrator.node_address.clone()?,
+            pub_key_ring: arbitrator.pub_key_ring.clone()?,
+            language_codes: arbitrator.language_codes.clone(),
+            registration_date: UNIX_EPOCH
+                + Duration::from_millis(arbitrator.registration_date.max(0) as u64),
+            btc_address: arbitrator.btc_address.clone(),
+            email_address: arbitrator.email_address.clone(),
+            info: arbitrator.info.clone(),
+            registration_pub_key: arbitrator.registration_pub_key.clone(),
+            registration_signature: arbitrator.registration_signature.clone(),
+        }),
+        _ => None,
+    }
+}
+
+/// The dispute agents a trade was assigned to, as bisq notes them in the
+/// extra data of its trade statistic.
+pub fn dispute_agent_assignments(
+    payload: &PersistableNetworkPayload,
+) -> Vec<(DisputeAgentKind, SystemTime, NodeAddress)> {
+    let stats = match payload.message {
+        Some(persistable_network_payload::Message::TradeStatistics2(ref stats)) => stats,
+        _ => return Vec::new(),
+    };
+    let date = UNIX_EPOCH + Duration::from_millis(stats.trade_date.max(0) as u64);
+    stats
+        .extra_data
+        .iter()
+        .filter_map(|entry| {
+            let kind = match entry.key() {
+                MEDIATOR_ADDRESS_KEY => DisputeAgentKind::Mediator,
+                REFUND_AGENT_ADDRESS_KEY => DisputeAgentKind::RefundAgent,
+                _ => return None,
+            };
+            Some((kind, date, entry.value().parse().ok()?))
+        })
+        .collect()
+}
+
 #[cfg(feature = "statistics")]
 pub use statistics::*;
 #[cfg(feature = "statistics")]
@@ -116,7 +204,7 @@ mod statistics {
     };
 
     pub fn trade_statistics2(payload: PersistableNetworkPayload) -> Option<statistics::Trade> {
-        let hash = payload.bisq_hash();
+        let hash = payload.bisq_hash().ok()?;
         if let persistable_network_payload::Message::TradeStatistics2(payload) = payload.message? {
             if payload.trade_price <= 0 || payload.trade_amount <= 0 {
                 return None;
@@ -0,0 +1,274 @@
+mod store;
+
+pub use store::BlockStore;
+
+use crate::{
+    bisq::{
+        constants::{seed_nodes, BaseCurrencyNetwork, LOCAL_CAPABILITIES},
+        payload::*,
+    },
+    domain::dao::{
+        bsq::BsqFees,
+        message::*,
+        voting::{self, CycleResult, CycleSchedule, DaoPayloads},
+        BsqBalance, BsqSupply, DaoState, Genesis,
+    },
+    p2p::{dispatch::*, Connection, Request, Status, Transport},
+    prelude::{fut::Either, *},
+    scheduler::{Schedule, Task},
+};
+use rand::{seq::SliceRandom, thread_rng};
+use std::{sync::Arc, time::Duration};
+
+const DAO_SYNC: Task = Task::new("dao_sync", Duration::from_secs(60));
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Syncs the BSQ blocks since the DAO genesis from the seed nodes like a
+/// bisq lite node: blocks are requested with `GetBlocksRequest`s until a
+/// response is empty, new blocks arrive as `NewBlockBroadcastMessage`s.
+pub struct DaoManager {
+    network: BaseCurrencyNetwork,
+    state: Option<DaoState>,
+    payloads: DaoPayloads,
+    bsq_fees: BsqFees,
+    store: BlockStore,
+    status: Status,
+    transport: Arc<dyn Transport>,
+    seeds: Vec<NodeAddress>,
+    connection: Option<Addr<Connection>>,
+    requesting: bool,
+    unsaved_blocks: bool,
+}
+impl Actor for DaoManager {
+    type Context = Context<Self>;
+    fn started(&mut self, ctx: &mut Self::Context) {
+        if self.state.is_none() {
+            return info!(
+                "No DAO genesis known for {:?}, not syncing blocks",
+                self.network
+            );
+        }
+        self.request_blocks(ctx);
+        ctx.schedule(DAO_SYNC, |manager, ctx| manager.request_blocks(ctx));
+    }
+}
+
+impl DaoManager {
+    pub fn start(
+        network: BaseCurrencyNetwork,
+        genesis: Option<Genesis>,
+        payloads: DaoPayloads,
+        bsq_fees: BsqFees,
+        store: BlockStore,
+        status: Status,
+        transport: Arc<dyn Transport>,
+        force_seed: Option<NodeAddress>,
+    ) -> Addr<Self> {
+        let state = genesis.map(|genesis| {
+            let blocks = store
+                .load(genesis.block_height)
+                .expect("Couldn't load DAO blocks");
+            let mut state = DaoState::new(genesis);
+            for block in blocks {
+                if let Err(e) = state.add_block(block) {
+                    warn!("Discarding stored DAO blocks: {}", e);
+                    break;
+                }
+            }
+            bsq_fees.update(state.fees().cloned(), state.height());
+            state
+        });
+        let mut seeds = match force_seed {
+            Some(seed) => vec![seed],
+            None => seed_nodes(network),
+        };
+        seeds.shuffle(&mut thread_rng());
+        Self {
+            network,
+            state,
+            payloads,
+            bsq_fees,
+            store,
+            status,
+            transport,
+            seeds,
+            connection: None,
+            requesting: false,
+            unsaved_blocks: false,
+        }
+        .start()
+    }
+
+    fn state(&self) -> Result<&DaoState, String> {
+        self.state
+            .as_ref()
+            .ok_or_else(|| format!("No DAO genesis known for {:?}", self.network))
+    }
+
+    fn request_blocks(&mut self, ctx: &mut Context<Self>) {
+        let from_block_height = match self.state.as_ref() {
+            Some(state) if !self.requesting => state.next_height(),
+            _ => return,
+        };
+        let seed = match self.seeds.first() {
+            Some(seed) => seed.clone(),
+            None => return,
+        };
+        let request = GetBlocksRequest {
+            from_block_height: from_block_height as i32,
+            nonce: gen_nonce(),
+            sender_node_address: self.status.local_addr(),
+            supported_capabilities: LOCAL_CAPABILITIES.clone(),
+        };
+        self.requesting = true;
+        let sending = self
+            .connection(seed.clone(), ctx)
+            .and_then(|conn, _, _| {
+                fut::wrap_future(
+                    conn.send(Request(request))
+                        .flatten()
+                        .timeout(REQUEST_TIMEOUT)
+                        .map_err(|_| ()),
+                )
+            })
+            .then(move |result, manager: &mut Self, ctx| {
+                manager.requesting = false;
+                match result {
+                    Ok(response) => manager.add_blocks(response.raw_blocks, ctx),
+                    Err(_) => {
+                        warn!("Seed {} didn't respond to GetBlocksRequest", seed);
+                        manager.connection = None;
+                        manager.seeds.rotate_left(1);
+                    }
+                }
+                fut::ok(())
+            });
+        ctx.spawn(sending);
+    }
+
+    fn connection(
+        &self,
+        seed: NodeAddress,
+        ctx: &mut Context<Self>,
+    ) -> impl ActorFuture<Item = Addr<Connection>, Error = (), Actor = Self> {
+        match self.connection.as_ref().filter(|conn| conn.connected()) {
+            Some(conn) => Either::A(fut::ok(conn.clone())),
+            None => Either::B(
+                fut::wrap_future(
+                    Connection::open(
+                        seed,
+                        self.network.into(),
+                        ActorDispatcher::<Self, NewBlockBroadcastMessage>::new(ctx.address()),
+                        &*self.transport,
+                    )
+                    .timeout(REQUEST_TIMEOUT)
+                    .map_err(|_| ()),
+                )
+                .map(|(_id, conn), manager: &mut Self, _| {
+                    manager.connection = Some(conn.clone());
+                    conn
+                }),
+            ),
+        }
+    }
+
+    // Keeps requesting until a response has no new blocks, then persists.
+    fn add_blocks(&mut self, blocks: Vec<BaseBlock>, ctx: &mut Context<Self>) {
+        let state = match self.state.as_mut() {
+            Some(state) => state,
+            None => return,
+        };
+        let mut added = false;
+        for block in blocks {
+            let at_next_height = block.height >= 0 && block.height as u32 == state.next_height();
+            if let Err(e) = state.add_block(block) {
+                warn!("Couldn't apply DAO block: {}", e);
+                // A block at the right height that doesn't connect means our
+                // tip was reorged away, it is requested again from the parent
+                if let (true, Some(tip)) = (at_next_height, state.height()) {
+                    state.rewind(tip);
+                    added = true;
+                }
+                break;
+            }
+            added = true;
+        }
+        if added {
+            self.unsaved_blocks = true;
+            return self.request_blocks(ctx);
+        }
+        if self.unsaved_blocks {
+            if let Err(e) = self
+                .store
+                .save(state.genesis().block_height, state.blocks())
+            {
+                return warn!("Couldn't persist DAO blocks: {}", e);
+            }
+            self.unsaved_blocks = false;
+            self.bsq_fees.update(state.fees().cloned(), state.height());
+            info!("DAO blocks synced up to {:?}", state.height());
+        }
+    }
+}
+
+impl Handler<Receive<NewBlockBroadcastMessage>> for DaoManager {
+    type Result = ();
+    fn handle(
+        &mut self,
+        Receive(_, msg): Receive<NewBlockBroadcastMessage>,
+        ctx: &mut Self::Context,
+    ) {
+        let (block, next_height) = match (msg.raw_block, self.state.as_ref()) {
+            (Some(block), Some(state)) => (block, state.next_height()),
+            _ => return,
+        };
+        if block.height < 0 || (block.height as u32) < next_height {
+            return;
+        }
+        if block.height as u32 > next_height {
+            return self.request_blocks(ctx);
+        }
+        self.add_blocks(vec![block], ctx);
+    }
+}
+
+impl Handler<GetDaoStatus> for DaoManager {
+    type Result = Result<DaoStatus, String>;
+    fn handle(&mut self, _: GetDaoStatus, _: &mut Self::Context) -> Self::Result {
+        let state = self.state()?;
+        Ok(DaoStatus {
+            genesis_tx_id: state.genesis().tx_id.clone(),
+            height: state.height(),
+            bsq_txs: state.txs().count(),
+            unspent_outputs: state.unspent().count(),
+            phase: state.height().and_then(|height| {
+                CycleSchedule::new(self.network, state.genesis().block_height).phase_at(height)
+            }),
+        })
+    }
+}
+
+impl Handler<GetBsqSupply> for DaoManager {
+    type Result = Result<BsqSupply, String>;
+    fn handle(&mut self, _: GetBsqSupply, _: &mut Self::Context) -> Self::Result {
+        Ok(self.state()?.supply())
+    }
+}
+
+impl Handler<GetBsqBalance> for DaoManager {
+    type Result = Result<BsqBalance, String>;
+    fn handle(
+        &mut self,
+        GetBsqBalance { address }: GetBsqBalance,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        Ok(self.state()?.balance(&address))
+    }
+}
+
+impl Handler<GetDaoCycles> for DaoManager {
+    type Result = Result<Vec<CycleResult>, String>;
+    fn handle(&mut self, _: GetDaoCycles, _: &mut Self::Context) -> Self::Result {
+        Ok(voting::tally(self.state()?, self.network, &self.payloads))
+    }
+}
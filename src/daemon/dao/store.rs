@@ -0,0 +1,49 @@
+use crate::bisq::payload::BaseBlock;
+use prost::Message;
+use std::{fs, io, path::PathBuf};
+
+#[derive(Clone, PartialEq, Message)]
+struct StoredBlocks {
+    #[prost(uint32, tag = "1")]
+    genesis_block_height: u32,
+    #[prost(message, repeated, tag = "2")]
+    blocks: Vec<BaseBlock>,
+}
+
+/// Keeps the raw DAO blocks so the BSQ state can be rebuilt after a
+/// restart without requesting all blocks since the genesis again.
+pub struct BlockStore {
+    path: PathBuf,
+}
+impl BlockStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Blocks that were synced from another genesis are discarded.
+    pub fn load(&self, genesis_block_height: u32) -> io::Result<Vec<BaseBlock>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let stored = StoredBlocks::decode(&fs::read(&self.path)?[..])
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if stored.genesis_block_height != genesis_block_height {
+            return Ok(Vec::new());
+        }
+        Ok(stored.blocks)
+    }
+
+    pub fn save(&self, genesis_block_height: u32, blocks: &[BaseBlock]) -> io::Result<()> {
+        let stored = StoredBlocks {
+            genesis_block_height,
+            blocks: blocks.to_vec(),
+        };
+        let mut serialized = Vec::with_capacity(stored.encoded_len());
+        stored
+            .encode(&mut serialized)
+            .expect("Could not encode DAO blocks");
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, serialized)?;
+        fs::rename(tmp_path, &self.path)
+    }
+}
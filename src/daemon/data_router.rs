@@ -1,39 +1,110 @@
-use super::convert;
+use super::{
+    convert,
+    data_store::{self, DataStore, Delta, StoredData},
+    sequenced_infos::{Claim, SequencedInfos, SequencedMessageInfo},
+    verifier::{self, IngestEntries, Ingested, Verifier},
+};
 use crate::{
     bisq::{
+        constants::LOCAL_CAPABILITIES,
+        crypto::sig,
         payload::{kind::*, *},
         PersistentMessageHash, SequencedMessageHash,
     },
     domain::{
-        offer::{message::*, OfferBook},
+        alert::Alerts,
+        budget::Evictions,
+        clock::{ClockSkew, TimeSource},
+        dao::voting::DaoPayloads,
+        dispute::{Arbitrators, DisputeAgents},
+        filter::FilterStore,
+        mailbox::Mailbox,
+        offer::{message::*, validation::OfferValidators, OfferBook},
+        payment_account::{AccountAgeWitnesses, SignedWitnesses},
         statistics::{StatsCache, Trade},
         CommandResult,
     },
-    p2p::{dispatch::Receive, message::Broadcast, Broadcaster, ConnectionId},
+    events::{Event, Events},
+    metrics,
+    p2p::{
+        dispatch::Receive,
+        message::{Broadcast, Direct},
+        Broadcaster, ConnectionId, GetKnownKeys, Misbehaved, Misbehavior, Queue, ReportMisbehavior,
+        DEFAULT_MAX_MESSAGE_SIZE, MAILBOX_CAPACITY,
+    },
     prelude::*,
+    scheduler::{Schedule, Task},
+    shutdown::Stop,
 };
+use prost::encoding::encoded_len_varint;
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{HashMap, HashSet, VecDeque},
     mem,
-    time::SystemTime,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+const SAVE_DATA: Task = Task::new("save_network_data", Duration::from_secs(10 * 60));
+const FLUSH_DELTAS: Task = Task::new("flush_network_data_deltas", Duration::from_secs(5));
+const EXPIRE_ENTRIES: Task = Task::new("storage_expiry", Duration::from_secs(60));
+/// How long the sequence number of a removed or expired entry is kept to
+/// refuse it being added again, as in bisq's `P2PDataStorage`.
+pub(super) const PURGE_AGE: Duration = Duration::from_secs(10 * 24 * 60 * 60);
+/// Data responses are split to stay below what bisq nodes accept, with room
+/// for the envelope.
+const MAX_RESPONSE_SIZE: usize = DEFAULT_MAX_MESSAGE_SIZE / 10 * 9;
+
 pub struct DataRouter {
     offer_book: Addr<OfferBook>,
+    // What was sent to the offer book until it was handled
+    offer_book_queue: Queue,
     broadcaster: Addr<Broadcaster>,
     #[cfg(feature = "statistics")]
     stats_cache: StatsCache,
-    sequenced_message_info: HashMap<SequencedMessageHash, SequencedMessageInfo>,
+    sequenced_message_info: SequencedInfos,
     persistent_message_info: HashSet<PersistentMessageHash>,
+    persistent_message_order: VecDeque<PersistentMessageHash>,
+    // With their size in a GetDataResponse, so requests are answered
+    // without encoding the payloads again
+    persistent_payloads: HashMap<PersistentMessageHash, (PersistableNetworkPayload, usize)>,
+    max_dedup_entries: Option<usize>,
+    evictions: Evictions,
+    clock_skew: ClockSkew,
+    dispute_agents: DisputeAgents,
+    arbitrators: Arbitrators,
+    account_age_witnesses: AccountAgeWitnesses,
+    signed_witnesses: SignedWitnesses,
+    dao_payloads: DaoPayloads,
+    mailbox: Mailbox,
+    filters: FilterStore,
+    alerts: Alerts,
+    events: Events,
+    offer_validators: OfferValidators,
+    seed_mode: bool,
+    store: DataStore,
+    // Set while the stored data is restored, which isn't logged again
+    restoring: bool,
+    verifier: Addr<Verifier>,
+    // Batches of entries whose signatures are still checked
+    pending_batches: usize,
+    save_when_verified: bool,
+    // Entries that didn't verify since the last report
+    invalid_payloads: usize,
+    // Payloads that couldn't be hashed since the last report
+    malformed_payloads: usize,
+    misbehavior: Option<Recipient<Misbehaved>>,
 }
 impl Actor for DataRouter {
     type Context = Context<Self>;
-}
-struct SequencedMessageInfo {
-    last_delivery: SystemTime,
-    sequence: i32,
-    owner_pub_key: Vec<u8>,
-    original_payload: StoragePayload,
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.set_mailbox_capacity(MAILBOX_CAPACITY);
+        ctx.schedule(SAVE_DATA, |router, _| router.save());
+        ctx.schedule(FLUSH_DELTAS, |router, _| {
+            if let Err(e) = router.store.flush() {
+                warn!("Couldn't persist network data changes: {}", e);
+            }
+        });
+        ctx.schedule(EXPIRE_ENTRIES, |router, _| router.expire_entries());
+    }
 }
 trait ResultHandler: FnOnce(Result<CommandResult, MailboxError>) -> Result<(), ()> {}
 impl<F> ResultHandler for F where F: FnOnce(Result<CommandResult, MailboxError>) -> Result<(), ()> {}
@@ -44,41 +115,342 @@ impl DataRouter {
         offer_book: Addr<OfferBook>,
         broadcaster: Addr<Broadcaster>,
         stats_cache: Option<StatsCache>,
+        max_dedup_entries: Option<usize>,
+        evictions: Evictions,
+        clock_skew: ClockSkew,
+        dispute_agents: DisputeAgents,
+        arbitrators: Arbitrators,
+        account_age_witnesses: AccountAgeWitnesses,
+        signed_witnesses: SignedWitnesses,
+        dao_payloads: DaoPayloads,
+        mailbox: Mailbox,
+        filters: FilterStore,
+        alerts: Alerts,
+        events: Events,
+        offer_validators: OfferValidators,
+        seed_mode: bool,
+        store: DataStore,
+        stored: StoredData,
     ) -> Addr<DataRouter> {
-        DataRouter {
+        let sequenced_message_info = SequencedInfos::new(max_dedup_entries, evictions.clone());
+        let mut router = DataRouter {
             offer_book,
+            offer_book_queue: Queue::named("OfferBook"),
             broadcaster,
             #[cfg(feature = "statistics")]
             stats_cache: stats_cache.expect("StatsCache missing"),
-            sequenced_message_info: HashMap::new(),
+            verifier: Verifier::start(sequenced_message_info.clone()),
+            sequenced_message_info,
             persistent_message_info: HashSet::new(),
+            persistent_message_order: VecDeque::new(),
+            persistent_payloads: HashMap::new(),
+            max_dedup_entries,
+            evictions,
+            clock_skew,
+            dispute_agents,
+            arbitrators,
+            account_age_witnesses,
+            signed_witnesses,
+            dao_payloads,
+            mailbox,
+            filters,
+            alerts,
+            events,
+            offer_validators,
+            seed_mode,
+            store,
+            restoring: false,
+            pending_batches: 0,
+            save_when_verified: false,
+            invalid_payloads: 0,
+            malformed_payloads: 0,
+            misbehavior: None,
+        };
+        // Entries that expired meanwhile are refused while routing
+        info!(
+            "Restoring {} entries and {} payloads from disk",
+            stored.entries.len(),
+            stored.payloads.len()
+        );
+        let StoredData {
+            entries,
+            payloads,
+            sequence_numbers,
+        } = stored;
+        router.restore_sequence_numbers(sequence_numbers.unwrap_or_default());
+        Actor::create(move |ctx| {
+            router.restoring = true;
+            router.restore_entries(entries);
+            router.route_bootstrap_data(Vec::new(), payloads, None, ctx);
+            router.restoring = false;
+            router
+        })
+    }
+    fn save(&mut self) {
+        let mut entries = Vec::new();
+        let mut sequence_number_entries = Vec::new();
+        self.sequenced_message_info.each_stripe(|stripe| {
+            for (hash, info) in stripe.iter() {
+                match info.entry.as_ref() {
+                    Some(entry) => entries.push(entry.clone()),
+                    None => sequence_number_entries.push(data_store::sequence_number_entry(
+                        *hash,
+                        info.sequence,
+                        info.last_delivery,
+                    )),
+                }
+            }
+        });
+        let stored = StoredData {
+            entries,
+            payloads: self
+                .persistent_payloads
+                .values()
+                .map(|(payload, _)| payload.clone())
+                .collect(),
+            sequence_numbers: Some(SequenceNumberMap {
+                sequence_number_entries,
+            }),
+        };
+        if let Err(e) = self.store.save(&stored) {
+            warn!("Couldn't persist network data: {}", e);
+        }
+    }
+    // The stored entries were verified when they were received, restoring
+    // them doesn't check their signatures again.
+    fn restore_entries(&mut self, entries: Vec<StorageEntryWrapper>) {
+        for wrapper in entries {
+            let hash = protected_entry(&wrapper)
+                .and_then(|entry| entry.storage_payload.as_ref())
+                .map(StoragePayload::bisq_hash);
+            let claimed =
+                hash.map(|hash| verifier::claim(&self.sequenced_message_info, hash, &wrapper));
+            if let Some(Ingested::Claimed(hash, sequence)) = claimed {
+                self.deliver_claimed(wrapper, hash, sequence);
+            }
+        }
+    }
+    fn record(&mut self, delta: Delta) {
+        if self.restoring {
+            return;
+        }
+        if let Err(e) = self.store.append(&delta) {
+            warn!("Couldn't persist network data change: {}", e);
+        }
+    }
+    fn restore_sequence_numbers(&mut self, sequence_numbers: SequenceNumberMap) {
+        for (hash, last_delivery, sequence) in restored_sequence_numbers(sequence_numbers) {
+            self.sequenced_message_info.stripe(&hash).insert(
+                hash,
+                SequencedMessageInfo {
+                    last_delivery,
+                    sequence,
+                    owner_pub_key: Vec::new(),
+                    original_payload: StoragePayload::default(),
+                    expires_at: None,
+                    entry: None,
+                },
+            );
+        }
+    }
+    // Removes entries whose TTL passed without a refresh from the domain,
+    // their sequence numbers are kept until they are purged.
+    fn expire_entries(&mut self) {
+        let now = SystemTime::now();
+        let mut expired = Vec::new();
+        self.sequenced_message_info.each_stripe(|stripe| {
+            stripe.retain(|hash, info| match info.expires_at {
+                Some(expires_at) if expires_at <= now => {
+                    info.expires_at = None;
+                    if let Some(entry) = info.entry.take() {
+                        expired.push((*hash, entry));
+                    }
+                    true
+                }
+                Some(_) => true,
+                None => info
+                    .last_delivery
+                    .elapsed()
+                    .map(|age| age < PURGE_AGE)
+                    .unwrap_or(true),
+            })
+        });
+        if !expired.is_empty() {
+            debug!("Expired {} storage entries", expired.len());
+        }
+        for (hash, entry) in expired {
+            if let Some(entry) = into_protected_entry(entry) {
+                self.remove_expired(hash, entry);
+            }
+        }
+    }
+    fn remove_expired(&mut self, hash: SequencedMessageHash, entry: ProtectedStorageEntry) {
+        match (&entry).into() {
+            StoragePayloadKind::Alert => {
+                if let Some(alert) = alert(&entry) {
+                    self.alerts.remove(alert);
+                }
+            }
+            StoragePayloadKind::Filter => {
+                if let Some(filter) = filter(&entry) {
+                    self.filters.remove(filter);
+                }
+            }
+            StoragePayloadKind::TempProposalPayload => {
+                if let Some(proposal) = temp_proposal(&entry) {
+                    self.dao_payloads.remove_temp_proposal(proposal)
+                }
+            }
+            StoragePayloadKind::Mediator | StoragePayloadKind::RefundAgent => {
+                if let Some((kind, agent)) = convert::dispute_agent(&entry) {
+                    self.dispute_agents.remove(kind, &agent.address)
+                }
+            }
+            StoragePayloadKind::Arbitrator => {
+                if let Some(arbitrator) = convert::arbitrator(&entry) {
+                    self.arbitrators.remove(&arbitrator.address)
+                }
+            }
+            StoragePayloadKind::OfferPayload if !self.seed_mode => {
+                if let Some(offer) = convert::open_offer(entry, hash) {
+                    arbiter_spawn!(self
+                        .offer_book_queue
+                        .track(self.offer_book.send(RemoveOffer(offer)))
+                        .then(Self::ignore_command_result()))
+                }
+            }
+            _ => (),
         }
-        .start()
     }
     fn ignore_command_result() -> impl ResultHandler {
         |_result| Ok(())
     }
-    fn handle_command_result<M>(&self, origin: ConnectionId, original: M) -> impl ResultHandler
+    fn handle_command_result<M>(
+        &self,
+        origin: Option<ConnectionId>,
+        original: M,
+    ) -> impl ResultHandler
     where
         M: Into<network_envelope::Message> + Send + Clone + 'static,
     {
         let broadcaster = self.broadcaster.clone();
+        let relayed = self.seed_mode;
         move |result| {
-            if let Ok(CommandResult::Accepted) = result {
-                arbiter_spawn!(broadcaster.send(Broadcast(original, Some(origin))));
+            if let (false, Ok(CommandResult::Accepted)) = (relayed, result) {
+                arbiter_spawn!(broadcaster.send(Broadcast(original, origin)));
             }
             Ok(())
         }
     }
 
+    // In seed mode everything that passes validation gets relayed right away
+    // instead of waiting for the domain to accept it.
+    fn relay_accepted<T, M>(&self, accepted: Option<T>, origin: Option<ConnectionId>, original: M)
+    where
+        M: Into<network_envelope::Message> + Send + Clone + 'static,
+    {
+        if self.seed_mode && accepted.is_some() {
+            arbiter_spawn!(self.broadcaster.send(Broadcast(original, origin)));
+        }
+    }
+    /// The entries and payloads we hold, without those whose keys are
+    /// excluded, each with its size in a `GetDataResponse`.
+    pub fn query(
+        &self,
+        excluded_keys: &HashSet<Vec<u8>>,
+    ) -> (
+        WithSizes<StorageEntryWrapper>,
+        WithSizes<PersistableNetworkPayload>,
+    ) {
+        let mut entries = Vec::new();
+        self.sequenced_message_info.each_stripe(|stripe| {
+            entries.extend(
+                stripe
+                    .iter()
+                    .filter(|(hash, _)| !excluded_keys.contains(&Vec::from(**hash)))
+                    .filter_map(|(_, info)| info.entry.clone().map(sized)),
+            )
+        });
+        (
+            entries,
+            self.persistent_payloads
+                .iter()
+                .filter(|(hash, _)| !excluded_keys.contains(&Vec::from(**hash)))
+                .map(|(_, payload)| payload.clone())
+                .collect(),
+        )
+    }
+    // What didn't fit into the first response follows in more responses with
+    // the same nonce. Bisq nodes only use the first one and get the rest
+    // with their next request.
+    fn get_data_responses(
+        &self,
+        request_nonce: i32,
+        is_get_updated_data_response: bool,
+        excluded_keys: Vec<Vec<u8>>,
+    ) -> Vec<GetDataResponse> {
+        let excluded_keys: HashSet<Vec<u8>> = excluded_keys.into_iter().collect();
+        let (entries, payloads) = self.query(&excluded_keys);
+        metrics::data_served(
+            entries.len() + payloads.len(),
+            entries.iter().map(|(_, len)| len).sum::<usize>()
+                + payloads.iter().map(|(_, len)| len).sum::<usize>(),
+        );
+        split_sized(
+            GetDataResponse {
+                request_nonce,
+                is_get_updated_data_response,
+                data_set: Vec::new(),
+                supported_capabilities: LOCAL_CAPABILITIES.clone(),
+                persistable_network_payload_items: Vec::new(),
+            },
+            entries,
+            payloads,
+            MAX_RESPONSE_SIZE,
+        )
+    }
+
+    // The signatures of the entries are checked and their sequence numbers
+    // claimed by the verifier, each batch is delivered to the domain once it
+    // comes back.
     fn route_bootstrap_data(
         &mut self,
         data: Vec<StorageEntryWrapper>,
         payloads: Vec<PersistableNetworkPayload>,
+        origin: Option<ConnectionId>,
+        ctx: &mut <Self as Actor>::Context,
     ) {
-        data.into_iter().for_each(|w| {
-            self.route_storage_entry_wrapper(Some(w), Self::ignore_command_result());
-        });
+        for batch in verifier::batches(data) {
+            self.pending_batches += 1;
+            ctx.spawn(
+                fut::wrap_future(self.verifier.send(IngestEntries(batch))).then(
+                    move |ingested, router: &mut Self, _| {
+                        router.pending_batches -= 1;
+                        match ingested {
+                            Ok(ingested) => {
+                                for (wrapper, ingested) in ingested {
+                                    match ingested {
+                                        Ingested::Invalid => router.invalid_payloads += 1,
+                                        Ingested::Stale => (),
+                                        Ingested::Claimed(hash, sequence) => {
+                                            router.record(Delta::entry(wrapper.clone()));
+                                            router.deliver_claimed(wrapper, hash, sequence)
+                                        }
+                                    }
+                                }
+                            }
+                            Err(e) => warn!("Couldn't verify entries: {:?}", e),
+                        }
+                        router.report_invalid_payloads(origin);
+                        if router.pending_batches == 0 && router.save_when_verified {
+                            router.save_when_verified = false;
+                            router.save();
+                        }
+                        fut::ok(())
+                    },
+                ),
+            );
+        }
         let mut trades = if cfg!(feature = "statistics") {
             Some(Vec::new())
         } else {
@@ -94,79 +466,207 @@ impl DataRouter {
         #[cfg(feature = "statistics")]
         arbiter_spawn!(self.stats_cache.bootstrap(trades.unwrap()));
     }
-    fn should_deliver_sequenced(
+    // Entries claimed on the verifier threads are delivered unless a newer
+    // one was routed meanwhile.
+    fn deliver_claimed(
         &mut self,
+        wrapper: StorageEntryWrapper,
         hash: SequencedMessageHash,
         sequence: i32,
-        owner_pub_key: Vec<u8>,
-        original_payload: &StoragePayload,
-    ) -> bool {
-        match self.sequenced_message_info.get_mut(&hash) {
-            Some(ref mut info) if sequence > info.sequence => {
-                info.sequence = sequence;
-                info.last_delivery = SystemTime::now();
-                true
-            }
-            None => {
-                self.sequenced_message_info.insert(
-                    hash,
-                    SequencedMessageInfo {
-                        sequence,
-                        last_delivery: SystemTime::now(),
-                        owner_pub_key,
-                        original_payload: original_payload.clone(),
-                    },
-                );
-                true
+    ) {
+        let current = self
+            .sequenced_message_info
+            .stripe(&hash)
+            .get(&hash)
+            .map(|info| info.sequence);
+        if current != Some(sequence) {
+            return;
+        }
+        match wrapper.message {
+            Some(storage_entry_wrapper::Message::ProtectedStorageEntry(entry)) => {
+                self.deliver_protected_entry(false, entry, hash, Self::ignore_command_result())
+            }
+            Some(storage_entry_wrapper::Message::ProtectedMailboxStorageEntry(entry)) => {
+                if let Some(inner) = entry.entry.clone() {
+                    self.deliver_protected_entry(false, inner, hash, Self::ignore_command_result());
+                }
+                self.mailbox.add(&entry);
             }
-            _ => false,
+            None => (),
         }
     }
+    fn remember_persistent_message(&mut self, hash: PersistentMessageHash) -> bool {
+        if !self.persistent_message_info.insert(hash) {
+            return false;
+        }
+        self.persistent_message_order.push_back(hash);
+        if let Some(max) = self.max_dedup_entries {
+            let mut evicted = 0;
+            while self.persistent_message_order.len() > max {
+                if let Some(oldest) = self.persistent_message_order.pop_front() {
+                    self.persistent_message_info.remove(&oldest);
+                    self.persistent_payloads.remove(&oldest);
+                    evicted += 1;
+                }
+            }
+            if evicted > 0 {
+                self.evictions.dedup_entries_evicted(evicted);
+            }
+        }
+        true
+    }
     fn route_storage_entry_wrapper(
         &mut self,
         entry_wrapper: Option<StorageEntryWrapper>,
         result_handler: impl ResultHandler + 'static,
-    ) -> Option<()> {
-        match entry_wrapper?.message? {
+    ) -> Option<SequencedMessageHash> {
+        let mut entry_wrapper = entry_wrapper?;
+        adjust_creation_time(&mut entry_wrapper);
+        let stored = entry_wrapper.clone();
+        let hash = match entry_wrapper.message? {
             storage_entry_wrapper::Message::ProtectedStorageEntry(entry) => {
-                self.route_protected_storage_entry(false, Some(entry), result_handler);
+                self.route_protected_storage_entry(false, Some(entry), result_handler)
             }
             storage_entry_wrapper::Message::ProtectedMailboxStorageEntry(entry) => {
-                self.route_protected_storage_entry(false, entry.entry, result_handler);
+                let hash =
+                    self.route_protected_storage_entry(false, entry.entry.clone(), result_handler)?;
+                self.mailbox.add(&entry);
+                Some(hash)
             }
+        }?;
+        if let Some(info) = self.sequenced_message_info.stripe(&hash).get_mut(&hash) {
+            info.entry = Some(stored.clone());
         }
-        Some(())
+        self.record(Delta::entry(stored));
+        Some(hash)
     }
     fn route_protected_storage_entry(
         &mut self,
         remove_data: bool,
         entry: Option<ProtectedStorageEntry>,
         result_handler: impl ResultHandler + 'static,
-    ) -> Option<()> {
+    ) -> Option<SequencedMessageHash> {
         let mut entry = entry?;
-        let bisq_hash = entry.verify()?;
-        if !self.should_deliver_sequenced(
-            bisq_hash,
-            entry.sequence_number,
-            mem::replace(&mut entry.owner_pub_key_bytes, Vec::new()),
-            entry.storage_payload.as_ref()?,
-        ) {
+        let bisq_hash = self.verified(entry.verify())?;
+        // Only the owner may remove an entry
+        if remove_data
+            && self
+                .sequenced_message_info
+                .stripe(&bisq_hash)
+                .get(&bisq_hash)
+                .map(|info| !sig::same_key(&info.owner_pub_key, &entry.owner_pub_key_bytes))
+                .unwrap_or(false)
+        {
             return None;
         }
-        #[allow(clippy::single_match)]
+        let expires_at = if remove_data {
+            None
+        } else {
+            Some(expires_at(&entry)?)
+        };
+        if !self.sequenced_message_info.claim(Claim {
+            hash: bisq_hash,
+            sequence: entry.sequence_number,
+            owner_pub_key: mem::take(&mut entry.owner_pub_key_bytes),
+            original_payload: entry.storage_payload.as_ref()?,
+            expires_at,
+        }) {
+            return None;
+        }
+        if remove_data {
+            if let Some(info) = self
+                .sequenced_message_info
+                .stripe(&bisq_hash)
+                .get_mut(&bisq_hash)
+            {
+                info.entry = None;
+            }
+            self.record(Delta::removed(
+                bisq_hash,
+                entry.sequence_number,
+                SystemTime::now(),
+            ));
+        }
+        self.deliver_protected_entry(remove_data, entry, bisq_hash, result_handler);
+        Some(bisq_hash)
+    }
+    // Hands an entry whose sequence number was claimed to the domain
+    fn deliver_protected_entry(
+        &mut self,
+        remove_data: bool,
+        entry: ProtectedStorageEntry,
+        bisq_hash: SequencedMessageHash,
+        result_handler: impl ResultHandler + 'static,
+    ) {
         match (&entry).into() {
-            StoragePayloadKind::OfferPayload => {
+            StoragePayloadKind::Alert => {
+                if let Some(alert) = alert(&entry) {
+                    if remove_data {
+                        self.alerts.remove(alert);
+                    } else if let Some(alert) = self.alerts.add(alert) {
+                        self.events.publish(Event::alert(&alert));
+                    } else {
+                        warn!("Alert {:?} isn't signed by a bisq developer", bisq_hash);
+                    }
+                }
+            }
+            StoragePayloadKind::Filter => {
+                if let Some(filter) = filter(&entry) {
+                    if remove_data {
+                        self.filters.remove(filter);
+                    } else if self.filters.apply(filter) {
+                        info!("Applying filter {:?}", bisq_hash);
+                        if !self.seed_mode {
+                            arbiter_spawn!(self.offer_book.send(ApplyFilter));
+                        }
+                    } else {
+                        warn!("Filter {:?} isn't signed by a bisq developer", bisq_hash);
+                    }
+                }
+            }
+            StoragePayloadKind::TempProposalPayload => {
+                if let Some(proposal) = temp_proposal(&entry) {
+                    if remove_data {
+                        self.dao_payloads.remove_temp_proposal(proposal)
+                    } else {
+                        self.dao_payloads.add_temp_proposal(proposal)
+                    }
+                }
+            }
+            StoragePayloadKind::Mediator | StoragePayloadKind::RefundAgent => {
+                if let Some((kind, agent)) = convert::dispute_agent(&entry) {
+                    if remove_data {
+                        self.dispute_agents.remove(kind, &agent.address)
+                    } else if !self.dispute_agents.add(kind, agent) {
+                        debug!("Ignoring {:?} with an invalid registration", kind);
+                    }
+                }
+            }
+            StoragePayloadKind::Arbitrator => {
+                if let Some(arbitrator) = convert::arbitrator(&entry) {
+                    if remove_data {
+                        self.arbitrators.remove(&arbitrator.address)
+                    } else if !self.arbitrators.add(arbitrator) {
+                        debug!("Ignoring Arbitrator with an invalid registration");
+                    }
+                }
+            }
+            // A seed only relays and serves offers, it doesn't keep a book
+            StoragePayloadKind::OfferPayload if !self.seed_mode => {
                 convert::open_offer(entry, bisq_hash)
                     .map(|offer| {
                         if remove_data {
                             arbiter_spawn!(self
-                                .offer_book
-                                .send(RemoveOffer(offer))
+                                .offer_book_queue
+                                .track(self.offer_book.send(RemoveOffer(offer)))
                                 .then(result_handler))
+                        } else if let Err(rejection) = self.offer_validators.validate(&offer) {
+                            debug!("Refusing offer {:?}, {}", offer.id, rejection);
+                            metrics::offer_rejected(rejection.rule);
                         } else {
                             arbiter_spawn!(self
-                                .offer_book
-                                .send(AddOffer(offer))
+                                .offer_book_queue
+                                .track(self.offer_book.send(AddOffer(offer)))
                                 .then(result_handler))
                         }
                     })
@@ -177,7 +677,75 @@ impl DataRouter {
             }
             _ => (),
         }
-        Some(())
+    }
+    // Mailbox messages are removed by their receiver rather than the sender
+    // that owns the entry, as in bisq's `P2PDataStorage.removeMailboxData`.
+    fn route_mailbox_removal(
+        &mut self,
+        removal: &ProtectedMailboxStorageEntry,
+    ) -> Option<SequencedMessageHash> {
+        let entry = removal.entry.as_ref()?;
+        if !sig::same_key(&entry.owner_pub_key_bytes, &removal.receivers_pub_key_bytes) {
+            return None;
+        }
+        let bisq_hash = self.verified(entry.verify())?;
+        let mut stripe = self.sequenced_message_info.stripe(&bisq_hash);
+        let info = stripe.get_mut(&bisq_hash)?;
+        let receiver = match info.entry.as_ref()?.message.as_ref()? {
+            storage_entry_wrapper::Message::ProtectedMailboxStorageEntry(stored) => {
+                &stored.receivers_pub_key_bytes
+            }
+            _ => return None,
+        };
+        if *receiver != removal.receivers_pub_key_bytes || entry.sequence_number <= info.sequence {
+            return None;
+        }
+        info.sequence = entry.sequence_number;
+        info.last_delivery = SystemTime::now();
+        info.expires_at = None;
+        info.entry = None;
+        drop(stripe);
+        self.record(Delta::removed(
+            bisq_hash,
+            entry.sequence_number,
+            SystemTime::now(),
+        ));
+        Some(bisq_hash)
+    }
+    fn verified<T>(&mut self, result: Option<T>) -> Option<T> {
+        if result.is_none() {
+            self.invalid_payloads += 1;
+        }
+        metrics::verified(result)
+    }
+    fn well_formed<T>(&mut self, result: Result<T, PayloadError>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(e) => {
+                debug!("Dropping malformed payload: {}", e);
+                metrics::malformed_payload();
+                self.malformed_payloads += 1;
+                None
+            }
+        }
+    }
+    // Peers have the connections that sent entries with bad signatures
+    // banned after a while, those that sent malformed payloads right away.
+    fn report_invalid_payloads(&mut self, origin: Option<ConnectionId>) {
+        let invalid = mem::replace(&mut self.invalid_payloads, 0);
+        let malformed = mem::replace(&mut self.malformed_payloads, 0);
+        if let (Some(origin), Some(misbehavior)) = (origin, self.misbehavior.as_ref()) {
+            if invalid > 0 {
+                let _ =
+                    misbehavior.do_send(Misbehaved(origin, Misbehavior::InvalidPayloads(invalid)));
+            }
+            if malformed > 0 {
+                let _ = misbehavior.do_send(Misbehaved(
+                    origin,
+                    Misbehavior::MalformedPayloads(malformed),
+                ));
+            }
+        }
     }
     #[allow(unused_variables)]
     fn route_persistable_network_payload(
@@ -185,18 +753,54 @@ impl DataRouter {
         payload: Option<PersistableNetworkPayload>,
         trades: Option<&mut Vec<Trade>>,
         result_handler: impl ResultHandler + 'static,
-    ) -> Option<()> {
+    ) -> Option<PersistentMessageHash> {
         let payload = payload?;
-        let bisq_hash = payload.bisq_hash();
-        if !self.persistent_message_info.insert(bisq_hash) {
+        let bisq_hash = self.well_formed(payload.bisq_hash())?;
+        if !self.remember_persistent_message(bisq_hash) {
             return None;
         }
+        self.persistent_payloads
+            .insert(bisq_hash, sized(payload.clone()));
+        self.record(Delta::payload(payload.clone()));
+        // Trade statistics name the agents the trade was assigned to
+        for (kind, date, address) in convert::dispute_agent_assignments(&payload) {
+            self.dispute_agents.record_trade(kind, date, address)
+        }
 
-        #[allow(clippy::single_match)]
         match PersistableNetworkPayloadKind::from(&payload) {
+            PersistableNetworkPayloadKind::AccountAgeWitness => {
+                if let Some(persistable_network_payload::Message::AccountAgeWitness(witness)) =
+                    payload.message
+                {
+                    self.account_age_witnesses.add(&witness)
+                }
+            }
+            PersistableNetworkPayloadKind::SignedWitness => {
+                if let Some(persistable_network_payload::Message::SignedWitness(witness)) =
+                    payload.message
+                {
+                    self.signed_witnesses.add(&witness)
+                }
+            }
+            PersistableNetworkPayloadKind::ProposalPayload => {
+                if let Some(persistable_network_payload::Message::ProposalPayload(proposal)) =
+                    payload.message
+                {
+                    self.dao_payloads.add_proposal(&proposal)
+                }
+            }
+            PersistableNetworkPayloadKind::BlindVotePayload => {
+                if let Some(persistable_network_payload::Message::BlindVotePayload(blind_vote)) =
+                    payload.message
+                {
+                    self.dao_payloads.add_blind_vote(&blind_vote)
+                }
+            }
             #[cfg(feature = "statistics")]
             PersistableNetworkPayloadKind::TradeStatistics2 => {
                 if let Some(trade) = convert::trade_statistics2(payload) {
+                    self.events
+                        .publish(crate::events::Event::trade_statistic(&trade));
                     if let Some(trades) = trades {
                         trades.push(trade)
                     } else {
@@ -206,7 +810,7 @@ impl DataRouter {
             }
             _ => (),
         }
-        Some(())
+        Some(bisq_hash)
     }
 }
 
@@ -215,7 +819,20 @@ pub enum DataRouterDispatch {
     RefreshOffer(RefreshOfferMessage),
     AddData(AddDataMessage),
     RemoveData(RemoveDataMessage),
+    RemoveMailboxData(RemoveMailboxDataMessage),
     AddPersistableNetworkPayload(AddPersistableNetworkPayloadMessage),
+    GetData {
+        nonce: i32,
+        is_get_updated_data_request: bool,
+        excluded_keys: Vec<Vec<u8>>,
+    },
+}
+
+/// Data we created ourselves, like our offers. It is routed like data from
+/// peers and broadcast to all of them once accepted.
+pub struct Publish(pub DataRouterDispatch);
+impl Message for Publish {
+    type Result = ();
 }
 
 impl Handler<Receive<DataRouterDispatch>> for DataRouter {
@@ -223,51 +840,174 @@ impl Handler<Receive<DataRouterDispatch>> for DataRouter {
     fn handle(
         &mut self,
         Receive(origin, dispatch): Receive<DataRouterDispatch>,
-        _ctx: &mut Self::Context,
+        ctx: &mut Self::Context,
+    ) {
+        self.dispatch(Some(origin), dispatch, ctx)
+    }
+}
+
+impl Handler<Publish> for DataRouter {
+    type Result = ();
+    fn handle(&mut self, Publish(dispatch): Publish, ctx: &mut Self::Context) {
+        self.dispatch(None, dispatch, ctx)
+    }
+}
+
+impl Handler<ReportMisbehavior> for DataRouter {
+    type Result = ();
+    fn handle(&mut self, ReportMisbehavior(to): ReportMisbehavior, _ctx: &mut Self::Context) {
+        self.misbehavior = Some(to);
+    }
+}
+
+impl Handler<GetKnownKeys> for DataRouter {
+    type Result = MessageResult<GetKnownKeys>;
+    fn handle(&mut self, _: GetKnownKeys, _ctx: &mut Self::Context) -> Self::Result {
+        let mut keys = Vec::new();
+        self.sequenced_message_info.each_stripe(|stripe| {
+            keys.extend(
+                stripe
+                    .iter()
+                    .filter(|(_, info)| info.entry.is_some())
+                    .map(|(hash, _)| Vec::from(*hash)),
+            )
+        });
+        keys.extend(self.persistent_payloads.keys().map(|hash| Vec::from(*hash)));
+        MessageResult(keys)
+    }
+}
+
+impl Handler<Stop> for DataRouter {
+    type Result = Result<(), ()>;
+    fn handle(&mut self, _: Stop, _ctx: &mut Self::Context) -> Self::Result {
+        self.save();
+        Ok(())
+    }
+}
+
+impl DataRouter {
+    fn dispatch(
+        &mut self,
+        origin: Option<ConnectionId>,
+        dispatch: DataRouterDispatch,
+        ctx: &mut <Self as Actor>::Context,
     ) {
         match dispatch {
             DataRouterDispatch::Bootstrap(data, persistable_network_payloads) => {
-                self.route_bootstrap_data(data, persistable_network_payloads)
+                self.route_bootstrap_data(data, persistable_network_payloads, origin, ctx);
+                // Most of the data arrives while bootstrapping
+                if self.pending_batches == 0 {
+                    self.save();
+                } else {
+                    self.save_when_verified = true;
+                }
             }
             DataRouterDispatch::RefreshOffer(msg) => {
-                let hash = msg.payload_hash();
-                if let Some(ref mut info) = self.sequenced_message_info.get_mut(&hash) {
-                    if info.sequence < msg.sequence_number
-                        && msg
-                            .verify(&*info.owner_pub_key, &info.original_payload)
-                            .is_some()
-                    {
-                        info.sequence = msg.sequence_number;
-                        info.last_delivery = SystemTime::now();
+                let hash = self.well_formed(msg.payload_hash());
+                let refreshed = hash.and_then(|hash| {
+                    let mut stripe = self.sequenced_message_info.stripe(&hash);
+                    match stripe.get_mut(&hash) {
+                        Some(info)
+                            if info.expires_at.is_some()
+                                && info.sequence < msg.sequence_number
+                                && msg
+                                    .verify(&info.owner_pub_key, &info.original_payload)
+                                    .is_some() =>
+                        {
+                            info.sequence = msg.sequence_number;
+                            info.last_delivery = SystemTime::now();
+                            info.expires_at =
+                                Some(info.last_delivery + StoragePayloadKind::OfferPayload.ttl());
+                            // The entry to log, if it's kept
+                            Some(info.entry.as_mut().map(|entry| {
+                                refresh_entry(entry, &msg);
+                                entry.clone()
+                            }))
+                        }
+                        _ => None,
+                    }
+                });
+                if let Some(refreshed) = refreshed {
+                    if let Some(entry) = refreshed {
+                        self.record(Delta::entry(entry));
+                    }
+                    self.relay_accepted(Some(()), origin, msg.clone());
+                    if !self.seed_mode {
                         Arbiter::spawn(
-                            self.offer_book
-                                .send(convert::refresh_offer(&msg))
+                            self.offer_book_queue
+                                .track(self.offer_book.send(convert::refresh_offer(&msg)))
                                 .then(self.handle_command_result(origin, msg)),
                         );
                     }
                 }
             }
             DataRouterDispatch::AddData(data) => {
-                self.route_storage_entry_wrapper(
+                let created_at = data
+                    .entry
+                    .as_ref()
+                    .and_then(protected_entry)
+                    .and_then(creation_time);
+                let accepted = self.route_storage_entry_wrapper(
                     data.entry.clone(),
-                    self.handle_command_result(origin, data),
+                    self.handle_command_result(origin, data.clone()),
                 );
+                // Only newly broadcast entries have a creation date close to now
+                if let (Some(_), Some(_), Some(created_at)) = (origin, accepted, created_at) {
+                    self.clock_skew.observe(TimeSource::Peer, created_at);
+                }
+                self.relay_accepted(accepted, origin, data);
             }
             DataRouterDispatch::RemoveData(data) => {
-                self.route_protected_storage_entry(
+                let accepted = self.route_protected_storage_entry(
                     true,
                     data.protected_storage_entry.clone(),
-                    self.handle_command_result(origin, data),
+                    self.handle_command_result(origin, data.clone()),
                 );
+                self.relay_accepted(accepted, origin, data);
+            }
+            DataRouterDispatch::RemoveMailboxData(data) => {
+                let accepted = data
+                    .protected_storage_entry
+                    .as_ref()
+                    .and_then(|removal| self.route_mailbox_removal(removal));
+                // There is no domain to accept it first
+                if accepted.is_some() {
+                    arbiter_spawn!(self.broadcaster.send(Broadcast(data, origin)));
+                }
             }
             DataRouterDispatch::AddPersistableNetworkPayload(msg) => {
-                self.route_persistable_network_payload(
-                    msg.payload.as_ref().map(Clone::clone),
+                let accepted = self.route_persistable_network_payload(
+                    msg.payload.clone(),
                     None,
-                    self.handle_command_result(origin, msg),
+                    self.handle_command_result(origin, msg.clone()),
                 );
+                self.relay_accepted(accepted, origin, msg);
+            }
+            DataRouterDispatch::GetData {
+                nonce,
+                is_get_updated_data_request,
+                excluded_keys,
+            } => {
+                if let Some(origin) = origin {
+                    let responses =
+                        self.get_data_responses(nonce, is_get_updated_data_request, excluded_keys);
+                    debug!(
+                        "Serving {} items in {} responses to {:?}",
+                        responses
+                            .iter()
+                            .map(|response| response.data_set.len()
+                                + response.persistable_network_payload_items.len())
+                            .sum::<usize>(),
+                        responses.len(),
+                        origin
+                    );
+                    for response in responses {
+                        arbiter_spawn!(self.broadcaster.send(Direct(response, origin)));
+                    }
+                }
             }
         }
+        self.report_invalid_payloads(origin);
     }
 }
 
@@ -289,13 +1029,321 @@ impl PayloadExtractor for DataRouterDispatch {
             network_envelope::Message::RemoveDataMessage(msg) => {
                 Extract::Succeeded(DataRouterDispatch::RemoveData(msg))
             }
+            network_envelope::Message::RemoveMailboxDataMessage(msg) => {
+                Extract::Succeeded(DataRouterDispatch::RemoveMailboxData(msg))
+            }
             network_envelope::Message::RefreshOfferMessage(msg) => {
                 Extract::Succeeded(DataRouterDispatch::RefreshOffer(msg))
             }
             network_envelope::Message::AddPersistableNetworkPayloadMessage(msg) => {
                 Extract::Succeeded(DataRouterDispatch::AddPersistableNetworkPayload(msg))
             }
+            network_envelope::Message::PreliminaryGetDataRequest(request) => {
+                Extract::Succeeded(DataRouterDispatch::GetData {
+                    nonce: request.nonce,
+                    is_get_updated_data_request: false,
+                    excluded_keys: request.excluded_keys,
+                })
+            }
+            network_envelope::Message::GetUpdatedDataRequest(request) => {
+                Extract::Succeeded(DataRouterDispatch::GetData {
+                    nonce: request.nonce,
+                    is_get_updated_data_request: true,
+                    excluded_keys: request.excluded_keys,
+                })
+            }
             _ => Extract::Failed(msg),
         }
     }
 }
+
+pub(super) fn protected_entry(wrapper: &StorageEntryWrapper) -> Option<&ProtectedStorageEntry> {
+    match wrapper.message.as_ref()? {
+        storage_entry_wrapper::Message::ProtectedStorageEntry(entry) => Some(entry),
+        storage_entry_wrapper::Message::ProtectedMailboxStorageEntry(mailbox_entry) => {
+            mailbox_entry.entry.as_ref()
+        }
+    }
+}
+
+fn protected_entry_mut(wrapper: &mut StorageEntryWrapper) -> Option<&mut ProtectedStorageEntry> {
+    match wrapper.message.as_mut()? {
+        storage_entry_wrapper::Message::ProtectedStorageEntry(entry) => Some(entry),
+        storage_entry_wrapper::Message::ProtectedMailboxStorageEntry(mailbox_entry) => {
+            mailbox_entry.entry.as_mut()
+        }
+    }
+}
+
+fn into_protected_entry(wrapper: StorageEntryWrapper) -> Option<ProtectedStorageEntry> {
+    match wrapper.message? {
+        storage_entry_wrapper::Message::ProtectedStorageEntry(entry) => Some(entry),
+        storage_entry_wrapper::Message::ProtectedMailboxStorageEntry(mailbox_entry) => {
+            mailbox_entry.entry
+        }
+    }
+}
+
+fn alert(entry: &ProtectedStorageEntry) -> Option<&Alert> {
+    match entry.storage_payload.as_ref()?.message.as_ref()? {
+        storage_payload::Message::Alert(alert) => Some(alert),
+        _ => None,
+    }
+}
+
+fn filter(entry: &ProtectedStorageEntry) -> Option<&Filter> {
+    match entry.storage_payload.as_ref()?.message.as_ref()? {
+        storage_payload::Message::Filter(filter) => Some(filter),
+        _ => None,
+    }
+}
+
+fn temp_proposal(entry: &ProtectedStorageEntry) -> Option<&TempProposalPayload> {
+    match entry.storage_payload.as_ref()?.message.as_ref()? {
+        storage_payload::Message::TempProposalPayload(proposal) => Some(proposal),
+        _ => None,
+    }
+}
+
+// None for entries without a creation time and those that expired already
+pub(super) fn expires_at(entry: &ProtectedStorageEntry) -> Option<SystemTime> {
+    let expires_at = creation_time(entry)?.checked_add(StoragePayloadKind::from(entry).ttl())?;
+    Some(expires_at).filter(|expires_at| *expires_at > SystemTime::now())
+}
+// Like java bisq's maybeAdjustCreationTimeStamp, entries created in the
+// future are stored as created now so they can't outlive their TTL.
+pub(super) fn adjust_creation_time(wrapper: &mut StorageEntryWrapper) {
+    if let Some(entry) = protected_entry_mut(wrapper) {
+        entry.creation_time_stamp = entry.creation_time_stamp.min(now_millis());
+    }
+}
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_millis() as i64
+}
+// Only the sequence number of entries that are gone is needed to refuse
+// them, purged entries aren't restored.
+fn restored_sequence_numbers(
+    sequence_numbers: SequenceNumberMap,
+) -> impl Iterator<Item = (SequencedMessageHash, SystemTime, i32)> {
+    sequence_numbers
+        .sequence_number_entries
+        .into_iter()
+        .filter_map(|entry| {
+            let hash = entry
+                .bytes
+                .and_then(|bytes| sha256::Hash::from_slice(&bytes.bytes).ok())
+                .map(SequencedMessageHash::new)?;
+            let value = entry.map_value.filter(|value| value.time_stamp >= 0)?;
+            let last_delivery = UNIX_EPOCH + Duration::from_millis(value.time_stamp as u64);
+            let purged = last_delivery
+                .elapsed()
+                .map(|age| age >= PURGE_AGE)
+                .unwrap_or(false);
+            Some((hash, last_delivery, value.sequence_nr)).filter(|_| !purged)
+        })
+}
+
+fn creation_time(entry: &ProtectedStorageEntry) -> Option<SystemTime> {
+    if entry.creation_time_stamp < 0 {
+        return None;
+    }
+    UNIX_EPOCH.checked_add(Duration::from_millis(entry.creation_time_stamp as u64))
+}
+
+// Fills `empty` with the entries and payloads, sized by `field_len`, and
+// more copies of it as each reaches `max_size`.
+fn split_sized(
+    empty: GetDataResponse,
+    entries: Vec<(StorageEntryWrapper, usize)>,
+    payloads: Vec<(PersistableNetworkPayload, usize)>,
+    max_size: usize,
+) -> Vec<GetDataResponse> {
+    let empty_size = prost::Message::encoded_len(&empty);
+    let mut responses = vec![empty.clone()];
+    let mut size = empty_size;
+    for (entry, len) in entries {
+        if size + len > max_size && size > empty_size {
+            responses.push(empty.clone());
+            size = empty_size;
+        }
+        size += len;
+        if let Some(last) = responses.last_mut() {
+            last.data_set.push(entry);
+        }
+    }
+    for (payload, len) in payloads {
+        if size + len > max_size && size > empty_size {
+            responses.push(empty.clone());
+            size = empty_size;
+        }
+        size += len;
+        if let Some(last) = responses.last_mut() {
+            last.persistable_network_payload_items.push(payload);
+        }
+    }
+    responses
+}
+
+/// Messages with their sizes in a `GetDataResponse`
+type WithSizes<M> = Vec<(M, usize)>;
+
+fn sized<M: prost::Message>(msg: M) -> (M, usize) {
+    let len = field_len(&msg);
+    (msg, len)
+}
+
+// Encoded as a repeated field with a tag of one byte
+fn field_len<M: prost::Message>(msg: &M) -> usize {
+    let len = msg.encoded_len();
+    1 + encoded_len_varint(len as u64) + len
+}
+
+fn refresh_entry(wrapper: &mut StorageEntryWrapper, msg: &RefreshOfferMessage) {
+    if let Some(entry) = protected_entry_mut(wrapper) {
+        entry.sequence_number = msg.sequence_number;
+        entry.signature = msg.signature.clone();
+        entry.creation_time_stamp = now_millis();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restores_the_sequence_numbers_of_recent_removals() {
+        let entry = |bytes: Vec<u8>, sequence_nr, time_stamp| SequenceNumberEntry {
+            bytes: Some(ByteArray { bytes }),
+            map_value: Some(MapValue {
+                sequence_nr,
+                time_stamp,
+            }),
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64;
+        let purged = now - PURGE_AGE.as_millis() as i64 - 60_000;
+        let restored: Vec<_> = restored_sequence_numbers(SequenceNumberMap {
+            sequence_number_entries: vec![
+                entry(vec![1; 32], 3, now),
+                entry(vec![2; 32], 4, purged),
+                entry(vec![3; 31], 5, now),
+                entry(vec![4; 32], 6, -1),
+            ],
+        })
+        .collect();
+
+        let hash = SequencedMessageHash::new(sha256::Hash::from_slice(&[1; 32]).unwrap());
+        let last_delivery = UNIX_EPOCH + Duration::from_millis(now as u64);
+        assert_eq!(restored, vec![(hash, last_delivery, 3)]);
+    }
+
+    #[test]
+    fn creation_time_of_entries() {
+        let mut entry = ProtectedStorageEntry {
+            creation_time_stamp: 1_500,
+            ..Default::default()
+        };
+        assert_eq!(
+            creation_time(&entry),
+            Some(UNIX_EPOCH + Duration::from_millis(1_500))
+        );
+        entry.creation_time_stamp = -1;
+        assert_eq!(creation_time(&entry), None);
+    }
+
+    #[test]
+    fn clamps_creation_times_from_the_future() {
+        let far_future = now_millis() + 100 * 365 * 24 * 60 * 60 * 1000;
+        let mut wrapper = StorageEntryWrapper {
+            message: Some(storage_entry_wrapper::Message::ProtectedStorageEntry(
+                ProtectedStorageEntry {
+                    creation_time_stamp: far_future,
+                    ..Default::default()
+                },
+            )),
+        };
+        let before = now_millis();
+        adjust_creation_time(&mut wrapper);
+        let entry = protected_entry(&wrapper).unwrap();
+        assert!(entry.creation_time_stamp >= before);
+        assert!(entry.creation_time_stamp <= now_millis());
+        let ttl = StoragePayloadKind::from(entry).ttl();
+        assert!(expires_at(entry).unwrap() <= SystemTime::now() + ttl);
+
+        let past = before - 1_000;
+        protected_entry_mut(&mut wrapper)
+            .unwrap()
+            .creation_time_stamp = past;
+        adjust_creation_time(&mut wrapper);
+        assert_eq!(protected_entry(&wrapper).unwrap().creation_time_stamp, past);
+    }
+
+    fn split_response(response: GetDataResponse, max_size: usize) -> Vec<GetDataResponse> {
+        let GetDataResponse {
+            request_nonce,
+            is_get_updated_data_response,
+            data_set,
+            supported_capabilities,
+            persistable_network_payload_items,
+        } = response;
+        split_sized(
+            GetDataResponse {
+                request_nonce,
+                is_get_updated_data_response,
+                data_set: Vec::new(),
+                supported_capabilities,
+                persistable_network_payload_items: Vec::new(),
+            },
+            data_set.into_iter().map(sized).collect(),
+            persistable_network_payload_items
+                .into_iter()
+                .map(sized)
+                .collect(),
+            max_size,
+        )
+    }
+
+    #[test]
+    fn splits_large_responses() {
+        let payload = PersistableNetworkPayload {
+            message: Some(persistable_network_payload::Message::AccountAgeWitness(
+                AccountAgeWitness {
+                    hash: vec![1; 20],
+                    date: 1_500_000_000_000,
+                },
+            )),
+        };
+        let response = GetDataResponse {
+            request_nonce: 42,
+            is_get_updated_data_response: true,
+            data_set: vec![StorageEntryWrapper::default(); 2],
+            supported_capabilities: LOCAL_CAPABILITIES.clone(),
+            persistable_network_payload_items: vec![payload; 100],
+        };
+        let max_size = prost::Message::encoded_len(&response) / 3;
+        let responses = split_response(response.clone(), max_size);
+        assert!(responses.len() >= 3);
+        for part in responses.iter() {
+            assert!(prost::Message::encoded_len(part) <= max_size);
+            assert_eq!(part.request_nonce, 42);
+            assert_eq!(part.supported_capabilities, response.supported_capabilities);
+        }
+        assert_eq!(
+            responses
+                .iter()
+                .map(|part| part.persistable_network_payload_items.len())
+                .sum::<usize>(),
+            100
+        );
+        assert_eq!(responses[0].data_set.len(), 2);
+        assert_eq!(
+            split_response(response.clone(), MAX_RESPONSE_SIZE),
+            vec![response]
+        );
+    }
+}
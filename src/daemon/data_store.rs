@@ -0,0 +1,511 @@
+use super::data_router::{expires_at, protected_entry, PURGE_AGE};
+use crate::bisq::{
+    payload::{
+        ByteArray, MapValue, PersistableNetworkPayload, SequenceNumberEntry, SequenceNumberMap,
+        StorageEntryWrapper,
+    },
+    SequencedMessageHash,
+};
+use prost::{encoding::decode_varint, Message};
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Cursor, Write},
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Clone, PartialEq, Message)]
+pub struct StoredData {
+    #[prost(message, repeated, tag = "1")]
+    pub entries: Vec<StorageEntryWrapper>,
+    #[prost(message, repeated, tag = "2")]
+    pub payloads: Vec<PersistableNetworkPayload>,
+    /// Of removed and expired entries, so they can't be replayed after a
+    /// restart, like bisq's `SequenceNumberMap`
+    #[prost(message, optional, tag = "3")]
+    pub sequence_numbers: Option<SequenceNumberMap>,
+}
+impl StoredData {
+    /// Keys of the persistable payloads for `excluded_keys` of data requests.
+    /// They never change so seed nodes don't need to send them again. Entries
+    /// are requested anyway as they could have been removed or refreshed
+    /// meanwhile.
+    pub fn payload_keys(&self) -> Vec<Vec<u8>> {
+        self.payloads
+            .iter()
+            .filter_map(|payload| payload.bisq_hash().ok())
+            .map(Vec::<u8>::from)
+            .collect()
+    }
+}
+
+/// A change of the data since the last snapshot, appended to the delta log
+/// as it happens.
+#[derive(Clone, PartialEq, Message)]
+pub struct Delta {
+    /// Added or refreshed
+    #[prost(message, optional, tag = "1")]
+    pub entry: Option<StorageEntryWrapper>,
+    #[prost(message, optional, tag = "2")]
+    pub payload: Option<PersistableNetworkPayload>,
+    /// The sequence number of a removed entry
+    #[prost(message, optional, tag = "3")]
+    pub removed: Option<SequenceNumberEntry>,
+}
+impl Delta {
+    pub fn entry(entry: StorageEntryWrapper) -> Self {
+        Self {
+            entry: Some(entry),
+            ..Self::default()
+        }
+    }
+    pub fn payload(payload: PersistableNetworkPayload) -> Self {
+        Self {
+            payload: Some(payload),
+            ..Self::default()
+        }
+    }
+    pub fn removed(hash: SequencedMessageHash, sequence: i32, at: SystemTime) -> Self {
+        Self {
+            removed: Some(sequence_number_entry(hash, sequence, at)),
+            ..Self::default()
+        }
+    }
+}
+
+pub fn sequence_number_entry(
+    hash: SequencedMessageHash,
+    sequence: i32,
+    last_delivery: SystemTime,
+) -> SequenceNumberEntry {
+    SequenceNumberEntry {
+        bytes: Some(ByteArray { bytes: hash.into() }),
+        map_value: Some(MapValue {
+            sequence_nr: sequence,
+            time_stamp: last_delivery
+                .duration_since(UNIX_EPOCH)
+                .map(|since| since.as_millis() as i64)
+                .unwrap_or_default(),
+        }),
+    }
+}
+
+/// What `risq compact` did.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Compaction {
+    pub replayed: usize,
+    pub entries: usize,
+    pub expired: usize,
+    pub purged: usize,
+    pub payloads: usize,
+}
+
+/// Keeps the data received from the network, offers and mailbox entries as
+/// well as trade statistics, witnesses, DAO payloads and the sequence numbers
+/// of entries that are gone, so a restart
+/// doesn't need a full sync from the seed nodes.
+///
+/// The data is written as a snapshot every few minutes, the changes in
+/// between are appended to a delta log next to it. Loading replays the
+/// log over the snapshot, writing the next snapshot truncates it.
+pub struct DataStore {
+    path: PathBuf,
+    delta_log: Option<BufWriter<File>>,
+}
+impl DataStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            delta_log: None,
+        }
+    }
+
+    fn delta_path(&self) -> PathBuf {
+        self.path.with_extension("delta")
+    }
+
+    pub fn load(&self) -> io::Result<StoredData> {
+        let (stored, replayed) = self.replayed()?;
+        if replayed > 0 {
+            info!("Replayed {} changes since the last snapshot", replayed);
+        }
+        Ok(stored)
+    }
+
+    fn replayed(&self) -> io::Result<(StoredData, usize)> {
+        if !self.path.exists() && !self.delta_path().exists() {
+            return Ok((StoredData::default(), 0));
+        }
+        let snapshot = if self.path.exists() {
+            StoredData::decode(&fs::read(&self.path)?[..])
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+        } else {
+            StoredData::default()
+        };
+        let deltas = self.deltas()?;
+        let replayed = deltas.len();
+        let mut replay = Replay::default();
+        replay.snapshot(snapshot);
+        deltas.into_iter().for_each(|delta| replay.apply(delta));
+        Ok((replay.into_stored(), replayed))
+    }
+
+    // A crash can leave a partly written change at the end, the log is read
+    // up to it.
+    fn deltas(&self) -> io::Result<Vec<Delta>> {
+        let path = self.delta_path();
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let log = fs::read(&path)?;
+        let mut cursor = Cursor::new(&log[..]);
+        let mut deltas = Vec::new();
+        while (cursor.position() as usize) < log.len() {
+            let len = match decode_varint(&mut cursor) {
+                Ok(len) => len as usize,
+                Err(_) => break,
+            };
+            let start = cursor.position() as usize;
+            match log
+                .get(start..start + len)
+                .and_then(|bytes| Delta::decode(bytes).ok())
+            {
+                Some(delta) => deltas.push(delta),
+                None => {
+                    warn!("Ignoring the truncated end of {}", path.display());
+                    break;
+                }
+            }
+            cursor.set_position((start + len) as u64);
+        }
+        Ok(deltas)
+    }
+
+    /// Appends a change to the delta log, it is written with the next
+    /// `flush`.
+    pub fn append(&mut self, delta: &Delta) -> io::Result<()> {
+        if self.delta_log.is_none() {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(self.delta_path())?;
+            self.delta_log = Some(BufWriter::new(file));
+        }
+        let mut serialized = Vec::with_capacity(delta.encoded_len() + 5);
+        delta
+            .encode_length_delimited(&mut serialized)
+            .expect("Could not encode network data change");
+        self.delta_log
+            .as_mut()
+            .expect("Opened above")
+            .write_all(&serialized)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        match self.delta_log.as_mut() {
+            Some(log) => log.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes a snapshot and truncates the delta log, whose changes it has.
+    pub fn save(&mut self, stored: &StoredData) -> io::Result<()> {
+        let mut serialized = Vec::with_capacity(stored.encoded_len());
+        stored
+            .encode(&mut serialized)
+            .expect("Could not encode network data");
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, serialized)?;
+        fs::rename(tmp_path, &self.path)?;
+        // Replaying changes the snapshot has already is harmless, a crash
+        // right here loses nothing
+        self.delta_log = None;
+        match fs::remove_file(self.delta_path()) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Rewrites the snapshot with the delta log replayed, without the
+    /// entries that expired, the sequence numbers old enough to be purged and
+    /// duplicate payloads. Only for the data of a stopped daemon.
+    pub fn compact(&mut self) -> io::Result<Compaction> {
+        let (stored, replayed) = self.replayed()?;
+        let now = SystemTime::now();
+        let mut compaction = Compaction {
+            replayed,
+            ..Compaction::default()
+        };
+        let mut entries = Vec::new();
+        let mut sequence_number_entries = Vec::new();
+        for wrapper in stored.entries {
+            let expired = protected_entry(&wrapper)
+                .filter(|entry| expires_at(entry).is_none())
+                .and_then(|entry| {
+                    let hash = entry.storage_payload.as_ref()?.bisq_hash();
+                    Some(sequence_number_entry(hash, entry.sequence_number, now))
+                });
+            match expired {
+                // Kept until purged, so the entry can't be replayed
+                Some(sequence_number) => {
+                    compaction.expired += 1;
+                    sequence_number_entries.push(sequence_number);
+                }
+                None => entries.push(wrapper),
+            }
+        }
+        compaction.entries = entries.len();
+        for sequence_number in stored
+            .sequence_numbers
+            .unwrap_or_default()
+            .sequence_number_entries
+        {
+            let purged = sequence_number
+                .map_value
+                .as_ref()
+                .and_then(|value| {
+                    let last_delivery =
+                        UNIX_EPOCH.checked_add(Duration::from_millis(value.time_stamp as u64))?;
+                    now.duration_since(last_delivery).ok()
+                })
+                .is_none_or(|age| age >= PURGE_AGE);
+            if purged {
+                compaction.purged += 1;
+            } else {
+                sequence_number_entries.push(sequence_number);
+            }
+        }
+        let mut seen = HashSet::new();
+        let payloads: Vec<_> = stored
+            .payloads
+            .into_iter()
+            .filter(|payload| payload.bisq_hash().is_ok_and(|hash| seen.insert(hash)))
+            .collect();
+        compaction.payloads = payloads.len();
+        self.save(&StoredData {
+            entries,
+            payloads,
+            sequence_numbers: Some(SequenceNumberMap {
+                sequence_number_entries,
+            }),
+        })?;
+        Ok(compaction)
+    }
+}
+
+enum Latest {
+    Entry(StorageEntryWrapper),
+    Removed(SequenceNumberEntry),
+}
+
+/// The snapshot with the changes of the delta log applied. Like routing,
+/// only a newer sequence number replaces an entry, so the changes a snapshot
+/// has already don't change it.
+#[derive(Default)]
+struct Replay {
+    latest: HashMap<Vec<u8>, (i32, Latest)>,
+    payloads: Vec<PersistableNetworkPayload>,
+}
+impl Replay {
+    fn snapshot(&mut self, snapshot: StoredData) {
+        for sequence_number in snapshot
+            .sequence_numbers
+            .unwrap_or_default()
+            .sequence_number_entries
+        {
+            self.apply(Delta {
+                removed: Some(sequence_number),
+                ..Delta::default()
+            });
+        }
+        for entry in snapshot.entries {
+            self.apply(Delta::entry(entry));
+        }
+        self.payloads = snapshot.payloads;
+    }
+
+    fn apply(&mut self, delta: Delta) {
+        if let Some(payload) = delta.payload {
+            self.payloads.push(payload);
+        }
+        if let Some(entry) = delta.entry {
+            let key = protected_entry(&entry).and_then(|protected| {
+                let hash = protected.storage_payload.as_ref()?.bisq_hash();
+                Some((Vec::from(hash), protected.sequence_number))
+            });
+            if let Some((hash, sequence)) = key {
+                self.replace(hash, sequence, Latest::Entry(entry));
+            }
+        }
+        if let Some(removed) = delta.removed {
+            let hash = removed.bytes.as_ref().map(|bytes| bytes.bytes.clone());
+            let sequence = removed.map_value.as_ref().map(|value| value.sequence_nr);
+            if let (Some(hash), Some(sequence)) = (hash, sequence) {
+                self.replace(hash, sequence, Latest::Removed(removed));
+            }
+        }
+    }
+
+    fn replace(&mut self, hash: Vec<u8>, sequence: i32, latest: Latest) {
+        match self.latest.get(&hash) {
+            Some((known, _)) if *known >= sequence => (),
+            _ => {
+                self.latest.insert(hash, (sequence, latest));
+            }
+        }
+    }
+
+    fn into_stored(self) -> StoredData {
+        let mut entries = Vec::new();
+        let mut sequence_number_entries = Vec::new();
+        for (_, (_, latest)) in self.latest {
+            match latest {
+                Latest::Entry(entry) => entries.push(entry),
+                Latest::Removed(sequence_number) => sequence_number_entries.push(sequence_number),
+            }
+        }
+        StoredData {
+            entries,
+            payloads: self.payloads,
+            sequence_numbers: Some(SequenceNumberMap {
+                sequence_number_entries,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bisq::payload::{
+        persistable_network_payload, storage_entry_wrapper, storage_payload, AccountAgeWitness,
+        OfferPayload, ProtectedStorageEntry, StoragePayload,
+    };
+    use std::{env, process};
+
+    fn entry(id: &str, sequence: i32, created: SystemTime) -> StorageEntryWrapper {
+        StorageEntryWrapper {
+            message: Some(storage_entry_wrapper::Message::ProtectedStorageEntry(
+                ProtectedStorageEntry {
+                    storage_payload: Some(StoragePayload {
+                        message: Some(storage_payload::Message::OfferPayload(OfferPayload {
+                            id: id.to_string(),
+                            ..Default::default()
+                        })),
+                    }),
+                    sequence_number: sequence,
+                    creation_time_stamp: created.duration_since(UNIX_EPOCH).unwrap().as_millis()
+                        as i64,
+                    ..Default::default()
+                },
+            )),
+        }
+    }
+
+    fn hash(entry: &StorageEntryWrapper) -> SequencedMessageHash {
+        protected_entry(entry)
+            .and_then(|entry| entry.storage_payload.as_ref())
+            .unwrap()
+            .bisq_hash()
+    }
+
+    #[test]
+    fn replays_the_delta_log_over_the_snapshot() {
+        let path = env::temp_dir().join(format!("risq-data-delta-{}", process::id()));
+        let now = SystemTime::now();
+        let mut store = DataStore::new(path.clone());
+        store
+            .save(&StoredData {
+                entries: vec![entry("a", 1, now), entry("b", 1, now)],
+                ..StoredData::default()
+            })
+            .unwrap();
+        store.append(&Delta::entry(entry("a", 2, now))).unwrap();
+        store
+            .append(&Delta::removed(hash(&entry("b", 1, now)), 2, now))
+            .unwrap();
+        // Written before the last snapshot by a daemon that crashed
+        store.append(&Delta::entry(entry("a", 1, now))).unwrap();
+        store
+            .append(&Delta::entry(entry("c", 1, UNIX_EPOCH)))
+            .unwrap();
+        store.flush().unwrap();
+        let mut log = OpenOptions::new()
+            .append(true)
+            .open(store.delta_path())
+            .unwrap();
+        log.write_all(&[42, 1, 2]).unwrap();
+
+        let loaded = DataStore::new(path.clone()).load().unwrap();
+        let mut entries = loaded.entries.clone();
+        entries.sort_by_key(|entry| hash(entry).as_bytes().to_vec());
+        let mut expected = vec![entry("a", 2, now), entry("c", 1, UNIX_EPOCH)];
+        expected.sort_by_key(|entry| hash(entry).as_bytes().to_vec());
+        assert_eq!(entries, expected);
+        let removed = &loaded.sequence_numbers.unwrap().sequence_number_entries;
+        assert_eq!(
+            removed,
+            &vec![sequence_number_entry(hash(&entry("b", 1, now)), 2, now)]
+        );
+
+        let compaction = store.compact().unwrap();
+        assert_eq!(
+            compaction,
+            Compaction {
+                replayed: 4,
+                entries: 1,
+                expired: 1,
+                purged: 0,
+                payloads: 0,
+            }
+        );
+        assert!(!store.delta_path().exists());
+        let compacted = store.load().unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(compacted.entries, vec![entry("a", 2, now)]);
+        assert_eq!(
+            compacted
+                .sequence_numbers
+                .unwrap()
+                .sequence_number_entries
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = env::temp_dir().join(format!("risq-data-{}", process::id()));
+        let mut store = DataStore::new(path.clone());
+        assert_eq!(store.load().unwrap(), StoredData::default());
+
+        let stored = StoredData {
+            entries: Vec::new(),
+            payloads: vec![PersistableNetworkPayload {
+                message: Some(persistable_network_payload::Message::AccountAgeWitness(
+                    AccountAgeWitness {
+                        hash: vec![1; 20],
+                        date: 1_500_000_000_000,
+                    },
+                )),
+            }],
+            sequence_numbers: Some(SequenceNumberMap {
+                sequence_number_entries: vec![SequenceNumberEntry {
+                    bytes: Some(ByteArray { bytes: vec![2; 32] }),
+                    map_value: Some(MapValue {
+                        sequence_nr: 3,
+                        time_stamp: 1_500_000_000_000,
+                    }),
+                }],
+            }),
+        };
+        store.save(&stored).unwrap();
+        let loaded = store.load().unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(loaded, stored);
+        assert_eq!(loaded.payload_keys().len(), 1);
+    }
+}
@@ -0,0 +1,450 @@
+use super::data_router::{DataRouterDispatch, Publish};
+use crate::{
+    bisq::{
+        constants::BaseCurrencyNetwork,
+        crypto::{self, KeyRing},
+        payload::*,
+        Sha256,
+    },
+    domain::mailbox::Mailbox,
+    error,
+    p2p::{dispatch::*, message::Broadcast, Broadcaster, Connection, Payload, Status, Transport},
+    prelude::{fut::Either, sync::oneshot, *},
+    scheduler::{Schedule, Task},
+};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use uuid::Uuid;
+
+const MAILBOX_POLLING: Task = Task::new("mailbox_polling", Duration::from_secs(10));
+/// After this the receiver is considered offline and the message goes to
+/// its mailbox instead.
+const DIRECT_SEND_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long a sent message waits for its `AckMessage`
+const ACK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Sends messages sealed for the key ring of a single peer as
+/// `PrefixedSealedAndSignedMessage`s, over our own connection to it or
+/// through its mailbox when it can't be reached. Messages sealed for us,
+/// from our connections or our mailbox, are handed to the actor that
+/// registered for their type.
+pub struct DirectMessages {
+    network: BaseCurrencyNetwork,
+    key_ring: Arc<KeyRing>,
+    status: Status,
+    transport: Arc<dyn Transport>,
+    connections: HashMap<NodeAddress, Addr<Connection>>,
+    broadcaster: Addr<Broadcaster>,
+    data_router: Recipient<Publish>,
+    mailbox: Mailbox,
+    /// By message type, eg. `ChatMessage`
+    handlers: HashMap<&'static str, Recipient<DirectMessage>>,
+    /// By the uid of the message that is acknowledged
+    awaiting_acks: HashMap<String, oneshot::Sender<AckMessage>>,
+}
+impl Actor for DirectMessages {
+    type Context = Context<Self>;
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.schedule(MAILBOX_POLLING, |direct, _| direct.check_mailbox());
+    }
+}
+
+impl DirectMessages {
+    pub fn start(
+        network: BaseCurrencyNetwork,
+        key_ring: Arc<KeyRing>,
+        status: Status,
+        transport: Arc<dyn Transport>,
+        broadcaster: Addr<Broadcaster>,
+        data_router: Recipient<Publish>,
+        mailbox: Mailbox,
+    ) -> Addr<Self> {
+        Self {
+            network,
+            key_ring,
+            status,
+            transport,
+            connections: HashMap::new(),
+            broadcaster,
+            data_router,
+            mailbox,
+            handlers: HashMap::new(),
+            awaiting_acks: HashMap::new(),
+        }
+        .start()
+    }
+
+    // Reuses our last connection to the peer, replies may also arrive through the Peers connections.
+    fn connection(
+        &self,
+        peer: NodeAddress,
+        ctx: &mut Context<Self>,
+    ) -> impl ActorFuture<Item = Addr<Connection>, Error = error::Error, Actor = Self> {
+        match self.connections.get(&peer).filter(|conn| conn.connected()) {
+            Some(conn) => Either::A(fut::ok(conn.clone())),
+            None => Either::B(
+                fut::wrap_future(Connection::open(
+                    peer.clone(),
+                    self.network.into(),
+                    ActorDispatcher::<Self, PrefixedSealedAndSignedMessage>::new(ctx.address()),
+                    &*self.transport,
+                ))
+                .map(move |(_id, conn), direct: &mut Self, _| {
+                    direct.connections.insert(peer, conn.clone());
+                    conn
+                }),
+            ),
+        }
+    }
+
+    fn seal_for(
+        &self,
+        peer: &NodeAddress,
+        peer_keys: &PubKeyRing,
+        msg: network_envelope::Message,
+    ) -> Result<PrefixedSealedAndSignedMessage, String> {
+        let local_addr = self
+            .status
+            .local_addr()
+            .ok_or("Our p2p server isn't listening yet")?;
+        let sealed = crypto::seal(msg, self.network.into(), &self.key_ring, peer_keys)
+            .map_err(|e| format!("Couldn't seal message: {}", e))?;
+        Ok(PrefixedSealedAndSignedMessage {
+            node_address: Some(local_addr),
+            sealed_and_signed: Some(sealed),
+            address_prefix_hash: peer.address_prefix_hash(),
+            uid: Uuid::new_v4().to_string(),
+        })
+    }
+
+    fn send_envelope(
+        &self,
+        peer: NodeAddress,
+        envelope: PrefixedSealedAndSignedMessage,
+        ctx: &mut Context<Self>,
+    ) -> impl ActorFuture<Item = (), Error = String, Actor = Self> {
+        let receiver = peer.clone();
+        self.connection(peer.clone(), ctx)
+            .and_then(|conn, _, _| fut::wrap_future(conn.send(Payload(envelope)).flatten()))
+            .map_err(move |e, _, _| format!("Couldn't send message to {}: {:?}", peer, e))
+            .timeout(DIRECT_SEND_TIMEOUT, format!("{} didn't respond", receiver))
+    }
+
+    // The receiver picks the message up from the network once it is online again.
+    fn store_in_mailbox(
+        &self,
+        envelope: PrefixedSealedAndSignedMessage,
+        receiver_keys: &PubKeyRing,
+    ) -> Result<(), String> {
+        let payload = StoragePayload {
+            message: Some(storage_payload::Message::MailboxStoragePayload(
+                MailboxStoragePayload {
+                    prefixed_sealed_and_signed_message: Some(envelope),
+                    sender_pub_key_for_add_operation_bytes: self
+                        .key_ring
+                        .pub_key_ring()
+                        .signature_pub_key_bytes,
+                    owner_pub_key_bytes: receiver_keys.signature_pub_key_bytes.clone(),
+                    extra_data: Vec::new(),
+                },
+            )),
+        };
+        let entry = ProtectedMailboxStorageEntry {
+            entry: Some(sign_entry(&self.key_ring, payload, 1)?),
+            receivers_pub_key_bytes: receiver_keys.signature_pub_key_bytes.clone(),
+        };
+        let msg = AddDataMessage {
+            entry: Some(StorageEntryWrapper {
+                message: Some(storage_entry_wrapper::Message::ProtectedMailboxStorageEntry(entry)),
+            }),
+        };
+        arbiter_spawn!(self.broadcaster.send(Broadcast(msg, None)));
+        Ok(())
+    }
+
+    // Like bisq every message we could open is removed from the network
+    // once it has been handled, messages for unknown trades included.
+    fn check_mailbox(&mut self) {
+        for mailbox_entry in self.mailbox.take() {
+            let entry = match mailbox_entry.entry {
+                Some(entry) => entry,
+                None => continue,
+            };
+            let payload = match entry
+                .storage_payload
+                .as_ref()
+                .and_then(|p| p.message.as_ref())
+            {
+                Some(storage_payload::Message::MailboxStoragePayload(payload)) => payload,
+                _ => continue,
+            };
+            let (sender, sealed) = match payload.prefixed_sealed_and_signed_message.as_ref() {
+                Some(PrefixedSealedAndSignedMessage {
+                    node_address: Some(sender),
+                    sealed_and_signed: Some(sealed),
+                    ..
+                }) => (sender.clone(), sealed),
+                _ => continue,
+            };
+            // Whoever stored the entry has to be the one who sealed the message
+            if sealed.sig_public_key_bytes != payload.sender_pub_key_for_add_operation_bytes {
+                continue;
+            }
+            let sig_pub_key = sealed.sig_public_key_bytes.clone();
+            match crypto::unseal(sealed, &self.key_ring) {
+                Ok(msg) => {
+                    debug!("Received mailbox message from {}", sender);
+                    self.deliver(sender, sig_pub_key, msg);
+                    self.remove_from_mailbox(&entry);
+                }
+                Err(e) => debug!("Couldn't unseal mailbox message from {}: {}", sender, e),
+            }
+        }
+    }
+
+    fn remove_from_mailbox(&self, entry: &ProtectedStorageEntry) {
+        let payload = match entry.storage_payload.clone() {
+            Some(payload) => payload,
+            None => return,
+        };
+        let removal = match sign_entry(&self.key_ring, payload, entry.sequence_number + 1) {
+            Ok(removal) => removal,
+            Err(e) => return warn!("Couldn't remove mailbox message: {}", e),
+        };
+        let msg = RemoveMailboxDataMessage {
+            protected_storage_entry: Some(ProtectedMailboxStorageEntry {
+                receivers_pub_key_bytes: removal.owner_pub_key_bytes.clone(),
+                entry: Some(removal),
+            }),
+        };
+        arbiter_spawn!(self
+            .data_router
+            .send(Publish(DataRouterDispatch::RemoveMailboxData(msg))));
+    }
+
+    fn deliver(
+        &mut self,
+        sender: NodeAddress,
+        sig_pub_key: Vec<u8>,
+        msg: network_envelope::Message,
+    ) {
+        if let network_envelope::Message::AckMessage(ack) = &msg {
+            if let Some(waiting) = self.awaiting_acks.remove(&ack.source_uid) {
+                let _ = waiting.send(ack.clone());
+            }
+        }
+        match self.handlers.get(msg.name()) {
+            Some(handler) => {
+                if let Err(e) = handler.do_send(DirectMessage {
+                    sender,
+                    sig_pub_key,
+                    msg,
+                }) {
+                    warn!("Couldn't hand on direct message: {}", e);
+                }
+            }
+            None => debug!("Ignoring sealed {} from {}", msg.name(), sender),
+        }
+    }
+}
+
+/// Signs a storage entry with our key, like bisq's `P2PDataStorage`
+/// does for the offers and mailbox entries it adds and removes.
+pub(super) fn sign_entry(
+    key_ring: &KeyRing,
+    payload: StoragePayload,
+    sequence_number: i32,
+) -> Result<ProtectedStorageEntry, String> {
+    let hash = DataAndSeqNrPair {
+        payload: Some(payload.clone()),
+        sequence_number,
+    }
+    .sha256();
+    let signature = key_ring
+        .sign(&hash.into_inner())
+        .map_err(|e| format!("Couldn't sign storage entry: {}", e))?;
+    Ok(ProtectedStorageEntry {
+        storage_payload: Some(payload),
+        owner_pub_key_bytes: key_ring.pub_key_ring().signature_pub_key_bytes,
+        sequence_number,
+        signature,
+        creation_time_stamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_millis() as i64,
+    })
+}
+
+/// A message sealed for us, `sig_pub_key` is the key it was signed with.
+pub struct DirectMessage {
+    pub sender: NodeAddress,
+    pub sig_pub_key: Vec<u8>,
+    pub msg: network_envelope::Message,
+}
+impl Message for DirectMessage {
+    type Result = ();
+}
+
+/// Hands messages of these types, eg. `ChatMessage`, to `handler`. There
+/// is one handler per type, the last one registered.
+pub struct RegisterHandler(pub &'static [&'static str], pub Recipient<DirectMessage>);
+impl Message for RegisterHandler {
+    type Result = ();
+}
+impl Handler<RegisterHandler> for DirectMessages {
+    type Result = ();
+    fn handle(&mut self, RegisterHandler(types, handler): RegisterHandler, _: &mut Self::Context) {
+        for message_type in types {
+            self.handlers.insert(*message_type, handler.clone());
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Delivery {
+    /// Sent over a connection to the receiver
+    Arrived,
+    /// The receiver confirmed it with an `AckMessage`
+    Acknowledged,
+    /// The receiver wasn't reachable
+    StoredInMailbox,
+}
+
+/// Seals `msg` for `receiver_keys` and sends it.
+pub struct SendDirect {
+    pub receiver: NodeAddress,
+    pub receiver_keys: PubKeyRing,
+    pub msg: network_envelope::Message,
+    /// Wait for the `AckMessage` of the message with this uid
+    pub ack_uid: Option<String>,
+    /// Store the message in the receiver's mailbox when it can't be reached
+    pub mailbox: bool,
+}
+impl Message for SendDirect {
+    type Result = Result<Delivery, String>;
+}
+impl Handler<SendDirect> for DirectMessages {
+    type Result = ResponseActFuture<Self, Delivery, String>;
+    fn handle(
+        &mut self,
+        SendDirect {
+            receiver,
+            receiver_keys,
+            msg,
+            ack_uid,
+            mailbox,
+        }: SendDirect,
+        ctx: &mut Self::Context,
+    ) -> Self::Result {
+        let envelope = match self.seal_for(&receiver, &receiver_keys, msg) {
+            Ok(envelope) => envelope,
+            Err(e) => return Box::new(fut::err(e)),
+        };
+        // Registered before sending, the ack may arrive before the send completes
+        let ack = ack_uid.map(|uid| {
+            let (send, receive) = oneshot::channel();
+            self.awaiting_acks.insert(uid.clone(), send);
+            (uid, receive)
+        });
+        Box::new(
+            self.send_envelope(receiver.clone(), envelope.clone(), ctx)
+                .then(move |sent, direct: &mut Self, _| match sent {
+                    Ok(()) => Either::A(match ack {
+                        Some((uid, receive)) => Either::A(
+                            fut::wrap_future(receive)
+                                .map_err(|_, _, _| ())
+                                .timeout(ACK_TIMEOUT, ())
+                                .then(move |ack, direct: &mut Self, _| {
+                                    direct.awaiting_acks.remove(&uid);
+                                    fut::result(match ack {
+                                        Ok(AckMessage { success: true, .. }) => {
+                                            Ok(Delivery::Acknowledged)
+                                        }
+                                        Ok(AckMessage { error_message, .. }) => Err(format!(
+                                            "{} refused the message: {}",
+                                            receiver, error_message
+                                        )),
+                                        // Arrived, but the receiver didn't confirm it in time
+                                        Err(_) => Ok(Delivery::Arrived),
+                                    })
+                                }),
+                        ),
+                        None => Either::B(fut::ok(Delivery::Arrived)),
+                    }),
+                    Err(e) => {
+                        if let Some((uid, _)) = ack.as_ref() {
+                            direct.awaiting_acks.remove(uid);
+                        }
+                        if !mailbox {
+                            return Either::B(fut::err(e));
+                        }
+                        info!("Storing message in the mailbox of {}: {}", receiver, e);
+                        Either::B(fut::result(
+                            direct
+                                .store_in_mailbox(envelope, &receiver_keys)
+                                .map(|()| Delivery::StoredInMailbox),
+                        ))
+                    }
+                }),
+        )
+    }
+}
+
+impl Handler<Receive<PrefixedSealedAndSignedMessage>> for DirectMessages {
+    type Result = ();
+    fn handle(
+        &mut self,
+        Receive(_, msg): Receive<PrefixedSealedAndSignedMessage>,
+        _: &mut Self::Context,
+    ) {
+        let local_addr = match self.status.local_addr() {
+            Some(addr) => addr,
+            None => return,
+        };
+        // Mailbox messages for other nodes share the type, only the prefix tells them apart
+        if msg.address_prefix_hash != local_addr.address_prefix_hash() {
+            return;
+        }
+        let (sender, sealed) = match (msg.node_address, msg.sealed_and_signed) {
+            (Some(sender), Some(sealed)) => (sender, sealed),
+            _ => return warn!("Received incomplete PrefixedSealedAndSignedMessage"),
+        };
+        match crypto::unseal(&sealed, &self.key_ring) {
+            Ok(inner) => self.deliver(sender, sealed.sig_public_key_bytes, inner),
+            Err(e) => debug!("Couldn't unseal message from {}: {}", sender, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signs_entries_with_our_key() {
+        let key_ring = KeyRing::generate().unwrap();
+        let payload = StoragePayload {
+            message: Some(storage_payload::Message::MailboxStoragePayload(
+                MailboxStoragePayload::default(),
+            )),
+        };
+        let entry = sign_entry(&key_ring, payload.clone(), 2).unwrap();
+        assert_eq!(entry.sequence_number, 2);
+        let hash = DataAndSeqNrPair {
+            payload: Some(payload),
+            sequence_number: 2,
+        }
+        .sha256();
+        assert_eq!(
+            crypto::sig::verify(
+                &entry.owner_pub_key_bytes,
+                &entry.signature,
+                &hash.into_inner()
+            ),
+            Some(true)
+        );
+    }
+}
@@ -0,0 +1,266 @@
+//! The identity of a node is the key of its hidden service, which makes its
+//! onion address, and its key ring, which signs its offers and seals what
+//! trading peers send it. `risq identity` moves it to another server or
+//! replaces it, always while the daemon is stopped.
+use super::{
+    trade::{OfferStore, TradeStore},
+    KEY_RING_PATH, OFFERS_PATH, SERIVCE_PRIVATE_KEY_PATH, TRADES_PATH,
+};
+use crate::bisq::crypto::{write_private_file, KeyRing, ENCRYPTION_KEY_FILE, SIGNATURE_KEY_FILE};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const IDENTITY_PATH: &str = "identity";
+/// The identity replaced by `risq identity rotate`, until the daemon moved
+/// our offers to the new one
+const PREVIOUS_PATH: &str = "previous";
+
+/// Everything that makes up the identity, for `risq identity export` and
+/// `import`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IdentityBundle {
+    /// The private key of the hidden service as tor gave it, `None` until
+    /// the daemon ran with tor
+    pub onion_key: Option<String>,
+    /// Base64 of the key files, encrypted when the key ring has a password
+    pub signature_key: String,
+    pub encryption_key: String,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Rotation {
+    /// Where the previous identity is kept
+    pub previous: PathBuf,
+    /// Signed again with the new identity on the next start
+    pub offers: usize,
+    pub new_key_ring: bool,
+}
+
+// Where the files of an identity are, under the risq home or a backup
+struct Files {
+    onion_key: PathBuf,
+    key_ring: PathBuf,
+}
+impl Files {
+    fn of_home(risq_home: &Path) -> Self {
+        Files {
+            onion_key: risq_home.join(SERIVCE_PRIVATE_KEY_PATH),
+            key_ring: risq_home.join(KEY_RING_PATH),
+        }
+    }
+
+    fn of_backup(dir: PathBuf) -> Self {
+        Files {
+            onion_key: dir.join("service.key"),
+            key_ring: dir.join(KEY_RING_PATH),
+        }
+    }
+
+    fn read(&self) -> io::Result<IdentityBundle> {
+        let onion_key = match fs::read_to_string(&self.onion_key) {
+            Ok(key) => Some(key),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        let key = |file| {
+            fs::read(self.key_ring.join(file))
+                .map(|key| base64::encode(&key))
+                .map_err(|e| io::Error::new(e.kind(), format!("Couldn't read key ring: {}", e)))
+        };
+        Ok(IdentityBundle {
+            onion_key,
+            signature_key: key(SIGNATURE_KEY_FILE)?,
+            encryption_key: key(ENCRYPTION_KEY_FILE)?,
+        })
+    }
+
+    fn write(&self, bundle: &IdentityBundle) -> io::Result<()> {
+        let decode = |key: &str| {
+            base64::decode(key).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        };
+        let signature_key = decode(&bundle.signature_key)?;
+        let encryption_key = decode(&bundle.encryption_key)?;
+        fs::create_dir_all(&self.key_ring)?;
+        write_private_file(&self.key_ring.join(SIGNATURE_KEY_FILE), &signature_key)?;
+        write_private_file(&self.key_ring.join(ENCRYPTION_KEY_FILE), &encryption_key)?;
+        match bundle.onion_key.as_ref() {
+            Some(key) => {
+                fs::create_dir_all(self.onion_key.parent().expect("Onion key without dir"))?;
+                write_private_file(&self.onion_key, key.as_bytes())
+            }
+            None => self.remove_onion_key(),
+        }
+    }
+
+    fn remove_onion_key(&self) -> io::Result<()> {
+        match fs::remove_file(&self.onion_key) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    fn exists(&self) -> bool {
+        self.key_ring.join(SIGNATURE_KEY_FILE).exists()
+    }
+}
+
+pub fn export_identity(risq_home: &Path) -> io::Result<IdentityBundle> {
+    Files::of_home(risq_home).read()
+}
+
+/// Replaces the identity with `bundle`. The one it replaces is kept under
+/// `identity/backup-<unix secs>` and returned.
+pub fn import_identity(risq_home: &Path, bundle: &IdentityBundle) -> io::Result<Option<PathBuf>> {
+    let files = Files::of_home(risq_home);
+    let backup = if files.exists() {
+        let dir = identity_dir(risq_home).join(format!("backup-{}", now_secs()));
+        Files::of_backup(dir.clone()).write(&files.read()?)?;
+        Some(dir)
+    } else {
+        None
+    };
+    files.write(bundle)?;
+    Ok(backup)
+}
+
+/// Gives the node a new onion address and, unless `keep_key_ring`, a new
+/// key ring. Our offers are removed from the network and published again
+/// with the new identity once the daemon started with it, so there must be
+/// no open trades, whose peers only know the previous one.
+pub fn rotate_identity(
+    risq_home: &Path,
+    keep_key_ring: bool,
+    password: Option<&str>,
+) -> io::Result<Rotation> {
+    let open_trades = TradeStore::new(risq_home.join(TRADES_PATH))
+        .load()?
+        .iter()
+        .filter(|trade| !trade.state().is_final())
+        .count();
+    if open_trades > 0 {
+        return Err(io::Error::other(format!(
+            "{} trades are open, their peers would lose track of us",
+            open_trades
+        )));
+    }
+    let previous = identity_dir(risq_home).join(PREVIOUS_PATH);
+    if previous.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "The last rotation isn't done yet, start the daemon to finish it",
+        ));
+    }
+    let files = Files::of_home(risq_home);
+    Files::of_backup(previous.clone()).write(&files.read()?)?;
+    files.remove_onion_key()?;
+    if !keep_key_ring {
+        fs::remove_file(files.key_ring.join(SIGNATURE_KEY_FILE))?;
+        fs::remove_file(files.key_ring.join(ENCRYPTION_KEY_FILE))?;
+        KeyRing::load_or_generate(&files.key_ring, password)?;
+    }
+    Ok(Rotation {
+        previous,
+        offers: OfferStore::new(risq_home.join(OFFERS_PATH)).load()?.len(),
+        new_key_ring: !keep_key_ring,
+    })
+}
+
+/// The identity before `risq identity rotate`, until our offers were moved
+/// to the new one.
+pub struct PreviousIdentity {
+    dir: PathBuf,
+    pub key_ring: KeyRing,
+}
+impl PreviousIdentity {
+    pub fn load(risq_home: &Path, password: Option<&str>) -> io::Result<Option<Self>> {
+        let dir = identity_dir(risq_home).join(PREVIOUS_PATH);
+        let files = Files::of_backup(dir.clone());
+        if !files.exists() {
+            return Ok(None);
+        }
+        Ok(Some(PreviousIdentity {
+            key_ring: KeyRing::load_or_generate(&files.key_ring, password)?,
+            dir,
+        }))
+    }
+
+    /// Keeps it as a backup once the offers were moved.
+    pub fn retire(self) -> io::Result<PathBuf> {
+        let backup = self.dir.with_file_name(format!("rotated-{}", now_secs()));
+        fs::rename(&self.dir, &backup)?;
+        Ok(backup)
+    }
+}
+
+fn identity_dir(risq_home: &Path) -> PathBuf {
+    risq_home.join(IDENTITY_PATH)
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    #[test]
+    fn exports_imports_and_rotates_the_identity() {
+        let dir = env::temp_dir().join(format!("risq-identity-{}", process::id()));
+        let (old_home, new_home) = (dir.join("old"), dir.join("new"));
+        KeyRing::load_or_generate(&old_home.join(KEY_RING_PATH), None).unwrap();
+        fs::create_dir_all(old_home.join("tor")).unwrap();
+        fs::write(old_home.join(SERIVCE_PRIVATE_KEY_PATH), "RSA1024:key").unwrap();
+
+        let bundle = export_identity(&old_home).unwrap();
+        assert_eq!(bundle.onion_key, Some("RSA1024:key".into()));
+        assert_eq!(import_identity(&new_home, &bundle).unwrap(), None);
+        assert_eq!(export_identity(&new_home).unwrap(), bundle);
+
+        let rotation = rotate_identity(&new_home, false, None).unwrap();
+        assert_eq!(rotation.offers, 0);
+        let rotated = export_identity(&new_home).unwrap();
+        assert_eq!(rotated.onion_key, None);
+        assert_ne!(rotated.signature_key, bundle.signature_key);
+        assert!(rotate_identity(&new_home, false, None).is_err());
+
+        let previous = PreviousIdentity::load(&new_home, None).unwrap().unwrap();
+        assert_eq!(
+            previous.key_ring.pub_key_ring(),
+            KeyRing::load_or_generate(&old_home.join(KEY_RING_PATH), None)
+                .unwrap()
+                .pub_key_ring()
+        );
+        previous.retire().unwrap();
+        assert!(PreviousIdentity::load(&new_home, None).unwrap().is_none());
+
+        let backup = import_identity(&new_home, &bundle).unwrap().unwrap();
+        #[cfg(unix)]
+        assert_private(&Files::of_home(&new_home));
+        let backup = Files::of_backup(backup);
+        assert_eq!(backup.read().unwrap(), rotated);
+        #[cfg(unix)]
+        assert_private(&backup);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[cfg(unix)]
+    fn assert_private(files: &Files) {
+        use std::os::unix::fs::PermissionsExt;
+        let key_ring = [SIGNATURE_KEY_FILE, ENCRYPTION_KEY_FILE]
+            .iter()
+            .map(|file| files.key_ring.join(file));
+        for path in key_ring.chain(Some(files.onion_key.clone()).filter(|key| key.exists())) {
+            let mode = fs::metadata(path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+}
@@ -0,0 +1,300 @@
+use crate::{
+    bisq::{
+        payload::{StorageEntryWrapper, StoragePayload},
+        SequencedMessageHash,
+    },
+    domain::budget::Evictions,
+};
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{Arc, Mutex, MutexGuard},
+    time::SystemTime,
+};
+
+/// The infos are spread over this many maps by the first byte of their
+/// hash, each behind a lock of its own.
+const STRIPES: usize = 64;
+
+pub struct SequencedMessageInfo {
+    pub last_delivery: SystemTime,
+    pub sequence: i32,
+    pub owner_pub_key: Vec<u8>,
+    pub original_payload: StoragePayload,
+    // None once the entry was removed or has expired
+    pub expires_at: Option<SystemTime>,
+    // Kept to be stored and, in seed mode, to answer GetData requests
+    pub entry: Option<StorageEntryWrapper>,
+}
+
+/// The latest sequence number of every storage entry, shared by the
+/// `DataRouter` and the verifier threads. Entries of the initial sync are
+/// claimed on the verifier threads right after their signature was checked,
+/// while the `DataRouter` goes on with other messages. Claiming a hash locks
+/// its stripe, so only a newer sequence number than the one seen last, from
+/// whichever thread, is ever delivered.
+#[derive(Clone)]
+pub struct SequencedInfos {
+    stripes: Arc<Vec<Mutex<HashMap<SequencedMessageHash, SequencedMessageInfo>>>>,
+    max_entries: Option<usize>,
+    /// The entries that may be evicted by their last delivery, kept with a
+    /// budget only. Removals keep their sequence number until it's purged,
+    /// so they aren't in here.
+    by_delivery: Arc<Mutex<BTreeSet<(SystemTime, SequencedMessageHash)>>>,
+    evictions: Evictions,
+}
+
+pub struct Claim<'a> {
+    pub hash: SequencedMessageHash,
+    pub sequence: i32,
+    pub owner_pub_key: Vec<u8>,
+    pub original_payload: &'a StoragePayload,
+    pub expires_at: Option<SystemTime>,
+}
+
+impl SequencedInfos {
+    pub fn new(max_entries: Option<usize>, evictions: Evictions) -> Self {
+        Self {
+            stripes: Arc::new((0..STRIPES).map(|_| Mutex::default()).collect()),
+            max_entries,
+            by_delivery: Arc::default(),
+            evictions,
+        }
+    }
+
+    /// The stripe `hash` belongs to, locked. Don't claim anything while
+    /// holding it.
+    pub fn stripe(
+        &self,
+        hash: &SequencedMessageHash,
+    ) -> MutexGuard<'_, HashMap<SequencedMessageHash, SequencedMessageInfo>> {
+        self.stripes[hash.as_bytes()[0] as usize % STRIPES]
+            .lock()
+            .expect("Corrupted lock in sequenced infos")
+    }
+
+    /// Runs `f` on the stripes one after the other.
+    pub fn each_stripe(
+        &self,
+        mut f: impl FnMut(&mut HashMap<SequencedMessageHash, SequencedMessageInfo>),
+    ) {
+        for stripe in self.stripes.iter() {
+            f(&mut stripe.lock().expect("Corrupted lock in sequenced infos"))
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        let mut len = 0;
+        self.each_stripe(|stripe| len += stripe.len());
+        len
+    }
+
+    /// Whether the entry is new or newer than the one we have, which it
+    /// replaces then.
+    pub fn claim(&self, claim: Claim) -> bool {
+        if !self.stripe(&claim.hash).contains_key(&claim.hash) {
+            self.make_room();
+        }
+        let mut stripe = self.stripe(&claim.hash);
+        let now = SystemTime::now();
+        match stripe.get_mut(&claim.hash) {
+            Some(info) if claim.sequence > info.sequence => {
+                self.unindex(info.last_delivery, claim.hash);
+                info.sequence = claim.sequence;
+                info.last_delivery = now;
+                info.expires_at = claim.expires_at;
+                // Unknown for the sequence numbers restored from disk
+                info.owner_pub_key = claim.owner_pub_key;
+                info.original_payload = claim.original_payload.clone();
+            }
+            Some(_) => return false,
+            None => {
+                stripe.insert(
+                    claim.hash,
+                    SequencedMessageInfo {
+                        sequence: claim.sequence,
+                        last_delivery: now,
+                        owner_pub_key: claim.owner_pub_key,
+                        original_payload: claim.original_payload.clone(),
+                        expires_at: claim.expires_at,
+                        entry: None,
+                    },
+                );
+            }
+        }
+        drop(stripe);
+        if claim.expires_at.is_some() {
+            self.index(now, claim.hash);
+        }
+        true
+    }
+
+    // The index may be locked while holding a stripe, but not the other way round.
+    // Indexing may lock all stripes, so it needs none held.
+    fn index(&self, last_delivery: SystemTime, hash: SequencedMessageHash) {
+        if let Some(max) = self.max_entries {
+            let mut by_delivery = self.by_delivery();
+            by_delivery.insert((last_delivery, hash));
+            // Entries removed from the stripes directly leave theirs behind
+            if by_delivery.len() > 2 * max + STRIPES {
+                drop(by_delivery);
+                self.reindex();
+            }
+        }
+    }
+
+    fn unindex(&self, last_delivery: SystemTime, hash: SequencedMessageHash) {
+        if self.max_entries.is_some() {
+            self.by_delivery().remove(&(last_delivery, hash));
+        }
+    }
+
+    fn reindex(&self) {
+        let mut by_delivery = BTreeSet::new();
+        self.each_stripe(|stripe| {
+            by_delivery.extend(
+                stripe
+                    .iter()
+                    .filter(|(_, info)| info.expires_at.is_some())
+                    .map(|(hash, info)| (info.last_delivery, *hash)),
+            )
+        });
+        *self.by_delivery() = by_delivery;
+    }
+
+    fn by_delivery(&self) -> MutexGuard<'_, BTreeSet<(SystemTime, SequencedMessageHash)>> {
+        self.by_delivery
+            .lock()
+            .expect("Corrupted lock in sequenced infos")
+    }
+
+    // Drops the least recently delivered entry that hasn't been removed. Refreshes for
+    // evicted offers will be ignored so they expire unless they get added again. Removals
+    // are kept until they're purged, even if that exceeds the budget, or the entry could
+    // be added again with its old sequence number.
+    fn make_room(&self) {
+        match self.max_entries {
+            Some(max) if self.len() >= max => (),
+            _ => return,
+        }
+        loop {
+            let oldest = {
+                let mut by_delivery = self.by_delivery();
+                let oldest = by_delivery.iter().next().copied();
+                if let Some(oldest) = oldest {
+                    by_delivery.remove(&oldest);
+                }
+                oldest
+            };
+            let (last_delivery, hash) = match oldest {
+                Some(oldest) => oldest,
+                None => return,
+            };
+            let mut stripe = self.stripe(&hash);
+            match stripe.get(&hash) {
+                Some(info) if info.expires_at.is_none() => (),
+                // Delivered again since it was indexed, without a claim like refreshes
+                Some(info) if info.last_delivery != last_delivery => {
+                    self.by_delivery().insert((info.last_delivery, hash));
+                }
+                Some(_) => {
+                    stripe.remove(&hash);
+                    self.evictions.dedup_entries_evicted(1);
+                    return;
+                }
+                None => (),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::{sha256, Hash};
+    use std::{thread, time::Duration};
+
+    fn hash(n: u32) -> SequencedMessageHash {
+        SequencedMessageHash::new(sha256::Hash::hash(&n.to_be_bytes()))
+    }
+
+    fn claim(infos: &SequencedInfos, n: u32, sequence: i32) -> bool {
+        claim_until(
+            infos,
+            n,
+            sequence,
+            Some(SystemTime::now() + Duration::from_secs(60)),
+        )
+    }
+
+    fn claim_until(
+        infos: &SequencedInfos,
+        n: u32,
+        sequence: i32,
+        expires_at: Option<SystemTime>,
+    ) -> bool {
+        infos.claim(Claim {
+            hash: hash(n),
+            sequence,
+            owner_pub_key: Vec::new(),
+            original_payload: &StoragePayload::default(),
+            expires_at,
+        })
+    }
+
+    #[test]
+    fn claims_newer_sequence_numbers_once() {
+        let infos = SequencedInfos::new(None, Evictions::default());
+        assert!(claim(&infos, 1, 1));
+        assert!(!claim(&infos, 1, 1));
+        assert!(claim(&infos, 1, 3));
+        assert!(!claim(&infos, 1, 2));
+
+        // Two syncs delivering the same entries concurrently
+        let threads: Vec<_> = (0..2)
+            .map(|_| {
+                let infos = infos.clone();
+                thread::spawn(move || (100..1100).filter(|n| claim(&infos, *n, 1)).count())
+            })
+            .collect();
+        let claimed: usize = threads.into_iter().map(|t| t.join().unwrap()).sum();
+        assert_eq!(claimed, 1000);
+        assert_eq!(infos.len(), 1001);
+    }
+
+    #[test]
+    fn evicts_the_least_recently_delivered() {
+        let evictions = Evictions::default();
+        let infos = SequencedInfos::new(Some(2), evictions.clone());
+        claim(&infos, 1, 1);
+        claim(&infos, 2, 1);
+        // Refreshed offers are delivered again without a claim
+        infos
+            .stripe(&hash(1))
+            .get_mut(&hash(1))
+            .unwrap()
+            .last_delivery = SystemTime::now() + Duration::from_secs(1);
+        claim(&infos, 3, 1);
+        assert_eq!(infos.len(), 2);
+        assert!(infos.stripe(&hash(1)).contains_key(&hash(1)));
+        assert!(!infos.stripe(&hash(2)).contains_key(&hash(2)));
+        assert_eq!(evictions.dedup_entries(), 1);
+    }
+
+    #[test]
+    fn keeps_removals_over_the_budget() {
+        let evictions = Evictions::default();
+        let infos = SequencedInfos::new(Some(2), evictions.clone());
+        claim(&infos, 1, 1);
+        claim(&infos, 2, 1);
+        // Removed, its sequence number has to outlive newer entries
+        claim_until(&infos, 1, 2, None);
+        claim(&infos, 3, 1);
+        claim(&infos, 4, 1);
+        assert!(infos.stripe(&hash(1)).contains_key(&hash(1)));
+        assert!(!infos.stripe(&hash(2)).contains_key(&hash(2)));
+        assert!(!infos.stripe(&hash(3)).contains_key(&hash(3)));
+        assert_eq!(infos.len(), 2);
+        assert_eq!(evictions.dedup_entries(), 2);
+        assert!(!claim(&infos, 1, 1));
+    }
+}
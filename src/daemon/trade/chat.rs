@@ -0,0 +1,261 @@
+use super::{now_millis, TradeManager};
+use crate::{
+    bisq::payload::*,
+    daemon::direct_messages::{Delivery, SendDirect},
+    domain::{
+        dispute::DisputeAgentKind,
+        offer::OfferId,
+        trade::{message::SendChatMessage, ChatDelivery, ChatLine, ChatParty},
+    },
+    prelude::*,
+};
+use std::time::{Duration, UNIX_EPOCH};
+use uuid::Uuid;
+
+impl Handler<SendChatMessage> for TradeManager {
+    type Result = ResponseActFuture<Self, ChatLine, String>;
+    fn handle(
+        &mut self,
+        SendChatMessage {
+            trade_id,
+            party,
+            text,
+        }: SendChatMessage,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        match self.send_chat(&trade_id, party, text) {
+            Ok(sending) => Box::new(sending),
+            Err(e) => Box::new(fut::err(e)),
+        }
+    }
+}
+
+/// Bisq identifies the trader of a chat by `PubKeyRing.hashCode()`, which
+/// Lombok derives from the `Arrays.hashCode` of both keys.
+fn trader_id(keys: &PubKeyRing) -> i32 {
+    let array_hash = |bytes: &[u8]| {
+        bytes.iter().fold(1i32, |hash, byte| {
+            hash.wrapping_mul(31).wrapping_add(i32::from(*byte as i8))
+        })
+    };
+    [
+        &keys.signature_pub_key_bytes,
+        &keys.encryption_pub_key_bytes,
+    ]
+    .iter()
+    .fold(1i32, |hash, key| {
+        hash.wrapping_mul(59).wrapping_add(array_hash(key))
+    })
+}
+
+fn ack_source_type(party: ChatParty) -> &'static str {
+    match party {
+        ChatParty::Peer => "TRADE_CHAT_MESSAGE",
+        ChatParty::Mediator => "MEDIATION_MESSAGE",
+        ChatParty::RefundAgent => "REFUND_MESSAGE",
+    }
+}
+
+fn agent_kind(party: ChatParty) -> Option<DisputeAgentKind> {
+    match party {
+        ChatParty::Peer => None,
+        ChatParty::Mediator => Some(DisputeAgentKind::Mediator),
+        ChatParty::RefundAgent => Some(DisputeAgentKind::RefundAgent),
+    }
+}
+
+impl TradeManager {
+    // The dispute agents are looked up in the network, their keys aren't part of the trade.
+    fn chat_receiver(
+        &self,
+        id: &OfferId,
+        party: ChatParty,
+    ) -> Result<(NodeAddress, PubKeyRing), String> {
+        let trade = self
+            .trades
+            .get(id)
+            .ok_or_else(|| format!("Unknown trade {}", String::from(id.clone())))?;
+        let kind = match agent_kind(party) {
+            Some(kind) => kind,
+            None => return Ok((trade.peer.clone(), trade.peer_pub_key_ring.clone())),
+        };
+        let address = match kind {
+            DisputeAgentKind::Mediator => trade.mediator.as_ref(),
+            DisputeAgentKind::RefundAgent => trade.refund_agent.as_ref(),
+        }
+        .ok_or_else(|| format!("The trade has no {}", party))?;
+        let agent = self
+            .dispute_agents
+            .get(kind, address)
+            .ok_or_else(|| format!("The {} of the trade isn't registered anymore", party))?;
+        Ok((agent.address, agent.pub_key_ring))
+    }
+
+    fn send_chat(
+        &mut self,
+        id: &OfferId,
+        party: ChatParty,
+        text: String,
+    ) -> Result<impl ActorFuture<Item = ChatLine, Error = String, Actor = Self>, String> {
+        if text.trim().is_empty() {
+            return Err("The message is empty".into());
+        }
+        let (receiver, receiver_keys) = self.chat_receiver(id, party)?;
+        let uid = Uuid::new_v4().to_string();
+        let msg = ChatMessage {
+            date: now_millis(),
+            trade_id: id.clone().into(),
+            trader_id: trader_id(&self.key_ring.pub_key_ring()),
+            sender_is_trader: true,
+            message: text.clone(),
+            sender_node_address: self.status.local_addr(),
+            uid: uid.clone(),
+            r#type: party.support_type() as i32,
+            ..Default::default()
+        };
+        if let Some(trade) = self.trades.get_mut(id) {
+            trade.chat.push(ChatLine {
+                uid: uid.clone(),
+                party,
+                sent: true,
+                text,
+                date: UNIX_EPOCH + Duration::from_millis(now_millis() as u64),
+                delivery: ChatDelivery::Sending,
+            });
+        }
+        self.persist();
+        let id = id.clone();
+        Ok(fut::wrap_future(self.direct.send(SendDirect {
+            receiver,
+            receiver_keys,
+            msg: msg.into(),
+            ack_uid: Some(uid.clone()),
+            mailbox: true,
+        }))
+        .then(move |sent, manager: &mut Self, _| {
+            let delivery = match sent {
+                Ok(Ok(Delivery::Arrived)) => ChatDelivery::Arrived,
+                Ok(Ok(Delivery::Acknowledged)) => ChatDelivery::Acknowledged,
+                Ok(Ok(Delivery::StoredInMailbox)) => ChatDelivery::StoredInMailbox,
+                Ok(Err(e)) => {
+                    warn!("Couldn't send chat message of {:?}: {}", id, e);
+                    ChatDelivery::Failed
+                }
+                Err(e) => {
+                    warn!("Couldn't send chat message of {:?}: {}", id, e);
+                    ChatDelivery::Failed
+                }
+            };
+            fut::result(
+                manager
+                    .set_chat_delivery(&id, &uid, delivery)
+                    .ok_or_else(|| "Trade is gone".to_string()),
+            )
+        }))
+    }
+
+    fn set_chat_delivery(
+        &mut self,
+        id: &OfferId,
+        uid: &str,
+        delivery: ChatDelivery,
+    ) -> Option<ChatLine> {
+        let line = self
+            .trades
+            .get_mut(id)?
+            .chat
+            .iter_mut()
+            .find(|line| line.sent && line.uid == uid)?;
+        // An ack may arrive before the direct send completes
+        if line.delivery != ChatDelivery::Acknowledged {
+            line.delivery = delivery;
+        }
+        let line = line.clone();
+        self.persist();
+        Some(line)
+    }
+
+    pub(super) fn on_chat_message(
+        &mut self,
+        sig_pub_key: Vec<u8>,
+        msg: ChatMessage,
+        ctx: &mut Context<Self>,
+    ) {
+        let id: OfferId = msg.trade_id.clone().into();
+        let party = match SupportType::from_i32(msg.r#type).and_then(ChatParty::from_support_type) {
+            Some(party) => party,
+            None => return debug!("Ignoring chat message of type {}", msg.r#type),
+        };
+        let from_party = match agent_kind(party) {
+            None => self.peer_trade(&id, &sig_pub_key).is_some(),
+            Some(kind) => self.trade_agent(&id, kind, &sig_pub_key).is_some(),
+        };
+        if !from_party {
+            return debug!("Chat message for {:?} isn't from its {}", id, party);
+        }
+        if let Some(trade) = self.trades.get_mut(&id) {
+            // Mailbox messages can arrive again until their removal reached all peers
+            if !trade.chat.iter().any(|line| line.uid == msg.uid) {
+                info!("New chat message from the {} of trade {:?}", party, id);
+                trade.chat.push(ChatLine {
+                    uid: msg.uid.clone(),
+                    party,
+                    sent: false,
+                    text: msg.message,
+                    date: UNIX_EPOCH + Duration::from_millis(msg.date as u64),
+                    delivery: ChatDelivery::Arrived,
+                });
+                self.persist();
+            }
+        }
+        self.send_chat_ack(&id, party, msg.uid, ctx);
+    }
+
+    pub(super) fn on_chat_ack(&mut self, ack: &AckMessage) {
+        if ack.source_msg_class_name != "ChatMessage" || !ack.success {
+            return;
+        }
+        let id: OfferId = ack.source_id.clone().into();
+        self.set_chat_delivery(&id, &ack.source_uid, ChatDelivery::Acknowledged);
+    }
+
+    fn send_chat_ack(
+        &mut self,
+        id: &OfferId,
+        party: ChatParty,
+        source_uid: String,
+        ctx: &mut Context<Self>,
+    ) {
+        let (receiver, receiver_keys) = match self.chat_receiver(id, party) {
+            Ok(receiver) => receiver,
+            Err(e) => return warn!("Couldn't acknowledge chat message: {}", e),
+        };
+        let ack = AckMessage {
+            uid: Uuid::new_v4().to_string(),
+            sender_node_address: self.status.local_addr(),
+            source_type: ack_source_type(party).into(),
+            source_msg_class_name: "ChatMessage".into(),
+            source_uid,
+            source_id: id.clone().into(),
+            success: true,
+            error_message: String::new(),
+        };
+        let sending = self.send_sealed_to(receiver, &receiver_keys, ack.into());
+        ctx.spawn(sending.map_err(|e, _, _| warn!("Couldn't acknowledge chat message: {}", e)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trader_id_like_java_hash_code() {
+        // Arrays.hashCode(new byte[] { 1, -2 }) == 990, of an empty array 1
+        let keys = PubKeyRing {
+            signature_pub_key_bytes: vec![1, 254],
+            encryption_pub_key_bytes: Vec::new(),
+        };
+        assert_eq!(trader_id(&keys), (59 + 990) * 59 + 1);
+    }
+}
@@ -0,0 +1,455 @@
+use super::{now_millis, refund::delayed_payout, txid, TradeManager};
+use crate::{
+    bisq::{constants::LOCAL_CAPABILITIES, payload::*, SequencedMessageHash},
+    domain::{
+        amount::NumberWithPrecision,
+        dispute::DisputeAgentKind,
+        format::BTC_PRECISION,
+        offer::{message::GetOpenOffers, OfferId, OpenOffer},
+        payment_account::validate_peer_account,
+        trade::{
+            protocol::ProtocolVariant,
+            wallet::{MakerDeposit, TakerFunding},
+            Trade, TradeRole, TradeState,
+        },
+    },
+    prelude::*,
+    wallet::tx,
+};
+use bitcoin::{consensus, secp256k1::Secp256k1};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+// Same tolerance as bisq's `Offer.checkTradePriceTolerance`
+const PRICE_TOLERANCE: f64 = 0.01;
+
+impl TradeManager {
+    pub(super) fn own_offer(
+        &self,
+        offers: &HashMap<SequencedMessageHash, OpenOffer>,
+        id: &OfferId,
+    ) -> Option<OpenOffer> {
+        let our_key = self.key_ring.pub_key_ring().signature_pub_key_bytes;
+        offers
+            .values()
+            .find(|offer| {
+                &offer.id == id
+                    && offer
+                        .payload
+                        .pub_key_ring
+                        .as_ref()
+                        .map(|keys| keys.signature_pub_key_bytes == our_key)
+                        .unwrap_or(false)
+            })
+            .cloned()
+    }
+
+    pub(super) fn availability(
+        &self,
+        offer: Option<&OpenOffer>,
+        takers_price: u64,
+        takers_capabilities: &[i32],
+    ) -> AvailabilityResult {
+        let offer = match offer {
+            Some(offer) => offer,
+            None => return AvailabilityResult::OfferTaken,
+        };
+        if self
+            .trades
+            .get(&offer.id)
+            .map(|trade| trade.state() != TradeState::Failed)
+            .unwrap_or(false)
+        {
+            return AvailabilityResult::OfferTaken;
+        }
+        let offer_price = offer.display_price.base_amount();
+        if offer_price == 0 {
+            return AvailabilityResult::MarketPriceNotAvailable;
+        }
+        let deviation = (takers_price as f64 - offer_price as f64).abs() / offer_price as f64;
+        if deviation > PRICE_TOLERANCE {
+            return AvailabilityResult::PriceOutOfTolerance;
+        }
+        if ProtocolVariant::negotiate(offer.payload.protocol_version, takers_capabilities).is_err()
+        {
+            return AvailabilityResult::MissingMandatoryCapability;
+        }
+        AvailabilityResult::Available
+    }
+
+    // Spreads our trades over the registered agents
+    fn select_agent(&self, kind: DisputeAgentKind) -> Option<NodeAddress> {
+        let assigned = self.trades.values().filter_map(|trade| match kind {
+            DisputeAgentKind::Mediator => trade.mediator.as_ref(),
+            DisputeAgentKind::RefundAgent => trade.refund_agent.as_ref(),
+        });
+        self.dispute_agents.select(kind, assigned)
+    }
+
+    pub(super) fn on_availability_request(
+        &mut self,
+        sender: NodeAddress,
+        sig_pub_key: Vec<u8>,
+        request: OfferAvailabilityRequest,
+        ctx: &mut Context<Self>,
+    ) {
+        let taker_keys = match request.pub_key_ring.clone() {
+            Some(keys) if keys.signature_pub_key_bytes == sig_pub_key => keys,
+            _ => return debug!("Availability request from {} has foreign keys", sender),
+        };
+        ctx.spawn(
+            fut::wrap_future(self.offer_book.send(GetOpenOffers))
+                .map_err(|e, _, _| format!("Couldn't query the offer book: {}", e))
+                .and_then(move |offers, manager: &mut Self, _| {
+                    let id = request.offer_id.clone().into();
+                    let offer = manager.own_offer(&offers, &id);
+                    let result = manager.availability(
+                        offer.as_ref(),
+                        request.takers_trade_price as u64,
+                        &request.supported_capabilities,
+                    );
+                    info!("{} asked for offer {:?}: {:?}", sender, id, result);
+                    if result == AvailabilityResult::Available {
+                        manager
+                            .taker_capabilities
+                            .insert(sender.clone(), request.supported_capabilities);
+                    }
+                    let response = OfferAvailabilityResponse {
+                        offer_id: request.offer_id,
+                        availability_result: result as i32,
+                        supported_capabilities: LOCAL_CAPABILITIES.clone(),
+                        uid: Uuid::new_v4().to_string(),
+                        arbitrator: None,
+                        mediator: manager.select_agent(DisputeAgentKind::Mediator),
+                        refund_agent: manager.select_agent(DisputeAgentKind::RefundAgent),
+                    };
+                    manager.send_sealed_to(sender, &taker_keys, response.into())
+                })
+                .map_err(|e, _, _| warn!("Couldn't answer availability request: {}", e)),
+        );
+    }
+
+    pub(super) fn on_inputs_for_deposit_tx_request(
+        &mut self,
+        sender: NodeAddress,
+        sig_pub_key: Vec<u8>,
+        request: InputsForDepositTxRequest,
+        ctx: &mut Context<Self>,
+    ) {
+        let taker_keys = match request.taker_pub_key_ring.clone() {
+            Some(keys) if keys.signature_pub_key_bytes == sig_pub_key => keys,
+            _ => return debug!("Trade request from {} has foreign keys", sender),
+        };
+        ctx.spawn(
+            fut::wrap_future(self.offer_book.send(GetOpenOffers))
+                .map_err(|e, _, _| warn!("Couldn't query the offer book: {}", e))
+                .and_then(move |offers, manager: &mut Self, ctx| {
+                    let id: OfferId = request.trade_id.clone().into();
+                    match manager.accept_taker(&offers, sender.clone(), taker_keys, request) {
+                        Ok(funding) => manager.prepare_deposit_tx(&id, funding, ctx),
+                        Err(e) => warn!("Rejected taker {} of offer {:?}: {}", sender, id, e),
+                    }
+                    fut::ok(())
+                }),
+        );
+    }
+
+    fn accept_taker(
+        &mut self,
+        offers: &HashMap<SequencedMessageHash, OpenOffer>,
+        taker: NodeAddress,
+        taker_keys: PubKeyRing,
+        request: InputsForDepositTxRequest,
+    ) -> Result<TakerFunding, String> {
+        let id: OfferId = request.trade_id.into();
+        let offer = self
+            .own_offer(offers, &id)
+            .ok_or("Not one of our open offers")?;
+        let capabilities = self
+            .taker_capabilities
+            .remove(&taker)
+            .ok_or("The taker didn't ask for the offer's availability")?;
+        match self.availability(Some(&offer), request.trade_price as u64, &capabilities) {
+            AvailabilityResult::Available => (),
+            result => return Err(format!("Offer is not available: {:?}", result)),
+        }
+        let amount = NumberWithPrecision::new(request.trade_amount as u64, BTC_PRECISION);
+        if amount < offer.amount.min.number() || amount > offer.amount.total.number() {
+            return Err(format!(
+                "Amount {} is out of range",
+                amount.format(BTC_PRECISION)
+            ));
+        }
+        let price = NumberWithPrecision::new(
+            request.trade_price as u64,
+            offer.market.right.bisq_internal_precision(),
+        );
+        let account = self
+            .payment_accounts
+            .get(&offer.payload.maker_payment_account_id)
+            .ok_or("The payment account of the offer is gone")?;
+        let taker_account = request
+            .taker_payment_account_payload
+            .filter(|payload| payload.payment_method_id == offer.payment_method.id())
+            .ok_or("The taker's payment account doesn't match the offer")?;
+        validate_peer_account(&taker_account, offer.market.non_btc_side())?;
+        let mut trade = Trade::new(TradeRole::Maker, offer, amount, price, taker, taker_keys);
+        trade.payment_account_id = Some(account.id);
+        trade.peer_payment_account = Some(taker_account);
+        trade.peer_capabilities = capabilities;
+        trade.mediator = request.mediator_node_address;
+        trade.refund_agent = request.refund_agent_node_address;
+        trade.taker_fee_tx_id = Some(request.taker_fee_tx_id.clone());
+        trade.peer_multi_sig_pub_key = Some(request.taker_multi_sig_pub_key.clone());
+        trade.peer_payout_address = Some(request.taker_payout_address_string.clone());
+
+        info!(
+            "Offer {:?} is taken by {} with the {:?} protocol",
+            id,
+            trade.peer,
+            trade.protocol()?
+        );
+        self.insert(trade);
+        Ok(TakerFunding {
+            taker_fee_tx_id: request.taker_fee_tx_id,
+            taker_fee: request.taker_fee as u64,
+            tx_fee: request.tx_fee as u64,
+            inputs: request.raw_transaction_inputs,
+            change_output_value: request.change_output_value as u64,
+            change_output_address: Some(request.change_output_address)
+                .filter(|addr| !addr.is_empty()),
+            multi_sig_pub_key: request.taker_multi_sig_pub_key,
+            payout_address: request.taker_payout_address_string,
+        })
+    }
+
+    fn prepare_deposit_tx(&mut self, id: &OfferId, funding: TakerFunding, ctx: &mut Context<Self>) {
+        let wallet = match self.wallet.clone() {
+            Some(wallet) => wallet,
+            None => return self.fail(id, "No wallet configured to fund the trade".into()),
+        };
+        let trade = match self.trades.get(id) {
+            Some(trade) => trade.clone(),
+            None => return,
+        };
+        let id = id.clone();
+        let preparing = wallet
+            .prepare_deposit_tx(&trade, &funding)
+            .join(wallet.block_height());
+        ctx.spawn(
+            fut::wrap_future(preparing).then(move |prepared, manager: &mut Self, ctx| {
+                match prepared {
+                    Ok((deposit, height)) => {
+                        manager.send_inputs_for_deposit_tx_response(&id, deposit, height, ctx)
+                    }
+                    Err(e) => manager.fail(&id, format!("Couldn't prepare the deposit tx: {}", e)),
+                }
+                fut::ok(())
+            }),
+        );
+    }
+
+    fn send_inputs_for_deposit_tx_response(
+        &mut self,
+        id: &OfferId,
+        deposit: MakerDeposit,
+        height: u32,
+        ctx: &mut Context<Self>,
+    ) {
+        let network = self.network;
+        let lock_time = match self.trades.get_mut(id) {
+            Some(trade) => {
+                trade.multi_sig_pub_key = Some(deposit.multi_sig_pub_key.clone());
+                trade.payout_address = Some(deposit.payout_address.clone());
+                let lock_time = height + tx::lock_time_delay(trade, network);
+                trade.lock_time = Some(lock_time);
+                lock_time
+            }
+            None => return,
+        };
+        if self
+            .advance(id, TradeState::MakerSentPublishDepositTxRequest)
+            .is_none()
+        {
+            return;
+        }
+        let account = match self.payment_account(id) {
+            Ok(account) => account,
+            Err(e) => return self.fail(id, e),
+        };
+        let signature = match self.key_ring.sign(&deposit.prepared_deposit_tx) {
+            Ok(signature) => signature,
+            Err(e) => return self.fail(id, format!("Couldn't sign the deposit tx: {}", e)),
+        };
+        let response = InputsForDepositTxResponse {
+            trade_id: id.clone().into(),
+            maker_payout_address_string: deposit.payout_address,
+            prepared_deposit_tx: deposit.prepared_deposit_tx,
+            maker_inputs: deposit.inputs,
+            maker_multi_sig_pub_key: deposit.multi_sig_pub_key,
+            maker_payment_account_payload: Some(account.payload()),
+            maker_account_id: account.id,
+            sender_node_address: self.status.local_addr(),
+            uid: Uuid::new_v4().to_string(),
+            account_age_witness_signature_of_prepared_deposit_tx: signature,
+            current_date: now_millis(),
+            lock_time: lock_time.into(),
+            ..Default::default()
+        };
+        self.send_sealed(id, response, ctx);
+    }
+
+    // The deposit is only published once both signed the delayed payout tx,
+    // which is the last resort to get the funds out of it.
+    pub(super) fn on_deposit_tx_signed(
+        &mut self,
+        id: &OfferId,
+        msg: DepositTxMessage,
+        ctx: &mut Context<Self>,
+    ) {
+        if !self.in_state(id, TradeState::MakerSentPublishDepositTxRequest) {
+            return;
+        }
+        self.send_ack(id, msg.uid, "DepositTxMessage", ctx);
+        let network = self.network;
+        let delayed_payout_tx = match self.trades.get_mut(id) {
+            Some(trade) => {
+                trade.deposit_tx = Some(msg.deposit_tx);
+                delayed_payout(trade, network)
+            }
+            None => return,
+        };
+        let delayed_payout_tx = match delayed_payout_tx {
+            Ok((delayed_payout_tx, _, _)) => consensus::serialize(&delayed_payout_tx),
+            Err(e) => return self.fail(id, format!("Couldn't build the delayed payout tx: {}", e)),
+        };
+        if self
+            .advance(id, TradeState::MakerSentDelayedPayoutTxSignatureRequest)
+            .is_none()
+        {
+            return;
+        }
+        let request = DelayedPayoutTxSignatureRequest {
+            uid: Uuid::new_v4().to_string(),
+            trade_id: id.clone().into(),
+            sender_node_address: self.status.local_addr(),
+            delayed_payout_tx,
+        };
+        self.send_sealed(id, request, ctx);
+    }
+
+    pub(super) fn on_delayed_payout_tx_signed(
+        &mut self,
+        id: &OfferId,
+        response: DelayedPayoutTxSignatureResponse,
+        ctx: &mut Context<Self>,
+    ) {
+        if !self.in_state(id, TradeState::MakerSentDelayedPayoutTxSignatureRequest) {
+            return;
+        }
+        self.send_ack(id, response.uid, "DelayedPayoutTxSignatureResponse", ctx);
+        let wallet = match self.wallet.clone() {
+            Some(wallet) => wallet,
+            None => return self.fail(id, "No wallet configured to publish the deposit tx".into()),
+        };
+        let trade = match self.trades.get(id) {
+            Some(trade) => trade.clone(),
+            None => return,
+        };
+        let (mut delayed_payout_tx, script, deposit_value) =
+            match delayed_payout(&trade, self.network) {
+                Ok(delayed_payout) => delayed_payout,
+                Err(e) => {
+                    return self.fail(id, format!("Couldn't build the delayed payout tx: {}", e))
+                }
+            };
+        let taker_signature = response.delayed_payout_tx_signature;
+        let sighash = tx::payout_sighash(&delayed_payout_tx, &script, deposit_value);
+        let valid = match trade.peer_multi_sig_pub_key.as_ref() {
+            Some(taker_pub_key) => tx::verify_signature(
+                &Secp256k1::verification_only(),
+                &sighash,
+                &taker_signature,
+                taker_pub_key,
+            ),
+            None => false,
+        };
+        if !valid {
+            return self.fail(id, "Taker's delayed payout signature is invalid".into());
+        }
+        let deposit_tx = trade.deposit_tx.clone().unwrap_or_default();
+        let signing = wallet.sign_payout_tx(&trade, &consensus::serialize(&delayed_payout_tx));
+        let id = id.clone();
+        ctx.spawn(
+            fut::wrap_future(
+                signing
+                    .map_err(|e| format!("Couldn't sign the delayed payout tx: {}", e))
+                    .and_then(move |maker_signature| {
+                        let (buyer_signature, seller_signature) =
+                            tx::buyer_and_seller(&trade, &maker_signature, &taker_signature);
+                        tx::finalize_payout(
+                            &mut delayed_payout_tx,
+                            &script,
+                            buyer_signature,
+                            seller_signature,
+                        );
+                        let delayed_payout_tx = consensus::serialize(&delayed_payout_tx);
+                        wallet
+                            .publish_deposit_tx(&trade, &deposit_tx)
+                            .map_err(|e| format!("Couldn't publish the deposit tx: {}", e))
+                            .map(move |deposit_tx| (deposit_tx, delayed_payout_tx))
+                    }),
+            )
+            .then(move |published, manager: &mut Self, ctx| {
+                match published {
+                    Ok((deposit_tx, delayed_payout_tx)) => {
+                        if let Some(trade) = manager.trades.get_mut(&id) {
+                            trade.delayed_payout_tx = Some(delayed_payout_tx);
+                        }
+                        manager.deposit_tx_published(&id, deposit_tx, ctx)
+                    }
+                    Err(e) => manager.fail(&id, e),
+                }
+                fut::ok(())
+            }),
+        );
+    }
+
+    fn deposit_tx_published(&mut self, id: &OfferId, deposit_tx: Vec<u8>, ctx: &mut Context<Self>) {
+        // Only witnesses of segwit inputs keep the txid the delayed payout tx spends
+        let signed_txid = self
+            .trades
+            .get(id)
+            .and_then(|trade| trade.deposit_tx.as_ref())
+            .and_then(|signed| txid(signed).ok());
+        let txid = match txid(&deposit_tx) {
+            Ok(txid) => txid,
+            Err(e) => {
+                return self.fail(id, format!("Wallet returned an invalid deposit tx: {}", e))
+            }
+        };
+        let txid_changed = signed_txid.as_ref() != Some(&txid);
+        if let Some(trade) = self.trades.get_mut(id) {
+            trade.deposit_tx_id = Some(txid);
+            trade.deposit_tx = Some(deposit_tx.clone());
+        }
+        let delayed_payout_tx = match self.advance(id, TradeState::DepositPublished) {
+            Some(trade) => trade.delayed_payout_tx.clone().unwrap_or_default(),
+            None => return,
+        };
+        let msg = DepositTxAndDelayedPayoutTxMessage {
+            uid: Uuid::new_v4().to_string(),
+            trade_id: id.clone().into(),
+            sender_node_address: self.status.local_addr(),
+            deposit_tx,
+            delayed_payout_tx,
+        };
+        self.send_sealed(id, msg, ctx);
+        if txid_changed {
+            self.fail(
+                id,
+                "Our inputs changed the deposit txid, the delayed payout tx is void".into(),
+            );
+        }
+    }
+}
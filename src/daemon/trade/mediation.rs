@@ -0,0 +1,303 @@
+use super::{payout::payout, txid, TradeManager};
+use crate::{
+    bisq::payload::*,
+    domain::{
+        dispute::DisputeAgentKind,
+        offer::OfferId,
+        trade::{message::AcceptMediation, wallet::TradeWallet, MediatedPayout, Trade, TradeState},
+    },
+    prelude::{fut::Either, *},
+    wallet::tx,
+};
+use bitcoin::{consensus, secp256k1::Secp256k1};
+use std::{convert::TryFrom, sync::Arc};
+use uuid::Uuid;
+
+impl Handler<AcceptMediation> for TradeManager {
+    type Result = ResponseActFuture<Self, Trade, String>;
+    fn handle(
+        &mut self,
+        AcceptMediation { trade_id }: AcceptMediation,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        match self.accept_mediation(&trade_id) {
+            Ok(accepting) => Box::new(accepting.and_then(move |_, manager: &mut Self, _| {
+                fut::result(
+                    manager
+                        .trades
+                        .get(&trade_id)
+                        .cloned()
+                        .ok_or_else(|| "Trade is gone".to_string()),
+                )
+            })),
+            Err(e) => Box::new(fut::err(e)),
+        }
+    }
+}
+
+// Mediation is only possible while the deposit is locked
+fn mediated(state: TradeState) -> bool {
+    state.funds_locked() || state == TradeState::Disputed
+}
+
+// Amounts are signed in the protobuf, a negative payout is rejected
+pub(super) fn disputed_payout(result: &DisputeResult) -> Result<MediatedPayout, String> {
+    let amount = |amount: i64| {
+        u64::try_from(amount).map_err(|_| format!("Negative payout of {} sat", amount))
+    };
+    Ok(MediatedPayout {
+        buyer_payout: amount(result.buyer_payout_amount)?,
+        seller_payout: amount(result.seller_payout_amount)?,
+    })
+}
+
+fn amounts(payout: MediatedPayout) -> (u64, u64) {
+    (payout.buyer_payout, payout.seller_payout)
+}
+
+impl TradeManager {
+    pub(super) fn on_mediation_result(
+        &mut self,
+        sig_pub_key: Vec<u8>,
+        msg: DisputeResultMessage,
+        ctx: &mut Context<Self>,
+    ) {
+        let result = match msg.dispute_result {
+            Some(result) => result,
+            None => return,
+        };
+        let id: OfferId = result.trade_id.clone().into();
+        // Only the mediator of the trade may propose a payout
+        let mediator = match self.trade_agent(&id, DisputeAgentKind::Mediator, &sig_pub_key) {
+            Some(mediator) => mediator,
+            None => return debug!("Mediation result for {:?} isn't from its mediator", id),
+        };
+        let payout = match disputed_payout(&result) {
+            Ok(payout) => payout,
+            Err(e) => return warn!("Invalid mediation result for {:?}: {}", id, e),
+        };
+        let state = match self.trades.get_mut(&id) {
+            Some(trade) if mediated(trade.state()) => {
+                trade.mediated_payout = Some(payout);
+                // A new proposal invalidates the signatures of the previous one
+                trade.mediated_payout_signature = None;
+                trade.peer_mediated_payout_signature = None;
+                trade.state()
+            }
+            _ => return,
+        };
+        info!(
+            "Mediator proposed a payout of {} sat to the buyer and {} sat to the seller for trade {:?}",
+            result.buyer_payout_amount, result.seller_payout_amount, id
+        );
+        if state == TradeState::Disputed {
+            self.persist();
+        } else if self.advance(&id, TradeState::Disputed).is_none() {
+            return;
+        }
+        self.send_agent_ack(&mediator, "MEDIATION_MESSAGE", &id, msg.uid, ctx);
+    }
+
+    fn accept_mediation(
+        &self,
+        id: &OfferId,
+    ) -> Result<Box<dyn ActorFuture<Item = (), Error = String, Actor = Self>>, String> {
+        let wallet = self.wallet.clone().ok_or("No wallet configured")?;
+        let trade = self
+            .trades
+            .get(id)
+            .ok_or_else(|| format!("Unknown trade {}", String::from(id.clone())))?;
+        if !mediated(trade.state()) {
+            return Err(format!(
+                "Can't accept a mediation in state {}",
+                trade.state()
+            ));
+        }
+        let proposal = trade
+            .mediated_payout
+            .ok_or("The mediator hasn't proposed a payout yet")?;
+        if trade.mediated_payout_signature.is_some() {
+            return Err("The mediated payout was already accepted".into());
+        }
+        let (payout_tx, _, _) = payout(trade, amounts(proposal))?;
+        let id = id.clone();
+        Ok(Box::new(
+            fut::wrap_future(wallet.sign_payout_tx(trade, &consensus::serialize(&payout_tx)))
+                .map_err(|e, _, _| format!("Couldn't sign the mediated payout tx: {}", e))
+                .and_then(move |signature, manager: &mut Self, ctx| {
+                    manager.mediation_accepted(&id, wallet, signature, ctx)
+                }),
+        ))
+    }
+
+    // Whoever accepts last publishes the payout
+    fn mediation_accepted(
+        &mut self,
+        id: &OfferId,
+        wallet: Arc<dyn TradeWallet>,
+        signature: Vec<u8>,
+        ctx: &mut Context<Self>,
+    ) -> impl ActorFuture<Item = (), Error = String, Actor = Self> {
+        let peer_signed = match self.trades.get_mut(id) {
+            Some(trade) => {
+                trade.mediated_payout_signature = Some(signature.clone());
+                trade.peer_mediated_payout_signature.is_some()
+            }
+            None => return Either::A(fut::err("Trade is gone".to_string())),
+        };
+        self.persist();
+        info!("Accepted the mediated payout of trade {:?}", id);
+        if peer_signed {
+            return Either::B(self.publish_mediated_payout(id, wallet));
+        }
+        let msg = MediatedPayoutTxSignatureMessage {
+            uid: Uuid::new_v4().to_string(),
+            trade_id: id.clone().into(),
+            tx_signature: signature,
+            sender_node_address: self.status.local_addr(),
+        };
+        self.send_sealed(id, msg, ctx);
+        Either::A(fut::ok(()))
+    }
+
+    fn publish_mediated_payout(
+        &self,
+        id: &OfferId,
+        wallet: Arc<dyn TradeWallet>,
+    ) -> impl ActorFuture<Item = (), Error = String, Actor = Self> {
+        let trade = match self.trades.get(id) {
+            Some(trade) => trade,
+            None => return Either::A(fut::err("Trade is gone".to_string())),
+        };
+        let payout_tx = trade
+            .mediated_payout
+            .ok_or_else(|| "The mediated payout is missing".to_string())
+            .and_then(|proposal| payout(trade, amounts(proposal)))
+            .and_then(|(mut payout_tx, script, deposit_value)| {
+                let own = trade
+                    .mediated_payout_signature
+                    .as_ref()
+                    .ok_or("We haven't signed the mediated payout")?;
+                let peer = trade
+                    .peer_mediated_payout_signature
+                    .as_ref()
+                    .ok_or("The peer hasn't signed the mediated payout")?;
+                let peer_key = trade
+                    .peer_multi_sig_pub_key
+                    .as_ref()
+                    .ok_or("The peer's multisig key is missing")?;
+                let sighash = tx::payout_sighash(&payout_tx, &script, deposit_value);
+                if !tx::verify_signature(&Secp256k1::verification_only(), &sighash, peer, peer_key)
+                {
+                    return Err("The peer's signature of the mediated payout is invalid".into());
+                }
+                let (buyer_signature, seller_signature) = tx::buyer_and_seller(trade, own, peer);
+                tx::finalize_payout(&mut payout_tx, &script, buyer_signature, seller_signature);
+                Ok(consensus::serialize(&payout_tx))
+            });
+        let payout_tx = match payout_tx {
+            Ok(payout_tx) => payout_tx,
+            Err(e) => return Either::A(fut::err(e)),
+        };
+        let id = id.clone();
+        Either::B(
+            fut::wrap_future(wallet.publish_tx(&payout_tx))
+                .map_err(|e, _, _| format!("Couldn't publish the mediated payout tx: {}", e))
+                .map(move |_, manager: &mut Self, ctx| {
+                    manager.mediated_payout_published(&id, payout_tx, ctx)
+                }),
+        )
+    }
+
+    fn mediated_payout_published(
+        &mut self,
+        id: &OfferId,
+        payout_tx: Vec<u8>,
+        ctx: &mut Context<Self>,
+    ) {
+        if let Some(trade) = self.trades.get_mut(id) {
+            trade.payout_tx_id = txid(&payout_tx).ok();
+        }
+        if self.advance(id, TradeState::PayoutPublished).is_none() {
+            return;
+        }
+        let msg = MediatedPayoutTxPublishedMessage {
+            trade_id: id.clone().into(),
+            payout_tx,
+            sender_node_address: self.status.local_addr(),
+            uid: Uuid::new_v4().to_string(),
+        };
+        self.send_sealed(id, msg, ctx);
+    }
+
+    pub(super) fn on_mediated_payout_signature(
+        &mut self,
+        id: &OfferId,
+        msg: MediatedPayoutTxSignatureMessage,
+        ctx: &mut Context<Self>,
+    ) {
+        // The peer may accept before the mediator's proposal reached us,
+        // its signature is checked once we accept as well
+        let we_signed = match self.trades.get_mut(id) {
+            Some(trade) if mediated(trade.state()) => {
+                trade.peer_mediated_payout_signature = Some(msg.tx_signature);
+                trade.mediated_payout_signature.is_some()
+            }
+            _ => return,
+        };
+        self.persist();
+        info!("Peer accepted the mediated payout of trade {:?}", id);
+        self.send_ack(id, msg.uid, "MediatedPayoutTxSignatureMessage", ctx);
+        if let (true, Some(wallet)) = (we_signed, self.wallet.clone()) {
+            let id = id.clone();
+            ctx.spawn(
+                self.publish_mediated_payout(&id, wallet)
+                    .map_err(move |e, _, _| warn!("Trade {:?}: {}", id, e)),
+            );
+        }
+    }
+
+    pub(super) fn on_mediated_payout_published(
+        &mut self,
+        id: &OfferId,
+        msg: MediatedPayoutTxPublishedMessage,
+        ctx: &mut Context<Self>,
+    ) {
+        let expected = match self.trades.get(id) {
+            Some(trade) if mediated(trade.state()) => trade
+                .mediated_payout
+                .ok_or_else(|| "No mediated payout".to_string())
+                .and_then(|proposal| payout(trade, amounts(proposal)))
+                .map(|(payout_tx, _, _)| payout_tx.txid().to_string()),
+            _ => return,
+        };
+        // Witnesses don't change the txid, so it must be the tx we agreed on
+        let txid = match (expected, txid(&msg.payout_tx)) {
+            (Ok(expected), Ok(txid)) if expected == txid => txid,
+            _ => return warn!("Peer of trade {:?} published an unexpected payout", id),
+        };
+        if let Some(trade) = self.trades.get_mut(id) {
+            trade.payout_tx_id = Some(txid);
+        }
+        if self.advance(id, TradeState::PayoutPublished).is_some() {
+            self.send_ack(id, msg.uid, "MediatedPayoutTxPublishedMessage", ctx);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_negative_payouts() {
+        let mut result = DisputeResult {
+            buyer_payout_amount: 90_000,
+            seller_payout_amount: 10_000,
+            ..Default::default()
+        };
+        assert_eq!(amounts(disputed_payout(&result).unwrap()), (90_000, 10_000));
+        result.seller_payout_amount = -1;
+        assert!(disputed_payout(&result).is_err());
+    }
+}
@@ -0,0 +1,606 @@
+mod chat;
+mod maker;
+mod mediation;
+mod offers;
+mod payout;
+mod refund;
+mod store;
+mod taker;
+mod witness;
+
+pub use store::{OfferStore, TradeStore};
+
+use crate::{
+    bisq::{constants::BaseCurrencyNetwork, crypto::KeyRing, payload::*},
+    daemon::{
+        data_router::Publish,
+        direct_messages::{self, DirectMessage, DirectMessages, RegisterHandler, SendDirect},
+        identity::PreviousIdentity,
+    },
+    domain::{
+        alert::Alerts,
+        amount::NumberWithPrecision,
+        dispute::{DisputeAgent, DisputeAgentKind, DisputeAgents},
+        fees::FeeRates,
+        format::BTC_PRECISION,
+        offer::{OfferBook, OfferId},
+        payment_account::{PaymentAccount, PaymentAccounts, SignedWitnesses},
+        trade::{
+            message::{FeeInfo, GetFees, GetTrades, GetWallet, WalletInfo},
+            wallet::TradeWallet,
+            Trade, TradeRole, TradeState,
+        },
+    },
+    events::{Event, Events},
+    p2p::{Broadcaster, Status},
+    prelude::{sync::oneshot, *},
+    scheduler::{Schedule, Task},
+};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use uuid::Uuid;
+
+/// The maker's signature key and where to answer an availability check
+type AvailabilityCheck = (Vec<u8>, oneshot::Sender<AvailabilityResult>);
+
+/// Runs the trade protocol with other peers, as taker of offers in the
+/// offer book and as maker of our own offers. All trade messages are
+/// exchanged as `PrefixedSealedAndSignedMessage`s, encrypted for the
+/// key ring of the receiver. Trades are persisted after every transition
+/// and resumed after a restart.
+pub struct TradeManager {
+    network: BaseCurrencyNetwork,
+    key_ring: Arc<KeyRing>,
+    /// Set after `risq identity rotate` until our offers were moved to the
+    /// new identity
+    previous_identity: Option<PreviousIdentity>,
+    offer_book: Addr<OfferBook>,
+    wallet: Option<Arc<dyn TradeWallet>>,
+    status: Status,
+    direct: Addr<DirectMessages>,
+    /// What takers announced in their availability requests, until they
+    /// send the inputs for the deposit tx
+    taker_capabilities: HashMap<NodeAddress, Vec<i32>>,
+    /// Availability checks waiting for the maker
+    availability_checks: HashMap<OfferId, Vec<AvailabilityCheck>>,
+    store: TradeStore,
+    trades: HashMap<OfferId, Trade>,
+    dispute_agents: DisputeAgents,
+    payment_accounts: PaymentAccounts,
+    fee_rates: FeeRates,
+    broadcaster: Addr<Broadcaster>,
+    data_router: Recipient<Publish>,
+    /// Our own offers by id
+    offers: HashMap<OfferId, offers::MyOffer>,
+    offer_store: OfferStore,
+    signed_witnesses: SignedWitnesses,
+    alerts: Alerts,
+    events: Events,
+}
+impl Actor for TradeManager {
+    type Context = Context<Self>;
+    fn started(&mut self, ctx: &mut Self::Context) {
+        let ids: Vec<OfferId> = self.trades.keys().cloned().collect();
+        for id in ids {
+            self.resume(&id, ctx);
+        }
+        ctx.schedule(TRADE_TIMEOUTS, |manager, _| manager.check_timeouts());
+        ctx.schedule(PAYOUT_DETECTION, |manager, ctx| manager.detect_payouts(ctx));
+        self.direct
+            .do_send(RegisterHandler(TRADE_MESSAGES, ctx.address().recipient()));
+        ctx.schedule(OFFER_PUBLISHING, |manager, _| manager.publish_offers());
+    }
+}
+
+const TRADE_TIMEOUTS: Task = Task::new("trade_timeouts", Duration::from_secs(10));
+const PAYOUT_DETECTION: Task = Task::new("payout_detection", Duration::from_secs(60));
+const OFFER_PUBLISHING: Task = Task::new("offer_publishing", Duration::from_secs(30));
+
+/// The messages of the trade protocol, disputes and trade chats, sealed for us
+const TRADE_MESSAGES: &[&str] = &[
+    "OfferAvailabilityRequest",
+    "OfferAvailabilityResponse",
+    "InputsForDepositTxRequest",
+    "InputsForDepositTxResponse",
+    "DepositTxMessage",
+    "DelayedPayoutTxSignatureRequest",
+    "DelayedPayoutTxSignatureResponse",
+    "DepositTxAndDelayedPayoutTxMessage",
+    "CounterCurrencyTransferStartedMessage",
+    "PayoutTxPublishedMessage",
+    "PeerPublishedDelayedPayoutTxMessage",
+    "DisputeResultMessage",
+    "MediatedPayoutTxSignatureMessage",
+    "MediatedPayoutTxPublishedMessage",
+    "ChatMessage",
+    "AckMessage",
+];
+
+impl TradeManager {
+    pub fn start(
+        network: BaseCurrencyNetwork,
+        key_ring: Arc<KeyRing>,
+        previous_identity: Option<PreviousIdentity>,
+        offer_book: Addr<OfferBook>,
+        wallet: Option<Arc<dyn TradeWallet>>,
+        status: Status,
+        direct: Addr<DirectMessages>,
+        store: TradeStore,
+        dispute_agents: DisputeAgents,
+        payment_accounts: PaymentAccounts,
+        fee_rates: FeeRates,
+        broadcaster: Addr<Broadcaster>,
+        data_router: Recipient<Publish>,
+        offer_store: OfferStore,
+        signed_witnesses: SignedWitnesses,
+        alerts: Alerts,
+        events: Events,
+    ) -> Addr<Self> {
+        let trades = store
+            .load()
+            .expect("Couldn't load trades")
+            .into_iter()
+            .map(|trade| (trade.id().clone(), trade))
+            .collect();
+        let offers = offer_store
+            .load()
+            .expect("Couldn't load offers")
+            .into_iter()
+            .filter_map(offers::MyOffer::restore)
+            .collect();
+        Self {
+            network,
+            key_ring,
+            previous_identity,
+            offer_book,
+            wallet,
+            status,
+            direct,
+            taker_capabilities: HashMap::new(),
+            availability_checks: HashMap::new(),
+            store,
+            trades,
+            dispute_agents,
+            payment_accounts,
+            fee_rates,
+            broadcaster,
+            data_router,
+            offers,
+            offer_store,
+            signed_witnesses,
+            alerts,
+            events,
+        }
+        .start()
+    }
+
+    fn persist(&self) {
+        if let Err(e) = self.store.save(self.trades.values()) {
+            error!("Couldn't persist trades: {}", e);
+        }
+    }
+
+    fn insert(&mut self, trade: Trade) {
+        self.events.publish(Event::trade_changed(&trade));
+        self.trades.insert(trade.id().clone(), trade);
+        self.persist();
+    }
+
+    fn payment_account(&self, id: &OfferId) -> Result<PaymentAccount, String> {
+        self.trades
+            .get(id)
+            .and_then(|trade| trade.payment_account_id.as_ref())
+            .and_then(|account_id| self.payment_accounts.get(account_id))
+            .ok_or_else(|| "The payment account of the trade is gone".into())
+    }
+
+    fn fail(&mut self, id: &OfferId, error: String) {
+        if let Some(trade) = self.trades.get_mut(id) {
+            trade.fail(error);
+            self.events.publish(Event::trade_changed(trade));
+            self.persist();
+        }
+    }
+
+    fn sign_entry(
+        &self,
+        payload: StoragePayload,
+        sequence_number: i32,
+    ) -> Result<ProtectedStorageEntry, String> {
+        direct_messages::sign_entry(&self.key_ring, payload, sequence_number)
+    }
+
+    fn check_timeouts(&mut self) {
+        let now = SystemTime::now();
+        let mut timed_out = false;
+        for trade in self.trades.values_mut() {
+            if trade.check_timeout(now) {
+                self.events.publish(Event::trade_changed(trade));
+                timed_out = true;
+            }
+        }
+        if timed_out {
+            self.persist();
+        }
+    }
+
+    // Payouts the peer didn't tell us about, eg. signed outside of risq, are found by the wallet.
+    fn detect_payouts(&mut self, ctx: &mut Context<Self>) {
+        let wallet = match self.wallet.clone() {
+            Some(wallet) => wallet,
+            None => return,
+        };
+        for trade in self.trades.values().filter(|trade| {
+            trade.deposit_tx_id.is_some()
+                && (trade.state() == TradeState::DepositPublished
+                    || trade.state() == TradeState::PaymentStarted
+                    || trade.state() == TradeState::Disputed)
+        }) {
+            let id = trade.id().clone();
+            ctx.spawn(fut::wrap_future(wallet.find_payout(trade)).then(
+                move |payout, manager: &mut Self, _| {
+                    match payout {
+                        Ok(Some(txid)) if manager.is_delayed_payout(&id, &txid) => {
+                            info!("Delayed payout {} of trade {:?} was published", txid, id);
+                            manager.advance(&id, TradeState::DelayedPayoutPublished);
+                        }
+                        Ok(Some(txid)) => {
+                            info!("Payout {} of trade {:?} was published", txid, id);
+                            if let Some(trade) = manager.trades.get_mut(&id) {
+                                trade.payout_tx_id = Some(txid);
+                            }
+                            manager.advance(&id, TradeState::PayoutPublished);
+                        }
+                        Ok(None) => (),
+                        Err(e) => warn!("Couldn't look for the payout of {:?}: {}", id, e),
+                    }
+                    fut::ok(())
+                },
+            ));
+        }
+    }
+
+    // Steps that were waiting for the peer continue when its next message arrives.
+    // Steps that were waiting for our wallet are repeated, as long as that can't
+    // spend funds twice.
+    fn resume(&mut self, id: &OfferId, ctx: &mut Context<Self>) {
+        let (role, state) = match self.trades.get(id) {
+            Some(trade) => (trade.role, trade.state()),
+            None => return,
+        };
+        match (role, state) {
+            (_, TradeState::Preparation) => {
+                self.fail(id, "Interrupted before any funds were committed".into())
+            }
+            (TradeRole::Taker, TradeState::TakerReceivedPublishDepositTxRequest) => {
+                self.sign_deposit_tx(id, ctx)
+            }
+            (_, TradeState::Failed)
+            | (_, TradeState::PayoutPublished)
+            | (_, TradeState::DelayedPayoutPublished) => (),
+            (_, state) => info!("Resuming trade {:?} in state {}", id, state),
+        }
+    }
+
+    fn in_state(&self, id: &OfferId, state: TradeState) -> bool {
+        self.trades.get(id).map(Trade::state) == Some(state)
+    }
+
+    fn advance(&mut self, id: &OfferId, next: TradeState) -> Option<&Trade> {
+        let result = self.trades.get_mut(id)?.advance(next);
+        if let Err(error) = result {
+            self.fail(id, error);
+            return None;
+        }
+        self.persist();
+        let trade = self.trades.get(id)?;
+        self.events.publish(Event::trade_changed(trade));
+        Some(trade)
+    }
+
+    fn send_sealed<M>(&mut self, id: &OfferId, msg: M, ctx: &mut Context<Self>)
+    where
+        M: Into<network_envelope::Message>,
+    {
+        let (peer, peer_keys) = match self.trades.get(id) {
+            Some(trade) => (trade.peer.clone(), trade.peer_pub_key_ring.clone()),
+            None => return,
+        };
+        let id = id.clone();
+        let sending = self.send_sealed_to(peer, &peer_keys, msg.into());
+        ctx.spawn(sending.then(move |result, manager: &mut Self, _| {
+            if let Err(e) = result {
+                manager.fail(&id, e);
+            }
+            fut::ok(())
+        }));
+    }
+
+    fn send_sealed_to(
+        &self,
+        peer: NodeAddress,
+        peer_keys: &PubKeyRing,
+        msg: network_envelope::Message,
+    ) -> impl ActorFuture<Item = (), Error = String, Actor = Self> {
+        fut::wrap_future(self.direct.send(SendDirect {
+            receiver: peer,
+            receiver_keys: peer_keys.clone(),
+            msg,
+            ack_uid: None,
+            mailbox: false,
+        }))
+        .then(|sent, _, _| {
+            fut::result(match sent {
+                Ok(sent) => sent.map(|_| ()),
+                Err(e) => Err(format!("Couldn't send direct message: {}", e)),
+            })
+        })
+    }
+
+    fn send_ack(
+        &mut self,
+        id: &OfferId,
+        source_uid: String,
+        source_msg_class_name: &str,
+        ctx: &mut Context<Self>,
+    ) {
+        let ack = AckMessage {
+            uid: Uuid::new_v4().to_string(),
+            sender_node_address: self.status.local_addr(),
+            source_type: "TRADE_MESSAGE".into(),
+            source_msg_class_name: source_msg_class_name.into(),
+            source_uid,
+            source_id: id.clone().into(),
+            success: true,
+            error_message: String::new(),
+        };
+        self.send_sealed(id, ack, ctx);
+    }
+
+    // Only the peer we are trading with may advance a trade.
+    fn peer_trade(&self, id: &OfferId, sig_pub_key: &[u8]) -> Option<&Trade> {
+        self.trades
+            .get(id)
+            .filter(|trade| trade.peer_pub_key_ring.signature_pub_key_bytes == sig_pub_key)
+    }
+
+    fn trade_with(&self, id: &OfferId, role: TradeRole, sig_pub_key: &[u8]) -> Option<&Trade> {
+        self.peer_trade(id, sig_pub_key)
+            .filter(|trade| trade.role == role)
+    }
+
+    // Dispute results are only accepted from the agent assigned to the trade.
+    fn trade_agent(
+        &self,
+        id: &OfferId,
+        kind: DisputeAgentKind,
+        sig_pub_key: &[u8],
+    ) -> Option<DisputeAgent> {
+        let trade = self.trades.get(id)?;
+        let address = match kind {
+            DisputeAgentKind::Mediator => trade.mediator.as_ref(),
+            DisputeAgentKind::RefundAgent => trade.refund_agent.as_ref(),
+        }?;
+        self.dispute_agents
+            .get(kind, address)
+            .filter(|agent| agent.pub_key_ring.signature_pub_key_bytes == sig_pub_key)
+    }
+
+    fn send_agent_ack(
+        &mut self,
+        agent: &DisputeAgent,
+        source_type: &str,
+        id: &OfferId,
+        source_uid: String,
+        ctx: &mut Context<Self>,
+    ) {
+        let ack = AckMessage {
+            uid: Uuid::new_v4().to_string(),
+            sender_node_address: self.status.local_addr(),
+            source_type: source_type.into(),
+            source_msg_class_name: "DisputeResultMessage".into(),
+            source_uid,
+            source_id: id.clone().into(),
+            success: true,
+            error_message: String::new(),
+        };
+        let sending = self.send_sealed_to(agent.address.clone(), &agent.pub_key_ring, ack.into());
+        ctx.spawn(
+            sending.map_err(|e, _, _| warn!("Couldn't acknowledge the dispute result: {}", e)),
+        );
+    }
+
+    fn on_message(
+        &mut self,
+        sender: NodeAddress,
+        sig_pub_key: Vec<u8>,
+        msg: network_envelope::Message,
+        ctx: &mut Context<Self>,
+    ) {
+        match msg {
+            network_envelope::Message::OfferAvailabilityRequest(request) => {
+                self.on_availability_request(sender, sig_pub_key, request, ctx)
+            }
+            network_envelope::Message::InputsForDepositTxRequest(request) => {
+                self.on_inputs_for_deposit_tx_request(sender, sig_pub_key, request, ctx)
+            }
+            network_envelope::Message::DepositTxMessage(msg) => {
+                let id = msg.trade_id.clone().into();
+                if self
+                    .trade_with(&id, TradeRole::Maker, &sig_pub_key)
+                    .is_some()
+                {
+                    self.on_deposit_tx_signed(&id, msg, ctx)
+                }
+            }
+            network_envelope::Message::OfferAvailabilityResponse(response) => {
+                let id = response.offer_id.clone().into();
+                self.on_availability_checked(&id, &sig_pub_key, &response);
+                if self
+                    .trade_with(&id, TradeRole::Taker, &sig_pub_key)
+                    .is_some()
+                {
+                    self.on_availability_response(&id, response, ctx)
+                }
+            }
+            network_envelope::Message::InputsForDepositTxResponse(response) => {
+                let id = response.trade_id.clone().into();
+                if self
+                    .trade_with(&id, TradeRole::Taker, &sig_pub_key)
+                    .is_some()
+                {
+                    self.on_inputs_for_deposit_tx_response(&id, response, ctx)
+                }
+            }
+            network_envelope::Message::DelayedPayoutTxSignatureRequest(request) => {
+                let id = request.trade_id.clone().into();
+                if self
+                    .trade_with(&id, TradeRole::Taker, &sig_pub_key)
+                    .is_some()
+                {
+                    self.on_delayed_payout_tx_signature_request(&id, request, ctx)
+                }
+            }
+            network_envelope::Message::DelayedPayoutTxSignatureResponse(response) => {
+                let id = response.trade_id.clone().into();
+                if self
+                    .trade_with(&id, TradeRole::Maker, &sig_pub_key)
+                    .is_some()
+                {
+                    self.on_delayed_payout_tx_signed(&id, response, ctx)
+                }
+            }
+            network_envelope::Message::DepositTxAndDelayedPayoutTxMessage(msg) => {
+                let id = msg.trade_id.clone().into();
+                if self
+                    .trade_with(&id, TradeRole::Taker, &sig_pub_key)
+                    .is_some()
+                {
+                    self.on_deposit_tx_published(&id, msg, ctx)
+                }
+            }
+            network_envelope::Message::CounterCurrencyTransferStartedMessage(msg) => {
+                let id = msg.trade_id.clone().into();
+                if self.peer_trade(&id, &sig_pub_key).is_some() {
+                    self.on_payment_started(&id, msg, ctx)
+                }
+            }
+            network_envelope::Message::PayoutTxPublishedMessage(msg) => {
+                let id = msg.trade_id.clone().into();
+                if self.peer_trade(&id, &sig_pub_key).is_some() {
+                    self.on_payout_published(&id, msg, ctx)
+                }
+            }
+            network_envelope::Message::PeerPublishedDelayedPayoutTxMessage(msg) => {
+                let id = msg.trade_id.clone().into();
+                if self.peer_trade(&id, &sig_pub_key).is_some() {
+                    self.on_delayed_payout_published(&id, msg, ctx)
+                }
+            }
+            network_envelope::Message::DisputeResultMessage(msg) => {
+                match SupportType::from_i32(msg.r#type) {
+                    Some(SupportType::Mediation) => self.on_mediation_result(sig_pub_key, msg, ctx),
+                    Some(SupportType::Refund) => self.on_refund_result(sig_pub_key, msg, ctx),
+                    _ => debug!(
+                        "Ignoring dispute result of type {} from {}",
+                        msg.r#type, sender
+                    ),
+                }
+            }
+            network_envelope::Message::MediatedPayoutTxSignatureMessage(msg) => {
+                let id = msg.trade_id.clone().into();
+                if self.peer_trade(&id, &sig_pub_key).is_some() {
+                    self.on_mediated_payout_signature(&id, msg, ctx)
+                }
+            }
+            network_envelope::Message::MediatedPayoutTxPublishedMessage(msg) => {
+                let id = msg.trade_id.clone().into();
+                if self.peer_trade(&id, &sig_pub_key).is_some() {
+                    self.on_mediated_payout_published(&id, msg, ctx)
+                }
+            }
+            network_envelope::Message::ChatMessage(msg) => {
+                self.on_chat_message(sig_pub_key, msg, ctx)
+            }
+            network_envelope::Message::AckMessage(ack) => {
+                debug!(
+                    "{} acknowledged {} of trade {} (success: {})",
+                    sender, ack.source_msg_class_name, ack.source_id, ack.success
+                );
+                self.on_chat_ack(&ack);
+            }
+            msg => debug!("Ignoring sealed message from {}: {:?}", sender, msg),
+        }
+    }
+}
+
+fn txid(raw_tx: &[u8]) -> Result<String, bitcoin::consensus::encode::Error> {
+    Ok(
+        bitcoin::consensus::deserialize::<bitcoin::Transaction>(raw_tx)?
+            .txid()
+            .to_string(),
+    )
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_millis() as i64
+}
+
+impl Handler<DirectMessage> for TradeManager {
+    type Result = ();
+    fn handle(
+        &mut self,
+        DirectMessage {
+            sender,
+            sig_pub_key,
+            msg,
+        }: DirectMessage,
+        ctx: &mut Self::Context,
+    ) {
+        self.on_message(sender, sig_pub_key, msg, ctx)
+    }
+}
+
+impl Handler<GetTrades> for TradeManager {
+    type Result = MessageResult<GetTrades>;
+    fn handle(&mut self, _: GetTrades, _: &mut Self::Context) -> Self::Result {
+        let mut trades: Vec<Trade> = self.trades.values().cloned().collect();
+        trades.sort_by_key(|trade| trade.created_at);
+        MessageResult(trades)
+    }
+}
+
+impl Handler<GetWallet> for TradeManager {
+    type Result = ResponseFuture<WalletInfo, String>;
+    fn handle(&mut self, _: GetWallet, _: &mut Self::Context) -> Self::Result {
+        let wallet = match self.wallet.as_ref() {
+            Some(wallet) => wallet,
+            None => return Box::new(future::err("No wallet configured".into())),
+        };
+        Box::new(wallet.balance().join(wallet.receive_address()).map(
+            |(balance, receive_address)| WalletInfo {
+                balance: NumberWithPrecision::new(balance, BTC_PRECISION),
+                receive_address,
+            },
+        ))
+    }
+}
+
+impl Handler<GetFees> for TradeManager {
+    type Result = ResponseFuture<FeeInfo, String>;
+    fn handle(&mut self, _: GetFees, _: &mut Self::Context) -> Self::Result {
+        let service = self.fee_rates.current(self.network);
+        let trade: Box<dyn Future<Item = Option<u64>, Error = String>> = match self.wallet.as_ref()
+        {
+            Some(wallet) => Box::new(wallet.fee_rate().map(Some)),
+            None => Box::new(future::ok(None)),
+        };
+        Box::new(trade.map(move |trade| FeeInfo { service, trade }))
+    }
+}
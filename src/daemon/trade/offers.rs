@@ -0,0 +1,583 @@
+use super::{now_millis, TradeManager};
+use crate::{
+    bisq::{
+        constants::{Capabilities, LOCAL_CAPABILITIES},
+        payload::*,
+        Sha256,
+    },
+    daemon::{
+        convert,
+        data_router::{DataRouterDispatch, Publish},
+        direct_messages,
+        identity::PreviousIdentity,
+    },
+    domain::{
+        amount::BtcAmount,
+        offer::{
+            compliance::{self, ACCOUNT_AGE_WITNESS_HASH_KEY},
+            OfferDirection, OfferId, OfferPrice, OpenOffer,
+        },
+        payment_account::{PaymentAccount, PaymentAccountDetails},
+        trade::{
+            message::{CreateOffer, RemoveMyOffer},
+            protocol::{BISQ_VERSION, TRADE_PROTOCOL_VERSION},
+            wallet::{MakerFunding, TradeWallet},
+            TradeState,
+        },
+        trade_fee::{self, FeeCurrency},
+    },
+    p2p::BootstrapState,
+    prelude::{future::Either, *},
+};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use uuid::Uuid;
+
+/// Bisq's `OpenOfferManager.REFRESH_INTERVAL_MS`, well within the 9 minutes
+/// an offer lives without a refresh.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(6 * 60);
+/// Bisq's `Restrictions.getMinTradeAmount`
+const MIN_TRADE_AMOUNT: u64 = 10_000;
+/// Offers further from the market price would hardly be taken.
+const MAX_MARKET_PRICE_MARGIN: f64 = 0.5;
+// Keys of `OfferPayload.extra_data` in bisq
+const CAPABILITIES_KEY: &str = "capabilities";
+const F2F_CITY_KEY: &str = "f2fCity";
+const F2F_EXTRA_INFO_KEY: &str = "f2fExtraInfo";
+
+/// One of our offers, as we last signed it.
+pub(super) struct MyOffer {
+    entry: ProtectedStorageEntry,
+    /// When it was last added or refreshed, `None` while it isn't published
+    /// after a restart
+    published_at: Option<SystemTime>,
+}
+impl MyOffer {
+    pub(super) fn restore(entry: ProtectedStorageEntry) -> Option<(OfferId, Self)> {
+        let id = offer_payload(&entry)?.id.clone().into();
+        Some((
+            id,
+            MyOffer {
+                entry,
+                published_at: None,
+            },
+        ))
+    }
+}
+
+impl Handler<CreateOffer> for TradeManager {
+    type Result = ResponseActFuture<Self, OpenOffer, String>;
+    fn handle(&mut self, create: CreateOffer, _: &mut Self::Context) -> Self::Result {
+        let (wallet, account, owner) = match self.check_new_offer(&create) {
+            Ok(checked) => checked,
+            Err(e) => return Box::new(fut::err(e)),
+        };
+        let amount = create.amount.total.sats();
+        let maker_fee = trade_fee::maker_fee(amount, FeeCurrency::Btc);
+        let needed = maker_fee + own_deposit(create.direction, amount);
+        let paying = wallet.balance().join(wallet.block_height()).and_then(
+            move |(balance, block_height)| {
+                if balance < needed {
+                    return Either::A(future::err(format!(
+                        "The offer needs {} BTC for the maker fee and the deposit",
+                        BtcAmount::from_sats(needed).format()
+                    )));
+                }
+                Either::B(
+                    wallet
+                        .pay_maker_fee(maker_fee)
+                        .map(move |funding| (funding, block_height)),
+                )
+            },
+        );
+        Box::new(fut::wrap_future(paying).and_then(
+            move |(funding, block_height), manager: &mut Self, _| {
+                let payload = new_offer_payload(
+                    &create,
+                    &account,
+                    manager.key_ring.pub_key_ring(),
+                    owner,
+                    &funding,
+                    block_height,
+                );
+                fut::result(manager.publish_new_offer(payload))
+            },
+        ))
+    }
+}
+
+impl Handler<RemoveMyOffer> for TradeManager {
+    type Result = Result<(), String>;
+    fn handle(&mut self, RemoveMyOffer(id): RemoveMyOffer, _: &mut Self::Context) -> Self::Result {
+        if self.is_taken(&id) {
+            return Err("The offer was already taken".into());
+        }
+        let offer = self
+            .offers
+            .get(&id)
+            .ok_or_else(|| format!("Unknown offer {:?}", id))?;
+        let payload = offer
+            .entry
+            .storage_payload
+            .clone()
+            .ok_or("The offer has no payload")?;
+        let removal = self.sign_entry(payload, offer.entry.sequence_number + 1)?;
+        info!("Removing offer {:?}", id);
+        arbiter_spawn!(self
+            .data_router
+            .send(Publish(DataRouterDispatch::RemoveData(RemoveDataMessage {
+                protected_storage_entry: Some(removal),
+            }))));
+        self.offers.remove(&id);
+        self.persist_offers();
+        Ok(())
+    }
+}
+
+impl TradeManager {
+    fn check_new_offer(
+        &self,
+        create: &CreateOffer,
+    ) -> Result<(Arc<dyn TradeWallet>, PaymentAccount, NodeAddress), String> {
+        if let Some(reason) = self.alerts.halting_offers() {
+            return Err(reason);
+        }
+        let wallet = self
+            .wallet
+            .clone()
+            .ok_or("No wallet configured to pay the maker fee")?;
+        let owner = match (self.status.bootstrap_state(), self.status.local_addr()) {
+            (BootstrapState::Bootstrapped, Some(owner)) => owner,
+            _ => return Err("Offers can only be published once the node is bootstrapped".into()),
+        };
+        let account = self
+            .payment_accounts
+            .get(&create.payment_account_id)
+            .ok_or_else(|| format!("Unknown payment account {}", create.payment_account_id))?;
+        if account.currency != create.market.non_btc_side() {
+            return Err(format!(
+                "The payment account is for {}, not {}",
+                account.currency.code,
+                create.market.non_btc_side().code
+            ));
+        }
+        let amount = create.amount.total;
+        let min_amount = create.amount.min;
+        if min_amount.sats() < MIN_TRADE_AMOUNT || min_amount > amount {
+            return Err(format!(
+                "The min amount must be between {} BTC and the amount",
+                BtcAmount::from_sats(MIN_TRADE_AMOUNT).format()
+            ));
+        }
+        match create.price {
+            OfferPrice::Fixed(price) if price.base_amount() == 0 => {
+                Err("The price must be above 0".into())
+            }
+            OfferPrice::MarketWithMargin(margin) if margin.abs() > MAX_MARKET_PRICE_MARGIN => {
+                Err(format!(
+                    "The margin must be between -{} and {}",
+                    MAX_MARKET_PRICE_MARGIN, MAX_MARKET_PRICE_MARGIN
+                ))
+            }
+            _ => Ok((wallet, account, owner)),
+        }
+    }
+
+    fn publish_new_offer(&mut self, payload: OfferPayload) -> Result<OpenOffer, String> {
+        let id: OfferId = payload.id.clone().into();
+        let entry = self.sign_entry(
+            StoragePayload {
+                message: Some(storage_payload::Message::OfferPayload(payload)),
+            },
+            1,
+        )?;
+        let offer = entry
+            .verify()
+            .and_then(|hash| convert::open_offer(entry.clone(), hash))
+            .ok_or("The offer is invalid")?;
+        info!("Publishing offer {:?}", id);
+        self.publish_entry(entry.clone());
+        self.offers.insert(
+            id,
+            MyOffer {
+                entry,
+                published_at: Some(SystemTime::now()),
+            },
+        );
+        self.persist_offers();
+        Ok(offer)
+    }
+
+    /// Publishes our offers again after a restart and refreshes them before
+    /// their TTL elapses, until they are taken. While offers are halted by an
+    /// alert they expire in the network and are published anew afterwards.
+    pub(super) fn publish_offers(&mut self) {
+        if self.status.bootstrap_state() != BootstrapState::Bootstrapped {
+            return;
+        }
+        if self.previous_identity.is_some() {
+            self.move_offers_to_new_identity();
+        }
+        if let Some(reason) = self.alerts.halting_offers() {
+            debug!("Not publishing offers: {}", reason);
+            for offer in self.offers.values_mut() {
+                offer.published_at = None;
+            }
+            return;
+        }
+        let now = SystemTime::now();
+        let due: Vec<OfferId> = self
+            .offers
+            .iter()
+            .filter(|(id, offer)| {
+                offer
+                    .published_at
+                    .and_then(|at| now.duration_since(at).ok())
+                    .map(|age| age >= REFRESH_INTERVAL)
+                    .unwrap_or(true)
+                    && !self.is_taken(id)
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in due.iter() {
+            if let Err(e) = self.republish(id, now) {
+                warn!("Couldn't publish offer {:?}: {}", id, e);
+            }
+        }
+        if !due.is_empty() {
+            self.persist_offers();
+        }
+    }
+
+    /// Removes the offers signed with the identity before `risq identity
+    /// rotate` and signs them again for the new key ring and onion address,
+    /// to be published as if after a restart.
+    fn move_offers_to_new_identity(&mut self) {
+        let owner = match self.status.local_addr() {
+            Some(owner) => owner,
+            None => return,
+        };
+        let previous = match self.previous_identity.take() {
+            Some(previous) => previous,
+            None => return,
+        };
+        let ids: Vec<OfferId> = self.offers.keys().cloned().collect();
+        for id in ids {
+            if let Err(e) = self.move_offer(&id, &previous, owner.clone()) {
+                warn!("Couldn't move offer {:?} to the new identity: {}", id, e);
+            }
+        }
+        self.persist_offers();
+        match previous.retire() {
+            Ok(backup) => info!(
+                "Moved offers to the new identity, kept the previous in {:?}",
+                backup
+            ),
+            Err(e) => error!("Couldn't retire the previous identity: {}", e),
+        }
+    }
+
+    fn move_offer(
+        &mut self,
+        id: &OfferId,
+        previous: &PreviousIdentity,
+        owner: NodeAddress,
+    ) -> Result<(), String> {
+        let offer = &self.offers[id];
+        let mut payload = offer_payload(&offer.entry)
+            .cloned()
+            .ok_or("The offer has no payload")?;
+        let removal = direct_messages::sign_entry(
+            &previous.key_ring,
+            offer
+                .entry
+                .storage_payload
+                .clone()
+                .ok_or("The offer has no payload")?,
+            offer.entry.sequence_number + 1,
+        )?;
+        arbiter_spawn!(self
+            .data_router
+            .send(Publish(DataRouterDispatch::RemoveData(RemoveDataMessage {
+                protected_storage_entry: Some(removal),
+            }))));
+        let pub_key_ring = self.key_ring.pub_key_ring();
+        // The age of the account is witnessed for the key ring
+        if let Some(account) = self.payment_accounts.get(&payload.maker_payment_account_id) {
+            let witness_hash = account.age_witness_hash(&pub_key_ring).to_hex();
+            for entry in payload.extra_data.iter_mut() {
+                if entry.key() == ACCOUNT_AGE_WITNESS_HASH_KEY {
+                    *entry = JavaStringMapEntry::new(
+                        ACCOUNT_AGE_WITNESS_HASH_KEY.into(),
+                        witness_hash.clone(),
+                    );
+                }
+            }
+        }
+        payload.owner_node_address = Some(owner);
+        payload.pub_key_ring = Some(pub_key_ring);
+        info!("Moving offer {:?} to the new identity", id);
+        let entry = self.sign_entry(
+            StoragePayload {
+                message: Some(storage_payload::Message::OfferPayload(payload)),
+            },
+            0,
+        )?;
+        self.offers.insert(
+            id.clone(),
+            MyOffer {
+                entry,
+                published_at: None,
+            },
+        );
+        Ok(())
+    }
+
+    fn republish(&mut self, id: &OfferId, now: SystemTime) -> Result<(), String> {
+        let offer = &self.offers[id];
+        let payload = offer
+            .entry
+            .storage_payload
+            .clone()
+            .ok_or("The offer has no payload")?;
+        let sequence_number = offer.entry.sequence_number + 1;
+        let entry = if offer.published_at.is_some() {
+            let hash = DataAndSeqNrPair {
+                payload: Some(payload.clone()),
+                sequence_number,
+            }
+            .sha256();
+            let signature = self
+                .key_ring
+                .sign(&hash.into_inner())
+                .map_err(|e| format!("Couldn't sign refresh: {}", e))?;
+            debug!("Refreshing offer {:?}", id);
+            arbiter_spawn!(self
+                .data_router
+                .send(Publish(DataRouterDispatch::RefreshOffer(
+                    RefreshOfferMessage {
+                        hash_of_data_and_seq_nr: hash.into_inner().to_vec(),
+                        signature,
+                        hash_of_payload: payload.bisq_hash().into(),
+                        sequence_number,
+                    }
+                ))));
+            ProtectedStorageEntry {
+                sequence_number,
+                ..offer.entry.clone()
+            }
+        } else {
+            info!("Publishing offer {:?} again", id);
+            let entry = self.sign_entry(payload, sequence_number)?;
+            self.publish_entry(entry.clone());
+            entry
+        };
+        self.offers.insert(
+            id.clone(),
+            MyOffer {
+                entry,
+                published_at: Some(now),
+            },
+        );
+        Ok(())
+    }
+
+    fn publish_entry(&self, entry: ProtectedStorageEntry) {
+        arbiter_spawn!(self.data_router.send(Publish(DataRouterDispatch::AddData(
+            AddDataMessage {
+                entry: Some(StorageEntryWrapper {
+                    message: Some(storage_entry_wrapper::Message::ProtectedStorageEntry(entry)),
+                }),
+            }
+        ))));
+    }
+
+    fn is_taken(&self, id: &OfferId) -> bool {
+        self.trades
+            .get(id)
+            .map(|trade| trade.state() != TradeState::Failed)
+            .unwrap_or(false)
+    }
+
+    fn persist_offers(&self) {
+        if let Err(e) = self
+            .offer_store
+            .save(self.offers.values().map(|offer| &offer.entry))
+        {
+            error!("Couldn't persist offers: {}", e);
+        }
+    }
+}
+
+fn offer_payload(entry: &ProtectedStorageEntry) -> Option<&OfferPayload> {
+    match entry.storage_payload.as_ref()?.message.as_ref()? {
+        storage_payload::Message::OfferPayload(payload) => Some(payload),
+        _ => None,
+    }
+}
+
+/// What we lock in the deposit tx as maker, the amount only when selling.
+fn own_deposit(direction: OfferDirection, amount: u64) -> u64 {
+    let deposit = compliance::default_security_deposit(amount);
+    match direction {
+        OfferDirection::Buy => deposit,
+        OfferDirection::Sell => deposit + amount,
+    }
+}
+
+/// The offer like bisq's `CreateOfferService` creates it, both traders
+/// deposit the default share of the amount and the fee is paid in BTC.
+fn new_offer_payload(
+    create: &CreateOffer,
+    account: &PaymentAccount,
+    pub_key_ring: PubKeyRing,
+    owner: NodeAddress,
+    funding: &MakerFunding,
+    block_height: u32,
+) -> OfferPayload {
+    let amount = create.amount.total.sats();
+    let deposit = compliance::default_security_deposit(amount);
+    let payment_method = account.payment_method();
+    let (price, use_market_based_price, market_price_margin) = match create.price {
+        OfferPrice::Fixed(price) => (
+            price
+                .with_precision(create.market.right.bisq_internal_precision())
+                .base_amount() as i64,
+            false,
+            0.0,
+        ),
+        OfferPrice::MarketWithMargin(margin) => (0, true, margin),
+    };
+    let mut extra_data = vec![
+        JavaStringMapEntry::new(
+            ACCOUNT_AGE_WITNESS_HASH_KEY.into(),
+            account.age_witness_hash(&pub_key_ring).to_hex(),
+        ),
+        JavaStringMapEntry::new(
+            CAPABILITIES_KEY.into(),
+            Capabilities::from(&LOCAL_CAPABILITIES[..]).to_string(),
+        ),
+    ];
+    if let PaymentAccountDetails::F2f {
+        city, extra_info, ..
+    } = &account.details
+    {
+        extra_data.push(JavaStringMapEntry::new(F2F_CITY_KEY.into(), city.clone()));
+        extra_data.push(JavaStringMapEntry::new(
+            F2F_EXTRA_INFO_KEY.into(),
+            extra_info.clone(),
+        ));
+    }
+    OfferPayload {
+        id: format!("{}-{}", Uuid::new_v4(), BISQ_VERSION.replace('.', "")),
+        date: now_millis(),
+        owner_node_address: Some(owner),
+        pub_key_ring: Some(pub_key_ring),
+        direction: match create.direction {
+            OfferDirection::Buy => offer_payload::Direction::Buy,
+            OfferDirection::Sell => offer_payload::Direction::Sell,
+        } as i32,
+        price,
+        market_price_margin,
+        use_market_based_price,
+        amount: amount as i64,
+        min_amount: create.amount.min.sats() as i64,
+        base_currency_code: create.market.left.code.clone(),
+        counter_currency_code: create.market.right.code.clone(),
+        payment_method_id: payment_method.id().into(),
+        maker_payment_account_id: account.id.clone(),
+        offer_fee_payment_tx_id: funding.maker_fee_tx_id.clone(),
+        country_code: account.country_code().unwrap_or_default().into(),
+        accepted_country_codes: account.accepted_country_codes(),
+        version_nr: BISQ_VERSION.into(),
+        block_height_at_offer_creation: i64::from(block_height),
+        tx_fee: funding.tx_fee as i64,
+        maker_fee: trade_fee::maker_fee(amount, FeeCurrency::Btc) as i64,
+        is_currency_for_maker_fee_btc: true,
+        buyer_security_deposit: deposit as i64,
+        seller_security_deposit: deposit as i64,
+        max_trade_limit: amount as i64,
+        max_trade_period: payment_method.max_trade_period().as_millis() as i64,
+        extra_data,
+        protocol_version: TRADE_PROTOCOL_VERSION,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bisq::{crypto::KeyRing, SequencedMessageHash},
+        domain::{
+            currency::Currency,
+            market::Market,
+            offer::{compliance::maker_witness_hash, OfferAmount},
+        },
+    };
+
+    #[test]
+    fn creates_offers_like_bisq() {
+        let account = PaymentAccount::new(
+            "Sepa".into(),
+            Currency::from_code("EUR").unwrap(),
+            PaymentAccountDetails::Sepa {
+                holder_name: "Satoshi".into(),
+                iban: "DE89 3704 0044 0532 0130 00".into(),
+                bic: "COBADEFFXXX".into(),
+            },
+        )
+        .unwrap();
+        let keys = KeyRing::generate().unwrap().pub_key_ring();
+        let create = CreateOffer {
+            market: Market::from_pair("btc_eur").unwrap(),
+            direction: OfferDirection::Sell,
+            price: OfferPrice::Fixed("9000.5".parse().unwrap()),
+            amount: OfferAmount {
+                total: "0.1".parse().unwrap(),
+                min: "0.05".parse().unwrap(),
+            },
+            payment_account_id: account.id.clone(),
+        };
+        let payload = new_offer_payload(
+            &create,
+            &account,
+            keys.clone(),
+            NodeAddress {
+                host_name: "risq.onion".into(),
+                port: 9999,
+            },
+            &MakerFunding {
+                maker_fee_tx_id: "ab".repeat(32),
+                tx_fee: 20_000,
+            },
+            600_000,
+        );
+        assert_eq!(payload.price, 90_005_000);
+        assert_eq!(payload.amount, 10_000_000);
+        assert_eq!(payload.min_amount, 5_000_000);
+        assert_eq!(payload.maker_fee, 10_000);
+        assert_eq!(payload.buyer_security_deposit, 1_500_000);
+        assert_eq!(payload.country_code, "DE");
+        assert_eq!(own_deposit(OfferDirection::Sell, 10_000_000), 11_500_000);
+
+        let offer = convert::open_offer(
+            ProtectedStorageEntry {
+                storage_payload: Some(StoragePayload {
+                    message: Some(storage_payload::Message::OfferPayload(payload)),
+                }),
+                ..Default::default()
+            },
+            SequencedMessageHash::new(sha256::Hash::hash(b"offer")),
+        )
+        .unwrap();
+        assert_eq!(compliance::check(&offer), vec![]);
+        assert_eq!(
+            maker_witness_hash(&offer),
+            Some(account.age_witness_hash(&keys))
+        );
+        assert!(account.supports(&offer));
+    }
+}
@@ -0,0 +1,234 @@
+use super::{txid, TradeManager};
+use crate::{
+    bisq::payload::*,
+    domain::{
+        offer::{OfferDirection, OfferId},
+        trade::{message::ConfirmPayment, wallet::TradeWallet, Trade, TradeState},
+    },
+    prelude::*,
+    wallet::tx,
+};
+use bitcoin::{consensus, secp256k1::Secp256k1, Script, Transaction};
+use std::sync::Arc;
+use uuid::Uuid;
+
+impl Handler<ConfirmPayment> for TradeManager {
+    type Result = ResponseActFuture<Self, Trade, String>;
+    fn handle(
+        &mut self,
+        ConfirmPayment { trade_id }: ConfirmPayment,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        match self.confirm_payment(&trade_id) {
+            Ok(confirming) => Box::new(confirming.and_then(move |_, manager: &mut Self, _| {
+                fut::result(
+                    manager
+                        .trades
+                        .get(&trade_id)
+                        .cloned()
+                        .ok_or_else(|| "Trade is gone".to_string()),
+                )
+            })),
+            Err(e) => Box::new(fut::err(e)),
+        }
+    }
+}
+
+/// The payout tx that pays (buyer, seller) `amounts`, with the script and
+/// value of the deposit output it spends.
+pub(super) fn payout(
+    trade: &Trade,
+    (buyer_payout, seller_payout): (u64, u64),
+) -> Result<(Transaction, Script, u64), String> {
+    let payout_tx = tx::payout_tx(trade, buyer_payout, seller_payout)?;
+    let (_, deposit_value) = tx::deposit_output(trade)?;
+    Ok((payout_tx, tx::trade_script(trade)?, deposit_value))
+}
+
+impl TradeManager {
+    fn confirm_payment(
+        &self,
+        id: &OfferId,
+    ) -> Result<Box<dyn ActorFuture<Item = (), Error = String, Actor = Self>>, String> {
+        let wallet = self.wallet.clone().ok_or("No wallet configured")?;
+        let trade = self
+            .trades
+            .get(id)
+            .ok_or_else(|| format!("Unknown trade {}", String::from(id.clone())))?;
+        match (trade.direction(), trade.state()) {
+            (OfferDirection::Buy, TradeState::DepositPublished) => {
+                Ok(Box::new(self.start_payment(wallet, trade.clone())?))
+            }
+            (OfferDirection::Sell, TradeState::PaymentStarted) => {
+                Ok(Box::new(self.release_payout(wallet, trade.clone())?))
+            }
+            (OfferDirection::Sell, TradeState::DepositPublished) => {
+                Err("The buyer hasn't started the payment yet".into())
+            }
+            (_, state) => Err(format!("Can't confirm the payment in state {}", state)),
+        }
+    }
+
+    // The buyer signs the payout first, the seller only adds its signature once the money arrived.
+    fn start_payment(
+        &self,
+        wallet: Arc<dyn TradeWallet>,
+        trade: Trade,
+    ) -> Result<impl ActorFuture<Item = (), Error = String, Actor = Self>, String> {
+        let (payout_tx, _, _) = payout(&trade, tx::payout_amounts(&trade))?;
+        let id = trade.id().clone();
+        Ok(
+            fut::wrap_future(wallet.sign_payout_tx(&trade, &consensus::serialize(&payout_tx)))
+                .map_err(|e, _, _| format!("Couldn't sign the payout tx: {}", e))
+                .map(move |buyer_signature, manager: &mut Self, ctx| {
+                    manager.payment_started(&id, buyer_signature, ctx)
+                }),
+        )
+    }
+
+    fn payment_started(&mut self, id: &OfferId, buyer_signature: Vec<u8>, ctx: &mut Context<Self>) {
+        if !self.in_state(id, TradeState::DepositPublished) {
+            return;
+        }
+        let buyer_payout_address = match self.advance(id, TradeState::PaymentStarted) {
+            Some(trade) => trade.payout_address.clone().unwrap_or_default(),
+            None => return,
+        };
+        let msg = CounterCurrencyTransferStartedMessage {
+            trade_id: id.clone().into(),
+            buyer_payout_address,
+            sender_node_address: self.status.local_addr(),
+            buyer_signature,
+            counter_currency_tx_id: String::new(),
+            uid: Uuid::new_v4().to_string(),
+        };
+        self.send_sealed(id, msg, ctx);
+    }
+
+    pub(super) fn on_payment_started(
+        &mut self,
+        id: &OfferId,
+        msg: CounterCurrencyTransferStartedMessage,
+        ctx: &mut Context<Self>,
+    ) {
+        let trade = match self.trades.get(id) {
+            Some(trade)
+                if trade.direction() == OfferDirection::Sell
+                    && trade.state() == TradeState::DepositPublished =>
+            {
+                trade
+            }
+            _ => return,
+        };
+        if trade.peer_payout_address.as_ref() != Some(&msg.buyer_payout_address) {
+            return self.fail(id, "Buyer changed its payout address".into());
+        }
+        let valid = payout(trade, tx::payout_amounts(trade)).and_then(
+            |(payout_tx, script, deposit_value)| {
+                let (buyer_pub_key, _) = tx::multi_sig_keys(trade)?;
+                let sighash = tx::payout_sighash(&payout_tx, &script, deposit_value);
+                Ok(tx::verify_signature(
+                    &Secp256k1::verification_only(),
+                    &sighash,
+                    &msg.buyer_signature,
+                    buyer_pub_key,
+                ))
+            },
+        );
+        match valid {
+            Ok(true) => (),
+            Ok(false) => return self.fail(id, "Buyer's payout signature is invalid".into()),
+            Err(e) => return self.fail(id, format!("Couldn't check the payout signature: {}", e)),
+        }
+        if let Some(trade) = self.trades.get_mut(id) {
+            trade.peer_payout_signature = Some(msg.buyer_signature);
+        }
+        if self.advance(id, TradeState::PaymentStarted).is_some() {
+            info!("Buyer started the payment of trade {:?}", id);
+            self.send_ack(id, msg.uid, "CounterCurrencyTransferStartedMessage", ctx);
+        }
+    }
+
+    fn release_payout(
+        &self,
+        wallet: Arc<dyn TradeWallet>,
+        trade: Trade,
+    ) -> Result<impl ActorFuture<Item = (), Error = String, Actor = Self>, String> {
+        let (mut payout_tx, script, _) = payout(&trade, tx::payout_amounts(&trade))?;
+        let buyer_signature = trade
+            .peer_payout_signature
+            .clone()
+            .ok_or("The buyer's payout signature is missing")?;
+        let id = trade.id().clone();
+        let signing = wallet.sign_payout_tx(&trade, &consensus::serialize(&payout_tx));
+        Ok(fut::wrap_future(
+            signing
+                .map_err(|e| format!("Couldn't sign the payout tx: {}", e))
+                .and_then(move |seller_signature| {
+                    tx::finalize_payout(
+                        &mut payout_tx,
+                        &script,
+                        &buyer_signature,
+                        &seller_signature,
+                    );
+                    let payout_tx = consensus::serialize(&payout_tx);
+                    wallet
+                        .publish_tx(&payout_tx)
+                        .map_err(|e| format!("Couldn't publish the payout tx: {}", e))
+                        .map(move |_| payout_tx)
+                }),
+        )
+        .map(move |payout_tx, manager: &mut Self, ctx| {
+            manager.payout_published(&id, payout_tx, ctx)
+        }))
+    }
+
+    fn payout_published(&mut self, id: &OfferId, payout_tx: Vec<u8>, ctx: &mut Context<Self>) {
+        if let Some(trade) = self.trades.get_mut(id) {
+            trade.payout_tx_id = txid(&payout_tx).ok();
+        }
+        if self.advance(id, TradeState::PayoutPublished).is_none() {
+            return;
+        }
+        let msg = PayoutTxPublishedMessage {
+            trade_id: id.clone().into(),
+            payout_tx,
+            sender_node_address: self.status.local_addr(),
+            uid: Uuid::new_v4().to_string(),
+            signed_witness: self.sign_peers_witness(id),
+        };
+        self.send_sealed(id, msg, ctx);
+    }
+
+    pub(super) fn on_payout_published(
+        &mut self,
+        id: &OfferId,
+        msg: PayoutTxPublishedMessage,
+        ctx: &mut Context<Self>,
+    ) {
+        let expected = match self.trades.get(id) {
+            Some(trade)
+                if trade.direction() == OfferDirection::Buy
+                    && trade.state() == TradeState::PaymentStarted =>
+            {
+                payout(trade, tx::payout_amounts(trade))
+                    .map(|(payout_tx, _, _)| payout_tx.txid().to_string())
+            }
+            _ => return,
+        };
+        // Witnesses don't change the txid, so it must be the tx we signed
+        let txid = match (expected, txid(&msg.payout_tx)) {
+            (Ok(expected), Ok(txid)) if expected == txid => txid,
+            _ => return warn!("Seller of trade {:?} published an unexpected payout", id),
+        };
+        if let Some(trade) = self.trades.get_mut(id) {
+            trade.payout_tx_id = Some(txid);
+        }
+        if self.advance(id, TradeState::PayoutPublished).is_some() {
+            if let Some(witness) = msg.signed_witness {
+                self.on_signed_witness(id, witness);
+            }
+            self.send_ack(id, msg.uid, "PayoutTxPublishedMessage", ctx);
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use super::{mediation::disputed_payout, txid, TradeManager};
+use crate::{
+    bisq::{constants::BaseCurrencyNetwork, payload::*},
+    domain::{
+        dispute::DisputeAgentKind,
+        offer::OfferId,
+        trade::{message::PublishDelayedPayout, Trade, TradeState},
+    },
+    prelude::*,
+    wallet::tx,
+};
+use bitcoin::{consensus, Script, Transaction};
+use uuid::Uuid;
+
+impl Handler<PublishDelayedPayout> for TradeManager {
+    type Result = ResponseActFuture<Self, Trade, String>;
+    fn handle(
+        &mut self,
+        PublishDelayedPayout { trade_id }: PublishDelayedPayout,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        match self.publish_delayed_payout(&trade_id) {
+            Ok(publishing) => Box::new(publishing.and_then(move |_, manager: &mut Self, _| {
+                fut::result(
+                    manager
+                        .trades
+                        .get(&trade_id)
+                        .cloned()
+                        .ok_or_else(|| "Trade is gone".to_string()),
+                )
+            })),
+            Err(e) => Box::new(fut::err(e)),
+        }
+    }
+}
+
+/// The delayed payout tx of the trade, with the script and value of the
+/// deposit output it spends.
+pub(super) fn delayed_payout(
+    trade: &Trade,
+    network: BaseCurrencyNetwork,
+) -> Result<(Transaction, Script, u64), String> {
+    let lock_time = trade.lock_time.ok_or("The lock time is missing")?;
+    let delayed_payout_tx =
+        tx::delayed_payout_tx(trade, &tx::donation_address(network), lock_time)?;
+    let (_, deposit_value) = tx::deposit_output(trade)?;
+    Ok((delayed_payout_tx, tx::trade_script(trade)?, deposit_value))
+}
+
+impl TradeManager {
+    pub(super) fn is_delayed_payout(&self, id: &OfferId, payout_tx_id: &str) -> bool {
+        self.trades
+            .get(id)
+            .and_then(|trade| trade.delayed_payout_tx.as_ref())
+            .and_then(|delayed_payout_tx| txid(delayed_payout_tx).ok())
+            .map(|delayed_payout_tx_id| delayed_payout_tx_id == payout_tx_id)
+            .unwrap_or(false)
+    }
+
+    fn publish_delayed_payout(
+        &self,
+        id: &OfferId,
+    ) -> Result<Box<dyn ActorFuture<Item = (), Error = String, Actor = Self>>, String> {
+        let wallet = self.wallet.clone().ok_or("No wallet configured")?;
+        let trade = self
+            .trades
+            .get(id)
+            .ok_or_else(|| format!("Unknown trade {}", String::from(id.clone())))?;
+        if !trade.state().funds_locked() && trade.state() != TradeState::Disputed {
+            return Err(format!(
+                "Can't publish the delayed payout in state {}",
+                trade.state()
+            ));
+        }
+        let delayed_payout_tx = trade
+            .delayed_payout_tx
+            .clone()
+            .ok_or("The trade has no delayed payout tx")?;
+        let signed = consensus::deserialize::<Transaction>(&delayed_payout_tx)
+            .map(|tx| tx.input.iter().all(|input| !input.witness.is_empty()))
+            .unwrap_or(false);
+        if !signed {
+            return Err("The delayed payout tx isn't signed by both traders".into());
+        }
+        let lock_time = trade.lock_time.unwrap_or_default();
+        let id = id.clone();
+        let publishing = wallet
+            .block_height()
+            .and_then(move |height| {
+                if height < lock_time {
+                    return Err(format!(
+                        "The delayed payout tx can be published in {} blocks",
+                        lock_time - height
+                    ));
+                }
+                Ok(())
+            })
+            .and_then(move |_| {
+                wallet
+                    .publish_tx(&delayed_payout_tx)
+                    .map_err(|e| format!("Couldn't publish the delayed payout tx: {}", e))
+            });
+        Ok(Box::new(fut::wrap_future(publishing).map(
+            move |_, manager: &mut Self, ctx| manager.delayed_payout_published(&id, ctx),
+        )))
+    }
+
+    fn delayed_payout_published(&mut self, id: &OfferId, ctx: &mut Context<Self>) {
+        if self
+            .advance(id, TradeState::DelayedPayoutPublished)
+            .is_none()
+        {
+            return;
+        }
+        let msg = PeerPublishedDelayedPayoutTxMessage {
+            uid: Uuid::new_v4().to_string(),
+            trade_id: id.clone().into(),
+            sender_node_address: self.status.local_addr(),
+        };
+        self.send_sealed(id, msg, ctx);
+    }
+
+    pub(super) fn on_delayed_payout_published(
+        &mut self,
+        id: &OfferId,
+        msg: PeerPublishedDelayedPayoutTxMessage,
+        ctx: &mut Context<Self>,
+    ) {
+        match self.trades.get(id).map(Trade::state) {
+            Some(state) if state.funds_locked() || state == TradeState::Disputed => (),
+            _ => return,
+        }
+        info!("Peer published the delayed payout tx of trade {:?}", id);
+        if self
+            .advance(id, TradeState::DelayedPayoutPublished)
+            .is_some()
+        {
+            self.send_ack(id, msg.uid, "PeerPublishedDelayedPayoutTxMessage", ctx);
+        }
+    }
+
+    pub(super) fn on_refund_result(
+        &mut self,
+        sig_pub_key: Vec<u8>,
+        msg: DisputeResultMessage,
+        ctx: &mut Context<Self>,
+    ) {
+        let result = match msg.dispute_result {
+            Some(result) => result,
+            None => return,
+        };
+        let id: OfferId = result.trade_id.clone().into();
+        let refund_agent = match self.trade_agent(&id, DisputeAgentKind::RefundAgent, &sig_pub_key)
+        {
+            Some(refund_agent) => refund_agent,
+            None => return debug!("Refund result for {:?} isn't from its refund agent", id),
+        };
+        let payout = match disputed_payout(&result) {
+            Ok(payout) => payout,
+            Err(e) => return warn!("Invalid refund result for {:?}: {}", id, e),
+        };
+        match self.trades.get_mut(&id) {
+            Some(trade) if trade.state() == TradeState::DelayedPayoutPublished => {
+                trade.refund_payout = Some(payout);
+            }
+            _ => return,
+        }
+        info!(
+            "Refund agent pays {} sat to the buyer and {} sat to the seller of trade {:?}",
+            result.buyer_payout_amount, result.seller_payout_amount, id
+        );
+        self.persist();
+        self.send_agent_ack(&refund_agent, "REFUND_MESSAGE", &id, msg.uid, ctx);
+    }
+}
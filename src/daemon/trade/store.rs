@@ -0,0 +1,436 @@
+use crate::{
+    bisq::{
+        payload::{
+            storage_payload, NodeAddress, OfferPayload, PaymentAccountPayload,
+            ProtectedStorageEntry, PubKeyRing, StoragePayload,
+        },
+        SequencedMessageHash,
+    },
+    daemon::convert,
+    domain::{
+        amount::NumberWithPrecision,
+        format::BTC_PRECISION,
+        trade::{ChatLine, MediatedPayout, Trade, TradeState},
+    },
+    prelude::{sha256, Hash},
+};
+use prost::Message;
+use std::{
+    fs, io,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Clone, PartialEq, Message)]
+struct StoredTrades {
+    #[prost(message, repeated, tag = "1")]
+    trades: Vec<StoredTrade>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct StoredTrade {
+    #[prost(string, tag = "1")]
+    role: String,
+    #[prost(string, tag = "2")]
+    state: String,
+    #[prost(uint64, tag = "3")]
+    state_changed_at: u64,
+    #[prost(string, tag = "4")]
+    error: String,
+    #[prost(message, optional, tag = "5")]
+    offer: Option<OfferPayload>,
+    #[prost(bytes, tag = "6")]
+    offer_hash: Vec<u8>,
+    #[prost(uint64, tag = "7")]
+    offer_created_at: u64,
+    #[prost(uint64, tag = "8")]
+    amount: u64,
+    #[prost(uint64, tag = "9")]
+    price: u64,
+    #[prost(message, optional, tag = "10")]
+    peer: Option<NodeAddress>,
+    #[prost(message, optional, tag = "11")]
+    peer_pub_key_ring: Option<PubKeyRing>,
+    #[prost(message, optional, tag = "12")]
+    mediator: Option<NodeAddress>,
+    #[prost(message, optional, tag = "13")]
+    refund_agent: Option<NodeAddress>,
+    #[prost(string, tag = "14")]
+    taker_fee_tx_id: String,
+    #[prost(string, tag = "15")]
+    deposit_tx_id: String,
+    #[prost(bytes, tag = "16")]
+    prepared_deposit_tx: Vec<u8>,
+    #[prost(uint64, tag = "17")]
+    created_at: u64,
+    #[prost(string, tag = "18")]
+    payout_tx_id: String,
+    #[prost(bytes, tag = "19")]
+    multi_sig_pub_key: Vec<u8>,
+    #[prost(string, tag = "20")]
+    payout_address: String,
+    #[prost(bytes, tag = "21")]
+    peer_multi_sig_pub_key: Vec<u8>,
+    #[prost(string, tag = "22")]
+    peer_payout_address: String,
+    #[prost(bytes, tag = "23")]
+    deposit_tx: Vec<u8>,
+    #[prost(bytes, tag = "24")]
+    peer_payout_signature: Vec<u8>,
+    #[prost(message, optional, tag = "25")]
+    mediated_payout: Option<StoredMediatedPayout>,
+    #[prost(bytes, tag = "26")]
+    mediated_payout_signature: Vec<u8>,
+    #[prost(bytes, tag = "27")]
+    peer_mediated_payout_signature: Vec<u8>,
+    #[prost(uint32, tag = "28")]
+    lock_time: u32,
+    #[prost(bytes, tag = "29")]
+    delayed_payout_tx: Vec<u8>,
+    #[prost(message, optional, tag = "30")]
+    refund_payout: Option<StoredMediatedPayout>,
+    #[prost(string, tag = "31")]
+    payment_account_id: String,
+    #[prost(message, optional, tag = "32")]
+    peer_payment_account: Option<PaymentAccountPayload>,
+    #[prost(message, repeated, tag = "33")]
+    chat: Vec<StoredChatLine>,
+    #[prost(uint64, tag = "34")]
+    taker_fee: u64,
+    #[prost(int32, repeated, tag = "35")]
+    peer_capabilities: Vec<i32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct StoredChatLine {
+    #[prost(string, tag = "1")]
+    uid: String,
+    #[prost(string, tag = "2")]
+    party: String,
+    #[prost(bool, tag = "3")]
+    sent: bool,
+    #[prost(string, tag = "4")]
+    text: String,
+    #[prost(uint64, tag = "5")]
+    date: u64,
+    #[prost(string, tag = "6")]
+    delivery: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct StoredMediatedPayout {
+    #[prost(uint64, tag = "1")]
+    buyer_payout: u64,
+    #[prost(uint64, tag = "2")]
+    seller_payout: u64,
+}
+
+/// Keeps all trades of this node in a single protobuf file that is
+/// rewritten after every state transition.
+pub struct TradeStore {
+    path: PathBuf,
+}
+impl TradeStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn load(&self) -> io::Result<Vec<Trade>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let stored = StoredTrades::decode(&fs::read(&self.path)?[..])
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        stored
+            .trades
+            .into_iter()
+            .map(|trade| restore(trade).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)))
+            .collect()
+    }
+
+    pub fn save<'a>(&self, trades: impl Iterator<Item = &'a Trade>) -> io::Result<()> {
+        let stored = StoredTrades {
+            trades: trades.map(store).collect(),
+        };
+        let mut serialized = Vec::with_capacity(stored.encoded_len());
+        stored
+            .encode(&mut serialized)
+            .expect("Could not encode trades");
+        // Write to a temp file first so that a crash can't leave half a file behind
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, serialized)?;
+        fs::rename(tmp_path, &self.path)
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct StoredOffers {
+    #[prost(message, repeated, tag = "1")]
+    entries: Vec<ProtectedStorageEntry>,
+}
+
+/// Keeps the signed entries of our own offers, with the sequence number
+/// they were last published or refreshed with.
+pub struct OfferStore {
+    path: PathBuf,
+}
+impl OfferStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn load(&self) -> io::Result<Vec<ProtectedStorageEntry>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        StoredOffers::decode(&fs::read(&self.path)?[..])
+            .map(|stored| stored.entries)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save<'a>(
+        &self,
+        entries: impl Iterator<Item = &'a ProtectedStorageEntry>,
+    ) -> io::Result<()> {
+        let stored = StoredOffers {
+            entries: entries.cloned().collect(),
+        };
+        let mut serialized = Vec::with_capacity(stored.encoded_len());
+        stored
+            .encode(&mut serialized)
+            .expect("Could not encode offers");
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, serialized)?;
+        fs::rename(tmp_path, &self.path)
+    }
+}
+
+fn store(trade: &Trade) -> StoredTrade {
+    StoredTrade {
+        role: trade.role.to_string(),
+        state: trade.state().to_string(),
+        state_changed_at: millis(trade.state_changed_at()),
+        error: trade.error().unwrap_or_default().to_string(),
+        offer: Some((*trade.offer.payload).clone()),
+        offer_hash: trade.offer.bisq_hash.into(),
+        offer_created_at: millis(trade.offer.created_at),
+        amount: trade.amount.with_precision(BTC_PRECISION).base_amount(),
+        price: trade.price.base_amount(),
+        peer: Some(trade.peer.clone()),
+        peer_pub_key_ring: Some(trade.peer_pub_key_ring.clone()),
+        peer_capabilities: trade.peer_capabilities.clone(),
+        mediator: trade.mediator.clone(),
+        refund_agent: trade.refund_agent.clone(),
+        payment_account_id: trade.payment_account_id.clone().unwrap_or_default(),
+        peer_payment_account: trade.peer_payment_account.clone(),
+        taker_fee_tx_id: trade.taker_fee_tx_id.clone().unwrap_or_default(),
+        taker_fee: trade.taker_fee.unwrap_or_default(),
+        deposit_tx_id: trade.deposit_tx_id.clone().unwrap_or_default(),
+        payout_tx_id: trade.payout_tx_id.clone().unwrap_or_default(),
+        multi_sig_pub_key: trade.multi_sig_pub_key.clone().unwrap_or_default(),
+        payout_address: trade.payout_address.clone().unwrap_or_default(),
+        peer_multi_sig_pub_key: trade.peer_multi_sig_pub_key.clone().unwrap_or_default(),
+        peer_payout_address: trade.peer_payout_address.clone().unwrap_or_default(),
+        deposit_tx: trade.deposit_tx.clone().unwrap_or_default(),
+        peer_payout_signature: trade.peer_payout_signature.clone().unwrap_or_default(),
+        mediated_payout: trade.mediated_payout.map(|payout| StoredMediatedPayout {
+            buyer_payout: payout.buyer_payout,
+            seller_payout: payout.seller_payout,
+        }),
+        mediated_payout_signature: trade.mediated_payout_signature.clone().unwrap_or_default(),
+        peer_mediated_payout_signature: trade
+            .peer_mediated_payout_signature
+            .clone()
+            .unwrap_or_default(),
+        lock_time: trade.lock_time.unwrap_or_default(),
+        delayed_payout_tx: trade.delayed_payout_tx.clone().unwrap_or_default(),
+        refund_payout: trade.refund_payout.map(|payout| StoredMediatedPayout {
+            buyer_payout: payout.buyer_payout,
+            seller_payout: payout.seller_payout,
+        }),
+        prepared_deposit_tx: trade.prepared_deposit_tx.clone().unwrap_or_default(),
+        chat: trade
+            .chat
+            .iter()
+            .map(|line| StoredChatLine {
+                uid: line.uid.clone(),
+                party: line.party.to_string(),
+                sent: line.sent,
+                text: line.text.clone(),
+                date: millis(line.date),
+                delivery: line.delivery.to_string(),
+            })
+            .collect(),
+        created_at: millis(trade.created_at),
+    }
+}
+
+fn restore(stored: StoredTrade) -> Result<Trade, String> {
+    let hash = sha256::Hash::from_slice(&stored.offer_hash).map_err(|e| e.to_string())?;
+    // The offer is rebuilt like it arrived from the network, its sequence doesn't matter anymore
+    let entry = ProtectedStorageEntry {
+        storage_payload: Some(StoragePayload {
+            message: Some(storage_payload::Message::OfferPayload(
+                stored.offer.ok_or("Trade without offer")?,
+            )),
+        }),
+        creation_time_stamp: stored.offer_created_at as i64,
+        ..Default::default()
+    };
+    let offer = convert::open_offer(entry, SequencedMessageHash::new(hash))
+        .ok_or("Trade with unsupported offer")?;
+    let price =
+        NumberWithPrecision::new(stored.price, offer.market.right.bisq_internal_precision());
+    let mut trade = Trade::new(
+        stored.role.parse()?,
+        offer,
+        NumberWithPrecision::new(stored.amount, BTC_PRECISION),
+        price,
+        stored.peer.ok_or("Trade without peer")?,
+        stored.peer_pub_key_ring.ok_or("Trade without peer keys")?,
+    );
+    trade.peer_capabilities = stored.peer_capabilities;
+    trade.mediator = stored.mediator;
+    trade.refund_agent = stored.refund_agent;
+    trade.payment_account_id = Some(stored.payment_account_id).filter(|id| !id.is_empty());
+    trade.peer_payment_account = stored.peer_payment_account;
+    trade.taker_fee_tx_id = Some(stored.taker_fee_tx_id).filter(|id| !id.is_empty());
+    trade.taker_fee = Some(stored.taker_fee).filter(|fee| *fee != 0);
+    trade.deposit_tx_id = Some(stored.deposit_tx_id).filter(|id| !id.is_empty());
+    trade.payout_tx_id = Some(stored.payout_tx_id).filter(|id| !id.is_empty());
+    trade.multi_sig_pub_key = Some(stored.multi_sig_pub_key).filter(|key| !key.is_empty());
+    trade.payout_address = Some(stored.payout_address).filter(|addr| !addr.is_empty());
+    trade.peer_multi_sig_pub_key =
+        Some(stored.peer_multi_sig_pub_key).filter(|key| !key.is_empty());
+    trade.peer_payout_address = Some(stored.peer_payout_address).filter(|addr| !addr.is_empty());
+    trade.deposit_tx = Some(stored.deposit_tx).filter(|tx| !tx.is_empty());
+    trade.peer_payout_signature = Some(stored.peer_payout_signature).filter(|sig| !sig.is_empty());
+    trade.mediated_payout = stored.mediated_payout.map(|payout| MediatedPayout {
+        buyer_payout: payout.buyer_payout,
+        seller_payout: payout.seller_payout,
+    });
+    trade.mediated_payout_signature =
+        Some(stored.mediated_payout_signature).filter(|sig| !sig.is_empty());
+    trade.peer_mediated_payout_signature =
+        Some(stored.peer_mediated_payout_signature).filter(|sig| !sig.is_empty());
+    trade.lock_time = Some(stored.lock_time).filter(|lock_time| *lock_time != 0);
+    trade.delayed_payout_tx = Some(stored.delayed_payout_tx).filter(|tx| !tx.is_empty());
+    trade.refund_payout = stored.refund_payout.map(|payout| MediatedPayout {
+        buyer_payout: payout.buyer_payout,
+        seller_payout: payout.seller_payout,
+    });
+    trade.prepared_deposit_tx = Some(stored.prepared_deposit_tx).filter(|tx| !tx.is_empty());
+    trade.chat = stored
+        .chat
+        .into_iter()
+        .map(|line| {
+            Ok(ChatLine {
+                uid: line.uid,
+                party: line.party.parse()?,
+                sent: line.sent,
+                text: line.text,
+                date: from_millis(line.date),
+                delivery: line.delivery.parse()?,
+            })
+        })
+        .collect::<Result<_, String>>()?;
+    trade.created_at = from_millis(stored.created_at);
+    let state: TradeState = stored.state.parse()?;
+    Ok(trade.restore(
+        state,
+        from_millis(stored.state_changed_at),
+        Some(stored.error).filter(|error| !error.is_empty()),
+    ))
+}
+
+fn millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_millis() as u64
+}
+
+fn from_millis(millis: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bisq::{crypto::KeyRing, payload::offer_payload},
+        domain::trade::{ChatDelivery, ChatParty, TradeRole},
+    };
+    use std::{env, process};
+
+    #[test]
+    fn save_and_load() {
+        let payload = OfferPayload {
+            id: "offer".into(),
+            direction: offer_payload::Direction::Sell as i32,
+            base_currency_code: "BTC".into(),
+            counter_currency_code: "EUR".into(),
+            price: 90_000_000,
+            amount: 1_000_000,
+            min_amount: 500_000,
+            ..Default::default()
+        };
+        let entry = ProtectedStorageEntry {
+            storage_payload: Some(StoragePayload {
+                message: Some(storage_payload::Message::OfferPayload(payload)),
+            }),
+            creation_time_stamp: 1_500_000_000_000,
+            ..Default::default()
+        };
+        let offer = convert::open_offer(
+            entry,
+            SequencedMessageHash::new(sha256::Hash::hash(b"offer")),
+        )
+        .unwrap();
+        let mut trade = Trade::new(
+            TradeRole::Taker,
+            offer,
+            NumberWithPrecision::new(1, 2),
+            NumberWithPrecision::new(90_000_000, 4),
+            "peer.onion:9999".parse().unwrap(),
+            KeyRing::generate().unwrap().pub_key_ring(),
+        );
+        trade.taker_fee_tx_id = Some("fee".into());
+        trade.taker_fee = Some(5_000);
+        trade.peer_capabilities = vec![12, 13];
+        trade.multi_sig_pub_key = Some(vec![2; 33]);
+        trade.advance(TradeState::TakerPublishedTakerFeeTx).unwrap();
+        trade.chat.push(ChatLine {
+            uid: "chat".into(),
+            party: ChatParty::Mediator,
+            sent: true,
+            text: "Hello".into(),
+            date: from_millis(1_500_000_000_000),
+            delivery: ChatDelivery::StoredInMailbox,
+        });
+
+        let path = env::temp_dir().join(format!("risq-trades-{}", process::id()));
+        let store = TradeStore::new(path.clone());
+        store.save(vec![&trade].into_iter()).unwrap();
+        let loaded = store.load().unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(loaded.len(), 1);
+        let loaded = &loaded[0];
+        assert_eq!(loaded.id(), trade.id());
+        assert_eq!(loaded.role, TradeRole::Taker);
+        assert_eq!(loaded.state(), TradeState::TakerPublishedTakerFeeTx);
+        assert_eq!(loaded.amount, trade.amount);
+        assert_eq!(loaded.price, trade.price);
+        assert_eq!(loaded.taker_fee_tx_id, trade.taker_fee_tx_id);
+        assert_eq!(loaded.taker_fee, Some(5_000));
+        assert_eq!(loaded.deposit_tx_id, None);
+        assert_eq!(loaded.multi_sig_pub_key, trade.multi_sig_pub_key);
+        assert_eq!(loaded.peer_multi_sig_pub_key, None);
+        assert_eq!(loaded.peer_pub_key_ring, trade.peer_pub_key_ring);
+        assert_eq!(loaded.peer_capabilities, trade.peer_capabilities);
+        assert_eq!(loaded.chat.len(), 1);
+        assert_eq!(loaded.chat[0].party, ChatParty::Mediator);
+        assert_eq!(loaded.chat[0].delivery, ChatDelivery::StoredInMailbox);
+        assert_eq!(loaded.chat[0].date, trade.chat[0].date);
+    }
+}
@@ -0,0 +1,535 @@
+use super::{now_millis, refund::delayed_payout, txid, TradeManager};
+use crate::{
+    bisq::{constants::LOCAL_CAPABILITIES, payload::*, SequencedMessageHash},
+    domain::{
+        amount::NumberWithPrecision,
+        format::BTC_PRECISION,
+        offer::{message::GetOpenOffers, OfferId, OpenOffer},
+        payment_account::validate_peer_account,
+        trade::{
+            message::{CheckAvailability, TakeOffer},
+            protocol::TRADE_PROTOCOL_VERSION,
+            wallet::TakerFunding,
+            Trade, TradeRole, TradeState,
+        },
+    },
+    prelude::{fut::Either, sync::oneshot, *},
+    wallet::tx,
+};
+use bitcoin::consensus;
+use std::{collections::HashMap, time::Duration};
+use uuid::Uuid;
+
+/// Makers answer over tor, which can take a while for a new circuit
+const AVAILABILITY_TIMEOUT: Duration = Duration::from_secs(60);
+
+impl Handler<TakeOffer> for TradeManager {
+    type Result = ResponseActFuture<Self, Trade, String>;
+    fn handle(
+        &mut self,
+        TakeOffer { offer_id, amount }: TakeOffer,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        Box::new(
+            fut::wrap_future(self.offer_book.send(GetOpenOffers))
+                .map_err(|e, _, _| format!("Couldn't query the offer book: {}", e))
+                .and_then(move |offers, manager: &mut Self, ctx| {
+                    fut::result(manager.take_offer(&offers, offer_id, amount, ctx))
+                }),
+        )
+    }
+}
+
+impl Handler<CheckAvailability> for TradeManager {
+    type Result = ResponseActFuture<Self, AvailabilityResult, String>;
+    fn handle(
+        &mut self,
+        CheckAvailability(offer_id): CheckAvailability,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        Box::new(
+            fut::wrap_future(self.offer_book.send(GetOpenOffers))
+                .map_err(|e, _, _| format!("Couldn't query the offer book: {}", e))
+                .and_then(move |offers, manager: &mut Self, _| {
+                    manager.check_availability(&offers, offer_id)
+                }),
+        )
+    }
+}
+
+impl TradeManager {
+    fn check_availability(
+        &mut self,
+        offers: &HashMap<SequencedMessageHash, OpenOffer>,
+        id: OfferId,
+    ) -> impl ActorFuture<Item = AvailabilityResult, Error = String, Actor = Self> {
+        if let Some(offer) = self.own_offer(offers, &id) {
+            let price = offer.display_price.base_amount();
+            return Either::A(fut::ok(self.availability(
+                Some(&offer),
+                price,
+                &LOCAL_CAPABILITIES,
+            )));
+        }
+        let offer = match offers.values().find(|offer| offer.id == id) {
+            Some(offer) => offer,
+            None => {
+                return Either::A(fut::err(format!(
+                    "Offer {} is not in the offer book",
+                    String::from(id)
+                )))
+            }
+        };
+        let (maker, maker_keys) = match (
+            offer.payload.owner_node_address.clone(),
+            offer.payload.pub_key_ring.clone(),
+        ) {
+            (Some(maker), Some(keys)) => (maker, keys),
+            _ => return Either::A(fut::err("Offer has no maker address or keys".into())),
+        };
+        let request = OfferAvailabilityRequest {
+            offer_id: id.clone().into(),
+            pub_key_ring: Some(self.key_ring.pub_key_ring()),
+            takers_trade_price: offer.display_price.base_amount() as i64,
+            supported_capabilities: LOCAL_CAPABILITIES.clone(),
+            uid: Uuid::new_v4().to_string(),
+        };
+        let (answer, answered) = oneshot::channel();
+        self.availability_checks
+            .entry(id.clone())
+            .or_default()
+            .push((maker_keys.signature_pub_key_bytes.clone(), answer));
+        debug!("Checking availability of offer {:?} with {}", id, maker);
+        Either::B(
+            self.send_sealed_to(maker, &maker_keys, request.into())
+                .and_then(|(), _, _| {
+                    fut::wrap_future(answered)
+                        .map_err(|_, _, _| "The availability check was dropped".to_string())
+                })
+                .timeout(AVAILABILITY_TIMEOUT, "The maker didn't answer".to_string())
+                .then(move |result, manager: &mut Self, _| {
+                    if let Some(checks) = manager.availability_checks.get_mut(&id) {
+                        checks.retain(|(_, answer)| !answer.is_closed());
+                        if checks.is_empty() {
+                            manager.availability_checks.remove(&id);
+                        }
+                    }
+                    fut::result(result)
+                }),
+        )
+    }
+
+    pub(super) fn on_availability_checked(
+        &mut self,
+        id: &OfferId,
+        sig_pub_key: &[u8],
+        response: &OfferAvailabilityResponse,
+    ) {
+        let checks = match self.availability_checks.remove(id) {
+            Some(checks) => checks,
+            None => return,
+        };
+        let result = AvailabilityResult::from_i32(response.availability_result)
+            .unwrap_or(AvailabilityResult::UnknownFailure);
+        let (answered, waiting): (Vec<_>, Vec<_>) = checks
+            .into_iter()
+            .partition(|(maker_key, _)| maker_key[..] == *sig_pub_key);
+        for (_, answer) in answered {
+            let _ = answer.send(result);
+        }
+        if !waiting.is_empty() {
+            self.availability_checks.insert(id.clone(), waiting);
+        }
+    }
+
+    fn take_offer(
+        &mut self,
+        offers: &HashMap<SequencedMessageHash, OpenOffer>,
+        id: OfferId,
+        amount: Option<NumberWithPrecision>,
+        ctx: &mut Context<Self>,
+    ) -> Result<Trade, String> {
+        if self.trades.contains_key(&id) {
+            return Err(format!("Offer {} was already taken", String::from(id)));
+        }
+        let offer = offers
+            .values()
+            .find(|offer| offer.id == id)
+            .cloned()
+            .ok_or_else(|| {
+                format!(
+                    "Offer {} is not in the offer book",
+                    String::from(id.clone())
+                )
+            })?;
+        if offer.payload.protocol_version != TRADE_PROTOCOL_VERSION {
+            return Err(format!(
+                "The maker's bisq {} uses trade protocol version {}, only version {} is supported",
+                offer.payload.version_nr, offer.payload.protocol_version, TRADE_PROTOCOL_VERSION
+            ));
+        }
+        let amount = amount.unwrap_or_else(|| offer.amount.total.number());
+        if amount < offer.amount.min.number() || amount > offer.amount.total.number() {
+            return Err(format!(
+                "Amount must be between {} and {} BTC",
+                offer.amount.min.format(),
+                offer.amount.total.format()
+            ));
+        }
+        if offer.display_price.base_amount() == 0 {
+            return Err("No market price available to take the offer".into());
+        }
+        let maker = offer
+            .payload
+            .owner_node_address
+            .clone()
+            .ok_or("Offer has no maker address")?;
+        let maker_keys = offer
+            .payload
+            .pub_key_ring
+            .clone()
+            .ok_or("Offer has no maker keys")?;
+        let account = self.payment_accounts.find(&offer).ok_or_else(|| {
+            format!(
+                "No {} account in {} to take the offer",
+                offer.payment_method,
+                offer.market.non_btc_side().code
+            )
+        })?;
+        let price = offer.display_price;
+        let mut trade = Trade::new(TradeRole::Taker, offer, amount, price, maker, maker_keys);
+        trade.payment_account_id = Some(account.id);
+
+        info!("Taking offer {:?} from {}", id, trade.peer);
+        self.insert(trade.clone());
+        let request = OfferAvailabilityRequest {
+            offer_id: id.clone().into(),
+            pub_key_ring: Some(self.key_ring.pub_key_ring()),
+            takers_trade_price: price.base_amount() as i64,
+            supported_capabilities: LOCAL_CAPABILITIES.clone(),
+            uid: Uuid::new_v4().to_string(),
+        };
+        self.send_sealed(&id, request, ctx);
+        Ok(trade)
+    }
+
+    pub(super) fn on_availability_response(
+        &mut self,
+        id: &OfferId,
+        response: OfferAvailabilityResponse,
+        ctx: &mut Context<Self>,
+    ) {
+        if !self.in_state(id, TradeState::Preparation) {
+            return;
+        }
+        match AvailabilityResult::from_i32(response.availability_result) {
+            Some(AvailabilityResult::Available) => (),
+            Some(AvailabilityResult::MissingMandatoryCapability) => {
+                return self.fail(
+                    id,
+                    "The maker's client requires a newer trade protocol".into(),
+                )
+            }
+            result => {
+                return self.fail(
+                    id,
+                    format!(
+                        "Offer is not available: {:?}",
+                        result.unwrap_or(AvailabilityResult::UnknownFailure)
+                    ),
+                )
+            }
+        }
+        let wallet = match self.wallet.clone() {
+            Some(wallet) => wallet,
+            None => return self.fail(id, "No wallet configured to fund the trade".into()),
+        };
+        let trade = match self.trades.get_mut(id) {
+            Some(trade) => {
+                trade.peer_capabilities = response.supported_capabilities;
+                trade.mediator = response.mediator;
+                trade.refund_agent = response.refund_agent;
+                trade.clone()
+            }
+            None => return,
+        };
+        match trade.protocol() {
+            Ok(variant) => info!("Trading offer {:?} with the {:?} protocol", id, variant),
+            Err(e) => return self.fail(id, format!("Can't trade with the maker: {}", e)),
+        }
+        let id = id.clone();
+        ctx.spawn(fut::wrap_future(wallet.fund_taker(&trade)).then(
+            move |funding, manager: &mut Self, ctx| {
+                match funding {
+                    Ok(funding) => manager.send_inputs_for_deposit_tx(&id, funding, ctx),
+                    Err(e) => manager.fail(&id, format!("Couldn't fund the trade: {}", e)),
+                }
+                fut::ok(())
+            },
+        ));
+    }
+
+    fn send_inputs_for_deposit_tx(
+        &mut self,
+        id: &OfferId,
+        funding: TakerFunding,
+        ctx: &mut Context<Self>,
+    ) {
+        if let Some(trade) = self.trades.get_mut(id) {
+            trade.taker_fee_tx_id = Some(funding.taker_fee_tx_id.clone());
+            trade.taker_fee = Some(funding.taker_fee);
+            trade.multi_sig_pub_key = Some(funding.multi_sig_pub_key.clone());
+            trade.payout_address = Some(funding.payout_address.clone());
+        }
+        let trade = match self.advance(id, TradeState::TakerPublishedTakerFeeTx) {
+            Some(trade) => trade.clone(),
+            None => return,
+        };
+        let account = match self.payment_account(id) {
+            Ok(account) => account,
+            Err(e) => return self.fail(id, e),
+        };
+        let offer_id: String = id.clone().into();
+        let signature = match self.key_ring.sign(offer_id.as_bytes()) {
+            Ok(signature) => signature,
+            Err(e) => return self.fail(id, format!("Couldn't sign the offer id: {}", e)),
+        };
+        let request = InputsForDepositTxRequest {
+            trade_id: offer_id,
+            sender_node_address: self.status.local_addr(),
+            trade_amount: trade.amount.with_precision(BTC_PRECISION).base_amount() as i64,
+            trade_price: trade.price.base_amount() as i64,
+            tx_fee: funding.tx_fee as i64,
+            taker_fee: funding.taker_fee as i64,
+            is_currency_for_taker_fee_btc: true,
+            raw_transaction_inputs: funding.inputs,
+            change_output_value: funding.change_output_value as i64,
+            change_output_address: funding.change_output_address.unwrap_or_default(),
+            taker_multi_sig_pub_key: funding.multi_sig_pub_key,
+            taker_payout_address_string: funding.payout_address,
+            taker_pub_key_ring: Some(self.key_ring.pub_key_ring()),
+            taker_fee_tx_id: funding.taker_fee_tx_id,
+            taker_payment_account_payload: Some(account.payload()),
+            taker_account_id: account.id,
+            accepted_mediator_node_addresses: trade.mediator.iter().cloned().collect(),
+            mediator_node_address: trade.mediator.clone(),
+            accepted_refund_agent_node_addresses: trade.refund_agent.iter().cloned().collect(),
+            refund_agent_node_address: trade.refund_agent.clone(),
+            uid: Uuid::new_v4().to_string(),
+            account_age_witness_signature_of_offer_id: signature,
+            current_date: now_millis(),
+            ..Default::default()
+        };
+        self.send_sealed(id, request, ctx);
+    }
+
+    pub(super) fn on_inputs_for_deposit_tx_response(
+        &mut self,
+        id: &OfferId,
+        response: InputsForDepositTxResponse,
+        ctx: &mut Context<Self>,
+    ) {
+        if !self.in_state(id, TradeState::TakerPublishedTakerFeeTx) {
+            return;
+        }
+        let invalid_account = self.trades.get(id).and_then(|trade| {
+            let payload = response.maker_payment_account_payload.as_ref()?;
+            validate_peer_account(payload, trade.offer.market.non_btc_side()).err()
+        });
+        if let Some(e) = invalid_account {
+            return self.fail(id, e);
+        }
+        if let Some(trade) = self.trades.get_mut(id) {
+            trade.prepared_deposit_tx = Some(response.prepared_deposit_tx);
+            trade.lock_time = Some(response.lock_time as u32).filter(|lock_time| *lock_time != 0);
+            trade.peer_multi_sig_pub_key = Some(response.maker_multi_sig_pub_key);
+            trade.peer_payout_address = Some(response.maker_payout_address_string);
+            trade.peer_payment_account = response.maker_payment_account_payload;
+        }
+        if self
+            .advance(id, TradeState::TakerReceivedPublishDepositTxRequest)
+            .is_none()
+        {
+            return;
+        }
+        self.send_ack(id, response.uid, "InputsForDepositTxResponse", ctx);
+        self.sign_deposit_tx(id, ctx);
+    }
+
+    pub(super) fn sign_deposit_tx(&mut self, id: &OfferId, ctx: &mut Context<Self>) {
+        let wallet = match self.wallet.clone() {
+            Some(wallet) => wallet,
+            None => return self.fail(id, "No wallet configured to sign the deposit tx".into()),
+        };
+        let (trade, prepared_deposit_tx) = match self.trades.get(id) {
+            Some(trade) => match trade.prepared_deposit_tx.clone() {
+                Some(tx) => (trade.clone(), tx),
+                None => return self.fail(id, "The prepared deposit tx is missing".into()),
+            },
+            None => return,
+        };
+        let id = id.clone();
+        ctx.spawn(
+            fut::wrap_future(wallet.sign_deposit_tx(&trade, &prepared_deposit_tx)).then(
+                move |signed, manager: &mut Self, ctx| {
+                    match signed {
+                        Ok(deposit_tx) => {
+                            if let Some(trade) = manager.trades.get_mut(&id) {
+                                trade.prepared_deposit_tx = None;
+                                trade.deposit_tx = Some(deposit_tx.clone());
+                            }
+                            if manager
+                                .advance(&id, TradeState::TakerSignedDepositTx)
+                                .is_some()
+                            {
+                                let msg = DepositTxMessage {
+                                    uid: Uuid::new_v4().to_string(),
+                                    trade_id: id.clone().into(),
+                                    sender_node_address: manager.status.local_addr(),
+                                    deposit_tx,
+                                };
+                                manager.send_sealed(&id, msg, ctx);
+                            }
+                        }
+                        Err(e) => manager.fail(&id, format!("Couldn't sign the deposit tx: {}", e)),
+                    }
+                    fut::ok(())
+                },
+            ),
+        );
+    }
+
+    pub(super) fn on_delayed_payout_tx_signature_request(
+        &mut self,
+        id: &OfferId,
+        request: DelayedPayoutTxSignatureRequest,
+        ctx: &mut Context<Self>,
+    ) {
+        if !self.in_state(id, TradeState::TakerSignedDepositTx) {
+            return;
+        }
+        self.send_ack(id, request.uid, "DelayedPayoutTxSignatureRequest", ctx);
+        let wallet = match self.wallet.clone() {
+            Some(wallet) => wallet,
+            None => {
+                return self.fail(
+                    id,
+                    "No wallet configured to sign the delayed payout tx".into(),
+                )
+            }
+        };
+        let trade = match self.trades.get(id) {
+            Some(trade) => trade.clone(),
+            None => return,
+        };
+        // Whatever the maker sent, we only sign the tx we expect
+        let delayed_payout_tx = match delayed_payout(&trade, self.network) {
+            Ok((expected, _, _))
+                if consensus::serialize(&expected) == request.delayed_payout_tx =>
+            {
+                request.delayed_payout_tx
+            }
+            Ok(_) => return self.fail(id, "Maker sent an unexpected delayed payout tx".into()),
+            Err(e) => return self.fail(id, format!("Couldn't check the delayed payout tx: {}", e)),
+        };
+        let lock_time = trade.lock_time.unwrap_or_default();
+        let delay = tx::lock_time_delay(&trade, self.network);
+        let signing = {
+            let wallet = wallet.clone();
+            let delayed_payout_tx = delayed_payout_tx.clone();
+            wallet
+                .block_height()
+                .map_err(|e| format!("Couldn't get the block height: {}", e))
+                .and_then(move |height| {
+                    let expected = height + delay;
+                    if lock_time + tx::LOCK_TIME_TOLERANCE < expected
+                        || lock_time > expected + tx::LOCK_TIME_TOLERANCE
+                    {
+                        return Err(format!(
+                            "Lock time {} is too far from block {}",
+                            lock_time, expected
+                        ));
+                    }
+                    Ok(())
+                })
+                .and_then(move |_| {
+                    wallet
+                        .sign_payout_tx(&trade, &delayed_payout_tx)
+                        .map_err(|e| format!("Couldn't sign the delayed payout tx: {}", e))
+                })
+        };
+        let id = id.clone();
+        ctx.spawn(
+            fut::wrap_future(signing).then(move |signed, manager: &mut Self, ctx| {
+                match signed {
+                    Ok(signature) => {
+                        manager.delayed_payout_tx_signed(&id, delayed_payout_tx, signature, ctx)
+                    }
+                    Err(e) => manager.fail(&id, e),
+                }
+                fut::ok(())
+            }),
+        );
+    }
+
+    fn delayed_payout_tx_signed(
+        &mut self,
+        id: &OfferId,
+        delayed_payout_tx: Vec<u8>,
+        delayed_payout_tx_signature: Vec<u8>,
+        ctx: &mut Context<Self>,
+    ) {
+        if let Some(trade) = self.trades.get_mut(id) {
+            trade.delayed_payout_tx = Some(delayed_payout_tx);
+        }
+        if self
+            .advance(id, TradeState::TakerSignedDelayedPayoutTx)
+            .is_none()
+        {
+            return;
+        }
+        let response = DelayedPayoutTxSignatureResponse {
+            uid: Uuid::new_v4().to_string(),
+            trade_id: id.clone().into(),
+            sender_node_address: self.status.local_addr(),
+            delayed_payout_tx_signature,
+        };
+        self.send_sealed(id, response, ctx);
+    }
+
+    pub(super) fn on_deposit_tx_published(
+        &mut self,
+        id: &OfferId,
+        msg: DepositTxAndDelayedPayoutTxMessage,
+        ctx: &mut Context<Self>,
+    ) {
+        if !self.in_state(id, TradeState::TakerSignedDelayedPayoutTx) {
+            return;
+        }
+        // The final tx only adds the witness to the one we signed
+        let valid_delayed_payout = self
+            .trades
+            .get(id)
+            .and_then(|trade| trade.delayed_payout_tx.as_ref())
+            .map(|signed| txid(signed).ok() == txid(&msg.delayed_payout_tx).ok())
+            .unwrap_or(false);
+        let txid = match txid(&msg.deposit_tx) {
+            Ok(txid) => txid,
+            Err(e) => return self.fail(id, format!("Received an invalid deposit tx: {}", e)),
+        };
+        if let Some(trade) = self.trades.get_mut(id) {
+            trade.deposit_tx_id = Some(txid);
+            trade.deposit_tx = Some(msg.deposit_tx);
+            if valid_delayed_payout {
+                trade.delayed_payout_tx = Some(msg.delayed_payout_tx);
+            }
+        }
+        if self.advance(id, TradeState::DepositPublished).is_none() {
+            return;
+        }
+        self.send_ack(id, msg.uid, "DepositTxAndDelayedPayoutTxMessage", ctx);
+        if !valid_delayed_payout {
+            self.fail(id, "Maker sent an invalid delayed payout tx".into());
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use super::{now_millis, TradeManager};
+use crate::{
+    bisq::{crypto::KeyRing, payload::*},
+    domain::{
+        format::BTC_PRECISION,
+        offer::{OfferDirection, OfferId},
+        payment_account::peer_age_witness_hash,
+        trade::{protocol::ProtocolVariant, Trade},
+    },
+    p2p::message::Broadcast,
+    prelude::*,
+};
+use std::time::SystemTime;
+
+/// Smaller trades don't get the buyer's account signed, bisq's
+/// `MINIMUM_TRADE_AMOUNT_FOR_SIGNING` (0.0025 BTC).
+const MIN_SIGNING_AMOUNT: u64 = 250_000;
+
+impl TradeManager {
+    /// Once the payment of a fiat trade arrived, the seller signs the
+    /// account of the buyer if its own account is a signer. The signed
+    /// witness is published and sent along with the payout.
+    pub(super) fn sign_peers_witness(&self, id: &OfferId) -> Option<SignedWitness> {
+        let trade = self.trades.get(id)?;
+        let peer_witness_hash = self.witness_to_sign(trade)?;
+        let amount = trade.amount.with_precision(BTC_PRECISION).base_amount();
+        match sign_witness(
+            &self.key_ring,
+            peer_witness_hash,
+            &trade.peer_pub_key_ring,
+            amount,
+        ) {
+            Ok(witness) => {
+                info!("Signed the payment account of the buyer of trade {:?}", id);
+                self.publish_witness(witness.clone());
+                Some(witness)
+            }
+            Err(e) => {
+                warn!("Couldn't sign the account of trade {:?}: {}", id, e);
+                None
+            }
+        }
+    }
+
+    // Like bisq's `isSignWitnessTrade`
+    fn witness_to_sign(&self, trade: &Trade) -> Option<Vec<u8>> {
+        if trade.direction() != OfferDirection::Sell
+            || trade.protocol() != Ok(ProtocolVariant::AccountSigning)
+        {
+            return None;
+        }
+        let payment_method = trade.offer.payment_method.supported()?;
+        let amount = trade.amount.with_precision(BTC_PRECISION).base_amount();
+        if !payment_method.has_chargeback_risk() || amount < MIN_SIGNING_AMOUNT {
+            return None;
+        }
+        let account = self
+            .payment_accounts
+            .get(trade.payment_account_id.as_ref()?)?;
+        let own_witness_hash = account.age_witness_hash(&self.key_ring.pub_key_ring());
+        if !self
+            .signed_witnesses
+            .is_signer(&own_witness_hash, SystemTime::now())
+        {
+            return None;
+        }
+        let peer_witness_hash = peer_age_witness_hash(
+            trade.peer_payment_account.as_ref()?,
+            &trade.peer_pub_key_ring,
+        )?;
+        if self.signed_witnesses.is_signed(&peer_witness_hash) {
+            return None;
+        }
+        Some(peer_witness_hash)
+    }
+
+    /// The buyer publishes the witness the seller signed for it, in case the
+    /// seller went offline before it reached the network.
+    pub(super) fn on_signed_witness(&self, id: &OfferId, witness: SignedWitness) {
+        let trade = match self.trades.get(id) {
+            Some(trade) if trade.direction() == OfferDirection::Buy => trade,
+            _ => return,
+        };
+        let pub_key_ring = self.key_ring.pub_key_ring();
+        let account = match trade
+            .payment_account_id
+            .as_ref()
+            .and_then(|id| self.payment_accounts.get(id))
+        {
+            Some(account) => account,
+            None => return,
+        };
+        if witness.account_age_witness_hash != account.age_witness_hash(&pub_key_ring)
+            || witness.witness_owner_pub_key != pub_key_ring.signature_pub_key_bytes
+            || witness.signer_pub_key != trade.peer_pub_key_ring.signature_pub_key_bytes
+            || witness.verify().is_none()
+        {
+            return warn!("Seller of trade {:?} sent an invalid signed witness", id);
+        }
+        info!(
+            "The seller of trade {:?} signed payment account {}",
+            id, account.name
+        );
+        self.publish_witness(witness);
+    }
+
+    fn publish_witness(&self, witness: SignedWitness) {
+        // Our own broadcasts don't reach the data router
+        self.signed_witnesses.add(&witness);
+        let msg = AddPersistableNetworkPayloadMessage {
+            payload: Some(PersistableNetworkPayload {
+                message: Some(persistable_network_payload::Message::SignedWitness(witness)),
+            }),
+        };
+        arbiter_spawn!(self.broadcaster.send(Broadcast(msg, None)));
+    }
+}
+
+fn sign_witness(
+    key_ring: &KeyRing,
+    account_age_witness_hash: Vec<u8>,
+    owner: &PubKeyRing,
+    trade_amount: u64,
+) -> Result<SignedWitness, String> {
+    let signature = key_ring
+        .sign(&SignedWitness::signed_data(&account_age_witness_hash))
+        .map_err(|e| format!("Couldn't sign witness: {}", e))?;
+    Ok(SignedWitness {
+        verification_method: signed_witness::VerificationMethod::Trade as i32,
+        account_age_witness_hash,
+        signature,
+        signer_pub_key: key_ring.pub_key_ring().signature_pub_key_bytes,
+        witness_owner_pub_key: owner.signature_pub_key_bytes.clone(),
+        date: now_millis(),
+        trade_amount: trade_amount as i64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::payment_account::{arbitrator_witness, SignedWitnesses};
+    use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+
+    #[test]
+    fn signed_witness_verifies() {
+        let key_ring = KeyRing::generate().unwrap();
+        let hash = vec![7; 20];
+        let mut witness =
+            sign_witness(&key_ring, hash.clone(), &PubKeyRing::default(), 250_000).unwrap();
+        assert!(witness.verify().is_some());
+        let arbitrator = SecretKey::from_slice(&[3; 32]).unwrap();
+        let witnesses = SignedWitnesses::with_arbitrator_keys(vec![PublicKey::from_secret_key(
+            &Secp256k1::signing_only(),
+            &arbitrator,
+        )]);
+        witnesses.add(&witness);
+        assert!(!witnesses.is_signed(&hash));
+
+        // The signer's own account was signed by an arbitrator 60 days ago
+        let signer_hash = vec![6; 20];
+        witnesses.add(&arbitrator_witness(
+            &arbitrator,
+            &signer_hash,
+            key_ring.pub_key_ring().signature_pub_key_bytes,
+            now_millis() - 60 * 24 * 60 * 60 * 1000,
+        ));
+        assert!(witnesses.is_signer(&signer_hash, SystemTime::now()));
+        assert!(witnesses.is_signed(&hash));
+        assert!(witnesses.signed_since(&hash).is_some());
+        assert!(!witnesses.is_signer(&hash, SystemTime::now()));
+
+        witness.account_age_witness_hash = vec![8; 20];
+        assert!(witness.verify().is_none());
+    }
+}
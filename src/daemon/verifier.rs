@@ -0,0 +1,244 @@
+use super::{
+    data_router::{adjust_creation_time, expires_at, protected_entry},
+    sequenced_infos::{Claim, SequencedInfos},
+};
+use crate::{
+    bisq::{payload::*, SequencedMessageHash},
+    metrics,
+    prelude::*,
+};
+
+/// Checking DSA signatures is CPU bound, more threads than cores don't help.
+const VERIFIER_THREADS: usize = 4;
+/// The entries of a `GetDataResponse` are verified in batches of this size,
+/// so the first ones are routed while the rest are still checked.
+pub const VERIFY_BATCH_SIZE: usize = 250;
+
+/// Checks the signatures of storage entries and claims their sequence
+/// numbers on threads of its own, so the thousands of entries a seed node
+/// sends while bootstrapping don't block the arbiter of the `DataRouter`.
+pub struct Verifier(SequencedInfos);
+impl Actor for Verifier {
+    type Context = SyncContext<Self>;
+}
+impl Verifier {
+    pub fn start(infos: SequencedInfos) -> Addr<Self> {
+        SyncArbiter::start(VERIFIER_THREADS, move || Verifier(infos.clone()))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Ingested {
+    /// The signature didn't verify
+    Invalid,
+    /// Expired or not newer than the entry we have
+    Stale,
+    /// Stored with this sequence number, the `DataRouter` delivers it
+    Claimed(SequencedMessageHash, i32),
+}
+
+/// Verifies the entries and claims the sequence numbers of the valid ones.
+pub struct IngestEntries(pub Vec<StorageEntryWrapper>);
+impl Message for IngestEntries {
+    type Result = Vec<(StorageEntryWrapper, Ingested)>;
+}
+impl Handler<IngestEntries> for Verifier {
+    type Result = MessageResult<IngestEntries>;
+    fn handle(
+        &mut self,
+        IngestEntries(entries): IngestEntries,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        MessageResult(ingest(&self.0, verify(entries)))
+    }
+}
+
+fn verify(
+    entries: Vec<StorageEntryWrapper>,
+) -> Vec<(StorageEntryWrapper, Option<SequencedMessageHash>)> {
+    let span = tracing::debug_span!("verify", entries = entries.len() as u64);
+    let _span = span.enter();
+    entries
+        .into_iter()
+        .map(|wrapper| {
+            let hash =
+                protected_entry(&wrapper).and_then(|entry| metrics::verified(entry.verify()));
+            (wrapper, hash)
+        })
+        .collect()
+}
+
+fn ingest(
+    infos: &SequencedInfos,
+    verified: Vec<(StorageEntryWrapper, Option<SequencedMessageHash>)>,
+) -> Vec<(StorageEntryWrapper, Ingested)> {
+    verified
+        .into_iter()
+        .map(|(mut wrapper, hash)| {
+            adjust_creation_time(&mut wrapper);
+            let ingested = match hash {
+                Some(hash) => claim(infos, hash, &wrapper),
+                None => Ingested::Invalid,
+            };
+            (wrapper, ingested)
+        })
+        .collect()
+}
+
+pub(super) fn claim(
+    infos: &SequencedInfos,
+    hash: SequencedMessageHash,
+    wrapper: &StorageEntryWrapper,
+) -> Ingested {
+    let claimed = protected_entry(wrapper).and_then(|entry| {
+        let claim = Claim {
+            hash,
+            sequence: entry.sequence_number,
+            owner_pub_key: entry.owner_pub_key_bytes.clone(),
+            original_payload: entry.storage_payload.as_ref()?,
+            expires_at: Some(expires_at(entry)?),
+        };
+        Some(entry.sequence_number).filter(|_| infos.claim(claim))
+    });
+    match claimed {
+        Some(sequence) => {
+            if let Some(info) = infos.stripe(&hash).get_mut(&hash) {
+                info.entry = Some(wrapper.clone());
+            }
+            Ingested::Claimed(hash, sequence)
+        }
+        None => Ingested::Stale,
+    }
+}
+
+/// Splits `entries` into batches of `VERIFY_BATCH_SIZE`.
+pub fn batches(mut entries: Vec<StorageEntryWrapper>) -> Vec<Vec<StorageEntryWrapper>> {
+    let mut batches = Vec::new();
+    while !entries.is_empty() {
+        let rest = entries.split_off(VERIFY_BATCH_SIZE.min(entries.len()));
+        batches.push(entries);
+        entries = rest;
+    }
+    batches
+}
+
+/// `count` offers as a seed node sends them on bootstrap, signed by one key.
+#[cfg(any(test, feature = "testkit"))]
+pub fn signed_entries(count: usize) -> Vec<StorageEntryWrapper> {
+    use crate::bisq::{crypto::KeyRing, Sha256};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let key_ring = KeyRing::generate().expect("Couldn't generate key ring");
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_millis() as i64;
+    (0..count)
+        .map(|idx| {
+            let payload = StoragePayload {
+                message: Some(storage_payload::Message::OfferPayload(OfferPayload {
+                    id: format!("offer-{}", idx),
+                    pub_key_ring: Some(key_ring.pub_key_ring()),
+                    ..Default::default()
+                })),
+            };
+            let hash = DataAndSeqNrPair {
+                payload: Some(payload.clone()),
+                sequence_number: 1,
+            }
+            .sha256();
+            StorageEntryWrapper {
+                message: Some(storage_entry_wrapper::Message::ProtectedStorageEntry(
+                    ProtectedStorageEntry {
+                        storage_payload: Some(payload),
+                        owner_pub_key_bytes: key_ring.pub_key_ring().signature_pub_key_bytes,
+                        sequence_number: 1,
+                        signature: key_ring
+                            .sign(&hash.into_inner())
+                            .expect("Couldn't sign offer"),
+                        creation_time_stamp: now,
+                    },
+                )),
+            }
+        })
+        .collect()
+}
+
+/// Ingests `entries` in batches on the verifier threads, the way the
+/// `DataRouter` does while bootstrapping.
+#[cfg(any(test, feature = "testkit"))]
+pub fn ingest_on_pool(infos: &SequencedInfos, entries: Vec<StorageEntryWrapper>) -> Vec<Ingested> {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let infos = infos.clone();
+    System::run(move || {
+        let verifier = Verifier::start(infos);
+        Arbiter::spawn(
+            future::join_all(
+                batches(entries)
+                    .into_iter()
+                    .map(move |batch| verifier.send(IngestEntries(batch))),
+            )
+            .then(move |result| {
+                let _ = sender.send(result.unwrap_or_default());
+                System::current().stop();
+                Ok(())
+            }),
+        );
+    })
+    .expect("Couldn't run the verifiers");
+    receiver
+        .recv()
+        .expect("Verifiers stopped without a result")
+        .into_iter()
+        .flatten()
+        .map(|(_, ingested)| ingested)
+        .collect()
+}
+
+/// Ingests `entries` on the calling thread, like the arbiter of the
+/// `DataRouter` did before the verifier threads.
+#[cfg(feature = "testkit")]
+pub fn ingest_on_this_thread(
+    infos: &SequencedInfos,
+    entries: Vec<StorageEntryWrapper>,
+) -> Vec<Ingested> {
+    ingest(infos, verify(entries))
+        .into_iter()
+        .map(|(_, ingested)| ingested)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::budget::Evictions;
+
+    #[test]
+    fn ingests_entries_in_batches() {
+        let mut entries = signed_entries(VERIFY_BATCH_SIZE + 1);
+        if let Some(storage_entry_wrapper::Message::ProtectedStorageEntry(entry)) =
+            entries[0].message.as_mut()
+        {
+            entry.sequence_number = 2;
+        }
+        assert_eq!(batches(entries.clone()).len(), 2);
+        let expected: Vec<_> = verify(entries.clone())
+            .into_iter()
+            .map(|(_, hash)| hash)
+            .collect();
+        assert_eq!(expected[0], None);
+        assert!(expected[1..].iter().all(Option::is_some));
+
+        let infos = SequencedInfos::new(None, Evictions::default());
+        let ingested = ingest_on_pool(&infos, entries.clone());
+        assert_eq!(ingested[0], Ingested::Invalid);
+        for (ingested, hash) in ingested[1..].iter().zip(&expected[1..]) {
+            assert_eq!(*ingested, Ingested::Claimed(hash.unwrap(), 1));
+        }
+        assert_eq!(infos.len(), VERIFY_BATCH_SIZE);
+        // A second sync sends the same entries
+        assert!(ingest_on_pool(&infos, entries)[1..]
+            .iter()
+            .all(|ingested| *ingested == Ingested::Stale));
+    }
+}
@@ -0,0 +1,141 @@
+use super::trade::protocol::BISQ_VERSION;
+use crate::bisq::{
+    constants::{self, BaseCurrencyNetwork},
+    crypto, payload,
+};
+use bitcoin::secp256k1::PublicKey;
+use std::sync::{Arc, RwLock};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Alert {
+    pub message: String,
+    pub version: String,
+    /// Announces a new version rather than a problem
+    pub update_info: bool,
+    signature: String,
+}
+impl Alert {
+    /// An update to a newer version than ours, like the alerts bisq's
+    /// `AlertManager` urges users to update for.
+    pub fn requires_update(&self) -> bool {
+        self.update_info && is_newer(&self.version, BISQ_VERSION)
+    }
+}
+
+/// The alerts the bisq developers currently broadcast.
+#[derive(Clone)]
+pub struct Alerts {
+    keys: Arc<Vec<PublicKey>>,
+    halt_offers: bool,
+    alerts: Arc<RwLock<Vec<Alert>>>,
+}
+impl Alerts {
+    /// With `halt_offers` our offers aren't published while an alert
+    /// requires an update.
+    pub fn new(network: BaseCurrencyNetwork, halt_offers: bool) -> Self {
+        Self::with_keys(
+            crypto::developer_keys(&constants::alert_pub_keys(network)),
+            halt_offers,
+        )
+    }
+
+    fn with_keys(keys: Vec<PublicKey>, halt_offers: bool) -> Self {
+        Self {
+            keys: Arc::new(keys),
+            halt_offers,
+            alerts: Arc::default(),
+        }
+    }
+
+    /// None unless the alert is signed by the alert key, as bisq's
+    /// `AlertManager.verifySignature` checks.
+    pub fn add(&self, alert: &payload::Alert) -> Option<Alert> {
+        if !crypto::verify_signed_message(&self.keys, &alert.message, &alert.signature_as_base64) {
+            return None;
+        }
+        let alert = Alert {
+            message: alert.message.clone(),
+            version: alert.version.clone(),
+            update_info: alert.is_update_info,
+            signature: alert.signature_as_base64.clone(),
+        };
+        let mut alerts = self.alerts.write().expect("Corrupted lock in alerts");
+        if !alerts.contains(&alert) {
+            alerts.push(alert.clone());
+        }
+        Some(alert)
+    }
+
+    pub fn remove(&self, alert: &payload::Alert) {
+        let mut alerts = self.alerts.write().expect("Corrupted lock in alerts");
+        alerts.retain(|current| current.signature != alert.signature_as_base64);
+    }
+
+    pub fn current(&self) -> Vec<Alert> {
+        self.alerts
+            .read()
+            .expect("Corrupted lock in alerts")
+            .clone()
+    }
+
+    /// Why our offers mustn't be published, if they are halted.
+    pub fn halting_offers(&self) -> Option<String> {
+        if !self.halt_offers {
+            return None;
+        }
+        let alerts = self.alerts.read().expect("Corrupted lock in alerts");
+        alerts
+            .iter()
+            .find(|alert| alert.requires_update())
+            .map(|alert| {
+                format!(
+                    "Offers are halted until updating to bisq {}: {}",
+                    alert.version, alert.message
+                )
+            })
+    }
+}
+
+fn is_newer(version: &str, than: &str) -> bool {
+    fn parts(version: &str) -> Vec<u32> {
+        version
+            .split('.')
+            .map(|part| part.trim().parse().unwrap_or(0))
+            .collect()
+    }
+    parts(version) > parts(than)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+
+    #[test]
+    fn halts_offers_for_signed_update_alerts() {
+        let key = SecretKey::from_slice(&[9; 32]).unwrap();
+        let alerts = Alerts::with_keys(
+            vec![PublicKey::from_secret_key(&Secp256k1::signing_only(), &key)],
+            true,
+        );
+        let mut alert = payload::Alert {
+            message: "Please update".into(),
+            version: "99.0.0".into(),
+            is_update_info: true,
+            ..Default::default()
+        };
+        assert_eq!(alerts.add(&alert), None);
+        assert_eq!(alerts.halting_offers(), None);
+
+        alert.signature_as_base64 = crypto::sign_message(&key, &alert.message);
+        assert!(alerts.add(&alert).unwrap().requires_update());
+        assert_eq!(alerts.current().len(), 1);
+        assert!(alerts.halting_offers().is_some());
+
+        alerts.remove(&alert);
+        assert!(alerts.current().is_empty());
+        assert_eq!(alerts.halting_offers(), None);
+        assert!(!is_newer("1.2.9", BISQ_VERSION));
+        assert!(is_newer("1.10.0", "1.9.5"));
+    }
+}
@@ -1,5 +1,7 @@
+use super::format::{self, Locale, BTC_PRECISION};
 use std::cmp::Ordering;
 use std::ops::*;
+use std::str::FromStr;
 
 #[cfg(feature = "statistics")]
 pub const ZERO: NumberWithPrecision = NumberWithPrecision {
@@ -20,30 +22,12 @@ impl NumberWithPrecision {
         }
     }
 
-    pub fn format(&self, target_precision: u32) -> String {
-        let mut ret = String::new();
-        let mut rest_amount = self.base_amount;
-
-        if target_precision > self.precision {
-            rest_amount *= 10_u64.pow(target_precision - self.precision);
-        } else if self.precision > target_precision {
-            rest_amount /= 10_u64.pow(self.precision - target_precision);
-        }
-
-        while ret.len() < target_precision as usize {
-            ret.push(char_of_last_digit(rest_amount));
-            rest_amount /= 10;
-        }
-        ret.push('.');
+    pub fn base_amount(&self) -> u64 {
+        self.base_amount
+    }
 
-        while rest_amount > 0 {
-            ret.push(char_of_last_digit(rest_amount));
-            rest_amount /= 10;
-        }
-        if ret.len() == target_precision as usize + 1 {
-            ret.push('0');
-        }
-        ret.chars().rev().collect()
+    pub fn format(&self, target_precision: u32) -> String {
+        format::number(*self, target_precision, Locale::RAW)
     }
 
     pub fn with_precision(&self, target_precision: u32) -> Self {
@@ -57,19 +41,27 @@ impl NumberWithPrecision {
     }
 }
 
-fn char_of_last_digit(n: u64) -> char {
-    match n % 10 {
-        0 => '0',
-        1 => '1',
-        2 => '2',
-        3 => '3',
-        4 => '4',
-        5 => '5',
-        6 => '6',
-        7 => '7',
-        8 => '8',
-        9 => '9',
-        _ => unimplemented!(),
+impl FromStr for NumberWithPrecision {
+    type Err = String;
+
+    // Parses decimal strings as returned by the api, eg. `9000.12340000`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |_| format!("Invalid number '{}'", s);
+        let mut parts = s.splitn(2, '.');
+        let integer = parts.next().unwrap_or_default();
+        let fraction = parts.next().unwrap_or_default();
+        let precision = fraction.len() as u32;
+        let integer: u64 = integer.parse().map_err(invalid)?;
+        let fraction: u64 = if fraction.is_empty() {
+            0
+        } else {
+            fraction.parse().map_err(invalid)?
+        };
+        integer
+            .checked_mul(10_u64.pow(precision))
+            .and_then(|base| base.checked_add(fraction))
+            .map(|base_amount| Self::new(base_amount, precision))
+            .ok_or_else(|| format!("Number '{}' is too large", s))
     }
 }
 
@@ -165,6 +157,44 @@ impl Ord for NumberWithPrecision {
     }
 }
 
+/// An amount of bitcoin, as bisq keeps them in satoshis.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BtcAmount(u64);
+impl BtcAmount {
+    pub const fn from_sats(sats: u64) -> Self {
+        BtcAmount(sats)
+    }
+
+    pub fn sats(self) -> u64 {
+        self.0
+    }
+
+    /// With the 8 decimals of BTC, to compute with prices.
+    pub fn number(self) -> NumberWithPrecision {
+        NumberWithPrecision::new(self.0, BTC_PRECISION)
+    }
+
+    pub fn format(self) -> String {
+        self.number().format(BTC_PRECISION)
+    }
+}
+impl FromStr for BtcAmount {
+    type Err = String;
+
+    // Parses BTC as given to the api, eg. `0.1`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let number: NumberWithPrecision = s.parse()?;
+        if number.precision > BTC_PRECISION {
+            return Err(format!("'{}' has more than {} decimals", s, BTC_PRECISION));
+        }
+        number
+            .base_amount
+            .checked_mul(10_u64.pow(BTC_PRECISION - number.precision))
+            .map(BtcAmount)
+            .ok_or_else(|| format!("Amount '{}' is too large", s))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -175,7 +205,7 @@ mod tests {
         let right_precision = 8;
 
         let price_base = 9000 * 10_u64.pow(left_precision);
-        let amount_base = 1 * 10_u64.pow(right_precision);
+        let amount_base = 10_u64.pow(right_precision);
         let price = NumberWithPrecision::new(price_base, left_precision);
         let amount = NumberWithPrecision::new(amount_base, right_precision);
 
@@ -193,4 +223,23 @@ mod tests {
         amount += NumberWithPrecision::new(123456789, 8);
         assert!(amount == NumberWithPrecision::new(2234567890, 9));
     }
+
+    #[test]
+    fn parse() {
+        let n: NumberWithPrecision = "9000.12340000".parse().unwrap();
+        assert!(n == NumberWithPrecision::new(90001234, 4));
+        assert!(&n.format(8) == "9000.12340000");
+        assert!("12".parse::<NumberWithPrecision>().unwrap() == NumberWithPrecision::new(12, 0));
+        assert!("1.2.3".parse::<NumberWithPrecision>().is_err());
+        assert!("-1".parse::<NumberWithPrecision>().is_err());
+    }
+
+    #[test]
+    fn parse_btc() {
+        let amount: BtcAmount = "0.1".parse().unwrap();
+        assert_eq!(amount, BtcAmount::from_sats(10_000_000));
+        assert_eq!(amount.format(), "0.10000000");
+        assert!(amount.number() == NumberWithPrecision::new(1, 1));
+        assert!("0.000000001".parse::<BtcAmount>().is_err());
+    }
 }
@@ -0,0 +1,41 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+/// Upper bounds for the in-memory stores. `None` means unbounded.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MemoryBudget {
+    pub max_offers: Option<usize>,
+    pub max_dedup_entries: Option<usize>,
+    pub max_trades: Option<usize>,
+}
+
+/// Counts the entries that have been dropped to stay within the MemoryBudget.
+#[derive(Clone, Default)]
+pub struct Evictions {
+    offers: Arc<AtomicUsize>,
+    dedup_entries: Arc<AtomicUsize>,
+    trades: Arc<AtomicUsize>,
+}
+impl Evictions {
+    pub fn offers(&self) -> usize {
+        self.offers.load(Ordering::Relaxed)
+    }
+    pub fn dedup_entries(&self) -> usize {
+        self.dedup_entries.load(Ordering::Relaxed)
+    }
+    pub fn trades(&self) -> usize {
+        self.trades.load(Ordering::Relaxed)
+    }
+
+    pub fn offers_evicted(&self, n: usize) {
+        self.offers.fetch_add(n, Ordering::Relaxed);
+    }
+    pub fn dedup_entries_evicted(&self, n: usize) {
+        self.dedup_entries.fetch_add(n, Ordering::Relaxed);
+    }
+    pub fn trades_evicted(&self, n: usize) {
+        self.trades.fetch_add(n, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,125 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+/// Offset to the network time above which TTL and offer date validation
+/// start to misbehave.
+pub const MAX_CLOCK_SKEW: Duration = Duration::from_secs(120);
+const MAX_SAMPLES: usize = 25;
+const MIN_SAMPLES: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeSource {
+    /// Creation dates of freshly broadcast data
+    Peer,
+    /// Date header of price node responses
+    PriceNode,
+}
+
+#[derive(Default)]
+struct Samples {
+    peers: VecDeque<i64>,
+    price_nodes: VecDeque<i64>,
+    warned: bool,
+}
+
+/// Estimates the offset of the local clock from the timestamps that
+/// peers and price nodes report.
+#[derive(Clone, Default)]
+pub struct ClockSkew {
+    samples: Arc<RwLock<Samples>>,
+}
+impl ClockSkew {
+    pub fn observe(&self, source: TimeSource, remote: SystemTime) {
+        let offset = offset_millis(remote, SystemTime::now());
+        let mut samples = self.samples.write().expect("Corrupted lock in clock");
+        let queue = match source {
+            TimeSource::Peer => &mut samples.peers,
+            TimeSource::PriceNode => &mut samples.price_nodes,
+        };
+        if queue.len() == MAX_SAMPLES {
+            queue.pop_front();
+        }
+        queue.push_back(offset);
+
+        let exceeded = estimate(&samples).is_some_and(exceeds_max);
+        if exceeded && !samples.warned {
+            warn!(
+                "Local clock is off by {}s compared to peers and price nodes, \
+                 offers and other data may be rejected or expire early",
+                estimate(&samples).unwrap_or_default() / 1000
+            );
+        } else if !exceeded && samples.warned {
+            info!("Local clock is back in sync with the network");
+        }
+        samples.warned = exceeded;
+    }
+
+    /// Median of `remote - local` in milliseconds, ie. positive when the local clock is behind.
+    pub fn offset_millis(&self) -> Option<i64> {
+        estimate(&self.samples.read().expect("Corrupted lock in clock"))
+    }
+
+    pub fn samples(&self) -> usize {
+        let samples = self.samples.read().expect("Corrupted lock in clock");
+        samples.peers.len() + samples.price_nodes.len()
+    }
+
+    pub fn exceeded(&self) -> bool {
+        self.offset_millis().is_some_and(exceeds_max)
+    }
+}
+
+fn estimate(samples: &Samples) -> Option<i64> {
+    let mut offsets: Vec<i64> = samples
+        .peers
+        .iter()
+        .chain(samples.price_nodes.iter())
+        .cloned()
+        .collect();
+    if offsets.len() < MIN_SAMPLES {
+        return None;
+    }
+    offsets.sort();
+    Some(offsets[offsets.len() / 2])
+}
+
+fn exceeds_max(offset: i64) -> bool {
+    u128::from(offset.unsigned_abs()) > MAX_CLOCK_SKEW.as_millis()
+}
+
+fn offset_millis(remote: SystemTime, local: SystemTime) -> i64 {
+    match remote.duration_since(local) {
+        Ok(ahead) => ahead.as_millis() as i64,
+        Err(behind) => -(behind.duration().as_millis() as i64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn median_offset() {
+        let skew = ClockSkew::default();
+        let now = SystemTime::now();
+        skew.observe(TimeSource::Peer, now + Duration::from_secs(300));
+        skew.observe(TimeSource::Peer, now + Duration::from_secs(301));
+        assert_eq!(skew.offset_millis(), None);
+        assert!(!skew.exceeded());
+
+        skew.observe(TimeSource::PriceNode, now - Duration::from_secs(10));
+        skew.observe(TimeSource::PriceNode, now + Duration::from_secs(302));
+        assert_eq!(skew.samples(), 4);
+        assert!(skew.offset_millis().unwrap() >= 300_000);
+        assert!(skew.exceeded());
+
+        for _ in 0..5 {
+            skew.observe(TimeSource::PriceNode, SystemTime::now());
+        }
+        assert!(skew.offset_millis().unwrap().abs() < 1000);
+        assert!(!skew.exceeded());
+    }
+}
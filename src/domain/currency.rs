@@ -1,3 +1,6 @@
+// The list of currencies is pushed one by one
+#![allow(clippy::vec_init_then_push)]
+
 use lazy_static::lazy_static;
 use std::str::FromStr;
 
@@ -25,6 +28,13 @@ impl CurrencyType {
             CurrencyType::Crypto => 8,
         }
     }
+
+    fn display_precision(&self) -> u32 {
+        match self {
+            CurrencyType::Fiat => 2,
+            CurrencyType::Crypto => 8,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -39,6 +49,10 @@ impl Currency {
         self.currency_type.bisq_internal_precision()
     }
 
+    pub fn display_precision(&self) -> u32 {
+        self.currency_type.display_precision()
+    }
+
     pub fn from_code(code: &str) -> Option<&'static Currency> {
         ALL.iter().find(|c| c.code == code)
     }
@@ -707,6 +721,11 @@ lazy_static! {
             name: "Kazakhstani Tenge".to_string(),
             currency_type: CurrencyType::Fiat,
         });
+        vec.push(Currency {
+            code: "L-BTC".to_string(),
+            name: "Liquid Bitcoin".to_string(),
+            currency_type: CurrencyType::Crypto,
+        });
         vec.push(Currency {
             code: "LBC".to_string(),
             name: "LBRY Credits".to_string(),
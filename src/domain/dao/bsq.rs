@@ -0,0 +1,163 @@
+use crate::domain::{
+    amount::NumberWithPrecision,
+    format::{self, Locale, BSQ_PRECISION},
+    offer::{validation::OfferValidator, OpenOffer},
+};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+/// BSQ a tx burnt: what its BSQ inputs held beyond its BSQ outputs. Trade
+/// fees paid in BSQ, like the maker fees of offers, are burnt this way.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BsqFee {
+    pub tx_id: String,
+    pub block_height: u32,
+    /// In satoshis of BSQ
+    pub burnt: u64,
+}
+impl BsqFee {
+    pub fn formatted(&self) -> String {
+        format::bsq(
+            NumberWithPrecision::new(self.burnt, BSQ_PRECISION),
+            Locale::RAW,
+        )
+    }
+}
+
+/// The fees of the BSQ txs the DAO sync has seen so far, shared with the
+/// offer validation and the api.
+#[derive(Clone, Default)]
+pub struct BsqFees {
+    fees: Arc<RwLock<HashMap<String, BsqFee>>>,
+    /// The height of the last synced block
+    height: Arc<RwLock<Option<u32>>>,
+}
+impl BsqFees {
+    pub fn get(&self, tx_id: &str) -> Option<BsqFee> {
+        self.fees
+            .read()
+            .expect("Corrupted lock in bsq fees")
+            .get(tx_id)
+            .cloned()
+    }
+
+    /// Replaces all fees, after new blocks or a reorg.
+    pub fn update(&self, fees: impl Iterator<Item = BsqFee>, height: Option<u32>) {
+        *self.fees.write().expect("Corrupted lock in bsq fees") =
+            fees.map(|fee| (fee.tx_id.clone(), fee)).collect();
+        *self.height.write().expect("Corrupted lock in bsq fees") = height;
+    }
+}
+
+/// Offers that claim to have paid their maker fee in BSQ have to have
+/// burnt at least that much. Fee txs the DAO sync hasn't seen yet pass,
+/// they may be unconfirmed or the sync may be behind.
+pub struct BsqMakerFee(pub BsqFees);
+impl OfferValidator for BsqMakerFee {
+    fn name(&self) -> &'static str {
+        "bsq_maker_fee"
+    }
+    fn validate(&self, offer: &OpenOffer) -> Result<(), String> {
+        if offer.payload.is_currency_for_maker_fee_btc {
+            return Ok(());
+        }
+        match self.0.get(&offer.payload.offer_fee_payment_tx_id) {
+            Some(fee) if fee.burnt < offer.payload.maker_fee as u64 => Err(format!(
+                "maker fee tx {} burnt {} BSQ of {} BSQ",
+                fee.tx_id,
+                fee.formatted(),
+                format::bsq(
+                    NumberWithPrecision::new(offer.payload.maker_fee as u64, BSQ_PRECISION),
+                    Locale::RAW
+                )
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bisq::payload::OfferPayload,
+        domain::{
+            dao::{
+                tests::{block, tx},
+                DaoState, Genesis,
+            },
+            offer::TestOffer,
+        },
+    };
+
+    fn synced_fees() -> BsqFees {
+        let mut state = DaoState::new(Genesis {
+            tx_id: "genesis".into(),
+            block_height: 10,
+            total_supply: 1000,
+        });
+        state
+            .add_block(block(
+                10,
+                vec![tx("genesis", &[], &[(600, "alice"), (400, "bob")])],
+            ))
+            .unwrap();
+        let mut malformed = tx("malformed", &[("genesis", 1)], &[(300, "bob")]);
+        malformed.message = None;
+        state
+            .add_block(block(
+                11,
+                vec![tx("fee", &[("genesis", 0)], &[(550, "alice")]), malformed],
+            ))
+            .unwrap();
+        let fees = BsqFees::default();
+        fees.update(state.fees().cloned(), state.height());
+        fees
+    }
+
+    fn bsq_fee_offer(tx_id: &str, maker_fee: i64) -> OpenOffer {
+        TestOffer::from_payload(OfferPayload {
+            id: "offer".into(),
+            offer_fee_payment_tx_id: tx_id.into(),
+            maker_fee,
+            is_currency_for_maker_fee_btc: false,
+            ..Default::default()
+        })
+        .build()
+    }
+
+    #[test]
+    fn parses_the_fees_of_bsq_txs() {
+        let fees = synced_fees();
+        assert_eq!(
+            fees.get("fee"),
+            Some(BsqFee {
+                tx_id: "fee".into(),
+                block_height: 11,
+                burnt: 50,
+            })
+        );
+        assert_eq!(fees.get("fee").unwrap().formatted(), "0.50");
+        assert_eq!(fees.get("genesis"), None);
+        assert_eq!(fees.get("malformed"), None);
+    }
+
+    #[test]
+    fn validates_bsq_maker_fees() {
+        let validator = BsqMakerFee(synced_fees());
+        assert_eq!(validator.validate(&bsq_fee_offer("fee", 50)), Ok(()));
+        assert_eq!(
+            validator.validate(&bsq_fee_offer("fee", 60)),
+            Err("maker fee tx fee burnt 0.50 BSQ of 0.60 BSQ".into())
+        );
+        assert_eq!(validator.validate(&bsq_fee_offer("malformed", 60)), Ok(()));
+        assert_eq!(validator.validate(&bsq_fee_offer("unknown", 60)), Ok(()));
+
+        let mut btc_fee = bsq_fee_offer("fee", 60);
+        Arc::make_mut(&mut btc_fee.payload).is_currency_for_maker_fee_btc = true;
+        assert_eq!(validator.validate(&btc_fee), Ok(()));
+    }
+}
@@ -0,0 +1,39 @@
+use super::{
+    voting::{CycleResult, Phase},
+    BsqBalance, BsqSupply,
+};
+use crate::prelude::Message;
+
+pub struct DaoStatus {
+    pub genesis_tx_id: String,
+    /// Last block we know of, `None` before the genesis block arrived
+    pub height: Option<u32>,
+    pub bsq_txs: usize,
+    pub unspent_outputs: usize,
+    /// Cycle and phase of the last block
+    pub phase: Option<(u32, Phase)>,
+}
+
+/// Fails when the network has no known DAO genesis.
+pub struct GetDaoStatus;
+impl Message for GetDaoStatus {
+    type Result = Result<DaoStatus, String>;
+}
+
+pub struct GetBsqSupply;
+impl Message for GetBsqSupply {
+    type Result = Result<BsqSupply, String>;
+}
+
+pub struct GetBsqBalance {
+    pub address: String,
+}
+impl Message for GetBsqBalance {
+    type Result = Result<BsqBalance, String>;
+}
+
+/// The results of all cycles that reached their result phase.
+pub struct GetDaoCycles;
+impl Message for GetDaoCycles {
+    type Result = Result<Vec<CycleResult>, String>;
+}
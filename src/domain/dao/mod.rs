@@ -0,0 +1,397 @@
+pub mod bsq;
+pub mod message;
+pub mod voting;
+
+use crate::bisq::{constants::BaseCurrencyNetwork, payload::*};
+use bsq::BsqFee;
+use std::collections::{HashMap, HashSet};
+
+/// The 2.5 million BSQ that were issued in the genesis tx, in satoshis.
+pub const GENESIS_TOTAL_SUPPLY: u64 = 250_000_000;
+
+/// The tx that created all BSQ that existed before the first issuance.
+#[derive(Clone, Debug)]
+pub struct Genesis {
+    pub tx_id: String,
+    pub block_height: u32,
+    pub total_supply: u64,
+}
+impl Genesis {
+    /// Only mainnet has a fixed genesis, on the other networks it has to be
+    /// configured like bisq's `--genesisTxId` and `--genesisBlockHeight`.
+    pub fn for_network(network: BaseCurrencyNetwork) -> Option<Self> {
+        match network {
+            BaseCurrencyNetwork::BtcMainnet => Some(Genesis {
+                tx_id: "81855816eca165f17f0668898faa8724a105196e90ffc4993f4cac980176674e".into(),
+                block_height: 524_717,
+                total_supply: GENESIS_TOTAL_SUPPLY,
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TxOutputKey {
+    pub tx_id: String,
+    pub index: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BsqOutput {
+    pub key: TxOutputKey,
+    pub value: u64,
+    pub address: String,
+}
+
+/// Amounts in satoshis of BSQ.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BsqSupply {
+    pub genesis: u64,
+    pub burnt: u64,
+    /// The sum of all unspent BSQ outputs
+    pub circulating: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BsqBalance {
+    pub balance: u64,
+    pub unspent_outputs: usize,
+}
+
+/// A BSQ tx with an OP_RETURN output, which carries the data of proposals
+/// and votes.
+#[derive(Clone, Debug)]
+pub struct OpReturnTx {
+    pub block_height: u32,
+    pub data: Vec<u8>,
+    /// The BSQ outputs spent by the tx
+    pub spent: Vec<BsqOutput>,
+    /// The BSQ outputs of the tx, the stake of a blind vote is the first one
+    pub outputs: Vec<BsqOutput>,
+    /// The output that becomes BSQ when a compensation request is accepted
+    pub first_btc_output: Option<BsqOutput>,
+    /// Hex encoded key of the first input, which signs the merit of issuances
+    pub pub_key: String,
+}
+
+/// The BSQ txs of all blocks since the genesis, derived from the raw
+/// blocks the way bisq's `TxParser` colors outputs: BSQ inputs are
+/// assigned to the outputs in order as long as they cover the output's
+/// value, what is left over is burnt.
+pub struct DaoState {
+    genesis: Genesis,
+    blocks: Vec<BaseBlock>,
+    txs: HashSet<String>,
+    unspent: HashMap<TxOutputKey, BsqOutput>,
+    burnt: u64,
+    /// By the id of the tx that burnt them
+    fees: HashMap<String, BsqFee>,
+    op_return_txs: HashMap<String, OpReturnTx>,
+}
+impl DaoState {
+    pub fn new(genesis: Genesis) -> Self {
+        Self {
+            genesis,
+            blocks: Vec::new(),
+            txs: HashSet::new(),
+            unspent: HashMap::new(),
+            burnt: 0,
+            fees: HashMap::new(),
+            op_return_txs: HashMap::new(),
+        }
+    }
+
+    pub fn genesis(&self) -> &Genesis {
+        &self.genesis
+    }
+
+    pub fn blocks(&self) -> &[BaseBlock] {
+        &self.blocks
+    }
+
+    pub fn height(&self) -> Option<u32> {
+        self.blocks.last().map(|block| block.height as u32)
+    }
+
+    pub fn next_height(&self) -> u32 {
+        self.height()
+            .map(|height| height + 1)
+            .unwrap_or(self.genesis.block_height)
+    }
+
+    /// Ids of the txs that spent or received BSQ.
+    pub fn txs(&self) -> impl Iterator<Item = &String> {
+        self.txs.iter()
+    }
+
+    pub fn unspent(&self) -> impl Iterator<Item = &BsqOutput> {
+        self.unspent.values()
+    }
+
+    /// The BSQ burnt by the txs that burnt any, eg. trade fees.
+    pub fn fees(&self) -> impl Iterator<Item = &BsqFee> {
+        self.fees.values()
+    }
+
+    pub fn op_return_txs(&self) -> impl Iterator<Item = (&String, &OpReturnTx)> {
+        self.op_return_txs.iter()
+    }
+
+    pub fn op_return_tx(&self, id: &str) -> Option<&OpReturnTx> {
+        self.op_return_txs.get(id)
+    }
+
+    pub fn supply(&self) -> BsqSupply {
+        BsqSupply {
+            genesis: self.genesis.total_supply,
+            burnt: self.burnt,
+            circulating: self.unspent.values().map(|output| output.value).sum(),
+        }
+    }
+
+    /// The unspent BSQ of `address`, which may have the `B` prefix bisq
+    /// shows in front of the BTC address of BSQ outputs.
+    pub fn balance(&self, address: &str) -> BsqBalance {
+        // BTC addresses never start with a capital B
+        let address = address.trim_start_matches('B');
+        let outputs: Vec<&BsqOutput> = self
+            .unspent
+            .values()
+            .filter(|output| output.address == address)
+            .collect();
+        BsqBalance {
+            balance: outputs.iter().map(|output| output.value).sum(),
+            unspent_outputs: outputs.len(),
+        }
+    }
+
+    /// Appends the next block, which has to connect to our last one.
+    pub fn add_block(&mut self, block: BaseBlock) -> Result<(), String> {
+        if block.height < 0 || block.height as u32 != self.next_height() {
+            return Err(format!(
+                "Expected block {} but got {}",
+                self.next_height(),
+                block.height
+            ));
+        }
+        if let Some(tip) = self.blocks.last() {
+            if block.previous_block_hash != tip.hash {
+                return Err(format!(
+                    "Block {} doesn't connect to {}",
+                    block.height, tip.hash
+                ));
+            }
+        }
+        let txs = match block.message.as_ref() {
+            Some(base_block::Message::RawBlock(block)) => &block.raw_txs,
+            Some(base_block::Message::Block(block)) => &block.txs,
+            None => return Err(format!("Block {} has no txs", block.height)),
+        };
+        for tx in txs {
+            self.parse_tx(block.height as u32, tx);
+        }
+        self.blocks.push(block);
+        Ok(())
+    }
+
+    /// Drops all blocks from `height` on, eg. after a reorg.
+    pub fn rewind(&mut self, height: u32) {
+        let blocks: Vec<BaseBlock> = self.blocks.drain(..).collect();
+        self.txs.clear();
+        self.unspent.clear();
+        self.burnt = 0;
+        self.fees.clear();
+        self.op_return_txs.clear();
+        for block in blocks
+            .into_iter()
+            .take_while(|block| (block.height as u32) < height)
+        {
+            self.add_block(block)
+                .expect("Blocks that were added before don't connect anymore");
+        }
+    }
+
+    fn parse_tx(&mut self, block_height: u32, tx: &BaseTx) {
+        let outputs = match tx.message.as_ref() {
+            Some(base_tx::Message::RawTx(tx)) => &tx.raw_tx_outputs,
+            Some(base_tx::Message::Tx(tx)) => &tx.tx_outputs,
+            None => return,
+        };
+        let is_genesis = tx.id == self.genesis.tx_id && block_height == self.genesis.block_height;
+        let spent: Vec<BsqOutput> = tx
+            .tx_inputs
+            .iter()
+            .filter_map(|input| {
+                self.unspent.remove(&TxOutputKey {
+                    tx_id: input.connected_tx_output_tx_id.clone(),
+                    index: input.connected_tx_output_index as u32,
+                })
+            })
+            .collect();
+        let mut available = if is_genesis {
+            self.genesis.total_supply
+        } else {
+            spent.iter().map(|output| output.value).sum()
+        };
+        if available == 0 {
+            return;
+        }
+        let mut bsq_outputs = Vec::new();
+        let mut first_btc_output = None;
+        let mut op_return_data = None;
+        for output in outputs {
+            if !output.op_return_data.is_empty() {
+                op_return_data = Some(output.op_return_data.clone());
+                continue;
+            }
+            let bsq_output = BsqOutput {
+                key: TxOutputKey {
+                    tx_id: tx.id.clone(),
+                    index: output.index as u32,
+                },
+                value: output.value as u64,
+                address: output.address.clone(),
+            };
+            // Once an output doesn't get BSQ, the following ones don't either
+            if first_btc_output.is_none() && available >= bsq_output.value {
+                available -= bsq_output.value;
+                bsq_outputs.push(bsq_output);
+            } else if first_btc_output.is_none() {
+                first_btc_output = Some(bsq_output);
+            }
+        }
+        for output in bsq_outputs.iter() {
+            self.unspent.insert(output.key.clone(), output.clone());
+        }
+        // What is left of the inputs is burnt, like trade fees
+        if !is_genesis && available > 0 {
+            self.burnt += available;
+            self.fees.insert(
+                tx.id.clone(),
+                BsqFee {
+                    tx_id: tx.id.clone(),
+                    block_height,
+                    burnt: available,
+                },
+            );
+        }
+        self.txs.insert(tx.id.clone());
+        if let Some(data) = op_return_data {
+            self.op_return_txs.insert(
+                tx.id.clone(),
+                OpReturnTx {
+                    block_height,
+                    data,
+                    spent,
+                    outputs: bsq_outputs,
+                    first_btc_output,
+                    pub_key: tx
+                        .tx_inputs
+                        .first()
+                        .map(|input| input.pub_key.clone())
+                        .unwrap_or_default(),
+                },
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub(super) fn tx(id: &str, inputs: &[(&str, i32)], outputs: &[(i64, &str)]) -> BaseTx {
+        BaseTx {
+            id: id.into(),
+            tx_inputs: inputs
+                .iter()
+                .map(|(tx_id, index)| TxInput {
+                    connected_tx_output_tx_id: tx_id.to_string(),
+                    connected_tx_output_index: *index,
+                    ..Default::default()
+                })
+                .collect(),
+            message: Some(base_tx::Message::RawTx(RawTx {
+                raw_tx_outputs: outputs
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (value, address))| BaseTxOutput {
+                        index: index as i32,
+                        value: *value,
+                        address: address.to_string(),
+                        tx_id: id.into(),
+                        ..Default::default()
+                    })
+                    .collect(),
+            })),
+            ..Default::default()
+        }
+    }
+
+    pub(super) fn block(height: i32, txs: Vec<BaseTx>) -> BaseBlock {
+        BaseBlock {
+            height,
+            hash: height.to_string(),
+            previous_block_hash: (height - 1).to_string(),
+            message: Some(base_block::Message::RawBlock(RawBlock { raw_txs: txs })),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn colors_bsq_outputs() {
+        let mut state = DaoState::new(Genesis {
+            tx_id: "genesis".into(),
+            block_height: 10,
+            total_supply: 1000,
+        });
+        assert!(state.add_block(block(11, Vec::new())).is_err());
+        state
+            .add_block(block(
+                10,
+                vec![tx("genesis", &[], &[(600, "alice"), (400, "bob")])],
+            ))
+            .unwrap();
+        // Sends 500 BSQ, 90 BSQ change, burns 10 BSQ as fee and the last output is BTC
+        state
+            .add_block(block(
+                11,
+                vec![tx(
+                    "transfer",
+                    &[("genesis", 0), ("btc", 0)],
+                    &[(500, "bob"), (90, "alice"), (5000, "alice")],
+                )],
+            ))
+            .unwrap();
+
+        assert_eq!(state.txs().count(), 2);
+        assert_eq!(state.unspent().count(), 3);
+        assert_eq!(
+            state.supply(),
+            BsqSupply {
+                genesis: 1000,
+                burnt: 10,
+                circulating: 990,
+            }
+        );
+        assert_eq!(
+            state.balance("Bbob"),
+            BsqBalance {
+                balance: 900,
+                unspent_outputs: 2,
+            }
+        );
+        assert_eq!(state.balance("alice").balance, 90);
+        let fee = |tx_id| state.fees().find(|fee| fee.tx_id == tx_id);
+        assert_eq!(fee("transfer").map(|fee| fee.burnt), Some(10));
+        assert_eq!(fee("genesis"), None);
+
+        state.rewind(11);
+        assert_eq!(state.height(), Some(10));
+        assert_eq!(state.supply().burnt, 0);
+        assert_eq!(state.fees().count(), 0);
+        assert_eq!(state.balance("alice").balance, 600);
+        assert!(state.add_block(block(12, Vec::new())).is_err());
+    }
+}
@@ -0,0 +1,734 @@
+use super::{DaoState, OpReturnTx};
+use crate::{
+    bisq::{constants::BaseCurrencyNetwork, crypto, payload::*},
+    prelude::{ripemd160, sha256, FromHex, Hash},
+};
+use bitcoin::secp256k1::{self, PublicKey, Secp256k1, Signature};
+use prost::Message;
+use std::{
+    collections::HashMap,
+    fmt,
+    ops::Range,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// Types of the OP_RETURN data of DAO txs, as in bisq's `OpReturnType`
+const PROPOSAL: u8 = 0x10;
+const COMPENSATION_REQUEST: u8 = 0x11;
+const REIMBURSEMENT_REQUEST: u8 = 0x12;
+const BLIND_VOTE: u8 = 0x13;
+const VOTE_REVEAL: u8 = 0x14;
+
+const HASH_LEN: usize = 20;
+const SECRET_KEY_LEN: usize = 16;
+/// Merit loses its weight linearly over 2 of bisq's 50_000 block years.
+const MERIT_MAX_AGE: u64 = 2 * 50_000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Phase {
+    Proposal,
+    Break1,
+    BlindVote,
+    Break2,
+    VoteReveal,
+    Break3,
+    Result,
+}
+const PHASES: [Phase; 7] = [
+    Phase::Proposal,
+    Phase::Break1,
+    Phase::BlindVote,
+    Phase::Break2,
+    Phase::VoteReveal,
+    Phase::Break3,
+    Phase::Result,
+];
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Phase::Proposal => "PROPOSAL",
+            Phase::Break1 => "BREAK1",
+            Phase::BlindVote => "BLIND_VOTE",
+            Phase::Break2 => "BREAK2",
+            Phase::VoteReveal => "VOTE_REVEAL",
+            Phase::Break3 => "BREAK3",
+            Phase::Result => "RESULT",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The phases of the DAO cycles that follow each other from the genesis
+/// block on. Uses the default phase lengths of bisq, changes of them that
+/// were voted for are not applied.
+pub struct CycleSchedule {
+    first_block: u32,
+    phase_blocks: [u32; 7],
+}
+impl CycleSchedule {
+    pub fn new(network: BaseCurrencyNetwork, genesis_block_height: u32) -> Self {
+        Self {
+            first_block: genesis_block_height,
+            phase_blocks: match network {
+                BaseCurrencyNetwork::BtcMainnet => [3601, 149, 451, 9, 451, 9, 10],
+                BaseCurrencyNetwork::BtcTestnet => [380, 10, 300, 10, 300, 10, 2],
+                _ => [4, 1, 2, 1, 2, 1, 2],
+            },
+        }
+    }
+
+    fn cycle_blocks(&self) -> u32 {
+        self.phase_blocks.iter().sum()
+    }
+
+    pub fn cycle(&self, index: u32) -> Range<u32> {
+        let start = self.first_block + index * self.cycle_blocks();
+        start..start + self.cycle_blocks()
+    }
+
+    pub fn phase(&self, index: u32, phase: Phase) -> Range<u32> {
+        let mut start = self.cycle(index).start;
+        for (candidate, blocks) in PHASES.iter().zip(self.phase_blocks.iter()) {
+            if *candidate == phase {
+                return start..start + blocks;
+            }
+            start += blocks;
+        }
+        unreachable!()
+    }
+
+    /// The index of the cycle and its phase at `height`.
+    pub fn phase_at(&self, height: u32) -> Option<(u32, Phase)> {
+        let offset = height.checked_sub(self.first_block)?;
+        let index = offset / self.cycle_blocks();
+        PHASES
+            .iter()
+            .find(|phase| self.phase(index, **phase).contains(&height))
+            .map(|phase| (index, *phase))
+    }
+}
+
+/// A proposal as published in the p2p network.
+#[derive(Clone, Debug)]
+pub struct ProposalInfo {
+    pub tx_id: String,
+    pub name: String,
+    pub link: String,
+    pub kind: &'static str,
+    /// BSQ requested by compensation and reimbursement requests
+    pub requested_bsq: Option<u64>,
+    pub created_at: SystemTime,
+    /// Only announced during the proposal phase, not yet persisted by the
+    /// network
+    pub temporary: bool,
+}
+impl ProposalInfo {
+    fn new(proposal: &Proposal, temporary: bool) -> Self {
+        Self {
+            tx_id: proposal.tx_id.clone(),
+            name: proposal.name.clone(),
+            link: proposal.link.clone(),
+            kind: requirements(proposal).0,
+            requested_bsq: requested_bsq(proposal),
+            created_at: millis(proposal.creation_date),
+            temporary,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BlindVoteInfo {
+    pub tx_id: String,
+    pub stake: u64,
+    pub date: SystemTime,
+}
+
+/// The proposals and blind votes of the p2p network, by tx id.
+#[derive(Clone, Default)]
+pub struct DaoPayloads {
+    proposals: Arc<RwLock<HashMap<String, Proposal>>>,
+    temp_proposals: Arc<RwLock<HashMap<String, Proposal>>>,
+    blind_votes: Arc<RwLock<HashMap<String, BlindVote>>>,
+}
+impl DaoPayloads {
+    pub fn add_proposal(&self, payload: &ProposalPayload) {
+        if let Some(proposal) = payload.proposal.as_ref() {
+            self.proposals
+                .write()
+                .expect("Corrupted lock in dao payloads")
+                .insert(proposal.tx_id.clone(), proposal.clone());
+        }
+    }
+
+    /// Proposals are broadcast as `TempProposalPayload`s during the proposal
+    /// phase and only persisted once it ended.
+    pub fn add_temp_proposal(&self, payload: &TempProposalPayload) {
+        if let Some(proposal) = payload.proposal.as_ref() {
+            self.temp_proposals
+                .write()
+                .expect("Corrupted lock in dao payloads")
+                .insert(proposal.tx_id.clone(), proposal.clone());
+        }
+    }
+
+    pub fn remove_temp_proposal(&self, payload: &TempProposalPayload) {
+        if let Some(proposal) = payload.proposal.as_ref() {
+            self.temp_proposals
+                .write()
+                .expect("Corrupted lock in dao payloads")
+                .remove(&proposal.tx_id);
+        }
+    }
+
+    pub fn add_blind_vote(&self, payload: &BlindVotePayload) {
+        if let Some(blind_vote) = payload.blind_vote.as_ref() {
+            self.blind_votes
+                .write()
+                .expect("Corrupted lock in dao payloads")
+                .insert(blind_vote.tx_id.clone(), blind_vote.clone());
+        }
+    }
+
+    /// The persisted proposals and the temporary ones not persisted yet,
+    /// oldest first.
+    pub fn proposals(&self) -> Vec<ProposalInfo> {
+        let proposals = self
+            .proposals
+            .read()
+            .expect("Corrupted lock in dao payloads");
+        let temp_proposals = self
+            .temp_proposals
+            .read()
+            .expect("Corrupted lock in dao payloads");
+        let mut infos: Vec<_> = proposals
+            .values()
+            .map(|proposal| ProposalInfo::new(proposal, false))
+            .chain(
+                temp_proposals
+                    .values()
+                    .filter(|proposal| !proposals.contains_key(&proposal.tx_id))
+                    .map(|proposal| ProposalInfo::new(proposal, true)),
+            )
+            .collect();
+        infos.sort_by_key(|info| info.created_at);
+        infos
+    }
+
+    /// Oldest first.
+    pub fn blind_votes(&self) -> Vec<BlindVoteInfo> {
+        let mut infos: Vec<_> = self
+            .blind_votes
+            .read()
+            .expect("Corrupted lock in dao payloads")
+            .values()
+            .map(|blind_vote| BlindVoteInfo {
+                tx_id: blind_vote.tx_id.clone(),
+                stake: blind_vote.stake as u64,
+                date: millis(blind_vote.date),
+            })
+            .collect();
+        infos.sort_by_key(|info| info.date);
+        infos
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ProposalResult {
+    pub tx_id: String,
+    pub name: String,
+    pub link: String,
+    pub kind: &'static str,
+    /// BSQ requested by compensation and reimbursement requests
+    pub requested_bsq: Option<u64>,
+    pub stake_of_accepted_votes: u64,
+    pub stake_of_rejected_votes: u64,
+    pub num_accepted_votes: usize,
+    pub num_rejected_votes: usize,
+    pub num_ignored_votes: usize,
+    pub accepted: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct CycleResult {
+    pub index: u32,
+    pub blocks: Range<u32>,
+    pub proposals: Vec<ProposalResult>,
+    pub blind_votes: usize,
+    pub vote_reveals: usize,
+    /// BSQ issued for accepted compensation and reimbursement requests
+    pub issued: u64,
+}
+
+struct Issuance {
+    height: u32,
+    amount: u64,
+    pub_key: String,
+}
+
+struct RevealedVote {
+    votes: Vec<VoteWithProposalTxId>,
+    weight: u64,
+}
+
+/// Computes the results of all cycles that reached their result phase,
+/// like bisq's `VoteResultService`: every revealed vote weighs the stake
+/// of its blind vote plus the merit of the voter's earlier compensations.
+pub fn tally(
+    state: &DaoState,
+    network: BaseCurrencyNetwork,
+    payloads: &DaoPayloads,
+) -> Vec<CycleResult> {
+    let height = match state.height() {
+        Some(height) => height,
+        None => return Vec::new(),
+    };
+    let schedule = CycleSchedule::new(network, state.genesis().block_height);
+    let proposals = payloads
+        .proposals
+        .read()
+        .expect("Corrupted lock in dao payloads");
+    let blind_votes = payloads
+        .blind_votes
+        .read()
+        .expect("Corrupted lock in dao payloads");
+    let mut issuances = HashMap::new();
+    (0..)
+        .take_while(|index| schedule.phase(*index, Phase::Result).start <= height)
+        .map(|index| {
+            tally_cycle(
+                index,
+                &schedule,
+                state,
+                &proposals,
+                &blind_votes,
+                &mut issuances,
+            )
+        })
+        .collect()
+}
+
+fn tally_cycle(
+    index: u32,
+    schedule: &CycleSchedule,
+    state: &DaoState,
+    proposals: &HashMap<String, Proposal>,
+    blind_votes: &HashMap<String, BlindVote>,
+    issuances: &mut HashMap<String, Issuance>,
+) -> CycleResult {
+    let in_phase = |tx: &OpReturnTx, phase| schedule.phase(index, phase).contains(&tx.block_height);
+    let mut cycle_proposals: Vec<(&Proposal, &OpReturnTx)> = proposals
+        .values()
+        .filter_map(|proposal| {
+            let tx = state.op_return_tx(&proposal.tx_id)?;
+            let hash = op_return_hash(&tx.data, proposal_op_return_type(proposal))?;
+            if in_phase(tx, Phase::Proposal) && hash == proposal_hash(proposal) {
+                Some((proposal, tx))
+            } else {
+                None
+            }
+        })
+        .collect();
+    cycle_proposals.sort_by_key(|(proposal, _)| proposal.creation_date);
+
+    let cycle_blind_votes: HashMap<&str, (&BlindVote, &OpReturnTx)> = blind_votes
+        .values()
+        .filter_map(|blind_vote| {
+            let tx = state.op_return_tx(&blind_vote.tx_id)?;
+            let hash = op_return_hash(&tx.data, BLIND_VOTE)?;
+            if in_phase(tx, Phase::BlindVote) && hash == hash160(&blind_vote.encrypted_votes) {
+                Some((blind_vote.tx_id.as_str(), (blind_vote, tx)))
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    let revealed_votes: Vec<RevealedVote> = state
+        .op_return_txs()
+        .filter(|(_, tx)| in_phase(tx, Phase::VoteReveal))
+        .filter_map(|(_, tx)| {
+            let secret_key = op_return_secret_key(&tx.data)?;
+            // The reveal tx spends the stake of the blind vote
+            let (blind_vote, blind_vote_tx) = tx
+                .spent
+                .iter()
+                .find_map(|output| cycle_blind_votes.get(output.key.tx_id.as_str()))?;
+            let votes = crypto::decrypt(secret_key, &blind_vote.encrypted_votes).ok()?;
+            let votes = VoteWithProposalTxIdList::decode(&votes[..]).ok()?;
+            let merit = crypto::decrypt(secret_key, &blind_vote.encrypted_merit_list)
+                .ok()
+                .and_then(|merits| MeritList::decode(&merits[..]).ok())
+                .map(|merits| {
+                    merit_stake(
+                        &merits,
+                        blind_vote_tx.block_height,
+                        &blind_vote.tx_id,
+                        issuances,
+                    )
+                })
+                .unwrap_or(0);
+            Some(RevealedVote {
+                votes: votes.item,
+                weight: blind_vote_tx.outputs.first()?.value + merit,
+            })
+        })
+        .collect();
+
+    let mut issued = 0;
+    let results = cycle_proposals
+        .into_iter()
+        .map(|(proposal, tx)| {
+            let (kind, quorum, threshold) = requirements(proposal);
+            let mut result = ProposalResult {
+                tx_id: proposal.tx_id.clone(),
+                name: proposal.name.clone(),
+                link: proposal.link.clone(),
+                kind,
+                requested_bsq: requested_bsq(proposal),
+                stake_of_accepted_votes: 0,
+                stake_of_rejected_votes: 0,
+                num_accepted_votes: 0,
+                num_rejected_votes: 0,
+                num_ignored_votes: 0,
+                accepted: false,
+            };
+            for revealed in revealed_votes.iter() {
+                let vote = revealed
+                    .votes
+                    .iter()
+                    .find(|vote| vote.proposal_tx_id == proposal.tx_id)
+                    .and_then(|vote| vote.vote.as_ref());
+                match vote {
+                    Some(Vote { accepted: true }) => {
+                        result.stake_of_accepted_votes += revealed.weight;
+                        result.num_accepted_votes += 1;
+                    }
+                    Some(Vote { accepted: false }) => {
+                        result.stake_of_rejected_votes += revealed.weight;
+                        result.num_rejected_votes += 1;
+                    }
+                    None => result.num_ignored_votes += 1,
+                }
+            }
+            let total = result.stake_of_accepted_votes + result.stake_of_rejected_votes;
+            result.accepted = total > 0
+                && total >= quorum
+                && result.stake_of_accepted_votes * 10_000 / total > threshold;
+
+            let issuance = tx
+                .first_btc_output
+                .as_ref()
+                .filter(|_| result.accepted && result.requested_bsq.is_some());
+            if let Some(output) = issuance {
+                issued += output.value;
+                // Only compensations earn merit
+                if kind == "COMPENSATION_REQUEST" {
+                    issuances.insert(
+                        proposal.tx_id.clone(),
+                        Issuance {
+                            height: schedule.phase(index, Phase::Result).start,
+                            amount: output.value,
+                            pub_key: tx.pub_key.clone(),
+                        },
+                    );
+                }
+            }
+            result
+        })
+        .collect();
+
+    CycleResult {
+        index,
+        blocks: schedule.cycle(index),
+        proposals: results,
+        blind_votes: cycle_blind_votes.len(),
+        vote_reveals: revealed_votes.len(),
+        issued,
+    }
+}
+
+// The kind of the proposal, its quorum in satoshis of BSQ and threshold in
+// basis points, the defaults of bisq's `Param`
+fn requirements(proposal: &Proposal) -> (&'static str, u64, u64) {
+    match proposal.message {
+        Some(proposal::Message::CompensationProposal(_)) => {
+            ("COMPENSATION_REQUEST", 2_000_000, 5_000)
+        }
+        Some(proposal::Message::ReimbursementProposal(_)) => {
+            ("REIMBURSEMENT_REQUEST", 2_000_000, 5_000)
+        }
+        Some(proposal::Message::ChangeParamProposal(_)) => ("CHANGE_PARAM", 10_000_000, 7_500),
+        Some(proposal::Message::RoleProposal(_)) => ("BONDED_ROLE", 5_000_000, 5_000),
+        Some(proposal::Message::ConfiscateBondProposal(_)) => {
+            ("CONFISCATE_BOND", 20_000_000, 8_500)
+        }
+        Some(proposal::Message::RemoveAssetProposal(_)) => ("REMOVE_ASSET", 1_000_000, 5_000),
+        Some(proposal::Message::GenericProposal(_)) | None => ("GENERIC", 500_000, 5_000),
+    }
+}
+
+fn requested_bsq(proposal: &Proposal) -> Option<u64> {
+    match proposal.message.as_ref()? {
+        proposal::Message::CompensationProposal(request) => Some(request.requested_bsq as u64),
+        proposal::Message::ReimbursementProposal(request) => Some(request.requested_bsq as u64),
+        _ => None,
+    }
+}
+
+fn proposal_op_return_type(proposal: &Proposal) -> u8 {
+    match proposal.message {
+        Some(proposal::Message::CompensationProposal(_)) => COMPENSATION_REQUEST,
+        Some(proposal::Message::ReimbursementProposal(_)) => REIMBURSEMENT_REQUEST,
+        _ => PROPOSAL,
+    }
+}
+
+// The OP_RETURN commits to the proposal before its tx id was known
+fn proposal_hash(proposal: &Proposal) -> Vec<u8> {
+    let proposal = Proposal {
+        tx_id: String::new(),
+        ..proposal.clone()
+    };
+    let mut serialized = Vec::with_capacity(proposal.encoded_len());
+    proposal
+        .encode(&mut serialized)
+        .expect("Could not encode proposal");
+    hash160(&serialized)
+}
+
+fn millis(millis: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis.max(0) as u64)
+}
+
+fn hash160(data: &[u8]) -> Vec<u8> {
+    ripemd160::Hash::hash(&sha256::Hash::hash(data).into_inner()).to_vec()
+}
+
+// OP_RETURN data starts with its type and a version byte
+fn op_return_hash(data: &[u8], op_return_type: u8) -> Option<Vec<u8>> {
+    if data.len() < 2 + HASH_LEN || data[0] != op_return_type {
+        return None;
+    }
+    Some(data[2..2 + HASH_LEN].to_vec())
+}
+
+fn op_return_secret_key(data: &[u8]) -> Option<&[u8]> {
+    if data.len() != 2 + HASH_LEN + SECRET_KEY_LEN || data[0] != VOTE_REVEAL {
+        return None;
+    }
+    Some(&data[2 + HASH_LEN..])
+}
+
+// Merit counts when the issuance is known and the key of its compensation
+// request signed the blind vote tx id
+fn merit_stake(
+    merits: &MeritList,
+    blind_vote_height: u32,
+    blind_vote_tx_id: &str,
+    issuances: &HashMap<String, Issuance>,
+) -> u64 {
+    let secp = Secp256k1::verification_only();
+    let message = match Vec::<u8>::from_hex(blind_vote_tx_id)
+        .ok()
+        .and_then(|tx_id| secp256k1::Message::from_slice(&tx_id).ok())
+    {
+        Some(message) => message,
+        None => return 0,
+    };
+    merits
+        .merit
+        .iter()
+        .filter_map(|merit| {
+            let issuance = issuances.get(&merit.issuance.as_ref()?.tx_id)?;
+            let pub_key = PublicKey::from_slice(&Vec::from_hex(&issuance.pub_key).ok()?).ok()?;
+            let mut signature = Signature::from_der(&merit.signature).ok()?;
+            signature.normalize_s();
+            secp.verify(&message, &signature, &pub_key).ok()?;
+            let age = u64::from(blind_vote_height.checked_sub(issuance.height)?);
+            Some(issuance.amount * (MERIT_MAX_AGE - age.min(MERIT_MAX_AGE)) / MERIT_MAX_AGE)
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::dao::Genesis;
+    use openssl::symm::{encrypt, Cipher};
+
+    fn tx(id: &str, inputs: &[(&str, i32)], outputs: &[i64], op_return_data: Vec<u8>) -> BaseTx {
+        let mut outputs: Vec<BaseTxOutput> = outputs
+            .iter()
+            .enumerate()
+            .map(|(index, value)| BaseTxOutput {
+                index: index as i32,
+                value: *value,
+                ..Default::default()
+            })
+            .collect();
+        outputs.push(BaseTxOutput {
+            index: outputs.len() as i32,
+            op_return_data,
+            ..Default::default()
+        });
+        BaseTx {
+            id: id.into(),
+            tx_inputs: inputs
+                .iter()
+                .map(|(tx_id, index)| TxInput {
+                    connected_tx_output_tx_id: tx_id.to_string(),
+                    connected_tx_output_index: *index,
+                    ..Default::default()
+                })
+                .collect(),
+            message: Some(base_tx::Message::RawTx(RawTx {
+                raw_tx_outputs: outputs,
+            })),
+            ..Default::default()
+        }
+    }
+
+    fn op_return(op_return_type: u8, data: &[&[u8]]) -> Vec<u8> {
+        let mut op_return = vec![op_return_type, 1];
+        for data in data {
+            op_return.extend_from_slice(data);
+        }
+        op_return
+    }
+
+    #[test]
+    fn tallies_revealed_votes() {
+        let schedule = CycleSchedule::new(BaseCurrencyNetwork::BtcRegtest, 100);
+        assert_eq!(schedule.phase(0, Phase::BlindVote), 105..107);
+        assert_eq!(schedule.phase_at(113), Some((1, Phase::Proposal)));
+        assert_eq!(schedule.phase_at(99), None);
+
+        let proposal = Proposal {
+            name: "Dev work".into(),
+            message: Some(proposal::Message::CompensationProposal(
+                CompensationProposal {
+                    requested_bsq: 1_000_000,
+                    bsq_address: "Baddress".into(),
+                },
+            )),
+            ..Default::default()
+        };
+        let secret_key = [7; 16];
+        let mut votes = Vec::new();
+        VoteWithProposalTxIdList {
+            item: vec![VoteWithProposalTxId {
+                proposal_tx_id: "proposal".into(),
+                vote: Some(Vote { accepted: true }),
+            }],
+        }
+        .encode(&mut votes)
+        .unwrap();
+        let encrypted_votes = encrypt(Cipher::aes_128_ecb(), &secret_key, None, &votes).unwrap();
+
+        let payloads = DaoPayloads::default();
+        payloads.add_proposal(&ProposalPayload {
+            proposal: Some(Proposal {
+                tx_id: "proposal".into(),
+                ..proposal.clone()
+            }),
+            hash: Vec::new(),
+        });
+        payloads.add_blind_vote(&BlindVotePayload {
+            blind_vote: Some(BlindVote {
+                encrypted_votes: encrypted_votes.clone(),
+                tx_id: "blind_vote".into(),
+                stake: 3_000_000,
+                ..Default::default()
+            }),
+            hash: Vec::new(),
+        });
+
+        let mut txs: HashMap<u32, BaseTx> = HashMap::new();
+        txs.insert(100, tx("genesis", &[], &[5_000_000, 5_000_000], Vec::new()));
+        txs.insert(
+            101,
+            tx(
+                "proposal",
+                &[("genesis", 0)],
+                &[4_900_000, 1_000_000],
+                op_return(COMPENSATION_REQUEST, &[&proposal_hash(&proposal)]),
+            ),
+        );
+        txs.insert(
+            105,
+            tx(
+                "blind_vote",
+                &[("genesis", 1)],
+                &[3_000_000, 1_990_000],
+                op_return(BLIND_VOTE, &[&hash160(&encrypted_votes)]),
+            ),
+        );
+        txs.insert(
+            108,
+            tx(
+                "vote_reveal",
+                &[("blind_vote", 0)],
+                &[2_990_000],
+                op_return(VOTE_REVEAL, &[&[0; HASH_LEN], &secret_key]),
+            ),
+        );
+        let mut state = DaoState::new(Genesis {
+            tx_id: "genesis".into(),
+            block_height: 100,
+            total_supply: 10_000_000,
+        });
+        for height in 100..112 {
+            state
+                .add_block(BaseBlock {
+                    height: height as i32,
+                    hash: height.to_string(),
+                    previous_block_hash: (height - 1).to_string(),
+                    message: Some(base_block::Message::RawBlock(RawBlock {
+                        raw_txs: txs.remove(&height).into_iter().collect(),
+                    })),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+
+        let results = tally(&state, BaseCurrencyNetwork::BtcRegtest, &payloads);
+        assert_eq!(results.len(), 1);
+        let cycle = &results[0];
+        assert_eq!((cycle.blind_votes, cycle.vote_reveals), (1, 1));
+        assert_eq!(cycle.issued, 1_000_000);
+        let result = &cycle.proposals[0];
+        assert_eq!(result.stake_of_accepted_votes, 3_000_000);
+        assert_eq!(result.num_accepted_votes, 1);
+        assert!(result.accepted);
+    }
+
+    #[test]
+    fn lists_temporary_proposals_until_persisted() {
+        let proposal = Proposal {
+            tx_id: "proposal".into(),
+            name: "Dev work".into(),
+            creation_date: 1_000,
+            ..Default::default()
+        };
+        let temp = TempProposalPayload {
+            proposal: Some(proposal.clone()),
+            ..Default::default()
+        };
+        let payloads = DaoPayloads::default();
+        payloads.add_temp_proposal(&temp);
+        let proposals = payloads.proposals();
+        assert_eq!(proposals.len(), 1);
+        assert!(proposals[0].temporary);
+        assert_eq!(proposals[0].kind, "GENERIC");
+
+        payloads.add_proposal(&ProposalPayload {
+            proposal: Some(proposal),
+            hash: Vec::new(),
+        });
+        assert!(!payloads.proposals()[0].temporary);
+        payloads.remove_temp_proposal(&temp);
+        assert_eq!(payloads.proposals().len(), 1);
+        assert!(payloads.blind_votes().is_empty());
+    }
+}
@@ -0,0 +1,322 @@
+use crate::{
+    bisq::{
+        crypto,
+        payload::{NodeAddress, PubKeyRing},
+    },
+    prelude::ToHex,
+};
+use bitcoin::secp256k1::PublicKey;
+use rand::seq::SliceRandom;
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
+
+/// How many of the latest trades in the network bisq's
+/// `DisputeAgentSelection` looks at.
+const LOOK_BACK_RANGE: usize = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DisputeAgentKind {
+    Mediator,
+    RefundAgent,
+}
+impl DisputeAgentKind {
+    pub fn to_lowercase(self) -> &'static str {
+        match self {
+            DisputeAgentKind::Mediator => "mediator",
+            DisputeAgentKind::RefundAgent => "refund_agent",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DisputeAgent {
+    pub address: NodeAddress,
+    pub pub_key_ring: PubKeyRing,
+    pub language_codes: Vec<String>,
+    pub registration_date: SystemTime,
+    pub email_address: String,
+    pub info: String,
+    /// Key of the bisq developer who registered the agent
+    pub registration_pub_key: Vec<u8>,
+    pub registration_signature: String,
+}
+
+// Like bisq's `DisputeAgentManager.isSignatureValid`, the registration key
+// signs the hex of the agent's signature key.
+fn is_registered(pub_key_ring: &PubKeyRing, registration_pub_key: &[u8], signature: &str) -> bool {
+    PublicKey::from_slice(registration_pub_key)
+        .map(|key| {
+            crypto::verify_signed_message(
+                &[key],
+                &pub_key_ring.signature_pub_key_bytes.to_hex(),
+                signature,
+            )
+        })
+        .unwrap_or(false)
+}
+
+#[derive(Default)]
+struct Registry {
+    agents: HashMap<DisputeAgentKind, HashMap<NodeAddress, DisputeAgent>>,
+    /// Agents of the latest trades in the network, oldest first
+    recent: HashMap<DisputeAgentKind, Vec<(SystemTime, NodeAddress)>>,
+}
+
+/// The mediators and refund agents that are currently registered in the
+/// p2p network. Agents leave when their entry is removed or expires.
+#[derive(Clone, Default)]
+pub struct DisputeAgents {
+    inner: Arc<RwLock<Registry>>,
+}
+impl DisputeAgents {
+    /// False if the agent's registration isn't signed.
+    pub fn add(&self, kind: DisputeAgentKind, agent: DisputeAgent) -> bool {
+        if !is_registered(
+            &agent.pub_key_ring,
+            &agent.registration_pub_key,
+            &agent.registration_signature,
+        ) {
+            return false;
+        }
+        let mut inner = self
+            .inner
+            .write()
+            .expect("Corrupted lock in dispute agents");
+        inner
+            .agents
+            .entry(kind)
+            .or_default()
+            .insert(agent.address.clone(), agent);
+        true
+    }
+
+    pub fn remove(&self, kind: DisputeAgentKind, address: &NodeAddress) {
+        let mut inner = self
+            .inner
+            .write()
+            .expect("Corrupted lock in dispute agents");
+        if let Some(agents) = inner.agents.get_mut(&kind) {
+            agents.remove(address);
+        }
+    }
+
+    pub fn get(&self, kind: DisputeAgentKind, address: &NodeAddress) -> Option<DisputeAgent> {
+        let inner = self.inner.read().expect("Corrupted lock in dispute agents");
+        inner.agents.get(&kind)?.get(address).cloned()
+    }
+
+    pub fn list(&self) -> Vec<(DisputeAgentKind, DisputeAgent)> {
+        let inner = self.inner.read().expect("Corrupted lock in dispute agents");
+        inner
+            .agents
+            .iter()
+            .flat_map(|(kind, agents)| agents.values().map(move |agent| (*kind, agent.clone())))
+            .collect()
+    }
+
+    /// Notes the agent a trade in the network was assigned to, as published
+    /// in its trade statistic.
+    pub fn record_trade(&self, kind: DisputeAgentKind, date: SystemTime, address: NodeAddress) {
+        let mut inner = self
+            .inner
+            .write()
+            .expect("Corrupted lock in dispute agents");
+        let recent = inner.recent.entry(kind).or_default();
+        let pos = recent
+            .iter()
+            .position(|(recent_date, _)| *recent_date > date)
+            .unwrap_or(recent.len());
+        recent.insert(pos, (date, address));
+        if recent.len() > LOOK_BACK_RANGE {
+            recent.remove(0);
+        }
+    }
+
+    /// How many of the latest trades in the network each agent was assigned
+    /// to.
+    pub fn usage(&self, kind: DisputeAgentKind) -> HashMap<NodeAddress, usize> {
+        let inner = self.inner.read().expect("Corrupted lock in dispute agents");
+        let mut usage = HashMap::new();
+        for (_, address) in inner.recent.get(&kind).into_iter().flatten() {
+            *usage.entry(address.clone()).or_insert(0) += 1;
+        }
+        usage
+    }
+
+    /// Picks the agent that was assigned to the fewest of the latest trades
+    /// in the network and the `assigned` ones, ties are broken randomly like
+    /// bisq's `DisputeAgentSelection`.
+    pub fn select<'a>(
+        &self,
+        kind: DisputeAgentKind,
+        assigned: impl Iterator<Item = &'a NodeAddress>,
+    ) -> Option<NodeAddress> {
+        let inner = self.inner.read().expect("Corrupted lock in dispute agents");
+        let agents = inner.agents.get(&kind)?;
+        let mut usage: HashMap<&NodeAddress, usize> =
+            agents.keys().map(|address| (address, 0)).collect();
+        let mut count = |address: &NodeAddress| {
+            if let Some(count) = usage.get_mut(address) {
+                *count += 1;
+            }
+        };
+        // Chaining them would tie the lock guard to the lifetime of `assigned`
+        for (_, address) in inner.recent.get(&kind).into_iter().flatten() {
+            count(address);
+        }
+        assigned.for_each(count);
+        let least = *usage.values().min()?;
+        let candidates: Vec<_> = usage
+            .into_iter()
+            .filter(|(_, count)| *count == least)
+            .map(|(address, _)| address)
+            .collect();
+        candidates
+            .choose(&mut rand::thread_rng())
+            .map(|address| (*address).clone())
+    }
+}
+
+/// An arbitrator of bisq's first trade protocol. They don't take new
+/// disputes, but old offers still list them.
+#[derive(Clone, Debug)]
+pub struct Arbitrator {
+    pub address: NodeAddress,
+    pub pub_key_ring: PubKeyRing,
+    pub language_codes: Vec<String>,
+    pub registration_date: SystemTime,
+    pub btc_address: String,
+    pub email_address: String,
+    pub info: String,
+    pub registration_pub_key: Vec<u8>,
+    pub registration_signature: String,
+}
+
+/// The arbitrators that are currently registered in the p2p network.
+#[derive(Clone, Default)]
+pub struct Arbitrators {
+    inner: Arc<RwLock<HashMap<NodeAddress, Arbitrator>>>,
+}
+impl Arbitrators {
+    /// False if the arbitrator's registration isn't signed.
+    pub fn add(&self, arbitrator: Arbitrator) -> bool {
+        if !is_registered(
+            &arbitrator.pub_key_ring,
+            &arbitrator.registration_pub_key,
+            &arbitrator.registration_signature,
+        ) {
+            return false;
+        }
+        let mut inner = self.inner.write().expect("Corrupted lock in arbitrators");
+        inner.insert(arbitrator.address.clone(), arbitrator);
+        true
+    }
+
+    pub fn remove(&self, address: &NodeAddress) {
+        let mut inner = self.inner.write().expect("Corrupted lock in arbitrators");
+        inner.remove(address);
+    }
+
+    pub fn contains(&self, address: &NodeAddress) -> bool {
+        let inner = self.inner.read().expect("Corrupted lock in arbitrators");
+        inner.contains_key(address)
+    }
+
+    pub fn list(&self) -> Vec<Arbitrator> {
+        let inner = self.inner.read().expect("Corrupted lock in arbitrators");
+        inner.values().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn agent(host: &str) -> DisputeAgent {
+        let key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let pub_key_ring = PubKeyRing {
+            signature_pub_key_bytes: host.as_bytes().to_vec(),
+            ..Default::default()
+        };
+        DisputeAgent {
+            address: format!("{}.onion:9999", host).parse().unwrap(),
+            registration_signature: crypto::sign_message(
+                &key,
+                &pub_key_ring.signature_pub_key_bytes.to_hex(),
+            ),
+            pub_key_ring,
+            language_codes: vec!["en".into()],
+            registration_date: UNIX_EPOCH,
+            email_address: String::new(),
+            info: String::new(),
+            registration_pub_key: PublicKey::from_secret_key(&Secp256k1::signing_only(), &key)
+                .serialize()
+                .to_vec(),
+        }
+    }
+
+    #[test]
+    fn least_used_agent() {
+        let agents = DisputeAgents::default();
+        assert_eq!(agents.select(DisputeAgentKind::Mediator, [].iter()), None);
+        assert!(agents.add(DisputeAgentKind::Mediator, agent("a")));
+        assert!(agents.add(DisputeAgentKind::Mediator, agent("b")));
+        assert!(agents.add(DisputeAgentKind::RefundAgent, agent("c")));
+        let mut unsigned = agent("d");
+        unsigned.registration_signature = agent("a").registration_signature;
+        assert!(!agents.add(DisputeAgentKind::Mediator, unsigned));
+        assert_eq!(agents.list().len(), 3);
+
+        let used = [agent("a").address, agent("a").address, agent("c").address];
+        assert_eq!(
+            agents.select(DisputeAgentKind::Mediator, used.iter()),
+            Some(agent("b").address)
+        );
+        for secs in 0..3 {
+            agents.record_trade(
+                DisputeAgentKind::Mediator,
+                UNIX_EPOCH + Duration::from_secs(secs),
+                agent("b").address,
+            );
+        }
+        assert_eq!(
+            agents.select(DisputeAgentKind::Mediator, used.iter()),
+            Some(agent("a").address)
+        );
+        assert_eq!(
+            agents
+                .usage(DisputeAgentKind::Mediator)
+                .get(&agent("b").address),
+            Some(&3)
+        );
+        agents.remove(DisputeAgentKind::Mediator, &agent("a").address);
+        assert_eq!(
+            agents.select(DisputeAgentKind::Mediator, used.iter()),
+            Some(agent("b").address)
+        );
+        assert!(agents
+            .get(DisputeAgentKind::RefundAgent, &agent("c").address)
+            .is_some());
+    }
+
+    #[test]
+    fn looks_back_at_the_latest_trades() {
+        let agents = DisputeAgents::default();
+        for secs in (0..LOOK_BACK_RANGE as u64 + 10).rev() {
+            let host = if secs < 10 { "old" } else { "new" };
+            agents.record_trade(
+                DisputeAgentKind::RefundAgent,
+                UNIX_EPOCH + Duration::from_secs(secs),
+                agent(host).address,
+            );
+        }
+        let usage = agents.usage(DisputeAgentKind::RefundAgent);
+        assert_eq!(usage.get(&agent("new").address), Some(&LOOK_BACK_RANGE));
+        assert_eq!(usage.get(&agent("old").address), None);
+    }
+}
@@ -0,0 +1,81 @@
+use crate::bisq::constants::BaseCurrencyNetwork;
+use std::{
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+/// Estimates older than this are ignored, eg. when the price nodes are
+/// unreachable for a while.
+const MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// A mining fee rate in sat/vB and when the fee service estimated it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FeeRate {
+    pub sats_per_vbyte: u64,
+    pub estimated_at: SystemTime,
+}
+
+/// The fee rate of bisq's fee service, which the price nodes serve via
+/// `/getFees`. Bisq peers build their trade txs with it, so it is
+/// preferred over the estimate of the wallet backend.
+#[derive(Clone, Default)]
+pub struct FeeRates {
+    latest: Arc<RwLock<Option<FeeRate>>>,
+}
+impl FeeRates {
+    pub fn update(&self, rate: FeeRate) {
+        let mut latest = self.latest.write().expect("Corrupted lock in fee rates");
+        // Price nodes may be behind each other
+        let newer = latest.filter(|latest| latest.estimated_at > rate.estimated_at);
+        if newer.is_none() {
+            *latest = Some(rate);
+        }
+    }
+
+    /// The fee service only estimates mainnet fees.
+    pub fn current(&self, network: BaseCurrencyNetwork) -> Option<FeeRate> {
+        if network != BaseCurrencyNetwork::BtcMainnet {
+            return None;
+        }
+        let latest = *self.latest.read().expect("Corrupted lock in fee rates");
+        latest.filter(|rate| {
+            rate.estimated_at
+                .elapsed()
+                .map(|age| age < MAX_AGE)
+                .unwrap_or(true)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignores_stale_and_other_networks() {
+        let rates = FeeRates::default();
+        let now = SystemTime::now();
+        rates.update(FeeRate {
+            sats_per_vbyte: 30,
+            estimated_at: now,
+        });
+        rates.update(FeeRate {
+            sats_per_vbyte: 10,
+            estimated_at: now - Duration::from_secs(60),
+        });
+        assert_eq!(
+            rates
+                .current(BaseCurrencyNetwork::BtcMainnet)
+                .map(|rate| rate.sats_per_vbyte),
+            Some(30)
+        );
+        assert_eq!(rates.current(BaseCurrencyNetwork::BtcTestnet), None);
+
+        let stale = FeeRates::default();
+        stale.update(FeeRate {
+            sats_per_vbyte: 30,
+            estimated_at: now - MAX_AGE,
+        });
+        assert_eq!(stale.current(BaseCurrencyNetwork::BtcMainnet), None);
+    }
+}
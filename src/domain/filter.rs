@@ -0,0 +1,174 @@
+use super::offer::{
+    compliance::{self, Anomaly, DelayedPayoutPolicy},
+    OpenOffer,
+};
+use crate::{
+    bisq::{
+        constants::{self, BaseCurrencyNetwork},
+        crypto,
+        payload::{Filter, NodeAddress},
+    },
+    prelude::*,
+};
+use bitcoin::secp256k1::PublicKey;
+use prost::Message;
+use std::sync::{Arc, RwLock};
+
+/// The filter the bisq developers published to ban offers, nodes, currencies
+/// and payment methods. Only the latest filter signed with one of their keys
+/// applies.
+#[derive(Clone)]
+pub struct FilterStore {
+    keys: Arc<Vec<PublicKey>>,
+    active: Arc<RwLock<Option<Filter>>>,
+    delayed_payout_policy: DelayedPayoutPolicy,
+}
+impl FilterStore {
+    pub fn new(network: BaseCurrencyNetwork) -> Self {
+        Self::with_keys(crypto::developer_keys(&constants::filter_pub_keys(network)))
+    }
+
+    fn with_keys(keys: Vec<PublicKey>) -> Self {
+        Self {
+            keys: Arc::new(keys),
+            active: Arc::default(),
+            delayed_payout_policy: DelayedPayoutPolicy::default(),
+        }
+    }
+
+    /// With `DelayedPayoutPolicy::Hide` the offers the filter rules out for
+    /// their delayed payout tx aren't allowed either.
+    pub fn with_delayed_payout_policy(mut self, policy: DelayedPayoutPolicy) -> Self {
+        self.delayed_payout_policy = policy;
+        self
+    }
+
+    /// False if the filter isn't signed by a developer key.
+    pub fn apply(&self, filter: &Filter) -> bool {
+        if !self.verify(filter) {
+            return false;
+        }
+        let mut active = self.active.write().expect("Corrupted lock in filters");
+        *active = Some(filter.clone());
+        true
+    }
+
+    pub fn remove(&self, filter: &Filter) {
+        let mut active = self.active.write().expect("Corrupted lock in filters");
+        if active
+            .as_ref()
+            .map(|active| active.signature_as_base64 == filter.signature_as_base64)
+            .unwrap_or(false)
+        {
+            *active = None;
+        }
+    }
+
+    pub fn allows_offer(&self, offer: &OpenOffer) -> bool {
+        let active = self.active.read().expect("Corrupted lock in filters");
+        active
+            .as_ref()
+            .map(|filter| {
+                !filter.banned_offer_ids.contains(&offer.payload.id)
+                    && !filter
+                        .banned_currencies
+                        .contains(&offer.market.non_btc_side().code)
+                    && !filter
+                        .banned_payment_methods
+                        .iter()
+                        .any(|id| id == offer.payment_method.id())
+                    && (self.delayed_payout_policy == DelayedPayoutPolicy::Flag
+                        || compliance::check_filter(offer, filter).is_empty())
+            })
+            .unwrap_or(true)
+    }
+
+    /// Why the delayed payout tx of a trade on the offer wouldn't hold up
+    /// under the active filter.
+    pub fn delayed_payout_anomalies(&self, offer: &OpenOffer) -> Vec<Anomaly> {
+        let active = self.active.read().expect("Corrupted lock in filters");
+        active
+            .as_ref()
+            .map(|filter| compliance::check_filter(offer, filter))
+            .unwrap_or_default()
+    }
+
+    pub fn allows_currency(&self, code: &str) -> bool {
+        let active = self.active.read().expect("Corrupted lock in filters");
+        active
+            .as_ref()
+            .map(|filter| !filter.banned_currencies.iter().any(|banned| banned == code))
+            .unwrap_or(true)
+    }
+
+    pub fn is_banned(&self, addr: &NodeAddress) -> bool {
+        let active = self.active.read().expect("Corrupted lock in filters");
+        let addr = addr.to_string();
+        active
+            .as_ref()
+            .map(|filter| filter.banned_node_address.contains(&addr))
+            .unwrap_or(false)
+    }
+
+    // Like bisq's `FilterManager.isSignatureValid`, the lists of bans are
+    // signed as the hex of their protobuf.
+    fn verify(&self, filter: &Filter) -> bool {
+        crypto::verify_signed_message(
+            &self.keys,
+            &signed_data(filter),
+            &filter.signature_as_base64,
+        )
+    }
+}
+
+fn signed_data(filter: &Filter) -> String {
+    let signed = Filter {
+        banned_offer_ids: filter.banned_offer_ids.clone(),
+        banned_node_address: filter.banned_node_address.clone(),
+        banned_payment_accounts: filter.banned_payment_accounts.clone(),
+        banned_currencies: filter.banned_currencies.clone(),
+        banned_payment_methods: filter.banned_payment_methods.clone(),
+        ..Default::default()
+    };
+    let mut serialized = Vec::with_capacity(signed.encoded_len());
+    signed
+        .encode(&mut serialized)
+        .expect("Could not encode filter");
+    serialized.to_hex()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+
+    #[test]
+    fn applies_signed_filters_only() {
+        let key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let filters = FilterStore::with_keys(vec![PublicKey::from_secret_key(
+            &Secp256k1::signing_only(),
+            &key,
+        )]);
+        let banned: NodeAddress = "banned.onion:9999".parse().unwrap();
+        let mut filter = Filter {
+            banned_node_address: vec![banned.to_string()],
+            banned_currencies: vec!["EUR".into()],
+            ..Default::default()
+        };
+        assert!(!filters.apply(&filter));
+        assert!(!filters.is_banned(&banned));
+
+        filter.signature_as_base64 = crypto::sign_message(&key, &signed_data(&filter));
+        assert!(filters.apply(&filter));
+        assert!(filters.is_banned(&banned));
+        assert!(!filters.is_banned(&"other.onion:9999".parse().unwrap()));
+        assert!(!filters.allows_currency("EUR"));
+        assert!(filters.allows_currency("USD"));
+
+        filter.banned_currencies.clear();
+        assert!(!filters.apply(&filter));
+        filters.remove(&filter);
+        assert!(!filters.is_banned(&banned));
+        assert!(filters.allows_currency("EUR"));
+    }
+}
@@ -0,0 +1,161 @@
+use super::{amount::NumberWithPrecision, currency::Currency, market::Market};
+use std::{borrow::Cow, env, str::FromStr};
+
+pub const BTC_PRECISION: u32 = 8;
+pub const BSQ_PRECISION: u32 = 2;
+const PERCENTAGE_PRECISION: usize = 2;
+
+/// Separators used when rendering numbers for humans.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Locale {
+    pub decimal_separator: char,
+    pub grouping_separator: Option<char>,
+}
+impl Locale {
+    /// Machine readable format used by the API, eg. `1234.5600`.
+    pub const RAW: Locale = Locale {
+        decimal_separator: '.',
+        grouping_separator: None,
+    };
+    pub const EN: Locale = Locale {
+        decimal_separator: '.',
+        grouping_separator: Some(','),
+    };
+    pub const DE: Locale = Locale {
+        decimal_separator: ',',
+        grouping_separator: Some('.'),
+    };
+    pub const FR: Locale = Locale {
+        decimal_separator: ',',
+        grouping_separator: Some(' '),
+    };
+
+    /// Picks the locale from LC_ALL, LC_NUMERIC or LANG like libc does,
+    /// falling back to `RAW` when none is set or it is unknown.
+    pub fn from_env() -> Self {
+        ["LC_ALL", "LC_NUMERIC", "LANG"]
+            .iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(Self::RAW)
+    }
+}
+impl FromStr for Locale {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_lowercase();
+        let name = name.split(&['.', '@'][..]).next().unwrap_or_default();
+        if let "raw" | "c" | "posix" = name {
+            return Ok(Self::RAW);
+        }
+        match name.split(&['_', '-'][..]).next() {
+            Some("en") | Some("ja") | Some("ko") | Some("zh") | Some("he") | Some("th") => {
+                Ok(Self::EN)
+            }
+            Some("de") | Some("es") | Some("it") | Some("nl") | Some("pt") | Some("da")
+            | Some("id") | Some("tr") | Some("el") => Ok(Self::DE),
+            Some("fr") | Some("ru") | Some("pl") | Some("cs") | Some("sv") | Some("nb")
+            | Some("fi") | Some("uk") | Some("hu") => Ok(Self::FR),
+            _ => Err(format!("Unknown locale '{}'", s)),
+        }
+    }
+}
+
+/// Renders `n` rounded down to `precision` decimals.
+pub fn number(n: NumberWithPrecision, precision: u32, locale: Locale) -> String {
+    let base_amount = n.with_precision(precision).base_amount();
+    let divisor = 10_u64.pow(precision);
+    let integer = (base_amount / divisor).to_string();
+
+    let mut ret = String::with_capacity(integer.len() * 2 + precision as usize);
+    for (i, digit) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i).is_multiple_of(3) {
+            if let Some(separator) = locale.grouping_separator {
+                ret.push(separator);
+            }
+        }
+        ret.push(digit);
+    }
+    if precision > 0 {
+        ret.push(locale.decimal_separator);
+        ret.push_str(&format!(
+            "{:0width$}",
+            base_amount % divisor,
+            width = precision as usize
+        ));
+    }
+    ret
+}
+
+/// Renders a BTC amount, eg. `NumberWithPrecision::new(satoshis, BTC_PRECISION)`.
+pub fn btc(n: NumberWithPrecision, locale: Locale) -> String {
+    number(n, BTC_PRECISION, locale)
+}
+
+/// Renders a BSQ amount, 1 BSQ are 100 satoshis.
+pub fn bsq(n: NumberWithPrecision, locale: Locale) -> String {
+    number(n, BSQ_PRECISION, locale)
+}
+
+/// Renders an amount of `currency`, eg. 2 decimals for fiat and 8 for crypto.
+pub fn amount(n: NumberWithPrecision, currency: &Currency, locale: Locale) -> String {
+    number(n, currency.display_precision(), locale)
+}
+
+/// Renders a price with the precision bisq uses for the non btc side of `market`.
+pub fn price(n: NumberWithPrecision, market: &Market, locale: Locale) -> String {
+    number(n, market.non_btc_side().bisq_internal_precision(), locale)
+}
+
+/// Renders a ratio as percentage, eg. a market price margin of `0.015` as `1.50%`.
+pub fn percentage(ratio: f64, locale: Locale) -> String {
+    let formatted = format!("{:.*}%", PERCENTAGE_PRECISION, ratio * 100.0);
+    formatted.replace('.', &locale.decimal_separator.to_string())
+}
+
+/// Quotes a field like RFC 4180 when it contains commas, quotes or line breaks.
+pub fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains(&[',', '"', '\r', '\n'][..]) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_numbers() {
+        let n = NumberWithPrecision::new(1_234_567_891_234, 8);
+        assert_eq!(number(n, 8, Locale::RAW), "12345.67891234");
+        assert_eq!(number(n, 2, Locale::EN), "12,345.67");
+        assert_eq!(number(n, 4, Locale::DE), "12.345,6789");
+        assert_eq!(number(n, 0, Locale::FR), "12 345");
+        let satoshis = NumberWithPrecision::new(5_000, BTC_PRECISION);
+        assert_eq!(btc(satoshis, Locale::RAW), "0.00005000");
+        assert_eq!(percentage(0.015, Locale::EN), "1.50%");
+        assert_eq!(percentage(-0.1, Locale::DE), "-10,00%");
+    }
+
+    #[test]
+    fn format_with_currency_precision() {
+        let price_eur = NumberWithPrecision::new(90_001_234, 4);
+        let eur = Market::from_pair("btc_eur").unwrap();
+        assert_eq!(price(price_eur, eur, Locale::EN), "9,000.1234");
+        assert_eq!(amount(price_eur, eur.right, Locale::EN), "9,000.12");
+        let xmr = Market::from_pair("xmr_btc").unwrap();
+        assert_eq!(amount(price_eur, xmr.left, Locale::RAW), "9000.12340000");
+    }
+
+    #[test]
+    fn parse_locales() {
+        assert_eq!("C".parse(), Ok(Locale::RAW));
+        assert_eq!("en_US.UTF-8".parse(), Ok(Locale::EN));
+        assert_eq!("de_DE@euro".parse(), Ok(Locale::DE));
+        assert_eq!("fr-CA".parse(), Ok(Locale::FR));
+        assert!("tlh".parse::<Locale>().is_err());
+    }
+}
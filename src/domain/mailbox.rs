@@ -0,0 +1,125 @@
+use crate::bisq::payload::{storage_payload, ProtectedMailboxStorageEntry};
+use std::{
+    collections::HashSet,
+    sync::{Arc, RwLock},
+};
+
+/// Messages that were stored in the p2p network for us while we were
+/// offline, or because our peer couldn't reach us directly. They are
+/// collected from the data the seed nodes and peers send until the trade
+/// manager picks them up.
+#[derive(Clone)]
+pub struct Mailbox {
+    receiver_pub_key_bytes: Arc<Vec<u8>>,
+    inner: Arc<RwLock<MailboxInner>>,
+}
+
+#[derive(Default)]
+struct MailboxInner {
+    entries: Vec<ProtectedMailboxStorageEntry>,
+    /// Uids of every message handed out, as seed nodes keep sending the
+    /// entries until our removal reached them.
+    taken: HashSet<String>,
+}
+
+impl Mailbox {
+    /// Collects the entries for the signature key of our key ring.
+    pub fn new(receiver_pub_key_bytes: Vec<u8>) -> Self {
+        Self {
+            receiver_pub_key_bytes: Arc::new(receiver_pub_key_bytes),
+            inner: Arc::default(),
+        }
+    }
+
+    /// Ignores entries for other receivers and messages that were
+    /// already collected.
+    pub fn add(&self, entry: &ProtectedMailboxStorageEntry) {
+        if entry.receivers_pub_key_bytes != *self.receiver_pub_key_bytes {
+            return;
+        }
+        let uid = match uid(entry) {
+            Some(uid) => uid,
+            None => return,
+        };
+        let mut inner = self.inner.write().expect("Corrupted lock in mailbox");
+        if inner.taken.contains(uid)
+            || inner
+                .entries
+                .iter()
+                .any(|pending| self::uid(pending) == Some(uid))
+        {
+            return;
+        }
+        inner.entries.push(entry.clone());
+    }
+
+    pub fn take(&self) -> Vec<ProtectedMailboxStorageEntry> {
+        let mut inner = self.inner.write().expect("Corrupted lock in mailbox");
+        let entries: Vec<_> = inner.entries.drain(..).collect();
+        for entry in entries.iter() {
+            if let Some(uid) = uid(entry) {
+                inner.taken.insert(uid.to_string());
+            }
+        }
+        entries
+    }
+}
+
+fn uid(entry: &ProtectedMailboxStorageEntry) -> Option<&str> {
+    match entry
+        .entry
+        .as_ref()?
+        .storage_payload
+        .as_ref()?
+        .message
+        .as_ref()?
+    {
+        storage_payload::Message::MailboxStoragePayload(payload) => payload
+            .prefixed_sealed_and_signed_message
+            .as_ref()
+            .map(|message| message.uid.as_str()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bisq::payload::*;
+
+    fn entry(receiver: u8, uid: &str) -> ProtectedMailboxStorageEntry {
+        ProtectedMailboxStorageEntry {
+            entry: Some(ProtectedStorageEntry {
+                storage_payload: Some(StoragePayload {
+                    message: Some(storage_payload::Message::MailboxStoragePayload(
+                        MailboxStoragePayload {
+                            prefixed_sealed_and_signed_message: Some(
+                                PrefixedSealedAndSignedMessage {
+                                    uid: uid.into(),
+                                    ..Default::default()
+                                },
+                            ),
+                            ..Default::default()
+                        },
+                    )),
+                }),
+                ..Default::default()
+            }),
+            receivers_pub_key_bytes: vec![receiver],
+        }
+    }
+
+    #[test]
+    fn hands_out_messages_once() {
+        let mailbox = Mailbox::new(vec![1]);
+        mailbox.add(&entry(1, "a"));
+        mailbox.add(&entry(1, "a"));
+        mailbox.add(&entry(2, "b"));
+        assert_eq!(mailbox.take(), vec![entry(1, "a")]);
+
+        mailbox.add(&entry(1, "a"));
+        mailbox.add(&entry(1, "c"));
+        assert_eq!(mailbox.take(), vec![entry(1, "c")]);
+        assert!(mailbox.take().is_empty());
+    }
+}
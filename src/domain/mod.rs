@@ -1,9 +1,22 @@
+pub mod alert;
 pub mod amount;
+pub mod budget;
+pub mod clock;
 pub mod currency;
+pub mod dao;
+pub mod dispute;
+pub mod fees;
+pub mod filter;
+pub mod format;
+pub mod mailbox;
 pub mod market;
 pub mod offer;
+pub mod payment_account;
+pub mod payment_method;
 pub mod price_feed;
 pub mod statistics;
+pub mod trade;
+pub mod trade_fee;
 
 use crate::prelude::*;
 pub enum CommandResult {
@@ -17,19 +17,23 @@ impl<'a> BtcOffer<'a> {
         }
     }
 
+    pub fn price(&self) -> NumberWithPrecision {
+        self.inner.display_price
+    }
+
     pub fn volume(&self) -> NumberWithPrecision {
         if self.inner.market.non_btc_side().is_crypto() {
-            self.inner.amount.total
+            self.inner.amount.total.number()
         } else {
-            self.inner.display_price * self.inner.amount.total
+            self.inner.display_price * self.inner.amount.total.number()
         }
     }
 
     pub fn amount(&self) -> NumberWithPrecision {
         if self.inner.market.non_btc_side().is_crypto() {
-            self.inner.amount.total / self.inner.display_price
+            self.inner.amount.total.number() / self.inner.display_price
         } else {
-            self.inner.amount.total
+            self.inner.amount.total.number()
         }
     }
 }
@@ -45,7 +49,7 @@ mod tests {
         OpenOffer::new(
             SequencedMessageHash::new(
                 sha256::Hash::from_str(
-                    &"2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824",
+                    "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824",
                 )
                 .unwrap(),
             ),
@@ -54,13 +58,14 @@ mod tests {
             OfferDirection::Buy,
             OfferPrice::Fixed(NumberWithPrecision::new(1000, 0)),
             OfferAmount {
-                total: NumberWithPrecision::new(1, 0),
-                min: NumberWithPrecision::new(5, 1),
+                total: BtcAmount::from_sats(100_000_000),
+                min: BtcAmount::from_sats(50_000_000),
             },
             "PAYMENT_METHOD".into(),
             "OFFER_FEE_TX_ID".into(),
             UNIX_EPOCH,
             0.into(),
+            Default::default(),
         )
     }
 
@@ -68,7 +73,7 @@ mod tests {
         OpenOffer::new(
             SequencedMessageHash::new(
                 sha256::Hash::from_str(
-                    &"2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824",
+                    "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824",
                 )
                 .unwrap(),
             ),
@@ -77,13 +82,14 @@ mod tests {
             OfferDirection::Sell,
             OfferPrice::Fixed(NumberWithPrecision::new(7800, 8)),
             OfferAmount {
-                total: NumberWithPrecision::new(5, 1),
-                min: NumberWithPrecision::new(5, 1),
+                total: BtcAmount::from_sats(50_000_000),
+                min: BtcAmount::from_sats(50_000_000),
             },
             "PAYMENT_METHOD".into(),
             "OFFER_FEE_TX_ID".into(),
             UNIX_EPOCH,
             0.into(),
+            Default::default(),
         )
     }
 
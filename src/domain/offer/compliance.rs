@@ -0,0 +1,348 @@
+use super::{validation::OfferValidator, OpenOffer};
+use crate::{
+    bisq::payload::{Filter, NodeAddress, OfferPayload},
+    domain::{
+        amount::NumberWithPrecision, dispute::Arbitrators, filter::FilterStore,
+        format::BTC_PRECISION, payment_method::PaymentMethodInfo, trade_fee::MIN_MAKER_FEE_BTC,
+    },
+    prelude::FromHex,
+};
+use std::{fmt, str::FromStr, time::Duration};
+
+/// Offers of older trade protocols have no delayed payout tx, so a disputed
+/// deposit can't be refunded by the refund agent.
+const DELAYED_PAYOUT_PROTOCOL_VERSION: i32 = 2;
+/// Bisq's `Restrictions.MIN_BUYER_SECURITY_DEPOSIT`, also the minimum of the
+/// seller's deposit.
+const MIN_SECURITY_DEPOSIT: u64 = 100_000;
+/// Bisq caps the buyer's deposit at 50% of the amount, the seller's is
+/// checked against the same range.
+const MAX_SECURITY_DEPOSIT_PERCENT: u64 = 50;
+/// Bisq's default deposit of both traders, in percent of the amount.
+const DEFAULT_SECURITY_DEPOSIT_PERCENT: u64 = 15;
+/// Far above any fee the DAO ever set, only to catch broken offers.
+const MAX_MAKER_FEE_PERCENT: u64 = 1;
+const MAX_TX_FEE: u64 = 1_000_000;
+pub const ACCOUNT_AGE_WITNESS_HASH_KEY: &str = "accountAgeWitnessHash";
+
+/// Deviations of an offer from what the bisq client creates, which Java
+/// peers would likely refuse to trade with.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Anomaly {
+    NoDelayedPayout {
+        protocol_version: i32,
+    },
+    BuyerDeposit(u64),
+    SellerDeposit(u64),
+    MakerFee(u64),
+    TxFee(u64),
+    MinAmountAboveAmount,
+    InvalidFeeTxId,
+    TradePeriod {
+        expected: Duration,
+        actual: Duration,
+    },
+    UnknownArbitrator(NodeAddress),
+    /// The filter disables trading for clients below `below`
+    TradingDisabled {
+        version: String,
+        below: String,
+    },
+    /// The filter banned every mediator the offer accepts
+    BannedMediators,
+}
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let btc = |sats: u64| NumberWithPrecision::new(sats, BTC_PRECISION).format(BTC_PRECISION);
+        match self {
+            Anomaly::NoDelayedPayout { protocol_version } => write!(
+                f,
+                "protocol version {} has no delayed payout tx",
+                protocol_version
+            ),
+            Anomaly::BuyerDeposit(sats) => {
+                write!(f, "buyer security deposit of {} BTC", btc(*sats))
+            }
+            Anomaly::SellerDeposit(sats) => {
+                write!(f, "seller security deposit of {} BTC", btc(*sats))
+            }
+            Anomaly::MakerFee(sats) => write!(f, "maker fee of {} BTC", btc(*sats)),
+            Anomaly::TxFee(sats) => write!(f, "tx fee of {} BTC", btc(*sats)),
+            Anomaly::MinAmountAboveAmount => write!(f, "min amount above the amount"),
+            Anomaly::InvalidFeeTxId => write!(f, "invalid maker fee tx id"),
+            Anomaly::TradePeriod { expected, actual } => write!(
+                f,
+                "trade period of {}h instead of {}h",
+                actual.as_secs() / 3600,
+                expected.as_secs() / 3600
+            ),
+            Anomaly::UnknownArbitrator(address) => write!(f, "unknown arbitrator {}", address),
+            Anomaly::TradingDisabled { version, below } => write!(
+                f,
+                "version {} can't trade, the filter disables versions below {}",
+                version, below
+            ),
+            Anomaly::BannedMediators => write!(f, "all mediators of the offer are banned"),
+        }
+    }
+}
+
+/// The security deposit the bisq client proposes for an offer of `amount`
+/// satoshis, for the buyer as well as the seller.
+pub fn default_security_deposit(amount: u64) -> u64 {
+    (amount * DEFAULT_SECURITY_DEPOSIT_PERCENT / 100).max(MIN_SECURITY_DEPOSIT)
+}
+
+/// Checks the fees and deposits of an offer against the limits of the bisq
+/// client. Only plausibility, the DAO changes the exact fees over time.
+pub fn check(offer: &OpenOffer) -> Vec<Anomaly> {
+    let payload: &OfferPayload = &offer.payload;
+    let amount = payload.amount.max(0) as u64;
+    let max_deposit = (amount * MAX_SECURITY_DEPOSIT_PERCENT / 100).max(MIN_SECURITY_DEPOSIT);
+    let deposit_ok = |deposit: u64| deposit >= MIN_SECURITY_DEPOSIT && deposit <= max_deposit;
+    let mut anomalies = Vec::new();
+
+    if payload.protocol_version < DELAYED_PAYOUT_PROTOCOL_VERSION {
+        anomalies.push(Anomaly::NoDelayedPayout {
+            protocol_version: payload.protocol_version,
+        });
+    }
+    let buyer_deposit = payload.buyer_security_deposit.max(0) as u64;
+    if !deposit_ok(buyer_deposit) {
+        anomalies.push(Anomaly::BuyerDeposit(buyer_deposit));
+    }
+    let seller_deposit = payload.seller_security_deposit.max(0) as u64;
+    if !deposit_ok(seller_deposit) {
+        anomalies.push(Anomaly::SellerDeposit(seller_deposit));
+    }
+    let maker_fee = payload.maker_fee.max(0) as u64;
+    // BSQ fees are in BSQ and can't be compared with the amount
+    let max_maker_fee = (amount * MAX_MAKER_FEE_PERCENT / 100).max(MIN_MAKER_FEE_BTC);
+    if maker_fee == 0
+        || payload.is_currency_for_maker_fee_btc
+            && (maker_fee < MIN_MAKER_FEE_BTC || maker_fee > max_maker_fee)
+    {
+        anomalies.push(Anomaly::MakerFee(maker_fee));
+    }
+    let tx_fee = payload.tx_fee.max(0) as u64;
+    if tx_fee == 0 || tx_fee > MAX_TX_FEE {
+        anomalies.push(Anomaly::TxFee(tx_fee));
+    }
+    if payload.min_amount > payload.amount {
+        anomalies.push(Anomaly::MinAmountAboveAmount);
+    }
+    let fee_tx_id = &payload.offer_fee_payment_tx_id;
+    if fee_tx_id.len() != 64 || !fee_tx_id.chars().all(|c| c.is_ascii_hexdigit()) {
+        anomalies.push(Anomaly::InvalidFeeTxId);
+    }
+    if let Some(payment_method) = PaymentMethodInfo::from_id(&payload.payment_method_id) {
+        let expected = payment_method.max_trade_period;
+        let actual = Duration::from_millis(payload.max_trade_period.max(0) as u64);
+        if actual != expected {
+            anomalies.push(Anomaly::TradePeriod { expected, actual });
+        }
+    }
+    anomalies
+}
+
+/// Arbitrators the offer lists that aren't registered in the network.
+pub fn check_arbitrators(offer: &OpenOffer, arbitrators: &Arbitrators) -> Vec<Anomaly> {
+    offer
+        .payload
+        .arbitrator_node_addresses
+        .iter()
+        .filter(|address| !arbitrators.contains(address))
+        .map(|address| Anomaly::UnknownArbitrator(address.clone()))
+        .collect()
+}
+
+/// Checks whether a trade on the offer could go through with its delayed
+/// payout tx under `filter`: the maker's version has to be allowed to trade
+/// and one of the mediators it accepts must not be banned, disputes go
+/// through them before the refund agent.
+pub fn check_filter(offer: &OpenOffer, filter: &Filter) -> Vec<Anomaly> {
+    let payload: &OfferPayload = &offer.payload;
+    let mut anomalies = Vec::new();
+    let below = &filter.disable_trade_below_version;
+    if !below.is_empty() && version_below(&payload.version_nr, below) {
+        anomalies.push(Anomaly::TradingDisabled {
+            version: payload.version_nr.clone(),
+            below: below.clone(),
+        });
+    }
+    if !payload.mediator_node_addresses.is_empty()
+        && payload
+            .mediator_node_addresses
+            .iter()
+            .all(|address| filter.mediators.contains(&address.to_string()))
+    {
+        anomalies.push(Anomaly::BannedMediators);
+    }
+    anomalies
+}
+
+// Versions are compared by their numbers, one that doesn't parse is too old
+fn version_below(version: &str, min: &str) -> bool {
+    let numbers = |version: &str| {
+        version
+            .split('.')
+            .map(u32::from_str)
+            .collect::<Result<Vec<_>, _>>()
+    };
+    match (numbers(version), numbers(min)) {
+        (Ok(version), Ok(min)) => version < min,
+        (Err(_), Ok(_)) => true,
+        _ => false,
+    }
+}
+
+/// What happens to offers whose delayed payout tx wouldn't hold up.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DelayedPayoutPolicy {
+    /// They are listed and flagged under `/offers/anomalies`
+    #[default]
+    Flag,
+    /// They aren't listed, and are removed when a new filter rules them out
+    Hide,
+}
+impl FromStr for DelayedPayoutPolicy {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flag" => Ok(DelayedPayoutPolicy::Flag),
+            "hide" => Ok(DelayedPayoutPolicy::Hide),
+            _ => Err(format!("'{}' is not one of flag, hide", s)),
+        }
+    }
+}
+
+/// Refuses offers without a delayed payout tx and those the active filter
+/// rules out, with `DelayedPayoutPolicy::Hide`.
+pub struct DelayedPayout(pub FilterStore);
+impl OfferValidator for DelayedPayout {
+    fn name(&self) -> &'static str {
+        "delayed_payout"
+    }
+    fn validate(&self, offer: &OpenOffer) -> Result<(), String> {
+        let protocol_version = offer.payload.protocol_version;
+        if protocol_version < DELAYED_PAYOUT_PROTOCOL_VERSION {
+            return Err(Anomaly::NoDelayedPayout { protocol_version }.to_string());
+        }
+        match self.0.delayed_payout_anomalies(offer).first() {
+            Some(anomaly) => Err(anomaly.to_string()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The account age witness the maker published for its payment account.
+pub fn maker_witness_hash(offer: &OpenOffer) -> Option<Vec<u8>> {
+    offer
+        .payload
+        .extra_data
+        .iter()
+        .find(|entry| entry.key() == ACCOUNT_AGE_WITNESS_HASH_KEY)
+        .and_then(|entry| Vec::<u8>::from_hex(entry.value()).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::offer::{OfferDirection, OfferPrice, TestOffer};
+
+    fn offer(payload: OfferPayload) -> OpenOffer {
+        TestOffer::from_payload(payload)
+            .direction(OfferDirection::Buy)
+            .price(OfferPrice::MarketWithMargin(0.0))
+            .build()
+    }
+
+    #[test]
+    fn flags_implausible_deposits() {
+        let mut payload = OfferPayload {
+            id: "offer".into(),
+            amount: 10_000_000,
+            min_amount: 5_000_000,
+            payment_method_id: "SEPA".into(),
+            offer_fee_payment_tx_id: "ab".repeat(32),
+            tx_fee: 20_000,
+            maker_fee: 20_000,
+            is_currency_for_maker_fee_btc: true,
+            buyer_security_deposit: 1_500_000,
+            seller_security_deposit: 1_500_000,
+            max_trade_period: PaymentMethodInfo::from_id("SEPA")
+                .unwrap()
+                .max_trade_period
+                .as_millis() as i64,
+            protocol_version: 2,
+            ..Default::default()
+        };
+        assert_eq!(check(&offer(payload.clone())), vec![]);
+
+        payload.protocol_version = 1;
+        payload.buyer_security_deposit = 6_000_000;
+        assert_eq!(
+            check(&offer(payload)),
+            vec![
+                Anomaly::NoDelayedPayout {
+                    protocol_version: 1
+                },
+                Anomaly::BuyerDeposit(6_000_000)
+            ]
+        );
+    }
+
+    #[test]
+    fn flags_unknown_arbitrators() {
+        let unknown: NodeAddress = "unknown.onion:9999".parse().unwrap();
+        let payload = OfferPayload {
+            arbitrator_node_addresses: vec![unknown.clone()],
+            ..Default::default()
+        };
+        assert_eq!(
+            check_arbitrators(&offer(payload), &Arbitrators::default()),
+            vec![Anomaly::UnknownArbitrator(unknown)]
+        );
+        assert_eq!(
+            check_arbitrators(&offer(OfferPayload::default()), &Arbitrators::default()),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn flags_offers_the_filter_rules_out() {
+        let mediator: NodeAddress = "mediator.onion:9999".parse().unwrap();
+        let mut payload = OfferPayload {
+            version_nr: "1.2.9".into(),
+            mediator_node_addresses: vec![mediator.clone()],
+            protocol_version: 2,
+            ..Default::default()
+        };
+        let mut filter = Filter {
+            disable_trade_below_version: "1.3.0".into(),
+            ..Default::default()
+        };
+        assert_eq!(
+            check_filter(&offer(payload.clone()), &filter),
+            vec![Anomaly::TradingDisabled {
+                version: "1.2.9".into(),
+                below: "1.3.0".into()
+            }]
+        );
+
+        payload.version_nr = "1.10.0".into();
+        filter.mediators = vec![mediator.to_string()];
+        assert_eq!(
+            check_filter(&offer(payload.clone()), &filter),
+            vec![Anomaly::BannedMediators]
+        );
+        payload
+            .mediator_node_addresses
+            .push("other.onion:9999".parse().unwrap());
+        assert_eq!(check_filter(&offer(payload), &filter), vec![]);
+
+        assert!(version_below("", "1.3.0"));
+        assert!(!version_below("1.3.0", "1.3.0"));
+        assert!(!version_below("1.3.0", "not a version"));
+    }
+}
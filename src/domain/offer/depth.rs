@@ -0,0 +1,112 @@
+use super::btc_offer::BtcOffer;
+use crate::domain::{amount::*, market::Market, offer::*};
+use std::cmp::Reverse;
+
+/// Open offers of a market summed up per price, in terms of the left side of
+/// the market like `BtcOffer`.
+pub struct Depth {
+    pub market: &'static Market,
+    /// Highest price first
+    pub buys: Vec<DepthLevel>,
+    /// Lowest price first
+    pub sells: Vec<DepthLevel>,
+}
+
+pub struct DepthLevel {
+    pub price: NumberWithPrecision,
+    pub amount: NumberWithPrecision,
+    /// Amount of this level and all levels with a better price
+    pub cumulative_amount: NumberWithPrecision,
+    pub num_offers: usize,
+}
+
+impl Depth {
+    pub fn new<'a>(market: &'static Market, offers: impl Iterator<Item = &'a OpenOffer>) -> Self {
+        let (mut buys, mut sells): (Vec<_>, Vec<_>) = offers
+            .filter(|offer| offer.market.pair == market.pair && offer.price().is_some())
+            .map(BtcOffer::new)
+            .partition(|offer| offer.direction() == OfferDirection::Buy);
+        buys.sort_by_key(|offer| Reverse(offer.price()));
+        sells.sort_by_key(BtcOffer::price);
+        Self {
+            market,
+            buys: levels(buys),
+            sells: levels(sells),
+        }
+    }
+}
+
+fn levels(offers: Vec<BtcOffer>) -> Vec<DepthLevel> {
+    let mut levels: Vec<DepthLevel> = Vec::new();
+    for offer in offers {
+        match levels.last_mut() {
+            Some(level) if level.price == offer.price() => {
+                level.amount += offer.amount();
+                level.cumulative_amount += offer.amount();
+                level.num_offers += 1;
+            }
+            last => {
+                let mut cumulative_amount = offer.amount();
+                if let Some(level) = last {
+                    cumulative_amount += level.cumulative_amount;
+                }
+                levels.push(DepthLevel {
+                    price: offer.price(),
+                    amount: offer.amount(),
+                    cumulative_amount,
+                    num_offers: 1,
+                });
+            }
+        }
+    }
+    levels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::offer::TestOffer;
+
+    fn offer(id: &str, direction: OfferDirection, price: u64, amount: u64) -> OpenOffer {
+        TestOffer::new(id)
+            .direction(direction)
+            .price(OfferPrice::Fixed(NumberWithPrecision::new(price, 0)))
+            .amount(amount * 10_000_000)
+            .build()
+    }
+
+    #[test]
+    fn sums_up_offers_per_price() {
+        let offers = [
+            offer("a", OfferDirection::Buy, 8900, 5),
+            offer("b", OfferDirection::Buy, 9000, 1),
+            offer("c", OfferDirection::Sell, 9100, 3),
+            offer("d", OfferDirection::Buy, 9000, 2),
+        ];
+        let depth = Depth::new(Market::from_pair("btc_eur").unwrap(), offers.iter());
+        let levels = |levels: &[DepthLevel]| -> Vec<(u64, u64, u64, usize)> {
+            levels
+                .iter()
+                .map(|level| {
+                    (
+                        level.price.base_amount(),
+                        level.amount.base_amount(),
+                        level.cumulative_amount.base_amount(),
+                        level.num_offers,
+                    )
+                })
+                .collect()
+        };
+        assert_eq!(
+            levels(&depth.buys),
+            vec![
+                (9000, 30_000_000, 30_000_000, 2),
+                (8900, 50_000_000, 80_000_000, 1)
+            ]
+        );
+        assert_eq!(
+            levels(&depth.sells),
+            vec![(9100, 30_000_000, 30_000_000, 1)]
+        );
+    }
+}
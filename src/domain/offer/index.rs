@@ -0,0 +1,130 @@
+use super::OpenOffer;
+use crate::bisq::{NodeAddress, SequencedMessageHash};
+use std::collections::{HashMap, HashSet};
+
+/// Secondary indexes of the open offers, so queries and depths of one
+/// market, maker or payment method don't scan the whole book.
+#[derive(Clone, Default)]
+pub struct OfferIndex {
+    by_market: HashMap<&'static str, HashSet<SequencedMessageHash>>,
+    by_maker: HashMap<NodeAddress, HashSet<SequencedMessageHash>>,
+    by_payment_method: HashMap<String, HashSet<SequencedMessageHash>>,
+}
+
+impl OfferIndex {
+    #[cfg(any(test, feature = "testkit"))]
+    pub fn new<'a>(offers: impl Iterator<Item = &'a OpenOffer>) -> Self {
+        let mut index = Self::default();
+        for offer in offers {
+            index.insert(offer);
+        }
+        index
+    }
+
+    pub fn insert(&mut self, offer: &OpenOffer) {
+        self.by_market
+            .entry(offer.market.pair.as_str())
+            .or_default()
+            .insert(offer.bisq_hash);
+        if let Some(maker) = offer.payload.owner_node_address.as_ref() {
+            self.by_maker
+                .entry(maker.clone())
+                .or_default()
+                .insert(offer.bisq_hash);
+        }
+        self.by_payment_method
+            .entry(offer.payment_method.id().to_string())
+            .or_default()
+            .insert(offer.bisq_hash);
+    }
+
+    pub fn remove(&mut self, offer: &OpenOffer) {
+        remove_from(
+            &mut self.by_market,
+            offer.market.pair.as_str(),
+            &offer.bisq_hash,
+        );
+        if let Some(maker) = offer.payload.owner_node_address.as_ref() {
+            remove_from(&mut self.by_maker, maker, &offer.bisq_hash);
+        }
+        remove_from(
+            &mut self.by_payment_method,
+            offer.payment_method.id(),
+            &offer.bisq_hash,
+        );
+    }
+
+    pub fn market<'a>(&'a self, pair: &str) -> impl Iterator<Item = &'a SequencedMessageHash> {
+        self.by_market.get(pair).into_iter().flatten()
+    }
+
+    pub fn maker<'a>(
+        &'a self,
+        maker: &NodeAddress,
+    ) -> impl Iterator<Item = &'a SequencedMessageHash> {
+        self.by_maker.get(maker).into_iter().flatten()
+    }
+
+    pub fn payment_method<'a>(
+        &'a self,
+        id: &str,
+    ) -> impl Iterator<Item = &'a SequencedMessageHash> {
+        self.by_payment_method.get(id).into_iter().flatten()
+    }
+}
+
+// Keys without offers are dropped, the book sees lots of makers come and go
+fn remove_from<K, Q>(
+    index: &mut HashMap<K, HashSet<SequencedMessageHash>>,
+    key: &Q,
+    hash: &SequencedMessageHash,
+) where
+    K: std::borrow::Borrow<Q> + std::hash::Hash + Eq,
+    Q: std::hash::Hash + Eq + ?Sized,
+{
+    if let Some(hashes) = index.get_mut(key) {
+        hashes.remove(hash);
+        if hashes.is_empty() {
+            index.remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::offer::TestOffer;
+
+    fn offer(id: &str, pair: &'static str, payment_method: &str, maker: &str) -> OpenOffer {
+        TestOffer::new(id)
+            .pair(pair)
+            .payment_method(payment_method)
+            .maker(maker)
+            .created_at(1)
+            .build()
+    }
+
+    #[test]
+    fn stays_consistent_with_the_book() {
+        let a = offer("a", "btc_eur", "SEPA", "maker1.onion:9999");
+        let b = offer("b", "btc_eur", "REVOLUT", "maker2.onion:9999");
+        let c = offer("c", "btc_usd", "SEPA", "maker1.onion:9999");
+        let mut index = OfferIndex::new(vec![&a, &b, &c].into_iter());
+        let count = |hashes: Vec<&SequencedMessageHash>| hashes.len();
+
+        assert_eq!(count(index.market("btc_eur").collect()), 2);
+        assert_eq!(count(index.payment_method("SEPA").collect()), 2);
+        let maker1 = "maker1.onion:9999".parse().unwrap();
+        assert_eq!(count(index.maker(&maker1).collect()), 2);
+
+        index.remove(&a);
+        index.remove(&c);
+        assert_eq!(
+            index.market("btc_eur").collect::<Vec<_>>(),
+            vec![&b.bisq_hash]
+        );
+        assert_eq!(count(index.maker(&maker1).collect()), 0);
+        assert!(!index.by_maker.contains_key(&maker1));
+        assert_eq!(count(index.market("btc_usd").collect()), 0);
+    }
+}
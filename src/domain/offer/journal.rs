@@ -0,0 +1,168 @@
+use super::{OfferId, OpenOffer};
+use crate::domain::price_feed::PriceData;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Changes kept at most, older versions have to get the whole book again.
+const MAX_ENTRIES: usize = 10_000;
+
+#[derive(Clone)]
+pub enum JournalChange {
+    Added(OpenOffer),
+    Refreshed(OpenOffer),
+    Removed(OpenOffer),
+}
+impl JournalChange {
+    fn offer(&self) -> &OpenOffer {
+        match self {
+            JournalChange::Added(offer)
+            | JournalChange::Refreshed(offer)
+            | JournalChange::Removed(offer) => offer,
+        }
+    }
+}
+
+/// What changed in the offer book between two versions, one entry per
+/// offer.
+#[derive(Default)]
+pub struct OfferChanges {
+    pub version: u64,
+    /// Offers that are new or were removed and added again since, they
+    /// replace what the poller had
+    pub added: Vec<OpenOffer>,
+    pub refreshed: Vec<OpenOffer>,
+    pub removed: Vec<OfferId>,
+    /// The market prices the premiums of the offers are relative to
+    pub price_data: Arc<HashMap<&'static str, PriceData>>,
+}
+
+/// The latest changes of the offer book, numbered by the version of the
+/// book after each of them.
+pub struct OfferJournal {
+    version: u64,
+    /// The oldest version the changes since can be told from
+    oldest: u64,
+    entries: VecDeque<(u64, JournalChange)>,
+    max_entries: usize,
+}
+impl Default for OfferJournal {
+    // Versions start at the millisecond the journal was created, versions
+    // of an earlier run of the daemon are older than its oldest one.
+    fn default() -> Self {
+        let start = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        Self::starting_at(start, MAX_ENTRIES)
+    }
+}
+impl OfferJournal {
+    fn starting_at(version: u64, max_entries: usize) -> Self {
+        Self {
+            version,
+            oldest: version,
+            entries: VecDeque::new(),
+            max_entries,
+        }
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn record(&mut self, change: JournalChange) {
+        self.version += 1;
+        self.entries.push_back((self.version, change));
+        while self.entries.len() > self.max_entries {
+            if let Some((version, _)) = self.entries.pop_front() {
+                self.oldest = version;
+            }
+        }
+    }
+
+    /// `None` when `since` is older than the journal or newer than the book.
+    pub fn changes_since(&self, since: u64) -> Option<OfferChanges> {
+        if since < self.oldest || since > self.version {
+            return None;
+        }
+        // The first and the last change of every offer tell what the poller
+        // has to do
+        let mut offers: Vec<OfferId> = Vec::new();
+        let mut changes: HashMap<&OfferId, (&JournalChange, &JournalChange, bool)> = HashMap::new();
+        for (_, change) in self.entries.iter().filter(|(version, _)| *version > since) {
+            let id = &change.offer().id;
+            let removed = matches!(change, JournalChange::Removed(_));
+            match changes.get_mut(id) {
+                Some((_, last, any_removed)) => {
+                    *last = change;
+                    *any_removed |= removed;
+                }
+                None => {
+                    offers.push(id.clone());
+                    changes.insert(id, (change, change, removed));
+                }
+            }
+        }
+        let mut result = OfferChanges {
+            version: self.version,
+            ..OfferChanges::default()
+        };
+        for id in offers {
+            let (first, last, any_removed) = changes[&id];
+            let new = matches!(first, JournalChange::Added(_));
+            match last {
+                // The poller never saw it
+                JournalChange::Removed(_) if new => (),
+                JournalChange::Removed(_) => result.removed.push(id),
+                _ if new || any_removed => result.added.push(last.offer().clone()),
+                _ => result.refreshed.push(last.offer().clone()),
+            }
+        }
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::offer::TestOffer;
+
+    fn offer(id: &str) -> OpenOffer {
+        TestOffer::new(id).created_at(1).build()
+    }
+
+    fn ids(offers: &[OpenOffer]) -> Vec<String> {
+        offers.iter().map(|offer| offer.id.clone().into()).collect()
+    }
+
+    #[test]
+    fn tells_the_changes_since_a_version() {
+        let mut journal = OfferJournal::starting_at(100, 5);
+        journal.record(JournalChange::Added(offer("a")));
+        journal.record(JournalChange::Added(offer("b")));
+        let since = journal.version();
+        journal.record(JournalChange::Refreshed(offer("a")));
+        journal.record(JournalChange::Added(offer("c")));
+        journal.record(JournalChange::Removed(offer("c")));
+        journal.record(JournalChange::Removed(offer("b")));
+
+        let changes = journal.changes_since(since).unwrap();
+        assert_eq!(changes.version, 106);
+        assert_eq!(ids(&changes.refreshed), vec!["a"]);
+        assert!(changes.added.is_empty());
+        assert_eq!(changes.removed, vec![offer("b").id]);
+
+        let changes = journal.changes_since(journal.version()).unwrap();
+        assert!(changes.added.is_empty() && changes.removed.is_empty());
+        assert!(journal.changes_since(journal.version() + 1).is_none());
+        // The first entry was dropped to stay within 5
+        assert!(journal.changes_since(100).is_none());
+        // b was added and removed since
+        let changes = journal.changes_since(101).unwrap();
+        assert_eq!(ids(&changes.refreshed), vec!["a"]);
+        assert!(changes.added.is_empty() && changes.removed.is_empty());
+    }
+}
@@ -1,6 +1,12 @@
-use super::{open_offer::OfferSequence, OpenOffer};
-use crate::{bisq::SequencedMessageHash, domain::CommandResult, prelude::Message};
-use std::{collections::HashMap, sync::Arc};
+use super::{open_offer::OfferSequence, Depth, OfferChanges, OfferDirection, OpenOffer};
+use crate::{
+    bisq::{NodeAddress, SequencedMessageHash},
+    domain::{
+        amount::BtcAmount, currency::Currency, market::Market, price_feed::PriceData, CommandResult,
+    },
+    prelude::{Message, Recipient},
+};
+use std::{collections::HashMap, str::FromStr, sync::Arc};
 
 pub struct AddOffer(pub OpenOffer);
 impl Message for AddOffer {
@@ -11,6 +17,12 @@ impl Message for RemoveOffer {
     type Result = CommandResult;
 }
 
+/// Drops the offers the filter bans since it changed.
+pub struct ApplyFilter;
+impl Message for ApplyFilter {
+    type Result = ();
+}
+
 pub struct RefreshOffer {
     pub bisq_hash: SequencedMessageHash,
     pub sequence: OfferSequence,
@@ -23,3 +35,152 @@ pub struct GetOpenOffers;
 impl Message for GetOpenOffers {
     type Result = Arc<HashMap<SequencedMessageHash, OpenOffer>>;
 }
+
+/// The changes of the offer book since the version of an earlier
+/// `OfferPage` or `OfferChanges`, `None` when the journal doesn't reach back
+/// that far.
+pub struct GetOfferChanges(pub u64);
+impl Message for GetOfferChanges {
+    type Result = Option<OfferChanges>;
+}
+
+/// The versions the offers were computed from, much cheaper to ask for
+/// than the offers.
+pub struct GetOfferBookVersion;
+impl Message for GetOfferBookVersion {
+    type Result = OfferBookVersion;
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct OfferBookVersion {
+    pub offers: u64,
+    /// Counts the updates of the market prices
+    pub prices: u64,
+}
+
+/// The depth of a market, computed again once its offers changed.
+pub struct GetDepth(pub &'static Market);
+impl Message for GetDepth {
+    type Result = Arc<Depth>;
+}
+
+/// Registers for every change of the offer book from now on. Subscribers
+/// that are gone are dropped with the next change.
+pub struct SubscribeOfferBook(pub Recipient<OfferBookChange>);
+impl Message for SubscribeOfferBook {
+    type Result = ();
+}
+
+#[derive(Clone)]
+pub enum OfferBookChange {
+    Added {
+        offer: OpenOffer,
+        premium: Option<f64>,
+    },
+    Refreshed {
+        offer: OpenOffer,
+        premium: Option<f64>,
+    },
+    Removed(OpenOffer),
+}
+impl Message for OfferBookChange {
+    type Result = ();
+}
+
+/// The open offers that pass all filters, sorted and paged.
+#[derive(Clone, Debug, Default)]
+pub struct QueryOffers {
+    pub direction: Option<OfferDirection>,
+    /// The non btc side of the market
+    pub currency: Option<&'static Currency>,
+    pub payment_method: Option<String>,
+    pub maker: Option<NodeAddress>,
+    /// Offers of at least this many BTC
+    pub min_amount: Option<BtcAmount>,
+    pub sort: OfferSort,
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+impl QueryOffers {
+    pub fn matches(&self, offer: &OpenOffer) -> bool {
+        self.direction
+            .map(|direction| direction == offer.direction)
+            .unwrap_or(true)
+            && self
+                .currency
+                .map(|currency| currency == offer.market.non_btc_side())
+                .unwrap_or(true)
+            && self
+                .payment_method
+                .as_ref()
+                .map(|method| method == offer.payment_method.id())
+                .unwrap_or(true)
+            && self
+                .maker
+                .as_ref()
+                .map(|maker| offer.payload.owner_node_address.as_ref() == Some(maker))
+                .unwrap_or(true)
+            && self
+                .min_amount
+                .map(|min_amount| offer.amount.total >= min_amount)
+                .unwrap_or(true)
+    }
+}
+impl Message for QueryOffers {
+    type Result = OfferPage;
+}
+
+pub struct OfferPage {
+    /// How many offers passed the filters
+    pub total: usize,
+    pub offers: Vec<OpenOffer>,
+    /// The market prices the premiums of the offers are relative to
+    pub price_data: Arc<HashMap<&'static str, PriceData>>,
+    /// Of the offer book, to ask for the changes since
+    pub version: u64,
+    /// Of the market prices, they change the prices and premiums of the
+    /// offers following the market
+    pub prices_version: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OfferSortKey {
+    CreatedAt,
+    Price,
+    Amount,
+    Premium,
+}
+
+/// Parses like `price` or `-price` for descending order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OfferSort {
+    pub key: OfferSortKey,
+    pub descending: bool,
+}
+impl Default for OfferSort {
+    fn default() -> Self {
+        OfferSort {
+            key: OfferSortKey::CreatedAt,
+            descending: false,
+        }
+    }
+}
+impl FromStr for OfferSort {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let descending = s.starts_with('-');
+        let key = match s.trim_start_matches('-') {
+            "created_at" => OfferSortKey::CreatedAt,
+            "price" => OfferSortKey::Price,
+            "amount" => OfferSortKey::Amount,
+            "premium" => OfferSortKey::Premium,
+            _ => {
+                return Err(format!(
+                    "Invalid sort '{}', expected [-]created_at, price, amount or premium",
+                    s
+                ))
+            }
+        };
+        Ok(OfferSort { key, descending })
+    }
+}
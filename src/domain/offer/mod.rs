@@ -1,7 +1,22 @@
+mod btc_offer;
+mod depth;
+mod index;
+mod journal;
 mod offer_book;
 mod open_offer;
+#[cfg(test)]
+mod test_offer;
 
+pub mod compliance;
 pub mod message;
+pub mod validation;
 
+pub use btc_offer::BtcOffer;
+pub use depth::{Depth, DepthLevel};
+#[cfg(feature = "testkit")]
+pub use index::OfferIndex;
+pub use journal::OfferChanges;
 pub use offer_book::OfferBook;
 pub use open_offer::{OfferAmount, OfferDirection, OfferId, OfferPrice, OpenOffer};
+#[cfg(test)]
+pub(crate) use test_offer::TestOffer;
@@ -1,33 +1,66 @@
-use super::{message::*, *};
+use super::{
+    index::OfferIndex,
+    journal::{JournalChange, OfferJournal},
+    message::*,
+    *,
+};
 use crate::{
     bisq::SequencedMessageHash,
-    domain::{price_feed::*, CommandResult},
+    domain::{
+        budget::Evictions, filter::FilterStore, market::Market, price_feed::*, CommandResult,
+    },
+    events::{Event, Events},
+    metrics,
+    p2p::MAILBOX_CAPACITY,
     prelude::*,
+    scheduler::{Schedule, Task},
+};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, SystemTime},
 };
-use std::{collections::HashMap, sync::Arc, time::Duration};
 
-const CHECK_TTL_INTERVAL: Duration = Duration::from_secs(40);
+const EXPIRE_OFFERS: Task = Task::new("offer_expiry", Duration::from_secs(40));
 
 pub struct OfferBook {
     open_offers: Arc<HashMap<SequencedMessageHash, OpenOffer>>,
+    /// Kept in step with `open_offers`
+    index: OfferIndex,
     price_feed: Addr<PriceFeed>,
     price_data: Arc<HashMap<&'static str, PriceData>>,
+    max_offers: Option<usize>,
+    evictions: Evictions,
+    events: Events,
+    filters: FilterStore,
+    subscribers: Vec<Recipient<OfferBookChange>>,
+    /// Depth of the markets asked for since their offers last changed
+    depths: HashMap<&'static str, Arc<Depth>>,
+    journal: OfferJournal,
+    prices_version: u64,
 }
 impl Actor for OfferBook {
     type Context = Context<Self>;
     fn started(&mut self, ctx: &mut Self::Context) {
-        ctx.run_interval(CHECK_TTL_INTERVAL, |offer_book, ctx| {
+        ctx.set_mailbox_capacity(MAILBOX_CAPACITY);
+        ctx.schedule(EXPIRE_OFFERS, |offer_book, ctx| {
             ctx.spawn(
                 fut::wrap_future(offer_book.price_feed.send(GetCurrentPrices)).then(
                     |maybe_data, offer_book: &mut OfferBook, _| {
                         if let Ok(price_data) = maybe_data {
+                            if !Arc::ptr_eq(&price_data, &offer_book.price_data) {
+                                offer_book.prices_version += 1;
+                            }
                             offer_book.price_data = price_data;
                         }
+                        let mut expired = Vec::new();
                         let open_offers = offer_book
                             .open_offers
                             .iter()
                             .filter_map(|(hash, offer)| {
                                 if offer.is_expired() {
+                                    expired.push(offer.clone());
                                     None
                                 } else {
                                     let mut offer = offer.clone();
@@ -37,6 +70,11 @@ impl Actor for OfferBook {
                             })
                             .collect();
                         offer_book.open_offers = Arc::new(open_offers);
+                        offer_book.depths.clear();
+                        for offer in expired {
+                            offer_book.index.remove(&offer);
+                            offer_book.removed(offer, "expired");
+                        }
                         fut::ok(())
                     },
                 ),
@@ -45,29 +83,134 @@ impl Actor for OfferBook {
     }
 }
 impl OfferBook {
-    pub fn start(price_feed: Addr<PriceFeed>) -> Addr<OfferBook> {
+    pub fn start(
+        price_feed: Addr<PriceFeed>,
+        max_offers: Option<usize>,
+        evictions: Evictions,
+        events: Events,
+        filters: FilterStore,
+    ) -> Addr<OfferBook> {
         OfferBook {
             open_offers: Arc::new(HashMap::new()),
+            index: OfferIndex::default(),
             price_feed,
             price_data: Arc::new(HashMap::new()),
+            max_offers,
+            evictions,
+            events,
+            filters,
+            subscribers: Vec::new(),
+            depths: HashMap::new(),
+            journal: OfferJournal::default(),
+            prices_version: 0,
         }
         .start()
     }
+
+    fn version(&self) -> OfferBookVersion {
+        OfferBookVersion {
+            offers: self.journal.version(),
+            prices: self.prices_version,
+        }
+    }
+
+    fn notify(&mut self, change: OfferBookChange) {
+        let market = match &change {
+            OfferBookChange::Added { offer, .. } => {
+                metrics::offer_added(&offer.market.pair);
+                self.journal.record(JournalChange::Added(offer.clone()));
+                offer.market
+            }
+            OfferBookChange::Refreshed { offer, .. } => {
+                self.journal.record(JournalChange::Refreshed(offer.clone()));
+                offer.market
+            }
+            OfferBookChange::Removed(offer) => {
+                metrics::offer_removed(&offer.market.pair);
+                self.journal.record(JournalChange::Removed(offer.clone()));
+                offer.market
+            }
+        };
+        self.depths.remove(market.pair.as_str());
+        self.subscribers
+            .retain(|subscriber| subscriber.do_send(change.clone()).is_ok());
+    }
+
+    fn removed(&mut self, offer: OpenOffer, reason: &str) {
+        self.events.publish(Event::offer_removed(&offer, reason));
+        self.notify(OfferBookChange::Removed(offer));
+    }
+
+    /// The offers of the narrowest index the query allows, the whole book
+    /// otherwise.
+    fn candidates<'a>(
+        &'a self,
+        query: &QueryOffers,
+    ) -> Box<dyn Iterator<Item = &'a OpenOffer> + 'a> {
+        let open_offers = &self.open_offers;
+        let hashes: Box<dyn Iterator<Item = &'a SequencedMessageHash> + 'a> =
+            match (&query.maker, &query.payment_method, query.currency) {
+                (Some(maker), _, _) => Box::new(self.index.maker(maker)),
+                (None, Some(method), _) => Box::new(self.index.payment_method(method)),
+                (None, None, Some(currency)) => {
+                    let market: &Market = currency.into();
+                    Box::new(self.index.market(&market.pair))
+                }
+                (None, None, None) => return Box::new(open_offers.values()),
+            };
+        Box::new(hashes.filter_map(move |hash| open_offers.get(hash)))
+    }
+
+    fn make_room(&mut self) {
+        if let Some(max_offers) = self.max_offers {
+            let excess = (self.open_offers.len() + 1).saturating_sub(max_offers);
+            if excess == 0 {
+                return;
+            }
+            let mut by_expiry: Vec<(SequencedMessageHash, SystemTime)> = self
+                .open_offers
+                .iter()
+                .map(|(hash, offer)| (*hash, offer.expires_at()))
+                .collect();
+            by_expiry.sort_unstable_by_key(|(_, expires_at)| *expires_at);
+            for (hash, _) in by_expiry.into_iter().take(excess) {
+                if let Some(offer) = Arc::make_mut(&mut self.open_offers).remove(&hash) {
+                    self.index.remove(&offer);
+                    self.removed(offer, "evicted");
+                }
+            }
+            warn!("Evicted {} offers to stay within budget", excess);
+            self.evictions.offers_evicted(excess);
+        }
+    }
 }
 
 impl Handler<AddOffer> for OfferBook {
     type Result = MessageResult<AddOffer>;
     fn handle(&mut self, AddOffer(mut offer): AddOffer, _ctx: &mut Self::Context) -> Self::Result {
-        if !offer.is_expired() {
+        if !offer.is_expired() && self.filters.allows_offer(&offer) {
             offer.update_display_price(&self.price_data);
             match self.open_offers.get(&offer.bisq_hash) {
                 None => {
                     info!("Adding {:?}", offer.id);
+                    self.make_room();
+                    let premium = offer.premium(&self.price_data);
+                    self.events.publish(Event::offer_added(&offer, premium));
+                    self.notify(OfferBookChange::Added {
+                        offer: offer.clone(),
+                        premium,
+                    });
+                    self.index.insert(&offer);
                     let offers = Arc::make_mut(&mut self.open_offers);
                     offers.insert(offer.bisq_hash, offer);
                     return MessageResult(CommandResult::Accepted);
                 }
                 Some(existing) if existing.would_refresh(offer.latest_sequence) => {
+                    self.events.publish(Event::offer_refreshed(&offer));
+                    self.notify(OfferBookChange::Refreshed {
+                        offer: offer.clone(),
+                        premium: offer.premium(&self.price_data),
+                    });
                     let offers = Arc::make_mut(&mut self.open_offers);
                     offers.insert(offer.bisq_hash, offer);
                     return MessageResult(CommandResult::Accepted);
@@ -90,12 +233,33 @@ impl Handler<RemoveOffer> for OfferBook {
             Some(_) => {
                 info!("Removing {:?}", offer.id);
                 let offers = Arc::make_mut(&mut self.open_offers);
-                offers.remove(&offer.bisq_hash);
+                if let Some(removed) = offers.remove(&offer.bisq_hash) {
+                    self.index.remove(&removed);
+                }
+                self.removed(offer, "removed");
                 MessageResult(CommandResult::Accepted)
             }
         }
     }
 }
+impl Handler<ApplyFilter> for OfferBook {
+    type Result = ();
+    fn handle(&mut self, _: ApplyFilter, _ctx: &mut Self::Context) -> Self::Result {
+        let banned: Vec<SequencedMessageHash> = self
+            .open_offers
+            .iter()
+            .filter(|(_, offer)| !self.filters.allows_offer(offer))
+            .map(|(hash, _)| *hash)
+            .collect();
+        for hash in banned {
+            if let Some(offer) = Arc::make_mut(&mut self.open_offers).remove(&hash) {
+                info!("Removing banned {:?}", offer.id);
+                self.index.remove(&offer);
+                self.removed(offer, "banned");
+            }
+        }
+    }
+}
 impl Handler<RefreshOffer> for OfferBook {
     type Result = MessageResult<RefreshOffer>;
     fn handle(
@@ -111,6 +275,10 @@ impl Handler<RefreshOffer> for OfferBook {
                 let offers = Arc::make_mut(&mut self.open_offers);
                 let offer = offers.get_mut(&bisq_hash).unwrap();
                 if offer.refresh(sequence) {
+                    let offer = offer.clone();
+                    let premium = offer.premium(&self.price_data);
+                    self.events.publish(Event::offer_refreshed(&offer));
+                    self.notify(OfferBookChange::Refreshed { offer, premium });
                     return MessageResult(CommandResult::Accepted);
                 }
             }
@@ -125,3 +293,175 @@ impl Handler<GetOpenOffers> for OfferBook {
         MessageResult(Arc::clone(&self.open_offers))
     }
 }
+impl Handler<GetOfferChanges> for OfferBook {
+    type Result = MessageResult<GetOfferChanges>;
+    fn handle(
+        &mut self,
+        GetOfferChanges(since): GetOfferChanges,
+        _ctx: &mut Self::Context,
+    ) -> Self::Result {
+        MessageResult(
+            self.journal
+                .changes_since(since)
+                .map(|changes| OfferChanges {
+                    price_data: Arc::clone(&self.price_data),
+                    ..changes
+                }),
+        )
+    }
+}
+impl Handler<GetDepth> for OfferBook {
+    type Result = MessageResult<GetDepth>;
+    fn handle(&mut self, GetDepth(market): GetDepth, _ctx: &mut Self::Context) -> Self::Result {
+        let open_offers = &self.open_offers;
+        let index = &self.index;
+        let depth = self.depths.entry(market.pair.as_str()).or_insert_with(|| {
+            Arc::new(Depth::new(
+                market,
+                index
+                    .market(&market.pair)
+                    .filter_map(|hash| open_offers.get(hash))
+                    .filter(|offer| !offer.is_expired()),
+            ))
+        });
+        MessageResult(Arc::clone(depth))
+    }
+}
+impl Handler<SubscribeOfferBook> for OfferBook {
+    type Result = ();
+    fn handle(
+        &mut self,
+        SubscribeOfferBook(subscriber): SubscribeOfferBook,
+        _ctx: &mut Self::Context,
+    ) -> Self::Result {
+        self.subscribers.push(subscriber);
+    }
+}
+impl Handler<QueryOffers> for OfferBook {
+    type Result = MessageResult<QueryOffers>;
+    fn handle(&mut self, query: QueryOffers, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(query_offers(
+            self.candidates(&query),
+            &self.price_data,
+            self.version(),
+            &query,
+        ))
+    }
+}
+
+impl Handler<GetOfferBookVersion> for OfferBook {
+    type Result = MessageResult<GetOfferBookVersion>;
+    fn handle(&mut self, _: GetOfferBookVersion, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.version())
+    }
+}
+
+fn query_offers<'a>(
+    offers: impl Iterator<Item = &'a OpenOffer>,
+    price_data: &Arc<HashMap<&'static str, PriceData>>,
+    version: OfferBookVersion,
+    query: &QueryOffers,
+) -> OfferPage {
+    let mut offers: Vec<_> = offers.filter(|offer| query.matches(offer)).collect();
+    let order = |order: Ordering| {
+        if query.sort.descending {
+            order.reverse()
+        } else {
+            order
+        }
+    };
+    offers.sort_by(|a, b| match query.sort.key {
+        OfferSortKey::CreatedAt => order(a.created_at.cmp(&b.created_at)),
+        OfferSortKey::Price => order(a.display_price.cmp(&b.display_price)),
+        OfferSortKey::Amount => order(a.amount.total.cmp(&b.amount.total)),
+        // Offers without a premium come last either way
+        OfferSortKey::Premium => match (a.premium(price_data), b.premium(price_data)) {
+            (Some(a), Some(b)) => order(a.partial_cmp(&b).unwrap_or(Ordering::Equal)),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        },
+    });
+    let total = offers.len();
+    OfferPage {
+        total,
+        offers: offers
+            .into_iter()
+            .skip(query.offset)
+            .take(query.limit.unwrap_or(total))
+            .cloned()
+            .collect(),
+        price_data: Arc::clone(price_data),
+        version: version.offers,
+        prices_version: version.prices,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{amount::BtcAmount, currency::Currency, offer::TestOffer};
+
+    fn offer(
+        id: &str,
+        pair: &'static str,
+        direction: OfferDirection,
+        btc: u64,
+        secs: u64,
+    ) -> OpenOffer {
+        TestOffer::new(id)
+            .pair(pair)
+            .direction(direction)
+            .amount(btc * 10_000_000)
+            .created_at(secs)
+            .build()
+    }
+
+    #[test]
+    fn filters_sorts_and_pages() {
+        let offers = [
+            offer("a", "btc_eur", OfferDirection::Sell, 5, 3),
+            offer("b", "btc_eur", OfferDirection::Sell, 1, 1),
+            offer("c", "btc_eur", OfferDirection::Buy, 5, 2),
+            offer("d", "btc_usd", OfferDirection::Sell, 5, 4),
+            offer("e", "btc_eur", OfferDirection::Sell, 3, 5),
+        ];
+        let ids = |page: OfferPage| -> (usize, Vec<String>) {
+            (
+                page.total,
+                page.offers.into_iter().map(|o| o.id.into()).collect(),
+            )
+        };
+        let query = QueryOffers {
+            direction: Some(OfferDirection::Sell),
+            currency: Currency::from_code("EUR"),
+            min_amount: Some(BtcAmount::from_sats(20_000_000)),
+            ..QueryOffers::default()
+        };
+        assert_eq!(
+            ids(query_offers(
+                offers.iter(),
+                &Arc::new(HashMap::new()),
+                OfferBookVersion::default(),
+                &query
+            )),
+            (2, vec!["a".to_string(), "e".to_string()])
+        );
+        let query = QueryOffers {
+            sort: "-created_at".parse().unwrap(),
+            offset: 1,
+            limit: Some(2),
+            ..QueryOffers::default()
+        };
+        assert_eq!(
+            ids(query_offers(
+                offers.iter(),
+                &Arc::new(HashMap::new()),
+                OfferBookVersion::default(),
+                &query
+            )),
+            (5, vec!["d".to_string(), "a".to_string()])
+        );
+        assert!("volume".parse::<OfferSort>().is_err());
+    }
+}
@@ -1,9 +1,16 @@
 use crate::{
-    bisq::SequencedMessageHash,
-    domain::{amount::NumberWithPrecision, currency::*, market::Market, price_feed::PriceData},
+    bisq::{payload::OfferPayload, SequencedMessageHash},
+    domain::{
+        amount::{BtcAmount, NumberWithPrecision},
+        currency::*,
+        market::Market,
+        payment_method::{PaymentMethod, PaymentMethodInfo},
+        price_feed::PriceData,
+    },
 };
 use std::{
     collections::HashMap,
+    fmt,
     sync::Arc,
     time::{Duration, SystemTime},
 };
@@ -54,8 +61,51 @@ pub enum OfferPrice {
 
 #[derive(Clone, Copy, PartialEq)]
 pub struct OfferAmount {
-    pub total: NumberWithPrecision,
-    pub min: NumberWithPrecision,
+    pub total: BtcAmount,
+    pub min: BtcAmount,
+}
+
+/// The payment method of an offer, by its bisq id when risq doesn't support
+/// it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OfferPaymentMethod {
+    Supported(PaymentMethod),
+    Other(String),
+}
+impl OfferPaymentMethod {
+    pub fn id(&self) -> &str {
+        match self {
+            OfferPaymentMethod::Supported(method) => method.id(),
+            OfferPaymentMethod::Other(id) => id,
+        }
+    }
+
+    pub fn supported(&self) -> Option<PaymentMethod> {
+        match self {
+            OfferPaymentMethod::Supported(method) => Some(*method),
+            OfferPaymentMethod::Other(_) => None,
+        }
+    }
+
+    /// Limits and currencies of the method, `None` when bisq doesn't know it.
+    pub fn info(&self) -> Option<&'static PaymentMethodInfo> {
+        match self {
+            OfferPaymentMethod::Supported(method) => Some(method.info()),
+            OfferPaymentMethod::Other(id) => PaymentMethodInfo::from_id(id),
+        }
+    }
+}
+impl From<&str> for OfferPaymentMethod {
+    fn from(id: &str) -> Self {
+        id.parse()
+            .map(OfferPaymentMethod::Supported)
+            .unwrap_or_else(|_| OfferPaymentMethod::Other(id.into()))
+    }
+}
+impl fmt::Display for OfferPaymentMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.id())
+    }
 }
 
 #[derive(Clone, PartialEq)]
@@ -65,10 +115,12 @@ pub struct OpenOffer {
     pub id: OfferId,
     pub direction: OfferDirection,
     pub amount: OfferAmount,
-    pub payment_method_id: String,
+    pub payment_method: OfferPaymentMethod,
     pub offer_fee_tx_id: String,
     pub created_at: SystemTime,
     pub display_price: NumberWithPrecision,
+    /// The original payload, needed to take the offer and to build the contract
+    pub payload: Arc<OfferPayload>,
 
     pub(super) latest_sequence: OfferSequence,
 
@@ -84,10 +136,11 @@ impl OpenOffer {
         direction: OfferDirection,
         price: OfferPrice,
         amount: OfferAmount,
-        payment_method_id: String,
+        payment_method: OfferPaymentMethod,
         offer_fee_tx_id: String,
         created_at: SystemTime,
         sequence: OfferSequence,
+        payload: Arc<OfferPayload>,
     ) -> OpenOffer {
         let display_price = if let OfferPrice::Fixed(price) = price {
             price
@@ -101,18 +154,39 @@ impl OpenOffer {
             direction,
             price,
             amount,
-            payment_method_id,
+            payment_method,
             display_price,
             created_at,
             expires_at: created_at + INITIAL_TTL,
             latest_sequence: sequence,
             offer_fee_tx_id,
+            payload,
+        }
+    }
+
+    pub fn market_price_margin(&self) -> Option<f64> {
+        match self.price {
+            OfferPrice::MarketWithMargin(margin) => Some(margin),
+            OfferPrice::Fixed(_) => None,
+        }
+    }
+
+    /// The fixed price or the one following the market price, `None` until a
+    /// market price is known for the latter.
+    pub fn price(&self) -> Option<NumberWithPrecision> {
+        if self.display_price.base_amount() > 0 {
+            Some(self.display_price)
+        } else {
+            None
         }
     }
 
     pub fn is_expired(&self) -> bool {
         self.expires_at.elapsed().is_ok()
     }
+    pub(super) fn expires_at(&self) -> SystemTime {
+        self.expires_at
+    }
 
     pub(super) fn update_display_price(
         &mut self,
@@ -138,6 +212,20 @@ impl OpenOffer {
         }
     }
 
+    /// How far the price is above the market price as a ratio, negative
+    /// below it. `None` without a market price for the currency.
+    pub fn premium(&self, price_data: &HashMap<&'static str, PriceData>) -> Option<f64> {
+        let data = price_data.get(self.market.non_btc_side().code.as_str())?;
+        let precision = data.currency.bisq_internal_precision();
+        let price = self.display_price.with_precision(precision).base_amount() as f64
+            / 10_f64.powi(precision as i32);
+        if price > 0.0 && data.price > 0.0 {
+            Some(price / data.price - 1.0)
+        } else {
+            None
+        }
+    }
+
     pub(super) fn would_refresh(&self, sequence: OfferSequence) -> bool {
         sequence > self.latest_sequence
     }
@@ -150,3 +238,36 @@ impl OpenOffer {
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::offer::TestOffer;
+
+    #[test]
+    fn follows_the_market_price() {
+        let mut offer = TestOffer::new("a")
+            .price(OfferPrice::MarketWithMargin(0.02))
+            .build();
+        assert_eq!(offer.price(), None);
+
+        let price_data: Arc<HashMap<&'static str, PriceData>> = Arc::new(
+            vec![(
+                "EUR",
+                serde_json::from_str(
+                    r#"{"currencyCode":"EUR","price":10000.0,"timestampSec":0,"provider":"BTCA"}"#,
+                )
+                .unwrap(),
+            )]
+            .into_iter()
+            .collect(),
+        );
+        offer.update_display_price(&price_data);
+        assert_eq!(offer.market_price_margin(), Some(0.02));
+        assert_eq!(
+            offer.price(),
+            Some(NumberWithPrecision::new(102_000_000, 4))
+        );
+        assert!((offer.premium(&price_data).unwrap() - 0.02).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,115 @@
+use super::{OfferAmount, OfferDirection, OfferPrice, OpenOffer};
+use crate::{
+    bisq::{payload::OfferPayload, SequencedMessageHash},
+    domain::{
+        amount::{BtcAmount, NumberWithPrecision},
+        market::Market,
+    },
+    prelude::{sha256, Hash},
+};
+use std::{
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+
+/// Builds offers for tests: unless told otherwise, selling 0.1 BTC for EUR
+/// at a fixed price of 9000 via SEPA.
+pub struct TestOffer {
+    id: String,
+    pair: &'static str,
+    direction: OfferDirection,
+    price: OfferPrice,
+    amount: OfferAmount,
+    payment_method: String,
+    offer_fee_tx_id: String,
+    created_secs: u64,
+    payload: OfferPayload,
+}
+impl TestOffer {
+    pub fn new(id: &str) -> Self {
+        Self {
+            id: id.into(),
+            pair: "btc_eur",
+            direction: OfferDirection::Sell,
+            price: OfferPrice::Fixed(NumberWithPrecision::new(9000, 0)),
+            amount: OfferAmount {
+                total: BtcAmount::from_sats(10_000_000),
+                min: BtcAmount::from_sats(10_000_000),
+            },
+            payment_method: "SEPA".into(),
+            offer_fee_tx_id: String::new(),
+            created_secs: 0,
+            payload: OfferPayload::default(),
+        }
+    }
+
+    /// Takes the id, amounts, payment method and fee tx from `payload`.
+    pub fn from_payload(payload: OfferPayload) -> Self {
+        let mut offer =
+            Self::new(&payload.id).amounts(payload.amount as u64, payload.min_amount as u64);
+        offer.payment_method = payload.payment_method_id.clone();
+        offer.offer_fee_tx_id = payload.offer_fee_payment_tx_id.clone();
+        offer.payload = payload;
+        offer
+    }
+
+    pub fn pair(mut self, pair: &'static str) -> Self {
+        self.pair = pair;
+        self
+    }
+
+    pub fn direction(mut self, direction: OfferDirection) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    pub fn price(mut self, price: OfferPrice) -> Self {
+        self.price = price;
+        self
+    }
+
+    /// In satoshis, for both the total and the minimum amount
+    pub fn amount(self, sats: u64) -> Self {
+        self.amounts(sats, sats)
+    }
+
+    pub fn amounts(mut self, total: u64, min: u64) -> Self {
+        self.amount = OfferAmount {
+            total: BtcAmount::from_sats(total),
+            min: BtcAmount::from_sats(min),
+        };
+        self
+    }
+
+    pub fn payment_method(mut self, payment_method: &str) -> Self {
+        self.payment_method = payment_method.into();
+        self
+    }
+
+    /// Seconds after the unix epoch
+    pub fn created_at(mut self, secs: u64) -> Self {
+        self.created_secs = secs;
+        self
+    }
+
+    pub fn maker(mut self, address: &str) -> Self {
+        self.payload.owner_node_address = Some(address.parse().expect("Invalid maker address"));
+        self
+    }
+
+    pub fn build(self) -> OpenOffer {
+        OpenOffer::new(
+            SequencedMessageHash::new(sha256::Hash::hash(self.id.as_bytes())),
+            Market::from_pair(self.pair).expect("Unknown market"),
+            self.id.into(),
+            self.direction,
+            self.price,
+            self.amount,
+            self.payment_method.as_str().into(),
+            self.offer_fee_tx_id,
+            UNIX_EPOCH + Duration::from_secs(self.created_secs),
+            0.into(),
+            Arc::new(self.payload),
+        )
+    }
+}
@@ -0,0 +1,289 @@
+use super::OpenOffer;
+use crate::domain::{
+    amount::BtcAmount,
+    currency::Currency,
+    payment_method::{PaymentMethodInfo, MAX_TRADE_LIMIT},
+    trade::protocol::TRADE_PROTOCOL_VERSION,
+};
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Bisq went live in April 2016, there are no older offers.
+const BISQ_LAUNCH: Duration = Duration::from_secs(1_460_000_000);
+/// How far the maker's clock may be ahead of ours.
+const MAX_FUTURE_DATE: Duration = Duration::from_secs(60 * 60);
+
+/// A rule an offer has to follow to be added to the offer book.
+pub trait OfferValidator: Send + Sync {
+    /// Identifies the rule in logs and metrics
+    fn name(&self) -> &'static str;
+    /// Fails with the reason the offer is refused.
+    fn validate(&self, offer: &OpenOffer) -> Result<(), String>;
+}
+
+/// The rule an offer violated, and how.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rejection {
+    pub rule: &'static str,
+    pub reason: String,
+}
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.rule, self.reason)
+    }
+}
+
+/// The rules offers from the network are checked against in order. The
+/// built-in ones can be dropped by name and custom ones added.
+#[derive(Clone)]
+pub struct OfferValidators {
+    validators: Vec<Arc<dyn OfferValidator>>,
+}
+impl Default for OfferValidators {
+    fn default() -> Self {
+        Self {
+            validators: vec![
+                Arc::new(OfferDate),
+                Arc::new(AmountBounds),
+                Arc::new(CurrencyCodes),
+                Arc::new(PaymentMethodCurrency),
+                Arc::new(MakerFeeTx),
+                Arc::new(ProtocolVersion),
+            ],
+        }
+    }
+}
+impl OfferValidators {
+    #[allow(clippy::should_implement_trait)]
+    pub fn add(mut self, validator: impl OfferValidator + 'static) -> Self {
+        self.validators.push(Arc::new(validator));
+        self
+    }
+
+    pub fn without(mut self, name: &str) -> Self {
+        self.validators.retain(|validator| validator.name() != name);
+        self
+    }
+
+    /// The first rule the offer violates.
+    pub fn validate(&self, offer: &OpenOffer) -> Result<(), Rejection> {
+        self.validators.iter().try_for_each(|validator| {
+            validator.validate(offer).map_err(|reason| Rejection {
+                rule: validator.name(),
+                reason,
+            })
+        })
+    }
+}
+
+/// Neither from before bisq existed nor from the future.
+pub struct OfferDate;
+impl OfferValidator for OfferDate {
+    fn name(&self) -> &'static str {
+        "offer_date"
+    }
+    fn validate(&self, offer: &OpenOffer) -> Result<(), String> {
+        let date = UNIX_EPOCH + Duration::from_millis(offer.payload.date.max(0) as u64);
+        if date < UNIX_EPOCH + BISQ_LAUNCH || date > SystemTime::now() + MAX_FUTURE_DATE {
+            return Err(format!("date {} ms", offer.payload.date));
+        }
+        Ok(())
+    }
+}
+
+/// Amounts within the trade limit of the payment method.
+pub struct AmountBounds;
+impl OfferValidator for AmountBounds {
+    fn name(&self) -> &'static str {
+        "amount_bounds"
+    }
+    fn validate(&self, offer: &OpenOffer) -> Result<(), String> {
+        let amount = offer.amount;
+        if amount.min.sats() == 0 || amount.min > amount.total {
+            return Err(format!(
+                "amount {} with min amount {}",
+                amount.total.format(),
+                amount.min.format()
+            ));
+        }
+        let limit = offer
+            .payment_method
+            .info()
+            .map(PaymentMethodInfo::max_trade_limit)
+            .unwrap_or(MAX_TRADE_LIMIT);
+        if amount.total.sats() > limit {
+            return Err(format!(
+                "amount {} above the limit of {} for {}",
+                amount.total.format(),
+                BtcAmount::from_sats(limit).format(),
+                offer.payment_method
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Known currencies, traded against BTC.
+pub struct CurrencyCodes;
+impl OfferValidator for CurrencyCodes {
+    fn name(&self) -> &'static str {
+        "currency_codes"
+    }
+    fn validate(&self, offer: &OpenOffer) -> Result<(), String> {
+        let base = &offer.payload.base_currency_code;
+        let counter = &offer.payload.counter_currency_code;
+        if Currency::from_code(base).is_none()
+            || Currency::from_code(counter).is_none()
+            || (base != "BTC") == (counter != "BTC")
+        {
+            return Err(format!("currency pair {}/{}", base, counter));
+        }
+        Ok(())
+    }
+}
+
+/// A currency the payment method can pay in, methods bisq doesn't know pass.
+pub struct PaymentMethodCurrency;
+impl OfferValidator for PaymentMethodCurrency {
+    fn name(&self) -> &'static str {
+        "payment_method_currency"
+    }
+    fn validate(&self, offer: &OpenOffer) -> Result<(), String> {
+        let currency = offer.market.non_btc_side();
+        match offer.payment_method.info() {
+            Some(method) if !method.currencies.supports(currency) => Err(format!(
+                "{} not available with {}",
+                currency.code, method.id
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Without the tx that paid the maker fee, the offer can't be taken.
+pub struct MakerFeeTx;
+impl OfferValidator for MakerFeeTx {
+    fn name(&self) -> &'static str {
+        "maker_fee_tx"
+    }
+    fn validate(&self, offer: &OpenOffer) -> Result<(), String> {
+        if offer.payload.offer_fee_payment_tx_id.is_empty() {
+            return Err("missing maker fee tx id".into());
+        }
+        Ok(())
+    }
+}
+
+/// Older protocols are still listed, as bisq does, newer ones we can't take.
+pub struct ProtocolVersion;
+impl OfferValidator for ProtocolVersion {
+    fn name(&self) -> &'static str {
+        "protocol_version"
+    }
+    fn validate(&self, offer: &OpenOffer) -> Result<(), String> {
+        let version = offer.payload.protocol_version;
+        if !(1..=TRADE_PROTOCOL_VERSION).contains(&version) {
+            return Err(format!("protocol version {}", version));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bisq::payload::OfferPayload,
+        domain::{
+            offer::{OfferDirection, OfferPrice, TestOffer},
+            payment_method::PaymentMethod,
+        },
+    };
+
+    fn offer(payload: OfferPayload) -> OpenOffer {
+        TestOffer::from_payload(payload)
+            .direction(OfferDirection::Buy)
+            .price(OfferPrice::MarketWithMargin(0.0))
+            .build()
+    }
+
+    struct NoSepa;
+    impl OfferValidator for NoSepa {
+        fn name(&self) -> &'static str {
+            "no_sepa"
+        }
+        fn validate(&self, offer: &OpenOffer) -> Result<(), String> {
+            if offer.payment_method.id() == "SEPA" {
+                return Err("SEPA".into());
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn rejects_with_the_first_violated_rule() {
+        let mut payload = OfferPayload {
+            id: "offer".into(),
+            date: 1_600_000_000_000,
+            amount: 10_000_000,
+            min_amount: 5_000_000,
+            base_currency_code: "BTC".into(),
+            counter_currency_code: "EUR".into(),
+            payment_method_id: "SEPA".into(),
+            offer_fee_payment_tx_id: "ab".repeat(32),
+            protocol_version: TRADE_PROTOCOL_VERSION,
+            ..Default::default()
+        };
+        let validators = OfferValidators::default();
+        assert_eq!(validators.validate(&offer(payload.clone())), Ok(()));
+        let with_custom = validators.clone().add(NoSepa);
+        assert_eq!(
+            with_custom
+                .validate(&offer(payload.clone()))
+                .map_err(|rejection| rejection.rule),
+            Err("no_sepa")
+        );
+        let mut swish = payload.clone();
+        swish.payment_method_id = "SWISH".into();
+        assert_eq!(
+            validators
+                .validate(&offer(swish))
+                .map_err(|rejection| rejection.rule),
+            Err("payment_method_currency")
+        );
+
+        payload.amount = PaymentMethod::Sepa.max_trade_limit() as i64 + 1;
+        payload.counter_currency_code = "XYZ".into();
+        let rejection = validators.validate(&offer(payload.clone())).unwrap_err();
+        assert_eq!(rejection.rule, "amount_bounds");
+        assert_eq!(
+            validators
+                .clone()
+                .without("amount_bounds")
+                .validate(&offer(payload.clone()))
+                .map_err(|rejection| rejection.rule),
+            Err("currency_codes")
+        );
+
+        payload.amount = 10_000_000;
+        payload.counter_currency_code = "EUR".into();
+        payload.date = 0;
+        payload.protocol_version = TRADE_PROTOCOL_VERSION + 1;
+        assert_eq!(
+            validators
+                .validate(&offer(payload.clone()))
+                .map_err(|rejection| rejection.rule),
+            Err("offer_date")
+        );
+        payload.date = 1_600_000_000_000;
+        assert_eq!(
+            validators
+                .validate(&offer(payload))
+                .map_err(|rejection| rejection.rule),
+            Err("protocol_version")
+        );
+    }
+}
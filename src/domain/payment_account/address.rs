@@ -0,0 +1,171 @@
+use bitcoin::{bech32, util::base58, Address};
+use lazy_static::lazy_static;
+use std::{collections::HashMap, str::FromStr};
+
+/// Checks the receiving address of an altcoin account, like the
+/// `AddressValidator` of the asset in bisq.
+type Validator = fn(&str) -> Result<(), String>;
+
+lazy_static! {
+    static ref VALIDATORS: HashMap<&'static str, Validator> = {
+        let mut validators: HashMap<&'static str, Validator> = HashMap::new();
+        validators.insert("BSQ", bsq);
+        validators.insert("DASH", dash);
+        validators.insert("DOGE", dogecoin);
+        validators.insert("ETH", ether);
+        validators.insert("L-BTC", liquid_bitcoin);
+        validators.insert("LTC", litecoin);
+        validators.insert("XMR", monero);
+        validators.insert("ZEC", zcash);
+        validators
+    };
+}
+
+/// Assets without a validator accept any address, the generic checks of the
+/// account still apply.
+pub fn validate(currency_code: &str, address: &str) -> Result<(), String> {
+    match VALIDATORS.get(currency_code) {
+        Some(validator) => validator(address)
+            .map_err(|e| format!("Invalid {} address {}: {}", currency_code, address, e)),
+        None => Ok(()),
+    }
+}
+
+const BASE58_CHARS: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+const BECH32_CHARS: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+fn is_base58(address: &str) -> bool {
+    address.chars().all(|c| BASE58_CHARS.contains(c))
+}
+
+// Base58check with a one byte version, like bitcoin's legacy addresses
+fn base58_version(address: &str, versions: &[u8]) -> Result<(), String> {
+    let data = base58::from_check(address).map_err(|e| e.to_string())?;
+    match data.split_first() {
+        Some((version, hash)) if hash.len() == 20 && versions.contains(version) => Ok(()),
+        Some((_, hash)) if hash.len() == 20 => Err("wrong version byte".into()),
+        _ => Err("wrong length".into()),
+    }
+}
+
+fn segwit(address: &str, hrp: &str) -> Result<(), String> {
+    let (decoded_hrp, data) = bech32::decode(address).map_err(|e| e.to_string())?;
+    if decoded_hrp != hrp {
+        return Err(format!("expected prefix {}1", hrp));
+    }
+    if data.is_empty() {
+        return Err("missing witness program".into());
+    }
+    Ok(())
+}
+
+fn bsq(address: &str) -> Result<(), String> {
+    if !address.starts_with('B') {
+        return Err("BSQ addresses start with B".into());
+    }
+    Address::from_str(&address[1..])
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+fn dash(address: &str) -> Result<(), String> {
+    base58_version(address, &[0x4c, 0x10])
+}
+
+fn dogecoin(address: &str) -> Result<(), String> {
+    base58_version(address, &[0x1e, 0x16])
+}
+
+fn ether(address: &str) -> Result<(), String> {
+    let hex = address.trim_start_matches("0x");
+    if hex.len() == 40 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(())
+    } else {
+        Err("expected 40 hex digits".into())
+    }
+}
+
+// Confidential addresses use blech32, which the bech32 crate can't check,
+// so like bisq only the format is validated.
+fn liquid_bitcoin(address: &str) -> Result<(), String> {
+    if is_base58(address) && ((26..=35).contains(&address.len()) || address.len() == 80) {
+        return Ok(());
+    }
+    let lower = address.to_lowercase();
+    if lower != address && address.to_uppercase() != address {
+        return Err("mixed case".into());
+    }
+    let (hrp, data) = match lower.rfind('1') {
+        Some(separator) => (&lower[..separator], &lower[separator + 1..]),
+        None => return Err("neither a base58 nor a bech32 address".into()),
+    };
+    if (2..=5).contains(&hrp.len())
+        && hrp.chars().all(|c| c.is_ascii_lowercase())
+        && (8..=87).contains(&data.len())
+        && data.chars().all(|c| BECH32_CHARS.contains(c))
+    {
+        Ok(())
+    } else {
+        Err("neither a base58 nor a bech32 address".into())
+    }
+}
+
+fn litecoin(address: &str) -> Result<(), String> {
+    if address.to_lowercase().starts_with("ltc1") {
+        segwit(address, "ltc")
+    } else {
+        base58_version(address, &[0x30, 0x32, 0x05])
+    }
+}
+
+// Without the keccak checksum of the CryptoNote address
+fn monero(address: &str) -> Result<(), String> {
+    if !is_base58(address) {
+        return Err("not base58".into());
+    }
+    match (address.chars().next(), address.len()) {
+        (Some('4'), 95) | (Some('8'), 95) | (Some('4'), 106) => Ok(()),
+        _ => Err("expected a standard, integrated or subaddress".into()),
+    }
+}
+
+// Only transparent addresses, like in bisq
+fn zcash(address: &str) -> Result<(), String> {
+    let data = base58::from_check(address).map_err(|e| e.to_string())?;
+    if data.len() == 22 && (data.starts_with(&[0x1c, 0xb8]) || data.starts_with(&[0x1c, 0xbd])) {
+        Ok(())
+    } else {
+        Err("only transparent addresses are supported".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bech32::ToBase32;
+
+    fn legacy(prefix: &[u8]) -> String {
+        let mut data = prefix.to_vec();
+        data.extend_from_slice(&[7; 20]);
+        base58::check_encode_slice(&data)
+    }
+
+    #[test]
+    fn altcoin_addresses() {
+        assert!(validate("LTC", &legacy(&[0x30])).is_ok());
+        assert!(validate("LTC", &legacy(&[0x00])).is_err());
+        let segwit = bech32::encode("ltc", [7; 21].to_base32()).unwrap();
+        assert!(validate("LTC", &segwit).is_ok());
+        assert!(validate("LTC", &segwit.replace("ltc1", "ltc2")).is_err());
+        assert!(validate("ZEC", &legacy(&[0x1c, 0xb8])).is_ok());
+        assert!(validate("ZEC", &legacy(&[0x16, 0x9a])).is_err());
+        assert!(validate("ETH", "0x2a65Aca4D5fC5B5C859090a6c34d164135398226").is_ok());
+        assert!(validate("ETH", "0x2a65Aca4D5fC5B5C859090a6c34d16413539822").is_err());
+        assert!(validate("XMR", &format!("4{}", "A".repeat(94))).is_ok());
+        assert!(validate("XMR", &format!("4{}", "0".repeat(94))).is_err());
+        assert!(validate("L-BTC", "H4Nk8x6Ym1UWMbu6dTkTMXwUWtc3KbThJv").is_ok());
+        assert!(validate("L-BTC", "ex1qx9lvcrwz2hcfqtsvfqsrplr2hzx39rqu2dlfks").is_ok());
+        assert!(validate("L-BTC", "invalid address").is_err());
+        assert!(validate("XYZ", "anything").is_ok());
+    }
+}
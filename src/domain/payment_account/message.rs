@@ -0,0 +1,24 @@
+use super::{PaymentAccount, PaymentAccountDetails};
+use crate::{domain::currency::Currency, prelude::Message};
+use std::time::SystemTime;
+
+pub struct PaymentAccountInfo {
+    pub account: PaymentAccount,
+    pub age_witness_hash: Vec<u8>,
+    pub age_witness_date: SystemTime,
+}
+
+/// Validates and persists a new payment account.
+pub struct CreatePaymentAccount {
+    pub name: String,
+    pub currency: &'static Currency,
+    pub details: PaymentAccountDetails,
+}
+impl Message for CreatePaymentAccount {
+    type Result = Result<PaymentAccountInfo, String>;
+}
+
+pub struct GetPaymentAccounts;
+impl Message for GetPaymentAccounts {
+    type Result = Vec<PaymentAccountInfo>;
+}
@@ -0,0 +1,906 @@
+mod address;
+pub mod message;
+
+use crate::{
+    bisq::{
+        constants::{self, BaseCurrencyNetwork},
+        crypto,
+        payload::*,
+    },
+    domain::{currency::Currency, offer::OpenOffer, payment_method::PaymentMethod},
+    prelude::{ripemd160, sha256, FromHex, Hash, ToHex},
+};
+use bitcoin::secp256k1::PublicKey;
+use rand::{thread_rng, Rng};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use uuid::Uuid;
+
+const SALT_LENGTH: usize = 32;
+const SALT_KEY: &str = "salt";
+/// How long a signed account has to wait before it can sign others.
+const SIGNER_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Countries that pay EUR via SEPA, the default accepted countries of
+/// SEPA accounts in bisq.
+const SEPA_EURO_COUNTRIES: [&str; 23] = [
+    "AT", "BE", "CY", "DE", "EE", "FI", "FR", "GR", "IE", "IT", "LV", "LT", "LU", "MC", "MT", "NL",
+    "PT", "SK", "SI", "ES", "AD", "SM", "VA",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PaymentAccountDetails {
+    Sepa {
+        holder_name: String,
+        iban: String,
+        bic: String,
+    },
+    Revolut {
+        account_id: String,
+    },
+    F2f {
+        country_code: String,
+        contact: String,
+        city: String,
+        extra_info: String,
+    },
+    Crypto {
+        address: String,
+    },
+}
+impl PaymentAccountDetails {
+    pub fn payment_method(&self) -> PaymentMethod {
+        match self {
+            PaymentAccountDetails::Sepa { .. } => PaymentMethod::Sepa,
+            PaymentAccountDetails::Revolut { .. } => PaymentMethod::Revolut,
+            PaymentAccountDetails::F2f { .. } => PaymentMethod::F2f,
+            PaymentAccountDetails::Crypto { .. } => PaymentMethod::Blockchains,
+        }
+    }
+
+    /// Removes the whitespace users like to copy along with account numbers.
+    fn normalized(self) -> Self {
+        match self {
+            PaymentAccountDetails::Sepa {
+                holder_name,
+                iban,
+                bic,
+            } => PaymentAccountDetails::Sepa {
+                holder_name: holder_name.trim().into(),
+                iban: iban.split_whitespace().collect::<String>().to_uppercase(),
+                bic: bic.split_whitespace().collect::<String>().to_uppercase(),
+            },
+            PaymentAccountDetails::Revolut { account_id } => PaymentAccountDetails::Revolut {
+                account_id: account_id.trim().into(),
+            },
+            PaymentAccountDetails::F2f {
+                country_code,
+                contact,
+                city,
+                extra_info,
+            } => PaymentAccountDetails::F2f {
+                country_code: country_code.trim().to_uppercase(),
+                contact: contact.trim().into(),
+                city: city.trim().into(),
+                extra_info: extra_info.trim().into(),
+            },
+            PaymentAccountDetails::Crypto { address } => PaymentAccountDetails::Crypto {
+                address: address.trim().into(),
+            },
+        }
+    }
+
+    fn validate(&self, currency: &Currency) -> Result<(), String> {
+        match self {
+            PaymentAccountDetails::Sepa {
+                holder_name,
+                iban,
+                bic,
+            } => {
+                if currency.code != "EUR" {
+                    return Err("SEPA accounts only support EUR".into());
+                }
+                not_empty("holder name", holder_name)?;
+                validate_iban(iban)?;
+                if !SEPA_EURO_COUNTRIES.contains(&&iban[..2]) {
+                    return Err(format!("{} is not a SEPA country", &iban[..2]));
+                }
+                validate_bic(bic)
+            }
+            PaymentAccountDetails::Revolut { account_id } => {
+                fiat(currency)?;
+                not_empty("account id", account_id)
+            }
+            PaymentAccountDetails::F2f {
+                country_code,
+                contact,
+                city,
+                ..
+            } => {
+                fiat(currency)?;
+                if country_code.len() != 2 || !country_code.chars().all(|c| c.is_ascii_uppercase())
+                {
+                    return Err(format!("Invalid country code {}", country_code));
+                }
+                not_empty("contact", contact)?;
+                not_empty("city", city)
+            }
+            PaymentAccountDetails::Crypto { address } => {
+                if !currency.is_crypto() || currency.code == "BTC" {
+                    return Err(format!("{} is not an altcoin", currency.code));
+                }
+                not_empty("address", address)?;
+                if address.contains(char::is_whitespace) {
+                    return Err("The address must not contain whitespace".into());
+                }
+                address::validate(&currency.code, address)
+            }
+        }
+    }
+
+    // The data that identifies the account in bisq's `getAgeWitnessInputData`
+    fn age_witness_input(&self) -> Vec<u8> {
+        let mut input = self.payment_method().id().as_bytes().to_vec();
+        match self {
+            PaymentAccountDetails::Sepa { iban, bic, .. } => {
+                input.extend_from_slice(iban.as_bytes());
+                input.extend_from_slice(bic.as_bytes());
+            }
+            PaymentAccountDetails::Revolut { account_id } => {
+                input.extend_from_slice(account_id.as_bytes())
+            }
+            PaymentAccountDetails::F2f {
+                contact,
+                city,
+                extra_info,
+                ..
+            } => {
+                input.extend_from_slice(contact.as_bytes());
+                input.extend_from_slice(city.as_bytes());
+                input.extend_from_slice(extra_info.as_bytes());
+            }
+            PaymentAccountDetails::Crypto { address } => {
+                input.extend_from_slice(address.as_bytes())
+            }
+        }
+        input
+    }
+
+    // The details a peer sent us, the names and salt aren't needed
+    fn from_payload(payload: &payment_account_payload::Message) -> Option<Self> {
+        use country_based_payment_account_payload::Message as CountryBased;
+        use payment_account_payload::Message;
+        match payload {
+            Message::CountryBasedPaymentAccountPayload(country_based) => {
+                match country_based.message.as_ref()? {
+                    CountryBased::SepaAccountPayload(sepa) => Some(PaymentAccountDetails::Sepa {
+                        holder_name: sepa.holder_name.clone(),
+                        iban: sepa.iban.clone(),
+                        bic: sepa.bic.clone(),
+                    }),
+                    CountryBased::F2fAccountPayload(f2f) => Some(PaymentAccountDetails::F2f {
+                        country_code: country_based.country_code.clone(),
+                        contact: f2f.contact.clone(),
+                        city: f2f.city.clone(),
+                        extra_info: f2f.extra_info.clone(),
+                    }),
+                    _ => None,
+                }
+            }
+            Message::RevolutAccountPayload(revolut) => Some(PaymentAccountDetails::Revolut {
+                account_id: revolut.account_id.clone(),
+            }),
+            Message::CryptoCurrencyAccountPayload(crypto) => Some(PaymentAccountDetails::Crypto {
+                address: crypto.address.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    fn payload_message(&self) -> payment_account_payload::Message {
+        match self.clone() {
+            PaymentAccountDetails::Sepa {
+                holder_name,
+                iban,
+                bic,
+            } => country_based(
+                iban[..2].to_string(),
+                country_based_payment_account_payload::Message::SepaAccountPayload(
+                    SepaAccountPayload {
+                        holder_name,
+                        iban,
+                        bic,
+                        accepted_country_codes: SEPA_EURO_COUNTRIES
+                            .iter()
+                            .map(|code| code.to_string())
+                            .collect(),
+                        ..Default::default()
+                    },
+                ),
+            ),
+            PaymentAccountDetails::Revolut { account_id } => {
+                payment_account_payload::Message::RevolutAccountPayload(RevolutAccountPayload {
+                    account_id,
+                })
+            }
+            PaymentAccountDetails::F2f {
+                country_code,
+                contact,
+                city,
+                extra_info,
+            } => country_based(
+                country_code,
+                country_based_payment_account_payload::Message::F2fAccountPayload(
+                    F2fAccountPayload {
+                        contact,
+                        city,
+                        extra_info,
+                    },
+                ),
+            ),
+            PaymentAccountDetails::Crypto { address } => {
+                payment_account_payload::Message::CryptoCurrencyAccountPayload(
+                    CryptoCurrencyAccountPayload { address },
+                )
+            }
+        }
+    }
+}
+
+fn country_based(
+    country_code: String,
+    message: country_based_payment_account_payload::Message,
+) -> payment_account_payload::Message {
+    payment_account_payload::Message::CountryBasedPaymentAccountPayload(
+        CountryBasedPaymentAccountPayload {
+            country_code,
+            message: Some(message),
+        },
+    )
+}
+
+fn not_empty(field: &str, value: &str) -> Result<(), String> {
+    if value.is_empty() {
+        Err(format!("The {} must not be empty", field))
+    } else {
+        Ok(())
+    }
+}
+
+fn fiat(currency: &Currency) -> Result<(), String> {
+    if currency.is_crypto() {
+        Err(format!("{} is not a national currency", currency.code))
+    } else {
+        Ok(())
+    }
+}
+
+fn validate_iban(iban: &str) -> Result<(), String> {
+    let well_formed = iban.len() >= 15
+        && iban.len() <= 34
+        && iban
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+        && iban[..2].chars().all(|c| c.is_ascii_uppercase())
+        && iban[2..4].chars().all(|c| c.is_ascii_digit());
+    if !well_formed {
+        return Err(format!("Invalid IBAN {}", iban));
+    }
+    // ISO 13616: the country code and check digits are moved to the end and
+    // letters count as 10 to 35, the resulting number mod 97 must be 1
+    let remainder = iban[4..]
+        .chars()
+        .chain(iban[..4].chars())
+        .filter_map(|c| c.to_digit(36))
+        .fold(0, |remainder, digit| {
+            if digit < 10 {
+                (remainder * 10 + digit) % 97
+            } else {
+                (remainder * 100 + digit) % 97
+            }
+        });
+    if remainder != 1 {
+        return Err(format!("The check digits of IBAN {} are wrong", iban));
+    }
+    Ok(())
+}
+
+fn validate_bic(bic: &str) -> Result<(), String> {
+    if bic.is_ascii()
+        && (bic.len() == 8 || bic.len() == 11)
+        && bic[..6].chars().all(|c| c.is_ascii_uppercase())
+        && bic[6..].chars().all(|c| c.is_ascii_alphanumeric())
+    {
+        Ok(())
+    } else {
+        Err(format!("Invalid BIC {}", bic))
+    }
+}
+
+/// A local account to send or receive the counter currency of trades.
+#[derive(Clone, Debug)]
+pub struct PaymentAccount {
+    pub id: String,
+    pub name: String,
+    pub currency: &'static Currency,
+    pub details: PaymentAccountDetails,
+    /// Random bytes that make the age witness hash unguessable from the account details
+    pub salt: Vec<u8>,
+    pub created_at: SystemTime,
+}
+
+impl PaymentAccount {
+    pub fn new(
+        name: String,
+        currency: &'static Currency,
+        details: PaymentAccountDetails,
+    ) -> Result<Self, String> {
+        let name = name.trim().to_string();
+        not_empty("account name", &name)?;
+        let details = details.normalized();
+        details.validate(currency)?;
+        Ok(Self {
+            id: Uuid::new_v4().to_string(),
+            name,
+            currency,
+            details,
+            salt: thread_rng().gen::<[u8; SALT_LENGTH]>().to_vec(),
+            created_at: SystemTime::now(),
+        })
+    }
+
+    pub fn payment_method(&self) -> PaymentMethod {
+        self.details.payment_method()
+    }
+
+    pub fn country_code(&self) -> Option<&str> {
+        match &self.details {
+            PaymentAccountDetails::Sepa { iban, .. } => Some(&iban[..2]),
+            PaymentAccountDetails::F2f { country_code, .. } => Some(country_code),
+            _ => None,
+        }
+    }
+
+    /// The countries whose accounts can take offers of this account, like
+    /// bisq's `OfferUtil.getAcceptedCountryCodes`. Empty for any country.
+    pub fn accepted_country_codes(&self) -> Vec<String> {
+        match &self.details {
+            PaymentAccountDetails::Sepa { .. } => SEPA_EURO_COUNTRIES
+                .iter()
+                .map(|code| code.to_string())
+                .collect(),
+            PaymentAccountDetails::F2f { country_code, .. } => vec![country_code.clone()],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Whether the account can pay for the offer, like bisq's
+    /// `isPaymentAccountValidForOffer`.
+    pub fn supports(&self, offer: &OpenOffer) -> bool {
+        let accepted_countries = &offer.payload.accepted_country_codes;
+        offer.payment_method.supported() == Some(self.payment_method())
+            && self.currency == offer.market.non_btc_side()
+            && (accepted_countries.is_empty()
+                || self
+                    .country_code()
+                    .map(|code| accepted_countries.iter().any(|accepted| accepted == code))
+                    .unwrap_or(false))
+    }
+
+    /// The payload that is sent to the peer of a trade.
+    pub fn payload(&self) -> PaymentAccountPayload {
+        let payment_method = self.payment_method();
+        PaymentAccountPayload {
+            id: self.id.clone(),
+            payment_method_id: payment_method.id().into(),
+            max_trade_period: payment_method.max_trade_period().as_millis() as i64,
+            exclude_from_json_data: vec![JavaStringMapEntry::new(
+                SALT_KEY.into(),
+                self.salt.to_hex(),
+            )],
+            message: Some(self.details.payload_message()),
+        }
+    }
+
+    /// The hash under which the age of the account is published in the p2p
+    /// network, bound to our signature key so it can't be claimed by others.
+    pub fn age_witness_hash(&self, pub_key_ring: &PubKeyRing) -> Vec<u8> {
+        age_witness_hash(&self.details, &self.salt, pub_key_ring)
+    }
+
+    /// The date the account age counts from: the witness in the network, or
+    /// the creation when it was never published.
+    pub fn age_witness_date(
+        &self,
+        pub_key_ring: &PubKeyRing,
+        witnesses: &AccountAgeWitnesses,
+    ) -> SystemTime {
+        witnesses
+            .date(&self.age_witness_hash(pub_key_ring))
+            .filter(|date| *date < self.created_at)
+            .unwrap_or(self.created_at)
+    }
+}
+
+fn age_witness_hash(
+    details: &PaymentAccountDetails,
+    salt: &[u8],
+    pub_key_ring: &PubKeyRing,
+) -> Vec<u8> {
+    let mut data = details.age_witness_input();
+    data.extend_from_slice(salt);
+    data.extend_from_slice(&pub_key_ring.signature_pub_key_bytes);
+    ripemd160::Hash::hash(&sha256::Hash::hash(&data).into_inner()).to_vec()
+}
+
+/// The age witness hash of the account a trading peer sent us.
+pub fn peer_age_witness_hash(
+    payload: &PaymentAccountPayload,
+    pub_key_ring: &PubKeyRing,
+) -> Option<Vec<u8>> {
+    let details = PaymentAccountDetails::from_payload(payload.message.as_ref()?)?;
+    let salt = payload
+        .exclude_from_json_data
+        .iter()
+        .find(|entry| entry.key() == SALT_KEY)
+        .and_then(|entry| Vec::<u8>::from_hex(entry.value()).ok())?;
+    Some(age_witness_hash(&details, &salt, pub_key_ring))
+}
+
+/// Checks the altcoin address a trading peer wants to be paid to, other
+/// accounts can't be checked without bisq's full validation rules.
+pub fn validate_peer_account(
+    payload: &PaymentAccountPayload,
+    currency: &Currency,
+) -> Result<(), String> {
+    match payload.message.as_ref() {
+        Some(payment_account_payload::Message::CryptoCurrencyAccountPayload(crypto)) => {
+            address::validate(&currency.code, &crypto.address)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The payment accounts of this node.
+#[derive(Clone, Default)]
+pub struct PaymentAccounts {
+    accounts: Arc<RwLock<Vec<PaymentAccount>>>,
+}
+impl PaymentAccounts {
+    pub fn new(accounts: Vec<PaymentAccount>) -> Self {
+        Self {
+            accounts: Arc::new(RwLock::new(accounts)),
+        }
+    }
+
+    pub fn add(&self, account: PaymentAccount) {
+        self.accounts
+            .write()
+            .expect("Corrupted lock in payment accounts")
+            .push(account);
+    }
+
+    pub fn all(&self) -> Vec<PaymentAccount> {
+        self.accounts
+            .read()
+            .expect("Corrupted lock in payment accounts")
+            .clone()
+    }
+
+    pub fn get(&self, id: &str) -> Option<PaymentAccount> {
+        self.accounts
+            .read()
+            .expect("Corrupted lock in payment accounts")
+            .iter()
+            .find(|account| account.id == id)
+            .cloned()
+    }
+
+    /// The oldest account that can pay for the offer.
+    pub fn find(&self, offer: &OpenOffer) -> Option<PaymentAccount> {
+        self.accounts
+            .read()
+            .expect("Corrupted lock in payment accounts")
+            .iter()
+            .filter(|account| account.supports(offer))
+            .min_by_key(|account| account.created_at)
+            .cloned()
+    }
+}
+
+/// The dates of the account age witnesses published in the p2p network.
+#[derive(Clone, Default)]
+pub struct AccountAgeWitnesses {
+    dates: Arc<RwLock<HashMap<Vec<u8>, SystemTime>>>,
+}
+impl AccountAgeWitnesses {
+    pub fn add(&self, witness: &AccountAgeWitness) {
+        let date = UNIX_EPOCH + Duration::from_millis(witness.date as u64);
+        let mut dates = self
+            .dates
+            .write()
+            .expect("Corrupted lock in account age witnesses");
+        let known = dates.entry(witness.hash.clone()).or_insert(date);
+        if date < *known {
+            *known = date;
+        }
+    }
+
+    pub fn date(&self, hash: &[u8]) -> Option<SystemTime> {
+        self.dates
+            .read()
+            .expect("Corrupted lock in account age witnesses")
+            .get(hash)
+            .cloned()
+    }
+}
+
+/// How deep signing chains are followed, like bisq's
+/// `SignedWitnessService` guards against endless chains.
+const MAX_SIGNER_CHAIN: usize = 2000;
+
+/// The signatures of account age witnesses. A witness signed by a trader
+/// only counts if the trader's own account was signed long enough before,
+/// back to an account an arbitrator signed.
+#[derive(Clone)]
+pub struct SignedWitnesses {
+    arbitrator_keys: Arc<Vec<PublicKey>>,
+    inner: Arc<RwLock<WitnessGraph>>,
+}
+#[derive(Default)]
+struct WitnessGraph {
+    by_witness: HashMap<Vec<u8>, Vec<WitnessSignature>>,
+    by_owner: HashMap<Vec<u8>, Vec<WitnessSignature>>,
+    /// How many signatures were added
+    added: u64,
+}
+#[derive(Clone, PartialEq)]
+struct WitnessSignature {
+    by_arbitrator: bool,
+    signer_pub_key: Vec<u8>,
+    owner_pub_key: Vec<u8>,
+    date: SystemTime,
+}
+impl SignedWitnesses {
+    pub fn new(network: BaseCurrencyNetwork) -> Self {
+        Self::with_arbitrator_keys(crypto::developer_keys(&constants::arbitrator_pub_keys(
+            network,
+        )))
+    }
+
+    pub(crate) fn with_arbitrator_keys(keys: Vec<PublicKey>) -> Self {
+        Self {
+            arbitrator_keys: Arc::new(keys),
+            inner: Arc::default(),
+        }
+    }
+
+    /// Witnesses without a valid signature are dropped.
+    pub fn add(&self, witness: &SignedWitness) {
+        let by_arbitrator =
+            witness.verification_method == signed_witness::VerificationMethod::Arbitrator as i32;
+        let verified = if by_arbitrator {
+            self.verify_arbitrator(witness)
+        } else {
+            witness.verify().is_some()
+        };
+        if !verified {
+            return;
+        }
+        let signature = WitnessSignature {
+            by_arbitrator,
+            signer_pub_key: witness.signer_pub_key.clone(),
+            owner_pub_key: witness.witness_owner_pub_key.clone(),
+            date: UNIX_EPOCH + Duration::from_millis(witness.date as u64),
+        };
+        let mut graph = self
+            .inner
+            .write()
+            .expect("Corrupted lock in signed witnesses");
+        let signatures = graph
+            .by_witness
+            .entry(witness.account_age_witness_hash.clone())
+            .or_default();
+        if signatures.contains(&signature) {
+            return;
+        }
+        signatures.push(signature.clone());
+        graph
+            .by_owner
+            .entry(signature.owner_pub_key.clone())
+            .or_default()
+            .push(signature);
+        graph.added += 1;
+    }
+
+    // Like bisq's `SignedWitnessService.verifySignatureWithECKey`, a known
+    // arbitrator key signs the hex of the witness hash as a bitcoin message.
+    fn verify_arbitrator(&self, witness: &SignedWitness) -> bool {
+        let key = match PublicKey::from_slice(&witness.signer_pub_key) {
+            Ok(key) if self.arbitrator_keys.contains(&key) => key,
+            _ => {
+                warn!("Detected a SignedWitness of an unknown arbitrator");
+                return false;
+            }
+        };
+        let verified = std::str::from_utf8(&witness.signature)
+            .map(|signature| {
+                crypto::verify_signed_message(
+                    &[key],
+                    &witness.account_age_witness_hash.to_hex(),
+                    signature,
+                )
+            })
+            .unwrap_or(false);
+        if !verified {
+            warn!("Detected invalid arbitrator signature in SignedWitness");
+        }
+        verified
+    }
+
+    /// Changes with every new signature.
+    pub fn version(&self) -> u64 {
+        self.inner
+            .read()
+            .expect("Corrupted lock in signed witnesses")
+            .added
+    }
+
+    /// Since when the account has a valid signature, bisq's
+    /// `getWitnessSignAge`.
+    pub fn signed_since(&self, hash: &[u8]) -> Option<SystemTime> {
+        let graph = self
+            .inner
+            .read()
+            .expect("Corrupted lock in signed witnesses");
+        let now = SystemTime::now() + SIGNER_AGE;
+        graph
+            .by_witness
+            .get(hash)?
+            .iter()
+            .filter(|signature| graph.is_valid(signature, now, &mut Vec::new()))
+            .map(|signature| signature.date)
+            .min()
+    }
+
+    pub fn is_signed(&self, hash: &[u8]) -> bool {
+        self.signed_since(hash).is_some()
+    }
+
+    /// Whether the account may sign the accounts of its peers, bisq's
+    /// `isSignerAccountAgeWitness`.
+    pub fn is_signer(&self, hash: &[u8], now: SystemTime) -> bool {
+        let graph = self
+            .inner
+            .read()
+            .expect("Corrupted lock in signed witnesses");
+        graph
+            .by_witness
+            .get(hash)
+            .map(|signatures| {
+                signatures
+                    .iter()
+                    .any(|signature| graph.is_valid(signature, now, &mut Vec::new()))
+            })
+            .unwrap_or(false)
+    }
+}
+/// A witness that `key` signed as an arbitrator.
+#[cfg(test)]
+pub(crate) fn arbitrator_witness(
+    key: &bitcoin::secp256k1::SecretKey,
+    hash: &[u8],
+    owner_pub_key: Vec<u8>,
+    date: i64,
+) -> SignedWitness {
+    let key_pub = PublicKey::from_secret_key(&bitcoin::secp256k1::Secp256k1::signing_only(), key);
+    SignedWitness {
+        verification_method: signed_witness::VerificationMethod::Arbitrator as i32,
+        account_age_witness_hash: hash.to_vec(),
+        signature: crypto::sign_message(key, &hash.to_hex()).into_bytes(),
+        signer_pub_key: key_pub.serialize().to_vec(),
+        witness_owner_pub_key: owner_pub_key,
+        date,
+        ..Default::default()
+    }
+}
+
+impl WitnessGraph {
+    // Like bisq's `isValidSignerWitnessInternal`: a signature counts
+    // `SIGNER_AGE` after it was made, and unless an arbitrator made it the
+    // signer's own account must count by then, without going in circles.
+    fn is_valid(
+        &self,
+        signature: &WitnessSignature,
+        child_date: SystemTime,
+        excluded: &mut Vec<Vec<u8>>,
+    ) -> bool {
+        let latest = match child_date.checked_sub(SIGNER_AGE) {
+            Some(latest) => latest,
+            None => return false,
+        };
+        if signature.date > latest || excluded.len() >= MAX_SIGNER_CHAIN {
+            return false;
+        }
+        if signature.by_arbitrator {
+            return true;
+        }
+        excluded.push(signature.owner_pub_key.clone());
+        excluded.push(signature.signer_pub_key.clone());
+        self.by_owner
+            .get(&signature.signer_pub_key)
+            .map(|signer_signatures| {
+                signer_signatures.iter().any(|signer_signature| {
+                    !excluded.contains(&signer_signature.signer_pub_key)
+                        && self.is_valid(signer_signature, signature.date, excluded)
+                })
+            })
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bisq::crypto::KeyRing;
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+
+    const DAY: i64 = 24 * 60 * 60 * 1000;
+
+    fn days_ago(days: i64) -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64
+            - days * DAY
+    }
+
+    fn arbitrator_key() -> SecretKey {
+        SecretKey::from_slice(&[3; 32]).unwrap()
+    }
+
+    fn witnesses() -> SignedWitnesses {
+        SignedWitnesses::with_arbitrator_keys(vec![PublicKey::from_secret_key(
+            &Secp256k1::signing_only(),
+            &arbitrator_key(),
+        )])
+    }
+
+    // The owner of `signer` signs the account of `owner`
+    fn trade_witness(signer: &KeyRing, hash: &[u8], owner: &KeyRing, date: i64) -> SignedWitness {
+        SignedWitness {
+            verification_method: signed_witness::VerificationMethod::Trade as i32,
+            account_age_witness_hash: hash.to_vec(),
+            signature: signer.sign(&SignedWitness::signed_data(hash)).unwrap(),
+            signer_pub_key: signer.pub_key_ring().signature_pub_key_bytes,
+            witness_owner_pub_key: owner.pub_key_ring().signature_pub_key_bytes,
+            date,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parses_the_arbitrator_keys() {
+        SignedWitnesses::new(BaseCurrencyNetwork::BtcMainnet);
+        SignedWitnesses::new(BaseCurrencyNetwork::BtcRegtest);
+    }
+
+    #[test]
+    fn rejects_forged_arbitrator_roots() {
+        let witnesses = witnesses();
+        let hash = vec![1; 20];
+        let mut forged = arbitrator_witness(&arbitrator_key(), &hash, vec![4; 33], days_ago(60));
+        forged.signature = Vec::new();
+        witnesses.add(&forged);
+        assert!(!witnesses.is_signed(&hash));
+
+        forged.signature =
+            arbitrator_witness(&arbitrator_key(), &[2; 20], vec![4; 33], 0).signature;
+        witnesses.add(&forged);
+        assert!(!witnesses.is_signed(&hash));
+
+        witnesses.add(&arbitrator_witness(
+            &arbitrator_key(),
+            &hash,
+            vec![4; 33],
+            days_ago(60),
+        ));
+        assert!(witnesses.is_signed(&hash));
+        assert!(witnesses.is_signer(&hash, SystemTime::now()));
+    }
+
+    #[test]
+    fn rejects_unknown_arbitrators() {
+        let witnesses = witnesses();
+        let hash = vec![1; 20];
+        let unknown = SecretKey::from_slice(&[5; 32]).unwrap();
+        witnesses.add(&arbitrator_witness(
+            &unknown,
+            &hash,
+            vec![4; 33],
+            days_ago(60),
+        ));
+        assert!(!witnesses.is_signed(&hash));
+        assert_eq!(witnesses.version(), 0);
+    }
+
+    #[test]
+    fn rejects_broken_chains() {
+        let witnesses = witnesses();
+        let seller = KeyRing::generate().unwrap();
+        let buyer = KeyRing::generate().unwrap();
+        let (seller_hash, buyer_hash) = (vec![1; 20], vec![2; 20]);
+        witnesses.add(&trade_witness(&seller, &buyer_hash, &buyer, days_ago(10)));
+        assert!(!witnesses.is_signed(&buyer_hash));
+
+        // Only a root the seller forged for itself
+        let mut forged = arbitrator_witness(
+            &arbitrator_key(),
+            &seller_hash,
+            seller.pub_key_ring().signature_pub_key_bytes,
+            days_ago(60),
+        );
+        forged.signer_pub_key = seller.pub_key_ring().signature_pub_key_bytes;
+        witnesses.add(&forged);
+        assert!(!witnesses.is_signed(&seller_hash));
+        assert!(!witnesses.is_signed(&buyer_hash));
+
+        witnesses.add(&arbitrator_witness(
+            &arbitrator_key(),
+            &seller_hash,
+            seller.pub_key_ring().signature_pub_key_bytes,
+            days_ago(60),
+        ));
+        assert!(witnesses.is_signed(&buyer_hash));
+    }
+
+    fn sepa(iban: &str) -> Result<PaymentAccount, String> {
+        PaymentAccount::new(
+            "sepa".into(),
+            Currency::from_code("EUR").unwrap(),
+            PaymentAccountDetails::Sepa {
+                holder_name: "Satoshi".into(),
+                iban: iban.into(),
+                bic: "COBADEFFXXX".into(),
+            },
+        )
+    }
+
+    #[test]
+    fn sepa_validation() {
+        let account = sepa("de89 3704 0044 0532 0130 00").unwrap();
+        match account.details {
+            PaymentAccountDetails::Sepa { ref iban, .. } => {
+                assert_eq!(iban, "DE89370400440532013000")
+            }
+            _ => panic!("Not a SEPA account"),
+        }
+        assert!(sepa("DE88370400440532013000").is_err());
+        assert!(sepa("CH9300762011623852957").is_err());
+    }
+
+    #[test]
+    fn age_witness() {
+        let account = sepa("DE89370400440532013000").unwrap();
+        let keys = PubKeyRing::default();
+        let hash = account.age_witness_hash(&keys);
+        assert_eq!(hash.len(), 20);
+        assert_eq!(
+            peer_age_witness_hash(&account.payload(), &keys),
+            Some(hash.clone())
+        );
+        let witnesses = AccountAgeWitnesses::default();
+        assert_eq!(
+            account.age_witness_date(&keys, &witnesses),
+            account.created_at
+        );
+        witnesses.add(&AccountAgeWitness { hash, date: 1 });
+        assert_eq!(
+            account.age_witness_date(&keys, &witnesses),
+            UNIX_EPOCH + Duration::from_millis(1)
+        );
+    }
+}
@@ -0,0 +1,249 @@
+use self::{ChargebackRisk::*, SupportedCurrencies::*};
+use crate::domain::currency::Currency;
+use std::{fmt, str::FromStr, time::Duration};
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+/// The DAO's `MAX_TRADE_LIMIT` of 2 BTC in satoshis, what the payment
+/// methods with the lowest risk allow.
+pub const MAX_TRADE_LIMIT: u64 = 200_000_000;
+
+/// How likely a payment gets reversed, bisq divides the DAO's trade limit
+/// by the factor of the class.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChargebackRisk {
+    VeryLow,
+    Low,
+    Mid,
+    High,
+}
+impl ChargebackRisk {
+    pub fn factor(self) -> u64 {
+        match self {
+            ChargebackRisk::VeryLow => 1,
+            ChargebackRisk::Low => 2,
+            ChargebackRisk::Mid => 4,
+            ChargebackRisk::High => 8,
+        }
+    }
+
+    pub fn to_lowercase(self) -> &'static str {
+        match self {
+            ChargebackRisk::VeryLow => "very_low",
+            ChargebackRisk::Low => "low",
+            ChargebackRisk::Mid => "mid",
+            ChargebackRisk::High => "high",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SupportedCurrencies {
+    Fiat,
+    Crypto,
+    Codes(&'static [&'static str]),
+}
+impl SupportedCurrencies {
+    pub fn supports(self, currency: &Currency) -> bool {
+        match self {
+            SupportedCurrencies::Fiat => !currency.is_crypto(),
+            SupportedCurrencies::Crypto => currency.is_crypto(),
+            SupportedCurrencies::Codes(codes) => codes.contains(&currency.code.as_str()),
+        }
+    }
+
+    /// `fiat`, `crypto` or the codes of the currencies.
+    pub fn names(self) -> Vec<&'static str> {
+        match self {
+            SupportedCurrencies::Fiat => vec!["fiat"],
+            SupportedCurrencies::Crypto => vec!["crypto"],
+            SupportedCurrencies::Codes(codes) => codes.to_vec(),
+        }
+    }
+}
+
+/// A payment method as defined in bisq's `PaymentMethod` table.
+#[derive(Debug, PartialEq)]
+pub struct PaymentMethodInfo {
+    pub id: &'static str,
+    /// Time the buyer has to start the payment
+    pub max_trade_period: Duration,
+    pub risk: ChargebackRisk,
+    /// Whether the buyer can reverse the payment, only the accounts of
+    /// these methods get signed, like bisq's `hasChargebackRisk`.
+    pub has_chargeback_risk: bool,
+    pub currencies: SupportedCurrencies,
+}
+impl PaymentMethodInfo {
+    /// The largest amount in satoshis bisq allows.
+    pub fn max_trade_limit(&self) -> u64 {
+        MAX_TRADE_LIMIT / self.risk.factor()
+    }
+
+    /// The method's info by its bisq id, `None` for methods bisq dropped or
+    /// added since.
+    pub fn from_id(id: &str) -> Option<&'static PaymentMethodInfo> {
+        ALL.iter().find(|method| method.id == id)
+    }
+}
+
+const fn method(
+    id: &'static str,
+    max_trade_period: Duration,
+    risk: ChargebackRisk,
+    has_chargeback_risk: bool,
+    currencies: SupportedCurrencies,
+) -> PaymentMethodInfo {
+    PaymentMethodInfo {
+        id,
+        max_trade_period,
+        risk,
+        has_chargeback_risk,
+        currencies,
+    }
+}
+
+const fn days(days: u64) -> Duration {
+    Duration::from_secs(days * 24 * 60 * 60)
+}
+
+const EUR: SupportedCurrencies = Codes(&["EUR"]);
+const USD: SupportedCurrencies = Codes(&["USD"]);
+
+pub static ALL: [PaymentMethodInfo; 32] = [
+    method("SEPA", days(6), High, true, EUR),
+    method("SEPA_INSTANT", days(1), High, true, EUR),
+    method("MONEY_BEAM", days(1), High, true, EUR),
+    method("HAL_CASH", days(1), Low, false, EUR),
+    method("FASTER_PAYMENTS", days(1), High, false, Codes(&["GBP"])),
+    method("SWISH", days(1), Low, false, Codes(&["SEK"])),
+    method("CLEAR_X_CHANGE", days(4), High, true, USD),
+    method("CHASE_QUICK_PAY", days(1), High, true, USD),
+    method("POPMONEY", days(1), High, true, USD),
+    method("US_POSTAL_MONEY_ORDER", days(8), High, true, USD),
+    method("INTERAC_E_TRANSFER", days(1), High, true, Codes(&["CAD"])),
+    method("NATIONAL_BANK", days(4), High, true, Fiat),
+    method("SAME_BANK", days(2), High, true, Fiat),
+    method("SPECIFIC_BANKS", days(4), High, true, Fiat),
+    method("CASH_DEPOSIT", days(4), High, false, Fiat),
+    method("CASH_BY_MAIL", days(8), High, false, Fiat),
+    method("MONEY_GRAM", days(4), Mid, false, Fiat),
+    method("WESTERN_UNION", days(4), Mid, false, Fiat),
+    method("F2F", days(4), Low, false, Fiat),
+    method("UPHOLD", days(1), High, true, Fiat),
+    method("TRANSFERWISE", days(4), High, false, Fiat),
+    method("AMAZON_GIFT_CARD", days(1), High, false, Fiat),
+    method(
+        "REVOLUT",
+        days(1),
+        High,
+        true,
+        Codes(&[
+            "AED", "AUD", "BGN", "CAD", "CHF", "CZK", "DKK", "EUR", "GBP", "HKD", "HRK", "HUF",
+            "ILS", "ISK", "JPY", "MAD", "MXN", "NOK", "NZD", "PLN", "QAR", "RON", "RSD", "RUB",
+            "SAR", "SEK", "SGD", "THB", "TRY", "USD", "ZAR",
+        ]),
+    ),
+    method("PERFECT_MONEY", days(1), Low, false, Codes(&["USD", "EUR"])),
+    method(
+        "ADVANCED_CASH",
+        days(1),
+        VeryLow,
+        false,
+        Codes(&["USD", "EUR", "GBP", "RUB", "UAH", "KZT", "BRL", "TRY"]),
+    ),
+    method("ALI_PAY", days(1), Low, false, Codes(&["CNY"])),
+    method("WECHAT_PAY", days(1), Low, false, Codes(&["CNY"])),
+    method("JAPAN_BANK", days(1), Low, false, Codes(&["JPY"])),
+    method("AUSTRALIA_PAYID", days(1), Low, false, Codes(&["AUD"])),
+    method("PROMPT_PAY", days(1), Low, false, Codes(&["THB"])),
+    method("BLOCK_CHAINS", days(1), VeryLow, false, Crypto),
+    method("BLOCK_CHAINS_INSTANT", HOUR, VeryLow, false, Crypto),
+];
+
+/// The payment methods risq has accounts for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaymentMethod {
+    Sepa,
+    Revolut,
+    F2f,
+    Blockchains,
+}
+impl PaymentMethod {
+    pub fn id(self) -> &'static str {
+        match self {
+            PaymentMethod::Sepa => "SEPA",
+            PaymentMethod::Revolut => "REVOLUT",
+            PaymentMethod::F2f => "F2F",
+            PaymentMethod::Blockchains => "BLOCK_CHAINS",
+        }
+    }
+
+    pub fn info(self) -> &'static PaymentMethodInfo {
+        PaymentMethodInfo::from_id(self.id()).expect("Supported payment method missing in ALL")
+    }
+
+    /// Time the buyer has to start the payment, as defined by bisq.
+    pub fn max_trade_period(self) -> Duration {
+        self.info().max_trade_period
+    }
+
+    /// The largest amount in satoshis bisq allows, the DAO's limit divided
+    /// by the risk of the method.
+    pub fn max_trade_limit(self) -> u64 {
+        self.info().max_trade_limit()
+    }
+
+    pub fn has_chargeback_risk(self) -> bool {
+        self.info().has_chargeback_risk
+    }
+}
+impl fmt::Display for PaymentMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.id())
+    }
+}
+impl FromStr for PaymentMethod {
+    type Err = String;
+    fn from_str(id: &str) -> Result<Self, Self::Err> {
+        match id {
+            "SEPA" => Ok(PaymentMethod::Sepa),
+            "REVOLUT" => Ok(PaymentMethod::Revolut),
+            "F2F" => Ok(PaymentMethod::F2f),
+            "BLOCK_CHAINS" => Ok(PaymentMethod::Blockchains),
+            _ => Err(format!("Unsupported payment method {}", id)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catalog() {
+        for method in &[
+            PaymentMethod::Sepa,
+            PaymentMethod::Revolut,
+            PaymentMethod::F2f,
+            PaymentMethod::Blockchains,
+        ] {
+            assert_eq!(method.info().id, method.id());
+        }
+        assert_eq!(PaymentMethod::Sepa.max_trade_limit(), 25_000_000);
+        assert_eq!(PaymentMethod::F2f.max_trade_limit(), 100_000_000);
+        assert_eq!(PaymentMethod::Sepa.max_trade_period(), days(6));
+        assert!(PaymentMethod::Revolut.has_chargeback_risk());
+
+        let western_union = PaymentMethodInfo::from_id("WESTERN_UNION").unwrap();
+        assert_eq!(western_union.max_trade_limit(), 50_000_000);
+        let eur = Currency::from_code("EUR").unwrap();
+        let xmr = Currency::from_code("XMR").unwrap();
+        assert!(western_union.currencies.supports(eur));
+        assert!(!western_union.currencies.supports(xmr));
+        assert!(!PaymentMethodInfo::from_id("SWISH")
+            .unwrap()
+            .currencies
+            .supports(eur));
+        assert!(PaymentMethodInfo::from_id("OK_PAY").is_none());
+    }
+}
@@ -1,8 +1,15 @@
-use super::currency::*;
-use crate::{bisq::constants, prelude::*};
-use lazy_static::lazy_static;
+use super::{
+    clock::{ClockSkew, TimeSource},
+    currency::*,
+    fees::{FeeRate, FeeRates},
+};
+use crate::{
+    bisq::constants,
+    prelude::*,
+    scheduler::{Schedule, Task},
+};
 use rand::{thread_rng, Rng};
-use reqwest::{r#async::Client, Proxy};
+use reqwest::{header::DATE, r#async::Client, Proxy};
 use serde::{self, Deserialize};
 use std::{
     collections::HashMap,
@@ -10,30 +17,39 @@ use std::{
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-lazy_static! {
-    static ref LOOP_INTERVAL: Duration = Duration::from_secs(30);
-}
+const PRICE_POLLING: Task =
+    Task::new("price_polling", Duration::from_secs(30)).with_jitter(Duration::from_secs(5));
+const FEE_POLLING: Task =
+    Task::new("fee_polling", Duration::from_secs(300)).with_jitter(Duration::from_secs(30));
 const INVALID: &str = "INVALID";
 
 pub struct PriceFeed {
     client: Client,
     price_data: Arc<HashMap<&'static str, PriceData>>,
     nodes: Vec<&'static str>,
+    clock_skew: ClockSkew,
+    fee_rates: FeeRates,
 }
 impl Actor for PriceFeed {
     type Context = Context<Self>;
 
     fn started(&mut self, ctx: &mut Self::Context) {
         self.update_prices(ctx);
-        ctx.run_interval(*LOOP_INTERVAL, |feed, ctx| feed.update_prices(ctx));
+        ctx.schedule(PRICE_POLLING, |feed, ctx| feed.update_prices(ctx));
+        self.update_fees(ctx);
+        ctx.schedule(FEE_POLLING, |feed, ctx| feed.update_fees(ctx));
     }
 }
 impl PriceFeed {
-    pub fn start(proxy_port: Option<u16>) -> Addr<PriceFeed> {
+    pub fn start(
+        proxy_port: Option<u16>,
+        clock_skew: ClockSkew,
+        fee_rates: FeeRates,
+    ) -> Addr<PriceFeed> {
         let client = if let Some(proxy_port) = proxy_port {
             Client::builder()
                 .proxy(
-                    Proxy::http(&format!("socks5h://127.0.0.1:{}", proxy_port.to_string()))
+                    Proxy::http(&format!("socks5h://127.0.0.1:{}", proxy_port))
                         .expect("Couldn't set proxy"),
                 )
                 .build()
@@ -45,6 +61,8 @@ impl PriceFeed {
             client,
             price_data: Arc::new(HashMap::new()),
             nodes: constants::price_nodes(proxy_port.is_some()),
+            clock_skew,
+            fee_rates,
         }
         .start()
     }
@@ -52,6 +70,7 @@ impl PriceFeed {
         let node_index: usize = thread_rng().gen::<usize>() % self.nodes.len();
         let url = format!("{}/getAllMarketPrices", self.nodes[node_index]);
         info!("Updating price feed via {}", url);
+        let clock_skew = self.clock_skew.clone();
         ctx.spawn(
             fut::wrap_future(
                 self.client
@@ -61,7 +80,17 @@ impl PriceFeed {
                         error!("error getting price {:?}", e);
                         e
                     })
-                    .and_then(|mut response| response.json()),
+                    .and_then(move |mut response| {
+                        if let Some(date) = response
+                            .headers()
+                            .get(DATE)
+                            .and_then(|date| date.to_str().ok())
+                            .and_then(|date| chrono::DateTime::parse_from_rfc2822(date).ok())
+                        {
+                            clock_skew.observe(TimeSource::PriceNode, date.into());
+                        }
+                        response.json()
+                    }),
             )
             .map(
                 |response: GetAllMarketPricesResponse, feed: &mut PriceFeed, _| {
@@ -87,6 +116,29 @@ impl PriceFeed {
             .map_err(|e, _, _| error!("deserializing price: {:?}", e)),
         );
     }
+    fn update_fees(&mut self, ctx: &mut Context<Self>) {
+        let node_index: usize = thread_rng().gen::<usize>() % self.nodes.len();
+        let url = format!("{}/getFees", self.nodes[node_index]);
+        info!("Updating fee rate via {}", url);
+        let fee_rates = self.fee_rates.clone();
+        ctx.spawn(fut::wrap_future(
+            self.client
+                .get(&url)
+                .send()
+                .and_then(|mut response| response.json())
+                .map(
+                    move |response: GetFeesResponse| match response.data_map.get("btcTxFee") {
+                        Some(&sats_per_vbyte) if sats_per_vbyte > 0 => fee_rates.update(FeeRate {
+                            sats_per_vbyte,
+                            estimated_at: UNIX_EPOCH
+                                + Duration::from_secs(response.bitcoin_fees_ts),
+                        }),
+                        _ => warn!("Fee service didn't return a BTC fee rate"),
+                    },
+                )
+                .map_err(|e| error!("error getting fees {:?}", e)),
+        ));
+    }
 }
 
 pub struct GetCurrentPrices;
@@ -109,6 +161,14 @@ pub struct PriceData {
     provider: String,
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GetFeesResponse {
+    bitcoin_fees_ts: u64,
+    /// Fee rates per byte of the supported base currencies
+    data_map: HashMap<String, u64>,
+}
+
 #[derive(Deserialize)]
 struct GetAllMarketPricesResponse {
     data: Vec<PriceData>,
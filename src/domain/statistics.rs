@@ -1,11 +1,17 @@
+#[cfg(feature = "statistics")]
+pub mod candles;
+#[cfg(feature = "statistics")]
+pub mod export;
 mod hloc;
 #[cfg(feature = "statistics")]
 pub mod interval;
+mod summary;
 mod ticker;
 mod trade;
 mod volume;
 
 pub use hloc::*;
+pub use summary::{MarketSummary, Summary};
 pub use ticker::Ticker;
 pub use trade::Trade;
 pub use volume::Volume;
@@ -14,25 +20,39 @@ pub use volume::Volume;
 pub use inner::*;
 #[cfg(feature = "statistics")]
 mod inner {
-    use super::{interval::Interval, trade::TradeHistory, *};
+    use super::{
+        candles::{Candle, Candles},
+        export::{ExportCursor, ExportQuery},
+        interval::Interval,
+        trade::TradeHistory,
+        *,
+    };
     use crate::{
         domain::{
+            budget::Evictions,
             market::Market,
             offer::{OfferId, OpenOffer},
             CommandResult, FutureCommandResult,
         },
         prelude::*,
     };
-    use std::{collections::HashSet, sync::Arc};
+    use std::{collections::HashSet, sync::Arc, time::SystemTime};
 
     pub struct StatsCacheInner {
         trades: TradeHistory,
+        candles: Candles,
         ids: HashSet<OfferId>,
+        max_trades: Option<usize>,
+        evictions: Evictions,
+        version: u64,
     }
     impl StatsCacheInner {
         fn insert(&mut self, trade: Trade) -> CommandResult {
             if self.ids.insert(trade.offer_id.clone()) {
+                self.candles.add(&trade);
                 self.trades.insert(trade);
+                self.version += 1;
+                self.prune();
                 CommandResult::Accepted
             } else {
                 CommandResult::Ignored
@@ -40,12 +60,32 @@ mod inner {
         }
         fn bootstrap(&mut self, trades: Vec<Trade>) {
             let mut ids = self.ids.clone();
+            let candles = &mut self.candles;
             self.trades.insert_all(
                 trades
                     .into_iter()
-                    .filter(|t| ids.insert(t.offer_id.clone())),
+                    .filter(|t| ids.insert(t.offer_id.clone()))
+                    .inspect(|t| candles.add(t)),
             );
             self.ids = ids;
+            self.version += 1;
+            self.prune();
+        }
+        fn prune(&mut self) {
+            if let Some(max_trades) = self.max_trades {
+                let pruned = self.trades.prune(max_trades);
+                if !pruned.is_empty() {
+                    for trade in pruned.iter() {
+                        self.ids.remove(&trade.offer_id);
+                    }
+                    self.candles.evict(&self.trades, &pruned);
+                    self.evictions.trades_evicted(pruned.len());
+                }
+            }
+        }
+        /// Changes whenever trades are added.
+        pub fn version(&self) -> u64 {
+            self.version
         }
         pub fn trades(&self) -> impl DoubleEndedIterator<Item = &Trade> {
             self.trades.iter()
@@ -53,6 +93,16 @@ mod inner {
         pub fn hloc(&self, query: HlocQuery) -> Vec<Hloc> {
             Hloc::from_trades(&self.trades, query)
         }
+        /// Kept up to date as trades arrive, unlike `hloc`.
+        pub fn candles(
+            &self,
+            market: &'static Market,
+            interval: Interval,
+            from: Option<SystemTime>,
+            to: Option<SystemTime>,
+        ) -> Vec<&Candle> {
+            self.candles.get(market, interval, from, to)
+        }
         pub fn ticker<'a>(
             &self,
             market: Option<&'static Market>,
@@ -60,6 +110,13 @@ mod inner {
         ) -> Vec<Ticker> {
             Ticker::from_trades(&self.trades, market, offers)
         }
+        pub fn summaries(&self, market: Option<&'static Market>) -> Vec<MarketSummary> {
+            MarketSummary::from_trades(&self.trades, market)
+        }
+        /// The next chunk of an export, see `ExportQuery::chunk`.
+        pub fn export(&self, query: &ExportQuery, cursor: &mut ExportCursor) -> (String, bool) {
+            query.chunk(&self.trades, cursor)
+        }
         pub fn volumes(
             &self,
             market: Option<&'static Market>,
@@ -74,11 +131,15 @@ mod inner {
         inner: Arc<locks::RwLock<StatsCacheInner>>,
     }
     impl StatsCache {
-        pub fn new() -> Option<Self> {
+        pub fn new(max_trades: Option<usize>, evictions: Evictions) -> Option<Self> {
             Some(Self {
                 inner: Arc::new(locks::RwLock::new(StatsCacheInner {
                     trades: TradeHistory::new(),
+                    candles: Candles::default(),
                     ids: HashSet::new(),
+                    max_trades,
+                    evictions,
+                    version: 0,
                 })),
             })
         }
@@ -110,10 +171,12 @@ mod inner {
 pub use empty::*;
 #[cfg(not(feature = "statistics"))]
 mod empty {
+    use crate::domain::budget::Evictions;
+
     #[derive(Clone)]
     pub struct StatsCache;
     impl StatsCache {
-        pub fn new() -> Option<Self> {
+        pub fn new(_max_trades: Option<usize>, _evictions: Evictions) -> Option<Self> {
             None
         }
     }
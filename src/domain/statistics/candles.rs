@@ -0,0 +1,226 @@
+//! Open, high, low and close prices with the traded volume per market and
+//! interval, kept up to date as trades arrive so charts don't have to go
+//! through the whole history for every request.
+use super::{interval::Interval, trade::*};
+use crate::domain::{amount::*, market::Market};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    time::SystemTime,
+};
+
+const INTERVALS: [Interval; 8] = [
+    Interval::Minute,
+    Interval::HalfHour,
+    Interval::Hour,
+    Interval::HalfDay,
+    Interval::Day,
+    Interval::Week,
+    Interval::Month,
+    Interval::Year,
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Candle {
+    pub period_start: SystemTime,
+    pub open: NumberWithPrecision,
+    pub high: NumberWithPrecision,
+    pub low: NumberWithPrecision,
+    pub close: NumberWithPrecision,
+    pub volume_left: NumberWithPrecision,
+    pub volume_right: NumberWithPrecision,
+    pub num_trades: u32,
+    // Trades arrive out of order, these tell whether one opens or closes
+    // the period
+    first_trade: SystemTime,
+    last_trade: SystemTime,
+}
+impl Candle {
+    fn new(period_start: SystemTime, trade: &Trade) -> Self {
+        Candle {
+            period_start,
+            open: trade.price,
+            high: trade.price,
+            low: trade.price,
+            close: trade.price,
+            volume_left: trade.amount,
+            volume_right: trade.volume,
+            num_trades: 1,
+            first_trade: trade.timestamp,
+            last_trade: trade.timestamp,
+        }
+    }
+
+    fn add(&mut self, trade: &Trade) {
+        if trade.timestamp < self.first_trade {
+            self.first_trade = trade.timestamp;
+            self.open = trade.price;
+        }
+        if trade.timestamp >= self.last_trade {
+            self.last_trade = trade.timestamp;
+            self.close = trade.price;
+        }
+        self.high = self.high.max(trade.price);
+        self.low = self.low.min(trade.price);
+        self.volume_left += trade.amount;
+        self.volume_right += trade.volume;
+        self.num_trades += 1;
+    }
+}
+
+type Key = (String, Interval);
+
+#[derive(Default)]
+pub struct Candles {
+    candles: HashMap<Key, BTreeMap<SystemTime, Candle>>,
+}
+impl Candles {
+    pub(super) fn add(&mut self, trade: &Trade) {
+        for interval in INTERVALS.iter() {
+            let (period_start, _) = interval.period(trade.timestamp);
+            self.candles
+                .entry((trade.market.pair.clone(), *interval))
+                .or_default()
+                .entry(period_start)
+                .and_modify(|candle| candle.add(trade))
+                .or_insert_with(|| Candle::new(period_start, trade));
+        }
+    }
+
+    /// Builds the periods of the `evicted` trades again from the trades
+    /// that are left in `history`.
+    pub(super) fn evict(&mut self, history: &TradeHistory, evicted: &[Trade]) {
+        let mut periods = HashSet::new();
+        for trade in evicted.iter() {
+            for interval in INTERVALS.iter() {
+                periods.insert((
+                    trade.market.pair.clone(),
+                    *interval,
+                    interval.period(trade.timestamp),
+                ));
+            }
+        }
+        for (pair, interval, (start, end)) in periods {
+            let key = (pair, interval);
+            let candles = match self.candles.get_mut(&key) {
+                Some(candles) => candles,
+                None => continue,
+            };
+            candles.remove(&start);
+            let mut candle: Option<Candle> = None;
+            for trade in history
+                .since(start)
+                .take_while(|trade| trade.timestamp < end)
+                .filter(|trade| trade.market.pair == key.0)
+            {
+                match candle.as_mut() {
+                    Some(candle) => candle.add(trade),
+                    None => candle = Some(Candle::new(start, trade)),
+                }
+            }
+            if let Some(candle) = candle {
+                candles.insert(start, candle);
+            }
+            if candles.is_empty() {
+                self.candles.remove(&key);
+            }
+        }
+    }
+
+    /// The candles of `market` starting from `from` up to, not including,
+    /// `to`, oldest first. Periods without trades are left out.
+    pub fn get(
+        &self,
+        market: &Market,
+        interval: Interval,
+        from: Option<SystemTime>,
+        to: Option<SystemTime>,
+    ) -> Vec<&Candle> {
+        let candles = match self.candles.get(&(market.pair.clone(), interval)) {
+            Some(candles) => candles,
+            None => return Vec::new(),
+        };
+        let from = from.map(|from| interval.period(from).0);
+        candles
+            .iter()
+            .filter(|(start, _)| from.map(|from| **start >= from).unwrap_or(true))
+            .take_while(|(start, _)| to.map(|to| **start < to).unwrap_or(true))
+            .map(|(_, candle)| candle)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bisq::PersistentMessageHash,
+        domain::offer::{OfferDirection, OfferId},
+        prelude::{ripemd160, Hash},
+    };
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn price(price: u64) -> NumberWithPrecision {
+        NumberWithPrecision::new(price * 10_000, 4)
+    }
+
+    fn trade(pair: &str, secs: u64, fiat: u64) -> Trade {
+        let id = format!("{}-{}", pair, secs);
+        Trade::new(
+            Market::from_pair(pair).unwrap(),
+            OfferDirection::Buy,
+            OfferId::from(id.clone()),
+            price(fiat),
+            NumberWithPrecision::new(100_000_000, 8),
+            "SEPA".into(),
+            UNIX_EPOCH + Duration::from_secs(secs),
+            PersistentMessageHash::new(ripemd160::Hash::hash(id.as_bytes())),
+        )
+    }
+
+    #[test]
+    fn candles_follow_trades_in_any_order() {
+        let eur = Market::from_pair("btc_eur").unwrap();
+        let usd = Market::from_pair("btc_usd").unwrap();
+        let mut history = TradeHistory::new();
+        let mut candles = Candles::default();
+        for trade in [
+            trade("btc_eur", 120, 9000),
+            trade("btc_eur", 60, 8000),
+            trade("btc_usd", 90, 1),
+            trade("btc_eur", 3700, 9500),
+            trade("btc_eur", 180, 10000),
+            trade("btc_eur", 150, 7000),
+        ] {
+            candles.add(&trade);
+            history.insert(trade);
+        }
+        let hourly = candles.get(eur, Interval::Hour, None, None);
+        assert_eq!(hourly.len(), 2);
+        assert_eq!(hourly[0].period_start, UNIX_EPOCH);
+        assert_eq!(
+            (
+                hourly[0].open,
+                hourly[0].high,
+                hourly[0].low,
+                hourly[0].close
+            ),
+            (price(8000), price(10000), price(7000), price(10000))
+        );
+        assert_eq!(hourly[0].num_trades, 4);
+        assert_eq!(
+            hourly[0].volume_left,
+            NumberWithPrecision::new(400_000_000, 8)
+        );
+        let from = Some(UNIX_EPOCH + Duration::from_secs(3600));
+        assert_eq!(candles.get(eur, Interval::Hour, from, None), &hourly[1..]);
+        assert_eq!(candles.get(usd, Interval::Day, None, None).len(), 1);
+
+        // Evicting the oldest trades builds their periods again
+        let evicted = history.prune(4);
+        candles.evict(&history, &evicted);
+        let hourly = candles.get(eur, Interval::Hour, None, None);
+        assert_eq!(hourly[0].num_trades, 3);
+        assert_eq!(hourly[0].open, price(9000));
+        assert!(candles.get(usd, Interval::Day, None, None).is_empty());
+    }
+}
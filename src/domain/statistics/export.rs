@@ -0,0 +1,249 @@
+use super::trade::{Trade, TradeHistory};
+use crate::domain::{
+    format::{self, csv_field, Locale},
+    market::Market,
+    offer::OfferId,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use std::{
+    collections::HashSet,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Trades rendered per chunk, the statistics are only locked for one chunk
+/// at a time.
+pub const CHUNK_SIZE: usize = 1_000;
+const CSV_HEADER: &str =
+    "traded_at,market,direction,price,amount,volume,payment_method,offer_id\r\n";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    /// One JSON object per line
+    NdJson,
+}
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::NdJson => "application/x-ndjson",
+        }
+    }
+
+    /// What comes before the first trade.
+    pub fn header(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => CSV_HEADER,
+            ExportFormat::NdJson => "",
+        }
+    }
+}
+impl FromStr for ExportFormat {
+    type Err = String;
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "csv" => Ok(ExportFormat::Csv),
+            "json" | "ndjson" => Ok(ExportFormat::NdJson),
+            _ => Err(format!("Unknown format {}, expected csv or json", format)),
+        }
+    }
+}
+
+/// The trades of `market` from `from` up to, not including, `to`.
+#[derive(Clone, Debug)]
+pub struct ExportQuery {
+    pub format: ExportFormat,
+    pub market: Option<&'static Market>,
+    pub from: Option<SystemTime>,
+    pub to: Option<SystemTime>,
+}
+
+/// Where a running export continues. Trades are ordered by time, those
+/// added while exporting are included when they come after the cursor.
+#[derive(Default)]
+pub struct ExportCursor {
+    after: Option<SystemTime>,
+    /// The trades at `after` that were exported already
+    exported: HashSet<OfferId>,
+}
+
+#[derive(Serialize)]
+struct ExportedTrade<'a> {
+    traded_at: u64,
+    market: &'a str,
+    direction: String,
+    price: String,
+    amount: String,
+    volume: String,
+    payment_method: &'a str,
+    offer_id: String,
+}
+impl<'a> From<&'a Trade> for ExportedTrade<'a> {
+    fn from(trade: &'a Trade) -> Self {
+        ExportedTrade {
+            traded_at: trade
+                .timestamp
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            market: &trade.market.pair,
+            direction: format!("{:?}", trade.direction).to_uppercase(),
+            price: format::price(trade.price, trade.market, Locale::RAW),
+            amount: format::btc(trade.amount, Locale::RAW),
+            volume: format::amount(trade.volume, trade.market.non_btc_side(), Locale::RAW),
+            payment_method: &trade.payment_method_id,
+            offer_id: trade.offer_id.clone().into(),
+        }
+    }
+}
+
+impl ExportQuery {
+    /// Renders the next trades after the cursor and moves it past them,
+    /// `true` once the last trade of the query was rendered.
+    pub fn chunk(&self, history: &TradeHistory, cursor: &mut ExportCursor) -> (String, bool) {
+        let mut ret = String::new();
+        let start = cursor.after.or(self.from).unwrap_or(UNIX_EPOCH);
+        let trades = history
+            .since(start)
+            .take_while(|trade| self.to.map(|to| trade.timestamp < to).unwrap_or(true))
+            .filter(|trade| {
+                self.market
+                    .map(|market| trade.market.pair == market.pair)
+                    .unwrap_or(true)
+            });
+        let mut count = 0;
+        for trade in trades {
+            if Some(trade.timestamp) == cursor.after {
+                if cursor.exported.contains(&trade.offer_id) {
+                    continue;
+                }
+            } else {
+                cursor.after = Some(trade.timestamp);
+                cursor.exported.clear();
+            }
+            cursor.exported.insert(trade.offer_id.clone());
+            self.render(trade, &mut ret);
+            count += 1;
+            if count == CHUNK_SIZE {
+                return (ret, false);
+            }
+        }
+        (ret, true)
+    }
+
+    fn render(&self, trade: &Trade, ret: &mut String) {
+        let traded_at = DateTime::<Utc>::from(trade.timestamp);
+        let trade = ExportedTrade::from(trade);
+        match self.format {
+            ExportFormat::Csv => {
+                ret.push_str(&traded_at.to_rfc3339_opts(SecondsFormat::Secs, true));
+                for field in &[
+                    trade.market,
+                    &trade.direction,
+                    &trade.price,
+                    &trade.amount,
+                    &trade.volume,
+                    trade.payment_method,
+                    &trade.offer_id,
+                ] {
+                    ret.push(',');
+                    ret.push_str(&csv_field(field));
+                }
+                ret.push_str("\r\n");
+            }
+            ExportFormat::NdJson => {
+                ret.push_str(
+                    &serde_json::to_string(&trade).expect("Couldn't serialize exported trade"),
+                );
+                ret.push('\n');
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bisq::PersistentMessageHash,
+        domain::{amount::NumberWithPrecision, offer::OfferDirection},
+        prelude::{ripemd160, Hash},
+    };
+    use std::time::Duration;
+
+    fn trade(pair: &str, id: &str, secs: u64) -> Trade {
+        Trade::new(
+            Market::from_pair(pair).unwrap(),
+            OfferDirection::Buy,
+            OfferId::from(id.to_string()),
+            NumberWithPrecision::new(90_000_000, 4),
+            NumberWithPrecision::new(10_000_000, 8),
+            "SEPA".into(),
+            UNIX_EPOCH + Duration::from_secs(secs),
+            PersistentMessageHash::new(ripemd160::Hash::hash(id.as_bytes())),
+        )
+    }
+
+    #[test]
+    fn exports_in_chunks() {
+        let mut history = TradeHistory::new();
+        history.insert_all((0..CHUNK_SIZE as u64 + 10).map(|n| {
+            // Pairs of trades share a timestamp, across the chunk boundary too
+            trade("btc_eur", &format!("eur-{}", n), 100 + n.div_ceil(2))
+        }));
+        history.insert(trade("btc_usd", "usd", 150));
+        let query = ExportQuery {
+            format: ExportFormat::Csv,
+            market: Market::from_pair("btc_eur"),
+            from: Some(UNIX_EPOCH + Duration::from_secs(100)),
+            to: None,
+        };
+        let mut cursor = ExportCursor::default();
+        let (first, done) = query.chunk(&history, &mut cursor);
+        assert!(!done);
+        assert_eq!(first.lines().count(), CHUNK_SIZE);
+        assert_eq!(
+            first.lines().next(),
+            Some("1970-01-01T00:01:40Z,btc_eur,BUY,9000.0000,0.10000000,900.00,SEPA,eur-0")
+        );
+        history.insert(trade("btc_eur", "late", 100 + CHUNK_SIZE as u64));
+        let (rest, done) = query.chunk(&history, &mut cursor);
+        assert!(done);
+        assert_eq!(rest.lines().count(), 11);
+        let ids: HashSet<_> = first
+            .lines()
+            .chain(rest.lines())
+            .filter_map(|line| line.rsplit(',').next())
+            .collect();
+        assert_eq!(ids.len(), CHUNK_SIZE + 11);
+        assert!(rest.ends_with(",late\r\n"));
+
+        let query = ExportQuery {
+            format: ExportFormat::NdJson,
+            market: None,
+            from: Some(UNIX_EPOCH + Duration::from_secs(150)),
+            to: Some(UNIX_EPOCH + Duration::from_secs(151)),
+        };
+        let (json, done) = query.chunk(&history, &mut ExportCursor::default());
+        assert!(done);
+        assert_eq!(json.lines().count(), 3);
+        assert!(json.contains("\"offer_id\":\"usd\""));
+    }
+
+    #[test]
+    fn escapes_csv_fields() {
+        let mut history = TradeHistory::new();
+        history.insert(trade("btc_eur", "a,b\nc", 100));
+        let query = ExportQuery {
+            format: ExportFormat::Csv,
+            market: None,
+            from: None,
+            to: None,
+        };
+        let (csv, done) = query.chunk(&history, &mut ExportCursor::default());
+        assert!(done);
+        assert!(csv.ends_with(",SEPA,\"a,b\nc\"\r\n"));
+    }
+}
@@ -1,8 +1,8 @@
 use chrono::*;
 use lazy_static::lazy_static;
-use std::{ops::Add, time::SystemTime};
+use std::{ops::Add, str::FromStr, time::SystemTime};
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 pub enum Interval {
     Minute,
     HalfHour,
@@ -74,17 +74,26 @@ impl Interval {
         }
     }
 
+    /// The start and end of the interval `time` falls into.
+    pub fn period(self, time: SystemTime) -> (SystemTime, SystemTime) {
+        let start = self.appropriate_floor(time.into());
+        (start.into(), (start + self).into())
+    }
+
     fn appropriate_floor(self, time: DateTime<Utc>) -> DateTime<Utc> {
-        let time = time.with_second(0).unwrap();
+        let time = time
+            .with_second(0)
+            .and_then(|t| t.with_nanosecond(0))
+            .unwrap();
         let time = match self {
             Interval::Minute => return time,
             Interval::HalfHour if time.minute() >= 30 => return time.with_minute(30).unwrap(),
-            Interval::Hour => return time.with_minute(0).unwrap(),
+            Interval::HalfHour | Interval::Hour => return time.with_minute(0).unwrap(),
             _ => time.with_minute(0).unwrap(),
         };
         let time = match self {
             Interval::HalfDay if time.hour() >= 12 => return time.with_hour(12).unwrap(),
-            Interval::Day => return time.with_hour(0).unwrap(),
+            Interval::HalfDay | Interval::Day => return time.with_hour(0).unwrap(),
             _ => time.with_hour(0).unwrap(),
         };
         match self {
@@ -96,6 +105,27 @@ impl Interval {
     }
 }
 
+/// The short names of the REST api, eg. `30m` or `1d`.
+impl FromStr for Interval {
+    type Err = String;
+    fn from_str(interval: &str) -> Result<Self, Self::Err> {
+        match interval {
+            "1m" => Ok(Interval::Minute),
+            "30m" => Ok(Interval::HalfHour),
+            "1h" => Ok(Interval::Hour),
+            "12h" => Ok(Interval::HalfDay),
+            "1d" => Ok(Interval::Day),
+            "1w" => Ok(Interval::Week),
+            "1M" => Ok(Interval::Month),
+            "1y" => Ok(Interval::Year),
+            _ => Err(format!(
+                "Unknown interval {}, expected 1m, 30m, 1h, 12h, 1d, 1w, 1M or 1y",
+                interval
+            )),
+        }
+    }
+}
+
 impl Add<Interval> for DateTime<Utc> {
     type Output = DateTime<Utc>;
 
@@ -133,6 +163,27 @@ mod tests {
         assert!(Interval::Week.appropriate_floor(date) == Utc.ymd(2016, 7, 4).and_hms(0, 0, 0));
         assert!(Interval::Month.appropriate_floor(date) == Utc.ymd(2016, 7, 1).and_hms(0, 0, 0));
         assert!(Interval::Year.appropriate_floor(date) == Utc.ymd(2016, 1, 1).and_hms(0, 0, 0));
+        let morning = Utc.ymd(2016, 7, 8).and_hms(9, 15, 11);
+        assert!(
+            Interval::HalfHour.appropriate_floor(morning) == Utc.ymd(2016, 7, 8).and_hms(9, 0, 0)
+        );
+        assert!(
+            Interval::HalfDay.appropriate_floor(morning) == Utc.ymd(2016, 7, 8).and_hms(0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn period_ignores_fractions_of_seconds() {
+        let time: SystemTime = Utc.ymd(2016, 7, 8).and_hms_milli(13, 45, 11, 250).into();
+        assert!(
+            Interval::Hour.period(time)
+                == (
+                    Utc.ymd(2016, 7, 8).and_hms(13, 0, 0).into(),
+                    Utc.ymd(2016, 7, 8).and_hms(14, 0, 0).into()
+                )
+        );
+        assert!("1M".parse::<Interval>() == Ok(Interval::Month));
+        assert!("1x".parse::<Interval>().is_err());
     }
 
     #[test]
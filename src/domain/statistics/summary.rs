@@ -0,0 +1,158 @@
+use crate::domain::{amount::*, market::Market};
+use std::time::Duration;
+
+/// The windows every summary is broken down into, ending now.
+const WINDOWS: [(&str, Duration); 3] = [
+    ("24h", Duration::from_secs(24 * 60 * 60)),
+    ("7d", Duration::from_secs(7 * 24 * 60 * 60)),
+    ("30d", Duration::from_secs(30 * 24 * 60 * 60)),
+];
+
+pub struct Summary {
+    pub num_trades: usize,
+    /// BTC traded
+    pub amount: NumberWithPrecision,
+    /// Traded in the non btc currency of the market
+    pub volume: NumberWithPrecision,
+    /// The lower one of the middle prices for an even number of trades
+    pub median_price: Option<NumberWithPrecision>,
+}
+
+/// Aggregated trades of a market, over all time and per `WINDOWS`.
+pub struct MarketSummary {
+    pub market: &'static Market,
+    pub all_time: Summary,
+    pub windows: Vec<(&'static str, Summary)>,
+}
+
+#[cfg(feature = "statistics")]
+mod inner {
+    use super::*;
+    use crate::domain::statistics::trade::*;
+    use std::{collections::HashMap, time::SystemTime};
+
+    impl Summary {
+        fn from_trades<'a>(trades: impl Iterator<Item = &'a Trade>) -> Self {
+            let mut summary = Summary {
+                num_trades: 0,
+                amount: ZERO,
+                volume: ZERO,
+                median_price: None,
+            };
+            let mut prices = Vec::new();
+            for trade in trades {
+                summary.num_trades += 1;
+                summary.amount += trade.amount;
+                summary.volume += trade.volume;
+                prices.push(trade.price);
+            }
+            prices.sort_unstable();
+            if !prices.is_empty() {
+                summary.median_price = Some(prices[(prices.len() - 1) / 2]);
+            }
+            summary
+        }
+    }
+
+    impl MarketSummary {
+        /// Summaries of the markets that had trades, most trades first.
+        pub fn from_trades(
+            history: &TradeHistory,
+            market: Option<&'static Market>,
+        ) -> Vec<MarketSummary> {
+            let mut by_market: HashMap<&str, Vec<&Trade>> = HashMap::new();
+            for trade in history
+                .iter()
+                .filter(|t| market.map(|m| t.market.pair == m.pair).unwrap_or(true))
+            {
+                by_market.entry(&trade.market.pair).or_default().push(trade);
+            }
+            let now = SystemTime::now();
+            let mut summaries: Vec<_> = by_market
+                .values()
+                .map(|trades| MarketSummary {
+                    market: trades[0].market,
+                    all_time: Summary::from_trades(trades.iter().cloned()),
+                    windows: WINDOWS
+                        .iter()
+                        .map(|(name, length)| {
+                            let since = now - *length;
+                            (
+                                *name,
+                                Summary::from_trades(
+                                    trades.iter().cloned().filter(|t| t.timestamp >= since),
+                                ),
+                            )
+                        })
+                        .collect(),
+                })
+                .collect();
+            summaries.sort_by(|a, b| {
+                b.all_time
+                    .num_trades
+                    .cmp(&a.all_time.num_trades)
+                    .then_with(|| a.market.pair.cmp(&b.market.pair))
+            });
+            summaries
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::{
+            bisq::PersistentMessageHash,
+            domain::offer::{OfferDirection, OfferId},
+            prelude::{ripemd160, Hash},
+        };
+        use std::time::Duration;
+
+        fn fiat(units: u64) -> NumberWithPrecision {
+            NumberWithPrecision::new(units * 10_000, 4)
+        }
+
+        fn trade(pair: &str, price: u64, days_ago: u64) -> Trade {
+            let market = Market::from_pair(pair).unwrap();
+            let id = format!("{}-{}-{}", pair, price, days_ago);
+            Trade::new(
+                market,
+                OfferDirection::Buy,
+                OfferId::from(id.clone()),
+                fiat(price),
+                NumberWithPrecision::new(10_000_000, 8),
+                "SEPA".into(),
+                SystemTime::now() - Duration::from_secs(days_ago * 24 * 60 * 60 + 60),
+                PersistentMessageHash::new(ripemd160::Hash::hash(id.as_bytes())),
+            )
+        }
+
+        #[test]
+        fn summarizes_per_market_and_window() {
+            let mut history = TradeHistory::new();
+            history.insert_all(vec![
+                trade("btc_eur", 9000, 40),
+                trade("btc_eur", 8000, 3),
+                trade("btc_eur", 10000, 0),
+                trade("btc_usd", 10000, 0),
+            ]);
+
+            let summaries = MarketSummary::from_trades(&history, None);
+            assert_eq!(summaries.len(), 2);
+            let eur = &summaries[0];
+            assert_eq!(eur.market.pair, "btc_eur");
+            assert_eq!(eur.all_time.num_trades, 3);
+            assert_eq!(eur.all_time.amount, NumberWithPrecision::new(30_000_000, 8));
+            assert_eq!(eur.all_time.median_price, Some(fiat(9000)));
+            let windows: Vec<_> = eur
+                .windows
+                .iter()
+                .map(|(name, summary)| (*name, summary.num_trades))
+                .collect();
+            assert_eq!(windows, vec![("24h", 1), ("7d", 2), ("30d", 2)]);
+
+            let usd = MarketSummary::from_trades(&history, Market::from_pair("btc_usd"));
+            assert_eq!(usd.len(), 1);
+            assert_eq!(usd[0].all_time.num_trades, 1);
+        }
+    }
+}
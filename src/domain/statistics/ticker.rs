@@ -12,8 +12,6 @@ pub struct Ticker {
     pub sell: Option<NumberWithPrecision>,
 }
 
-#[cfg(feature = "statistics")]
-pub use inner::*;
 #[cfg(feature = "statistics")]
 mod inner {
     use super::Ticker;
@@ -55,7 +53,7 @@ mod inner {
 
             let earliest = SystemTime::now() - Duration::from_secs(24 * 60 * 60);
             while next.timestamp >= earliest {
-                if let Some(mut ticker) = tickers.get_mut(&next.market.pair) {
+                if let Some(ticker) = tickers.get_mut(&next.market.pair) {
                     ticker.last = Some(ticker.last.unwrap_or(next.price));
                     ticker.high =
                         Some(ticker.high.map(|h| h.max(next.price)).unwrap_or(next.price));
@@ -103,7 +101,7 @@ mod inner {
             offers: impl Iterator<Item = &'a OpenOffer>,
         ) -> Vec<Ticker> {
             for offer in offers {
-                if let Some(mut ticker) = tickers.get_mut(&offer.market.pair) {
+                if let Some(ticker) = tickers.get_mut(&offer.market.pair) {
                     match (offer.direction, ticker.buy, ticker.sell) {
                         (OfferDirection::Buy, _, None) => ticker.sell = Some(offer.display_price),
                         (OfferDirection::Sell, None, _) => ticker.buy = Some(offer.display_price),
@@ -116,7 +114,7 @@ mod inner {
                     }
                 }
             }
-            let mut ret: Vec<Ticker> = tickers.into_iter().map(|(_, ticker)| ticker).collect();
+            let mut ret: Vec<Ticker> = tickers.into_values().collect();
             ret.sort_unstable_by(|a, b| a.market.pair.cmp(&b.market.pair));
             ret
         }
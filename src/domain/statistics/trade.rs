@@ -6,7 +6,7 @@ use crate::{
         offer::{OfferDirection, OfferId},
     },
 };
-use std::time::SystemTime;
+use std::{cmp::Ordering, time::SystemTime};
 
 #[derive(Clone)]
 pub struct Trade {
@@ -74,14 +74,31 @@ impl TradeHistory {
         }
     }
     pub(super) fn insert_all(&mut self, trades: impl IntoIterator<Item = Trade>) {
-        self.inner.extend(trades.into_iter());
-        self.inner
-            .sort_unstable_by(|a, b| a.timestamp.cmp(&b.timestamp));
+        self.inner.extend(trades);
+        self.inner.sort_unstable_by_key(|t| t.timestamp);
+    }
+    pub(super) fn prune(&mut self, max_trades: usize) -> Vec<Trade> {
+        let excess = self.inner.len().saturating_sub(max_trades);
+        self.inner.drain(..excess).collect()
     }
     pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Trade> {
         self.inner.iter()
     }
     pub fn first_trade_time(&self) -> Option<SystemTime> {
-        self.inner.get(0).map(|t| t.timestamp)
+        self.inner.first().map(|t| t.timestamp)
+    }
+    /// The trades from `time` on.
+    pub fn since(&self, time: SystemTime) -> impl Iterator<Item = &Trade> {
+        let start = self
+            .inner
+            .binary_search_by(|trade| {
+                if trade.timestamp < time {
+                    Ordering::Less
+                } else {
+                    Ordering::Greater
+                }
+            })
+            .unwrap_or_else(|idx| idx);
+        self.inner[start..].iter()
     }
 }
@@ -7,8 +7,6 @@ pub struct Volume {
     pub num_trades: u32,
 }
 
-#[cfg(feature = "statistics")]
-pub use inner::*;
 #[cfg(feature = "statistics")]
 mod inner {
     use super::*;
@@ -0,0 +1,93 @@
+use crate::bisq::payload::SupportType;
+use std::{fmt, str::FromStr, time::SystemTime};
+
+/// Who a chat of a trade is with, each one is a separate conversation in
+/// bisq: the trading peer, or the mediator and refund agent of a dispute.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChatParty {
+    Peer,
+    Mediator,
+    RefundAgent,
+}
+impl ChatParty {
+    pub fn support_type(self) -> SupportType {
+        match self {
+            ChatParty::Peer => SupportType::Trade,
+            ChatParty::Mediator => SupportType::Mediation,
+            ChatParty::RefundAgent => SupportType::Refund,
+        }
+    }
+
+    pub fn from_support_type(support_type: SupportType) -> Option<Self> {
+        match support_type {
+            SupportType::Trade => Some(ChatParty::Peer),
+            SupportType::Mediation => Some(ChatParty::Mediator),
+            SupportType::Refund => Some(ChatParty::RefundAgent),
+            SupportType::Arbitration => None,
+        }
+    }
+}
+impl fmt::Display for ChatParty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChatParty::Peer => write!(f, "peer"),
+            ChatParty::Mediator => write!(f, "mediator"),
+            ChatParty::RefundAgent => write!(f, "refund_agent"),
+        }
+    }
+}
+impl FromStr for ChatParty {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "peer" => Ok(ChatParty::Peer),
+            "mediator" => Ok(ChatParty::Mediator),
+            "refund_agent" => Ok(ChatParty::RefundAgent),
+            _ => Err(format!("Unknown chat party '{}'", s)),
+        }
+    }
+}
+
+/// How far a message we sent got.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChatDelivery {
+    Sending,
+    /// Sent directly to the receiver
+    Arrived,
+    /// The receiver was offline, the message waits in the p2p network
+    StoredInMailbox,
+    /// The receiver confirmed it with an `AckMessage`
+    Acknowledged,
+    Failed,
+}
+impl fmt::Display for ChatDelivery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+impl FromStr for ChatDelivery {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Sending" => Ok(ChatDelivery::Sending),
+            "Arrived" => Ok(ChatDelivery::Arrived),
+            "StoredInMailbox" => Ok(ChatDelivery::StoredInMailbox),
+            "Acknowledged" => Ok(ChatDelivery::Acknowledged),
+            "Failed" => Ok(ChatDelivery::Failed),
+            _ => Err(format!("Unknown chat delivery '{}'", s)),
+        }
+    }
+}
+
+/// A message of a trade chat, `uid` is the one of bisq's `ChatMessage`.
+#[derive(Clone, Debug)]
+pub struct ChatLine {
+    pub uid: String,
+    pub party: ChatParty,
+    /// Whether we wrote it, otherwise `party` did
+    pub sent: bool,
+    pub text: String,
+    pub date: SystemTime,
+    /// Only tracked for the messages we sent
+    pub delivery: ChatDelivery,
+}
@@ -0,0 +1,181 @@
+use super::{Trade, TradeRole};
+use crate::domain::{
+    amount::NumberWithPrecision,
+    format::{self, csv_field, Locale, BSQ_PRECISION, BTC_PRECISION},
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::time::SystemTime;
+
+const CSV_COLUMNS: [&str; 18] = [
+    "id",
+    "created_at",
+    "completed_at",
+    "market",
+    "role",
+    "direction",
+    "amount",
+    "price",
+    "payment_method",
+    "state",
+    "peer",
+    "fee",
+    "fee_currency",
+    "fee_tx_id",
+    "deposit_tx_id",
+    "payout_tx_id",
+    "delayed_payout_tx_id",
+    "error",
+];
+
+/// When the trade protocol ended, `None` while the trade is still running.
+pub fn completed_at(trade: &Trade) -> Option<SystemTime> {
+    if trade.state().is_final() {
+        Some(trade.state_changed_at())
+    } else {
+        None
+    }
+}
+
+/// The trade fee we paid and its currency. The maker's fee is part of its
+/// offer and may have been paid in BSQ.
+pub fn trade_fee(trade: &Trade) -> Option<(String, &'static str)> {
+    match trade.role {
+        TradeRole::Maker if trade.offer.payload.is_currency_for_maker_fee_btc => Some((
+            format::btc(
+                NumberWithPrecision::new(trade.offer.payload.maker_fee as u64, BTC_PRECISION),
+                Locale::RAW,
+            ),
+            "BTC",
+        )),
+        TradeRole::Maker => Some((
+            format::bsq(
+                NumberWithPrecision::new(trade.offer.payload.maker_fee as u64, BSQ_PRECISION),
+                Locale::RAW,
+            ),
+            "BSQ",
+        )),
+        TradeRole::Taker => trade.taker_fee.map(|fee| {
+            (
+                format::btc(NumberWithPrecision::new(fee, BTC_PRECISION), Locale::RAW),
+                "BTC",
+            )
+        }),
+    }
+}
+
+/// The tx that paid our trade fee.
+pub fn fee_tx_id(trade: &Trade) -> Option<&str> {
+    match trade.role {
+        TradeRole::Maker => Some(trade.offer.offer_fee_tx_id.as_str()),
+        TradeRole::Taker => trade.taker_fee_tx_id.as_deref(),
+    }
+}
+
+/// Renders the trades as CSV for accounting, one line per trade with
+/// RFC 3339 timestamps in UTC.
+pub fn csv<'a>(trades: impl Iterator<Item = &'a Trade>) -> String {
+    let mut ret = CSV_COLUMNS.join(",");
+    ret.push_str("\r\n");
+    for trade in trades {
+        let (fee, fee_currency) = trade_fee(trade).unwrap_or_default();
+        let fields = vec![
+            trade.id().clone().into(),
+            timestamp(trade.created_at),
+            completed_at(trade).map(timestamp).unwrap_or_default(),
+            trade.offer.market.pair.clone(),
+            trade.role.to_string(),
+            format!("{:?}", trade.direction()).to_uppercase(),
+            format::btc(trade.amount, Locale::RAW),
+            format::price(trade.price, trade.offer.market, Locale::RAW),
+            trade.offer.payment_method.to_string(),
+            trade.state().to_string(),
+            trade.peer.to_string(),
+            fee,
+            fee_currency.to_string(),
+            fee_tx_id(trade).unwrap_or_default().to_string(),
+            trade.deposit_tx_id.clone().unwrap_or_default(),
+            trade.payout_tx_id.clone().unwrap_or_default(),
+            trade.delayed_payout_tx_id().unwrap_or_default(),
+            trade.error().unwrap_or_default().to_string(),
+        ];
+        let fields: Vec<_> = fields.iter().map(|field| csv_field(field)).collect();
+        ret.push_str(&fields.join(","));
+        ret.push_str("\r\n");
+    }
+    ret
+}
+
+fn timestamp(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bisq::{
+            payload::{OfferPayload, PubKeyRing},
+            SequencedMessageHash,
+        },
+        domain::{
+            amount::BtcAmount,
+            market::Market,
+            offer::{OfferAmount, OfferDirection, OfferPrice, OpenOffer},
+            trade::TradeState,
+        },
+        prelude::{sha256, Hash},
+    };
+    use std::{
+        sync::Arc,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    #[test]
+    fn csv_export() {
+        let payload = OfferPayload {
+            id: "offer".into(),
+            amount: 1_000_000,
+            min_amount: 1_000_000,
+            payment_method_id: "SEPA".into(),
+            ..Default::default()
+        };
+        let offer = OpenOffer::new(
+            SequencedMessageHash::new(sha256::Hash::hash(&[])),
+            Market::from_pair("btc_eur").unwrap(),
+            "offer".to_string().into(),
+            OfferDirection::Buy,
+            OfferPrice::Fixed(NumberWithPrecision::new(90_000_000, 4)),
+            OfferAmount {
+                total: BtcAmount::from_sats(1_000_000),
+                min: BtcAmount::from_sats(1_000_000),
+            },
+            "SEPA".into(),
+            "fee".into(),
+            UNIX_EPOCH,
+            0.into(),
+            Arc::new(payload),
+        );
+        let mut trade = Trade::new(
+            TradeRole::Taker,
+            offer,
+            NumberWithPrecision::new(1_000_000, BTC_PRECISION),
+            NumberWithPrecision::new(90_000_000, 4),
+            "peer.onion:9999".parse().unwrap(),
+            PubKeyRing::default(),
+        );
+        trade.created_at = UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+        trade.taker_fee_tx_id = Some("taker_fee".into());
+        trade.taker_fee = Some(5_000);
+        assert_eq!(completed_at(&trade), None);
+        trade.fail("Peer said \"no\", sorry".into());
+        assert_eq!(trade.state(), TradeState::Failed);
+
+        let csv = csv(vec![&trade].into_iter());
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].split(',').count(), CSV_COLUMNS.len());
+        assert!(lines[1].starts_with("offer,2017-07-14T02:40:00Z,20"));
+        assert!(lines[1].contains(",btc_eur,Taker,SELL,0.01000000,9000.0000,SEPA,Failed,"));
+        assert!(lines[1].contains(",0.00005000,BTC,taker_fee,,,,\"Peer said \"\"no\"\", sorry\""));
+    }
+}
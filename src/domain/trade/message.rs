@@ -0,0 +1,112 @@
+use super::{ChatLine, ChatParty, Trade};
+use crate::{
+    bisq::payload::AvailabilityResult,
+    domain::{
+        amount::NumberWithPrecision,
+        fees::FeeRate,
+        market::Market,
+        offer::{OfferAmount, OfferDirection, OfferId, OfferPrice, OpenOffer},
+    },
+    prelude::Message,
+};
+
+/// Creates an offer for one of our payment accounts, pays its maker fee and
+/// publishes it in the p2p network. The direction is the one of the BTC side.
+pub struct CreateOffer {
+    pub market: &'static Market,
+    pub direction: OfferDirection,
+    pub price: OfferPrice,
+    pub amount: OfferAmount,
+    pub payment_account_id: String,
+}
+impl Message for CreateOffer {
+    type Result = Result<OpenOffer, String>;
+}
+
+/// Withdraws one of our offers from the p2p network, unless it was taken.
+pub struct RemoveMyOffer(pub OfferId);
+impl Message for RemoveMyOffer {
+    type Result = Result<(), String>;
+}
+
+/// Takes `amount` of the offer, or its full amount when `None`.
+pub struct TakeOffer {
+    pub offer_id: OfferId,
+    pub amount: Option<NumberWithPrecision>,
+}
+impl Message for TakeOffer {
+    type Result = Result<Trade, String>;
+}
+
+/// Asks the maker whether the offer can still be taken, without taking
+/// it. Our own offers are checked without asking anyone.
+pub struct CheckAvailability(pub OfferId);
+impl Message for CheckAvailability {
+    type Result = Result<AvailabilityResult, String>;
+}
+
+pub struct GetTrades;
+impl Message for GetTrades {
+    type Result = Vec<Trade>;
+}
+
+/// Confirms the payment of a trade: as buyer that it was started, as
+/// seller that it arrived, which releases the payout.
+pub struct ConfirmPayment {
+    pub trade_id: OfferId,
+}
+impl Message for ConfirmPayment {
+    type Result = Result<Trade, String>;
+}
+
+/// Accepts the payout the mediator proposed for a trade.
+pub struct AcceptMediation {
+    pub trade_id: OfferId,
+}
+impl Message for AcceptMediation {
+    type Result = Result<Trade, String>;
+}
+
+/// Publishes the delayed payout tx of a disputed trade once its lock time
+/// is reached, so the refund agent can take over.
+pub struct PublishDelayedPayout {
+    pub trade_id: OfferId,
+}
+impl Message for PublishDelayedPayout {
+    type Result = Result<Trade, String>;
+}
+
+/// Sends a chat message to the peer or a dispute agent of a trade. When
+/// they can't be reached it is stored in their mailbox in the p2p network.
+pub struct SendChatMessage {
+    pub trade_id: OfferId,
+    pub party: ChatParty,
+    pub text: String,
+}
+impl Message for SendChatMessage {
+    type Result = Result<ChatLine, String>;
+}
+
+pub struct WalletInfo {
+    pub balance: NumberWithPrecision,
+    pub receive_address: String,
+}
+
+/// Fails when no wallet is configured.
+pub struct GetWallet;
+impl Message for GetWallet {
+    type Result = Result<WalletInfo, String>;
+}
+
+pub struct FeeInfo {
+    /// The latest estimate of bisq's fee service
+    pub service: Option<FeeRate>,
+    /// The rate in sat/vB the wallet builds trade txs with, unknown
+    /// without a wallet
+    pub trade: Option<u64>,
+}
+
+pub struct GetFees;
+impl Message for GetFees {
+    type Result = Result<FeeInfo, String>;
+}
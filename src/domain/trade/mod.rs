@@ -0,0 +1,10 @@
+mod chat;
+mod state;
+
+pub mod history;
+pub mod message;
+pub mod protocol;
+pub mod wallet;
+
+pub use chat::{ChatDelivery, ChatLine, ChatParty};
+pub use state::{MediatedPayout, Trade, TradeRole, TradeState};
@@ -0,0 +1,73 @@
+use crate::bisq::constants::{Capabilities, Capability};
+
+/// The trade protocol risq implements, bisq's `Version.TRADE_PROTOCOL_VERSION`
+/// since the delayed payout tx was introduced.
+pub const TRADE_PROTOCOL_VERSION: i32 = 2;
+/// The bisq release of that protocol, announced in our offers.
+pub const BISQ_VERSION: &str = "1.3.4";
+/// Without them the peer's client can't settle a dispute of this protocol.
+const MANDATORY_CAPABILITIES: [Capability; 2] = [Capability::Mediation, Capability::RefundAgent];
+
+/// The variants of the trade protocol, depending on what the bisq client
+/// of the peer supports.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProtocolVariant {
+    /// Mediation and refund agent, the buyer's account isn't signed
+    Unsigned,
+    /// The seller also signs the account age witness of the buyer
+    AccountSigning,
+}
+impl ProtocolVariant {
+    /// Picks the variant for an offer of `protocol_version` and the
+    /// capabilities the peer sent in the availability exchange, refusing
+    /// peers we can't trade with.
+    pub fn negotiate(protocol_version: i32, capabilities: &[i32]) -> Result<Self, String> {
+        if protocol_version != TRADE_PROTOCOL_VERSION {
+            return Err(format!(
+                "Trade protocol version {} is not supported, only version {}",
+                protocol_version, TRADE_PROTOCOL_VERSION
+            ));
+        }
+        let capabilities = Capabilities::from(capabilities);
+        let has = |capability: Capability| capabilities.contains(capability);
+        let missing: Vec<_> = MANDATORY_CAPABILITIES
+            .iter()
+            .filter(|capability| !has(**capability))
+            .map(|capability| format!("{:?}", capability))
+            .collect();
+        if !missing.is_empty() {
+            return Err(format!(
+                "Peer's client is too old, it lacks {}",
+                missing.join(", ")
+            ));
+        }
+        if has(Capability::SignedAccountAgeWitness) {
+            Ok(ProtocolVariant::AccountSigning)
+        } else {
+            Ok(ProtocolVariant::Unsigned)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bisq::constants::LOCAL_CAPABILITIES;
+
+    #[test]
+    fn negotiates_variant() {
+        assert_eq!(
+            ProtocolVariant::negotiate(2, &LOCAL_CAPABILITIES),
+            Ok(ProtocolVariant::AccountSigning)
+        );
+        assert_eq!(
+            ProtocolVariant::negotiate(2, &[12, 13]),
+            Ok(ProtocolVariant::Unsigned)
+        );
+        assert_eq!(
+            ProtocolVariant::negotiate(2, &[11, 12]),
+            Err("Peer's client is too old, it lacks RefundAgent".into())
+        );
+        assert!(ProtocolVariant::negotiate(1, &LOCAL_CAPABILITIES).is_err());
+    }
+}
@@ -0,0 +1,423 @@
+use crate::{
+    bisq::{
+        payload::{PaymentAccountPayload, PubKeyRing},
+        NodeAddress,
+    },
+    domain::{
+        amount::NumberWithPrecision,
+        offer::{OfferDirection, OfferId, OpenOffer},
+        trade::{protocol::ProtocolVariant, ChatLine},
+    },
+};
+use bitcoin::{consensus, Transaction};
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+/// How long we wait for the peer (or our wallet) before a trade that
+/// hasn't locked any funds yet is given up.
+const PROTOCOL_TIMEOUT: Duration = Duration::from_secs(180);
+/// Once the deposit is published the trade has to complete within this
+/// period, otherwise it goes to dispute. This is the longest trade period
+/// of bisq's payment methods.
+const TRADE_PERIOD: Duration = Duration::from_secs(8 * 24 * 60 * 60);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TradeRole {
+    Maker,
+    Taker,
+}
+impl fmt::Display for TradeRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+impl FromStr for TradeRole {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Maker" => Ok(TradeRole::Maker),
+            "Taker" => Ok(TradeRole::Taker),
+            _ => Err(format!("Unknown trade role '{}'", s)),
+        }
+    }
+}
+
+/// Steps of the trade protocol, named after the bisq trade states they correspond to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TradeState {
+    /// Taker: waiting for the maker to confirm that the offer is still available.
+    /// Maker: the taker's inputs arrived and our inputs are being reserved
+    Preparation,
+    /// Taker fee paid and inputs for the deposit tx sent to the maker
+    TakerPublishedTakerFeeTx,
+    /// The maker prepared the deposit tx with its inputs
+    TakerReceivedPublishDepositTxRequest,
+    /// Our inputs of the deposit tx are signed and sent to the maker
+    TakerSignedDepositTx,
+    /// The delayed payout tx is signed and sent back to the maker
+    TakerSignedDelayedPayoutTx,
+    /// The deposit tx with our inputs was sent to the taker for signing
+    MakerSentPublishDepositTxRequest,
+    /// The taker signed the deposit tx and was asked to sign the delayed payout tx
+    MakerSentDelayedPayoutTxSignatureRequest,
+    /// The maker published the deposit tx
+    DepositPublished,
+    /// The buyer started the payment and sent its signature of the payout tx
+    PaymentStarted,
+    /// Given up before any funds were locked
+    Failed,
+    /// Something went wrong after the deposit was published, the funds
+    /// can only be released with the help of a mediator
+    Disputed,
+    /// The deposit was paid out, the trade is complete
+    PayoutPublished,
+    /// The deposit went to the donation address, the refund agent pays
+    /// the traders out of the DAO's funds
+    DelayedPayoutPublished,
+}
+impl TradeState {
+    const ALL: [TradeState; 13] = [
+        TradeState::Preparation,
+        TradeState::TakerPublishedTakerFeeTx,
+        TradeState::TakerReceivedPublishDepositTxRequest,
+        TradeState::TakerSignedDepositTx,
+        TradeState::TakerSignedDelayedPayoutTx,
+        TradeState::MakerSentPublishDepositTxRequest,
+        TradeState::MakerSentDelayedPayoutTxSignatureRequest,
+        TradeState::DepositPublished,
+        TradeState::PaymentStarted,
+        TradeState::Failed,
+        TradeState::Disputed,
+        TradeState::PayoutPublished,
+        TradeState::DelayedPayoutPublished,
+    ];
+
+    fn can_advance_to(self, next: TradeState) -> bool {
+        use TradeState::*;
+        match (self, next) {
+            (DepositPublished, PaymentStarted)
+            | (DepositPublished, Disputed)
+            | (DepositPublished, PayoutPublished)
+            | (DepositPublished, DelayedPayoutPublished)
+            | (PaymentStarted, Disputed)
+            | (PaymentStarted, PayoutPublished)
+            | (PaymentStarted, DelayedPayoutPublished)
+            | (Disputed, PayoutPublished)
+            | (Disputed, DelayedPayoutPublished) => true,
+            (Failed, _) | (Disputed, _) | (PayoutPublished, _) | (DelayedPayoutPublished, _) => {
+                false
+            }
+            (DepositPublished, _) | (PaymentStarted, _) => false,
+            (_, Failed) => true,
+            (Preparation, TakerPublishedTakerFeeTx)
+            | (TakerPublishedTakerFeeTx, TakerReceivedPublishDepositTxRequest)
+            | (TakerReceivedPublishDepositTxRequest, TakerSignedDepositTx)
+            | (TakerSignedDepositTx, TakerSignedDelayedPayoutTx)
+            | (TakerSignedDelayedPayoutTx, DepositPublished)
+            | (Preparation, MakerSentPublishDepositTxRequest)
+            | (MakerSentPublishDepositTxRequest, MakerSentDelayedPayoutTxSignatureRequest)
+            | (MakerSentDelayedPayoutTxSignatureRequest, DepositPublished) => true,
+            _ => false,
+        }
+    }
+
+    pub fn funds_locked(self) -> bool {
+        self == TradeState::DepositPublished || self == TradeState::PaymentStarted
+    }
+
+    /// The trade protocol is over, nothing will change anymore.
+    pub fn is_final(self) -> bool {
+        self == TradeState::Failed
+            || self == TradeState::PayoutPublished
+            || self == TradeState::DelayedPayoutPublished
+    }
+
+    fn timeout(self) -> Option<Duration> {
+        match self {
+            TradeState::Failed
+            | TradeState::Disputed
+            | TradeState::PayoutPublished
+            | TradeState::DelayedPayoutPublished => None,
+            TradeState::DepositPublished | TradeState::PaymentStarted => Some(TRADE_PERIOD),
+            _ => Some(PROTOCOL_TIMEOUT),
+        }
+    }
+}
+impl fmt::Display for TradeState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+impl FromStr for TradeState {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TradeState::ALL
+            .iter()
+            .find(|state| state.to_string() == s)
+            .cloned()
+            .ok_or_else(|| format!("Unknown trade state '{}'", s))
+    }
+}
+
+/// The split of the deposit a mediator proposed for a disputed trade, or
+/// that the refund agent decided on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MediatedPayout {
+    pub buyer_payout: u64,
+    pub seller_payout: u64,
+}
+
+#[derive(Clone)]
+pub struct Trade {
+    pub role: TradeRole,
+    pub offer: OpenOffer,
+    pub amount: NumberWithPrecision,
+    pub price: NumberWithPrecision,
+    pub peer: NodeAddress,
+    pub peer_pub_key_ring: PubKeyRing,
+    /// What the peer's client announced in the availability exchange
+    pub peer_capabilities: Vec<i32>,
+    pub mediator: Option<NodeAddress>,
+    pub refund_agent: Option<NodeAddress>,
+    /// Our account that sends or receives the counter currency
+    pub payment_account_id: Option<String>,
+    pub peer_payment_account: Option<PaymentAccountPayload>,
+    pub taker_fee_tx_id: Option<String>,
+    /// The fee we paid as taker, in satoshis
+    pub taker_fee: Option<u64>,
+    pub deposit_tx_id: Option<String>,
+    pub payout_tx_id: Option<String>,
+    /// Our key of the 2-of-2 deposit output
+    pub multi_sig_pub_key: Option<Vec<u8>>,
+    pub payout_address: Option<String>,
+    pub peer_multi_sig_pub_key: Option<Vec<u8>>,
+    pub peer_payout_address: Option<String>,
+    /// The deposit tx spent by the payout tx, signed by the taker until
+    /// the maker published it
+    pub deposit_tx: Option<Vec<u8>>,
+    /// Block height from which the delayed payout tx can be published
+    pub lock_time: Option<u32>,
+    /// Finalized by the maker, the taker keeps the tx it signed until the
+    /// maker sends the final one
+    pub delayed_payout_tx: Option<Vec<u8>>,
+    /// Kept by the seller until it confirms that the buyer's payment arrived
+    pub peer_payout_signature: Option<Vec<u8>>,
+    pub mediated_payout: Option<MediatedPayout>,
+    /// Our signature of the mediated payout, set once we accepted it
+    pub mediated_payout_signature: Option<Vec<u8>>,
+    pub peer_mediated_payout_signature: Option<Vec<u8>>,
+    /// What the refund agent pays the traders after the delayed payout
+    pub refund_payout: Option<MediatedPayout>,
+    /// Kept by the taker until the deposit tx is signed
+    pub prepared_deposit_tx: Option<Vec<u8>>,
+    /// The chats with the peer and the dispute agents, in the order the
+    /// messages arrived
+    pub chat: Vec<ChatLine>,
+    pub created_at: SystemTime,
+
+    state: TradeState,
+    state_changed_at: SystemTime,
+    error: Option<String>,
+}
+impl Trade {
+    pub fn new(
+        role: TradeRole,
+        offer: OpenOffer,
+        amount: NumberWithPrecision,
+        price: NumberWithPrecision,
+        peer: NodeAddress,
+        peer_pub_key_ring: PubKeyRing,
+    ) -> Self {
+        Self {
+            role,
+            offer,
+            amount,
+            price,
+            peer,
+            peer_pub_key_ring,
+            peer_capabilities: Vec::new(),
+            mediator: None,
+            refund_agent: None,
+            payment_account_id: None,
+            peer_payment_account: None,
+            taker_fee_tx_id: None,
+            taker_fee: None,
+            deposit_tx_id: None,
+            payout_tx_id: None,
+            multi_sig_pub_key: None,
+            payout_address: None,
+            peer_multi_sig_pub_key: None,
+            peer_payout_address: None,
+            deposit_tx: None,
+            lock_time: None,
+            delayed_payout_tx: None,
+            peer_payout_signature: None,
+            mediated_payout: None,
+            mediated_payout_signature: None,
+            peer_mediated_payout_signature: None,
+            refund_payout: None,
+            prepared_deposit_tx: None,
+            chat: Vec::new(),
+            created_at: SystemTime::now(),
+            state: TradeState::Preparation,
+            state_changed_at: SystemTime::now(),
+            error: None,
+        }
+    }
+
+    /// Puts a persisted trade back into the state it was stored in.
+    pub fn restore(
+        mut self,
+        state: TradeState,
+        state_changed_at: SystemTime,
+        error: Option<String>,
+    ) -> Self {
+        self.state = state;
+        self.state_changed_at = state_changed_at;
+        self.error = error;
+        self
+    }
+
+    /// Bisq identifies a trade by the id of the offer that was taken.
+    pub fn id(&self) -> &OfferId {
+        &self.offer.id
+    }
+
+    /// The side we are on, the maker trades in the direction of its offer.
+    pub fn direction(&self) -> OfferDirection {
+        match self.role {
+            TradeRole::Maker => self.offer.direction,
+            TradeRole::Taker => self.offer.direction.oposite(),
+        }
+    }
+
+    pub fn protocol(&self) -> Result<ProtocolVariant, String> {
+        ProtocolVariant::negotiate(self.offer.payload.protocol_version, &self.peer_capabilities)
+    }
+
+    pub fn state(&self) -> TradeState {
+        self.state
+    }
+
+    pub fn state_changed_at(&self) -> SystemTime {
+        self.state_changed_at
+    }
+
+    pub fn delayed_payout_tx_id(&self) -> Option<String> {
+        self.delayed_payout_tx
+            .as_ref()
+            .and_then(|tx| consensus::deserialize::<Transaction>(tx).ok())
+            .map(|tx| tx.txid().to_string())
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    pub fn advance(&mut self, next: TradeState) -> Result<(), String> {
+        if !self.state.can_advance_to(next) {
+            return Err(format!(
+                "Trade {:?} can't move from {} to {}",
+                self.offer.id, self.state, next
+            ));
+        }
+        info!(
+            "Trade {:?} moved from {} to {}",
+            self.offer.id, self.state, next
+        );
+        self.state = next;
+        self.state_changed_at = SystemTime::now();
+        Ok(())
+    }
+
+    /// Gives up the trade, or opens a dispute once our funds are locked in the deposit tx.
+    pub fn fail(&mut self, error: String) {
+        let next = if self.state.funds_locked() {
+            TradeState::Disputed
+        } else {
+            TradeState::Failed
+        };
+        if self.state.can_advance_to(next) {
+            warn!("Trade {:?} moved to {}: {}", self.offer.id, next, error);
+            self.state = next;
+            self.state_changed_at = SystemTime::now();
+            self.error = Some(error);
+        }
+    }
+
+    /// Fails the trade if it stayed in its state for too long. Returns whether it did.
+    pub fn check_timeout(&mut self, now: SystemTime) -> bool {
+        let timeout = match self.state.timeout() {
+            Some(timeout) => timeout,
+            None => return false,
+        };
+        match now.duration_since(self.state_changed_at) {
+            Ok(waited) if waited > timeout => {
+                let error = if self.state.funds_locked() {
+                    "Trade period is over".to_string()
+                } else {
+                    format!("Timed out in state {}", self.state)
+                };
+                self.fail(error);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn taker_transitions() {
+        use TradeState::*;
+        let happy_path = [
+            Preparation,
+            TakerPublishedTakerFeeTx,
+            TakerReceivedPublishDepositTxRequest,
+            TakerSignedDepositTx,
+            TakerSignedDelayedPayoutTx,
+            DepositPublished,
+        ];
+        for step in happy_path.windows(2) {
+            assert!(step[0].can_advance_to(step[1]));
+            assert!(!step[1].can_advance_to(step[0]));
+            assert!(step[0].can_advance_to(Failed));
+        }
+        assert!(!Preparation.can_advance_to(TakerSignedDepositTx));
+        assert!(!TakerSignedDepositTx.can_advance_to(DepositPublished));
+        assert!(!DepositPublished.can_advance_to(Failed));
+        assert!(DepositPublished.can_advance_to(Disputed));
+        assert!(DepositPublished.can_advance_to(PaymentStarted));
+        assert!(PaymentStarted.can_advance_to(PayoutPublished));
+        assert!(!PaymentStarted.can_advance_to(Failed));
+        assert!(!PaymentStarted.can_advance_to(DepositPublished));
+        assert!(!Failed.can_advance_to(Preparation));
+        assert!(!Disputed.can_advance_to(DepositPublished));
+        assert!(Disputed.can_advance_to(PayoutPublished));
+        assert!(!PayoutPublished.can_advance_to(Disputed));
+        assert!(Disputed.can_advance_to(DelayedPayoutPublished));
+        assert!(!DelayedPayoutPublished.can_advance_to(PayoutPublished));
+        for state in TradeState::ALL.iter() {
+            assert_eq!(state.to_string().parse(), Ok(*state));
+        }
+    }
+
+    #[test]
+    fn maker_transitions() {
+        use TradeState::*;
+        assert!(Preparation.can_advance_to(MakerSentPublishDepositTxRequest));
+        assert!(MakerSentPublishDepositTxRequest
+            .can_advance_to(MakerSentDelayedPayoutTxSignatureRequest));
+        assert!(!MakerSentPublishDepositTxRequest.can_advance_to(DepositPublished));
+        assert!(MakerSentDelayedPayoutTxSignatureRequest.can_advance_to(DepositPublished));
+        assert!(MakerSentPublishDepositTxRequest.can_advance_to(Failed));
+        assert!(!MakerSentPublishDepositTxRequest.can_advance_to(TakerSignedDepositTx));
+        assert!(!TakerPublishedTakerFeeTx.can_advance_to(MakerSentPublishDepositTxRequest));
+    }
+}
@@ -0,0 +1,77 @@
+use super::Trade;
+use crate::{bisq::payload::RawTransactionInput, prelude::Future};
+
+pub type WalletFuture<T> = Box<dyn Future<Item = T, Error = String>>;
+
+/// What the taker contributes to the deposit transaction.
+pub struct TakerFunding {
+    pub taker_fee_tx_id: String,
+    pub taker_fee: u64,
+    pub tx_fee: u64,
+    pub inputs: Vec<RawTransactionInput>,
+    pub change_output_value: u64,
+    pub change_output_address: Option<String>,
+    pub multi_sig_pub_key: Vec<u8>,
+    pub payout_address: String,
+}
+
+/// What the maker paid to publish an offer.
+pub struct MakerFunding {
+    pub maker_fee_tx_id: String,
+    /// Mining fee of the trade txs at the current fee rate
+    pub tx_fee: u64,
+}
+
+/// What the maker contributes to the deposit transaction.
+pub struct MakerDeposit {
+    /// The deposit tx with the inputs of both traders, our inputs are not signed yet
+    pub prepared_deposit_tx: Vec<u8>,
+    pub inputs: Vec<RawTransactionInput>,
+    pub multi_sig_pub_key: Vec<u8>,
+    pub payout_address: String,
+}
+
+/// The bitcoin wallet that funds and signs the transactions of a trade.
+pub trait TradeWallet {
+    /// Publishes the taker fee tx and reserves inputs covering the trade
+    /// amount, security deposit and mining fee of the deposit tx.
+    fn fund_taker(&self, trade: &Trade) -> WalletFuture<TakerFunding>;
+
+    /// Publishes the maker fee tx of a new offer. The inputs of the deposit
+    /// are only reserved once the offer is taken.
+    fn pay_maker_fee(&self, maker_fee: u64) -> WalletFuture<MakerFunding>;
+
+    /// Signs our inputs of the deposit tx that the maker prepared.
+    fn sign_deposit_tx(&self, trade: &Trade, prepared_deposit_tx: &[u8]) -> WalletFuture<Vec<u8>>;
+
+    /// Verifies the taker fee tx and builds the deposit tx from the
+    /// taker's and our reserved inputs.
+    fn prepare_deposit_tx(&self, trade: &Trade, taker: &TakerFunding)
+        -> WalletFuture<MakerDeposit>;
+
+    /// Adds our signatures to the deposit tx the taker signed and
+    /// broadcasts it. Returns the published tx.
+    fn publish_deposit_tx(&self, trade: &Trade, signed_deposit_tx: &[u8]) -> WalletFuture<Vec<u8>>;
+
+    /// Signs a tx that spends the deposit, like the payout or the delayed
+    /// payout tx, with our multisig key and returns the DER encoded signature.
+    fn sign_payout_tx(&self, trade: &Trade, payout_tx: &[u8]) -> WalletFuture<Vec<u8>>;
+
+    /// Broadcasts a fully signed tx and returns its id.
+    fn publish_tx(&self, tx: &[u8]) -> WalletFuture<String>;
+
+    /// Height of the best block the wallet knows of.
+    fn block_height(&self) -> WalletFuture<u32>;
+
+    /// Looks for a tx that spends the deposit and returns its id.
+    fn find_payout(&self, trade: &Trade) -> WalletFuture<Option<String>>;
+
+    /// Mining fee rate in sat/vB of the trade txs.
+    fn fee_rate(&self) -> WalletFuture<u64>;
+
+    /// Satoshis that can be used for new trades.
+    fn balance(&self) -> WalletFuture<u64>;
+
+    /// A fresh address to fund the wallet.
+    fn receive_address(&self) -> WalletFuture<String>;
+}
@@ -0,0 +1,75 @@
+const SATOSHIS_PER_BTC: u64 = 100_000_000;
+const SATOSHIS_PER_BSQ: u64 = 100;
+
+// Genesis values of bisq's `Param`s, per BTC of the trade amount. The DAO
+// can vote to change them, which isn't tracked yet.
+const MAKER_FEE_BTC: u64 = 100_000;
+const TAKER_FEE_BTC: u64 = 300_000;
+pub const MIN_MAKER_FEE_BTC: u64 = 5_000;
+const MIN_TAKER_FEE_BTC: u64 = 5_000;
+const MAKER_FEE_BSQ: u64 = 50;
+const TAKER_FEE_BSQ: u64 = 150;
+const MIN_MAKER_FEE_BSQ: u64 = 5;
+const MIN_TAKER_FEE_BSQ: u64 = 15;
+
+/// Trade fees are paid in BTC or, cheaper, in BSQ.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FeeCurrency {
+    Btc,
+    Bsq,
+}
+
+/// The fee the maker pays for an offer of `amount` satoshis, in satoshis
+/// of `currency`. Like bisq's `CoinUtil.getMakerFee`.
+pub fn maker_fee(amount: u64, currency: FeeCurrency) -> u64 {
+    match currency {
+        FeeCurrency::Btc => fee(amount, MAKER_FEE_BTC, MIN_MAKER_FEE_BTC),
+        FeeCurrency::Bsq => fee(amount, MAKER_FEE_BSQ, MIN_MAKER_FEE_BSQ),
+    }
+}
+
+/// The fee the taker pays for a trade of `amount` satoshis, in satoshis of
+/// `currency`. Like bisq's `CoinUtil.getTakerFee`.
+pub fn taker_fee(amount: u64, currency: FeeCurrency) -> u64 {
+    match currency {
+        FeeCurrency::Btc => fee(amount, TAKER_FEE_BTC, MIN_TAKER_FEE_BTC),
+        FeeCurrency::Bsq => fee(amount, TAKER_FEE_BSQ, MIN_TAKER_FEE_BSQ),
+    }
+}
+
+// Bisq's `CoinUtil.getFeePerBtc`, rounded to the nearest satoshi
+fn fee(amount: u64, per_btc: u64, min: u64) -> u64 {
+    let sats_per_btc = u128::from(SATOSHIS_PER_BTC);
+    let fee = (u128::from(amount) * u128::from(per_btc) + sats_per_btc / 2) / sats_per_btc;
+    (fee as u64).max(min)
+}
+
+/// Values a BSQ fee in satoshis at the BSQ market price, which the price
+/// nodes report in BTC per BSQ.
+pub fn bsq_fee_in_btc(bsq_fee: u64, btc_per_bsq: f64) -> u64 {
+    (bsq_fee as f64 / SATOSHIS_PER_BSQ as f64 * btc_per_bsq * SATOSHIS_PER_BTC as f64).round()
+        as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fees_of_amount() {
+        assert_eq!(maker_fee(100_000_000, FeeCurrency::Btc), 100_000);
+        assert_eq!(taker_fee(100_000_000, FeeCurrency::Btc), 300_000);
+        assert_eq!(maker_fee(100_000_000, FeeCurrency::Bsq), 50);
+        assert_eq!(taker_fee(100_000_000, FeeCurrency::Bsq), 150);
+        // 0.01 BTC pays the minimum fees
+        assert_eq!(maker_fee(1_000_000, FeeCurrency::Btc), MIN_MAKER_FEE_BTC);
+        assert_eq!(taker_fee(1_000_000, FeeCurrency::Bsq), MIN_TAKER_FEE_BSQ);
+        assert_eq!(taker_fee(12_345_678, FeeCurrency::Btc), 37_037);
+        assert_eq!(
+            taker_fee(21_000_000 * SATOSHIS_PER_BTC, FeeCurrency::Btc),
+            6_300_000_000_000
+        );
+        // 1.5 BSQ at 0.0001 BTC/BSQ
+        assert_eq!(bsq_fee_in_btc(150, 0.000_1), 15_000);
+    }
+}
@@ -3,13 +3,15 @@ use crate::{
         constants::{BaseCurrencyNetwork, LOCAL_CAPABILITIES},
         payload::*,
     },
+    domain::filter::FilterStore,
+    events::Events,
     p2p::{
-        dispatch::*, message::Direct, server, BootstrapState, Broadcaster, ConnectionId, Peers,
-        Status,
+        dispatch::*, message::Direct, server, transport, BanList, BootstrapState, Broadcaster,
+        ConnectionId, Peers, Status, DEFAULT_MAX_CONNECTIONS,
     },
     prelude::*,
 };
-use std::path::Path;
+use std::{path::Path, sync::Arc};
 
 #[derive(Clone)]
 struct SeedDataResponder(Addr<Broadcaster>);
@@ -53,17 +55,23 @@ pub fn run(server_port: u16, _fixtures: Option<&Path>) {
     let network = BaseCurrencyNetwork::BtcRegtest;
     let broadcaster = Broadcaster::start();
     let bootstrap_state = BootstrapState::init();
-    let p2p_status = Status::new(bootstrap_state.clone());
+    let p2p_status = Status::new(network, bootstrap_state.clone(), Events::default());
 
     let peers = Peers::start(
         network,
         broadcaster.clone(),
-        p2p_status,
+        p2p_status.clone(),
         SeedDataResponder(broadcaster),
+        Arc::new(transport::Direct),
+        DEFAULT_MAX_CONNECTIONS,
+        Vec::new(),
+        None,
+        FilterStore::new(network),
+        BanList::default(),
         None,
     );
 
-    server::start(server_port, peers, None, None);
+    server::start(server_port, peers, None, None, None, p2p_status);
 
     let _ = sys.run();
 }
@@ -8,6 +8,9 @@ use crate::prelude::{
 use prost::{DecodeError, EncodeError};
 use std::io;
 
+// The causes are only read through Debug
+#[allow(dead_code)]
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug)]
 pub enum Error {
     IoError(io::Error),
@@ -17,6 +20,8 @@ pub enum Error {
     MailboxError(MailboxError),
     SendMPSCError,
     ReceiveMPSCError,
+    /// A peer announced a message of this many bytes
+    MessageTooLarge(usize),
 }
 
 impl From<io::Error> for Error {
@@ -0,0 +1,266 @@
+use crate::{
+    bisq::NodeAddress,
+    domain::{
+        alert::Alert,
+        amount::NumberWithPrecision,
+        format::{self, Locale, BSQ_PRECISION, BTC_PRECISION},
+        offer::{compliance, OpenOffer},
+        trade::Trade,
+    },
+    metrics,
+    prelude::ToHex,
+};
+use serde::Serialize;
+use std::{
+    sync::{
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    time::UNIX_EPOCH,
+};
+
+/// Something that happened in the daemon, for local consumers like trading
+/// bots. Serialized as JSON with the kind of event under `event`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    OfferAdded {
+        id: String,
+        market: String,
+        direction: String,
+        price: String,
+        /// How far the price is above the market price, eg. `0.01` for 1%
+        premium: Option<f64>,
+        amount: String,
+        min_amount: String,
+        payment_method: String,
+        created_at: u64,
+        /// In `maker_fee_currency`, `BTC` or `BSQ`
+        maker_fee: String,
+        maker_fee_currency: String,
+        maker_fee_tx_id: String,
+        /// Onion address of the maker, eg. `abc.onion:9999`
+        maker: Option<String>,
+        /// Hex of the maker's account age witness hash
+        maker_age_witness_hash: Option<String>,
+    },
+    /// Republished by its maker, which keeps it from expiring
+    OfferRefreshed {
+        id: String,
+        market: String,
+    },
+    OfferRemoved {
+        id: String,
+        market: String,
+        /// `removed` by its maker, `expired`, `banned` by a filter or
+        /// `evicted` to stay within the memory budget
+        reason: String,
+    },
+    TradeChanged {
+        id: String,
+        market: String,
+        role: String,
+        state: String,
+        error: Option<String>,
+    },
+    /// A trade on the network as published in bisq's trade statistics
+    TradeStatistic {
+        offer_id: String,
+        market: String,
+        direction: String,
+        price: String,
+        amount: String,
+        volume: String,
+        payment_method: String,
+        traded_at: u64,
+    },
+    /// Signed by bisq's developers
+    Alert {
+        message: String,
+        version: String,
+        update_info: bool,
+    },
+    PeerConnected {
+        addr: String,
+    },
+    PeerDisconnected {
+        addr: String,
+    },
+}
+impl Event {
+    pub fn offer_added(offer: &OpenOffer, premium: Option<f64>) -> Self {
+        Event::OfferAdded {
+            id: offer.id.clone().into(),
+            market: offer.market.pair.clone(),
+            direction: format!("{:?}", offer.direction).to_uppercase(),
+            price: format::price(offer.display_price, offer.market, Locale::RAW),
+            premium,
+            amount: format::btc(offer.amount.total.number(), Locale::RAW),
+            min_amount: format::btc(offer.amount.min.number(), Locale::RAW),
+            payment_method: offer.payment_method.to_string(),
+            created_at: offer
+                .created_at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            maker_fee: if offer.payload.is_currency_for_maker_fee_btc {
+                format::btc(
+                    NumberWithPrecision::new(offer.payload.maker_fee as u64, BTC_PRECISION),
+                    Locale::RAW,
+                )
+            } else {
+                format::bsq(
+                    NumberWithPrecision::new(offer.payload.maker_fee as u64, BSQ_PRECISION),
+                    Locale::RAW,
+                )
+            },
+            maker_fee_currency: if offer.payload.is_currency_for_maker_fee_btc {
+                "BTC".into()
+            } else {
+                "BSQ".into()
+            },
+            maker_fee_tx_id: offer.payload.offer_fee_payment_tx_id.clone(),
+            maker: offer
+                .payload
+                .owner_node_address
+                .as_ref()
+                .map(NodeAddress::to_string),
+            maker_age_witness_hash: compliance::maker_witness_hash(offer).map(|hash| hash.to_hex()),
+        }
+    }
+
+    pub fn alert(alert: &Alert) -> Self {
+        Event::Alert {
+            message: alert.message.clone(),
+            version: alert.version.clone(),
+            update_info: alert.update_info,
+        }
+    }
+
+    pub fn offer_refreshed(offer: &OpenOffer) -> Self {
+        Event::OfferRefreshed {
+            id: offer.id.clone().into(),
+            market: offer.market.pair.clone(),
+        }
+    }
+
+    pub fn offer_removed(offer: &OpenOffer, reason: &str) -> Self {
+        Event::OfferRemoved {
+            id: offer.id.clone().into(),
+            market: offer.market.pair.clone(),
+            reason: reason.into(),
+        }
+    }
+
+    pub fn trade_changed(trade: &Trade) -> Self {
+        Event::TradeChanged {
+            id: trade.id().clone().into(),
+            market: trade.offer.market.pair.clone(),
+            role: trade.role.to_string(),
+            state: trade.state().to_string(),
+            error: trade.error().map(String::from),
+        }
+    }
+
+    #[cfg(feature = "statistics")]
+    pub fn trade_statistic(trade: &crate::domain::statistics::Trade) -> Self {
+        Event::TradeStatistic {
+            offer_id: trade.offer_id.clone().into(),
+            market: trade.market.pair.clone(),
+            direction: format!("{:?}", trade.direction).to_uppercase(),
+            price: format::price(trade.price, trade.market, Locale::RAW),
+            amount: format::btc(trade.amount, Locale::RAW),
+            volume: format::amount(trade.volume, trade.market.non_btc_side(), Locale::RAW),
+            payment_method: trade.payment_method_id.clone(),
+            traded_at: trade
+                .timestamp
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        }
+    }
+
+    pub fn peer_connected(addr: &NodeAddress) -> Self {
+        Event::PeerConnected {
+            addr: addr.to_string(),
+        }
+    }
+
+    pub fn peer_disconnected(addr: &NodeAddress) -> Self {
+        Event::PeerDisconnected {
+            addr: addr.to_string(),
+        }
+    }
+
+    /// Consumers subscribe to one or more of offers, trades, statistics,
+    /// alerts and peers.
+    pub fn topic(&self) -> &'static str {
+        match self {
+            Event::OfferAdded { .. }
+            | Event::OfferRefreshed { .. }
+            | Event::OfferRemoved { .. } => "offers",
+            Event::TradeChanged { .. } => "trades",
+            Event::TradeStatistic { .. } => "statistics",
+            Event::Alert { .. } => "alerts",
+            Event::PeerConnected { .. } | Event::PeerDisconnected { .. } => "peers",
+        }
+    }
+}
+
+/// Events a subscriber may fall behind before newer ones are dropped.
+pub const SUBSCRIBER_BACKLOG: usize = 10_000;
+
+/// Fans events out to the publishers of the daemon. Without subscribers
+/// publishing is a no-op.
+#[derive(Clone, Default)]
+pub struct Events {
+    subscribers: Arc<Mutex<Vec<SyncSender<Event>>>>,
+}
+impl Events {
+    pub fn subscribe(&self) -> Receiver<Event> {
+        self.subscribe_with_backlog(SUBSCRIBER_BACKLOG)
+    }
+
+    /// Events published while `backlog` ones are waiting for the subscriber
+    /// are dropped and counted in the metrics.
+    pub fn subscribe_with_backlog(&self, backlog: usize) -> Receiver<Event> {
+        let (sender, receiver) = mpsc::sync_channel(backlog);
+        self.subscribers
+            .lock()
+            .expect("Corrupted lock in events")
+            .push(sender);
+        receiver
+    }
+
+    pub fn publish(&self, event: Event) {
+        let mut subscribers = self.subscribers.lock().expect("Corrupted lock in events");
+        subscribers.retain(|subscriber| match subscriber.try_send(event.clone()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                metrics::event_dropped(event.topic());
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_events_beyond_the_backlog() {
+        let events = Events::default();
+        let slow = events.subscribe_with_backlog(1);
+        let gone = events.subscribe();
+        drop(gone);
+        for addr in &["a.onion:9999", "b.onion:9999"] {
+            events.publish(Event::PeerConnected {
+                addr: addr.to_string(),
+            });
+        }
+        assert_eq!(slow.try_iter().count(), 1);
+        assert_eq!(events.subscribers.lock().unwrap().len(), 1);
+    }
+}
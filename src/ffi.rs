@@ -0,0 +1,231 @@
+//! C ABI for applications that embed risq instead of running the daemon,
+//! declared in `include/risq.h`. Strings passed in are UTF-8, strings handed
+//! out must be released with `risq_string_free`.
+use crate::{
+    bisq::constants::BaseCurrencyNetwork,
+    events::Event,
+    node::{Node, NodeBuilder},
+};
+use std::{
+    ffi::{CStr, CString},
+    os::raw::{c_char, c_void},
+    path::PathBuf,
+    ptr,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, RecvTimeoutError},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// How often subscriptions check whether the node was stopped.
+const STOP_CHECK: Duration = Duration::from_millis(100);
+
+pub type RisqEventCallback = extern "C" fn(event: *const c_char, user_data: *mut c_void);
+
+pub struct RisqNode {
+    node: Node,
+    stopped: Arc<AtomicBool>,
+    subscriptions: Vec<JoinHandle<()>>,
+}
+
+/// Starts a node without tor when `tor_proxy_port` is 0. Returns NULL when
+/// the arguments are invalid or the node couldn't be started.
+///
+/// # Safety
+/// `network` (eg. "BtcMainnet") and `risq_home` must be NUL terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn risq_start(
+    network: *const c_char,
+    risq_home: *const c_char,
+    api_port: u16,
+    server_port: u16,
+    tor_proxy_port: u16,
+) -> *mut RisqNode {
+    let network = match to_str(network).and_then(|n| BaseCurrencyNetwork::from_str(n).ok()) {
+        Some(network) => network,
+        None => return ptr::null_mut(),
+    };
+    let risq_home = match to_str(risq_home) {
+        Some(risq_home) => PathBuf::from(risq_home),
+        None => return ptr::null_mut(),
+    };
+    let mut builder = NodeBuilder::new(network, risq_home)
+        .api_port(api_port)
+        .server_port(server_port);
+    if tor_proxy_port != 0 {
+        builder = builder.tor_proxy(tor_proxy_port);
+    }
+    match builder.start() {
+        Ok(node) => Box::into_raw(Box::new(RisqNode {
+            node,
+            stopped: Arc::new(AtomicBool::new(false)),
+            subscriptions: Vec::new(),
+        })),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// The open offers as JSON array, of one market (eg. "btc_eur") or of all
+/// markets when `market` is NULL. Returns NULL when the node is gone.
+///
+/// # Safety
+/// `node` must come from `risq_start` and not be stopped yet, `market` must
+/// be NULL or a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn risq_offers(node: *const RisqNode, market: *const c_char) -> *mut c_char {
+    let node = match node.as_ref() {
+        Some(node) => node,
+        None => return ptr::null_mut(),
+    };
+    let offers = match node.node.offers(to_str(market)) {
+        Some(offers) => offers,
+        None => return ptr::null_mut(),
+    };
+    match serde_json::to_string(&offers) {
+        Ok(json) => into_c_string(json),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Calls `callback` with every event of the node as JSON (the format of the
+/// zmq publisher) and `user_data`, from a thread of its own. The event string
+/// is only valid during the call. Returns 0 on success.
+///
+/// # Safety
+/// `node` must come from `risq_start` and not be stopped yet. `user_data`
+/// must stay valid until `risq_stop` returns.
+#[no_mangle]
+pub unsafe extern "C" fn risq_subscribe(
+    node: *mut RisqNode,
+    callback: RisqEventCallback,
+    user_data: *mut c_void,
+) -> i32 {
+    match node.as_mut() {
+        Some(node) => {
+            let subscription = subscribe(
+                node.node.events(),
+                callback,
+                UserData(user_data),
+                node.stopped.clone(),
+            );
+            node.subscriptions.push(subscription);
+            0
+        }
+        None => -1,
+    }
+}
+
+/// Stops the node, waits for it and its subscriptions and frees it.
+///
+/// # Safety
+/// `node` must come from `risq_start` and can't be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn risq_stop(node: *mut RisqNode) {
+    if node.is_null() {
+        return;
+    }
+    let RisqNode {
+        node,
+        stopped,
+        subscriptions,
+    } = *Box::from_raw(node);
+    stopped.store(true, Ordering::Relaxed);
+    for subscription in subscriptions {
+        let _ = subscription.join();
+    }
+    node.stop();
+}
+
+/// # Safety
+/// `s` must be NULL or a string returned by risq.
+#[no_mangle]
+pub unsafe extern "C" fn risq_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+struct UserData(*mut c_void);
+// The caller vouches for user_data being usable from another thread
+unsafe impl Send for UserData {}
+
+fn subscribe(
+    events: Receiver<Event>,
+    callback: RisqEventCallback,
+    user_data: UserData,
+    stopped: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let user_data = user_data;
+        while !stopped.load(Ordering::Relaxed) {
+            let event = match events.recv_timeout(STOP_CHECK) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => return,
+            };
+            if let Ok(json) = serde_json::to_string(&event) {
+                if let Ok(json) = CString::new(json) {
+                    callback(json.as_ptr(), user_data.0);
+                }
+            }
+        }
+    })
+}
+
+unsafe fn to_str<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    CStr::from_ptr(s).to_str().ok()
+}
+
+fn into_c_string(s: String) -> *mut c_char {
+    CString::new(s)
+        .map(CString::into_raw)
+        .unwrap_or_else(|_| ptr::null_mut())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::Events;
+    use std::sync::Mutex;
+
+    extern "C" fn collect(event: *const c_char, user_data: *mut c_void) {
+        let received = unsafe { &*(user_data as *const Mutex<Vec<String>>) };
+        let event = unsafe { CStr::from_ptr(event) }
+            .to_str()
+            .unwrap()
+            .to_string();
+        received.lock().unwrap().push(event);
+    }
+
+    #[test]
+    fn subscription_calls_back() {
+        let events = Events::default();
+        let received = Mutex::new(Vec::<String>::new());
+        let stopped = Arc::new(AtomicBool::new(false));
+        let subscription = subscribe(
+            events.subscribe(),
+            collect,
+            UserData(&received as *const _ as *mut c_void),
+            stopped.clone(),
+        );
+        events.publish(Event::PeerConnected {
+            addr: "peer.onion:9999".into(),
+        });
+        while received.lock().unwrap().is_empty() {
+            thread::sleep(Duration::from_millis(10));
+        }
+        stopped.store(true, Ordering::Relaxed);
+        subscription.join().unwrap();
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![r#"{"event":"peer_connected","addr":"peer.onion:9999"}"#.to_string()]
+        );
+    }
+}
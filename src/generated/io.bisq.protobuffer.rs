@@ -505,6 +505,8 @@ pub struct PayoutTxPublishedMessage {
     pub sender_node_address: ::std::option::Option<NodeAddress>,
     #[prost(string, tag="4")]
     pub uid: std::string::String,
+    #[prost(message, optional, tag="5")]
+    pub signed_witness: ::std::option::Option<SignedWitness>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 #[cfg_attr(feature = "dummy-seed", derive(serde::Serialize, serde::Deserialize))]
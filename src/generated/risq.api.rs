@@ -0,0 +1,66 @@
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListOffersRequest {
+    /// eg. "btc_eur", all markets when empty
+    #[prost(string, tag="1")]
+    pub market: std::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListOffersResponse {
+    #[prost(message, repeated, tag="1")]
+    pub offers: ::std::vec::Vec<Offer>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Offer {
+    #[prost(message, optional, tag="1")]
+    pub payload: ::std::option::Option<crate::bisq::payload::OfferPayload>,
+    #[prost(string, tag="2")]
+    pub market: std::string::String,
+    /// The fixed price or the one following the market price, with the
+    /// precision of the non btc currency. 0 while no market price is known
+    #[prost(int64, tag="3")]
+    pub price: i64,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListPeersRequest {
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListPeersResponse {
+    #[prost(message, repeated, tag="1")]
+    pub peers: ::std::vec::Vec<Peer>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Peer {
+    #[prost(string, tag="1")]
+    pub connection_id: std::string::String,
+    /// Not set until the peer identified itself
+    #[prost(message, optional, tag="2")]
+    pub address: ::std::option::Option<crate::bisq::payload::NodeAddress>,
+    /// Unix time in ms of the last message from the peer
+    #[prost(int64, tag="3")]
+    pub alive_at: i64,
+    /// Of the last ping, 0 until the peer answered one
+    #[prost(uint32, tag="4")]
+    pub round_trip_ms: u32,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetStatusRequest {
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct NodeStatus {
+    #[prost(string, tag="1")]
+    pub state: std::string::String,
+    /// Percentage of the bootstrap phases reached
+    #[prost(uint32, tag="2")]
+    pub progress: u32,
+    #[prost(message, repeated, tag="3")]
+    pub phases: ::std::vec::Vec<BootstrapPhase>,
+    #[prost(message, optional, tag="4")]
+    pub local_address: ::std::option::Option<crate::bisq::payload::NodeAddress>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct BootstrapPhase {
+    #[prost(string, tag="1")]
+    pub name: std::string::String,
+    #[prost(bool, tag="2")]
+    pub reached: bool,
+}
@@ -6,25 +6,79 @@
 #[macro_use]
 mod prelude;
 
+#[cfg(not(target_arch = "wasm32"))]
 mod api;
 #[macro_use]
 mod bisq;
-#[cfg(feature = "checker")]
+#[cfg(all(feature = "checker", not(target_arch = "wasm32")))]
 mod checker;
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub mod client;
+#[cfg(not(target_arch = "wasm32"))]
+mod crash;
+#[cfg(not(target_arch = "wasm32"))]
 mod daemon;
+#[cfg(not(target_arch = "wasm32"))]
 mod domain;
-#[cfg(feature = "dummy-seed")]
+#[cfg(all(feature = "dummy-seed", not(target_arch = "wasm32")))]
 mod dummy_seed;
+#[cfg(not(target_arch = "wasm32"))]
 mod error;
+#[cfg(not(target_arch = "wasm32"))]
+mod events;
+#[cfg(not(target_arch = "wasm32"))]
+mod ffi;
+#[cfg(not(target_arch = "wasm32"))]
+mod logging;
+#[cfg(not(target_arch = "wasm32"))]
+mod metrics;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod node;
+#[cfg(not(target_arch = "wasm32"))]
+mod notifier;
+#[cfg(not(target_arch = "wasm32"))]
 mod p2p;
+#[cfg(not(target_arch = "wasm32"))]
+mod reload;
+#[cfg(not(target_arch = "wasm32"))]
+mod rotating_file;
+#[cfg(not(target_arch = "wasm32"))]
+mod scheduler;
+#[cfg(not(target_arch = "wasm32"))]
+mod shutdown;
+#[cfg(all(feature = "testkit", not(target_arch = "wasm32")))]
+pub mod testkit;
+#[cfg(not(target_arch = "wasm32"))]
+mod wallet;
 
-#[cfg(not(target_os = "android"))]
+#[cfg(not(any(target_os = "android", target_arch = "wasm32")))]
 pub mod cli;
 
 #[macro_use]
 extern crate log;
 
-#[cfg(target_os = "android")]
-pub use bisq::constants::BaseCurrencyNetwork;
+#[cfg(not(target_arch = "wasm32"))]
+pub use bisq::{constants::BaseCurrencyNetwork, NodeAddress};
 #[cfg(target_os = "android")]
 pub use daemon::*;
+#[cfg(not(target_arch = "wasm32"))]
+pub use domain::budget::MemoryBudget;
+#[cfg(not(target_arch = "wasm32"))]
+pub use domain::offer::{
+    validation::{OfferValidator, OfferValidators, Rejection},
+    OpenOffer,
+};
+#[cfg(not(target_arch = "wasm32"))]
+pub use events::Event;
+#[cfg(all(feature = "statistics", not(target_arch = "wasm32")))]
+pub use node::TradeStatistic;
+#[cfg(not(target_arch = "wasm32"))]
+pub use node::{Node, NodeBuilder, Offer, Peer};
+#[cfg(not(target_arch = "wasm32"))]
+pub use p2p::{ConnectionKind, SyncState};
+
+// Decoding, hashing and verifying bisq payloads is all there is on wasm32
+#[cfg(target_arch = "wasm32")]
+pub use bisq::{
+    constants, payload, NodeAddress, PersistentMessageHash, SequencedMessageHash, Sha256,
+};
@@ -0,0 +1,257 @@
+use crate::rotating_file::{RotatingFile, RotationConfig};
+use lazy_static::lazy_static;
+use log::LevelFilter;
+use std::{
+    collections::VecDeque,
+    env, fmt,
+    io::{self, Write},
+    str::FromStr,
+    sync::{Arc, Mutex, RwLock},
+};
+use tracing::{
+    field::{Field, Visit},
+    Event, Subscriber,
+};
+use tracing_subscriber::{
+    fmt::Subscriber as FmtSubscriber,
+    layer::{Context, Layer, SubscriberExt},
+    EnvFilter,
+};
+
+const RECENT_LOGS_SIZE: usize = 200;
+
+type Reload = Box<dyn Fn(EnvFilter) -> Result<(), String> + Send + Sync>;
+
+lazy_static! {
+    static ref RECENT_LOGS: Mutex<VecDeque<String>> =
+        Mutex::new(VecDeque::with_capacity(RECENT_LOGS_SIZE));
+    static ref RELOAD: RwLock<Option<Reload>> = RwLock::new(None);
+    static ref FILTERS: RwLock<String> = RwLock::new(String::new());
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    Text,
+    /// One JSON object per line, with the fields and spans of the event
+    Json,
+}
+impl FromStr for LogFormat {
+    type Err = String;
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!(
+                "Unknown log format {}, expected text or json",
+                format
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LogConfig {
+    /// `RUST_LOG` style, eg. `info,risq::p2p=debug`
+    pub filters: String,
+    pub format: LogFormat,
+    /// Logs to stderr without a file
+    pub file: Option<RotationConfig>,
+}
+
+/// Where log lines are written, a new one is made for every line.
+#[derive(Clone)]
+enum Output {
+    Stderr,
+    File(Arc<Mutex<RotatingFile>>),
+}
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Stderr => io::stderr().write(buf),
+            Output::File(file) => {
+                file.lock()
+                    .map_err(|_| io::Error::other("Corrupted log file"))?
+                    .append(buf)?;
+                Ok(buf.len())
+            }
+        }
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Stderr => io::stderr().flush(),
+            Output::File(file) => file
+                .lock()
+                .map_err(|_| io::Error::other("Corrupted log file"))?
+                .flush(),
+        }
+    }
+}
+
+/// Keeps the most recent log lines so they can be attached to a crash
+/// report.
+struct Recorder;
+impl<S: Subscriber> Layer<S> for Recorder {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut line = RecordedLine::default();
+        event.record(&mut line);
+        if let Ok(mut logs) = RECENT_LOGS.lock() {
+            if logs.len() == RECENT_LOGS_SIZE {
+                logs.pop_front();
+            }
+            logs.push_back(format!(
+                "{} {} {} - {}{}",
+                chrono::Utc::now().to_rfc3339(),
+                event.metadata().level(),
+                line.target
+                    .as_deref()
+                    .unwrap_or_else(|| event.metadata().target()),
+                line.message,
+                line.fields
+            ));
+        }
+    }
+}
+
+#[derive(Default)]
+struct RecordedLine {
+    message: String,
+    fields: String,
+    /// Of the events converted from the `log` macros
+    target: Option<String>,
+}
+impl Visit for RecordedLine {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "log.target" {
+            self.target = Some(value.to_string());
+        } else {
+            self.record_debug(field, &value)
+        }
+    }
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        match field.name() {
+            "message" => self.message = format!("{:?}", value),
+            name if name.starts_with("log.") => (),
+            name => self.fields.push_str(&format!(" {}={:?}", name, value)),
+        }
+    }
+}
+
+// Both formats are set up alike but differ in their type
+macro_rules! install {
+    ($builder:expr, $filter:expr, $output:expr, $ansi:expr) => {{
+        let output = $output;
+        let builder = $builder
+            .with_writer(move || output.clone())
+            .with_ansi($ansi)
+            .with_env_filter($filter)
+            .with_filter_reloading();
+        let handle = builder.reload_handle();
+        *RELOAD.write().expect("Corrupted lock in logging") = Some(Box::new(move |filter| {
+            handle.reload(filter).map_err(|e| e.to_string())
+        }));
+        tracing::subscriber::set_global_default(builder.finish().with(Recorder))
+            .map_err(|e| e.to_string())
+    }};
+}
+
+/// Installs the logger, the records of the `log` macros are passed on to
+/// `tracing` so they are logged within the current spans.
+pub fn init(config: &LogConfig) -> Result<(), String> {
+    validate(&config.filters)?;
+    let output = match config.file.as_ref() {
+        Some(file) => Output::File(Arc::new(Mutex::new(
+            RotatingFile::open(file.clone())
+                .map_err(|e| format!("Couldn't open {}: {}", file.path.display(), e))?,
+        ))),
+        None => Output::Stderr,
+    };
+    let ansi = config.file.is_none()
+        && env::var("RUST_LOG_STYLE")
+            .map(|style| style != "never")
+            .unwrap_or(true);
+    let filter = env_filter(&config.filters)?;
+    match config.format {
+        LogFormat::Text => install!(FmtSubscriber::builder(), filter, output, ansi),
+        LogFormat::Json => install!(FmtSubscriber::builder().json(), filter, output, false),
+    }?;
+    tracing_log::LogTracer::init().map_err(|e| e.to_string())?;
+    *FILTERS.write().expect("Corrupted lock in logging") = config.filters.clone();
+    Ok(())
+}
+
+pub fn filters() -> String {
+    FILTERS.read().expect("Corrupted lock in logging").clone()
+}
+
+pub fn set_filters(filters: &str) -> Result<(), String> {
+    validate(filters)?;
+    if let Some(reload) = RELOAD.read().expect("Corrupted lock in logging").as_ref() {
+        reload(env_filter(filters)?)?;
+    }
+    *FILTERS.write().expect("Corrupted lock in logging") = filters.to_string();
+    Ok(())
+}
+
+pub fn recent_logs() -> Vec<String> {
+    // try_lock so a panic while logging doesn't deadlock the crash hook
+    RECENT_LOGS
+        .try_lock()
+        .map(|logs| logs.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+fn env_filter(filters: &str) -> Result<EnvFilter, String> {
+    EnvFilter::try_new(filters).map_err(|e| format!("Invalid log filters '{}': {}", filters, e))
+}
+
+// Besides modules the directives can name spans, eg. `risq[connection]=debug`
+pub fn validate(filters: &str) -> Result<(), String> {
+    if filters.trim().is_empty() {
+        return Err("No log filters given".into());
+    }
+    for directive in filters.split(',').map(str::trim) {
+        let mut parts = directive.rsplitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some(_), None) => (),
+            (Some(level), Some(target)) if !target.is_empty() => {
+                LevelFilter::from_str(level)
+                    .map_err(|_| format!("Invalid log level '{}' in '{}'", level, directive))?;
+            }
+            _ => return Err(format!("Invalid log directive '{}'", directive)),
+        }
+    }
+    env_filter(filters).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_filters() {
+        assert!(validate("info").is_ok());
+        assert!(validate("warn,risq::p2p=debug").is_ok());
+        assert!(validate("risq[connection]=trace").is_ok());
+        assert!(validate("").is_err());
+        assert!(validate("risq::p2p=loud").is_err());
+        assert!(validate("=debug").is_err());
+    }
+
+    #[test]
+    fn records_recent_logs() {
+        let _guard = tracing::subscriber::set_default(
+            FmtSubscriber::builder()
+                .with_writer(io::sink)
+                .finish()
+                .with(Recorder),
+        );
+        tracing::info!(target: "risq::p2p", peers = 3, "connected");
+        let logs = recent_logs();
+        let line = logs.last().unwrap();
+        assert!(
+            line.ends_with(" INFO risq::p2p - connected peers=3"),
+            "{}",
+            line
+        );
+    }
+}
@@ -0,0 +1,323 @@
+use crate::{
+    bisq::payload::{network_envelope, VersionMismatch},
+    p2p::{self, ConnectionKind, Status},
+};
+use lazy_static::lazy_static;
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        RwLock,
+    },
+    time::Duration,
+};
+
+lazy_static! {
+    static ref MESSAGES_RECEIVED: RwLock<BTreeMap<&'static str, u64>> =
+        RwLock::new(BTreeMap::new());
+    static ref MESSAGES_SHED: RwLock<BTreeMap<&'static str, u64>> = RwLock::new(BTreeMap::new());
+    static ref VERSION_MISMATCHES: RwLock<BTreeMap<&'static str, u64>> =
+        RwLock::new(BTreeMap::new());
+    static ref OFFERS: RwLock<BTreeMap<&'static str, u64>> = RwLock::new(BTreeMap::new());
+    static ref OFFERS_REJECTED: RwLock<BTreeMap<&'static str, u64>> = RwLock::new(BTreeMap::new());
+    static ref EVENTS_DROPPED: RwLock<BTreeMap<&'static str, u64>> = RwLock::new(BTreeMap::new());
+    static ref TOR_BOOTSTRAP: RwLock<Option<Duration>> = RwLock::new(None);
+}
+static VERIFICATION_FAILURES: AtomicUsize = AtomicUsize::new(0);
+static MALFORMED_PAYLOADS: AtomicUsize = AtomicUsize::new(0);
+static DATA_REQUESTS_SERVED: AtomicUsize = AtomicUsize::new(0);
+static DATA_ITEMS_SERVED: AtomicUsize = AtomicUsize::new(0);
+static DATA_BYTES_SERVED: AtomicUsize = AtomicUsize::new(0);
+static READS_PAUSED: AtomicUsize = AtomicUsize::new(0);
+
+pub fn message_received(msg: &network_envelope::Message) {
+    *MESSAGES_RECEIVED
+        .write()
+        .expect("Corrupted lock in metrics")
+        .entry(msg.name())
+        .or_insert(0) += 1;
+}
+
+/// Counts the gossip dropped while the actors were behind.
+pub fn message_shed(msg: &network_envelope::Message) {
+    *MESSAGES_SHED
+        .write()
+        .expect("Corrupted lock in metrics")
+        .entry(msg.name())
+        .or_insert(0) += 1;
+}
+
+/// Counts the envelopes peers sent in another message version than ours.
+pub fn version_mismatch(mismatch: VersionMismatch) {
+    *VERSION_MISMATCHES
+        .write()
+        .expect("Corrupted lock in metrics")
+        .entry(mismatch.as_str())
+        .or_insert(0) += 1;
+}
+
+/// Counts the times a connection stopped reading to let the actors catch up.
+pub fn read_paused() {
+    READS_PAUSED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Counts the entries whose signature didn't verify.
+pub fn verified<T>(result: Option<T>) -> Option<T> {
+    if result.is_none() {
+        VERIFICATION_FAILURES.fetch_add(1, Ordering::Relaxed);
+    }
+    result
+}
+
+/// Counts the payloads that couldn't be hashed.
+pub fn malformed_payload() {
+    MALFORMED_PAYLOADS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Counts a `GetDataRequest` answered with `items` entries and payloads of
+/// `bytes` in total.
+pub fn data_served(items: usize, bytes: usize) {
+    DATA_REQUESTS_SERVED.fetch_add(1, Ordering::Relaxed);
+    DATA_ITEMS_SERVED.fetch_add(items, Ordering::Relaxed);
+    DATA_BYTES_SERVED.fetch_add(bytes, Ordering::Relaxed);
+}
+
+pub fn offer_added(market: &'static str) {
+    *OFFERS
+        .write()
+        .expect("Corrupted lock in metrics")
+        .entry(market)
+        .or_insert(0) += 1;
+}
+
+pub fn offer_removed(market: &'static str) {
+    if let Some(count) = OFFERS
+        .write()
+        .expect("Corrupted lock in metrics")
+        .get_mut(market)
+    {
+        *count = count.saturating_sub(1);
+    }
+}
+
+/// Counts the offers refused by the validation `rule`.
+pub fn offer_rejected(rule: &'static str) {
+    *OFFERS_REJECTED
+        .write()
+        .expect("Corrupted lock in metrics")
+        .entry(rule)
+        .or_insert(0) += 1;
+}
+
+/// Counts the events of `topic` a subscriber was too far behind for.
+pub fn event_dropped(topic: &'static str) {
+    *EVENTS_DROPPED
+        .write()
+        .expect("Corrupted lock in metrics")
+        .entry(topic)
+        .or_insert(0) += 1;
+}
+
+/// How long it took until the hidden service was published.
+pub fn tor_bootstrapped(duration: Duration) {
+    *TOR_BOOTSTRAP.write().expect("Corrupted lock in metrics") = Some(duration);
+}
+
+/// All metrics in the Prometheus text format, with the peers and the
+/// bootstrap progress of the network of `status`.
+pub fn render(status: &Status) -> String {
+    let mut out = String::new();
+    metric(
+        &mut out,
+        "risq_connected_peers",
+        "gauge",
+        "Connections to peers",
+        &[(None, status.connections().len() as f64)],
+    );
+    metric(
+        &mut out,
+        "risq_inbound_peers",
+        "gauge",
+        "Connections peers opened to us",
+        &[(
+            None,
+            status
+                .connections()
+                .values()
+                .filter(|conn| conn.kind == ConnectionKind::Direct)
+                .count() as f64,
+        )],
+    );
+    metric(
+        &mut out,
+        "risq_bootstrap_progress_percent",
+        "gauge",
+        "How far the data of the network was synced",
+        &[(None, status.bootstrap_progress() as f64)],
+    );
+    let offers = OFFERS.read().expect("Corrupted lock in metrics");
+    metric(
+        &mut out,
+        "risq_offers",
+        "gauge",
+        "Open offers per market",
+        &labeled("market", &offers),
+    );
+    let rejected = OFFERS_REJECTED.read().expect("Corrupted lock in metrics");
+    metric(
+        &mut out,
+        "risq_offers_rejected_total",
+        "counter",
+        "Offers refused per validation rule",
+        &labeled("rule", &rejected),
+    );
+    let messages = MESSAGES_RECEIVED.read().expect("Corrupted lock in metrics");
+    metric(
+        &mut out,
+        "risq_messages_received_total",
+        "counter",
+        "Messages received from peers per kind",
+        &labeled("kind", &messages),
+    );
+    let shed = MESSAGES_SHED.read().expect("Corrupted lock in metrics");
+    metric(
+        &mut out,
+        "risq_messages_shed_total",
+        "counter",
+        "Gossip from peers dropped under load per kind",
+        &labeled("kind", &shed),
+    );
+    let mismatches = VERSION_MISMATCHES
+        .read()
+        .expect("Corrupted lock in metrics");
+    metric(
+        &mut out,
+        "risq_message_version_mismatches_total",
+        "counter",
+        "Envelopes received in another message version than ours",
+        &labeled("mismatch", &mismatches),
+    );
+    let dropped = EVENTS_DROPPED.read().expect("Corrupted lock in metrics");
+    metric(
+        &mut out,
+        "risq_events_dropped_total",
+        "counter",
+        "Events dropped for subscribers that fell behind per topic",
+        &labeled("topic", &dropped),
+    );
+    metric(
+        &mut out,
+        "risq_mailbox_queue_depth",
+        "gauge",
+        "Messages from peers waiting for each actor",
+        &labeled("actor", &p2p::queue_depths()),
+    );
+    metric(
+        &mut out,
+        "risq_reads_paused_total",
+        "counter",
+        "Times a connection paused reading while an actor was behind",
+        &[(None, READS_PAUSED.load(Ordering::Relaxed) as f64)],
+    );
+    metric(
+        &mut out,
+        "risq_verification_failures_total",
+        "counter",
+        "Storage entries with an invalid signature",
+        &[(None, VERIFICATION_FAILURES.load(Ordering::Relaxed) as f64)],
+    );
+    metric(
+        &mut out,
+        "risq_malformed_payloads_total",
+        "counter",
+        "Payloads from peers that couldn't be hashed",
+        &[(None, MALFORMED_PAYLOADS.load(Ordering::Relaxed) as f64)],
+    );
+    metric(
+        &mut out,
+        "risq_data_requests_served_total",
+        "counter",
+        "Data requests of peers answered",
+        &[(None, DATA_REQUESTS_SERVED.load(Ordering::Relaxed) as f64)],
+    );
+    metric(
+        &mut out,
+        "risq_data_items_served_total",
+        "counter",
+        "Entries and payloads sent in answer to data requests",
+        &[(None, DATA_ITEMS_SERVED.load(Ordering::Relaxed) as f64)],
+    );
+    metric(
+        &mut out,
+        "risq_data_bytes_served_total",
+        "counter",
+        "Size of the responses to data requests",
+        &[(None, DATA_BYTES_SERVED.load(Ordering::Relaxed) as f64)],
+    );
+    if let Some(duration) = *TOR_BOOTSTRAP.read().expect("Corrupted lock in metrics") {
+        metric(
+            &mut out,
+            "risq_tor_bootstrap_seconds",
+            "gauge",
+            "Time until the hidden service was published",
+            &[(None, duration.as_millis() as f64 / 1000.0)],
+        );
+    }
+    out
+}
+
+fn labeled(label: &str, values: &BTreeMap<&'static str, u64>) -> Vec<(Option<String>, f64)> {
+    values
+        .iter()
+        .map(|(value, count)| (Some(format!("{}=\"{}\"", label, value)), *count as f64))
+        .collect()
+}
+
+pub(crate) fn metric(
+    out: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    samples: &[(Option<String>, f64)],
+) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    for (labels, value) in samples {
+        match labels {
+            Some(labels) => {
+                let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+            }
+            None => {
+                let _ = writeln!(out, "{} {}", name, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_prometheus_text() {
+        let mut out = String::new();
+        let mut values = BTreeMap::new();
+        values.insert("btc_eur", 2);
+        metric(
+            &mut out,
+            "risq_offers",
+            "gauge",
+            "Open offers per market",
+            &labeled("market", &values),
+        );
+        assert_eq!(
+            out,
+            "# HELP risq_offers Open offers per market\n\
+             # TYPE risq_offers gauge\n\
+             risq_offers{market=\"btc_eur\"} 2\n"
+        );
+        assert_eq!(verified::<()>(None), None);
+        assert!(VERIFICATION_FAILURES.load(Ordering::Relaxed) > 0);
+    }
+}
@@ -0,0 +1,499 @@
+//! Running risq as a library: configure a node with a `NodeBuilder`, start it
+//! in the background and read its offer book, peers, trade statistics and
+//! events through the returned `Node`.
+use crate::{
+    api::{EventLogConfig, MqttConfig, PostgresConfig},
+    bisq::{constants::BaseCurrencyNetwork, NodeAddress},
+    daemon::{self, DaemonConfig, NetworkHandles},
+    domain::{
+        amount::BtcAmount,
+        budget::MemoryBudget,
+        dao::Genesis,
+        format::{self, Locale},
+        offer::{
+            compliance::{self, DelayedPayoutPolicy},
+            message::GetOpenOffers,
+            validation::OfferValidators,
+            OpenOffer,
+        },
+        payment_account::SignedWitnesses,
+        price_feed::{GetCurrentPrices, PriceData},
+    },
+    events::Event,
+    notifier::{NotifierConfig, Rule},
+    p2p::{ConnectionKind, SyncState},
+    prelude::*,
+    wallet::WalletConfig,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    path::PathBuf,
+    sync::mpsc::{self, Receiver},
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const DEFAULT_API_PORT: u16 = 7477;
+const DEFAULT_API_BIND: &str = "127.0.0.1";
+const DEFAULT_SERVER_PORT: u16 = 5000;
+
+/// Configures a node. Without further options it connects to the network
+/// without tor, listens on the same ports as `risq daemon` and keeps
+/// everything in memory that it receives.
+pub struct NodeBuilder {
+    config: DaemonConfig,
+}
+impl NodeBuilder {
+    /// `risq_home` is where keys, trades and dao blocks are stored.
+    pub fn new(network: BaseCurrencyNetwork, risq_home: impl Into<PathBuf>) -> Self {
+        Self {
+            config: DaemonConfig {
+                api_port: DEFAULT_API_PORT,
+                api_bind: DEFAULT_API_BIND.into(),
+                api_token: None,
+                api_cors_origins: Vec::new(),
+                zmq_port: None,
+                grpc_port: None,
+                mqtt: None,
+                postgres: None,
+                event_log: None,
+                offer_archive: false,
+                notifiers: Vec::new(),
+                notification_rules: Vec::new(),
+                server_port: DEFAULT_SERVER_PORT,
+                network,
+                force_seed: None,
+                clearnet_address: None,
+                risq_home: risq_home.into(),
+                tor_control_port: None,
+                tor_proxy_port: None,
+                hidden_service_port: None,
+                tor_binary: None,
+                memory_budget: MemoryBudget::default(),
+                seed_mode: false,
+                max_connections: None,
+                max_message_size: None,
+                max_upload_rate: None,
+                p2p_network_version: None,
+                halt_offers_on_alert: false,
+                delayed_payout_policy: DelayedPayoutPolicy::default(),
+                offer_validators: OfferValidators::default(),
+                wallet: None,
+                key_ring_password: None,
+                dao_genesis: Genesis::for_network(network),
+            },
+        }
+    }
+
+    pub fn api_port(mut self, port: u16) -> Self {
+        self.config.api_port = port;
+        self
+    }
+
+    /// Address the API listens on, localhost unless it should be reachable
+    /// from other hosts.
+    pub fn api_bind(mut self, addr: impl Into<String>) -> Self {
+        self.config.api_bind = addr.into();
+        self
+    }
+
+    /// Require this token from every client of the API. Without it only
+    /// requests that change something need a token, the one written to
+    /// `api.cookie` in `risq_home` on start.
+    pub fn api_token(mut self, token: impl Into<String>) -> Self {
+        self.config.api_token = Some(token.into());
+        self
+    }
+
+    /// Web pages from these origins may use the API, `*` allows any.
+    pub fn api_cors_origins(mut self, origins: Vec<String>) -> Self {
+        self.config.api_cors_origins = origins;
+        self
+    }
+
+    /// Publish events on a zmq PUB socket on localhost.
+    pub fn zmq_port(mut self, port: u16) -> Self {
+        self.config.zmq_port = Some(port);
+        self
+    }
+
+    /// Serve the gRPC api of proto/risq/risq.proto on localhost.
+    pub fn grpc_port(mut self, port: u16) -> Self {
+        self.config.grpc_port = Some(port);
+        self
+    }
+
+    /// Port of the p2p node.
+    pub fn server_port(mut self, port: u16) -> Self {
+        self.config.server_port = port;
+        self
+    }
+
+    /// Connect through tor and publish the p2p node as a hidden service.
+    pub fn tor(mut self, proxy_port: u16, control_port: u16, hidden_service_port: u16) -> Self {
+        self.config.tor_proxy_port = Some(proxy_port);
+        self.config.tor_control_port = Some(control_port);
+        self.config.hidden_service_port = Some(hidden_service_port);
+        self
+    }
+
+    /// Launch the tor binary at `path` for the ports given to `tor`, keeping
+    /// its data under `risq_home/tor/data`. It exits along with the process.
+    pub fn launch_tor(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.tor_binary = Some(path.into());
+        self
+    }
+
+    /// Connect through tor without publishing a hidden service.
+    pub fn tor_proxy(mut self, proxy_port: u16) -> Self {
+        self.config.tor_proxy_port = Some(proxy_port);
+        self
+    }
+
+    pub fn force_seed(mut self, seed: NodeAddress) -> Self {
+        self.config.force_seed = Some(seed);
+        self
+    }
+
+    /// Also listens on the port of `addr` on all interfaces and tells it to
+    /// the peers that have a clearnet address.
+    pub fn clearnet_address(mut self, addr: NodeAddress) -> Self {
+        self.config.clearnet_address = Some(addr);
+        self
+    }
+
+    pub fn seed_mode(mut self, seed_mode: bool) -> Self {
+        self.config.seed_mode = seed_mode;
+        self
+    }
+
+    /// How many peers to connect to at most.
+    pub fn max_connections(mut self, max_connections: usize) -> Self {
+        self.config.max_connections = Some(max_connections);
+        self
+    }
+
+    /// Close connections to peers that send messages larger than
+    /// `max_size` bytes, bisq's 10 MB by default. Applies to all nodes of
+    /// the process.
+    pub fn max_message_size(mut self, max_size: usize) -> Self {
+        self.config.max_message_size = Some(max_size);
+        self
+    }
+
+    /// Write at most `bytes_per_sec` to all peers together. Pings and
+    /// direct messages are written before the answers to data requests.
+    /// Applies to all nodes of the process.
+    pub fn max_upload_rate(mut self, bytes_per_sec: usize) -> Self {
+        self.config.max_upload_rate = Some(bytes_per_sec);
+        self
+    }
+
+    /// Send envelopes of `version` in the tens of the message version
+    /// instead of bisq's, to follow a protocol bump before risq knows it.
+    pub fn p2p_network_version(mut self, version: i32) -> Self {
+        self.config.p2p_network_version = Some(version);
+        self
+    }
+
+    /// Stop publishing our offers while an alert of bisq's developers asks
+    /// to update.
+    /// Keep the lifecycle of every offer in `risq_home/offer_archive.sqlite`,
+    /// served under `/history/offers`.
+    pub fn offer_archive(mut self, offer_archive: bool) -> Self {
+        self.config.offer_archive = offer_archive;
+        self
+    }
+
+    pub fn halt_offers_on_alert(mut self, halt: bool) -> Self {
+        self.config.halt_offers_on_alert = halt;
+        self
+    }
+
+    pub fn delayed_payout_policy(mut self, policy: DelayedPayoutPolicy) -> Self {
+        self.config.delayed_payout_policy = policy;
+        self
+    }
+
+    /// The rules offers from the network have to follow to be listed,
+    /// `OfferValidators::default()` unless replaced.
+    pub fn offer_validators(mut self, validators: OfferValidators) -> Self {
+        self.config.offer_validators = validators;
+        self
+    }
+
+    /// Keep the key ring under `risq_home/keys` encrypted with `password`.
+    pub fn key_ring_password(mut self, password: impl Into<String>) -> Self {
+        self.config.key_ring_password = Some(password.into());
+        self
+    }
+
+    pub fn memory_budget(mut self, memory_budget: MemoryBudget) -> Self {
+        self.config.memory_budget = memory_budget;
+        self
+    }
+
+    pub(crate) fn mqtt(mut self, mqtt: MqttConfig) -> Self {
+        self.config.mqtt = Some(mqtt);
+        self
+    }
+
+    pub(crate) fn postgres(mut self, postgres: PostgresConfig) -> Self {
+        self.config.postgres = Some(postgres);
+        self
+    }
+
+    pub(crate) fn event_log(mut self, event_log: EventLogConfig) -> Self {
+        self.config.event_log = Some(event_log);
+        self
+    }
+
+    pub(crate) fn notifiers(mut self, notifiers: Vec<NotifierConfig>, rules: Vec<Rule>) -> Self {
+        self.config.notifiers = notifiers;
+        self.config.notification_rules = rules;
+        self
+    }
+
+    pub(crate) fn wallet(mut self, wallet: WalletConfig) -> Self {
+        self.config.wallet = Some(wallet);
+        self
+    }
+
+    pub(crate) fn dao_genesis(mut self, genesis: Genesis) -> Self {
+        self.config.dao_genesis = Some(genesis);
+        self
+    }
+
+    /// Starts the node on a thread of its own. Fails when it couldn't be
+    /// started, eg. because `risq_home` isn't writable.
+    pub fn start(self) -> Result<Node, String> {
+        let (sender, receiver) = mpsc::channel();
+        let config = self.config;
+        let thread = thread::spawn(move || {
+            let sys = System::new("risq");
+            let handles = daemon::start(vec![config]);
+            let _ = sender.send((System::current(), handles));
+            let _ = sys.run();
+        });
+        // Starting panics instead of returning errors, dropping the sender
+        match receiver.recv() {
+            Ok((system, mut handles)) => Ok(Node {
+                system,
+                handles: handles.remove(0),
+                thread: Some(thread),
+            }),
+            Err(_) => Err("Couldn't start node".into()),
+        }
+    }
+
+    /// Runs the node on the current thread until it is stopped.
+    pub fn run(self) {
+        run(vec![self])
+    }
+}
+
+/// Runs several nodes, eg. one per network, on the current thread.
+pub fn run(nodes: Vec<NodeBuilder>) {
+    daemon::run(nodes.into_iter().map(|node| node.config).collect())
+}
+
+/// A node started by `NodeBuilder::start`. It keeps running when dropped.
+pub struct Node {
+    system: System,
+    handles: NetworkHandles,
+    thread: Option<JoinHandle<()>>,
+}
+impl Node {
+    /// The open offers, of one market (eg. "btc_eur") or of all markets,
+    /// oldest first. `None` when the node is gone.
+    pub fn offers(&self, market: Option<&str>) -> Option<Vec<Offer>> {
+        let offers = self.handles.offer_book.send(GetOpenOffers).wait().ok()?;
+        let prices = self.handles.price_feed.send(GetCurrentPrices).wait().ok()?;
+        let mut offers: Vec<_> = offers
+            .values()
+            .filter(|offer| market.map(|m| m == offer.market.pair).unwrap_or(true))
+            .collect();
+        offers.sort_by_key(|offer| offer.created_at);
+        Some(
+            offers
+                .into_iter()
+                .map(|offer| Offer::new(offer, &prices, &self.handles.signed_witnesses))
+                .collect(),
+        )
+    }
+
+    /// The trades of one market or of all markets, oldest first.
+    #[cfg(feature = "statistics")]
+    pub fn trades(&self, market: Option<&str>) -> Option<Vec<TradeStatistic>> {
+        let stats = self.handles.stats_cache.as_ref()?.inner().wait().ok()?;
+        Some(
+            stats
+                .trades()
+                .filter(|trade| market.map(|m| m == trade.market.pair).unwrap_or(true))
+                .map(TradeStatistic::from)
+                .collect(),
+        )
+    }
+
+    /// The peers we are connected to, seed nodes first.
+    pub fn peers(&self) -> Vec<Peer> {
+        let mut peers: Vec<_> = self
+            .handles
+            .p2p_status
+            .connections()
+            .iter()
+            .map(|(id, status)| Peer {
+                connection_id: String::from(*id),
+                addr: status.addr.clone(),
+                kind: status.kind,
+                alive_at: unix_secs(status.alive_at),
+                round_trip_time: status.round_trip_time,
+            })
+            .collect();
+        peers.sort_by_key(|p| Reverse(p.kind));
+        peers
+    }
+
+    /// How far the data of the network was received.
+    pub fn sync_state(&self) -> SyncState {
+        self.handles.p2p_status.sync_state()
+    }
+
+    /// Every event of the node from now on, until it is stopped.
+    pub fn events(&self) -> Receiver<Event> {
+        self.handles.events.subscribe()
+    }
+
+    /// Stops the node and waits for it.
+    pub fn stop(mut self) {
+        self.system.stop();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Offer {
+    pub id: String,
+    pub market: String,
+    pub direction: String,
+    /// `None` for an offer following the market price while no market price
+    /// is known
+    pub price: Option<String>,
+    /// The margin of an offer following the market price, eg. `0.02` for a
+    /// BUY offer paying 2% less than the market price
+    pub market_price_margin: Option<f64>,
+    /// How far the price is above the market price, eg. `0.01` for 1%
+    pub premium: Option<f64>,
+    pub amount: String,
+    pub min_amount: String,
+    pub payment_method: String,
+    /// BTC the payment method allows per trade, `None` for methods bisq
+    /// doesn't know
+    pub max_trade_limit: Option<String>,
+    /// Hex of the maker's account age witness hash, to look up under
+    /// `/account-age/{hash}`
+    pub maker_age_witness_hash: Option<String>,
+    /// Since when the maker's account is signed, unix seconds
+    pub maker_signed_since: Option<u64>,
+    pub created_at: u64,
+}
+impl Offer {
+    pub(crate) fn new(
+        offer: &OpenOffer,
+        prices: &HashMap<&'static str, PriceData>,
+        signed_witnesses: &SignedWitnesses,
+    ) -> Self {
+        let witness_hash = compliance::maker_witness_hash(offer);
+        Offer {
+            id: offer.id.clone().into(),
+            market: offer.market.pair.clone(),
+            direction: format!("{:?}", offer.direction).to_uppercase(),
+            price: offer
+                .price()
+                .map(|price| format::price(price, offer.market, Locale::RAW)),
+            market_price_margin: offer.market_price_margin(),
+            premium: offer.premium(prices),
+            amount: format::btc(offer.amount.total.number(), Locale::RAW),
+            min_amount: format::btc(offer.amount.min.number(), Locale::RAW),
+            payment_method: offer.payment_method.to_string(),
+            max_trade_limit: offer.payment_method.info().map(|method| {
+                format::btc(
+                    BtcAmount::from_sats(method.max_trade_limit()).number(),
+                    Locale::RAW,
+                )
+            }),
+            maker_signed_since: witness_hash
+                .as_ref()
+                .and_then(|hash| signed_witnesses.signed_since(hash))
+                .map(unix_secs),
+            maker_age_witness_hash: witness_hash.map(|hash| hash.to_hex()),
+            created_at: unix_secs(offer.created_at),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Peer {
+    pub connection_id: String,
+    /// `None` until the peer told us its address
+    pub addr: Option<NodeAddress>,
+    pub kind: ConnectionKind,
+    /// When we last heard from the peer, unix seconds
+    pub alive_at: u64,
+    /// Of the last ping, `None` until the peer answered one
+    pub round_trip_time: Option<Duration>,
+}
+
+#[cfg(feature = "statistics")]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TradeStatistic {
+    pub offer_id: String,
+    pub market: String,
+    pub direction: String,
+    pub price: String,
+    pub amount: String,
+    pub volume: String,
+    pub payment_method: String,
+    pub timestamp: u64,
+}
+#[cfg(feature = "statistics")]
+impl From<&crate::domain::statistics::Trade> for TradeStatistic {
+    fn from(trade: &crate::domain::statistics::Trade) -> Self {
+        TradeStatistic {
+            offer_id: trade.offer_id.clone().into(),
+            market: trade.market.pair.clone(),
+            direction: format!("{:?}", trade.direction).to_uppercase(),
+            price: format::price(trade.price, trade.market, Locale::RAW),
+            amount: format::btc(trade.amount, Locale::RAW),
+            volume: format::amount(trade.volume, trade.market.non_btc_side(), Locale::RAW),
+            payment_method: trade.payment_method_id.clone(),
+            timestamp: unix_secs(trade.timestamp),
+        }
+    }
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder_defaults_to_no_tor() {
+        let builder = NodeBuilder::new(BaseCurrencyNetwork::BtcRegtest, "/tmp/risq")
+            .api_port(7478)
+            .tor_proxy(9050);
+        assert_eq!(builder.config.api_port, 7478);
+        assert_eq!(builder.config.server_port, DEFAULT_SERVER_PORT);
+        assert_eq!(builder.config.tor_proxy_port, Some(9050));
+        assert_eq!(builder.config.hidden_service_port, None);
+    }
+}
@@ -0,0 +1,298 @@
+use super::{http, Rule};
+use crate::{
+    domain::format::{self, Locale},
+    events::{Event, Events},
+};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    fs, io,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// An offer rule set through `PUT /alerts/rules` with the webhook it posts
+/// to.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WebhookRule {
+    /// In the syntax of `--notify-rule`, eg. `sell xmr_btc premium>2 amount>0.5`
+    pub rule: String,
+    pub webhook: String,
+    /// The JSON body to post, `{{field}}` in its strings is replaced by that
+    /// field of the offer. Without it the offer is posted as is.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<Value>,
+}
+
+struct Parsed {
+    rule: Rule,
+    webhook: Url,
+    config: WebhookRule,
+}
+
+/// The webhook rules of the api, kept in a file so they last over restarts.
+/// Unlike the rules of the notifiers they can be changed while the daemon
+/// runs.
+#[derive(Clone, Default)]
+pub struct AlertRules {
+    path: Option<PathBuf>,
+    rules: Arc<RwLock<Vec<Parsed>>>,
+}
+impl AlertRules {
+    /// Empty when there is no file at `path`.
+    pub fn load(path: PathBuf) -> io::Result<Self> {
+        let configs: Vec<WebhookRule> = if path.exists() {
+            serde_json::from_slice(&fs::read(&path)?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+        } else {
+            Vec::new()
+        };
+        let rules = configs
+            .into_iter()
+            .map(parse)
+            .collect::<Result<_, _>>()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(Self {
+            path: Some(path),
+            rules: Arc::new(RwLock::new(rules)),
+        })
+    }
+
+    pub fn list(&self) -> Vec<WebhookRule> {
+        self.rules
+            .read()
+            .expect("Corrupted lock in alert rules")
+            .iter()
+            .map(|parsed| parsed.config.clone())
+            .collect()
+    }
+
+    /// Replaces all rules, none of them if one is invalid.
+    pub fn set(&self, configs: Vec<WebhookRule>) -> Result<(), String> {
+        let rules = configs
+            .iter()
+            .cloned()
+            .map(parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut current = self.rules.write().expect("Corrupted lock in alert rules");
+        if let Some(path) = self.path.as_ref() {
+            let serialized = serde_json::to_vec_pretty(&configs).map_err(|e| e.to_string())?;
+            let tmp_path = path.with_extension("tmp");
+            fs::write(&tmp_path, serialized)
+                .and_then(|_| fs::rename(tmp_path, path))
+                .map_err(|e| format!("Couldn't save alert rules: {}", e))?;
+        }
+        *current = rules;
+        Ok(())
+    }
+
+    /// The webhooks to post to for an offer event and their bodies.
+    fn matching(&self, event: &Event) -> Vec<(Url, Value)> {
+        self.rules
+            .read()
+            .expect("Corrupted lock in alert rules")
+            .iter()
+            .filter_map(|parsed| {
+                let message = parsed.rule.offer_alert(event)?;
+                let body = match parsed.config.template.as_ref() {
+                    Some(template) => render(template, &fields(event, &parsed.config, &message)),
+                    None => json!({
+                        "rule": parsed.config.rule,
+                        "message": message,
+                        "offer": event,
+                    }),
+                };
+                Some((parsed.webhook.clone(), body))
+            })
+            .collect()
+    }
+}
+
+fn parse(config: WebhookRule) -> Result<Parsed, String> {
+    let rule: Rule = config.rule.parse()?;
+    if let Rule::Price { .. } = rule {
+        return Err(format!(
+            "Invalid rule '{}': only offer rules can post to webhooks",
+            config.rule
+        ));
+    }
+    let webhook: Url = config
+        .webhook
+        .parse()
+        .map_err(|e| format!("Invalid webhook '{}': {}", config.webhook, e))?;
+    match webhook.scheme() {
+        "http" | "https" => (),
+        _ => return Err(format!("Invalid webhook '{}': not http", config.webhook)),
+    }
+    Ok(Parsed {
+        rule,
+        webhook,
+        config,
+    })
+}
+
+fn fields(event: &Event, config: &WebhookRule, message: &str) -> Vec<(&'static str, String)> {
+    let mut fields = vec![("rule", config.rule.clone()), ("message", message.into())];
+    if let Event::OfferAdded {
+        id,
+        market,
+        direction,
+        price,
+        premium,
+        amount,
+        min_amount,
+        payment_method,
+        ..
+    } = event
+    {
+        fields.extend(vec![
+            ("id", id.clone()),
+            ("market", market.clone()),
+            ("direction", direction.clone()),
+            ("price", price.clone()),
+            (
+                "premium",
+                premium
+                    .map(|premium| format::percentage(premium, Locale::RAW))
+                    .unwrap_or_default(),
+            ),
+            ("amount", amount.clone()),
+            ("min_amount", min_amount.clone()),
+            ("payment_method", payment_method.clone()),
+        ]);
+    }
+    fields
+}
+
+fn render(template: &Value, fields: &[(&str, String)]) -> Value {
+    match template {
+        Value::String(text) => {
+            Value::String(fields.iter().fold(text.clone(), |text, (name, value)| {
+                text.replace(&format!("{{{{{}}}}}", name), value)
+            }))
+        }
+        Value::Array(values) => {
+            Value::Array(values.iter().map(|value| render(value, fields)).collect())
+        }
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, value)| (key.clone(), render(value, fields)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// Evaluates the rules on every new offer and posts to the webhooks of
+/// those it matches. Like the notifiers it leaves out the offers created
+/// before the start.
+pub fn watch(rules: AlertRules, events: &Events, proxy_port: Option<u16>) {
+    let events = events.subscribe();
+    let started_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    thread::spawn(move || {
+        for event in events.iter() {
+            match event {
+                Event::OfferAdded { created_at, .. } if created_at >= started_at => {
+                    for (webhook, body) in rules.matching(&event) {
+                        if let Err(e) = http::send_json("POST", &webhook, &[], &body, proxy_port) {
+                            warn!("Couldn't post alert to {}: {}", webhook, e);
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    fn offer(premium: Option<f64>) -> Event {
+        Event::OfferAdded {
+            id: "offer".into(),
+            market: "xmr_btc".into(),
+            direction: "SELL".into(),
+            price: "0.00700000".into(),
+            premium,
+            amount: "0.60000000".into(),
+            min_amount: "0.60000000".into(),
+            payment_method: "BLOCK_CHAINS".into(),
+            created_at: 0,
+            maker_fee: "0.0001".into(),
+            maker_fee_currency: "BTC".into(),
+            maker_fee_tx_id: "fee".into(),
+            maker: None,
+            maker_age_witness_hash: None,
+        }
+    }
+
+    fn webhook_rule(template: Option<Value>) -> WebhookRule {
+        WebhookRule {
+            rule: "sell xmr_btc premium>2 amount>0.5".into(),
+            webhook: "http://localhost:8080/hook".into(),
+            template,
+        }
+    }
+
+    #[test]
+    fn posts_matching_offers_to_webhooks() {
+        let rules = AlertRules::default();
+        rules
+            .set(vec![webhook_rule(Some(json!({
+                "text": "{{direction}} {{amount}} BTC at {{premium}}",
+                "tags": ["{{market}}"],
+                "priority": 1,
+            })))])
+            .unwrap();
+        assert!(rules.matching(&offer(Some(0.01))).is_empty());
+        assert_eq!(
+            rules.matching(&offer(Some(0.03))),
+            vec![(
+                "http://localhost:8080/hook".parse().unwrap(),
+                json!({
+                    "text": "SELL 0.60000000 BTC at 3.00%",
+                    "tags": ["xmr_btc"],
+                    "priority": 1,
+                })
+            )]
+        );
+
+        rules.set(vec![webhook_rule(None)]).unwrap();
+        let (_, body) = rules.matching(&offer(Some(0.03))).remove(0);
+        assert_eq!(body["offer"]["id"], "offer");
+        assert_eq!(body["rule"], "sell xmr_btc premium>2 amount>0.5");
+    }
+
+    #[test]
+    fn refuses_invalid_rules_and_keeps_the_valid_ones() {
+        let path = env::temp_dir().join(format!("risq-alert-rules-{}", process::id()));
+        let rules = AlertRules::load(path.clone()).unwrap();
+        rules.set(vec![webhook_rule(None)]).unwrap();
+        let invalid = |rule: &str, webhook: &str| {
+            rules.set(vec![
+                webhook_rule(None),
+                WebhookRule {
+                    rule: rule.into(),
+                    webhook: webhook.into(),
+                    template: None,
+                },
+            ])
+        };
+        assert!(invalid("price btc_eur >9000", "http://localhost/hook").is_err());
+        assert!(invalid("sell btc_eur", "ftp://localhost/hook").is_err());
+        assert!(invalid("sell btc_xyz", "http://localhost/hook").is_err());
+
+        let loaded = AlertRules::load(path.clone()).unwrap().list();
+        fs::remove_file(path).unwrap();
+        assert_eq!(loaded, vec![webhook_rule(None)]);
+    }
+}
@@ -0,0 +1,28 @@
+use super::Notifier;
+use std::process::Command;
+
+/// Runs a shell command with the message in `$RISQ_NOTIFICATION`, eg.
+/// `notify-send risq "$RISQ_NOTIFICATION"`.
+pub struct ShellCommand {
+    command: String,
+}
+impl ShellCommand {
+    pub fn new(command: String) -> Self {
+        Self { command }
+    }
+}
+impl Notifier for ShellCommand {
+    fn notify(&self, message: &str) -> Result<(), String> {
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .env("RISQ_NOTIFICATION", message)
+            .status()
+            .map_err(|e| format!("Couldn't run '{}': {}", self.command, e))?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(format!("'{}' failed with {}", self.command, status))
+        }
+    }
+}
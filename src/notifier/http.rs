@@ -0,0 +1,92 @@
+use openssl::ssl::{SslConnector, SslMethod};
+use reqwest::Url;
+use socks::Socks5Stream;
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{IpAddr, TcpStream},
+    path::Path,
+    time::Duration,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// The vendored openssl doesn't know where the system keeps its CAs.
+const CA_FILES: [&str; 3] = [
+    "/etc/ssl/certs/ca-certificates.crt",
+    "/etc/pki/tls/certs/ca-bundle.crt",
+    "/etc/ssl/cert.pem",
+];
+
+/// Sends `body` as JSON over http or https and checks for a 2xx status, via
+/// tor when `proxy_port` is given unless the host is local. The reqwest
+/// build of risq has no TLS.
+pub fn send_json(
+    method: &str,
+    url: &Url,
+    headers: &[(&str, &str)],
+    body: &serde_json::Value,
+    proxy_port: Option<u16>,
+) -> Result<(), String> {
+    let host = url.host_str().ok_or("Url without host")?;
+    let port = url.port_or_known_default().ok_or("Url without port")?;
+    let local = host == "localhost"
+        || host
+            .parse::<IpAddr>()
+            .map(|ip| ip.is_loopback())
+            .unwrap_or(false);
+    let stream = match proxy_port.filter(|_| !local) {
+        Some(proxy_port) => Socks5Stream::connect(("127.0.0.1", proxy_port), (host, port))
+            .map(Socks5Stream::into_inner),
+        None => TcpStream::connect((host, port)),
+    }
+    .map_err(|e| format!("Couldn't connect to {}: {}", host, e))?;
+    stream
+        .set_read_timeout(Some(TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(TIMEOUT)))
+        .map_err(|e| e.to_string())?;
+
+    let body = body.to_string();
+    let mut target = url.path().to_string();
+    if let Some(query) = url.query() {
+        target.push('?');
+        target.push_str(query);
+    }
+    let mut request = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        method,
+        target,
+        host,
+        body.len()
+    );
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+    request.push_str(&body);
+
+    let status = if url.scheme() == "https" {
+        let mut connector = SslConnector::builder(SslMethod::tls()).map_err(|e| e.to_string())?;
+        if let Some(ca_file) = CA_FILES.iter().find(|file| Path::new(file).exists()) {
+            connector.set_ca_file(ca_file).map_err(|e| e.to_string())?;
+        }
+        let stream = connector
+            .build()
+            .connect(host, stream)
+            .map_err(|e| format!("TLS with {} failed: {}", host, e))?;
+        exchange(stream, &request)
+    } else {
+        exchange(stream, &request)
+    }
+    .map_err(|e| format!("Request to {} failed: {}", host, e))?;
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(format!("{} answered {}", host, status.trim_end())),
+    }
+}
+
+// Returns the status line of the response
+fn exchange(mut stream: impl Read + Write, request: &str) -> std::io::Result<String> {
+    stream.write_all(request.as_bytes())?;
+    let mut status = String::new();
+    BufReader::new(stream).read_line(&mut status)?;
+    Ok(status)
+}
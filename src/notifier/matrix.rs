@@ -0,0 +1,44 @@
+use super::{http, Notifier};
+use reqwest::Url;
+use serde_json::json;
+use uuid::Uuid;
+
+/// Posts to a Matrix room as the user of the access token.
+pub struct Matrix {
+    homeserver: Url,
+    room_id: String,
+    authorization: String,
+    proxy_port: Option<u16>,
+}
+impl Matrix {
+    pub fn new(
+        homeserver: Url,
+        room_id: String,
+        access_token: &str,
+        proxy_port: Option<u16>,
+    ) -> Self {
+        Self {
+            homeserver,
+            room_id,
+            authorization: format!("Bearer {}", access_token),
+            proxy_port,
+        }
+    }
+}
+impl Notifier for Matrix {
+    fn notify(&self, message: &str) -> Result<(), String> {
+        let mut url = self.homeserver.clone();
+        url.path_segments_mut()
+            .map_err(|_| "Invalid Matrix homeserver url")?
+            .pop_if_empty()
+            .extend(&["_matrix", "client", "r0", "rooms", &self.room_id, "send"])
+            .extend(&["m.room.message", &Uuid::new_v4().to_string()]);
+        http::send_json(
+            "PUT",
+            &url,
+            &[("Authorization", &self.authorization)],
+            &json!({ "msgtype": "m.text", "body": message }),
+            self.proxy_port,
+        )
+    }
+}
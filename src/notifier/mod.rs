@@ -0,0 +1,180 @@
+mod alert_rules;
+mod command;
+mod http;
+mod matrix;
+mod rule;
+mod telegram;
+mod webhook;
+
+pub use alert_rules::{watch as watch_alert_rules, AlertRules, WebhookRule};
+pub use rule::Rule;
+
+use crate::{
+    domain::{
+        offer::{message::GetOpenOffers, OfferBook},
+        price_feed::{GetCurrentPrices, PriceFeed},
+    },
+    events::{Event, Events},
+    prelude::*,
+    reload,
+};
+use command::ShellCommand;
+use matrix::Matrix;
+use reqwest::Url;
+use std::{
+    collections::HashMap,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use telegram::Telegram;
+use webhook::Webhook;
+
+/// The price feed polls every 30 seconds.
+const PRICE_CHECK: Duration = Duration::from_secs(30);
+
+/// Delivers alerts to the user.
+pub trait Notifier: Send {
+    /// Blocks until the message was delivered.
+    fn notify(&self, message: &str) -> Result<(), String>;
+}
+
+#[derive(Clone)]
+pub enum NotifierConfig {
+    Telegram {
+        token: String,
+        chat_id: String,
+    },
+    Matrix {
+        homeserver: Url,
+        room_id: String,
+        access_token: String,
+    },
+    ShellCommand(String),
+    Webhook(Url),
+}
+
+fn open(config: NotifierConfig, proxy_port: Option<u16>) -> Result<Box<dyn Notifier>, String> {
+    Ok(match config {
+        NotifierConfig::Telegram { token, chat_id } => {
+            Box::new(Telegram::new(&token, chat_id, proxy_port)?)
+        }
+        NotifierConfig::Matrix {
+            homeserver,
+            room_id,
+            access_token,
+        } => Box::new(Matrix::new(homeserver, room_id, &access_token, proxy_port)),
+        NotifierConfig::ShellCommand(command) => Box::new(ShellCommand::new(command)),
+        NotifierConfig::Webhook(url) => Box::new(Webhook::new(url, proxy_port)),
+    })
+}
+
+/// Checks new offers and the market prices against the rules and notifies
+/// through all notifiers when one matches. Offers created before the start
+/// are left out so that bootstrapping doesn't flood the user, price rules
+/// notify once whenever the price crosses their threshold. So do rules with
+/// a premium bound for every open offer whose premium crosses it. The rules
+/// are replaced when the config file is reloaded.
+pub fn start(
+    configs: Vec<NotifierConfig>,
+    rules: Vec<Rule>,
+    events: &Events,
+    price_feed: Addr<PriceFeed>,
+    offer_book: Addr<OfferBook>,
+    proxy_port: Option<u16>,
+) -> Result<(), String> {
+    let notifiers = configs
+        .into_iter()
+        .map(|config| open(config, proxy_port))
+        .collect::<Result<Vec<_>, _>>()?;
+    if notifiers.is_empty() {
+        return Ok(());
+    }
+    let (reloaded, reloaded_rules) = mpsc::channel();
+    reload::on_update(move |update| {
+        if let Some(rules) = update.notify_rules.as_ref() {
+            let _ = reloaded.send(rules.clone());
+        }
+    });
+    let mut rules = rules;
+    let events = events.subscribe();
+    let started_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let mut price_met = vec![false; rules.len()];
+    let mut watch_premiums = rules.iter().any(Rule::bounds_premium);
+    // Per rule whether each open offer met it at the last check
+    let mut premium_met: Vec<HashMap<String, bool>> = vec![HashMap::new(); rules.len()];
+    let mut price_checked_at = Instant::now();
+    thread::spawn(move || loop {
+        if let Some(reloaded) = reloaded_rules.try_iter().last() {
+            rules = reloaded;
+            price_met = vec![false; rules.len()];
+            watch_premiums = rules.iter().any(Rule::bounds_premium);
+            premium_met = vec![HashMap::new(); rules.len()];
+        }
+        match events.recv_timeout(PRICE_CHECK) {
+            Ok(event) => match event {
+                Event::OfferAdded { created_at, .. } if created_at >= started_at => {
+                    for message in rules.iter().filter_map(|rule| rule.offer_alert(&event)) {
+                        notify(&notifiers, &message);
+                    }
+                }
+                _ => (),
+            },
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        if price_checked_at.elapsed() < PRICE_CHECK {
+            continue;
+        }
+        price_checked_at = Instant::now();
+        let prices = match price_feed.send(GetCurrentPrices).wait() {
+            Ok(prices) => prices,
+            Err(_) => return,
+        };
+        for (rule, met) in rules.iter().zip(price_met.iter_mut()) {
+            if let Some((now_met, message)) = rule.price_alert(&prices) {
+                if now_met && !*met {
+                    notify(&notifiers, &message);
+                }
+                *met = now_met;
+            }
+        }
+        if !watch_premiums {
+            continue;
+        }
+        let offers = match offer_book.send(GetOpenOffers).wait() {
+            Ok(offers) => offers,
+            Err(_) => return,
+        };
+        let offers: Vec<_> = offers
+            .values()
+            .map(|offer| Event::offer_added(offer, offer.premium(&prices)))
+            .collect();
+        for (rule, met) in rules.iter().zip(premium_met.iter_mut()) {
+            let mut now_met = HashMap::new();
+            for offer in offers.iter() {
+                if let Event::OfferAdded { id, .. } = offer {
+                    let message = rule.premium_alert(offer);
+                    if let (Some(message), Some(false)) = (message.as_ref(), met.get(id)) {
+                        notify(&notifiers, message);
+                    }
+                    now_met.insert(id.clone(), message.is_some());
+                }
+            }
+            *met = now_met;
+        }
+    });
+    Ok(())
+}
+
+fn notify(notifiers: &[Box<dyn Notifier>], message: &str) {
+    info!("Notifying: {}", message);
+    for notifier in notifiers {
+        if let Err(e) = notifier.notify(message) {
+            warn!("Couldn't notify: {}", e);
+        }
+    }
+}
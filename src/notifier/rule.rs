@@ -0,0 +1,311 @@
+use crate::{
+    domain::{
+        amount::NumberWithPrecision,
+        format::{self, Locale},
+        market::Market,
+        offer::OfferDirection,
+        price_feed::PriceData,
+    },
+    events::Event,
+};
+use std::{collections::HashMap, str::FromStr};
+
+/// When to notify. Offer rules like `sell btc_eur amount>0.1 premium<2`
+/// match new offers and, with a premium bound, open offers whose premium
+/// crosses it as the market price moves. Price rules like
+/// `price btc_eur >9000` match the market price of the non btc side of the
+/// market.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Rule {
+    Offer {
+        direction: OfferDirection,
+        market: &'static Market,
+        min_amount: Option<NumberWithPrecision>,
+        /// As ratio, `0.02` for offers less than 2% above the market price
+        max_premium: Option<f64>,
+        /// As ratio, `0.03` for offers more than 3% above the market price
+        min_premium: Option<f64>,
+    },
+    Price {
+        market: &'static Market,
+        above: bool,
+        price: f64,
+    },
+}
+impl FromStr for Rule {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| format!("Invalid rule '{}': {}", s, reason);
+        let tokens: Vec<&str> = s.split_whitespace().collect();
+        if tokens.len() < 2 {
+            return Err(invalid(
+                "expected 'buy|sell <market> ..' or 'price <market> ..'",
+            ));
+        }
+        let market = Market::from_pair(&tokens[1].to_lowercase().replace('/', "_"))
+            .ok_or_else(|| invalid("unknown market"))?;
+        let direction = match tokens[0].to_lowercase().as_str() {
+            "buy" => OfferDirection::Buy,
+            "sell" => OfferDirection::Sell,
+            "price" => {
+                let condition = match tokens.get(2) {
+                    Some(condition) if tokens.len() == 3 => condition,
+                    _ => return Err(invalid("expected 'price <market> >price' or '<price'")),
+                };
+                let (above, price) = match condition.split_at(1) {
+                    (">", price) => (true, price),
+                    ("<", price) => (false, price),
+                    _ => return Err(invalid("expected > or < before the price")),
+                };
+                return Ok(Rule::Price {
+                    market,
+                    above,
+                    price: price.parse().map_err(|_| invalid("invalid price"))?,
+                });
+            }
+            _ => return Err(invalid("expected buy, sell or price")),
+        };
+        let mut min_amount = None;
+        let mut max_premium = None;
+        let mut min_premium = None;
+        let expected = "expected amount>btc, premium<percent or premium>percent";
+        for condition in &tokens[2..] {
+            let operator = condition
+                .find(&['>', '<'][..])
+                .ok_or_else(|| invalid(expected))?;
+            let (name, value) = condition.split_at(operator);
+            match value.split_at(1) {
+                (">", amount) if name == "amount" => {
+                    min_amount = Some(
+                        amount
+                            .parse::<NumberWithPrecision>()
+                            .map_err(|e| invalid(&e))?,
+                    )
+                }
+                (operator, percent) if name == "premium" => {
+                    let percent: f64 = percent
+                        .trim_end_matches('%')
+                        .parse()
+                        .map_err(|_| invalid("invalid premium"))?;
+                    if operator == "<" {
+                        max_premium = Some(percent / 100.0);
+                    } else {
+                        min_premium = Some(percent / 100.0);
+                    }
+                }
+                _ => return Err(invalid(expected)),
+            }
+        }
+        Ok(Rule::Offer {
+            direction,
+            market,
+            min_amount,
+            max_premium,
+            min_premium,
+        })
+    }
+}
+impl Rule {
+    /// The message for a new offer this rule matches.
+    pub fn offer_alert(&self, event: &Event) -> Option<String> {
+        self.matching_offer(event)
+            .map(|offer| format!("New {}", offer))
+    }
+
+    /// The message for an open offer this rule matches now that its
+    /// premium moved, `None` for rules without a premium bound.
+    pub fn premium_alert(&self, event: &Event) -> Option<String> {
+        if !self.bounds_premium() {
+            return None;
+        }
+        self.matching_offer(event)
+            .map(|offer| format!("Premium crossed: {}", offer))
+    }
+
+    pub fn bounds_premium(&self) -> bool {
+        match self {
+            Rule::Offer {
+                max_premium,
+                min_premium,
+                ..
+            } => max_premium.is_some() || min_premium.is_some(),
+            Rule::Price { .. } => false,
+        }
+    }
+
+    fn matching_offer(&self, event: &Event) -> Option<String> {
+        let (direction, market, min_amount, max_premium, min_premium) = match self {
+            Rule::Offer {
+                direction,
+                market,
+                min_amount,
+                max_premium,
+                min_premium,
+            } => (direction, market, min_amount, max_premium, min_premium),
+            Rule::Price { .. } => return None,
+        };
+        let (offer_direction, price, premium, amount, payment_method) = match event {
+            Event::OfferAdded {
+                market: offer_market,
+                direction,
+                price,
+                premium,
+                amount,
+                payment_method,
+                ..
+            } if *offer_market == market.pair => {
+                (direction, price, premium, amount, payment_method)
+            }
+            _ => return None,
+        };
+        if *offer_direction != format!("{:?}", direction).to_uppercase() {
+            return None;
+        }
+        if let Some(min_amount) = min_amount {
+            if amount.parse::<NumberWithPrecision>().ok()? <= *min_amount {
+                return None;
+            }
+        }
+        if let Some(max_premium) = max_premium {
+            if premium
+                .map(|premium| premium >= *max_premium)
+                .unwrap_or(true)
+            {
+                return None;
+            }
+        }
+        if let Some(min_premium) = min_premium {
+            if premium
+                .map(|premium| premium <= *min_premium)
+                .unwrap_or(true)
+            {
+                return None;
+            }
+        }
+        Some(format!(
+            "{} offer for {} BTC in {} at {}{} via {}",
+            offer_direction,
+            amount,
+            name(market),
+            price,
+            premium
+                .map(|premium| format!(" ({} premium)", format::percentage(premium, Locale::RAW)))
+                .unwrap_or_default(),
+            payment_method
+        ))
+    }
+
+    /// Whether the market price meets this rule and the message telling so,
+    /// `None` without a price.
+    pub fn price_alert(&self, prices: &HashMap<&'static str, PriceData>) -> Option<(bool, String)> {
+        let (market, above, price) = match self {
+            Rule::Price {
+                market,
+                above,
+                price,
+            } => (market, *above, *price),
+            Rule::Offer { .. } => return None,
+        };
+        let current = prices.get(market.non_btc_side().code.as_str())?.price;
+        Some((
+            if above {
+                current > price
+            } else {
+                current < price
+            },
+            format!(
+                "{} price is {} {} at {}",
+                name(market),
+                if above { "above" } else { "below" },
+                price,
+                current
+            ),
+        ))
+    }
+}
+
+fn name(market: &Market) -> String {
+    market.pair.to_uppercase().replace('_', "/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offer_rules() {
+        let rule: Rule = "sell BTC/EUR amount>0.1 premium<2%".parse().unwrap();
+        assert_eq!(
+            rule,
+            Rule::Offer {
+                direction: OfferDirection::Sell,
+                market: Market::from_pair("btc_eur").unwrap(),
+                min_amount: Some(NumberWithPrecision::new(1, 1)),
+                max_premium: Some(0.02),
+                min_premium: None,
+            }
+        );
+        let offer = |direction: &str, amount: &str, premium: Option<f64>| Event::OfferAdded {
+            id: "offer".into(),
+            market: "btc_eur".into(),
+            direction: direction.into(),
+            price: "9000.0000".into(),
+            premium,
+            amount: amount.into(),
+            min_amount: amount.into(),
+            payment_method: "SEPA".into(),
+            created_at: 0,
+            maker_fee: "0.0001".into(),
+            maker_fee_currency: "BTC".into(),
+            maker_fee_tx_id: "fee".into(),
+            maker: None,
+            maker_age_witness_hash: None,
+        };
+        assert_eq!(
+            rule.offer_alert(&offer("SELL", "0.50000000", Some(0.015))),
+            Some("New SELL offer for 0.50000000 BTC in BTC/EUR at 9000.0000 (1.50% premium) via SEPA".into())
+        );
+        assert_eq!(
+            rule.offer_alert(&offer("BUY", "0.50000000", Some(0.015))),
+            None
+        );
+        assert_eq!(
+            rule.offer_alert(&offer("SELL", "0.10000000", Some(0.015))),
+            None
+        );
+        assert_eq!(
+            rule.offer_alert(&offer("SELL", "0.50000000", Some(0.025))),
+            None
+        );
+        assert_eq!(rule.offer_alert(&offer("SELL", "0.50000000", None)), None);
+
+        let rule: Rule = "buy btc_eur premium>3".parse().unwrap();
+        assert_eq!(
+            rule.premium_alert(&offer("BUY", "0.50000000", Some(0.035))),
+            Some("Premium crossed: BUY offer for 0.50000000 BTC in BTC/EUR at 9000.0000 (3.50% premium) via SEPA".into())
+        );
+        assert_eq!(
+            rule.premium_alert(&offer("BUY", "0.50000000", Some(0.025))),
+            None
+        );
+        assert_eq!(
+            "buy btc_eur".parse::<Rule>().unwrap().premium_alert(&offer(
+                "BUY",
+                "0.50000000",
+                Some(0.035)
+            )),
+            None
+        );
+
+        assert_eq!(
+            "price btc_usd <8000".parse(),
+            Ok(Rule::Price {
+                market: Market::from_pair("btc_usd").unwrap(),
+                above: false,
+                price: 8000.0,
+            })
+        );
+        assert!("sell btc_xyz".parse::<Rule>().is_err());
+        assert!("sell btc_eur premium=2".parse::<Rule>().is_err());
+    }
+}
@@ -0,0 +1,32 @@
+use super::{http, Notifier};
+use reqwest::Url;
+use serde_json::json;
+
+/// Messages a chat through a Telegram bot, which has to be a member of it.
+pub struct Telegram {
+    url: Url,
+    chat_id: String,
+    proxy_port: Option<u16>,
+}
+impl Telegram {
+    pub fn new(token: &str, chat_id: String, proxy_port: Option<u16>) -> Result<Self, String> {
+        Ok(Self {
+            url: format!("https://api.telegram.org/bot{}/sendMessage", token)
+                .parse()
+                .map_err(|_| "Invalid Telegram bot token".to_string())?,
+            chat_id,
+            proxy_port,
+        })
+    }
+}
+impl Notifier for Telegram {
+    fn notify(&self, message: &str) -> Result<(), String> {
+        http::send_json(
+            "POST",
+            &self.url,
+            &[],
+            &json!({ "chat_id": self.chat_id, "text": message }),
+            self.proxy_port,
+        )
+    }
+}
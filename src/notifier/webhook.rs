@@ -0,0 +1,26 @@
+use super::{http, Notifier};
+use reqwest::Url;
+use serde_json::json;
+
+/// Posts `{"text": <message>}`, which Slack, Mattermost and Rocket.Chat
+/// incoming webhooks accept as is.
+pub struct Webhook {
+    url: Url,
+    proxy_port: Option<u16>,
+}
+impl Webhook {
+    pub fn new(url: Url, proxy_port: Option<u16>) -> Self {
+        Self { url, proxy_port }
+    }
+}
+impl Notifier for Webhook {
+    fn notify(&self, message: &str) -> Result<(), String> {
+        http::send_json(
+            "POST",
+            &self.url,
+            &[],
+            &json!({ "text": message }),
+            self.proxy_port,
+        )
+    }
+}
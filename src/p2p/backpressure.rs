@@ -0,0 +1,172 @@
+//! Keeps what peers send from piling up in front of the actors handling it.
+//! Every message dispatched to an actor is counted until the actor handled
+//! it. When a queue grows past `SHED_DEPTH` connections drop low priority
+//! gossip as it arrives, past `PAUSE_DEPTH` they stop reading from their
+//! sockets until it shrank again.
+use crate::{
+    bisq::payload::*,
+    metrics,
+    prelude::{Async, Future, Poll, Stream},
+};
+use lazy_static::lazy_static;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, Instant},
+};
+use tokio::timer::Delay;
+
+/// Messages an actor's mailbox takes before their senders have to wait.
+pub const MAILBOX_CAPACITY: usize = 256;
+const SHED_DEPTH: usize = 1_000;
+const PAUSE_DEPTH: usize = 5_000;
+/// How long a paused connection waits before it checks the queues again
+const PAUSE: Duration = Duration::from_millis(50);
+
+lazy_static! {
+    static ref QUEUES: RwLock<BTreeMap<&'static str, Arc<AtomicUsize>>> =
+        RwLock::new(BTreeMap::new());
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Pressure {
+    Normal,
+    Shedding,
+    Saturated,
+}
+impl Pressure {
+    fn of(depth: usize) -> Self {
+        if depth >= PAUSE_DEPTH {
+            Pressure::Saturated
+        } else if depth >= SHED_DEPTH {
+            Pressure::Shedding
+        } else {
+            Pressure::Normal
+        }
+    }
+
+    fn current() -> Self {
+        Self::of(
+            QUEUES
+                .read()
+                .expect("Corrupted lock in backpressure")
+                .values()
+                .map(|depth| depth.load(Ordering::Relaxed))
+                .max()
+                .unwrap_or(0),
+        )
+    }
+}
+
+/// The messages on their way to the actor of `name`.
+#[derive(Clone)]
+pub struct Queue {
+    depth: Arc<AtomicUsize>,
+}
+impl Queue {
+    /// The same queue for every caller with the same `name`.
+    pub fn named(name: &'static str) -> Self {
+        let mut queues = QUEUES.write().expect("Corrupted lock in backpressure");
+        Queue {
+            depth: queues.entry(name).or_default().clone(),
+        }
+    }
+
+    /// Counts the message sent by `send` until it was handled.
+    pub fn track<F: Future>(&self, send: F) -> impl Future<Item = F::Item, Error = F::Error> {
+        self.depth.fetch_add(1, Ordering::Relaxed);
+        let queued = Queued(self.depth.clone());
+        send.then(move |result| {
+            drop(queued);
+            result
+        })
+    }
+}
+
+// Also leaves the queue when the send is dropped unfinished
+struct Queued(Arc<AtomicUsize>);
+impl Drop for Queued {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// The number of messages waiting for each actor.
+pub fn queue_depths() -> BTreeMap<&'static str, u64> {
+    QUEUES
+        .read()
+        .expect("Corrupted lock in backpressure")
+        .iter()
+        .map(|(name, depth)| (*name, depth.load(Ordering::Relaxed) as u64))
+        .collect()
+}
+
+/// Whether `msg` is dropped instead of handled. Refreshed offers and
+/// persistable payloads come again with the next refresh or sync, the rest
+/// is always handled.
+pub(super) fn sheds(msg: &network_envelope::Message) -> bool {
+    match msg {
+        network_envelope::Message::RefreshOfferMessage(_)
+        | network_envelope::Message::AddPersistableNetworkPayloadMessage(_) => {
+            Pressure::current() != Pressure::Normal
+        }
+        _ => false,
+    }
+}
+
+/// Stops reading from `inner` while a queue is saturated.
+pub(super) struct Throttled<S> {
+    inner: S,
+    pause: Option<Delay>,
+}
+impl<S> Throttled<S> {
+    pub fn new(inner: S) -> Self {
+        Throttled { inner, pause: None }
+    }
+}
+impl<S: Stream> Stream for Throttled<S> {
+    type Item = S::Item;
+    type Error = S::Error;
+    fn poll(&mut self) -> Poll<Option<S::Item>, S::Error> {
+        loop {
+            if let Some(pause) = self.pause.as_mut() {
+                if let Ok(Async::NotReady) = pause.poll() {
+                    return Ok(Async::NotReady);
+                }
+                self.pause = None;
+            }
+            if Pressure::current() != Pressure::Saturated {
+                return self.inner.poll();
+            }
+            metrics::read_paused();
+            self.pause = Some(Delay::new(Instant::now() + PAUSE));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::future;
+
+    #[test]
+    fn sheds_before_it_pauses() {
+        assert_eq!(Pressure::of(0), Pressure::Normal);
+        assert_eq!(Pressure::of(SHED_DEPTH), Pressure::Shedding);
+        assert_eq!(Pressure::of(PAUSE_DEPTH), Pressure::Saturated);
+    }
+
+    #[test]
+    fn counts_messages_until_they_were_handled() {
+        let queue = Queue {
+            depth: Arc::new(AtomicUsize::new(0)),
+        };
+        let sent = queue.track(future::ok::<_, ()>(1));
+        assert_eq!(queue.depth.load(Ordering::Relaxed), 1);
+        assert_eq!(sent.wait(), Ok(1));
+        assert_eq!(queue.depth.load(Ordering::Relaxed), 0);
+    }
+}
@@ -0,0 +1,157 @@
+use crate::bisq::payload::NodeAddress;
+use prost::Message;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Clone, PartialEq, Message)]
+struct StoredBans {
+    #[prost(message, repeated, tag = "1")]
+    bans: Vec<BannedPeer>,
+}
+
+#[derive(Clone, PartialEq, Message, Serialize)]
+pub struct BannedPeer {
+    /// Banned on every port
+    #[prost(string, tag = "1")]
+    pub host: String,
+    #[prost(string, tag = "2")]
+    pub reason: String,
+    /// Unix seconds
+    #[prost(uint64, tag = "3")]
+    pub banned_at: u64,
+}
+
+/// Peers the operator banned, unlike the bans for misbehavior they don't
+/// expire and are kept in a file so they last over restarts. Connections
+/// to them aren't opened, those they open are dropped once they identify.
+#[derive(Clone, Default)]
+pub struct BanList {
+    path: Option<PathBuf>,
+    bans: Arc<RwLock<BTreeMap<String, BannedPeer>>>,
+}
+impl BanList {
+    /// Empty when there is no file at `path`.
+    pub fn load(path: PathBuf) -> io::Result<Self> {
+        let bans = if path.exists() {
+            StoredBans::decode(&fs::read(&path)?[..])
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+                .bans
+        } else {
+            Vec::new()
+        };
+        Ok(Self {
+            path: Some(path),
+            bans: Arc::new(RwLock::new(
+                bans.into_iter()
+                    .map(|ban| (ban.host.clone(), ban))
+                    .collect(),
+            )),
+        })
+    }
+
+    pub fn is_banned(&self, addr: &NodeAddress) -> bool {
+        self.bans
+            .read()
+            .expect("Corrupted lock in ban list")
+            .contains_key(&addr.host_name)
+    }
+
+    pub fn list(&self) -> Vec<BannedPeer> {
+        self.bans
+            .read()
+            .expect("Corrupted lock in ban list")
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// `onion` may include a port, the host is banned on all of them.
+    pub fn ban(&self, onion: &str, reason: String) -> Result<BannedPeer, String> {
+        let host = host(onion)?;
+        let ban = BannedPeer {
+            host: host.clone(),
+            reason,
+            banned_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        };
+        let mut bans = self.bans.write().expect("Corrupted lock in ban list");
+        bans.insert(host, ban.clone());
+        self.save(&bans)
+            .map_err(|e| format!("Couldn't save ban list: {}", e))?;
+        Ok(ban)
+    }
+
+    /// `false` if the host wasn't banned.
+    pub fn unban(&self, onion: &str) -> Result<bool, String> {
+        let host = host(onion)?;
+        let mut bans = self.bans.write().expect("Corrupted lock in ban list");
+        if bans.remove(&host).is_none() {
+            return Ok(false);
+        }
+        self.save(&bans)
+            .map_err(|e| format!("Couldn't save ban list: {}", e))?;
+        Ok(true)
+    }
+
+    fn save(&self, bans: &BTreeMap<String, BannedPeer>) -> io::Result<()> {
+        let path = match self.path.as_ref() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let stored = StoredBans {
+            bans: bans.values().cloned().collect(),
+        };
+        let mut serialized = Vec::with_capacity(stored.encoded_len());
+        stored
+            .encode(&mut serialized)
+            .expect("Could not encode ban list");
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, serialized)?;
+        fs::rename(tmp_path, path)
+    }
+}
+
+fn host(onion: &str) -> Result<String, String> {
+    let host = onion.trim().split(':').next().unwrap_or_default();
+    if host.is_empty()
+        || !host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+    {
+        return Err(format!("'{}' is not a host name", onion));
+    }
+    Ok(host.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process, str::FromStr};
+
+    #[test]
+    fn bans_hosts_across_restarts() {
+        let path = env::temp_dir().join(format!("risq-bans-{}", process::id()));
+        let bans = BanList::load(path.clone()).unwrap();
+        let peer = NodeAddress::from_str("abcdefgh.onion:9999").unwrap();
+        assert!(!bans.is_banned(&peer));
+        bans.ban("ABCDEFGH.onion:8000", "spam".into()).unwrap();
+        assert!(bans.is_banned(&peer));
+        assert!(bans.ban("not a host", String::new()).is_err());
+
+        let restarted = BanList::load(path.clone()).unwrap();
+        assert!(restarted.is_banned(&peer));
+        assert_eq!(restarted.list()[0].reason, "spam");
+        assert_eq!(restarted.unban("abcdefgh.onion"), Ok(true));
+        assert_eq!(restarted.unban("abcdefgh.onion"), Ok(false));
+        assert!(!BanList::load(path.clone()).unwrap().is_banned(&peer));
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1,8 +1,9 @@
 use super::{
-    connection::{Connection, ConnectionId, Request},
-    dispatch::SendableDispatcher,
+    connection::{transport::Transport, Connection, ConnectionId, Request},
+    dispatch::{self, Dispatch, Dispatcher, SendableDispatcher},
     peers::{Peers, SeedConnection},
-    server::event::ServerStarted,
+    server::{event::ServerStarted, LocalAddrs},
+    status::{BootstrapPhase, Status},
 };
 use crate::{
     bisq::{
@@ -10,13 +11,14 @@ use crate::{
         payload::*,
     },
     error::Error,
-    prelude::{sync::oneshot, *},
+    prelude::{fut::Either, future::Shared, sync::oneshot, *},
 };
 use rand::{seq::SliceRandom, thread_rng};
 use std::{
-    fmt,
-    sync::{Arc, RwLock},
+    fmt, mem,
+    sync::{Arc, Mutex, RwLock},
 };
+use tracing_futures::Instrument;
 
 #[derive(Clone, Copy, PartialEq)]
 pub enum BootstrapState {
@@ -42,186 +44,250 @@ impl fmt::Display for BootstrapState {
 pub struct Bootstrap<D: SendableDispatcher> {
     network: BaseCurrencyNetwork,
     state: Arc<RwLock<BootstrapState>>,
-    proxy_port: Option<u16>,
-    addr_notify: Option<oneshot::Sender<NodeAddress>>,
-    addr_rec: Option<oneshot::Receiver<NodeAddress>>,
+    transport: Arc<dyn Transport>,
+    addr_notify: Option<oneshot::Sender<LocalAddrs>>,
+    addr_rec: Shared<oneshot::Receiver<LocalAddrs>>,
     seed_nodes: Vec<NodeAddress>,
+    /// Peers from earlier runs, most recently seen first, to bootstrap from
+    /// when no seed node can be reached
+    known_peers: Vec<NodeAddress>,
     peers: Addr<Peers<D>>,
     dispatcher: D,
+    status: Status,
+    known_keys: Vec<Vec<u8>>,
 }
 impl<D: SendableDispatcher> Actor for Bootstrap<D> {
     type Context = Context<Bootstrap<D>>;
     fn started(&mut self, ctx: &mut Self::Context) {
         *self.state.write().expect("Corrupted lock in bootstrap") =
             BootstrapState::InitialBootstrapInProgress;
-
-        let addr = self.seed_nodes.pop().expect("No seed nodes defined");
-        ctx.spawn(
-            fut::wrap_future(bootstrap_from_seed(
-                addr.clone(),
-                self.addr_rec.take().expect("Receiver already removed"),
-                self.network,
-                self.dispatcher.clone(),
-                self.proxy_port,
-            ))
-            .map_err(|_, _, _| ())
-            .and_then(move |seed_result, bootstrap: &mut Bootstrap<D>, _ctx| {
-                *bootstrap
-                    .state
-                    .write()
-                    .expect("Corrupted lock in bootstrap") = BootstrapState::Bootstrapped;
-
-                fut::wrap_future(
-                    bootstrap
-                        .peers
-                        .send(SeedConnection(
-                            addr,
-                            seed_result.connection_id,
-                            seed_result.connection,
-                        ))
-                        .map_err(|_| ()),
-                )
-            })
-            .map(|_, _, ctx| ctx.stop()),
-        );
+        self.bootstrap_from_next(ctx);
     }
 }
 impl<D: SendableDispatcher> Handler<ServerStarted> for Bootstrap<D> {
     type Result = ();
-    fn handle(&mut self, ServerStarted(local_addr): ServerStarted, _ctx: &mut Self::Context) {
+    fn handle(&mut self, ServerStarted(local_addrs): ServerStarted, _ctx: &mut Self::Context) {
         self.addr_notify
             .take()
             .expect("Local addr notifier already used")
-            .send(local_addr)
+            .send(local_addrs)
             .map_err(|e| error!("ERR: {:?}", e))
             .expect("Couldn't send local address");
     }
 }
 impl<D: SendableDispatcher> Bootstrap<D> {
+    fn bootstrap_from_next(&mut self, ctx: &mut <Self as Actor>::Context) {
+        let addr = match self.seed_nodes.pop() {
+            Some(addr) => addr,
+            None if !self.known_peers.is_empty() => self.known_peers.remove(0),
+            None => {
+                error!("Couldn't bootstrap from any seed node or known peer");
+                return ctx.stop();
+            }
+        };
+        let span = tracing::info_span!("data_sync", seed = %addr);
+        ctx.spawn(
+            fut::wrap_future(
+                bootstrap_from_seed(
+                    addr.clone(),
+                    self.addr_rec.clone(),
+                    self.network,
+                    self.dispatcher.clone(),
+                    &*self.transport,
+                    self.status.clone(),
+                    self.known_keys.clone(),
+                )
+                .instrument(span),
+            )
+            .then(move |seed_result, bootstrap: &mut Bootstrap<D>, ctx| {
+                let seed_result = match seed_result {
+                    Ok(seed_result) => seed_result,
+                    Err(e) => {
+                        warn!("Couldn't bootstrap from {:?}: {:?}", addr, e);
+                        bootstrap.bootstrap_from_next(ctx);
+                        return Either::A(fut::ok(()));
+                    }
+                };
+                *bootstrap
+                    .state
+                    .write()
+                    .expect("Corrupted lock in bootstrap") = BootstrapState::Bootstrapped;
+
+                Either::B(
+                    fut::wrap_future(
+                        bootstrap
+                            .peers
+                            .send(SeedConnection(
+                                addr,
+                                seed_result.connection_id,
+                                seed_result.connection,
+                            ))
+                            .map_err(|_| ()),
+                    )
+                    .map(|_, _, ctx: &mut <Self as Actor>::Context| ctx.stop()),
+                )
+            }),
+        );
+    }
+
     pub fn start(
         network: BaseCurrencyNetwork,
         state: Arc<RwLock<BootstrapState>>,
         peers: Addr<Peers<D>>,
         dispatcher: D,
-        proxy_port: Option<u16>,
+        transport: Arc<dyn Transport>,
         force_seed: Option<NodeAddress>,
+        known_peers: Vec<NodeAddress>,
+        status: Status,
+        known_keys: Vec<Vec<u8>>,
     ) -> Addr<Bootstrap<D>> {
-        let mut seed_nodes = match force_seed {
-            Some(addr) => vec![addr],
-            None => seed_nodes(network),
+        let (mut seed_nodes, known_peers) = match force_seed {
+            Some(addr) => (vec![addr], Vec::new()),
+            None => (seed_nodes(network), known_peers),
         };
         seed_nodes.shuffle(&mut thread_rng());
         let (addr_notify, addr_rec) = oneshot::channel();
         Self {
             network,
             addr_notify: Some(addr_notify),
-            addr_rec: Some(addr_rec),
-            proxy_port,
+            addr_rec: addr_rec.shared(),
+            transport,
             seed_nodes,
+            known_peers,
             peers,
             dispatcher,
             state,
+            status,
+            known_keys,
         }
         .start()
     }
 }
+// Large data responses arrive in batches that are dispatched right away,
+// their keys are excluded from the `GetUpdatedDataRequest` as well.
+#[derive(Clone, Default)]
+struct ReceivedKeys(Arc<Mutex<Vec<Vec<u8>>>>);
+impl ReceivedKeys {
+    fn take(&self) -> Vec<Vec<u8>> {
+        mem::take(&mut *self.0.lock().expect("Corrupted lock in bootstrap"))
+    }
+}
+impl Dispatcher for ReceivedKeys {
+    fn dispatch(&self, _conn: ConnectionId, msg: network_envelope::Message) -> Dispatch {
+        if let network_envelope::Message::GetDataResponse(response) = &msg {
+            self.0
+                .lock()
+                .expect("Corrupted lock in bootstrap")
+                .extend(get_excluded_keys(response));
+        }
+        Dispatch::Retained(msg)
+    }
+}
+
 struct SeedResult {
     connection: Addr<Connection>,
     connection_id: ConnectionId,
 }
 fn bootstrap_from_seed<D: SendableDispatcher>(
     seed_addr: NodeAddress,
-    local_addr: oneshot::Receiver<NodeAddress>,
+    local_addrs: Shared<oneshot::Receiver<LocalAddrs>>,
     network: BaseCurrencyNetwork,
     dispatcher: D,
-    proxy_port: Option<u16>,
+    transport: &dyn Transport,
+    status: Status,
+    known_keys: Vec<Vec<u8>>,
 ) -> impl Future<Item = SeedResult, Error = Error> {
     let preliminary_get_data_request = PreliminaryGetDataRequest {
         nonce: gen_nonce(),
-        excluded_keys: Vec::new(),
+        excluded_keys: known_keys.clone(),
         supported_capabilities: LOCAL_CAPABILITIES.clone(),
     };
     info!("Bootstrapping from seed: {:?}", seed_addr);
-    Connection::open(seed_addr, network.into(), dispatcher.clone(), proxy_port)
-        .and_then(|(id, conn)| {
-            debug!("Sending PreliminaryGetDataRequest to seed.");
-            conn.send(Request(preliminary_get_data_request))
-                .flatten()
-                .map(move |response| (id, conn, response))
-        })
-        .and_then(move |(id, conn, preliminary_data_response)| {
-            debug!(
-                "Preliminary data response has {} items",
-                preliminary_data_response.data_set.len()
-                    + preliminary_data_response
-                        .persistable_network_payload_items
-                        .len()
-            );
-            let excluded_keys = get_excluded_keys(&preliminary_data_response);
-            dispatcher.dispatch(id, preliminary_data_response.into());
+    let seed_status = status.clone();
+    let received_keys = ReceivedKeys::default();
+    Connection::open(
+        seed_addr.clone(),
+        network.into(),
+        dispatch::chain(received_keys.clone()).forward_to(dispatcher.clone()),
+        transport,
+    )
+    .and_then(move |(id, conn)| {
+        seed_status.phase_reached(BootstrapPhase::SeedConnected);
+        debug!("Sending PreliminaryGetDataRequest to seed.");
+        conn.send(Request(preliminary_get_data_request))
+            .flatten()
+            .map(move |response| (id, conn, response))
+    })
+    .and_then(move |(id, conn, preliminary_data_response)| {
+        debug!(
+            "Preliminary data response has {} items",
+            preliminary_data_response.data_set.len()
+                + preliminary_data_response
+                    .persistable_network_payload_items
+                    .len()
+        );
+        let mut excluded_keys = get_excluded_keys(&preliminary_data_response);
+        excluded_keys.extend(received_keys.take());
+        excluded_keys.extend(known_keys);
+        dispatcher.dispatch(id, preliminary_data_response.into());
+        status.phase_reached(BootstrapPhase::PreliminaryDataReceived);
 
-            local_addr
-                .map(move |addr| {
-                    (
-                        GetUpdatedDataRequest {
-                            sender_node_address: addr.into(),
-                            nonce: gen_nonce(),
-                            excluded_keys,
-                        },
-                        id,
-                        conn,
-                        dispatcher,
-                    )
-                })
-                .map_err(|e| e.into())
-        })
-        .and_then(|(request, id, conn, dispatcher)| {
-            debug!("Sending GetUpdatedDataRequest to seed.");
-            conn.send(Request(request))
-                .flatten()
-                .map(move |get_updated_data_response| {
-                    debug!(
-                        "Update data response has {} items",
-                        get_updated_data_response.data_set.len()
-                            + get_updated_data_response
-                                .persistable_network_payload_items
-                                .len()
-                    );
-                    dispatcher.dispatch(id, get_updated_data_response.into());
-                    SeedResult {
-                        connection_id: id,
-                        connection: conn,
-                    }
-                })
-        })
+        local_addrs
+            .map(move |addrs| {
+                (
+                    GetUpdatedDataRequest {
+                        sender_node_address: Some(addrs.for_peer(Some(&seed_addr))),
+                        nonce: gen_nonce(),
+                        excluded_keys,
+                    },
+                    id,
+                    conn,
+                    dispatcher,
+                    status,
+                )
+            })
+            .map_err(|_| Error::ReceiveOneshotError)
+    })
+    .and_then(|(request, id, conn, dispatcher, status)| {
+        debug!("Sending GetUpdatedDataRequest to seed.");
+        conn.send(Request(request))
+            .flatten()
+            .map(move |get_updated_data_response| {
+                debug!(
+                    "Update data response has {} items",
+                    get_updated_data_response.data_set.len()
+                        + get_updated_data_response
+                            .persistable_network_payload_items
+                            .len()
+                );
+                dispatcher.dispatch(id, get_updated_data_response.into());
+                status.phase_reached(BootstrapPhase::UpdatedDataReceived);
+                SeedResult {
+                    connection_id: id,
+                    connection: conn,
+                }
+            })
+    })
 }
+// Malformed entries and payloads of the seed node are left out, they are
+// dropped when the response is routed.
 fn get_excluded_keys(preliminary_data_response: &GetDataResponse) -> Vec<Vec<u8>> {
     preliminary_data_response
         .data_set
         .iter()
-        .map(|w| w.message.as_ref().expect("Couldn't unwrap message"))
-        .map(|m| match m {
-            storage_entry_wrapper::Message::ProtectedStorageEntry(entry) => entry,
+        .filter_map(|w| match w.message.as_ref()? {
+            storage_entry_wrapper::Message::ProtectedStorageEntry(entry) => Some(entry),
             storage_entry_wrapper::Message::ProtectedMailboxStorageEntry(mailbox_entry) => {
-                mailbox_entry
-                    .entry
-                    .as_ref()
-                    .expect("Couldn't unwrap StorageEntry")
+                mailbox_entry.entry.as_ref()
             }
         })
-        .map(|entry| {
-            entry
-                .storage_payload
-                .as_ref()
-                .expect("Couldn't unwrap storage_payload")
-                .bisq_hash()
-                .into()
-        })
+        .filter_map(|entry| entry.storage_payload.as_ref())
+        .map(|payload| payload.bisq_hash().into())
         .chain(
             preliminary_data_response
                 .persistable_network_payload_items
                 .iter()
-                .map(PersistableNetworkPayload::bisq_hash)
+                .filter_map(|payload| payload.bisq_hash().ok())
                 .map(Vec::<u8>::from),
         )
         .collect()
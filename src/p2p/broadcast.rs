@@ -2,11 +2,26 @@ use super::{
     connection::{Connection, ConnectionId, Payload},
     peers::event::ConnectionAdded,
 };
-use crate::{bisq::payload::network_envelope, prelude::*};
-use std::collections::HashMap;
+use crate::{
+    bisq::payload::{network_envelope, NetworkEnvelope},
+    prelude::*,
+};
+use prost::Message as _;
+use rand::{seq::SliceRandom, thread_rng};
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime},
+};
+
+/// Data from peers is relayed to this many random peers, like bisq's
+/// `BroadcastHandler` does for data it doesn't own.
+const MAX_RELAY_PEERS: usize = 5;
+/// How long a broadcast message is remembered to not send it twice
+const RECENT_BROADCAST_TTL: Duration = Duration::from_secs(10 * 60);
 
 pub struct Broadcaster {
     connections: HashMap<ConnectionId, WeakAddr<Connection>>,
+    recent_broadcasts: HashMap<sha256::Hash, SystemTime>,
 }
 impl Actor for Broadcaster {
     type Context = Context<Broadcaster>;
@@ -16,11 +31,83 @@ impl Broadcaster {
     pub fn start() -> Addr<Self> {
         Self {
             connections: HashMap::new(),
+            recent_broadcasts: HashMap::new(),
         }
         .start()
     }
+
+    // False if the same message was broadcast recently
+    fn remember(&mut self, message: &network_envelope::Message) -> bool {
+        let envelope = NetworkEnvelope {
+            message_version: 0,
+            message: Some(message.clone()),
+        };
+        let mut serialized = Vec::with_capacity(envelope.encoded_len());
+        envelope
+            .encode(&mut serialized)
+            .expect("Could not encode message");
+        let hash = sha256::Hash::hash(&serialized);
+        let now = SystemTime::now();
+        self.recent_broadcasts.retain(|_, at| {
+            now.duration_since(*at)
+                .map(|age| age < RECENT_BROADCAST_TTL)
+                .unwrap_or(true)
+        });
+        self.recent_broadcasts.insert(hash, now).is_none()
+    }
+
+    // Sends to `backup` instead when sending to `id` fails
+    fn send(
+        &mut self,
+        id: ConnectionId,
+        backup: Option<ConnectionId>,
+        message: network_envelope::Message,
+        ctx: &mut <Self as Actor>::Context,
+    ) {
+        let conn = match self.connections.get(&id).and_then(WeakAddr::upgrade) {
+            Some(conn) => conn,
+            None => {
+                self.connections.remove(&id);
+                if let Some(backup) = backup {
+                    self.send(backup, None, message, ctx);
+                }
+                return;
+            }
+        };
+        ctx.spawn(
+            fut::wrap_future(conn.send(Payload(message.clone())).flatten()).then(
+                move |result, broadcaster: &mut Self, ctx| {
+                    if let (Err(e), Some(backup)) = (result, backup) {
+                        debug!(
+                            "Broadcast to {:?} failed, sending to {:?}: {:?}",
+                            id, backup, e
+                        );
+                        broadcaster.send(backup, None, message, ctx);
+                    }
+                    fut::ok(())
+                },
+            ),
+        );
+    }
 }
 
+/// Our own messages go to all peers, data of others to a random few, each
+/// with a spare peer to fall back to.
+fn fan_out<T: Copy>(mut candidates: Vec<T>, own: bool) -> Vec<(T, Option<T>)> {
+    candidates.shuffle(&mut thread_rng());
+    let count = if own {
+        candidates.len()
+    } else {
+        MAX_RELAY_PEERS.min(candidates.len())
+    };
+    let spares = candidates.split_off(count);
+    candidates
+        .into_iter()
+        .zip(spares.into_iter().map(Some).chain(std::iter::repeat(None)))
+        .collect()
+}
+
+/// Broadcasts to the connected peers except the one the message came from.
 pub struct Broadcast<M: Into<network_envelope::Message>>(pub M, pub Option<ConnectionId>);
 impl<M> Message for Broadcast<M>
 where
@@ -33,27 +120,30 @@ where
     M: Into<network_envelope::Message> + Send + Clone,
 {
     type Result = ();
-    fn handle(&mut self, Broadcast(message, exclude): Broadcast<M>, _ctx: &mut Self::Context) {
-        self.connections.retain(|id, conn| {
-            conn.upgrade()
-                .map(|conn| match exclude {
-                    Some(exclude) if id == &exclude => (),
-                    _ => arbiter_spawn!(conn.send(Payload(message.clone()))),
-                })
-                .is_some()
-        });
+    fn handle(&mut self, Broadcast(message, exclude): Broadcast<M>, ctx: &mut Self::Context) {
+        let message = message.into();
+        if !self.remember(&message) {
+            return;
+        }
+        self.connections.retain(|_, conn| conn.upgrade().is_some());
+        let candidates = self
+            .connections
+            .keys()
+            .filter(|id| Some(**id) != exclude)
+            .cloned()
+            .collect();
+        for (id, backup) in fan_out(candidates, exclude.is_none()) {
+            self.send(id, backup, message.clone(), ctx);
+        }
     }
 }
-#[cfg(feature = "dummy-seed")]
 pub struct Direct<M: Into<network_envelope::Message>>(pub M, pub ConnectionId);
-#[cfg(feature = "dummy-seed")]
 impl<M> Message for Direct<M>
 where
     M: Into<network_envelope::Message>,
 {
     type Result = ();
 }
-#[cfg(feature = "dummy-seed")]
 impl<M: 'static> Handler<Direct<M>> for Broadcaster
 where
     M: Into<network_envelope::Message> + Send + Clone,
@@ -79,3 +169,24 @@ impl Handler<ConnectionAdded> for Broadcaster {
         self.connections.insert(id, conn);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relays_to_few_peers_with_spares() {
+        let own = fan_out((0..8).collect(), true);
+        assert_eq!(own.len(), 8);
+        assert!(own.iter().all(|(_, backup)| backup.is_none()));
+
+        let relayed = fan_out((0..8).collect(), false);
+        assert_eq!(relayed.len(), MAX_RELAY_PEERS);
+        let mut used: Vec<i32> = relayed
+            .iter()
+            .flat_map(|(id, backup)| std::iter::once(*id).chain(*backup))
+            .collect();
+        used.sort();
+        assert_eq!(used, (0..8).collect::<Vec<_>>());
+    }
+}
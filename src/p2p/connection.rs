@@ -1,24 +1,60 @@
+mod capabilities;
+mod data_response;
+mod faults;
 mod message_stream;
+mod rate_limit;
+mod send_queue;
+pub mod transport;
 
-use super::dispatch::{Dispatch, Dispatcher, SendableDispatcher};
+use super::{
+    backpressure::{self, Throttled},
+    dispatch::{Dispatch, Dispatcher, SendableDispatcher},
+    traffic::{self, Direction, Traffic},
+};
 use crate::{
-    bisq::{constants::CloseConnectionReason, correlation::*, payload::*},
-    error,
+    bisq::{
+        constants::{Capabilities, CloseConnectionReason},
+        correlation::*,
+        payload::{kind::MessageKind, *},
+    },
+    error, metrics,
     prelude::{
-        future::Either,
-        io::{flush, write_all},
+        future,
+        io::{self, flush, shutdown, write_all},
         net::TcpStream,
-        reactor::Handle,
-        sync::{mpsc, oneshot},
+        sync::oneshot,
         *,
     },
 };
-use message_stream::MessageStream;
+#[cfg(feature = "testkit")]
+pub use faults::{inject_faults, Faults};
+use faults::{ConnectionFaults, Injected};
+use message_stream::{MessageStream, Received};
 use prost::{encoding::encoded_len_varint, Message};
-use socks::Socks5Stream;
-use std::{collections::HashMap, net::ToSocketAddrs, thread};
+use rate_limit::Limits;
+pub use send_queue::limit_upload_rate;
+use send_queue::SendQueue;
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+use tokio::timer::Delay;
+use tracing::Span;
+use tracing_futures::Instrument;
+use transport::Transport;
 use uuid::Uuid;
 
+/// bisq's `Connection.MAX_PERMITTED_MESSAGE_SIZE`
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 10 * 1024 * 1024;
+static MAX_MESSAGE_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_MESSAGE_SIZE);
+
+/// Connections opened from now on are closed when their peer sends a
+/// message larger than `max_size` bytes, before it is read.
+pub fn limit_message_size(max_size: usize) {
+    MAX_MESSAGE_SIZE.store(max_size, Ordering::Relaxed);
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub struct ConnectionId(Uuid);
 impl ConnectionId {
@@ -33,71 +69,149 @@ impl From<ConnectionId> for String {
 }
 pub struct Connection {
     id: ConnectionId,
-    writer: mpsc::Sender<network_envelope::Message>,
+    queue: SendQueue,
     dispatcher: Box<dyn Dispatcher>,
     response_channels: HashMap<CorrelationId, oneshot::Sender<network_envelope::Message>>,
+    /// What the peer announced last, until then everything is sent
+    capabilities: Option<Capabilities>,
+    limits: Limits,
+    /// Of the envelopes we send
+    message_version: MessageVersion,
+    /// Set once the peer exceeded the limits or turned out to be on another
+    /// network, the connection is closing
+    closing: bool,
+    misbehavior: Option<Recipient<Misbehaved>>,
+    traffic: Traffic,
+    /// Around everything that is logged while handling the connection
+    span: Span,
 }
 impl Actor for Connection {
     type Context = Context<Connection>;
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        Traffic::close(&self.id);
+    }
 }
-impl StreamHandler<network_envelope::Message, error::Error> for Connection {
-    fn handle(&mut self, msg: network_envelope::Message, _ctx: &mut Self::Context) {
+impl StreamHandler<Received, error::Error> for Connection {
+    fn handle(&mut self, received: Received, ctx: &mut Self::Context) {
+        // Entered borrows the span, which would keep self borrowed for the whole handler
+        let span = self.span.clone();
+        let _span = span.enter();
+        let msg = match received {
+            Received::Message(message_version, msg) => {
+                if !self.accepts(message_version, ctx) {
+                    return;
+                }
+                msg
+            }
+            // Handed on as they arrive, the rest of the response answers the request
+            Received::DataBatch(message_version, batch) => {
+                // Part of an envelope that is still arriving
+                self.traffic.record(
+                    Direction::Received,
+                    MessageKind {
+                        message: "GetDataResponse",
+                        payload: None,
+                    },
+                    batch.encoded_len(),
+                );
+                if !self.accepts(message_version, ctx) {
+                    return;
+                }
+                if let Dispatch::Retained(_) = self.dispatcher.dispatch(self.id, batch.into()) {
+                    debug!("{:?} retained a batch of data", self.id)
+                }
+                return;
+            }
+        };
+        metrics::message_received(&msg);
+        self.traffic.record(
+            Direction::Received,
+            MessageKind::from(&msg),
+            traffic::envelope_len(msg.encoded_len()),
+        );
+        if self.closing {
+            return;
+        }
+        if !self.limits.allow(&msg, Instant::now()) {
+            warn!("{:?} exceeded the rate limits", self.id);
+            self.closing = true;
+            if let Some(misbehavior) = self.misbehavior.as_ref() {
+                let _ = misbehavior.do_send(Misbehaved(self.id, Misbehavior::Flooding));
+            }
+            ctx.notify(Shutdown(CloseConnectionReason::RuleViolation));
+            return;
+        }
+        if let Some(capabilities) = capabilities::announced(&msg) {
+            self.capabilities = Some(capabilities);
+        }
         if let Some(id) = Option::<CorrelationId>::from(&msg) {
             if let Some(channel) = self.response_channels.remove(&id) {
                 channel.send(msg).expect("Couldn't send response");
                 return;
             }
         }
+        if backpressure::sheds(&msg) {
+            metrics::message_shed(&msg);
+            return;
+        }
         if let Dispatch::Retained(msg) = self.dispatcher.dispatch(self.id, msg) {
             warn!("{:?} retained message: {:?}", self.id, msg)
         }
     }
 
+    fn error(&mut self, err: error::Error, _ctx: &mut Self::Context) -> Running {
+        let _span = self.span.enter();
+        if let error::Error::MessageTooLarge(size) = err {
+            warn!("{:?} announced a message of {} bytes", self.id, size);
+        }
+        Running::Stop
+    }
+
     fn finished(&mut self, ctx: &mut Self::Context) {
+        let _span = self.span.enter();
         info!("{:?} closed.", self.id);
         ctx.stop();
     }
 }
 
 impl Connection {
+    /// Whether to handle what arrived in an envelope of `message_version`.
+    /// Peers of other protocol versions are still understood as far as we
+    /// can, those of another network are disconnected like bisq does.
+    fn accepts(&mut self, message_version: i32, ctx: &mut Context<Self>) -> bool {
+        let mismatch = match self.message_version.mismatch(message_version) {
+            Some(mismatch) => mismatch,
+            None => return true,
+        };
+        metrics::version_mismatch(mismatch);
+        if self.traffic.version_mismatch(message_version) == 1 {
+            warn!(
+                "{:?} sent message version {}, ours is {}",
+                self.id,
+                message_version,
+                i32::from(self.message_version)
+            );
+        }
+        if mismatch != VersionMismatch::OtherNetwork {
+            return true;
+        }
+        if !self.closing {
+            self.closing = true;
+            ctx.notify(Shutdown(CloseConnectionReason::RuleViolation));
+        }
+        false
+    }
+
     pub fn open<D: SendableDispatcher>(
         addr: NodeAddress,
         message_version: MessageVersion,
         dispatcher: D,
-        proxy_port: Option<u16>,
+        transport: &dyn Transport,
     ) -> impl Future<Item = (ConnectionId, Addr<Connection>), Error = error::Error> {
-        match proxy_port {
-            None => Either::A(
-                TcpStream::connect(&addr.to_socket_addrs().unwrap().next().unwrap())
-                    .map(move |tcp| Connection::from_tcp_stream(tcp, message_version, dispatcher))
-                    .map_err(|err| err.into()),
-            ),
-            Some(proxy_port) => {
-                let (send, receive) = oneshot::channel::<Result<Socks5Stream, error::Error>>();
-                thread::spawn(move || {
-                    send.send(
-                        Socks5Stream::connect(
-                            ("127.0.0.1", proxy_port),
-                            (addr.host_name.as_str(), addr.port as u16),
-                        )
-                        .map_err(|e| e.into()),
-                    )
-                    .expect("Couldn't send Socks5Stream");
-                });
-                Either::B(
-                    receive
-                        .map_err(error::Error::from)
-                        .flatten()
-                        .and_then(|stream| {
-                            TcpStream::from_std(stream.into_inner(), &Handle::default())
-                                .map_err(|e| e.into())
-                        })
-                        .map(move |tcp| {
-                            Connection::from_tcp_stream(tcp, message_version, dispatcher)
-                        }),
-                )
-            }
-        }
+        transport
+            .connect(&addr)
+            .map(move |tcp| Connection::from_tcp_stream(tcp, message_version, dispatcher))
     }
     pub fn from_tcp_stream<D: SendableDispatcher>(
         connection: TcpStream,
@@ -105,45 +219,93 @@ impl Connection {
         dispatcher: D,
     ) -> (ConnectionId, Addr<Connection>) {
         let (reader, writer) = connection.split();
-        let (send, rec) = mpsc::channel(10);
+        let (queue, scheduled) = SendQueue::new();
         let id = ConnectionId::new();
-        arbiter_spawn!(future::loop_fn((rec, writer), move |(rec, writer)| {
-            rec.into_future()
-                .map_err(|(e, _)| e.into())
-                .and_then(|(msg, rec)| {
-                    msg.ok_or(error::Error::ReceiveMPSCError)
-                        .map(|msg| (msg, rec))
-                })
-                .and_then(move |(msg, rec)| {
-                    debug!("Sending message {:?}", msg);
-                    let envelope = NetworkEnvelope {
-                        message_version: message_version.into(),
-                        message: Some(msg),
-                    };
-                    let len = envelope.encoded_len();
-                    let required = len + encoded_len_varint(len as u64);
-                    let mut serialized = Vec::with_capacity(required);
-                    envelope
-                        .encode_length_delimited(&mut serialized)
-                        .expect("Could not encode message");
-                    write_all(writer, serialized)
-                        .and_then(|(writer, _)| flush(writer))
-                        .then(|writer| match writer {
-                            Ok(writer) => Ok(Loop::Continue((rec, writer))),
-                            Err(e) => Ok(Loop::Break(e)),
+        let traffic = Traffic::open(id);
+        let sent = traffic.clone();
+        let span = tracing::info_span!("connection", id = %String::from(id));
+        arbiter_spawn!(future::loop_fn(
+            (scheduled, writer, ConnectionFaults::for_connection()),
+            move |(scheduled, writer, mut faults)| {
+                let sent = sent.clone();
+                scheduled
+                    .into_future()
+                    .map_err(|(e, _)| e)
+                    .and_then(|(msg, scheduled)| {
+                        msg.ok_or(error::Error::ReceiveMPSCError)
+                            .map(|msg| (msg, scheduled))
+                    })
+                    .and_then(move |(msg, scheduled)| {
+                        debug!("Sending message {:?}", msg);
+                        let kind = MessageKind::from(&msg);
+                        let envelope = NetworkEnvelope {
+                            message_version: message_version.into(),
+                            message: Some(msg),
+                        };
+                        let len = envelope.encoded_len();
+                        let required = len + encoded_len_varint(len as u64);
+                        sent.record(Direction::Sent, kind, required);
+                        let mut serialized = Vec::with_capacity(required);
+                        envelope
+                            .encode_length_delimited(&mut serialized)
+                            .expect("Could not encode message");
+                        let Injected {
+                            frames,
+                            delay,
+                            disconnect,
+                        } = faults.inject(serialized, required - len);
+                        // Over the upload limit the most urgent message waits for
+                        // its turn, the others stay queued
+                        let wait = send_queue::reserve_upload(required) + delay;
+                        let turn = if wait == Default::default() {
+                            future::Either::A(future::ok(()))
+                        } else {
+                            future::Either::B(Delay::new(Instant::now() + wait).then(|_| Ok(())))
+                        };
+                        turn.and_then(move |()| {
+                            stream::iter_ok(frames).fold(writer, |writer, frame| {
+                                write_all(writer, frame).map(|(writer, _)| writer)
+                            })
+                        })
+                        .and_then(flush)
+                        .then(move |writer| match writer {
+                            Ok(writer) if disconnect => {
+                                future::Either::A(shutdown(writer).then(|_| {
+                                    Ok(Loop::Break(io::Error::new(
+                                        io::ErrorKind::ConnectionAborted,
+                                        "Injected disconnect",
+                                    )))
+                                }))
+                            }
+                            Ok(writer) => future::Either::B(future::ok(Loop::Continue((
+                                scheduled, writer, faults,
+                            )))),
+                            Err(e) => future::Either::B(future::ok(Loop::Break(e))),
                         })
-                })
-                .map_err(|_| ())
-        }));
+                    })
+                    .map_err(|_| ())
+            },
+        )
+        .instrument(span.clone()));
         (
             id,
             Connection::create(move |ctx| {
-                ctx.add_stream(MessageStream::new(reader));
+                ctx.add_stream(Throttled::new(MessageStream::new(
+                    reader,
+                    MAX_MESSAGE_SIZE.load(Ordering::Relaxed),
+                )));
                 Connection {
                     id,
-                    writer: send,
+                    queue,
                     dispatcher: Box::new(dispatcher),
                     response_channels: HashMap::new(),
+                    capabilities: None,
+                    limits: Limits::new(),
+                    message_version,
+                    closing: false,
+                    misbehavior: None,
+                    traffic,
+                    span,
                 }
             }),
         )
@@ -161,6 +323,32 @@ impl<D: SendableDispatcher> Handler<SetDispatcher<D>> for Connection {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+pub enum Misbehavior {
+    /// Exceeded the rate limits, the connection was closed
+    Flooding,
+    /// Sent this many entries whose signature didn't verify
+    InvalidPayloads(usize),
+    /// Sent this many payloads that can't even be hashed, bisq doesn't
+    /// send them
+    MalformedPayloads(usize),
+}
+pub struct Misbehaved(pub ConnectionId, pub Misbehavior);
+impl actix::Message for Misbehaved {
+    type Result = ();
+}
+/// Where to report what the peer of a connection does wrong.
+pub struct ReportMisbehavior(pub Recipient<Misbehaved>);
+impl actix::Message for ReportMisbehavior {
+    type Result = ();
+}
+impl Handler<ReportMisbehavior> for Connection {
+    type Result = ();
+    fn handle(&mut self, ReportMisbehavior(to): ReportMisbehavior, _ctx: &mut Self::Context) {
+        self.misbehavior = Some(to);
+    }
+}
+
 pub struct Payload<M: Into<network_envelope::Message>>(pub M);
 impl<M> actix::Message for Payload<M>
 where
@@ -174,13 +362,18 @@ where
 {
     type Result = Box<dyn Future<Item = (), Error = error::Error>>;
     fn handle(&mut self, Payload(msg): Payload<M>, _ctx: &mut Self::Context) -> Self::Result {
-        Box::new(
-            self.writer
-                .clone()
-                .sink_from_err::<error::Error>()
-                .send(msg.into())
-                .map(|_| ()),
-        )
+        let _span = self.span.enter();
+        let msg = match self.capabilities {
+            Some(capabilities) => match capabilities::supported(msg.into(), capabilities) {
+                Some(msg) => msg,
+                None => {
+                    debug!("{:?} doesn't support the message, not sending it", self.id);
+                    return Box::new(future::ok(()));
+                }
+            },
+            None => msg.into(),
+        };
+        Box::new(self.queue.send(msg))
     }
 }
 pub struct Request<M: Into<network_envelope::Message> + ResponseExtractor>(pub M);
@@ -201,33 +394,27 @@ where
             Option::<CorrelationId>::from(&msg).expect("Request without correlation_id");
         let (send, receive) = oneshot::channel::<network_envelope::Message>();
         self.response_channels.insert(correlation_id.clone(), send);
-        Box::new(
-            self.writer
-                .clone()
-                .sink_from_err::<error::Error>()
-                .send(msg)
-                .and_then(|_| {
-                    receive
-                        .map(<M as ResponseExtractor>::extract)
-                        .map_err(|e| e.into())
-                }),
-        )
+        Box::new(self.queue.send(msg).and_then(|()| {
+            receive
+                .map(<M as ResponseExtractor>::extract)
+                .map_err(|e| e.into())
+        }))
     }
 }
 pub struct Shutdown(pub CloseConnectionReason);
 impl actix::Message for Shutdown {
-    type Result = ();
+    type Result = Result<(), ()>;
 }
+/// Resolves once the `CloseConnectionMessage` was sent.
 impl Handler<Shutdown> for Connection {
-    type Result = ();
-    fn handle(&mut self, Shutdown(reason): Shutdown, ctx: &mut Self::Context) {
+    type Result = ResponseActFuture<Self, (), ()>;
+    fn handle(&mut self, Shutdown(reason): Shutdown, _ctx: &mut Self::Context) -> Self::Result {
+        let _span = self.span.enter();
         let reason: String = reason.into();
         info!("Shutting down {:?} because {}", self.id, reason);
-        ctx.spawn(
+        Box::new(
             fut::wrap_future(
-                self.writer
-                    .clone()
-                    .sink_from_err::<error::Error>()
+                self.queue
                     .send(CloseConnectionMessage { reason }.into())
                     .then(|_| Ok(())),
             )
@@ -235,6 +422,6 @@ impl Handler<Shutdown> for Connection {
                 ctx.stop();
                 fut::ok(())
             }),
-        );
+        )
     }
 }
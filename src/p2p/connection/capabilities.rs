@@ -0,0 +1,129 @@
+use crate::bisq::{
+    constants::{Capabilities, Capability},
+    payload::{kind::*, *},
+};
+
+/// The capabilities a peer announces with some of its messages.
+pub(super) fn announced(msg: &network_envelope::Message) -> Option<Capabilities> {
+    let ordinals = match msg {
+        network_envelope::Message::PreliminaryGetDataRequest(msg) => &msg.supported_capabilities,
+        network_envelope::Message::GetDataResponse(msg) => &msg.supported_capabilities,
+        network_envelope::Message::GetPeersRequest(msg) => &msg.supported_capabilities,
+        network_envelope::Message::GetPeersResponse(msg) => &msg.supported_capabilities,
+        network_envelope::Message::OfferAvailabilityRequest(msg) => &msg.supported_capabilities,
+        network_envelope::Message::OfferAvailabilityResponse(msg) => &msg.supported_capabilities,
+        network_envelope::Message::GetBlocksRequest(msg) => &msg.supported_capabilities,
+        _ => return None,
+    };
+    Some(Capabilities::from(&ordinals[..]))
+}
+
+/// What of `msg` a peer with `capabilities` accepts, like bisq's
+/// `Connection.noCapabilityRequiredOrCapabilityIsSupported`. Data responses
+/// lose the entries the peer doesn't support.
+pub(super) fn supported(
+    msg: network_envelope::Message,
+    capabilities: Capabilities,
+) -> Option<network_envelope::Message> {
+    let msg = match msg {
+        network_envelope::Message::GetDataResponse(mut response) => {
+            response
+                .data_set
+                .retain(|entry| capabilities.contains_all(wrapper_requires(entry)));
+            response
+                .persistable_network_payload_items
+                .retain(|payload| {
+                    capabilities.contains_all(
+                        PersistableNetworkPayloadKind::from(payload).required_capabilities(),
+                    )
+                });
+            return Some(response.into());
+        }
+        msg => msg,
+    };
+    let required = match &msg {
+        network_envelope::Message::AddDataMessage(msg) => {
+            msg.entry.as_ref().map(wrapper_requires).unwrap_or(&[])
+        }
+        network_envelope::Message::RemoveDataMessage(msg) => msg
+            .protected_storage_entry
+            .as_ref()
+            .map(|entry| StoragePayloadKind::from(entry).required_capabilities())
+            .unwrap_or(&[]),
+        network_envelope::Message::AddPersistableNetworkPayloadMessage(msg) => msg
+            .payload
+            .as_ref()
+            .map(|payload| PersistableNetworkPayloadKind::from(payload).required_capabilities())
+            .unwrap_or(&[]),
+        network_envelope::Message::NewBlockBroadcastMessage(_) => &[Capability::ReceiveBsqBlock],
+        network_envelope::Message::AckMessage(_) => &[Capability::AckMsg],
+        network_envelope::Message::NewDaoStateHashMessage(_)
+        | network_envelope::Message::NewProposalStateHashMessage(_) => &[Capability::DaoState],
+        _ => &[],
+    };
+    if capabilities.contains_all(required) {
+        Some(msg)
+    } else {
+        None
+    }
+}
+
+fn wrapper_requires(wrapper: &StorageEntryWrapper) -> &'static [Capability] {
+    match wrapper.message.as_ref() {
+        Some(storage_entry_wrapper::Message::ProtectedStorageEntry(entry)) => {
+            StoragePayloadKind::from(entry).required_capabilities()
+        }
+        _ => &[],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(payload: storage_payload::Message) -> StorageEntryWrapper {
+        StorageEntryWrapper {
+            message: Some(storage_entry_wrapper::Message::ProtectedStorageEntry(
+                ProtectedStorageEntry {
+                    storage_payload: Some(StoragePayload {
+                        message: Some(payload),
+                    }),
+                    ..Default::default()
+                },
+            )),
+        }
+    }
+
+    #[test]
+    fn filters_unsupported_payloads() {
+        let capabilities: Capabilities = "0, 1, 12".parse().unwrap();
+        assert!(capabilities.contains(Capability::Mediation));
+        assert!(!capabilities.contains(Capability::RefundAgent));
+        assert_eq!(capabilities.to_string(), "0, 1, 12");
+
+        let offer = entry(storage_payload::Message::OfferPayload(Default::default()));
+        let refund_agent = entry(storage_payload::Message::RefundAgent(Default::default()));
+        let response = GetDataResponse {
+            data_set: vec![offer.clone(), refund_agent.clone()],
+            ..Default::default()
+        };
+        assert_eq!(
+            supported(response.into(), capabilities),
+            Some(
+                GetDataResponse {
+                    data_set: vec![offer],
+                    ..Default::default()
+                }
+                .into()
+            )
+        );
+        let add = AddDataMessage {
+            entry: Some(refund_agent),
+        };
+        assert_eq!(supported(add.clone().into(), capabilities), None);
+        assert_eq!(
+            supported(add.clone().into(), "12, 13".parse().unwrap()),
+            Some(add.into())
+        );
+    }
+}
@@ -0,0 +1,231 @@
+use crate::{bisq::payload::*, error};
+use prost::Message;
+use std::{io, mem};
+
+/// Entries and payloads handed on at once while a response still arrives.
+const BATCH_SIZE: usize = 1000;
+
+const MESSAGE_VERSION_TAG: u64 = 1;
+// Of the `message` of a `NetworkEnvelope`
+const GET_DATA_RESPONSE_TAG: u64 = 3;
+const VARINT: u64 = 0;
+const LENGTH_DELIMITED: u64 = 2;
+
+pub(super) enum Start {
+    /// Too little of the envelope was read to tell
+    Undecided,
+    /// The envelope holds another message
+    Other,
+    /// The response starts after this many bytes of the envelope
+    DataResponse(DataResponseDecoder, usize),
+}
+
+/// Whether the envelope starting with `envelope` holds a `GetDataResponse`.
+pub(super) fn start(envelope: &[u8]) -> Result<Start, error::Error> {
+    let mut pos = 0;
+    let mut message_version = 0;
+    loop {
+        let (key, key_len) = match varint(&envelope[pos..])? {
+            Some(key) => key,
+            None => return Ok(Start::Undecided),
+        };
+        match (key >> 3, key & 7) {
+            (GET_DATA_RESPONSE_TAG, LENGTH_DELIMITED) => {
+                return Ok(match varint(&envelope[pos + key_len..])? {
+                    Some((len, len_len)) => Start::DataResponse(
+                        DataResponseDecoder {
+                            left: len as usize,
+                            message_version,
+                            response: GetDataResponse::default(),
+                        },
+                        pos + key_len + len_len,
+                    ),
+                    None => Start::Undecided,
+                });
+            }
+            (MESSAGE_VERSION_TAG, VARINT) => match varint(&envelope[pos + key_len..])? {
+                Some((version, len)) => {
+                    message_version = version as i32;
+                    pos += key_len + len;
+                }
+                None => return Ok(Start::Undecided),
+            },
+            _ => return Ok(Start::Other),
+        }
+    }
+}
+
+/// Decodes a `GetDataResponse` field by field as it arrives, so its entries
+/// can be handed on in batches instead of keeping all of them in memory
+/// until the last one was read.
+pub(super) struct DataResponseDecoder {
+    /// Bytes of the response that weren't decoded yet
+    left: usize,
+    /// Of the envelope around the response
+    message_version: i32,
+    response: GetDataResponse,
+}
+impl DataResponseDecoder {
+    pub fn message_version(&self) -> i32 {
+        self.message_version
+    }
+
+    /// Decodes the complete fields `buf` starts with and removes them.
+    /// Batches are full responses without the capabilities.
+    pub fn decode(&mut self, buf: &mut Vec<u8>) -> Result<Vec<GetDataResponse>, error::Error> {
+        let mut batches = Vec::new();
+        let mut pos = 0;
+        while self.left > 0 {
+            let available = &buf[pos..buf.len().min(pos + self.left)];
+            let len = match field_len(available)? {
+                Some(len) => len,
+                None => break,
+            };
+            self.response.merge(&available[..len])?;
+            pos += len;
+            self.left -= len;
+            if self.response.data_set.len() + self.response.persistable_network_payload_items.len()
+                >= BATCH_SIZE
+            {
+                batches.push(GetDataResponse {
+                    request_nonce: self.response.request_nonce,
+                    is_get_updated_data_response: self.response.is_get_updated_data_response,
+                    data_set: mem::take(&mut self.response.data_set),
+                    supported_capabilities: Vec::new(),
+                    persistable_network_payload_items: mem::take(
+                        &mut self.response.persistable_network_payload_items,
+                    ),
+                });
+            }
+        }
+        buf.drain(..pos);
+        Ok(batches)
+    }
+
+    /// What wasn't handed on in a batch, once the envelope was read.
+    pub fn finish(self) -> Result<GetDataResponse, error::Error> {
+        if self.left > 0 {
+            return Err(invalid_data("Truncated GetDataResponse"));
+        }
+        Ok(self.response)
+    }
+}
+
+// The value and length of the varint `buf` starts with, None while incomplete
+fn varint(buf: &[u8]) -> Result<Option<(u64, usize)>, error::Error> {
+    let mut value = 0;
+    for (idx, byte) in buf.iter().take(10).enumerate() {
+        value |= u64::from(byte & 0x7f) << (7 * idx);
+        if byte & 0x80 == 0 {
+            return Ok(Some((value, idx + 1)));
+        }
+    }
+    if buf.len() >= 10 {
+        Err(invalid_data("Invalid varint"))
+    } else {
+        Ok(None)
+    }
+}
+
+// The length of the field `buf` starts with, None while incomplete
+fn field_len(buf: &[u8]) -> Result<Option<usize>, error::Error> {
+    let (key, key_len) = match varint(buf)? {
+        Some(key) => key,
+        None => return Ok(None),
+    };
+    let value_len = match key & 7 {
+        VARINT => match varint(&buf[key_len..])? {
+            Some((_, len)) => len,
+            None => return Ok(None),
+        },
+        1 => 8,
+        LENGTH_DELIMITED => match varint(&buf[key_len..])? {
+            Some((len, len_len)) => (len as usize)
+                .checked_add(len_len)
+                .ok_or_else(|| invalid_data("Invalid length"))?,
+            None => return Ok(None),
+        },
+        5 => 4,
+        _ => return Err(invalid_data("Unsupported wire type")),
+    };
+    Ok(key_len
+        .checked_add(value_len)
+        .filter(|len| *len <= buf.len()))
+}
+
+fn invalid_data(description: &str) -> error::Error {
+    io::Error::new(io::ErrorKind::InvalidData, description).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_responses_in_batches() {
+        let payload = PersistableNetworkPayload {
+            message: Some(persistable_network_payload::Message::AccountAgeWitness(
+                AccountAgeWitness {
+                    hash: vec![1; 20],
+                    date: 1_500_000_000_000,
+                },
+            )),
+        };
+        let response = GetDataResponse {
+            request_nonce: 42,
+            is_get_updated_data_response: false,
+            data_set: vec![StorageEntryWrapper::default(); 10],
+            supported_capabilities: vec![0, 1, 2],
+            persistable_network_payload_items: vec![payload; BATCH_SIZE + 10],
+        };
+        let envelope = NetworkEnvelope {
+            message_version: 10,
+            message: Some(response.clone().into()),
+        };
+        let mut encoded = Vec::new();
+        envelope.encode(&mut encoded).unwrap();
+
+        let mut buf = Vec::new();
+        let mut chunks = encoded.chunks(100);
+        let mut decoder = loop {
+            buf.extend_from_slice(chunks.next().unwrap());
+            match start(&buf).unwrap() {
+                Start::Undecided => continue,
+                Start::Other => panic!("Not recognized as GetDataResponse"),
+                Start::DataResponse(decoder, header_len) => {
+                    buf.drain(..header_len);
+                    break decoder;
+                }
+            }
+        };
+        assert_eq!(decoder.message_version(), 10);
+        let mut batches = decoder.decode(&mut buf).unwrap();
+        for chunk in chunks {
+            buf.extend_from_slice(chunk);
+            batches.extend(decoder.decode(&mut buf).unwrap());
+        }
+        assert!(buf.is_empty());
+        let rest = decoder.finish().unwrap();
+
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].request_nonce, 42);
+        assert_eq!(
+            batches[0].data_set.len() + batches[0].persistable_network_payload_items.len(),
+            BATCH_SIZE
+        );
+        assert_eq!(rest.supported_capabilities, response.supported_capabilities);
+        assert_eq!(
+            rest.data_set.len() + rest.persistable_network_payload_items.len(),
+            20
+        );
+
+        let mut ping = Vec::new();
+        NetworkEnvelope {
+            message_version: 10,
+            message: Some(Ping::default().into()),
+        }
+        .encode(&mut ping)
+        .unwrap();
+        assert!(matches!(start(&ping).unwrap(), Start::Other));
+    }
+}
@@ -0,0 +1,179 @@
+//! Faults injected into the frames connections write, to test how nodes
+//! cope with a bad network. Only the `testkit` feature can turn them on,
+//! otherwise every frame is written as it is.
+use std::time::Duration;
+
+/// What a connection writes for a frame.
+pub struct Injected {
+    /// Written one after the other, none when the frame was dropped
+    pub frames: Vec<Vec<u8>>,
+    /// Before writing them
+    pub delay: Duration,
+    /// Closes the connection once they are written
+    pub disconnect: bool,
+}
+impl Injected {
+    fn unchanged(frame: Vec<u8>) -> Self {
+        Injected {
+            frames: vec![frame],
+            delay: Duration::default(),
+            disconnect: false,
+        }
+    }
+}
+
+#[cfg(not(feature = "testkit"))]
+pub struct ConnectionFaults;
+#[cfg(not(feature = "testkit"))]
+impl ConnectionFaults {
+    pub fn for_connection() -> Self {
+        ConnectionFaults
+    }
+
+    pub fn inject(&mut self, frame: Vec<u8>, _header_len: usize) -> Injected {
+        Injected::unchanged(frame)
+    }
+}
+
+#[cfg(feature = "testkit")]
+pub use injection::*;
+
+#[cfg(feature = "testkit")]
+mod injection {
+    use super::Injected;
+    use lazy_static::lazy_static;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::{sync::Mutex, time::Duration};
+
+    lazy_static! {
+        // With the number of connections opened since they were set
+        static ref FAULTS: Mutex<Option<(Faults, u64)>> = Mutex::new(None);
+    }
+
+    /// The chance of each fault for every frame written, from 0 to 1.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct Faults {
+        /// The same seed injects the same faults, as long as the connections
+        /// are opened and write their frames in the same order
+        pub seed: u64,
+        pub drop: f64,
+        /// By up to `max_delay`, the frames after it wait as well
+        pub delay: f64,
+        pub max_delay: Duration,
+        pub duplicate: f64,
+        /// Flips a bit of the message, the length in front of it stays intact
+        pub corrupt: f64,
+        pub disconnect: f64,
+    }
+
+    /// Connections opened from now on inject `faults` into what they write,
+    /// `None` turns it off.
+    pub fn inject_faults(faults: Option<Faults>) {
+        *FAULTS.lock().expect("Corrupted lock in faults") = faults.map(|faults| (faults, 0));
+    }
+
+    pub struct ConnectionFaults(Option<(Faults, StdRng)>);
+    impl ConnectionFaults {
+        pub fn for_connection() -> Self {
+            let mut faults = FAULTS.lock().expect("Corrupted lock in faults");
+            ConnectionFaults(faults.as_mut().map(|(faults, opened)| {
+                *opened += 1;
+                let rng = StdRng::seed_from_u64(faults.seed ^ *opened);
+                (faults.clone(), rng)
+            }))
+        }
+
+        /// `frame` starts with the `header_len` bytes of its length.
+        pub fn inject(&mut self, mut frame: Vec<u8>, header_len: usize) -> Injected {
+            let (faults, rng) = match self.0.as_mut() {
+                Some(injecting) => injecting,
+                None => return Injected::unchanged(frame),
+            };
+            // Every chance is rolled for every frame, so the rng advances
+            // the same way whatever was injected before
+            let mut roll = |chance: f64| rng.gen::<f64>() < chance;
+            let (drop, delay, duplicate, corrupt, disconnect) = (
+                roll(faults.drop),
+                roll(faults.delay),
+                roll(faults.duplicate),
+                roll(faults.corrupt),
+                roll(faults.disconnect),
+            );
+            let delay_by = faults.max_delay.mul_f64(rng.gen::<f64>());
+            if corrupt && frame.len() > header_len {
+                let pos = rng.gen_range(header_len, frame.len());
+                frame[pos] ^= 1 << rng.gen_range(0, 8);
+            }
+            let frames = match (drop, duplicate) {
+                (true, _) => Vec::new(),
+                (false, true) => vec![frame.clone(), frame],
+                (false, false) => vec![frame],
+            };
+            Injected {
+                frames,
+                delay: if delay { delay_by } else { Duration::default() },
+                disconnect,
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn faults(opened: u64, faults: Faults) -> ConnectionFaults {
+            let rng = StdRng::seed_from_u64(faults.seed ^ opened);
+            ConnectionFaults(Some((faults, rng)))
+        }
+
+        fn run(faults: &mut ConnectionFaults) -> Vec<(Vec<Vec<u8>>, Duration, bool)> {
+            (0..100u8)
+                .map(|n| {
+                    let injected = faults.inject(vec![1, n, n, n], 1);
+                    (injected.frames, injected.delay, injected.disconnect)
+                })
+                .collect()
+        }
+
+        #[test]
+        fn injects_the_same_faults_with_the_same_seed() {
+            let config = Faults {
+                seed: 7,
+                drop: 0.1,
+                delay: 0.1,
+                max_delay: Duration::from_millis(500),
+                duplicate: 0.1,
+                corrupt: 0.1,
+                disconnect: 0.1,
+            };
+            let injected = run(&mut faults(1, config.clone()));
+            assert_eq!(injected, run(&mut faults(1, config.clone())));
+            assert_ne!(injected, run(&mut faults(2, config)));
+
+            assert!(injected.iter().any(|(frames, _, _)| frames.is_empty()));
+            assert!(injected.iter().any(|(frames, _, _)| frames.len() == 2));
+            assert!(injected
+                .iter()
+                .any(|(_, delay, _)| *delay > Duration::default()));
+            assert!(injected.iter().any(|(_, _, disconnect)| *disconnect));
+            assert!(injected.iter().all(|(frames, delay, _)| {
+                *delay <= Duration::from_millis(500) && frames.iter().all(|frame| frame[0] == 1)
+            }));
+            assert!(injected
+                .iter()
+                .enumerate()
+                .any(|(n, (frames, _, _))| frames.iter().any(|frame| frame[1..] != [n as u8; 3])));
+        }
+
+        #[test]
+        fn injects_nothing_without_chances() {
+            let injected = run(&mut faults(1, Faults::default()));
+            assert!(injected.iter().enumerate().all(|(n, injected)| *injected
+                == (
+                    vec![vec![1, n as u8, n as u8, n as u8]],
+                    Duration::default(),
+                    false
+                )));
+        }
+    }
+}
@@ -1,5 +1,6 @@
+use super::data_response::{self, DataResponseDecoder, Start};
 use crate::{
-    bisq::payload::{network_envelope, NetworkEnvelope},
+    bisq::payload::{network_envelope, GetDataResponse, NetworkEnvelope},
     error,
     prelude::{
         io::{AsyncRead, ReadHalf},
@@ -9,13 +10,31 @@ use crate::{
 };
 use futures::try_ready;
 use prost::{encoding::decode_varint, Message};
-use std::{collections::VecDeque, io};
+use std::{collections::VecDeque, io, mem};
 
+// Frames are read in chunks of this size, so only their size is buffered
+const READ_CHUNK: usize = 64 * 1024;
+
+pub enum Received {
+    /// With the message version of the envelope it arrived in
+    Message(i32, network_envelope::Message),
+    /// Entries of a `GetDataResponse` that is still arriving. Its last
+    /// entries and the capabilities follow as a `Message`.
+    DataBatch(i32, GetDataResponse),
+}
+
+enum Body {
+    Undecided,
+    Other,
+    DataResponse(DataResponseDecoder),
+}
 enum MessageStreamState {
     MessageInProgress {
-        size: usize,
-        pos: usize,
+        /// Bytes of the frame still to be read
+        unread: usize,
+        /// Read but not decoded yet
         buf: Vec<u8>,
+        body: Body,
     },
     BetweenMessages {
         buf: [u8; 10],
@@ -25,43 +44,62 @@ enum MessageStreamState {
 }
 pub struct MessageStream {
     reader: ReadHalf<TcpStream>,
+    max_size: usize,
     state: MessageStreamState,
     buffer: VecDeque<NetworkEnvelope>,
+    batches: VecDeque<(i32, GetDataResponse)>,
 }
 impl MessageStream {
-    pub fn new(reader: ReadHalf<TcpStream>) -> MessageStream {
+    /// Frames larger than `max_size` fail the stream before they are read.
+    pub fn new(reader: ReadHalf<TcpStream>, max_size: usize) -> MessageStream {
         MessageStream {
             reader,
+            max_size,
             state: MessageStreamState::BetweenMessages {
                 buf: [0; 10],
                 pos: 0,
             },
             buffer: VecDeque::new(),
+            batches: VecDeque::new(),
         }
     }
-    fn next_from_buffer(&mut self) -> Option<network_envelope::Message> {
-        let msg = self.buffer.pop_front()?.message;
-        match msg {
+    // Bundled envelopes count with the version of the bundle, that is
+    // what the peer's connection wrote
+    fn next_from_buffer(&mut self) -> Option<(i32, network_envelope::Message)> {
+        let NetworkEnvelope {
+            message_version,
+            message,
+        } = self.buffer.pop_front()?;
+        match message {
             Some(network_envelope::Message::BundleOfEnvelopes(msg)) => {
-                msg.envelopes
-                    .into_iter()
-                    .rev()
-                    .for_each(|envelope| self.buffer.push_front(envelope));
+                msg.envelopes.into_iter().rev().for_each(|envelope| {
+                    self.buffer.push_front(NetworkEnvelope {
+                        message_version,
+                        ..envelope
+                    })
+                });
                 self.next_from_buffer()
             }
             None => self.next_from_buffer(),
-            _ => msg,
+            Some(msg) => Some((message_version, msg)),
         }
     }
 }
 impl Stream for MessageStream {
-    type Item = network_envelope::Message;
+    type Item = Received;
     type Error = error::Error;
 
     fn poll(&mut self) -> Result<Async<Option<Self::Item>>, Self::Error> {
-        if let Some(msg) = self.next_from_buffer() {
+        if let Some((message_version, batch)) = self.batches.pop_front() {
+            debug!("Receiving {} data items", batch.data_set.len());
+            return Ok(Async::Ready(Some(Received::DataBatch(
+                message_version,
+                batch,
+            ))));
+        }
+        if let Some((message_version, msg)) = self.next_from_buffer() {
             debug!("Receiving msg: {:?}", msg);
-            return Ok(Async::Ready(Some(msg)));
+            return Ok(Async::Ready(Some(Received::Message(message_version, msg))));
         }
         let next_read = match self.state {
             MessageStreamState::Empty => panic!("Stream is already finished"),
@@ -85,30 +123,82 @@ impl Stream for MessageStream {
                 }
                 let mut size_reader: VecDeque<u8> = buf.iter().take(*pos).cloned().collect();
                 let size = decode_varint(&mut size_reader)? as usize;
-                let buf = vec![0; size];
-                self.state = MessageStreamState::MessageInProgress { size, pos: 0, buf };
+                if size > self.max_size {
+                    self.state = MessageStreamState::Empty;
+                    return Err(error::Error::MessageTooLarge(size));
+                }
+                self.state = MessageStreamState::MessageInProgress {
+                    unread: size,
+                    buf: Vec::new(),
+                    body: Body::Undecided,
+                };
                 return self.poll();
             }
             MessageStreamState::MessageInProgress {
-                ref mut size,
-                ref mut pos,
+                ref mut unread,
                 ref mut buf,
+                ref mut body,
             } => {
-                while *pos < *size {
-                    let n = try_ready!(self.reader.poll_read(&mut buf[*pos..]));
-                    *pos += n;
+                while *unread > 0 {
+                    let read = buf.len();
+                    buf.resize(read + READ_CHUNK.min(*unread), 0);
+                    let n = match self.reader.poll_read(&mut buf[read..]) {
+                        Ok(Async::Ready(n)) => n,
+                        Ok(Async::NotReady) => {
+                            buf.truncate(read);
+                            return Ok(Async::NotReady);
+                        }
+                        Err(e) => {
+                            buf.truncate(read);
+                            return Err(e.into());
+                        }
+                    };
+                    buf.truncate(read + n);
+                    *unread -= n;
                     if n == 0 {
                         return Err(
                             io::Error::new(io::ErrorKind::UnexpectedEof, "early eof").into()
                         );
                     }
+                    if let Body::Undecided = body {
+                        match data_response::start(buf)? {
+                            Start::Undecided => (),
+                            Start::Other => *body = Body::Other,
+                            Start::DataResponse(decoder, header_len) => {
+                                buf.drain(..header_len);
+                                *body = Body::DataResponse(decoder);
+                            }
+                        }
+                    }
+                    if let Body::DataResponse(decoder) = body {
+                        let message_version = decoder.message_version();
+                        self.batches.extend(
+                            decoder
+                                .decode(buf)?
+                                .into_iter()
+                                .map(|batch| (message_version, batch)),
+                        );
+                        if !self.batches.is_empty() {
+                            return self.poll();
+                        }
+                    }
                 }
-                match NetworkEnvelope::decode(&*buf) {
+                let decoded = match mem::replace(body, Body::Undecided) {
+                    Body::DataResponse(decoder) => {
+                        let message_version = decoder.message_version();
+                        decoder.finish().map(|response| NetworkEnvelope {
+                            message_version,
+                            message: Some(response.into()),
+                        })
+                    }
+                    _ => NetworkEnvelope::decode(&*buf).map_err(error::Error::from),
+                };
+                match decoded {
                     Ok(res) => res,
                     Err(e) => {
                         self.state = MessageStreamState::Empty;
                         debug!("Decode error {:?}", e);
-                        return Err(e.into());
+                        return Err(e);
                     }
                 }
             }
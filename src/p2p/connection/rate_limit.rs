@@ -0,0 +1,84 @@
+use crate::bisq::payload::*;
+use std::time::{Duration, Instant};
+
+// bisq's `Connection` throttles at 200 messages per second as well
+const MAX_MESSAGES: (u32, Duration) = (200, Duration::from_secs(1));
+// Data requests are answered with everything we know
+const MAX_GET_DATA_REQUESTS: (u32, Duration) = (3, Duration::from_secs(60));
+
+struct RateLimit {
+    max: u32,
+    per: Duration,
+    window_start: Instant,
+    count: u32,
+}
+impl RateLimit {
+    fn new((max, per): (u32, Duration), now: Instant) -> Self {
+        Self {
+            max,
+            per,
+            window_start: now,
+            count: 0,
+        }
+    }
+
+    fn allow(&mut self, now: Instant) -> bool {
+        if now.duration_since(self.window_start) >= self.per {
+            self.window_start = now;
+            self.count = 0;
+        }
+        self.count += 1;
+        self.count <= self.max
+    }
+}
+
+/// What a peer may send over one connection.
+pub(super) struct Limits {
+    messages: RateLimit,
+    get_data_requests: RateLimit,
+}
+impl Limits {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            messages: RateLimit::new(MAX_MESSAGES, now),
+            get_data_requests: RateLimit::new(MAX_GET_DATA_REQUESTS, now),
+        }
+    }
+
+    pub fn allow(&mut self, msg: &network_envelope::Message, now: Instant) -> bool {
+        self.messages.allow(now)
+            && match msg {
+                network_envelope::Message::PreliminaryGetDataRequest(_)
+                | network_envelope::Message::GetUpdatedDataRequest(_) => {
+                    self.get_data_requests.allow(now)
+                }
+                _ => true,
+            }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_messages_per_window() {
+        let mut limits = Limits::new();
+        // Not before the windows started
+        let start = Instant::now();
+        let ping: network_envelope::Message = Ping::default().into();
+        let request: network_envelope::Message = PreliminaryGetDataRequest::default().into();
+        for _ in 0..MAX_GET_DATA_REQUESTS.0 {
+            assert!(limits.allow(&request, start));
+        }
+        assert!(!limits.allow(&request, start));
+        assert!(limits.allow(&ping, start));
+        for _ in MAX_GET_DATA_REQUESTS.0 + 2..MAX_MESSAGES.0 {
+            assert!(limits.allow(&ping, start));
+        }
+        assert!(!limits.allow(&ping, start));
+        assert!(limits.allow(&ping, start + MAX_MESSAGES.1));
+        assert!(limits.allow(&request, start + MAX_GET_DATA_REQUESTS.1));
+    }
+}
@@ -0,0 +1,194 @@
+use crate::{
+    bisq::payload::*,
+    error,
+    prelude::{sync::mpsc, *},
+};
+use lazy_static::lazy_static;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Messages of a priority that wait to be written before their senders
+/// have to wait as well
+const QUEUE_SIZE: usize = 10;
+/// Upload that wasn't used adds up to at most a second
+const BURST: Duration = Duration::from_secs(1);
+
+/// Bytes per second over all connections, 0 for no limit
+static UPLOAD_RATE: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static! {
+    static ref UPLOAD: Mutex<Upload> = Mutex::new(Upload::default());
+}
+
+/// Connections write at most `bytes_per_sec` together from now on.
+pub fn limit_upload_rate(bytes_per_sec: usize) {
+    UPLOAD_RATE.store(bytes_per_sec, Ordering::Relaxed);
+}
+
+/// How long to wait before writing `bytes`, messages are written whole
+/// once their turn came.
+pub(super) fn reserve_upload(bytes: usize) -> Duration {
+    match UPLOAD_RATE.load(Ordering::Relaxed) {
+        0 => Duration::default(),
+        rate => UPLOAD
+            .lock()
+            .expect("Corrupted lock in upload limit")
+            .reserve(bytes, rate, Instant::now()),
+    }
+}
+
+#[derive(Default)]
+struct Upload {
+    /// When everything reserved so far was written at the rate
+    written_at: Option<Instant>,
+}
+impl Upload {
+    fn reserve(&mut self, bytes: usize, rate: usize, now: Instant) -> Duration {
+        let start = self
+            .written_at
+            .map_or(now, |written_at| written_at.max(now));
+        let written_at = start + Duration::from_secs_f64(bytes as f64 / rate as f64);
+        self.written_at = Some(written_at);
+        (written_at - now).checked_sub(BURST).unwrap_or_default()
+    }
+}
+
+/// Order in which the queued messages of a connection are written.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) enum Priority {
+    /// Keep the connection alive or are addressed to the peer alone
+    Urgent,
+    Normal,
+    /// Answers to sync requests, sent in chunks of up to a few MB
+    Bulk,
+}
+impl From<&network_envelope::Message> for Priority {
+    fn from(msg: &network_envelope::Message) -> Self {
+        match msg {
+            network_envelope::Message::Ping(_)
+            | network_envelope::Message::Pong(_)
+            | network_envelope::Message::CloseConnectionMessage(_)
+            | network_envelope::Message::PrefixedSealedAndSignedMessage(_)
+            | network_envelope::Message::AckMessage(_) => Priority::Urgent,
+            network_envelope::Message::GetDataResponse(_)
+            | network_envelope::Message::GetBlocksResponse(_) => Priority::Bulk,
+            _ => Priority::Normal,
+        }
+    }
+}
+
+/// The sending side of the bounded queues of a connection, one for each
+/// priority.
+#[derive(Clone)]
+pub(super) struct SendQueue {
+    urgent: mpsc::Sender<network_envelope::Message>,
+    normal: mpsc::Sender<network_envelope::Message>,
+    bulk: mpsc::Sender<network_envelope::Message>,
+}
+impl SendQueue {
+    pub fn new() -> (Self, Scheduled) {
+        let (urgent, urgent_rec) = mpsc::channel(QUEUE_SIZE);
+        let (normal, normal_rec) = mpsc::channel(QUEUE_SIZE);
+        let (bulk, bulk_rec) = mpsc::channel(QUEUE_SIZE);
+        (
+            Self {
+                urgent,
+                normal,
+                bulk,
+            },
+            Scheduled([urgent_rec, normal_rec, bulk_rec]),
+        )
+    }
+
+    /// Resolves once the message was queued.
+    pub fn send(
+        &self,
+        msg: network_envelope::Message,
+    ) -> impl Future<Item = (), Error = error::Error> {
+        let queue = match Priority::from(&msg) {
+            Priority::Urgent => &self.urgent,
+            Priority::Normal => &self.normal,
+            Priority::Bulk => &self.bulk,
+        };
+        queue
+            .clone()
+            .sink_from_err::<error::Error>()
+            .send(msg)
+            .map(|_| ())
+    }
+}
+
+/// The queued messages of a connection, the most urgent first. Ends once
+/// every `SendQueue` was dropped.
+pub(super) struct Scheduled([mpsc::Receiver<network_envelope::Message>; 3]);
+impl Stream for Scheduled {
+    type Item = network_envelope::Message;
+    type Error = error::Error;
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let mut closed = 0;
+        for queue in self.0.iter_mut() {
+            match queue.poll()? {
+                Async::Ready(Some(msg)) => return Ok(Async::Ready(Some(msg))),
+                Async::Ready(None) => closed += 1,
+                Async::NotReady => (),
+            }
+        }
+        if closed == self.0.len() {
+            Ok(Async::Ready(None))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_urgent_messages_first() {
+        let (queue, scheduled) = SendQueue::new();
+        future::lazy(|| {
+            queue.send(GetDataResponse::default().into()).join3(
+                queue.send(GetPeersRequest::default().into()),
+                queue.send(Ping::default().into()),
+            )
+        })
+        .wait()
+        .unwrap();
+        drop(queue);
+        let priorities: Vec<Priority> = scheduled
+            .collect()
+            .wait()
+            .unwrap()
+            .iter()
+            .map(Priority::from)
+            .collect();
+        assert_eq!(
+            priorities,
+            vec![Priority::Urgent, Priority::Normal, Priority::Bulk]
+        );
+    }
+
+    #[test]
+    fn limits_the_upload_rate() {
+        let now = Instant::now();
+        let mut upload = Upload::default();
+        // A second of upload goes out right away
+        assert_eq!(upload.reserve(1_000, 1_000, now), Duration::default());
+        assert_eq!(upload.reserve(500, 1_000, now), Duration::from_millis(500));
+        assert_eq!(
+            upload.reserve(2_000, 1_000, now + Duration::from_secs(1)),
+            Duration::from_millis(1_500)
+        );
+        // Idle time doesn't add up beyond the burst
+        let later = now + Duration::from_secs(60);
+        assert_eq!(upload.reserve(1_000, 1_000, later), Duration::default());
+        assert_eq!(upload.reserve(1_000, 1_000, later), Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,189 @@
+use crate::{
+    bisq::payload::NodeAddress,
+    error,
+    prelude::{net::TcpStream, reactor::Handle, sync::oneshot, *},
+};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use socks::Socks5Stream;
+use std::{
+    io,
+    net::{SocketAddr, ToSocketAddrs},
+    sync::Arc,
+    thread,
+};
+
+pub type ConnectFuture = Box<dyn Future<Item = TcpStream, Error = error::Error>>;
+
+/// Opens the streams that connections to peers run on.
+pub trait Transport: Send + Sync {
+    fn connect(&self, addr: &NodeAddress) -> ConnectFuture;
+
+    /// Whether `addr` can be connected to at all.
+    fn reaches(&self, _addr: &NodeAddress) -> bool {
+        true
+    }
+}
+
+/// Through tor when a proxy port is given, clear TCP otherwise.
+pub fn for_proxy(proxy_port: Option<u16>) -> Arc<dyn Transport> {
+    match proxy_port {
+        Some(proxy_port) => Arc::new(Socks5::new(proxy_port)),
+        None => Arc::new(Direct),
+    }
+}
+
+/// For nodes that also listen on clearnet: tor for onion addresses, clear
+/// TCP for the peers that only have a clearnet address.
+pub fn dual_stack(proxy_port: Option<u16>) -> Arc<dyn Transport> {
+    match proxy_port {
+        Some(proxy_port) => Arc::new(DualStack(Socks5::new(proxy_port))),
+        None => Arc::new(Direct),
+    }
+}
+
+/// Clear TCP, onion addresses can't be reached.
+pub struct Direct;
+impl Transport for Direct {
+    fn connect(&self, addr: &NodeAddress) -> ConnectFuture {
+        Box::new(
+            future::result(resolve(addr))
+                .and_then(|socket_addr| TcpStream::connect(&socket_addr))
+                .map_err(error::Error::from),
+        )
+    }
+
+    fn reaches(&self, addr: &NodeAddress) -> bool {
+        !addr.is_onion()
+    }
+}
+
+/// Tor's SOCKS5 proxy. Every peer gets credentials of its own, so with tor's
+/// default `IsolateSOCKSAuth` each peer is connected to over a circuit of
+/// its own.
+pub struct Socks5 {
+    proxy_port: u16,
+    // Keeps the circuits of different risq processes apart
+    session: String,
+}
+impl Socks5 {
+    pub fn new(proxy_port: u16) -> Self {
+        Self {
+            proxy_port,
+            session: thread_rng().sample_iter(&Alphanumeric).take(16).collect(),
+        }
+    }
+}
+impl Transport for Socks5 {
+    fn connect(&self, addr: &NodeAddress) -> ConnectFuture {
+        let (send, receive) = oneshot::channel::<Result<Socks5Stream, error::Error>>();
+        let proxy_port = self.proxy_port;
+        let username = addr.to_string();
+        let password = self.session.clone();
+        let addr = addr.clone();
+        thread::spawn(move || {
+            let _ = send.send(
+                Socks5Stream::connect_with_password(
+                    ("127.0.0.1", proxy_port),
+                    (addr.host_name.as_str(), addr.port as u16),
+                    &username,
+                    &password,
+                )
+                .map_err(|e| e.into()),
+            );
+        });
+        Box::new(
+            receive
+                .map_err(error::Error::from)
+                .flatten()
+                .and_then(|stream| {
+                    TcpStream::from_std(stream.into_inner(), &Handle::default())
+                        .map_err(|e| e.into())
+                }),
+        )
+    }
+}
+
+pub struct DualStack(Socks5);
+impl Transport for DualStack {
+    fn connect(&self, addr: &NodeAddress) -> ConnectFuture {
+        if addr.is_onion() {
+            self.0.connect(addr)
+        } else {
+            Direct.connect(addr)
+        }
+    }
+}
+
+// Prefers IPv4, like a local bisq seed node listens on `localhost`
+fn resolve(addr: &NodeAddress) -> Result<SocketAddr, io::Error> {
+    let mut socket_addrs: Vec<SocketAddr> = addr.to_socket_addrs()?.collect();
+    socket_addrs.sort_by_key(|socket_addr| !socket_addr.is_ipv4());
+    socket_addrs.into_iter().next().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Couldn't resolve {}", addr),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bisq::{constants::BaseCurrencyNetwork, payload::network_envelope},
+        p2p::{
+            dispatch::{Dispatch, Dispatcher},
+            Connection, ConnectionId,
+        },
+    };
+    use std::net::TcpListener;
+
+    // Connects to a local listener whatever the address
+    struct Loopback(SocketAddr);
+    impl Transport for Loopback {
+        fn connect(&self, _addr: &NodeAddress) -> ConnectFuture {
+            Box::new(TcpStream::connect(&self.0).map_err(error::Error::from))
+        }
+    }
+
+    #[derive(Clone)]
+    struct Ignore;
+    impl Dispatcher for Ignore {
+        fn dispatch(&self, _conn: ConnectionId, _msg: network_envelope::Message) -> Dispatch {
+            Dispatch::Consumed
+        }
+    }
+
+    #[test]
+    fn resolves_clear_addresses_only() {
+        let seed: NodeAddress = "localhost:2002".parse().unwrap();
+        assert!(resolve(&seed).unwrap().is_ipv4());
+        assert!(resolve(&"peer.onion:9999".parse().unwrap()).is_err());
+        assert!(Direct.reaches(&seed));
+        assert!(!Direct.reaches(&"peer.onion:9999".parse().unwrap()));
+    }
+
+    #[test]
+    fn opens_connections_through_the_transport() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let transport = Loopback(listener.local_addr().unwrap());
+        let accepted = thread::spawn(move || listener.accept().is_ok());
+        System::run(move || {
+            Arbiter::spawn(
+                Connection::open(
+                    "peer.onion:9999".parse().unwrap(),
+                    BaseCurrencyNetwork::BtcRegtest.into(),
+                    Ignore,
+                    &transport,
+                )
+                .then(|result| {
+                    assert!(result.is_ok());
+                    System::current().stop();
+                    Ok(())
+                }),
+            );
+        })
+        .unwrap();
+        assert!(accepted.join().unwrap());
+    }
+}
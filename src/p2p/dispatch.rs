@@ -1,4 +1,4 @@
-use super::connection::ConnectionId;
+use super::{backpressure::Queue, connection::ConnectionId};
 use crate::{bisq::payload::*, prelude::*};
 use actix::dev::ToEnvelope;
 use std::marker::PhantomData;
@@ -24,6 +24,7 @@ where
     A: Actor + Handler<Receive<<M as PayloadExtractor>::Extraction>>,
 {
     addr: Addr<A>,
+    queue: Queue,
     phantom: PhantomData<M>,
 }
 impl<A, M> ActorDispatcher<A, M>
@@ -34,6 +35,7 @@ where
     pub fn new(addr: Addr<A>) -> Self {
         ActorDispatcher {
             addr,
+            queue: Queue::named(actor_name::<A>()),
             phantom: PhantomData,
         }
     }
@@ -47,7 +49,7 @@ where
     fn dispatch(&self, conn: ConnectionId, msg: network_envelope::Message) -> Dispatch {
         match <M as PayloadExtractor>::extract(msg) {
             Extract::Succeeded(extraction) => {
-                arbiter_spawn!(self.addr.send(Receive(conn, extraction)));
+                arbiter_spawn!(self.queue.track(self.addr.send(Receive(conn, extraction))));
                 Dispatch::Consumed
             }
             Extract::Failed(msg) => Dispatch::Retained(msg),
@@ -62,11 +64,20 @@ where
     fn clone(&self) -> Self {
         Self {
             addr: self.addr.clone(),
+            queue: self.queue.clone(),
             phantom: PhantomData,
         }
     }
 }
 
+// The queues are labeled by the type of their actor, without its path and
+// generic arguments, whose paths would end up in the label otherwise
+fn actor_name<A>() -> &'static str {
+    let name = std::any::type_name::<A>();
+    let name = name.split('<').next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name)
+}
+
 pub struct Chain<F: Dispatcher + Sized> {
     first: F,
 }
@@ -99,3 +110,17 @@ impl<F: Dispatcher + Sized, N: Dispatcher + Sized> Dispatcher for ForwardTo<F, N
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Router<T>(PhantomData<T>);
+
+    #[test]
+    fn names_actors_by_their_type() {
+        assert_eq!(actor_name::<Receive<u8>>(), "Receive");
+        assert_eq!(actor_name::<Router<std::sync::Arc<u8>>>(), "Router");
+        assert_eq!(actor_name::<u8>(), "u8");
+    }
+}
@@ -1,22 +1,34 @@
+mod backpressure;
+mod ban_list;
 mod bootstrap;
 mod broadcast;
 mod connection;
 mod peers;
 mod status;
 mod tor;
+mod traffic;
 
 pub mod dispatch;
 pub mod server;
 
+pub use backpressure::{queue_depths, Queue, MAILBOX_CAPACITY};
+pub use ban_list::BanList;
 pub use bootstrap::{Bootstrap, BootstrapState};
 pub use broadcast::Broadcaster;
-pub use connection::{Connection, ConnectionId, Request};
-pub use peers::Peers;
+#[cfg(feature = "testkit")]
+pub use connection::{inject_faults, Faults};
+pub use connection::{
+    limit_message_size, limit_upload_rate,
+    transport::{self, Transport},
+    Connection, ConnectionId, Misbehaved, Misbehavior, Payload, ReportMisbehavior, Request,
+    Shutdown, DEFAULT_MAX_MESSAGE_SIZE,
+};
+pub use peers::{GetKnownKeys, PeerStore, Peers, DEFAULT_MAX_CONNECTIONS};
 pub use server::TorConfig;
 pub use status::*;
+pub use tor::launch as launch_tor;
+pub use traffic::{Traffic, TrafficReport};
 
 pub mod message {
-    pub use super::broadcast::Broadcast;
-    #[cfg(feature = "dummy-seed")]
-    pub use super::broadcast::Direct;
+    pub use super::broadcast::{Broadcast, Direct};
 }
@@ -1,11 +1,13 @@
 mod keep_alive;
+mod store;
 
 use super::{
+    ban_list::BanList,
     broadcast::Broadcaster,
-    connection::*,
+    connection::{transport::Transport, *},
     dispatch::{self, ActorDispatcher, Receive, SendableDispatcher},
-    server::event::*,
-    status::Status,
+    server::{event::*, LocalAddrs},
+    status::{ConnectionKind, Status},
 };
 use crate::{
     bisq::{
@@ -14,23 +16,65 @@ use crate::{
         },
         payload::*,
     },
+    domain::filter::FilterStore,
     prelude::{fut::Either, *},
+    reload::ConfigUpdated,
+    scheduler::{Schedule, Task},
+    shutdown::{self, Stop},
 };
 use keep_alive::*;
+use rand::{thread_rng, Rng};
 use std::{
+    cmp::Reverse,
     collections::{HashMap, HashSet},
     convert::TryInto,
+    sync::Arc,
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
+use tracing_futures::Instrument;
 
-const CONSOLIDATE_CONNECTIONS: Duration = Duration::from_secs(60);
-const MAX_CONNECTIONS: usize = 12;
-const MIN_CONNECTIONS: usize = MAX_CONNECTIONS / 7 * 10;
+pub use store::{KnownPeer, PeerStore};
+
+const CONSOLIDATE_CONNECTIONS: Task = Task::new("consolidate_connections", Duration::from_secs(60));
+const PEER_EXCHANGE: Task = Task::new("peer_exchange", Duration::from_secs(10 * 60));
+const DATA_RESYNC: Task =
+    Task::new("data_resync", Duration::from_secs(30 * 60)).with_jitter(Duration::from_secs(5 * 60));
+// Asked for the data we missed on every resync
+const RESYNC_PEERS: usize = 2;
+pub const DEFAULT_MAX_CONNECTIONS: usize = 12;
+const MIN_CONNECTIONS: usize = DEFAULT_MAX_CONNECTIONS / 7 * 10;
+// Limits of bisq's PeerManager
+const MAX_PERSISTED_PEERS: usize = 500;
+const MAX_FAILED_CONNECTION_ATTEMPTS: u32 = 5;
+const MAX_PEER_AGE: Duration = Duration::from_secs(14 * 24 * 60 * 60);
+// Opening a connection through a broken tor circuit can hang for a long time
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2 * 60);
+const RECONNECT_BACKOFF: Duration = Duration::from_secs(30);
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(60 * 60);
+// Bisq nodes don't relay entries with bad signatures, a few might still be
+// left from before a key rotation
+const MAX_INVALID_PAYLOADS: usize = 10;
+const BAN_DURATION: Duration = Duration::from_secs(60 * 60);
+
+/// Asks for the keys of the data we hold, to exclude them from data requests.
+pub struct GetKnownKeys;
+impl Message for GetKnownKeys {
+    type Result = Vec<Vec<u8>>;
+}
+
+struct PeerConnection {
+    addr: Addr<Connection>,
+    kind: ConnectionKind,
+    last_active: SystemTime,
+}
 
 struct PeerInfo {
     reported_alive_at: SystemTime,
     gossiped_capabilities: Option<Vec<Capability>>,
     reported_capabilities: Option<Vec<Capability>>,
+    failed_connection_attempts: u32,
+    /// Not before this after a failed connection attempt
+    reconnect_at: Option<SystemTime>,
 }
 impl From<(NodeAddress, &PeerInfo)> for Peer {
     fn from((addr, info): (NodeAddress, &PeerInfo)) -> Peer {
@@ -44,24 +88,58 @@ impl From<(NodeAddress, &PeerInfo)> for Peer {
             supported_capabilities: info
                 .reported_capabilities
                 .as_ref()
-                .or_else(|| info.gossiped_capabilities.as_ref())
+                .or(info.gossiped_capabilities.as_ref())
                 .map(|v| v.iter().map(|c| *c as i32).collect())
                 .unwrap_or_default(),
         }
     }
 }
 
+fn known_peer_info(known: KnownPeer) -> Option<(NodeAddress, PeerInfo)> {
+    let peer = known.peer?;
+    Some((
+        peer.node_address?,
+        PeerInfo {
+            reported_alive_at: UNIX_EPOCH + Duration::from_millis(peer.date as u64),
+            gossiped_capabilities: Some(capabilities(peer.supported_capabilities)),
+            reported_capabilities: None,
+            failed_connection_attempts: known.failed_connection_attempts,
+            reconnect_at: None,
+        },
+    ))
+}
+
+fn capabilities(capabilities: Vec<i32>) -> Vec<Capability> {
+    capabilities
+        .into_iter()
+        .filter_map(|i| i.try_into().ok())
+        .collect()
+}
+
 pub struct Peers<D: SendableDispatcher> {
     keep_alive: Addr<KeepAlive>,
     broadcaster: Addr<Broadcaster>,
     network: BaseCurrencyNetwork,
-    connections: HashMap<ConnectionId, Addr<Connection>>,
+    connections: HashMap<ConnectionId, PeerConnection>,
+    /// Addresses we are opening connections to
+    connecting: HashSet<NodeAddress>,
     identified_connections: HashMap<ConnectionId, NodeAddress>,
     peer_infos: HashMap<NodeAddress, PeerInfo>,
-    local_addr: Option<NodeAddress>,
+    local_addrs: Option<LocalAddrs>,
     dispatcher: D,
-    proxy_port: Option<u16>,
+    transport: Arc<dyn Transport>,
     status: Status,
+    max_connections: usize,
+    store: Option<PeerStore>,
+    filters: FilterStore,
+    ban_list: BanList,
+    /// Entries with bad signatures received over each connection
+    invalid_payloads: HashMap<ConnectionId, usize>,
+    /// Peers that misbehaved, until when they are banned
+    bans: HashMap<NodeAddress, SystemTime>,
+    known_keys: Option<Recipient<GetKnownKeys>>,
+    /// Peers asked in the current round of data resyncs
+    resynced_from: HashSet<NodeAddress>,
 }
 
 impl<D: SendableDispatcher> Peers<D> {
@@ -70,19 +148,37 @@ impl<D: SendableDispatcher> Peers<D> {
         broadcaster: Addr<Broadcaster>,
         status: Status,
         dispatcher: D,
-        proxy_port: Option<u16>,
+        transport: Arc<dyn Transport>,
+        max_connections: usize,
+        known_peers: Vec<KnownPeer>,
+        store: Option<PeerStore>,
+        filters: FilterStore,
+        ban_list: BanList,
+        known_keys: Option<Recipient<GetKnownKeys>>,
     ) -> Addr<Self> {
         Self {
             keep_alive: KeepAlive::start(),
             broadcaster,
             network,
             connections: HashMap::new(),
+            connecting: HashSet::new(),
             identified_connections: HashMap::new(),
-            peer_infos: HashMap::new(),
-            local_addr: None,
+            peer_infos: known_peers
+                .into_iter()
+                .filter_map(known_peer_info)
+                .collect(),
+            local_addrs: None,
             dispatcher,
-            proxy_port,
+            transport,
             status,
+            max_connections,
+            store,
+            filters,
+            ban_list,
+            invalid_payloads: HashMap::new(),
+            bans: HashMap::new(),
+            known_keys,
+            resynced_from: HashSet::new(),
         }
         .start()
     }
@@ -101,14 +197,28 @@ impl<D: SendableDispatcher> Peers<D> {
         id: ConnectionId,
         conn: Addr<Connection>,
         addr: Option<NodeAddress>,
+        kind: ConnectionKind,
+        ctx: &mut <Self as Actor>::Context,
     ) {
-        info!("Adding {:?} @ {:?}", id, addr);
+        info!("Adding {:?} {:?} @ {:?}", kind, id, addr);
+        arbiter_spawn!(conn.send(ReportMisbehavior(ctx.address().recipient())));
         let for_keep_alive = conn.downgrade();
         let for_broadcaster = conn.downgrade();
-        self.connections.insert(id, conn);
-        self.status.connection_added(id, addr.clone());
+        self.connections.insert(
+            id,
+            PeerConnection {
+                addr: conn,
+                kind,
+                last_active: SystemTime::now(),
+            },
+        );
+        self.status.connection_added(id, addr.clone(), kind);
         if let Some(addr) = addr.as_ref() {
-            self.update_peer_info(&addr, SystemTime::now(), None, None);
+            self.update_peer_info(addr, SystemTime::now(), None, None);
+            if let Some(info) = self.peer_infos.get_mut(addr) {
+                info.failed_connection_attempts = 0;
+                info.reconnect_at = None;
+            }
             self.identified_connections.insert(id, addr.clone());
         }
         arbiter_spawn!(self
@@ -117,6 +227,7 @@ impl<D: SendableDispatcher> Peers<D> {
         arbiter_spawn!(self
             .broadcaster
             .send(event::ConnectionAdded(id, for_broadcaster)));
+        self.evict_connections();
     }
 
     fn update_peer_info(
@@ -126,10 +237,8 @@ impl<D: SendableDispatcher> Peers<D> {
         gossiped_capabilities: Option<Vec<i32>>,
         reported_capabilities: Option<Vec<i32>>,
     ) {
-        let gossiped_capabilities = gossiped_capabilities
-            .map(|c| c.into_iter().filter_map(|i| i.try_into().ok()).collect());
-        let reported_capabilities = reported_capabilities
-            .map(|c| c.into_iter().filter_map(|i| i.try_into().ok()).collect());
+        let gossiped_capabilities = gossiped_capabilities.map(capabilities);
+        let reported_capabilities = reported_capabilities.map(capabilities);
         if let Some(info) = self.peer_infos.get_mut(addr) {
             if reported_alive_at > info.reported_alive_at {
                 info.reported_alive_at = reported_alive_at;
@@ -147,6 +256,8 @@ impl<D: SendableDispatcher> Peers<D> {
                     reported_alive_at,
                     gossiped_capabilities,
                     reported_capabilities,
+                    failed_connection_attempts: 0,
+                    reconnect_at: None,
                 },
             );
         }
@@ -154,11 +265,20 @@ impl<D: SendableDispatcher> Peers<D> {
 
     fn consolidate_connections(&mut self, ctx: &mut <Self as Actor>::Context) {
         info!("Consolidating peer connections");
+        let banned: Vec<ConnectionId> = self
+            .identified_connections
+            .iter()
+            .filter(|(_, addr)| self.is_banned(addr))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in banned {
+            self.drop_connection(&id, CloseConnectionReason::PeerBanned);
+        }
         let remove_ids: Vec<ConnectionId> = self
             .connections
             .iter()
             .filter_map(|(id, conn)| {
-                if self.identified_connections.get(&id).is_none() || !conn.connected() {
+                if !self.identified_connections.contains_key(id) || !conn.addr.connected() {
                     Some(id)
                 } else {
                     None
@@ -171,10 +291,14 @@ impl<D: SendableDispatcher> Peers<D> {
                 self.drop_connection(&id, CloseConnectionReason::UnknownPeerAddress);
             } else {
                 self.connections.remove(&id);
+                self.invalid_payloads.remove(&id);
                 self.status.connection_removed(&id);
             }
         });
+        let now = SystemTime::now();
+        self.bans.retain(|_, until| *until > now);
 
+        self.purge_peer_infos();
         ctx.spawn(self.update_alive_times().then(|_, peers, _ctx| {
             let candidates = peers.new_connection_candidates();
             if candidates.len() + peers.identified_connections.len() < MIN_CONNECTIONS * 2 {
@@ -191,59 +315,163 @@ impl<D: SendableDispatcher> Peers<D> {
     fn drop_connection(&mut self, id: &ConnectionId, reason: CloseConnectionReason) {
         self.status.connection_removed(id);
         self.identified_connections.remove(id);
-        if let Some(addr) = self.connections.remove(id) {
-            if addr.connected() {
-                arbiter_spawn!(addr.send(Shutdown(reason)));
+        self.invalid_payloads.remove(id);
+        if let Some(conn) = self.connections.remove(id) {
+            if conn.addr.connected() {
+                arbiter_spawn!(conn.addr.send(Shutdown(reason)));
             }
         }
     }
-    fn new_connection_candidates(&self) -> HashSet<&NodeAddress> {
-        let mut candidates: HashSet<&NodeAddress> = self.peer_infos.keys().collect();
-        self.identified_connections.values().for_each(|v| {
-            candidates.remove(&v);
+    // Drops the least useful connections while there are too many
+    fn evict_connections(&mut self) {
+        let excess = self.connections.len().saturating_sub(self.max_connections);
+        if excess == 0 {
+            return;
+        }
+        let mut ranked: Vec<_> = self
+            .connections
+            .iter()
+            .map(|(id, conn)| {
+                (
+                    usefulness(
+                        conn.kind,
+                        self.identified_connections.contains_key(id),
+                        conn.last_active,
+                    ),
+                    *id,
+                )
+            })
+            .collect();
+        ranked.sort_by_key(|(rank, _)| *rank);
+        ranked.into_iter().take(excess).for_each(|(_, id)| {
+            self.drop_connection(&id, CloseConnectionReason::TooManyConnectionsOpen)
+        });
+    }
+    fn is_banned(&self, addr: &NodeAddress) -> bool {
+        self.filters.is_banned(addr)
+            || self.ban_list.is_banned(addr)
+            || self
+                .bans
+                .get(addr)
+                .map(|until| *until > SystemTime::now())
+                .unwrap_or(false)
+    }
+    // Closes the connection, its peer isn't connected to again for a while
+    fn ban(&mut self, id: &ConnectionId) {
+        if let Some(addr) = self.identified_connections.get(id) {
+            info!("Banning {:?} for {:?}", addr, BAN_DURATION);
+            self.bans
+                .insert(addr.clone(), SystemTime::now() + BAN_DURATION);
+        }
+        self.drop_connection(id, CloseConnectionReason::RuleViolation);
+    }
+    fn connection_failed(&mut self, addr: &NodeAddress) {
+        if let Some(info) = self.peer_infos.get_mut(addr) {
+            info.failed_connection_attempts += 1;
+            info.reconnect_at =
+                Some(SystemTime::now() + reconnect_backoff(info.failed_connection_attempts));
+        }
+    }
+    // Forgets peers that we couldn't reach repeatedly or that weren't seen
+    // for a long time.
+    fn purge_peer_infos(&mut self) {
+        let now = SystemTime::now();
+        let connected: HashSet<&NodeAddress> = self.identified_connections.values().collect();
+        self.peer_infos.retain(|addr, info| {
+            connected.contains(addr)
+                || (info.failed_connection_attempts < MAX_FAILED_CONNECTION_ATTEMPTS
+                    && now
+                        .duration_since(info.reported_alive_at)
+                        .map(|age| age < MAX_PEER_AGE)
+                        .unwrap_or(true))
+        });
+    }
+    // Reliable and recently seen peers first, skipping those we are connected
+    // or connecting to, those still backing off and banned ones.
+    fn new_connection_candidates(&self) -> Vec<NodeAddress> {
+        let now = SystemTime::now();
+        let connected: HashSet<&NodeAddress> = self.identified_connections.values().collect();
+        let mut candidates: Vec<(&NodeAddress, &PeerInfo)> = self
+            .peer_infos
+            .iter()
+            .filter(|(addr, info)| {
+                !connected.contains(addr)
+                    && !self.connecting.contains(*addr)
+                    && info.reconnect_at.map(|at| at <= now).unwrap_or(true)
+                    && !self.is_banned(addr)
+                    && self.transport.reaches(addr)
+            })
+            .collect();
+        candidates.sort_by_key(|(_, info)| {
+            (
+                info.failed_connection_attempts,
+                Reverse(info.reported_alive_at),
+            )
         });
         candidates
+            .into_iter()
+            .map(|(addr, _)| addr.clone())
+            .collect()
     }
 
     fn do_consolidate_connections(&mut self, ctx: &mut <Self as Actor>::Context) {
-        if self.identified_connections.len() < MIN_CONNECTIONS {
+        if shutdown::shutting_down() {
+            return;
+        }
+        if self.identified_connections.len() < MIN_CONNECTIONS.min(self.max_connections) {
+            let open_slots = DEFAULT_MAX_CONNECTIONS
+                .min(self.max_connections)
+                .saturating_sub(self.connections.len() + self.connecting.len());
             self.new_connection_candidates()
                 .into_iter()
-                .take(MAX_CONNECTIONS - self.connections.len())
-                .cloned()
+                .take(open_slots)
                 .for_each(|addr| {
+                    self.connecting.insert(addr.clone());
                     ctx.spawn(
                         fut::wrap_future(
                             Connection::open(
                                 addr.clone(),
                                 self.network.into(),
                                 self.get_dispatcher(ctx.address()),
-                                self.proxy_port,
+                                &*self.transport,
                             )
-                            .map_err(|_| ()),
+                            .timeout(CONNECT_TIMEOUT),
                         )
-                        .map(|(id, conn), peers: &mut Self, ctx| {
-                            peers.add_connection(id, conn, Some(addr));
-                            ctx.spawn(peers.request_peers_from(id));
+                        .then(move |result, peers: &mut Self, ctx| {
+                            peers.connecting.remove(&addr);
+                            match result {
+                                Ok((id, conn)) => {
+                                    peers.add_connection(
+                                        id,
+                                        conn,
+                                        Some(addr),
+                                        ConnectionKind::Reported,
+                                        ctx,
+                                    );
+                                    ctx.spawn(peers.request_peers_from(id));
+                                }
+                                Err(e) => {
+                                    debug!("Couldn't connect to {:?}: {:?}", addr, e);
+                                    peers.connection_failed(&addr)
+                                }
+                            }
+                            fut::ok(())
                         }),
                     );
                 });
-        } else if self.connections.len() > MAX_CONNECTIONS {
-            let to_drop: Vec<ConnectionId> = self
-                .connections
-                .keys()
-                .take(self.connections.len() - MAX_CONNECTIONS)
-                .cloned()
-                .collect();
-            to_drop.into_iter().for_each(|id| {
-                self.drop_connection(&id, CloseConnectionReason::TooManyConnectionsOpen)
-            });
         }
+        self.evict_connections();
+    }
+
+    // The address the peer of a connection is told, once we are listening
+    fn local_addr_for(&self, id: &ConnectionId) -> Option<NodeAddress> {
+        let addrs = self.local_addrs.as_ref()?;
+        Some(addrs.for_peer(self.identified_connections.get(id)))
     }
 
     fn request_peers(&self) -> impl ActorFuture<Item = (), Error = (), Actor = Self> {
         let ids: Vec<ConnectionId> = self.connections.keys().cloned().collect();
-        fut::wrap_stream(stream::iter_ok::<_, ()>(ids.into_iter()))
+        fut::wrap_stream(stream::iter_ok::<_, ()>(ids))
             .and_then(|id, peers: &mut Self, _ctx| peers.request_peers_from(id))
             .finish()
     }
@@ -252,9 +480,9 @@ impl<D: SendableDispatcher> Peers<D> {
         &self,
         id: ConnectionId,
     ) -> impl ActorFuture<Item = (), Error = (), Actor = Self> {
-        if let Some(conn) = self.connections.get(&id) {
+        if let Some(conn) = self.connections.get(&id).map(|conn| &conn.addr) {
             let request = GetPeersRequest {
-                sender_node_address: self.local_addr.clone(),
+                sender_node_address: self.local_addr_for(&id),
                 nonce: gen_nonce(),
                 supported_capabilities: LOCAL_CAPABILITIES.clone(),
                 reported_peers: self.peers_to_report(&id),
@@ -269,13 +497,9 @@ impl<D: SendableDispatcher> Peers<D> {
                               },
                               peers: &mut Peers<D>,
                               _ctx| {
-                            if let Some(ref addr) = peers
-                                .identified_connections
-                                .get(&id)
-                                .map(NodeAddress::clone)
-                            {
+                            if let Some(ref addr) = peers.identified_connections.get(&id).cloned() {
                                 peers.update_peer_info(
-                                    &addr,
+                                    addr,
                                     SystemTime::now(),
                                     None,
                                     Some(supported_capabilities),
@@ -325,16 +549,102 @@ impl<D: SendableDispatcher> Peers<D> {
         )
     }
 
+    // Asks a few seed nodes, or other peers, for what we missed while offline
+    // or cut off from parts of the network.
+    fn resync_data(&mut self, ctx: &mut <Self as Actor>::Context) {
+        let known_keys = match self.known_keys.as_ref() {
+            Some(known_keys) => known_keys.clone(),
+            None => return,
+        };
+        let seed_nodes = constants::seed_nodes(self.network);
+        let candidates = self
+            .identified_connections
+            .iter()
+            .filter_map(|(id, addr)| {
+                let conn = self.connections.get(id)?;
+                let seed = conn.kind == ConnectionKind::Seed || seed_nodes.contains(addr);
+                Some((*id, addr.clone(), seed))
+            })
+            .collect();
+        let ids = pick_resync_peers(candidates, &mut self.resynced_from);
+        if ids.is_empty() {
+            return;
+        }
+        ctx.spawn(
+            fut::wrap_future(known_keys.send(GetKnownKeys))
+                .map_err(|e, _, _| warn!("Couldn't get known keys: {:?}", e))
+                .and_then(move |excluded_keys, peers: &mut Self, _ctx| {
+                    let requests: Vec<_> = ids
+                        .into_iter()
+                        .filter_map(|id| peers.connections.get(&id).map(|conn| (id, &conn.addr)))
+                        .map(|(id, conn)| {
+                            let request = GetUpdatedDataRequest {
+                                sender_node_address: peers.local_addr_for(&id),
+                                nonce: gen_nonce(),
+                                excluded_keys: excluded_keys.clone(),
+                            };
+                            let dispatcher = peers.dispatcher.clone();
+                            debug!("Resyncing data from {:?}", id);
+                            let span =
+                                tracing::info_span!("data_sync", connection = %String::from(id));
+                            conn.send(Request(request))
+                                .flatten()
+                                .then(move |result| {
+                                    match result {
+                                        Ok(response) => {
+                                            debug!(
+                                                "Resync response has {} items",
+                                                response.data_set.len()
+                                                    + response
+                                                        .persistable_network_payload_items
+                                                        .len()
+                                            );
+                                            dispatcher.dispatch(id, response.into());
+                                        }
+                                        Err(e) => debug!("Couldn't resync from {:?}: {:?}", id, e),
+                                    }
+                                    Ok::<_, ()>(())
+                                })
+                                .instrument(span)
+                        })
+                        .collect();
+                    fut::wrap_future(future::join_all(requests)).map(|_, _, _| ())
+                }),
+        );
+    }
+
+    fn save_peers(&self) {
+        let store = match self.store.as_ref() {
+            Some(store) => store,
+            None => return,
+        };
+        let mut peers: Vec<_> = self.peer_infos.iter().collect();
+        peers.sort_by_key(|(_, info)| Reverse(info.reported_alive_at));
+        let known_peers = peers
+            .into_iter()
+            .take(MAX_PERSISTED_PEERS)
+            .map(|(addr, info)| KnownPeer {
+                peer: Some((addr.clone(), info).into()),
+                failed_connection_attempts: info.failed_connection_attempts,
+            })
+            .collect();
+        if let Err(e) = store.save(known_peers) {
+            warn!("Couldn't save peers: {}", e);
+        }
+    }
+
     fn update_alive_times(&self) -> impl ActorFuture<Item = (), Error = (), Actor = Self> {
         fut::wrap_future(self.keep_alive.send(ReportLastActive))
             .and_then(|alive_times, peers: &mut Self, _| {
-                alive_times.into_iter().for_each(|(id, last_active)| {
-                    if let Some(ref addr) = peers
-                        .identified_connections
-                        .get(&id)
-                        .map(NodeAddress::clone)
-                    {
-                        peers.status.connection_alive(&id, last_active);
+                alive_times.into_iter().for_each(|(id, info)| {
+                    let last_active = info.last_active;
+                    if let Some(conn) = peers.connections.get_mut(&id) {
+                        conn.last_active = last_active;
+                    }
+                    if let Some(ref addr) = peers.identified_connections.get(&id).cloned() {
+                        peers
+                            .status
+                            .connection_alive(&id, last_active, info.round_trip_time);
                         peers.update_peer_info(addr, last_active, None, None)
                     }
                 });
@@ -346,12 +656,57 @@ impl<D: SendableDispatcher> Peers<D> {
 impl<D: SendableDispatcher> Actor for Peers<D> {
     type Context = Context<Peers<D>>;
     fn started(&mut self, ctx: &mut Self::Context) {
-        ctx.run_interval(CONSOLIDATE_CONNECTIONS, |peers, ctx| {
+        ctx.schedule(CONSOLIDATE_CONNECTIONS, |peers, ctx| {
             peers.consolidate_connections(ctx);
         });
+        ctx.schedule(PEER_EXCHANGE, |peers, ctx| {
+            ctx.spawn(peers.request_peers().map(|_, peers, _| peers.save_peers()));
+        });
+        ctx.schedule(DATA_RESYNC, |peers, ctx| peers.resync_data(ctx));
     }
 }
 
+/// Seed nodes first, then the other peers, skipping those asked already
+/// until every candidate had its turn.
+fn pick_resync_peers<T>(
+    mut candidates: Vec<(T, NodeAddress, bool)>,
+    asked: &mut HashSet<NodeAddress>,
+) -> Vec<T> {
+    if candidates.iter().all(|(_, addr, _)| asked.contains(addr)) {
+        asked.clear();
+    }
+    candidates.sort_by_key(|(_, addr, seed)| (asked.contains(addr), !seed));
+    candidates
+        .into_iter()
+        .take(RESYNC_PEERS)
+        .map(|(id, addr, _)| {
+            asked.insert(addr);
+            id
+        })
+        .collect()
+}
+
+/// Unidentified connections are the least useful, then by kind the ones that
+/// were quiet for the longest.
+fn usefulness(
+    kind: ConnectionKind,
+    identified: bool,
+    last_active: SystemTime,
+) -> (bool, ConnectionKind, SystemTime) {
+    (identified, kind, last_active)
+}
+
+/// Doubles with every failed attempt, up to half of it is jitter so that
+/// peers which failed together aren't retried together.
+fn reconnect_backoff(failed_attempts: u32) -> Duration {
+    let backoff = RECONNECT_BACKOFF
+        .checked_mul(1 << failed_attempts.saturating_sub(1).min(16))
+        .map(|backoff| backoff.min(MAX_RECONNECT_BACKOFF))
+        .unwrap_or(MAX_RECONNECT_BACKOFF);
+    let jitter = thread_rng().gen_range(0, backoff.as_millis() as u64 / 2 + 1);
+    backoff / 2 + Duration::from_millis(jitter)
+}
+
 pub struct SeedConnection(pub NodeAddress, pub ConnectionId, pub Addr<Connection>);
 impl Message for SeedConnection {
     type Result = ();
@@ -364,7 +719,7 @@ impl<D: SendableDispatcher> Handler<SeedConnection> for super::Peers<D> {
         ctx: &mut Self::Context,
     ) -> Self::Result {
         arbiter_spawn!(connection.send(SetDispatcher(self.get_dispatcher(ctx.address()))));
-        self.add_connection(id, connection, Some(addr));
+        self.add_connection(id, connection, Some(addr), ConnectionKind::Seed, ctx);
         self.consolidate_connections(ctx);
     }
 }
@@ -386,11 +741,15 @@ impl<D: SendableDispatcher> Handler<Receive<GetPeersRequest>> for Peers<D> {
     ) -> Self::Result {
         self.add_to_peer_infos(reported_peers);
         if let Some(addr) = sender_node_address {
+            if self.is_banned(&addr) {
+                info!("Dropping connection to banned peer {:?}", addr);
+                return self.drop_connection(&conn_id, CloseConnectionReason::PeerBanned);
+            }
             self.update_peer_info(&addr, SystemTime::now(), None, Some(supported_capabilities));
             self.status.connection_identified(&conn_id, &addr);
             self.identified_connections.insert(conn_id, addr);
         }
-        if let Some(conn) = self.connections.get(&conn_id).map(Addr::clone) {
+        if let Some(conn) = self.connections.get(&conn_id).map(|conn| conn.addr.clone()) {
             ctx.spawn(self.update_alive_times().then(move |_, peers, _| {
                 let res = GetPeersResponse {
                     request_nonce: nonce,
@@ -417,10 +776,10 @@ impl<D: SendableDispatcher> Handler<ServerStarted> for Peers<D> {
     type Result = ();
     fn handle(
         &mut self,
-        ServerStarted(addr): ServerStarted,
+        ServerStarted(addrs): ServerStarted,
         _: &mut Self::Context,
     ) -> Self::Result {
-        self.local_addr = Some(addr);
+        self.local_addrs = Some(addrs);
     }
 }
 
@@ -432,9 +791,78 @@ impl<D: SendableDispatcher> Handler<IncomingConnection> for Peers<D> {
         IncomingConnection(tcp): IncomingConnection,
         ctx: &mut Self::Context,
     ) -> Self::Result {
+        if shutdown::shutting_down() {
+            return;
+        }
         let dispatcher = self.get_dispatcher(ctx.address());
         let (id, conn) = Connection::from_tcp_stream(tcp, self.network.into(), dispatcher);
-        self.add_connection(id, conn, None);
+        self.add_connection(id, conn, None, ConnectionKind::Direct, ctx);
+    }
+}
+
+impl<D: SendableDispatcher> Handler<Misbehaved> for Peers<D> {
+    type Result = ();
+    fn handle(
+        &mut self,
+        Misbehaved(id, misbehavior): Misbehaved,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        match misbehavior {
+            Misbehavior::Flooding => self.ban(&id),
+            Misbehavior::MalformedPayloads(count) => {
+                warn!("{:?} sent {} malformed payloads", id, count);
+                self.ban(&id)
+            }
+            // Counted while the connection is ours only
+            Misbehavior::InvalidPayloads(_) if !self.connections.contains_key(&id) => (),
+            Misbehavior::InvalidPayloads(count) => {
+                let invalid = self.invalid_payloads.entry(id).or_insert(0);
+                *invalid += count;
+                warn!("{:?} sent {} entries that didn't verify", id, invalid);
+                if *invalid >= MAX_INVALID_PAYLOADS {
+                    self.ban(&id);
+                }
+            }
+        }
+    }
+}
+
+/// Closes all connections with `AppShutDown` and saves the peers.
+impl<D: SendableDispatcher> Handler<ConfigUpdated> for Peers<D> {
+    type Result = ();
+    fn handle(&mut self, ConfigUpdated(update): ConfigUpdated, _: &mut Self::Context) {
+        if let Some(max_connections) = update.max_connections(self.network) {
+            info!("Max connections changed to {}", max_connections);
+            self.max_connections = max_connections;
+            self.evict_connections();
+        }
+    }
+}
+
+impl<D: SendableDispatcher> Handler<Stop> for Peers<D> {
+    type Result = ResponseFuture<(), ()>;
+    fn handle(&mut self, _: Stop, _: &mut Self::Context) -> Self::Result {
+        self.save_peers();
+        self.identified_connections.clear();
+        let status = &mut self.status;
+        let closing: Vec<_> = self
+            .connections
+            .drain()
+            .filter_map(|(id, conn)| {
+                status.connection_removed(&id);
+                if conn.addr.connected() {
+                    Some(
+                        conn.addr
+                            .send(Shutdown(CloseConnectionReason::AppShutDown))
+                            .then(|_| Ok(())),
+                    )
+                } else {
+                    None
+                }
+            })
+            .collect();
+        info!("Closing {} connections", closing.len());
+        Box::new(future::join_all(closing).map(|_| ()))
     }
 }
 
@@ -447,3 +875,81 @@ pub mod event {
         type Result = ();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restores_known_peers() {
+        let addr: NodeAddress = "peer.onion:9999".parse().unwrap();
+        let info = PeerInfo {
+            reported_alive_at: UNIX_EPOCH + Duration::from_millis(1_500_000_000_000),
+            gossiped_capabilities: None,
+            reported_capabilities: Some(vec![Capability::TradeStatistics2]),
+            failed_connection_attempts: 2,
+            reconnect_at: None,
+        };
+        let known = KnownPeer {
+            peer: Some((addr.clone(), &info).into()),
+            failed_connection_attempts: info.failed_connection_attempts,
+        };
+        let (restored_addr, restored) = known_peer_info(known.clone()).unwrap();
+        assert_eq!(restored_addr, addr);
+        assert_eq!(restored.failed_connection_attempts, 2);
+        assert_eq!(Some(Peer::from((restored_addr, &restored))), known.peer);
+        assert!(known_peer_info(KnownPeer::default()).is_none());
+    }
+
+    #[test]
+    fn evicts_least_useful_connections_first() {
+        let now = SystemTime::now();
+        let earlier = now - Duration::from_secs(60);
+        let mut ranked = vec![
+            usefulness(ConnectionKind::Seed, true, earlier),
+            usefulness(ConnectionKind::Reported, true, now),
+            usefulness(ConnectionKind::Direct, false, now),
+            usefulness(ConnectionKind::Reported, true, earlier),
+            usefulness(ConnectionKind::Direct, true, now),
+        ];
+        ranked.sort();
+        assert_eq!(
+            ranked,
+            vec![
+                (false, ConnectionKind::Direct, now),
+                (true, ConnectionKind::Direct, now),
+                (true, ConnectionKind::Reported, earlier),
+                (true, ConnectionKind::Reported, now),
+                (true, ConnectionKind::Seed, earlier),
+            ]
+        );
+    }
+
+    #[test]
+    fn rotates_resync_peers() {
+        let mut candidates: Vec<(usize, NodeAddress, bool)> = (0..3)
+            .map(|i| (i, format!("peer{}.onion:9999", i).parse().unwrap(), false))
+            .collect();
+        candidates.push((3, "seed.onion:8000".parse().unwrap(), true));
+        let mut asked = HashSet::new();
+
+        let first = pick_resync_peers(candidates.clone(), &mut asked);
+        assert_eq!(first.len(), RESYNC_PEERS);
+        assert_eq!(first[0], 3);
+        let second = pick_resync_peers(candidates.clone(), &mut asked);
+        assert!(second.iter().all(|id| !first.contains(id)));
+        assert_eq!(asked.len(), 4);
+        let third = pick_resync_peers(candidates, &mut asked);
+        assert_eq!(third[0], 3);
+        assert!(pick_resync_peers(Vec::<(usize, _, _)>::new(), &mut asked).is_empty());
+    }
+
+    #[test]
+    fn backs_off_exponentially() {
+        for (attempts, max) in &[(1, 30), (2, 60), (4, 240), (20, 3600)] {
+            let backoff = reconnect_backoff(*attempts);
+            assert!(backoff >= Duration::from_secs(max / 2));
+            assert!(backoff <= Duration::from_secs(*max));
+        }
+    }
+}
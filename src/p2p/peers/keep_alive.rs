@@ -1,38 +1,46 @@
 use super::event::ConnectionAdded;
 use crate::{
-    bisq::payload::{gen_nonce, Ping, Pong},
+    bisq::{
+        constants::CloseConnectionReason,
+        payload::{gen_nonce, Ping, Pong},
+    },
     p2p::{
-        connection::{Connection, ConnectionId, Payload, Request},
+        connection::{Connection, ConnectionId, Payload, Request, Shutdown},
         dispatch::Receive,
     },
     prelude::*,
+    scheduler::{Schedule, Task},
 };
-use lazy_static::lazy_static;
-use rand::{thread_rng, Rng};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     time::{Duration, SystemTime},
 };
 
-lazy_static! {
-    static ref LOOP_INTERVAL_SEC: u64 = thread_rng().gen::<u64>() % 5 + 30;
-    static ref LOOP_INTERVAL: Duration = Duration::from_secs(*LOOP_INTERVAL_SEC);
-    static ref LAST_ACTIVITY_AGE: Duration = Duration::from_secs(*LOOP_INTERVAL_SEC / 2);
-}
+const PING_PEERS: Task =
+    Task::new("keep_alive", Duration::from_secs(30)).with_jitter(Duration::from_secs(5));
+const LAST_ACTIVITY_AGE: Duration = Duration::from_secs(15);
+/// Peers that don't answer a ping within this time are disconnected,
+/// circuits over tor can take a while
+const PONG_TIMEOUT: Duration = Duration::from_secs(90);
 
-struct Info {
-    last_active: SystemTime,
-    last_round_trip_time: Duration,
+#[derive(Clone, Copy)]
+pub struct Info {
+    pub last_active: SystemTime,
+    /// Of our last ping, or the one the peer reported until we measured
+    pub round_trip_time: Option<Duration>,
 }
 pub struct KeepAlive {
     infos: HashMap<ConnectionId, Info>,
     connections: HashMap<ConnectionId, WeakAddr<Connection>>,
+    /// Pinged connections that didn't answer yet
+    awaiting_pong: HashSet<ConnectionId>,
 }
 impl KeepAlive {
     pub fn start() -> Addr<KeepAlive> {
         KeepAlive {
             infos: HashMap::new(),
             connections: HashMap::new(),
+            awaiting_pong: HashSet::new(),
         }
         .start()
     }
@@ -41,19 +49,70 @@ impl Actor for KeepAlive {
     type Context = Context<KeepAlive>;
 
     fn started(&mut self, ctx: &mut Self::Context) {
-        ctx.run_interval(*LOOP_INTERVAL, |keep_alive, ctx| {
-            let infos = &mut keep_alive.infos;
-            keep_alive.connections.retain(|id, conn| {
-                if ping_peer(id.to_owned(), conn, infos.get(id), ctx) {
-                    true
-                } else {
-                    infos.remove(id);
-                    false
+        ctx.schedule(PING_PEERS, |keep_alive, ctx| {
+            let now = SystemTime::now();
+            let ids: Vec<ConnectionId> = keep_alive.connections.keys().cloned().collect();
+            for id in ids {
+                if !should_ping(
+                    keep_alive.infos.get(&id),
+                    keep_alive.awaiting_pong.contains(&id),
+                    now,
+                ) {
+                    continue;
                 }
-            })
+                match keep_alive.connections.get(&id).and_then(WeakAddr::upgrade) {
+                    Some(conn) => keep_alive.ping_peer(id, conn, ctx),
+                    None => {
+                        keep_alive.connections.remove(&id);
+                        keep_alive.infos.remove(&id);
+                        keep_alive.awaiting_pong.remove(&id);
+                    }
+                }
+            }
         });
     }
 }
+impl KeepAlive {
+    fn ping_peer(&mut self, id: ConnectionId, conn: Addr<Connection>, ctx: &mut Context<Self>) {
+        let send_time = SystemTime::now();
+        let ping = Ping {
+            nonce: gen_nonce(),
+            last_round_trip_time: self
+                .infos
+                .get(&id)
+                .and_then(|info| info.round_trip_time)
+                .map_or(0, |rtt| rtt.as_millis() as i32),
+        };
+        self.awaiting_pong.insert(id);
+        ctx.spawn(
+            fut::wrap_future(
+                conn.send(Request(ping))
+                    .flatten()
+                    .timeout(PONG_TIMEOUT)
+                    .then(|pong| Ok::<_, ()>(pong.is_ok())),
+            )
+            .map(move |answered, keep_alive: &mut KeepAlive, _ctx| {
+                keep_alive.awaiting_pong.remove(&id);
+                if answered {
+                    let now = SystemTime::now();
+                    keep_alive.infos.insert(
+                        id,
+                        Info {
+                            last_active: now,
+                            round_trip_time: Some(
+                                now.duration_since(send_time).expect("Pong before Ping"),
+                            ),
+                        },
+                    );
+                } else if keep_alive.connections.remove(&id).is_some() {
+                    info!("{:?} didn't answer our ping, disconnecting", id);
+                    keep_alive.infos.remove(&id);
+                    arbiter_spawn!(conn.send(Shutdown(CloseConnectionReason::SendMsgTimeout)));
+                }
+            }),
+        );
+    }
+}
 impl Handler<ConnectionAdded> for KeepAlive {
     type Result = ();
     fn handle(
@@ -66,30 +125,31 @@ impl Handler<ConnectionAdded> for KeepAlive {
 }
 pub struct ReportLastActive;
 impl Message for ReportLastActive {
-    type Result = HashMap<ConnectionId, SystemTime>;
+    type Result = HashMap<ConnectionId, Info>;
 }
 impl Handler<ReportLastActive> for KeepAlive {
     type Result = MessageResult<ReportLastActive>;
 
     fn handle(&mut self, _: ReportLastActive, _: &mut Self::Context) -> Self::Result {
-        MessageResult(
-            self.infos
-                .iter()
-                .map(|(id, info)| (*id, info.last_active))
-                .collect(),
-        )
+        MessageResult(self.infos.clone())
     }
 }
 
 impl Handler<Receive<Ping>> for KeepAlive {
     type Result = ();
     fn handle(&mut self, Receive(id, ping): Receive<Ping>, _: &mut Self::Context) -> Self::Result {
-        let now = SystemTime::now();
+        let reported = Some(Duration::from_millis(ping.last_round_trip_time as u64))
+            .filter(|rtt| *rtt > Duration::default());
+        let round_trip_time = self
+            .infos
+            .get(&id)
+            .and_then(|info| info.round_trip_time)
+            .or(reported);
         self.infos.insert(
             id,
             Info {
-                last_active: now,
-                last_round_trip_time: Duration::from_millis(ping.last_round_trip_time as u64),
+                last_active: SystemTime::now(),
+                round_trip_time,
             },
         );
         if let Some(conn) = self.connections.get(&id) {
@@ -102,51 +162,35 @@ impl Handler<Receive<Ping>> for KeepAlive {
     }
 }
 
-fn ping_peer(
-    id: ConnectionId,
-    conn: &WeakAddr<Connection>,
-    info: Option<&Info>,
-    ctx: &mut Context<KeepAlive>,
-) -> bool {
-    let send_time = SystemTime::now();
-    let should_ping = match info {
-        Some(info)
-            if send_time
-                .duration_since(info.last_active)
-                .map(|t| t > *LAST_ACTIVITY_AGE)
-                .unwrap_or(false) =>
-        {
-            true
-        }
+/// Peers that were quiet for a while are pinged, unless they still owe us
+/// a pong.
+fn should_ping(info: Option<&Info>, awaiting_pong: bool, now: SystemTime) -> bool {
+    if awaiting_pong {
+        return false;
+    }
+    match info {
+        Some(info) => now
+            .duration_since(info.last_active)
+            .map(|quiet| quiet > LAST_ACTIVITY_AGE)
+            .unwrap_or(false),
         None => true,
-        _ => false,
-    };
-    if should_ping {
-        if let Some(conn) = conn.upgrade() {
-            let ping = Ping {
-                nonce: gen_nonce(),
-                last_round_trip_time: info.map_or(0, |i| i.last_round_trip_time.as_millis() as i32),
-            };
-            ctx.spawn(
-                fut::wrap_future(conn.send(Request(ping)).flatten().map(move |_pong| {
-                    let ret = SystemTime::now();
-                    Info {
-                        last_active: ret,
-                        last_round_trip_time: ret
-                            .duration_since(send_time)
-                            .expect("Pong before Ping"),
-                    }
-                }))
-                .map(move |info, keep_alive: &mut KeepAlive, _ctx| {
-                    keep_alive.infos.insert(id, info)
-                })
-                .then(|_, _, _| fut::ok(())),
-            );
-            true
-        } else {
-            false
-        }
-    } else {
-        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pings_quiet_peers_once() {
+        let now = SystemTime::now();
+        let info = |quiet| Info {
+            last_active: now - Duration::from_secs(quiet),
+            round_trip_time: None,
+        };
+        assert!(should_ping(None, false, now));
+        assert!(!should_ping(Some(&info(5)), false, now));
+        assert!(should_ping(Some(&info(20)), false, now));
+        assert!(!should_ping(Some(&info(20)), true, now));
     }
 }
@@ -0,0 +1,50 @@
+use crate::bisq::payload::Peer;
+use prost::Message;
+use std::{fs, io, path::PathBuf};
+
+#[derive(Clone, PartialEq, Message)]
+struct StoredPeers {
+    #[prost(message, repeated, tag = "1")]
+    peers: Vec<KnownPeer>,
+}
+
+/// A peer from the peer exchange, with how often we failed to connect to it
+/// since it was last seen.
+#[derive(Clone, PartialEq, Message)]
+pub struct KnownPeer {
+    #[prost(message, optional, tag = "1")]
+    pub peer: Option<Peer>,
+    #[prost(uint32, tag = "2")]
+    pub failed_connection_attempts: u32,
+}
+
+/// Keeps the peers we learned about, to connect to them after a restart and
+/// to bootstrap from when no seed node is reachable.
+pub struct PeerStore {
+    path: PathBuf,
+}
+impl PeerStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn load(&self) -> io::Result<Vec<KnownPeer>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        StoredPeers::decode(&fs::read(&self.path)?[..])
+            .map(|stored| stored.peers)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub(super) fn save(&self, peers: Vec<KnownPeer>) -> io::Result<()> {
+        let stored = StoredPeers { peers };
+        let mut serialized = Vec::with_capacity(stored.encoded_len());
+        stored
+            .encode(&mut serialized)
+            .expect("Could not encode peers");
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, serialized)?;
+        fs::rename(tmp_path, &self.path)
+    }
+}
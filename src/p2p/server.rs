@@ -2,81 +2,207 @@ use super::{
     bootstrap::Bootstrap,
     dispatch::SendableDispatcher,
     peers::Peers,
-    tor::{AddOnionConfig, TorControl},
+    status::{BootstrapPhase, Status, TorStatus},
+    tor::{AddOnionConfig, BootstrapProgress, TorControl},
 };
 use crate::{
     bisq::payload::NodeAddress,
+    metrics,
     prelude::{
         net::{TcpListener, TcpStream},
         *,
     },
+    scheduler::{Schedule, Task},
+    shutdown::Stop,
 };
 use std::{
     io,
     net::{IpAddr, Ipv4Addr, SocketAddr},
     path::PathBuf,
+    time::{Duration, Instant},
 };
 
+const CHECK_TOR: Task = Task::new("check_tor", Duration::from_secs(10));
+
 pub struct TorConfig {
     pub hidden_service_port: u16,
     pub tc_port: u16,
     pub private_key_path: PathBuf,
 }
 
+/// The addresses peers reach us at. Peers with a clearnet address are told
+/// the clearnet one, if there is one, all others the primary one, so that
+/// onion peers can't link the hidden service to it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LocalAddrs {
+    /// The hidden service, or the listener without tor
+    pub primary: NodeAddress,
+    pub clearnet: Option<NodeAddress>,
+}
+impl LocalAddrs {
+    pub fn for_peer(&self, peer: Option<&NodeAddress>) -> NodeAddress {
+        match (peer, self.clearnet.as_ref()) {
+            (Some(peer), Some(clearnet)) if !peer.is_onion() => clearnet.clone(),
+            _ => self.primary.clone(),
+        }
+    }
+}
+
 pub struct Server<D: SendableDispatcher> {
     listen_port: u16,
     tor_conf: Option<TorConfig>,
+    /// Listened on at all interfaces, next to the hidden service
+    clearnet: Option<NodeAddress>,
     peers: Addr<Peers<D>>,
     bootstrap: Option<Addr<Bootstrap<D>>>,
+    status: Status,
+    started: Instant,
+    tor_control: Option<TorControl>,
+    // Id of the hidden service, while it is published
+    service_id: Option<String>,
 }
 pub fn start<D: SendableDispatcher>(
     listen_port: u16,
     peers: Addr<Peers<D>>,
     bootstrap: Option<Addr<Bootstrap<D>>>,
     tor_conf: Option<TorConfig>,
+    clearnet: Option<NodeAddress>,
+    status: Status,
 ) -> Addr<Server<D>> {
     Server {
         listen_port,
         tor_conf,
+        clearnet,
         peers,
         bootstrap,
+        status,
+        started: Instant::now(),
+        tor_control: None,
+        service_id: None,
     }
     .start()
 }
 impl<D: SendableDispatcher> Actor for Server<D> {
     type Context = Context<Server<D>>;
     fn started(&mut self, ctx: &mut Self::Context) {
-        let listen_socket =
-            SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), self.listen_port);
+        let clearnet_port = self.clearnet.as_ref().map(|addr| addr.port as u16);
+        // The clearnet address may be the one of the tor listener
+        let listen_ip = if clearnet_port == Some(self.listen_port) {
+            Ipv4Addr::UNSPECIFIED
+        } else {
+            Ipv4Addr::LOCALHOST
+        };
+        let listen_socket = SocketAddr::new(IpAddr::V4(listen_ip), self.listen_port);
         let tcp = TcpListener::bind(&listen_socket).expect("Unable to bind port");
         ctx.add_stream(tcp.incoming());
-        let addr = match &self.tor_conf {
-            Some(tor_conf) => {
-                let mut tc = TorControl::connect(("127.0.0.1", tor_conf.tc_port))
-                    .expect("Couldn't authenticate to TorControl");
-                let onion_addr = tc
-                    .add_v2_onion(AddOnionConfig {
-                        virtual_port: tor_conf.hidden_service_port,
-                        target_port: self.listen_port,
-                        private_key_path: tor_conf.private_key_path.clone(),
-                    })
-                    .expect("Couldn't create hidden service");
-                NodeAddress {
-                    host_name: onion_addr.onion_service,
-                    port: onion_addr.port as i32,
-                }
-            }
-            None => NodeAddress {
+        if let Some(port) = clearnet_port.filter(|port| *port != self.listen_port) {
+            let clearnet_socket = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port);
+            let tcp = TcpListener::bind(&clearnet_socket).expect("Unable to bind clearnet port");
+            ctx.add_stream(tcp.incoming());
+        }
+        if self.tor_conf.is_some() {
+            self.status.set_tor(TorStatus::default());
+            self.check_tor();
+            ctx.schedule(CHECK_TOR, |server, _| server.check_tor());
+        } else {
+            self.status.phase_reached(BootstrapPhase::TorReady);
+            let addr = self.clearnet.clone().unwrap_or_else(|| NodeAddress {
                 host_name: "127.0.0.1".to_string(),
                 port: self.listen_port as i32,
-            },
-        };
-
+            });
+            self.listening(addr);
+        }
+    }
+}
+impl<D: SendableDispatcher> Server<D> {
+    fn listening(&mut self, addr: NodeAddress) {
         info!("Server started @ {:?}", addr);
+        if let Some(clearnet) = self.clearnet.as_ref().filter(|clearnet| **clearnet != addr) {
+            info!("Clearnet peers are told {:?}", clearnet);
+        }
+        self.status.set_local_addr(addr.clone());
+        self.status.phase_reached(BootstrapPhase::Listening);
+        let addrs = LocalAddrs {
+            primary: addr,
+            clearnet: self.clearnet.clone(),
+        };
         if let Some(bootstrap) = self.bootstrap.as_ref() {
-            arbiter_spawn!(bootstrap.send(event::ServerStarted(addr.clone())))
+            arbiter_spawn!(bootstrap.send(event::ServerStarted(addrs.clone())))
+        }
+        arbiter_spawn!(self.peers.send(event::ServerStarted(addrs)));
+    }
+
+    // Publishes the hidden service once tor finished bootstrapping, and again
+    // after tor restarted.
+    fn check_tor(&mut self) {
+        let tor_conf = match self.tor_conf.as_ref() {
+            Some(tor_conf) => tor_conf,
+            None => return,
+        };
+        let mut tor_status = self.status.tor().unwrap_or_default();
+        if self.tor_control.is_none() {
+            match TorControl::connect(("127.0.0.1", tor_conf.tc_port)) {
+                Ok(tc) => self.tor_control = Some(tc),
+                Err(e) => {
+                    debug!("Couldn't connect to the tor control port: {:?}", e);
+                    self.status.set_tor(TorStatus {
+                        reachable: false,
+                        hidden_service: None,
+                        ..tor_status
+                    });
+                    return;
+                }
+            }
+        }
+        let tc = self
+            .tor_control
+            .as_mut()
+            .expect("No tor control connection");
+        tor_status.reachable = true;
+        match tc.bootstrap_progress() {
+            Ok(progress) => tor_status.bootstrap = Some(progress),
+            Err(e) => {
+                warn!("Lost the tor control connection: {:?}", e);
+                self.tor_control = None;
+                self.service_id = None;
+                self.status.set_tor(TorStatus::default());
+                return;
+            }
+        }
+        let bootstrapped = tor_status
+            .bootstrap
+            .as_ref()
+            .map(BootstrapProgress::is_done)
+            .unwrap_or(false);
+        if !bootstrapped || self.service_id.is_some() {
+            return self.status.set_tor(tor_status);
+        }
+        match tc.add_v2_onion(AddOnionConfig {
+            virtual_port: tor_conf.hidden_service_port,
+            target_port: self.listen_port,
+            private_key_path: tor_conf.private_key_path.clone(),
+        }) {
+            Ok(onion_addr) => {
+                let addr = NodeAddress {
+                    host_name: onion_addr.onion_service,
+                    port: onion_addr.port as i32,
+                };
+                self.service_id = Some(onion_addr.service_id);
+                tor_status.hidden_service = Some(addr.clone());
+                self.status.set_tor(tor_status);
+                if self.status.local_addr().is_none() {
+                    metrics::tor_bootstrapped(self.started.elapsed());
+                    self.status.phase_reached(BootstrapPhase::TorReady);
+                    self.listening(addr);
+                } else {
+                    info!("Published hidden service {:?} again", addr);
+                }
+            }
+            Err(e) => {
+                warn!("Couldn't create hidden service: {:?}", e);
+                self.status.set_tor(tor_status);
+            }
         }
-        arbiter_spawn!(self.peers.send(event::ServerStarted(addr)));
     }
 }
 impl<D: SendableDispatcher> StreamHandler<TcpStream, io::Error> for Server<D> {
@@ -85,12 +211,27 @@ impl<D: SendableDispatcher> StreamHandler<TcpStream, io::Error> for Server<D> {
     }
 }
 
+impl<D: SendableDispatcher> Handler<Stop> for Server<D> {
+    type Result = Result<(), ()>;
+    fn handle(&mut self, _: Stop, ctx: &mut Self::Context) -> Self::Result {
+        // Stopping drops the listener
+        ctx.stop();
+        if let (Some(tc), Some(service_id)) = (self.tor_control.as_mut(), self.service_id.take()) {
+            match tc.del_onion(&service_id) {
+                Ok(()) => info!("Removed hidden service {}.onion", service_id),
+                Err(e) => warn!("Couldn't remove hidden service: {:?}", e),
+            }
+        }
+        Ok(())
+    }
+}
+
 pub mod event {
-    use crate::bisq::payload::NodeAddress;
+    use super::LocalAddrs;
     use actix::Message;
     use tokio::net::TcpStream;
 
-    pub struct ServerStarted(pub NodeAddress);
+    pub struct ServerStarted(pub LocalAddrs);
     impl Message for ServerStarted {
         type Result = ();
     }
@@ -99,3 +240,37 @@ pub mod event {
         type Result = ();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_clearnet_peers_the_clearnet_address() {
+        let onion: NodeAddress = "risq.onion:9999".parse().unwrap();
+        let clearnet: NodeAddress = "203.0.113.7:9999".parse().unwrap();
+        let addrs = LocalAddrs {
+            primary: onion.clone(),
+            clearnet: Some(clearnet.clone()),
+        };
+        assert_eq!(
+            addrs.for_peer(Some(&"peer.onion:9999".parse().unwrap())),
+            onion
+        );
+        assert_eq!(
+            addrs.for_peer(Some(&"seed.example:8000".parse().unwrap())),
+            clearnet
+        );
+        // Not identified yet
+        assert_eq!(addrs.for_peer(None), onion);
+
+        let tor_only = LocalAddrs {
+            primary: onion.clone(),
+            clearnet: None,
+        };
+        assert_eq!(
+            tor_only.for_peer(Some(&"seed.example:8000".parse().unwrap())),
+            onion
+        );
+    }
+}
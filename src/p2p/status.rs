@@ -1,30 +1,138 @@
-use super::{bootstrap::BootstrapState, connection::ConnectionId};
-use crate::bisq::NodeAddress;
+use super::{bootstrap::BootstrapState, connection::ConnectionId, tor::BootstrapProgress};
+use crate::{
+    bisq::{constants::BaseCurrencyNetwork, payload::MessageVersion, NodeAddress},
+    events::{Event, Events},
+};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    fmt,
     sync::{Arc, RwLock, RwLockReadGuard},
-    time::SystemTime,
+    time::{Duration, SystemTime},
 };
 
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum BootstrapPhase {
+    TorReady,
+    SeedConnected,
+    PreliminaryDataReceived,
+    UpdatedDataReceived,
+    Listening,
+}
+impl BootstrapPhase {
+    pub const ALL: [BootstrapPhase; 5] = [
+        BootstrapPhase::TorReady,
+        BootstrapPhase::SeedConnected,
+        BootstrapPhase::PreliminaryDataReceived,
+        BootstrapPhase::UpdatedDataReceived,
+        BootstrapPhase::Listening,
+    ];
+}
+impl fmt::Display for BootstrapPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TorReady => write!(f, "TorReady"),
+            Self::SeedConnected => write!(f, "SeedConnected"),
+            Self::PreliminaryDataReceived => write!(f, "PreliminaryDataReceived"),
+            Self::UpdatedDataReceived => write!(f, "UpdatedDataReceived"),
+            Self::Listening => write!(f, "Listening"),
+        }
+    }
+}
+
+/// How far the data of the network was received.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SyncState {
+    PreSync,
+    Syncing,
+    Synced,
+}
+impl fmt::Display for SyncState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PreSync => write!(f, "pre-sync"),
+            Self::Syncing => write!(f, "syncing"),
+            Self::Synced => write!(f, "synced"),
+        }
+    }
+}
+
+/// How a connection came about. When there are too many, connections are
+/// dropped in this order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ConnectionKind {
+    /// The peer connected to us
+    Direct,
+    /// We connected to a peer from the peer exchange
+    Reported,
+    /// The seed node we bootstrapped from
+    Seed,
+}
+impl fmt::Display for ConnectionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Direct => write!(f, "direct"),
+            Self::Reported => write!(f, "reported"),
+            Self::Seed => write!(f, "seed"),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct ConnectionStatus {
     pub addr: Option<NodeAddress>,
+    pub kind: ConnectionKind,
     pub alive_at: SystemTime,
+    /// Of the last ping, `None` until the peer answered one
+    pub round_trip_time: Option<Duration>,
+}
+
+/// Why the node may not be reachable yet when it runs behind tor.
+#[derive(Clone, Default)]
+pub struct TorStatus {
+    /// Whether the control port answers
+    pub reachable: bool,
+    pub bootstrap: Option<BootstrapProgress>,
+    /// Our onion address, while the hidden service is published
+    pub hidden_service: Option<NodeAddress>,
 }
 
 #[derive(Clone)]
 pub struct Status {
     bootstrap_state: Arc<RwLock<BootstrapState>>,
     connections: Arc<RwLock<HashMap<ConnectionId, ConnectionStatus>>>,
+    reached_phases: Arc<RwLock<HashSet<BootstrapPhase>>>,
+    local_addr: Arc<RwLock<Option<NodeAddress>>>,
+    tor: Arc<RwLock<Option<TorStatus>>>,
+    message_version: MessageVersion,
+    started_at: SystemTime,
+    events: Events,
 }
 
 impl Status {
-    pub fn new(bootstrap_state: Arc<RwLock<BootstrapState>>) -> Self {
+    pub fn new(
+        network: BaseCurrencyNetwork,
+        bootstrap_state: Arc<RwLock<BootstrapState>>,
+        events: Events,
+    ) -> Self {
         Self {
             bootstrap_state,
             connections: Arc::new(RwLock::new(HashMap::new())),
+            reached_phases: Arc::new(RwLock::new(HashSet::new())),
+            local_addr: Arc::new(RwLock::new(None)),
+            tor: Arc::new(RwLock::new(None)),
+            message_version: network.into(),
+            started_at: SystemTime::now(),
+            events,
         }
     }
+
+    pub fn message_version(&self) -> MessageVersion {
+        self.message_version
+    }
+
+    pub fn started_at(&self) -> SystemTime {
+        self.started_at
+    }
     pub fn bootstrap_state(&self) -> BootstrapState {
         *self
             .bootstrap_state
@@ -32,11 +140,88 @@ impl Status {
             .expect("Corrupted lock in status")
     }
 
-    pub fn connections(&self) -> RwLockReadGuard<HashMap<ConnectionId, ConnectionStatus>> {
+    pub fn phase_reached(&self, phase: BootstrapPhase) {
+        let mut phases = self
+            .reached_phases
+            .write()
+            .expect("Corrupted lock in status");
+        if phases.insert(phase) {
+            info!(
+                "Bootstrap progress {}% - {}",
+                phases.len() * 100 / BootstrapPhase::ALL.len(),
+                phase
+            );
+        }
+    }
+
+    pub fn bootstrap_phases(&self) -> Vec<(BootstrapPhase, bool)> {
+        let phases = self
+            .reached_phases
+            .read()
+            .expect("Corrupted lock in status");
+        BootstrapPhase::ALL
+            .iter()
+            .map(|phase| (*phase, phases.contains(phase)))
+            .collect()
+    }
+
+    pub fn sync_state(&self) -> SyncState {
+        let phases = self
+            .reached_phases
+            .read()
+            .expect("Corrupted lock in status");
+        if phases.contains(&BootstrapPhase::UpdatedDataReceived) {
+            SyncState::Synced
+        } else if phases.contains(&BootstrapPhase::SeedConnected) {
+            SyncState::Syncing
+        } else {
+            SyncState::PreSync
+        }
+    }
+
+    pub fn bootstrap_progress(&self) -> usize {
+        self.reached_phases
+            .read()
+            .expect("Corrupted lock in status")
+            .len()
+            * 100
+            / BootstrapPhase::ALL.len()
+    }
+
+    /// Address under which peers can reach us, known once the server is listening.
+    pub fn local_addr(&self) -> Option<NodeAddress> {
+        self.local_addr
+            .read()
+            .expect("Corrupted lock in status")
+            .clone()
+    }
+
+    pub fn set_local_addr(&mut self, addr: NodeAddress) {
+        *self.local_addr.write().expect("Corrupted lock in status") = Some(addr);
+    }
+
+    /// `None` without tor.
+    pub fn tor(&self) -> Option<TorStatus> {
+        self.tor.read().expect("Corrupted lock in status").clone()
+    }
+
+    pub fn set_tor(&mut self, tor: TorStatus) {
+        *self.tor.write().expect("Corrupted lock in status") = Some(tor);
+    }
+
+    pub fn connections(&self) -> RwLockReadGuard<'_, HashMap<ConnectionId, ConnectionStatus>> {
         self.connections.read().expect("Corrupted lock in status")
     }
 
-    pub fn connection_added(&mut self, id: ConnectionId, addr: Option<NodeAddress>) {
+    pub fn connection_added(
+        &mut self,
+        id: ConnectionId,
+        addr: Option<NodeAddress>,
+        kind: ConnectionKind,
+    ) {
+        if let Some(addr) = addr.as_ref() {
+            self.events.publish(Event::peer_connected(addr));
+        }
         self.connections
             .write()
             .expect("Corrupted lock in status")
@@ -44,16 +229,22 @@ impl Status {
                 id,
                 ConnectionStatus {
                     addr,
+                    kind,
                     alive_at: SystemTime::now(),
+                    round_trip_time: None,
                 },
             );
     }
 
     pub fn connection_removed(&mut self, id: &ConnectionId) {
-        self.connections
+        let removed = self
+            .connections
             .write()
             .expect("Corrupted lock in status")
             .remove(id);
+        if let Some(addr) = removed.and_then(|status| status.addr) {
+            self.events.publish(Event::peer_disconnected(&addr));
+        }
     }
 
     pub fn connection_identified(&mut self, id: &ConnectionId, addr: &NodeAddress) {
@@ -61,16 +252,22 @@ impl Status {
         let status = connections.get_mut(id).expect("Connection not in status");
         if status.addr.is_none() {
             status.addr = Some(addr.to_owned());
+            self.events.publish(Event::peer_connected(addr));
         }
         status.alive_at = SystemTime::now();
     }
 
-    pub fn connection_alive(&mut self, id: &ConnectionId, at: SystemTime) {
-        self.connections
-            .write()
-            .expect("Corrupted lock in status")
-            .get_mut(id)
-            .expect("Connection not in status")
-            .alive_at = at;
+    pub fn connection_alive(
+        &mut self,
+        id: &ConnectionId,
+        at: SystemTime,
+        round_trip_time: Option<Duration>,
+    ) {
+        let mut connections = self.connections.write().expect("Corrupted lock in status");
+        let status = connections.get_mut(id).expect("Connection not in status");
+        status.alive_at = at;
+        if round_trip_time.is_some() {
+            status.round_trip_time = round_trip_time;
+        }
     }
 }
@@ -9,7 +9,8 @@ use std::{
     fs::{self, File},
     io::{self, BufRead, Read, Write},
     net::{TcpStream, ToSocketAddrs},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    process::{self, Child, Command, Stdio},
     str::FromStr,
 };
 
@@ -36,6 +37,8 @@ pub enum TCErrorKind {
     InvalidDescriptor,
     UnmanagedEntity,
 }
+// The causes are only read through Debug
+#[allow(dead_code)]
 #[derive(Debug)]
 pub enum TCError {
     IoError(io::Error),
@@ -89,9 +92,25 @@ pub struct AddOnionConfig {
 #[derive(Debug)]
 pub struct OnionAddr {
     pub port: u16,
+    pub service_id: String,
     pub onion_service: String,
 }
 
+/// How far tor got connecting to the tor network, from
+/// `GETINFO status/bootstrap-phase`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BootstrapProgress {
+    /// 100 once tor can build circuits
+    pub progress: u8,
+    pub summary: String,
+}
+impl BootstrapProgress {
+    pub fn is_done(&self) -> bool {
+        self.progress >= 100
+    }
+}
+
+#[allow(dead_code)]
 #[derive(Debug)]
 struct ProtocolInfo {
     cookiefile: String,
@@ -127,16 +146,27 @@ impl TorControl {
                 }
             }
         }
-        if private_key != "" {
+        if !private_key.is_empty() {
             let mut key_file = File::create(conf.private_key_path)?;
             key_file.write_all(private_key.as_bytes())?
         }
         Ok(OnionAddr {
             port: conf.virtual_port,
+            service_id: service_id.to_string(),
             onion_service: service_id.to_string() + ".onion",
         })
     }
 
+    pub fn del_onion(&mut self, service_id: &str) -> TCResult<()> {
+        send_command(&mut self.0, format!("DEL_ONION {}", service_id))?;
+        read_lines(&mut self.0).map(|_| ())
+    }
+
+    pub fn bootstrap_progress(&mut self) -> TCResult<BootstrapProgress> {
+        send_command(&mut self.0, "GETINFO status/bootstrap-phase".into())?;
+        parse_bootstrap_phase(&read_lines(&mut self.0)?.join(" "))
+    }
+
     fn protocol_info(&mut self) -> TCResult<ProtocolInfo> {
         send_command(
             &mut self.0,
@@ -228,6 +258,51 @@ impl TorControl {
     }
 }
 
+/// Starts the tor binary at `binary` with its data under `data_dir`. It
+/// exits along with risq.
+pub fn launch(
+    binary: &Path,
+    data_dir: &Path,
+    socks_port: u16,
+    control_port: u16,
+) -> io::Result<Child> {
+    fs::create_dir_all(data_dir)?;
+    info!(
+        "Launching {} with data in {}",
+        binary.display(),
+        data_dir.display()
+    );
+    Command::new(binary)
+        .arg("--SocksPort")
+        .arg(socks_port.to_string())
+        .arg("--ControlPort")
+        .arg(control_port.to_string())
+        .arg("--CookieAuthentication")
+        .arg("1")
+        .arg("--DataDirectory")
+        .arg(data_dir)
+        .arg("--__OwningControllerProcess")
+        .arg(process::id().to_string())
+        .stdout(Stdio::null())
+        .spawn()
+}
+
+// eg. `status/bootstrap-phase=NOTICE BOOTSTRAP PROGRESS=50 TAG=loading_descriptors SUMMARY="Loading relay descriptors"`
+fn parse_bootstrap_phase(response: &str) -> TCResult<BootstrapProgress> {
+    let progress = response
+        .split(' ')
+        .find(|section| section.starts_with("PROGRESS="))
+        .and_then(|section| section["PROGRESS=".len()..].parse().ok())
+        .ok_or(TCError::UnknownResponse)?;
+    let summary = response
+        .split("SUMMARY=\"")
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .unwrap_or_default()
+        .to_string();
+    Ok(BootstrapProgress { progress, summary })
+}
+
 #[allow(clippy::write_with_newline)]
 fn send_command(writer: &mut impl Write, command: String) -> Result<(), io::Error> {
     write!(writer, "{}\r\n", command)?;
@@ -246,7 +321,7 @@ fn is_last_line(line: &str) -> TCResult<bool> {
 }
 
 fn parse_status(line: &str) -> TCResult<u32> {
-    (&line[0..3]).parse().map_err(|_| TCError::UnknownResponse)
+    line[0..3].parse().map_err(|_| TCError::UnknownResponse)
 }
 
 fn parse_line<'b>(
@@ -258,8 +333,8 @@ fn parse_line<'b>(
         return Err(TCError::UnknownResponse);
     }
     let (buf_s, msg) = buf.split_at(4);
-    let status = parse_status(&buf_s)?;
-    let is_last_line = is_last_line(&buf_s)?;
+    let status = parse_status(buf_s)?;
+    let is_last_line = is_last_line(buf_s)?;
     Ok((status, is_last_line, msg))
 }
 fn read_lines(read: &mut impl BufRead) -> TCResult<Vec<String>> {
@@ -289,3 +364,20 @@ fn handle_code(status: u32) -> TCResult<()> {
             .unwrap_or(UnknownResponse)),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_bootstrap_phase() {
+        let progress = parse_bootstrap_phase(
+            "status/bootstrap-phase=NOTICE BOOTSTRAP PROGRESS=50 TAG=loading_descriptors SUMMARY=\"Loading relay descriptors\" OK",
+        )
+        .unwrap();
+        assert_eq!(progress.progress, 50);
+        assert_eq!(progress.summary, "Loading relay descriptors");
+        assert!(!progress.is_done());
+        assert!(parse_bootstrap_phase("status/bootstrap-phase=NOTICE BOOTSTRAP").is_err());
+    }
+}
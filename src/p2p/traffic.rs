@@ -0,0 +1,270 @@
+use super::connection::ConnectionId;
+use crate::bisq::payload::kind::MessageKind;
+use lazy_static::lazy_static;
+use prost::encoding::encoded_len_varint;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex, RwLock},
+    time::Instant,
+};
+
+/// Rates are averaged over the last minute
+const WINDOW_SECS: u64 = 60;
+// The message version field of the envelope around a message
+const MESSAGE_VERSION_LEN: usize = 2;
+
+lazy_static! {
+    static ref CONNECTIONS: RwLock<HashMap<ConnectionId, Traffic>> = RwLock::new(HashMap::new());
+}
+
+/// Bytes of the length delimited envelope around a message that encodes
+/// to `message_len` bytes, messages are counted as if they were sent on
+/// their own even when they arrived bundled.
+pub fn envelope_len(message_len: usize) -> usize {
+    let len = message_len + MESSAGE_VERSION_LEN;
+    len + encoded_len_varint(len as u64)
+}
+
+#[derive(Clone, Copy)]
+pub enum Direction {
+    Received,
+    Sent,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct Counter {
+    pub messages: u64,
+    pub bytes: u64,
+}
+impl Counter {
+    fn add(&mut self, bytes: usize) {
+        self.messages += 1;
+        self.bytes += bytes as u64;
+    }
+}
+
+/// The traffic of a connection, kept until it is closed.
+#[derive(Clone)]
+pub struct Traffic(Arc<Mutex<ConnectionTraffic>>);
+impl Traffic {
+    pub fn open(id: ConnectionId) -> Self {
+        let traffic = Traffic(Arc::new(Mutex::new(ConnectionTraffic::new(Instant::now()))));
+        CONNECTIONS
+            .write()
+            .expect("Corrupted lock in traffic")
+            .insert(id, traffic.clone());
+        traffic
+    }
+
+    pub fn close(id: &ConnectionId) {
+        CONNECTIONS
+            .write()
+            .expect("Corrupted lock in traffic")
+            .remove(id);
+    }
+
+    pub fn record(&self, direction: Direction, kind: MessageKind, bytes: usize) {
+        self.0.lock().expect("Corrupted lock in traffic").record(
+            direction,
+            kind,
+            bytes,
+            Instant::now(),
+        )
+    }
+
+    /// Counts an envelope the peer sent in another message version than
+    /// ours, returns how many it sent so far.
+    pub fn version_mismatch(&self, message_version: i32) -> u64 {
+        let mut traffic = self.0.lock().expect("Corrupted lock in traffic");
+        traffic.version_mismatches += 1;
+        traffic.peer_message_version = Some(message_version);
+        traffic.version_mismatches
+    }
+
+    /// `None` once the connection was closed.
+    pub fn report(id: &ConnectionId) -> Option<TrafficReport> {
+        let traffic = CONNECTIONS
+            .read()
+            .expect("Corrupted lock in traffic")
+            .get(id)?
+            .clone();
+        let report = traffic
+            .0
+            .lock()
+            .expect("Corrupted lock in traffic")
+            .report(Instant::now());
+        Some(report)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrafficReport {
+    pub open_secs: u64,
+    pub received: DirectionReport,
+    pub sent: DirectionReport,
+    /// Envelopes received in another message version than ours
+    pub version_mismatches: u64,
+    /// The version of the last of them
+    pub peer_message_version: Option<i32>,
+}
+#[derive(Debug, Serialize)]
+pub struct DirectionReport {
+    pub messages: u64,
+    pub bytes: u64,
+    /// Over the last minute
+    pub messages_per_sec: f64,
+    pub bytes_per_sec: f64,
+    pub kinds: BTreeMap<String, Counter>,
+}
+
+struct ConnectionTraffic {
+    opened: Instant,
+    received: DirectionTraffic,
+    sent: DirectionTraffic,
+    version_mismatches: u64,
+    peer_message_version: Option<i32>,
+}
+impl ConnectionTraffic {
+    fn new(opened: Instant) -> Self {
+        Self {
+            opened,
+            received: DirectionTraffic::default(),
+            sent: DirectionTraffic::default(),
+            version_mismatches: 0,
+            peer_message_version: None,
+        }
+    }
+
+    fn record(&mut self, direction: Direction, kind: MessageKind, bytes: usize, now: Instant) {
+        let second = now.duration_since(self.opened).as_secs();
+        let traffic = match direction {
+            Direction::Received => &mut self.received,
+            Direction::Sent => &mut self.sent,
+        };
+        traffic.total.add(bytes);
+        traffic.kinds.entry(kind).or_default().add(bytes);
+        traffic.window.add(second, bytes);
+    }
+
+    fn report(&self, now: Instant) -> TrafficReport {
+        let open_secs = now.duration_since(self.opened).as_secs();
+        TrafficReport {
+            open_secs,
+            received: self.received.report(open_secs),
+            sent: self.sent.report(open_secs),
+            version_mismatches: self.version_mismatches,
+            peer_message_version: self.peer_message_version,
+        }
+    }
+}
+
+#[derive(Default)]
+struct DirectionTraffic {
+    total: Counter,
+    kinds: BTreeMap<MessageKind, Counter>,
+    window: Window,
+}
+impl DirectionTraffic {
+    fn report(&self, second: u64) -> DirectionReport {
+        let last_minute = self.window.sum(second);
+        // Young connections haven't been open for the whole window
+        let secs = (second + 1).min(WINDOW_SECS) as f64;
+        DirectionReport {
+            messages: self.total.messages,
+            bytes: self.total.bytes,
+            messages_per_sec: last_minute.messages as f64 / secs,
+            bytes_per_sec: last_minute.bytes as f64 / secs,
+            kinds: self
+                .kinds
+                .iter()
+                .map(|(kind, counter)| (kind.to_string(), *counter))
+                .collect(),
+        }
+    }
+}
+
+/// One counter for each second of the window, by seconds since the
+/// connection was opened.
+struct Window {
+    buckets: [Counter; WINDOW_SECS as usize],
+    /// The latest second counted
+    second: u64,
+}
+impl Default for Window {
+    fn default() -> Self {
+        Self {
+            buckets: [Counter::default(); WINDOW_SECS as usize],
+            second: 0,
+        }
+    }
+}
+impl Window {
+    fn add(&mut self, second: u64, bytes: usize) {
+        if second > self.second {
+            for passed in self.second + 1..=second.min(self.second + WINDOW_SECS) {
+                self.buckets[(passed % WINDOW_SECS) as usize] = Counter::default();
+            }
+            self.second = second;
+        }
+        self.buckets[(self.second % WINDOW_SECS) as usize].add(bytes);
+    }
+
+    /// Of the window ending at `second`.
+    fn sum(&self, second: u64) -> Counter {
+        let mut sum = Counter::default();
+        for counted in (second + 1).saturating_sub(WINDOW_SECS)..=self.second {
+            let bucket = self.buckets[(counted % WINDOW_SECS) as usize];
+            sum.messages += bucket.messages;
+            sum.bytes += bucket.bytes;
+        }
+        sum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn counts_by_kind_and_over_the_last_minute() {
+        let opened = Instant::now();
+        let at = |secs| opened + Duration::from_secs(secs);
+        let ping = MessageKind {
+            message: "Ping",
+            payload: None,
+        };
+        let offer = MessageKind {
+            message: "AddDataMessage",
+            payload: Some("OfferPayload"),
+        };
+        let mut traffic = ConnectionTraffic::new(opened);
+        traffic.record(Direction::Received, offer, 1_000, at(0));
+        traffic.record(Direction::Received, ping, 10, at(0));
+        traffic.record(Direction::Sent, ping, 10, at(1));
+
+        let report = traffic.report(at(1));
+        assert_eq!(report.received.messages, 2);
+        assert_eq!(report.received.bytes, 1_010);
+        assert_eq!(report.received.bytes_per_sec, 505.0);
+        assert_eq!(
+            report.received.kinds.get("AddDataMessage/OfferPayload"),
+            Some(&Counter {
+                messages: 1,
+                bytes: 1_000
+            })
+        );
+        assert_eq!(report.sent.messages, 1);
+
+        traffic.record(Direction::Received, offer, 600, at(100));
+        let report = traffic.report(at(119));
+        assert_eq!(report.open_secs, 119);
+        assert_eq!(report.received.bytes, 1_610);
+        assert_eq!(report.received.messages_per_sec, 1.0 / 60.0);
+        assert_eq!(report.received.bytes_per_sec, 10.0);
+        // Nothing sent in the last minute
+        assert_eq!(report.sent.bytes_per_sec, 0.0);
+        assert_eq!(traffic.report(at(200)).received.bytes_per_sec, 0.0);
+    }
+}
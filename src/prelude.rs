@@ -1,12 +1,14 @@
+#[cfg(not(target_arch = "wasm32"))]
 pub use actix::{
     fut::{ActorFuture, ActorStream},
     *,
 };
-pub use bitcoin_hashes::{
-    hex::{FromHex, ToHex},
-    hmac, ripemd160, sha256, Hash, HashEngine,
-};
+#[cfg(not(target_arch = "wasm32"))]
+pub use bitcoin_hashes::{hex::FromHex, hmac, HashEngine};
+pub use bitcoin_hashes::{hex::ToHex, ripemd160, sha256, Hash};
+#[cfg(not(target_arch = "wasm32"))]
 pub use futures_locks as locks;
+#[cfg(not(target_arch = "wasm32"))]
 pub use tokio::{
     io, net,
     prelude::{
@@ -14,9 +16,10 @@ pub use tokio::{
         stream::Stream,
         *,
     },
-    reactor, runtime, sync,
+    reactor, sync,
 };
 
+#[cfg(not(target_arch = "wasm32"))]
 macro_rules! arbiter_spawn {
     ($expr:expr) => {
         Arbiter::spawn($expr.then(|_| Ok(())))
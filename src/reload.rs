@@ -0,0 +1,154 @@
+//! Reloading the config file on SIGHUP or `POST /admin/reload`. Settings
+//! that don't need a restart are applied right away: the process wide ones
+//! here, the others by the actors subscribed to `ConfigUpdated` and the
+//! handlers registered with `on_update`.
+use crate::{bisq::constants::BaseCurrencyNetwork, logging, notifier::Rule, p2p, prelude::*};
+use lazy_static::lazy_static;
+use std::sync::{Arc, Mutex};
+
+type Source = Box<dyn Fn() -> Result<ConfigUpdate, String> + Send>;
+type Handler = Box<dyn Fn(&ConfigUpdate) + Send>;
+
+lazy_static! {
+    static ref SOURCE: Mutex<Option<Source>> = Mutex::new(None);
+    static ref SUBSCRIBERS: Mutex<Vec<Recipient<ConfigUpdated>>> = Mutex::new(Vec::new());
+    static ref HANDLERS: Mutex<Vec<Handler>> = Mutex::new(Vec::new());
+}
+
+/// The settings of the config file that can change while the daemon runs.
+/// Those that aren't set, or are overridden by a flag or env var, stay as
+/// they are.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConfigUpdate {
+    pub log_filters: Option<String>,
+    /// Bytes per second, 0 for no limit
+    pub max_upload_rate: Option<usize>,
+    pub api_token: Option<String>,
+    pub notify_rules: Option<Vec<Rule>>,
+    pub max_connections: Vec<(BaseCurrencyNetwork, usize)>,
+}
+impl ConfigUpdate {
+    pub fn max_connections(&self, network: BaseCurrencyNetwork) -> Option<usize> {
+        self.max_connections
+            .iter()
+            .find(|(n, _)| *n == network)
+            .map(|(_, max)| *max)
+    }
+
+    /// The names of the settings it changes, like the flags.
+    pub fn settings(&self) -> Vec<&'static str> {
+        let mut settings = Vec::new();
+        if self.log_filters.is_some() {
+            settings.push("log-level");
+        }
+        if self.max_upload_rate.is_some() {
+            settings.push("max-upload-rate");
+        }
+        if self.api_token.is_some() {
+            settings.push("api-token");
+        }
+        if self.notify_rules.is_some() {
+            settings.push("notify-rule");
+        }
+        if !self.max_connections.is_empty() {
+            settings.push("max-connections");
+        }
+        settings
+    }
+}
+
+pub struct ConfigUpdated(pub Arc<ConfigUpdate>);
+impl Message for ConfigUpdated {
+    type Result = ();
+}
+
+/// Reads the settings from the config file, set once by the cli.
+pub fn set_source(source: impl Fn() -> Result<ConfigUpdate, String> + Send + 'static) {
+    *SOURCE.lock().expect("Corrupted lock in reload") = Some(Box::new(source));
+}
+
+pub fn subscribe(subscriber: Recipient<ConfigUpdated>) {
+    SUBSCRIBERS
+        .lock()
+        .expect("Corrupted lock in reload")
+        .push(subscriber);
+}
+
+/// For state that isn't kept by an actor.
+pub fn on_update(handler: impl Fn(&ConfigUpdate) + Send + 'static) {
+    HANDLERS
+        .lock()
+        .expect("Corrupted lock in reload")
+        .push(Box::new(handler));
+}
+
+/// Reads the config file again and applies what it sets. Nothing is
+/// applied when it is invalid.
+pub fn run() -> Result<Arc<ConfigUpdate>, String> {
+    let update = match SOURCE.lock().expect("Corrupted lock in reload").as_ref() {
+        Some(source) => source()?,
+        None => return Err("There is no config file to reload".into()),
+    };
+    if let Some(filters) = update.log_filters.as_ref() {
+        logging::set_filters(filters)?;
+    }
+    if let Some(rate) = update.max_upload_rate {
+        p2p::limit_upload_rate(rate);
+    }
+    let update = Arc::new(update);
+    SUBSCRIBERS
+        .lock()
+        .expect("Corrupted lock in reload")
+        .retain(|subscriber| subscriber.do_send(ConfigUpdated(update.clone())).is_ok());
+    for handler in HANDLERS.lock().expect("Corrupted lock in reload").iter() {
+        handler(&update);
+    }
+    info!("Reloaded config: {}", update.settings().join(", "));
+    Ok(update)
+}
+
+/// Reloads on every SIGHUP.
+#[cfg(unix)]
+pub fn on_signals() {
+    use tokio_signal::unix::Signal;
+    Arbiter::spawn(
+        Signal::new(libc::SIGHUP)
+            .flatten_stream()
+            .for_each(|_| {
+                if let Err(e) = run() {
+                    error!("Couldn't reload config: {}", e);
+                }
+                Ok(())
+            })
+            .map_err(|e| error!("Couldn't listen for SIGHUP: {}", e)),
+    );
+}
+/// There is no SIGHUP, only `POST /admin/reload` reloads.
+#[cfg(not(unix))]
+pub fn on_signals() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_the_settings_it_changes() {
+        let update = ConfigUpdate {
+            max_upload_rate: Some(0),
+            max_connections: vec![(BaseCurrencyNetwork::BtcTestnet, 20)],
+            ..ConfigUpdate::default()
+        };
+        assert_eq!(
+            update.settings(),
+            vec!["max-upload-rate", "max-connections"]
+        );
+        assert_eq!(
+            update.max_connections(BaseCurrencyNetwork::BtcTestnet),
+            Some(20)
+        );
+        assert_eq!(
+            update.max_connections(BaseCurrencyNetwork::BtcMainnet),
+            None
+        );
+    }
+}
@@ -0,0 +1,73 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RotationConfig {
+    pub path: PathBuf,
+    /// Bytes after which the file is rotated
+    pub max_size: u64,
+    /// Rotated files kept as `<path>.1` (the latest) to `<path>.<max_files>`
+    pub max_files: usize,
+}
+
+/// A file that is appended to until it is full, then moved aside.
+pub struct RotatingFile {
+    config: RotationConfig,
+    file: File,
+    size: u64,
+}
+impl RotatingFile {
+    pub fn open(config: RotationConfig) -> io::Result<Self> {
+        if let Some(dir) = config.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&config.path)?;
+        let size = file.metadata()?.len();
+        Ok(Self { config, file, size })
+    }
+
+    /// Rotates first when `bytes` don't fit anymore, they are never split.
+    pub fn append(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if self.size > 0 && self.size + bytes.len() as u64 > self.config.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(bytes)?;
+        self.size += bytes.len() as u64;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let rotated = |n: usize| {
+            let mut path = OsString::from(&self.config.path);
+            path.push(format!(".{}", n));
+            PathBuf::from(path)
+        };
+        if self.config.max_files == 0 {
+            fs::remove_file(&self.config.path)?;
+        } else {
+            for n in (1..self.config.max_files).rev() {
+                if rotated(n).exists() {
+                    fs::rename(rotated(n), rotated(n + 1))?;
+                }
+            }
+            fs::rename(&self.config.path, rotated(1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.config.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
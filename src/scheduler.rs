@@ -0,0 +1,215 @@
+use crate::prelude::*;
+use lazy_static::lazy_static;
+use rand::{thread_rng, Rng};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+lazy_static! {
+    static ref TASKS: RwLock<HashMap<usize, TaskStats>> = RwLock::new(HashMap::new());
+}
+static NEXT_TASK_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A periodic task of an actor. Every run is delayed by a random
+/// amount up to `jitter` so that peers don't act in lockstep.
+#[derive(Clone, Copy)]
+pub struct Task {
+    name: &'static str,
+    interval: Duration,
+    jitter: Duration,
+}
+impl Task {
+    pub const fn new(name: &'static str, interval: Duration) -> Self {
+        Self {
+            name,
+            interval,
+            jitter: Duration::from_secs(0),
+        }
+    }
+
+    pub const fn with_jitter(self, jitter: Duration) -> Self {
+        Self { jitter, ..self }
+    }
+
+    fn next_delay(&self) -> Duration {
+        let jitter_millis = self.jitter.as_millis() as u64;
+        if jitter_millis == 0 {
+            return self.interval;
+        }
+        self.interval + Duration::from_millis(thread_rng().gen_range(0, jitter_millis))
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct TaskStats {
+    pub name: &'static str,
+    pub interval_secs: u64,
+    pub jitter_secs: u64,
+    pub runs: u64,
+    pub last_run: Option<u64>,
+    pub last_duration_ms: u64,
+    pub max_duration_ms: u64,
+}
+
+/// Snapshot of all currently scheduled tasks.
+pub fn stats() -> Vec<TaskStats> {
+    let mut stats: Vec<TaskStats> = TASKS
+        .read()
+        .expect("Corrupted lock in scheduler")
+        .values()
+        .cloned()
+        .collect();
+    stats.sort_by_key(|s| s.name);
+    stats
+}
+
+#[derive(Clone)]
+pub struct TaskHandle(Arc<AtomicBool>);
+impl TaskHandle {
+    /// Stops the task before its next run.
+    #[allow(dead_code)]
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// Removes the stats of a task once it is cancelled or its actor stopped.
+struct Registration(usize);
+impl Registration {
+    fn new(task: &Task) -> Self {
+        let id = NEXT_TASK_ID.fetch_add(1, Ordering::Relaxed);
+        TASKS.write().expect("Corrupted lock in scheduler").insert(
+            id,
+            TaskStats {
+                name: task.name,
+                interval_secs: task.interval.as_secs(),
+                jitter_secs: task.jitter.as_secs(),
+                runs: 0,
+                last_run: None,
+                last_duration_ms: 0,
+                max_duration_ms: 0,
+            },
+        );
+        Registration(id)
+    }
+
+    fn record(&self, duration: Duration) {
+        let mut tasks = TASKS.write().expect("Corrupted lock in scheduler");
+        if let Some(stats) = tasks.get_mut(&self.0) {
+            let duration_ms = duration.as_millis() as u64;
+            stats.runs += 1;
+            stats.last_run = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|t| t.as_secs());
+            stats.last_duration_ms = duration_ms;
+            stats.max_duration_ms = stats.max_duration_ms.max(duration_ms);
+        }
+    }
+}
+impl Drop for Registration {
+    fn drop(&mut self) {
+        if let Ok(mut tasks) = TASKS.write() {
+            tasks.remove(&self.0);
+        }
+    }
+}
+
+pub trait Schedule<A: Actor> {
+    /// Runs `f` every `task.interval` (plus jitter) until the handle is
+    /// cancelled or the actor stops. The recorded duration only covers `f`
+    /// itself, not the futures it spawns.
+    fn schedule<F>(&mut self, task: Task, f: F) -> TaskHandle
+    where
+        F: FnMut(&mut A, &mut A::Context) + 'static;
+}
+impl<A> Schedule<A> for Context<A>
+where
+    A: Actor<Context = Context<A>>,
+{
+    fn schedule<F>(&mut self, task: Task, f: F) -> TaskHandle
+    where
+        F: FnMut(&mut A, &mut Context<A>) + 'static,
+    {
+        let handle = TaskHandle(Arc::new(AtomicBool::new(false)));
+        schedule_next(self, task, f, handle.clone(), Registration::new(&task));
+        handle
+    }
+}
+
+fn schedule_next<A, F>(
+    ctx: &mut Context<A>,
+    task: Task,
+    mut f: F,
+    handle: TaskHandle,
+    registration: Registration,
+) where
+    A: Actor<Context = Context<A>>,
+    F: FnMut(&mut A, &mut Context<A>) + 'static,
+{
+    ctx.run_later(task.next_delay(), move |actor, ctx| {
+        if handle.is_cancelled() {
+            debug!("Task {} cancelled", task.name);
+            return;
+        }
+        let started = Instant::now();
+        f(actor, ctx);
+        registration.record(started.elapsed());
+        schedule_next(ctx, task, f, handle, registration);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Counter(usize);
+    impl Actor for Counter {
+        type Context = Context<Self>;
+        fn started(&mut self, ctx: &mut Self::Context) {
+            let task = Task::new("test_counter", Duration::from_millis(1));
+            let handle = ctx.schedule(task, |counter, _| counter.0 += 1);
+            ctx.run_later(Duration::from_millis(50), move |counter, _| {
+                handle.cancel();
+                let runs = stats()
+                    .into_iter()
+                    .find(|s| s.name == "test_counter")
+                    .map(|s| s.runs as usize);
+                assert_eq!(runs, Some(counter.0));
+            });
+            ctx.run_later(Duration::from_millis(100), |counter, _| {
+                assert!(counter.0 > 0);
+                assert!(stats().iter().all(|s| s.name != "test_counter"));
+                System::current().stop();
+            });
+        }
+    }
+
+    #[test]
+    fn schedule_and_cancel() {
+        let _ = System::run(|| {
+            Counter(0).start();
+        });
+    }
+
+    #[test]
+    fn jitter() {
+        let task = Task::new("test", Duration::from_secs(10));
+        assert_eq!(task.next_delay(), Duration::from_secs(10));
+        let task = task.with_jitter(Duration::from_secs(5));
+        for _ in 0..100 {
+            let delay = task.next_delay();
+            assert!(delay >= Duration::from_secs(10) && delay < Duration::from_secs(15));
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! Graceful shutdown on SIGINT / SIGTERM or `POST /shutdown`: the registered
+//! actors stop listening, close their connections and flush their stores one
+//! after the other before the actix system is stopped.
+use crate::prelude::*;
+use lazy_static::lazy_static;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+/// How long a step may take before the next one is started.
+const STEP_TIMEOUT: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    static ref STEPS: Mutex<Vec<Recipient<Stop>>> = Mutex::new(Vec::new());
+}
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Asks an actor to get ready for the process to exit. The shutdown waits
+/// for the response before the next step.
+pub struct Stop;
+impl Message for Stop {
+    type Result = Result<(), ()>;
+}
+
+/// Steps run in the order they were registered.
+pub fn register(step: Recipient<Stop>) {
+    STEPS.lock().expect("Corrupted lock in shutdown").push(step);
+}
+
+pub fn shutting_down() -> bool {
+    SHUTTING_DOWN.load(Ordering::SeqCst)
+}
+
+/// Runs the registered steps and stops the actix system. Only the first
+/// call does anything.
+pub fn run() -> impl Future<Item = (), Error = ()> {
+    if SHUTTING_DOWN.swap(true, Ordering::SeqCst) {
+        return future::Either::A(future::ok(()));
+    }
+    info!("Shutting down");
+    let steps: Vec<_> = STEPS
+        .lock()
+        .expect("Corrupted lock in shutdown")
+        .drain(..)
+        .collect();
+    future::Either::B(
+        stream::iter_ok(steps)
+            .for_each(|step| {
+                step.send(Stop).timeout(STEP_TIMEOUT).then(|result| {
+                    if let Err(e) = result {
+                        warn!("Shutdown step failed: {:?}", e);
+                    }
+                    Ok(())
+                })
+            })
+            .then(|_: Result<(), ()>| {
+                info!("Shutdown complete");
+                System::current().stop();
+                Ok(())
+            }),
+    )
+}
+
+/// Shuts down on the first SIGINT or SIGTERM.
+#[cfg(unix)]
+pub fn on_signals() {
+    use tokio_signal::unix::Signal;
+    let signals = Signal::new(libc::SIGINT)
+        .flatten_stream()
+        .select(Signal::new(libc::SIGTERM).flatten_stream());
+    Arbiter::spawn(signals.into_future().then(|_| run()));
+}
+/// Shuts down on ctrl-c.
+#[cfg(not(unix))]
+pub fn on_signals() {
+    Arbiter::spawn(
+        tokio_signal::ctrl_c()
+            .flatten_stream()
+            .into_future()
+            .then(|_| run()),
+    );
+}
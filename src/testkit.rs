@@ -0,0 +1,309 @@
+//! Several risq nodes in one process that talk p2p over localhost TCP, to
+//! test what they exchange end to end. The first node is a seed node the
+//! others bootstrap from with a `PreliminaryGetDataRequest`, data published
+//! to any node is relayed through it. `inject_faults` makes the connections
+//! between them drop, delay, duplicate or corrupt frames and disconnect.
+use crate::{
+    bisq::{
+        constants::BaseCurrencyNetwork, crypto::KeyRing, payload::*, SequencedMessageHash, Sha256,
+    },
+    daemon,
+    domain::{
+        amount::{BtcAmount, NumberWithPrecision},
+        budget::Evictions,
+        market,
+        offer::{OfferAmount, OfferDirection, OfferPrice, OpenOffer},
+    },
+    error,
+    node::{Node, NodeBuilder},
+    p2p::{dispatch::*, transport, Connection, ConnectionId, Payload},
+    prelude::*,
+};
+use std::{
+    path::PathBuf,
+    sync::mpsc,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+pub use crate::domain::offer::OfferIndex;
+/// Faults in what the connections of all nodes write, eg. to see sequence
+/// numbers, dedup and reconnects hold up when frames get lost.
+pub use crate::p2p::{inject_faults, Faults};
+
+const NETWORK: BaseCurrencyNetwork = BaseCurrencyNetwork::BtcRegtest;
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+pub struct TestNetwork {
+    dir: PathBuf,
+    base_port: u16,
+    nodes: Vec<(NodeAddress, Node)>,
+}
+impl TestNetwork {
+    /// Starts the seed node. Node `n` listens on `base_port + n` for peers
+    /// and on `base_port + 100 + n` for the api, and keeps its data in
+    /// `dir/node-<n>`.
+    pub fn start(dir: impl Into<PathBuf>, base_port: u16) -> Result<Self, String> {
+        let mut network = TestNetwork {
+            dir: dir.into(),
+            base_port,
+            nodes: Vec::new(),
+        };
+        network.add_node()?;
+        Ok(network)
+    }
+
+    /// Starts a node that bootstraps from the seed node and returns its index.
+    pub fn add_node(&mut self) -> Result<usize, String> {
+        let idx = self.nodes.len();
+        let port = self.base_port + idx as u16;
+        let mut builder = NodeBuilder::new(NETWORK, self.dir.join(format!("node-{}", idx)))
+            .server_port(port)
+            .api_port(self.base_port + 100 + idx as u16);
+        builder = match self.nodes.first() {
+            None => builder.seed_mode(true),
+            Some((seed, _)) => builder.force_seed(seed.clone()),
+        };
+        let node = builder.start()?;
+        self.nodes.push((
+            NodeAddress {
+                host_name: "127.0.0.1".into(),
+                port: port.into(),
+            },
+            node,
+        ));
+        Ok(idx)
+    }
+
+    /// Node 0 is the seed node.
+    pub fn node(&self, idx: usize) -> &Node {
+        &self.nodes[idx].1
+    }
+
+    /// Sends `entry` to a node like a peer adding data to the network does.
+    pub fn publish(&self, idx: usize, entry: ProtectedStorageEntry) -> Result<(), String> {
+        send(
+            self.nodes[idx].0.clone(),
+            AddDataMessage {
+                entry: Some(StorageEntryWrapper {
+                    message: Some(storage_entry_wrapper::Message::ProtectedStorageEntry(entry)),
+                }),
+            },
+        )
+    }
+
+    pub fn stop(self) {
+        for (_, node) in self.nodes.into_iter().rev() {
+            node.stop();
+        }
+    }
+}
+
+/// An offer selling 0.01 BTC for EUR, signed by a new key like a bisq maker
+/// signs the offers it publishes.
+pub fn signed_offer(id: &str) -> ProtectedStorageEntry {
+    let key_ring = KeyRing::generate().expect("Couldn't generate key ring");
+    let payload = StoragePayload {
+        message: Some(storage_payload::Message::OfferPayload(OfferPayload {
+            id: id.into(),
+            date: now_millis(),
+            pub_key_ring: Some(key_ring.pub_key_ring()),
+            direction: offer_payload::Direction::Sell as i32,
+            base_currency_code: "BTC".into(),
+            counter_currency_code: "EUR".into(),
+            price: 90_000_000,
+            amount: 1_000_000,
+            min_amount: 1_000_000,
+            payment_method_id: "SEPA".into(),
+            ..Default::default()
+        })),
+    };
+    let sequence_number = 1;
+    let hash = DataAndSeqNrPair {
+        payload: Some(payload.clone()),
+        sequence_number,
+    }
+    .sha256();
+    ProtectedStorageEntry {
+        storage_payload: Some(payload),
+        owner_pub_key_bytes: key_ring.pub_key_ring().signature_pub_key_bytes,
+        sequence_number,
+        signature: key_ring
+            .sign(&hash.into_inner())
+            .expect("Couldn't sign offer"),
+        creation_time_stamp: now_millis(),
+    }
+}
+
+/// Offer `n` of a big offer book, spread over 50 markets, 20 payment
+/// methods and 200 makers.
+pub fn open_offer(n: usize) -> OpenOffer {
+    let payment_methods = [
+        "SEPA",
+        "SEPA_INSTANT",
+        "REVOLUT",
+        "ZELLE",
+        "F2F",
+        "UPHOLD",
+        "MONEY_GRAM",
+        "WISE",
+        "CASH_DEPOSIT",
+        "NATIONAL_BANK",
+        "SAME_BANK",
+        "SPECIFIC_BANKS",
+        "INTERAC_E_TRANSFER",
+        "SWISH",
+        "PERFECT_MONEY",
+        "ALI_PAY",
+        "WECHAT_PAY",
+        "POPMONEY",
+        "HAL_CASH",
+        "PROMPT_PAY",
+    ];
+    let id = format!("offer-{}", n);
+    OpenOffer::new(
+        SequencedMessageHash::new(sha256::Hash::hash(id.as_bytes())),
+        &market::ALL[n % 50],
+        id.into(),
+        if n.is_multiple_of(2) {
+            OfferDirection::Buy
+        } else {
+            OfferDirection::Sell
+        },
+        OfferPrice::Fixed(NumberWithPrecision::new(9000, 0)),
+        OfferAmount {
+            total: BtcAmount::from_sats(10_000_000),
+            min: BtcAmount::from_sats(1_000_000),
+        },
+        payment_methods[n % payment_methods.len()].into(),
+        String::new(),
+        SystemTime::now(),
+        0.into(),
+        Arc::new(OfferPayload {
+            owner_node_address: Some(NodeAddress {
+                host_name: format!("maker{}.onion", n % 200),
+                port: 9999,
+            }),
+            ..Default::default()
+        }),
+    )
+}
+
+/// Polls `check` until it holds, false if it didn't within `timeout`.
+pub fn wait_until(timeout: Duration, mut check: impl FnMut() -> bool) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        if check() {
+            return true;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    check()
+}
+
+/// `count` offers signed by one key, like the entries a seed node sends a
+/// bootstrapping node.
+pub fn signed_entries(count: usize) -> Vec<StorageEntryWrapper> {
+    daemon::signed_entries(count)
+}
+
+/// Verifies `entries` and claims their sequence numbers on the calling
+/// thread, returns how many were claimed.
+pub fn ingest_on_this_thread(entries: Vec<StorageEntryWrapper>) -> usize {
+    claimed(daemon::ingest_on_this_thread(&sequenced_infos(), entries))
+}
+
+/// Like `ingest_on_this_thread` but on the verifier threads, in batches.
+pub fn ingest_on_pool(entries: Vec<StorageEntryWrapper>) -> usize {
+    claimed(daemon::ingest_on_pool(&sequenced_infos(), entries))
+}
+
+fn sequenced_infos() -> daemon::SequencedInfos {
+    daemon::SequencedInfos::new(None, Evictions::default())
+}
+
+fn claimed(ingested: Vec<daemon::Ingested>) -> usize {
+    ingested
+        .iter()
+        .filter(|ingested| matches!(ingested, daemon::Ingested::Claimed(..)))
+        .count()
+}
+
+#[derive(Clone)]
+struct IgnoreReplies;
+impl Dispatcher for IgnoreReplies {
+    fn dispatch(&self, _conn: ConnectionId, _msg: network_envelope::Message) -> Dispatch {
+        Dispatch::Consumed
+    }
+}
+
+// Opens a connection of its own that is closed once the message was written
+fn send<M>(addr: NodeAddress, message: M) -> Result<(), String>
+where
+    M: Into<network_envelope::Message> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = System::run(move || {
+            Arbiter::spawn(
+                Connection::open(addr, NETWORK.into(), IgnoreReplies, &transport::Direct)
+                    .and_then(|(_, conn)| {
+                        conn.send(Payload(message))
+                            .from_err::<error::Error>()
+                            .flatten()
+                    })
+                    .then(move |result| {
+                        let _ = sender.send(result.map_err(|e| format!("{:?}", e)));
+                        System::current().stop();
+                        Ok(())
+                    }),
+            )
+        });
+    });
+    receiver
+        .recv()
+        .map_err(|_| "Connection thread died".to_string())?
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    #[test]
+    fn propagates_offers_through_the_seed_node() {
+        let dir = std::env::temp_dir().join(format!("risq-testkit-{}", std::process::id()));
+        let mut network = TestNetwork::start(&dir, 17100).unwrap();
+        let maker = network.add_node().unwrap();
+        let taker = network.add_node().unwrap();
+        let has_offer = |network: &TestNetwork, idx| {
+            network
+                .node(idx)
+                .offers(Some("btc_eur"))
+                .map(|offers| offers.iter().any(|offer| offer.id == "testkit-offer"))
+                .unwrap_or(false)
+        };
+        network
+            .publish(maker, signed_offer("testkit-offer"))
+            .unwrap();
+        assert!(wait_until(TIMEOUT, || has_offer(&network, maker)));
+        assert!(wait_until(TIMEOUT, || has_offer(&network, 0)));
+        assert!(wait_until(TIMEOUT, || has_offer(&network, taker)));
+
+        // A node joining later receives it in the seed node's GetDataResponse
+        let late = network.add_node().unwrap();
+        assert!(wait_until(TIMEOUT, || has_offer(&network, late)));
+
+        network.stop();
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
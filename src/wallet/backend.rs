@@ -0,0 +1,29 @@
+use crate::domain::trade::wallet::WalletFuture;
+use bitcoin::{Address, Transaction};
+
+pub struct Utxo {
+    pub txid: String,
+    pub vout: u32,
+    pub value: u64,
+}
+
+/// Where the embedded wallet looks up its coins and broadcasts its txs.
+pub trait ChainBackend: Send + Sync {
+    /// Fee rate in sat/vB to confirm within `target` blocks.
+    fn fee_rate(&self, target: u32) -> WalletFuture<Option<f64>>;
+
+    fn tip_height(&self) -> WalletFuture<u32>;
+
+    /// Whether `address` was ever paid to, in the chain or the mempool.
+    fn address_used(&self, address: &Address) -> WalletFuture<bool>;
+
+    fn utxos(&self, address: &Address) -> WalletFuture<Vec<Utxo>>;
+
+    fn raw_tx(&self, txid: &str) -> WalletFuture<Transaction>;
+
+    /// The id of the tx that spends the output, if it was spent.
+    fn spending_tx(&self, txid: &str, vout: u32) -> WalletFuture<Option<String>>;
+
+    /// Broadcasts the tx and returns its id.
+    fn broadcast(&self, tx: &Transaction) -> WalletFuture<String>;
+}
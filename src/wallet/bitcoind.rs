@@ -0,0 +1,790 @@
+use super::*;
+use crate::{
+    bisq::{constants::btc_fee_receiver, payload::RawTransactionInput},
+    domain::trade::wallet::{MakerDeposit, MakerFunding, TakerFunding, WalletFuture},
+    prelude::*,
+};
+use bitcoin::{consensus, Address, OutPoint, Transaction};
+use bitcoin_hashes::sha256d;
+use reqwest::r#async::Client;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use std::{collections::HashSet, fs, path::PathBuf};
+
+/// How many of the latest wallet txs are searched for a payout.
+const PAYOUT_SCAN_DEPTH: u32 = 100;
+
+#[derive(Clone)]
+pub enum BitcoindAuth {
+    UserPass {
+        user: String,
+        password: String,
+    },
+    /// The `.cookie` file bitcoind writes to its data dir on every start
+    CookieFile(PathBuf),
+}
+
+#[derive(Clone)]
+struct Rpc {
+    client: Client,
+    url: Url,
+    auth: BitcoindAuth,
+}
+
+#[derive(Deserialize)]
+struct RpcResponse {
+    result: Value,
+    error: Option<RpcError>,
+}
+
+#[derive(Deserialize)]
+struct RpcError {
+    message: String,
+}
+
+impl Rpc {
+    fn credentials(&self) -> Result<(String, String), String> {
+        match &self.auth {
+            BitcoindAuth::UserPass { user, password } => Ok((user.clone(), password.clone())),
+            BitcoindAuth::CookieFile(path) => {
+                let cookie = fs::read_to_string(path)
+                    .map_err(|e| format!("Couldn't read cookie file {:?}: {}", path, e))?;
+                let mut parts = cookie.trim().splitn(2, ':');
+                match (parts.next(), parts.next()) {
+                    (Some(user), Some(password)) => Ok((user.into(), password.into())),
+                    _ => Err(format!("Malformed cookie file {:?}", path)),
+                }
+            }
+        }
+    }
+
+    fn call<T>(&self, method: &'static str, params: Value) -> WalletFuture<T>
+    where
+        T: DeserializeOwned + 'static,
+    {
+        let (user, password) = match self.credentials() {
+            Ok(credentials) => credentials,
+            Err(e) => return Box::new(future::err(e)),
+        };
+        let body = json!({
+            "jsonrpc": "1.0",
+            "id": "risq",
+            "method": method,
+            "params": params,
+        });
+        Box::new(
+            self.client
+                .post(self.url.clone())
+                .basic_auth(user, Some(password))
+                .json(&body)
+                .send()
+                .map_err(move |e| format!("Couldn't call bitcoind: {}", e))
+                // bitcoind answers errors with a status code and the error in the body
+                .and_then(move |mut response| {
+                    let status = response.status();
+                    response.json::<RpcResponse>().map_err(move |e| {
+                        format!("Invalid response from bitcoind ({}): {}", status, e)
+                    })
+                })
+                .and_then(move |response| match response.error {
+                    Some(error) => Err(format!("{} failed: {}", method, error.message)),
+                    None => serde_json::from_value(response.result)
+                        .map_err(|e| format!("Unexpected result of {}: {}", method, e)),
+                }),
+        )
+    }
+}
+
+#[derive(Deserialize)]
+struct FeeEstimate {
+    /// BTC per kvB
+    feerate: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct FundedTx {
+    hex: String,
+}
+
+#[derive(Deserialize)]
+struct SignedTx {
+    hex: String,
+    complete: bool,
+}
+
+#[derive(Deserialize)]
+struct WalletTx {
+    hex: String,
+}
+
+#[derive(Deserialize)]
+struct ListedTx {
+    txid: String,
+}
+
+#[derive(Deserialize)]
+struct AddressInfo {
+    pubkey: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct VerboseTx {
+    vout: Vec<VerboseOutput>,
+}
+
+#[derive(Deserialize)]
+struct VerboseOutput {
+    value: f64,
+    #[serde(rename = "scriptPubKey")]
+    script_pub_key: ScriptPubKey,
+}
+
+#[derive(Deserialize)]
+struct ScriptPubKey {
+    // bitcoind replaced `addresses` with `address` in 22.0
+    address: Option<String>,
+    #[serde(default)]
+    addresses: Vec<String>,
+}
+
+/// A wallet that lives in bitcoind and is driven through its JSON-RPC
+/// interface. Trade funds are reserved with `lockunspent` and the 2-of-2
+/// deposit output is imported as watch-only to detect the payout.
+#[derive(Clone)]
+pub struct BitcoindWallet {
+    rpc: Rpc,
+    network: BaseCurrencyNetwork,
+    fee_rates: FeeRates,
+}
+
+impl BitcoindWallet {
+    pub fn new(
+        url: Url,
+        auth: BitcoindAuth,
+        network: BaseCurrencyNetwork,
+        fee_rates: FeeRates,
+    ) -> Self {
+        Self {
+            rpc: Rpc {
+                client: Client::new(),
+                url,
+                auth,
+            },
+            network,
+            fee_rates,
+        }
+    }
+
+    /// Fee rate in sat/vB
+    fn estimate_fee_rate(&self) -> WalletFuture<Option<u64>> {
+        Box::new(
+            self.rpc
+                .call("estimatesmartfee", json!([FEE_TARGET_BLOCKS]))
+                .map(|estimate: FeeEstimate| {
+                    estimate
+                        .feerate
+                        .map(|rate| (rate * 100_000.0).ceil() as u64)
+                }),
+        )
+    }
+
+    fn reserve(&self, value: u64) -> WalletFuture<Reserved> {
+        let rpc = self.rpc.clone();
+        Box::new(
+            self.rpc
+                .call::<String>("getnewaddress", json!([]))
+                // Let the wallet select the coins for a placeholder tx that is never published
+                .and_then({
+                    let rpc = rpc.clone();
+                    move |address| {
+                        rpc.call::<String>(
+                            "createrawtransaction",
+                            json!([[], [{ address: btc(value) }]]),
+                        )
+                    }
+                })
+                .and_then({
+                    let rpc = rpc.clone();
+                    move |raw| {
+                        rpc.call::<FundedTx>(
+                            "fundrawtransaction",
+                            json!([raw, { "lockUnspents": true }]),
+                        )
+                    }
+                })
+                .and_then(|funded| decode(&funded.hex))
+                .and_then({
+                    let rpc = rpc.clone();
+                    move |tx| {
+                        let parents = tx.input.iter().map(|input| {
+                            let outpoint = input.previous_output;
+                            rpc.call::<WalletTx>(
+                                "gettransaction",
+                                json!([outpoint.txid.to_string(), true]),
+                            )
+                            .and_then(|parent| decode(&parent.hex))
+                            .and_then(move |parent| {
+                                let output = parent
+                                    .output
+                                    .get(outpoint.vout as usize)
+                                    .ok_or("Wallet selected a missing output")?;
+                                Ok(RawTransactionInput {
+                                    index: outpoint.vout as i64,
+                                    value: output.value as i64,
+                                    parent_transaction: consensus::serialize(&parent),
+                                })
+                            })
+                        });
+                        future::join_all(parents.collect::<Vec<_>>())
+                    }
+                })
+                .and_then(move |inputs| {
+                    let total: u64 = inputs.iter().map(|input| input.value as u64).sum();
+                    let change_output_value = total - value;
+                    let change: WalletFuture<Option<String>> = if change_output_value < DUST_LIMIT {
+                        Box::new(future::ok(None))
+                    } else {
+                        Box::new(rpc.call("getrawchangeaddress", json!([])).map(Some))
+                    };
+                    change.map(move |change_output_address| Reserved {
+                        change_output_value: change_output_address
+                            .as_ref()
+                            .map(|_| change_output_value)
+                            .unwrap_or(0),
+                        change_output_address,
+                        inputs,
+                    })
+                }),
+        )
+    }
+
+    fn trade_keys(&self) -> WalletFuture<TradeKeys> {
+        let rpc = self.rpc.clone();
+        Box::new(
+            self.rpc
+                .call::<String>("getnewaddress", json!([]))
+                .and_then({
+                    let rpc = rpc.clone();
+                    move |address| rpc.call::<AddressInfo>("getaddressinfo", json!([address]))
+                })
+                .and_then(|info| {
+                    let pub_key = info.pubkey.ok_or("Wallet address has no public key")?;
+                    Vec::from_hex(&pub_key).map_err(|e| e.to_string())
+                })
+                .and_then(move |multi_sig_pub_key| {
+                    rpc.call("getnewaddress", json!([]))
+                        .map(move |payout_address| TradeKeys {
+                            multi_sig_pub_key,
+                            payout_address,
+                        })
+                }),
+        )
+    }
+
+    fn verify_taker_fee(&self, trade: &Trade, taker: &TakerFunding) -> WalletFuture<()> {
+        let expected = taker_fee(trade);
+        if taker.taker_fee < expected {
+            return Box::new(future::err(format!(
+                "Taker fee of {} sat is below {} sat",
+                taker.taker_fee, expected
+            )));
+        }
+        let receiver = btc_fee_receiver(self.network);
+        let paid = taker.taker_fee;
+        Box::new(
+            self.rpc
+                .call("getrawtransaction", json!([taker.taker_fee_tx_id, true]))
+                .and_then(move |tx: VerboseTx| {
+                    let pays_fee = tx.vout.iter().any(|output| {
+                        let script = &output.script_pub_key;
+                        sats(output.value) >= paid
+                            && script
+                                .address
+                                .iter()
+                                .chain(script.addresses.iter())
+                                .any(|address| address == receiver)
+                    });
+                    if pays_fee {
+                        Ok(())
+                    } else {
+                        Err("Taker fee tx doesn't pay the trade fee".into())
+                    }
+                }),
+        )
+    }
+
+    /// Imports the 2-of-2 output of the deposit tx, so the wallet sees the payout.
+    fn watch_deposit(&self, deposit_tx: &Transaction) -> WalletFuture<()> {
+        let address = deposit_tx.output.first().and_then(|output| {
+            Address::from_script(&output.script_pubkey, bitcoin_network(self.network))
+        });
+        let address = match address {
+            Some(address) => address,
+            None => return Box::new(future::err("Deposit tx has no 2-of-2 output".into())),
+        };
+        Box::new(
+            self.rpc
+                .call::<Value>("importaddress", json!([address.to_string(), "risq", false]))
+                // Descriptor wallets can't import addresses, the payout is still found
+                // when it pays to one of our addresses
+                .then(move |result| {
+                    if let Err(e) = result {
+                        warn!("Couldn't watch deposit address {}: {}", address, e);
+                    }
+                    Ok(())
+                }),
+        )
+    }
+
+    fn sign(&self, tx: &[u8]) -> WalletFuture<SignedTx> {
+        self.rpc
+            .call("signrawtransactionwithwallet", json!([tx.to_hex()]))
+    }
+}
+
+impl TradeWallet for BitcoindWallet {
+    fn fund_taker(&self, trade: &Trade) -> WalletFuture<TakerFunding> {
+        let trade = trade.clone();
+        let wallet = self.clone();
+        let receiver = btc_fee_receiver(self.network);
+        let taker_fee = taker_fee(&trade);
+        Box::new(self.fee_rate().and_then(move |fee_rate| {
+            let tx_fee = tx_fee(fee_rate);
+            // The fee is only paid once the trade funds are reserved
+            wallet
+                .reserve(deposit_contribution(&trade, tx_fee, true))
+                .join(wallet.trade_keys())
+                .and_then(move |(reserved, keys)| {
+                    wallet
+                        .rpc
+                        .call::<String>("sendtoaddress", json!([receiver, btc(taker_fee)]))
+                        .map(move |taker_fee_tx_id| TakerFunding {
+                            taker_fee_tx_id,
+                            taker_fee,
+                            tx_fee,
+                            inputs: reserved.inputs,
+                            change_output_value: reserved.change_output_value,
+                            change_output_address: reserved.change_output_address,
+                            multi_sig_pub_key: keys.multi_sig_pub_key,
+                            payout_address: keys.payout_address,
+                        })
+                })
+        }))
+    }
+
+    fn pay_maker_fee(&self, maker_fee: u64) -> WalletFuture<MakerFunding> {
+        let wallet = self.clone();
+        let receiver = btc_fee_receiver(self.network);
+        Box::new(self.fee_rate().and_then(move |fee_rate| {
+            wallet
+                .rpc
+                .call::<String>("sendtoaddress", json!([receiver, btc(maker_fee)]))
+                .map(move |maker_fee_tx_id| MakerFunding {
+                    maker_fee_tx_id,
+                    tx_fee: tx_fee(fee_rate),
+                })
+        }))
+    }
+
+    fn sign_deposit_tx(&self, trade: &Trade, prepared_deposit_tx: &[u8]) -> WalletFuture<Vec<u8>> {
+        let tx: Transaction = match consensus::deserialize(prepared_deposit_tx) {
+            Ok(tx) => tx,
+            Err(e) => return Box::new(future::err(format!("Invalid deposit tx: {}", e))),
+        };
+        if let Err(e) = check_deposit_output(trade, &tx) {
+            return Box::new(future::err(e));
+        }
+        Box::new(
+            self.watch_deposit(&tx)
+                .join(self.sign(prepared_deposit_tx))
+                .and_then(|(_, signed)| Vec::from_hex(&signed.hex).map_err(|e| e.to_string())),
+        )
+    }
+
+    fn prepare_deposit_tx(
+        &self,
+        trade: &Trade,
+        taker: &TakerFunding,
+    ) -> WalletFuture<MakerDeposit> {
+        let taker_inputs: Result<Vec<Value>, String> = taker
+            .inputs
+            .iter()
+            .map(|input| {
+                let parent: Transaction = consensus::deserialize(&input.parent_transaction)
+                    .map_err(|e| format!("Invalid taker input: {}", e))?;
+                Ok(json!({ "txid": parent.txid().to_string(), "vout": input.index }))
+            })
+            .collect();
+        let taker_inputs = match taker_inputs {
+            Ok(inputs) => inputs,
+            Err(e) => return Box::new(future::err(e)),
+        };
+        let taker_pub_key = taker.multi_sig_pub_key.clone();
+        let mut outputs = vec![];
+        if let Some(address) = &taker.change_output_address {
+            outputs.push(json!({ address.clone(): btc(taker.change_output_value) }));
+        }
+        let deposit_value = deposit_output_value(trade, taker.tx_fee);
+        let we_buy = trade.direction() == OfferDirection::Buy;
+        let network = bitcoin_network(self.network);
+        let rpc = self.rpc.clone();
+        Box::new(
+            self.verify_taker_fee(trade, taker)
+                .and_then({
+                    let wallet = self.clone();
+                    let own_deposit = deposit_contribution(trade, taker.tx_fee, false);
+                    move |_| wallet.reserve(own_deposit).join(wallet.trade_keys())
+                })
+                .and_then(move |(reserved, keys)| {
+                    let (buyer, seller) = if we_buy {
+                        (&keys.multi_sig_pub_key, &taker_pub_key)
+                    } else {
+                        (&taker_pub_key, &keys.multi_sig_pub_key)
+                    };
+                    let multi_sig = Address::p2wsh(&tx::multi_sig_script(buyer, seller), network);
+                    let mut inputs = taker_inputs;
+                    for input in reserved.inputs.iter() {
+                        let parent: Transaction = consensus::deserialize(&input.parent_transaction)
+                            .map_err(|e| e.to_string())?;
+                        inputs.push(json!({
+                            "txid": parent.txid().to_string(),
+                            "vout": input.index,
+                        }));
+                    }
+                    outputs.insert(0, json!({ multi_sig.to_string(): btc(deposit_value) }));
+                    if let Some(address) = &reserved.change_output_address {
+                        outputs.push(json!({ address.clone(): btc(reserved.change_output_value) }));
+                    }
+                    Ok((json!([inputs, outputs]), reserved, keys))
+                })
+                .and_then(move |(params, reserved, keys)| {
+                    rpc.call::<String>("createrawtransaction", params)
+                        .and_then(move |raw| {
+                            let prepared_deposit_tx =
+                                Vec::from_hex(&raw).map_err(|e| e.to_string())?;
+                            Ok(MakerDeposit {
+                                prepared_deposit_tx,
+                                inputs: reserved.inputs,
+                                multi_sig_pub_key: keys.multi_sig_pub_key,
+                                payout_address: keys.payout_address,
+                            })
+                        })
+                }),
+        )
+    }
+
+    fn publish_deposit_tx(&self, _: &Trade, signed_deposit_tx: &[u8]) -> WalletFuture<Vec<u8>> {
+        let tx: Transaction = match consensus::deserialize(signed_deposit_tx) {
+            Ok(tx) => tx,
+            Err(e) => return Box::new(future::err(format!("Invalid deposit tx: {}", e))),
+        };
+        let rpc = self.rpc.clone();
+        Box::new(
+            self.watch_deposit(&tx)
+                .join(self.sign(signed_deposit_tx))
+                .and_then(|(_, signed)| {
+                    if signed.complete {
+                        Ok(signed.hex)
+                    } else {
+                        Err("Deposit tx is missing signatures".to_string())
+                    }
+                })
+                .and_then(move |hex| {
+                    rpc.call::<String>("sendrawtransaction", json!([hex]))
+                        .and_then(move |_| Vec::from_hex(&hex).map_err(|e| e.to_string()))
+                }),
+        )
+    }
+
+    fn sign_payout_tx(&self, trade: &Trade, payout_tx: &[u8]) -> WalletFuture<Vec<u8>> {
+        let (tx, script, value) = match payout_to_sign(trade, payout_tx) {
+            Ok(payout) => payout,
+            Err(e) => return Box::new(future::err(e)),
+        };
+        let deposit = json!({
+            "txid": tx.input[0].previous_output.txid.to_string(),
+            "vout": tx.input[0].previous_output.vout,
+            "scriptPubKey": script.to_v0_p2wsh().as_bytes().to_hex(),
+            "witnessScript": script.as_bytes().to_hex(),
+            "amount": btc(value),
+        });
+        Box::new(
+            self.rpc
+                .call::<SignedTx>(
+                    "signrawtransactionwithwallet",
+                    json!([payout_tx.to_hex(), [deposit]]),
+                )
+                .and_then(|signed| decode(&signed.hex))
+                // Our signature ends up in the witness, next to the empty dummy and the script
+                .and_then(move |signed| {
+                    signed.input[0]
+                        .witness
+                        .iter()
+                        .find(|item| !item.is_empty() && item[..] != script.as_bytes()[..])
+                        .map(|signature| signature[..signature.len() - 1].to_vec())
+                        .ok_or_else(|| "Our multisig key isn't in the wallet".to_string())
+                }),
+        )
+    }
+
+    fn publish_tx(&self, tx: &[u8]) -> WalletFuture<String> {
+        self.rpc.call("sendrawtransaction", json!([tx.to_hex()]))
+    }
+
+    fn block_height(&self) -> WalletFuture<u32> {
+        self.rpc.call("getblockcount", json!([]))
+    }
+
+    fn find_payout(&self, trade: &Trade) -> WalletFuture<Option<String>> {
+        let deposit = match trade
+            .deposit_tx_id
+            .as_ref()
+            .map(|id| sha256d::Hash::from_hex(id))
+        {
+            Some(Ok(txid)) => OutPoint::new(txid, 0),
+            _ => return Box::new(future::ok(None)),
+        };
+        let rpc = self.rpc.clone();
+        Box::new(
+            self.rpc
+                .call::<Option<Value>>("gettxout", json!([deposit.txid.to_string(), 0, true]))
+                .and_then(move |unspent| -> WalletFuture<Option<String>> {
+                    if unspent.is_some() {
+                        return Box::new(future::ok(None));
+                    }
+                    let lookup = rpc.clone();
+                    Box::new(
+                        rpc.call::<Vec<ListedTx>>(
+                            "listtransactions",
+                            json!(["*", PAYOUT_SCAN_DEPTH, 0, true]),
+                        )
+                        .and_then(move |listed| {
+                            let txids: HashSet<String> =
+                                listed.into_iter().map(|tx| tx.txid).collect();
+                            future::join_all(
+                                txids
+                                    .into_iter()
+                                    .map(|txid| {
+                                        lookup
+                                            .call::<WalletTx>("gettransaction", json!([txid, true]))
+                                            .and_then(|tx| decode(&tx.hex))
+                                    })
+                                    .collect::<Vec<_>>(),
+                            )
+                        })
+                        .map(move |txs| {
+                            txs.into_iter()
+                                .find(|tx| {
+                                    tx.input
+                                        .iter()
+                                        .any(|input| input.previous_output == deposit)
+                                })
+                                .map(|tx| tx.txid().to_string())
+                        }),
+                    )
+                }),
+        )
+    }
+
+    fn balance(&self) -> WalletFuture<u64> {
+        Box::new(
+            self.rpc
+                .call("getbalance", json!(["*", 0]))
+                .map(|balance: f64| sats(balance)),
+        )
+    }
+
+    fn fee_rate(&self) -> WalletFuture<u64> {
+        let wallet = self.clone();
+        fee_rate(&self.fee_rates, self.network, move || {
+            wallet.estimate_fee_rate()
+        })
+    }
+
+    fn receive_address(&self) -> WalletFuture<String> {
+        self.rpc.call("getnewaddress", json!([]))
+    }
+}
+
+fn decode(hex: &str) -> Result<Transaction, String> {
+    let bytes = Vec::from_hex(hex).map_err(|e| e.to_string())?;
+    consensus::deserialize(&bytes).map_err(|e| e.to_string())
+}
+
+fn btc(sats: u64) -> f64 {
+    sats as f64 / 100_000_000.0
+}
+
+fn sats(btc: f64) -> u64 {
+    (btc * 100_000_000.0).round() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        env,
+        io::{Read, Write},
+        net::{Ipv4Addr, TcpListener},
+        process, thread,
+    };
+    use tokio::runtime::current_thread::Runtime;
+
+    // Answers one JSON-RPC request like bitcoind and hands out the request
+    fn bitcoind(status: &'static str, body: &'static str) -> (Url, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 4096];
+            loop {
+                let read = conn.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..read]);
+                let text = String::from_utf8_lossy(&request).to_lowercase();
+                let complete = text.find("\r\n\r\n").is_some_and(|headers_end| {
+                    let content_length = text
+                        .lines()
+                        .find_map(|line| line.strip_prefix("content-length: "))
+                        .map_or(0, |len| len.trim().parse::<usize>().unwrap());
+                    request.len() >= headers_end + 4 + content_length
+                });
+                if read == 0 || complete {
+                    break;
+                }
+            }
+            write!(
+                conn,
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            )
+            .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (url.parse().unwrap(), handle)
+    }
+
+    fn rpc(url: Url, auth: BitcoindAuth) -> Rpc {
+        Rpc {
+            client: Client::new(),
+            url,
+            auth,
+        }
+    }
+
+    fn user_pass() -> BitcoindAuth {
+        BitcoindAuth::UserPass {
+            user: "user".into(),
+            password: "pass".into(),
+        }
+    }
+
+    fn run<T>(future: WalletFuture<T>) -> Result<T, String> {
+        Runtime::new().unwrap().block_on(future)
+    }
+
+    #[test]
+    fn calls_bitcoind_with_the_credentials() {
+        let (url, request) = bitcoind("200 OK", r#"{"result":812345,"error":null,"id":"risq"}"#);
+        let height: Result<u32, String> =
+            run(rpc(url, user_pass()).call("getblockcount", json!([])));
+        assert_eq!(height, Ok(812_345));
+
+        let request = request.join().unwrap();
+        assert!(request.starts_with("POST / HTTP/1.1\r\n"));
+        // base64 of user:pass
+        assert!(request.contains("authorization: Basic dXNlcjpwYXNz\r\n"));
+        let body: Value = serde_json::from_str(request.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(
+            body,
+            json!({"jsonrpc": "1.0", "id": "risq", "method": "getblockcount", "params": []})
+        );
+    }
+
+    #[test]
+    fn maps_bitcoind_errors() {
+        let (url, _) = bitcoind(
+            "500 Internal Server Error",
+            r#"{"result":null,"error":{"code":-5,"message":"No such mempool or blockchain transaction"},"id":"risq"}"#,
+        );
+        let tx: Result<VerboseTx, String> =
+            run(rpc(url, user_pass()).call("getrawtransaction", json!(["ab", true])));
+        assert_eq!(
+            tx.err(),
+            Some("getrawtransaction failed: No such mempool or blockchain transaction".into())
+        );
+
+        let (url, _) = bitcoind("200 OK", r#"{"result":"812345","error":null,"id":"risq"}"#);
+        let height: Result<u32, String> =
+            run(rpc(url, user_pass()).call("getblockcount", json!([])));
+        assert!(height
+            .unwrap_err()
+            .starts_with("Unexpected result of getblockcount: "));
+
+        // bitcoind rejects wrong credentials without a body
+        let (url, _) = bitcoind("401 Unauthorized", "");
+        let height: Result<u32, String> =
+            run(rpc(url, user_pass()).call("getblockcount", json!([])));
+        assert!(height
+            .unwrap_err()
+            .starts_with("Invalid response from bitcoind (401 Unauthorized): "));
+    }
+
+    #[test]
+    fn reads_the_cookie_file() {
+        let path = env::temp_dir().join(format!("risq-bitcoind-cookie-{}", process::id()));
+        let rpc = rpc(
+            "http://localhost:8332".parse().unwrap(),
+            BitcoindAuth::CookieFile(path.clone()),
+        );
+        assert!(rpc
+            .credentials()
+            .unwrap_err()
+            .starts_with("Couldn't read cookie file"));
+        fs::write(&path, "__cookie__:secret\n").unwrap();
+        assert_eq!(
+            rpc.credentials(),
+            Ok(("__cookie__".into(), "secret".into()))
+        );
+        fs::write(&path, "__cookie__").unwrap();
+        assert!(rpc
+            .credentials()
+            .unwrap_err()
+            .starts_with("Malformed cookie file"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn deserializes_results() {
+        let (url, _) = bitcoind(
+            "200 OK",
+            r#"{"result":{"feerate":0.00012,"blocks":6},"error":null,"id":"risq"}"#,
+        );
+        let wallet = BitcoindWallet::new(
+            url,
+            user_pass(),
+            BaseCurrencyNetwork::BtcRegtest,
+            FeeRates::default(),
+        );
+        assert_eq!(run(wallet.estimate_fee_rate()), Ok(Some(12)));
+
+        let tx: VerboseTx = serde_json::from_value(json!({
+            "vout": [
+                {"value": 0.0005, "scriptPubKey": {"address": "bc1q-new"}},
+                {"value": 0.1, "scriptPubKey": {"addresses": ["1-legacy"]}},
+            ],
+        }))
+        .unwrap();
+        assert_eq!(sats(tx.vout[0].value), 50_000);
+        assert_eq!(
+            tx.vout[0].script_pub_key.address.as_deref(),
+            Some("bc1q-new")
+        );
+        assert_eq!(tx.vout[1].script_pub_key.addresses, vec!["1-legacy"]);
+        let signed: SignedTx =
+            serde_json::from_value(json!({"hex": "00", "complete": false, "errors": []})).unwrap();
+        assert!(!signed.complete);
+    }
+}
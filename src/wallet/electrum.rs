@@ -0,0 +1,272 @@
+use super::backend::{ChainBackend, Utxo};
+use crate::{
+    bisq::payload::NodeAddress,
+    domain::trade::wallet::WalletFuture,
+    prelude::{sync::oneshot, *},
+};
+use bitcoin::{consensus, Address, OutPoint, Script, Transaction};
+use bufstream::BufStream;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use socks::Socks5Stream;
+use std::{
+    io::{self, BufRead, Write},
+    net::TcpStream,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Deserialize)]
+struct ListUnspent {
+    tx_hash: String,
+    tx_pos: u32,
+    value: u64,
+}
+
+#[derive(Deserialize)]
+struct HistoryEntry {
+    tx_hash: String,
+}
+
+#[derive(Deserialize)]
+struct Header {
+    height: u32,
+}
+
+/// Client of the JSON-RPC protocol of an Electrum server (electrs, ElectrumX,
+/// Fulcrum) over plain TCP, through tor when a proxy port is given. Requests
+/// share one connection, which is opened again once it broke.
+#[derive(Clone)]
+pub struct Electrum {
+    server: NodeAddress,
+    proxy_port: Option<u16>,
+    connection: Arc<Mutex<Option<BufStream<TcpStream>>>>,
+}
+
+impl Electrum {
+    pub fn new(server: NodeAddress, proxy_port: Option<u16>) -> Self {
+        Self {
+            server,
+            proxy_port,
+            connection: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    // The protocol is line based, so the blocking request runs on a thread of its own
+    fn call<T: DeserializeOwned + Send + 'static>(
+        &self,
+        method: &'static str,
+        params: Vec<Value>,
+    ) -> WalletFuture<T> {
+        let (send, receive) = oneshot::channel();
+        let electrum = self.clone();
+        thread::spawn(move || {
+            let _ = send.send(electrum.blocking_call(method, params).and_then(|result| {
+                serde_json::from_value(result)
+                    .map_err(|e| format!("Invalid response to {}: {}", method, e))
+            }));
+        });
+        Box::new(
+            receive
+                .map_err(|_| "Electrum request was dropped".to_string())
+                .flatten(),
+        )
+    }
+
+    fn blocking_call(&self, method: &str, params: Vec<Value>) -> Result<Value, String> {
+        let mut connection = self
+            .connection
+            .lock()
+            .expect("Corrupted lock in electrum connection");
+        if connection.is_none() {
+            *connection = Some(
+                self.connect()
+                    .map_err(|e| format!("Couldn't connect to {}: {}", self.server, e))?,
+            );
+        }
+        let stream = connection.as_mut().expect("Connected above");
+        match request(stream, method, params) {
+            Ok(response) => response,
+            Err(e) => {
+                *connection = None;
+                Err(format!("Electrum request {} failed: {}", method, e))
+            }
+        }
+    }
+
+    fn connect(&self) -> io::Result<BufStream<TcpStream>> {
+        let address = (self.server.host_name.as_str(), self.server.port as u16);
+        let stream = match self.proxy_port {
+            Some(proxy_port) => {
+                Socks5Stream::connect(("127.0.0.1", proxy_port), address)?.into_inner()
+            }
+            None => TcpStream::connect(address)?,
+        };
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        Ok(BufStream::new(stream))
+    }
+}
+
+// Servers may push notifications of earlier subscriptions in between, they
+// carry no id and are skipped.
+fn request(
+    stream: &mut BufStream<TcpStream>,
+    method: &str,
+    params: Vec<Value>,
+) -> io::Result<Result<Value, String>> {
+    writeln!(
+        stream,
+        "{}",
+        json!({ "jsonrpc": "2.0", "id": 0, "method": method, "params": params })
+    )?;
+    stream.flush()?;
+    loop {
+        let mut line = String::new();
+        if stream.read_line(&mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let response: Value = serde_json::from_str(&line)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if response.get("id") != Some(&json!(0)) {
+            continue;
+        }
+        return Ok(match response.get("error") {
+            Some(error) if !error.is_null() => Err(format!("{} was refused: {}", method, error)),
+            _ => Ok(response.get("result").cloned().unwrap_or(Value::Null)),
+        });
+    }
+}
+
+/// Electrum indexes addresses by the reversed sha256 of their script.
+fn script_hash(script: &Script) -> String {
+    let mut hash = sha256::Hash::hash(script.as_bytes()).into_inner();
+    hash.reverse();
+    hash.to_hex()
+}
+
+fn decode_tx(hex: &str) -> Result<Transaction, String> {
+    let bytes = Vec::from_hex(hex).map_err(|e| e.to_string())?;
+    consensus::deserialize(&bytes).map_err(|e| e.to_string())
+}
+
+impl ChainBackend for Electrum {
+    fn fee_rate(&self, target: u32) -> WalletFuture<Option<f64>> {
+        // BTC per kB, -1 when the server has no estimate
+        Box::new(
+            self.call("blockchain.estimatefee", vec![json!(target)])
+                .map(|btc_per_kb: f64| Some(btc_per_kb * 100_000.0).filter(|rate| *rate > 0.0)),
+        )
+    }
+
+    fn tip_height(&self) -> WalletFuture<u32> {
+        Box::new(
+            self.call("blockchain.headers.subscribe", Vec::new())
+                .map(|header: Header| header.height),
+        )
+    }
+
+    fn address_used(&self, address: &Address) -> WalletFuture<bool> {
+        Box::new(
+            self.call(
+                "blockchain.scripthash.get_history",
+                vec![json!(script_hash(&address.script_pubkey()))],
+            )
+            .map(|history: Vec<HistoryEntry>| !history.is_empty()),
+        )
+    }
+
+    fn utxos(&self, address: &Address) -> WalletFuture<Vec<Utxo>> {
+        Box::new(
+            self.call(
+                "blockchain.scripthash.listunspent",
+                vec![json!(script_hash(&address.script_pubkey()))],
+            )
+            .map(|unspent: Vec<ListUnspent>| {
+                unspent
+                    .into_iter()
+                    .map(|utxo| Utxo {
+                        txid: utxo.tx_hash,
+                        vout: utxo.tx_pos,
+                        value: utxo.value,
+                    })
+                    .collect()
+            }),
+        )
+    }
+
+    fn raw_tx(&self, txid: &str) -> WalletFuture<Transaction> {
+        Box::new(
+            self.call("blockchain.transaction.get", vec![json!(txid)])
+                .and_then(|hex: String| decode_tx(&hex)),
+        )
+    }
+
+    // Electrum has no index of spent outputs, the spending tx is among the
+    // txs of the output's script.
+    fn spending_tx(&self, txid: &str, vout: u32) -> WalletFuture<Option<String>> {
+        let electrum = self.clone();
+        let txid = txid.to_string();
+        Box::new(self.raw_tx(&txid).and_then(move |tx| -> WalletFuture<_> {
+            let output = match tx.output.get(vout as usize) {
+                Some(output) => output,
+                None => return Box::new(future::err(format!("{} has no output {}", txid, vout))),
+            };
+            let outpoint = OutPoint::new(tx.txid(), vout);
+            let fetcher = electrum.clone();
+            Box::new(
+                electrum
+                    .call(
+                        "blockchain.scripthash.get_history",
+                        vec![json!(script_hash(&output.script_pubkey))],
+                    )
+                    .and_then(move |history: Vec<HistoryEntry>| {
+                        future::join_all(
+                            history
+                                .into_iter()
+                                .filter(|entry| entry.tx_hash != txid)
+                                .map(|entry| fetcher.raw_tx(&entry.tx_hash))
+                                .collect::<Vec<_>>(),
+                        )
+                    })
+                    .map(move |txs| {
+                        txs.into_iter()
+                            .find(|tx| {
+                                tx.input
+                                    .iter()
+                                    .any(|input| input.previous_output == outpoint)
+                            })
+                            .map(|tx| tx.txid().to_string())
+                    }),
+            )
+        }))
+    }
+
+    fn broadcast(&self, tx: &Transaction) -> WalletFuture<String> {
+        Box::new(
+            self.call(
+                "blockchain.transaction.broadcast",
+                vec![json!(consensus::serialize(tx).to_hex())],
+            )
+            .map_err(|e| format!("Couldn't broadcast tx: {}", e)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn script_hash_like_electrum() {
+        // The example of the Electrum protocol docs
+        let address = Address::from_str("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa").unwrap();
+        assert_eq!(
+            script_hash(&address.script_pubkey()),
+            "8b01df4e368ea28f8dc0423bcf7a4923e3a12d307c875e47a0cfbf90b5c39161"
+        );
+    }
+}
@@ -0,0 +1,604 @@
+use super::{
+    backend::ChainBackend,
+    keys::{Chain, WalletKeys},
+    *,
+};
+use crate::{
+    bisq::{constants::btc_fee_receiver, payload::RawTransactionInput},
+    domain::trade::wallet::{MakerDeposit, MakerFunding, TakerFunding, WalletFuture},
+    prelude::*,
+};
+use bitcoin::{
+    blockdata::transaction::{SigHashType, TxIn, TxOut},
+    consensus,
+    secp256k1::Message as SighashMessage,
+    util::bip143::SighashComponents,
+    Address, OutPoint, Script,
+};
+use bitcoin_hashes::sha256d;
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    io,
+    path::Path,
+    str::FromStr,
+    sync::Mutex,
+};
+
+/// Unused addresses after the last used one that are checked for funds.
+const GAP_LIMIT: u32 = 20;
+/// Receive addresses that are searched for the multisig key of a trade.
+/// Multisig keys are never funded, so the gap limit doesn't apply to them.
+const KEY_SEARCH_LIMIT: u32 = 1_000;
+// vsizes of P2WPKH inputs and outputs and the tx overhead
+const INPUT_VSIZE: u64 = 68;
+const OUTPUT_VSIZE: u64 = 31;
+const TX_OVERHEAD_VSIZE: u64 = 11;
+
+#[derive(Clone)]
+struct Coin {
+    outpoint: OutPoint,
+    value: u64,
+    chain: Chain,
+    index: u32,
+}
+
+#[derive(Default)]
+struct State {
+    next_index: HashMap<Chain, u32>,
+    /// Reserved for trades, forgotten on restart like bitcoind's `lockunspent`
+    locked: HashSet<OutPoint>,
+}
+
+/// A wallet that keeps its own keys and looks up its coins on an Esplora
+/// or Electrum server, so no full node is needed. Coins are found by scanning the
+/// addresses of the BIP84 account up to the gap limit.
+#[derive(Clone)]
+pub struct EmbeddedWallet {
+    backend: Arc<dyn ChainBackend>,
+    keys: Arc<WalletKeys>,
+    state: Arc<Mutex<State>>,
+    network: BaseCurrencyNetwork,
+    fee_rates: FeeRates,
+}
+
+impl EmbeddedWallet {
+    pub fn open(
+        dir: &Path,
+        password: &str,
+        backend: Arc<dyn ChainBackend>,
+        network: BaseCurrencyNetwork,
+        fee_rates: FeeRates,
+    ) -> io::Result<Self> {
+        Ok(Self {
+            backend,
+            keys: Arc::new(WalletKeys::load_or_generate(dir, password, network)?),
+            state: Arc::new(Mutex::new(State::default())),
+            network,
+            fee_rates,
+        })
+    }
+
+    fn mark_used(&self, chain: Chain, index: u32) {
+        let mut state = self.state.lock().unwrap();
+        let next = state.next_index.entry(chain).or_insert(0);
+        *next = (*next).max(index + 1);
+    }
+
+    fn fresh_index(&self, chain: Chain) -> u32 {
+        let mut state = self.state.lock().unwrap();
+        let next = state.next_index.entry(chain).or_insert(0);
+        *next += 1;
+        *next - 1
+    }
+
+    fn scan_chain(&self, chain: Chain) -> WalletFuture<Vec<Coin>> {
+        let wallet = self.clone();
+        Box::new(future::loop_fn(
+            (0, Vec::new()),
+            move |(start, mut coins): (u32, Vec<Coin>)| {
+                let batch: Vec<_> = (start..start + GAP_LIMIT)
+                    .map(|index| {
+                        let address = wallet.keys.address(chain, index);
+                        let backend = wallet.backend.clone();
+                        wallet
+                            .backend
+                            .address_used(&address)
+                            .and_then(move |used| -> WalletFuture<_> {
+                                if used {
+                                    Box::new(backend.utxos(&address).map(Some))
+                                } else {
+                                    Box::new(future::ok(None))
+                                }
+                            })
+                            .map(move |utxos| (index, utxos))
+                    })
+                    .collect();
+                let wallet = wallet.clone();
+                future::join_all(batch).map(move |results| {
+                    let mut used = false;
+                    for (index, utxos) in results {
+                        let utxos = match utxos {
+                            Some(utxos) => utxos,
+                            None => continue,
+                        };
+                        used = true;
+                        wallet.mark_used(chain, index);
+                        for utxo in utxos {
+                            if let Ok(txid) = sha256d::Hash::from_hex(&utxo.txid) {
+                                coins.push(Coin {
+                                    outpoint: OutPoint::new(txid, utxo.vout),
+                                    value: utxo.value,
+                                    chain,
+                                    index,
+                                });
+                            }
+                        }
+                    }
+                    if used {
+                        Loop::Continue((start + GAP_LIMIT, coins))
+                    } else {
+                        Loop::Break(coins)
+                    }
+                })
+            },
+        ))
+    }
+
+    /// All our unspent coins, including the reserved ones.
+    fn scan(&self) -> WalletFuture<Vec<Coin>> {
+        Box::new(
+            self.scan_chain(Chain::External)
+                .join(self.scan_chain(Chain::Internal))
+                .map(|(mut external, internal)| {
+                    external.extend(internal);
+                    external
+                }),
+        )
+    }
+
+    fn spendable(&self) -> WalletFuture<Vec<Coin>> {
+        let state = self.state.clone();
+        Box::new(self.scan().map(move |coins| {
+            let state = state.lock().unwrap();
+            coins
+                .into_iter()
+                .filter(|coin| !state.locked.contains(&coin.outpoint))
+                .collect()
+        }))
+    }
+
+    fn estimate_fee_rate(&self) -> WalletFuture<Option<u64>> {
+        Box::new(
+            self.backend
+                .fee_rate(FEE_TARGET_BLOCKS)
+                .map(|rate| rate.map(|rate| rate.ceil() as u64)),
+        )
+    }
+
+    fn reserve(&self, value: u64) -> WalletFuture<Reserved> {
+        let wallet = self.clone();
+        Box::new(
+            self.spendable()
+                .and_then(move |coins| {
+                    let selected = select(coins, value, 0, 0)?;
+                    let mut state = wallet.state.lock().unwrap();
+                    state
+                        .locked
+                        .extend(selected.iter().map(|coin| coin.outpoint));
+                    Ok((wallet.clone(), selected))
+                })
+                .and_then(move |(wallet, selected)| {
+                    let parents: Vec<_> = selected
+                        .iter()
+                        .map(|coin| {
+                            let coin = coin.clone();
+                            wallet.backend.raw_tx(&coin.outpoint.txid.to_string()).map(
+                                move |parent| RawTransactionInput {
+                                    index: coin.outpoint.vout as i64,
+                                    value: coin.value as i64,
+                                    parent_transaction: consensus::serialize(&parent),
+                                },
+                            )
+                        })
+                        .collect();
+                    let total: u64 = selected.iter().map(|coin| coin.value).sum();
+                    let change_output_value = total - value;
+                    let change_output_address = if change_output_value < DUST_LIMIT {
+                        None
+                    } else {
+                        let index = wallet.fresh_index(Chain::Internal);
+                        Some(wallet.keys.address(Chain::Internal, index).to_string())
+                    };
+                    future::join_all(parents).map(move |inputs| Reserved {
+                        change_output_value: change_output_address
+                            .as_ref()
+                            .map(|_| change_output_value)
+                            .unwrap_or(0),
+                        change_output_address,
+                        inputs,
+                    })
+                }),
+        )
+    }
+
+    // Called after a scan, so that fresh indexes are really unused
+    fn trade_keys(&self) -> TradeKeys {
+        let multi_sig = self.fresh_index(Chain::External);
+        let payout = self.fresh_index(Chain::External);
+        TradeKeys {
+            multi_sig_pub_key: self.keys.public_key(Chain::External, multi_sig).to_bytes(),
+            payout_address: self.keys.address(Chain::External, payout).to_string(),
+        }
+    }
+
+    fn key_index(&self, pub_key: &[u8]) -> Option<u32> {
+        (0..KEY_SEARCH_LIMIT)
+            .find(|index| self.keys.public_key(Chain::External, *index).to_bytes() == pub_key)
+    }
+
+    /// Pays `value` to `address` from coins that aren't reserved and returns the txid.
+    fn pay(&self, address: &str, value: u64, fee_rate: u64) -> WalletFuture<String> {
+        let script_pubkey = match Address::from_str(address) {
+            Ok(address) => address.script_pubkey(),
+            Err(e) => return Box::new(future::err(format!("Invalid address: {}", e))),
+        };
+        let wallet = self.clone();
+        Box::new(
+            self.spendable()
+                .and_then(move |coins| {
+                    let selected = select(coins, value, fee_rate, 2)?;
+                    let total: u64 = selected.iter().map(|coin| coin.value).sum();
+                    let fee = fee_rate * vsize(selected.len(), 2);
+                    let mut output = vec![TxOut {
+                        value,
+                        script_pubkey,
+                    }];
+                    let change = total - value - fee;
+                    if change >= DUST_LIMIT {
+                        let index = wallet.fresh_index(Chain::Internal);
+                        output.push(TxOut {
+                            value: change,
+                            script_pubkey: wallet
+                                .keys
+                                .address(Chain::Internal, index)
+                                .script_pubkey(),
+                        });
+                    }
+                    let mut tx = Transaction {
+                        version: 2,
+                        lock_time: 0,
+                        input: selected.iter().map(|coin| tx_in(coin.outpoint)).collect(),
+                        output,
+                    };
+                    wallet.sign(&mut tx, &selected)?;
+                    Ok((wallet, tx))
+                })
+                .and_then(|(wallet, tx)| wallet.backend.broadcast(&tx)),
+        )
+    }
+
+    /// Signs the inputs that spend one of `coins`, the others are left alone.
+    fn sign(&self, tx: &mut Transaction, coins: &[Coin]) -> Result<(), String> {
+        let coins: HashMap<OutPoint, &Coin> =
+            coins.iter().map(|coin| (coin.outpoint, coin)).collect();
+        let network = bitcoin_network(self.network);
+        let mut signed = 0;
+        for index in 0..tx.input.len() {
+            let coin = match coins.get(&tx.input[index].previous_output) {
+                Some(coin) => coin,
+                None => continue,
+            };
+            let key = self.keys.private_key(coin.chain, coin.index);
+            let public_key = key.public_key(self.keys.secp());
+            let script_code = Address::p2pkh(&public_key, network).script_pubkey();
+            let sighash =
+                SighashComponents::new(tx).sighash_all(&tx.input[index], &script_code, coin.value);
+            let message = SighashMessage::from_slice(&sighash[..]).expect("Sighash is 32 bytes");
+            let mut signature = self
+                .keys
+                .secp()
+                .sign(&message, &key.key)
+                .serialize_der()
+                .to_vec();
+            signature.push(SigHashType::All as u8);
+            tx.input[index].witness = vec![signature, public_key.to_bytes()];
+            signed += 1;
+        }
+        if signed == 0 {
+            return Err("None of the inputs belong to the wallet".into());
+        }
+        Ok(())
+    }
+
+    fn verify_taker_fee(&self, trade: &Trade, taker: &TakerFunding) -> WalletFuture<()> {
+        let expected = taker_fee(trade);
+        if taker.taker_fee < expected {
+            return Box::new(future::err(format!(
+                "Taker fee of {} sat is below {} sat",
+                taker.taker_fee, expected
+            )));
+        }
+        let receiver = match Address::from_str(btc_fee_receiver(self.network)) {
+            Ok(receiver) => receiver.script_pubkey(),
+            Err(e) => return Box::new(future::err(format!("Invalid fee receiver: {}", e))),
+        };
+        let paid = taker.taker_fee;
+        Box::new(
+            self.backend
+                .raw_tx(&taker.taker_fee_tx_id)
+                .and_then(move |tx| {
+                    let to_receiver: u64 = tx
+                        .output
+                        .iter()
+                        .filter(|output| output.script_pubkey == receiver)
+                        .map(|output| output.value)
+                        .sum();
+                    if to_receiver >= paid {
+                        Ok(())
+                    } else {
+                        Err("Taker fee tx doesn't pay the trade fee".into())
+                    }
+                }),
+        )
+    }
+}
+
+impl TradeWallet for EmbeddedWallet {
+    fn fund_taker(&self, trade: &Trade) -> WalletFuture<TakerFunding> {
+        let trade = trade.clone();
+        let wallet = self.clone();
+        let receiver = btc_fee_receiver(self.network);
+        let taker_fee = taker_fee(&trade);
+        Box::new(self.fee_rate().and_then(move |fee_rate| {
+            let tx_fee = tx_fee(fee_rate);
+            // The fee is only paid once the trade funds are reserved
+            wallet
+                .reserve(deposit_contribution(&trade, tx_fee, true))
+                .and_then(move |reserved| {
+                    let keys = wallet.trade_keys();
+                    wallet
+                        .pay(receiver, taker_fee, fee_rate)
+                        .map(move |taker_fee_tx_id| TakerFunding {
+                            taker_fee_tx_id,
+                            taker_fee,
+                            tx_fee,
+                            inputs: reserved.inputs,
+                            change_output_value: reserved.change_output_value,
+                            change_output_address: reserved.change_output_address,
+                            multi_sig_pub_key: keys.multi_sig_pub_key,
+                            payout_address: keys.payout_address,
+                        })
+                })
+        }))
+    }
+
+    fn pay_maker_fee(&self, maker_fee: u64) -> WalletFuture<MakerFunding> {
+        let wallet = self.clone();
+        let receiver = btc_fee_receiver(self.network);
+        Box::new(self.fee_rate().and_then(move |fee_rate| {
+            wallet
+                .pay(receiver, maker_fee, fee_rate)
+                .map(move |maker_fee_tx_id| MakerFunding {
+                    maker_fee_tx_id,
+                    tx_fee: tx_fee(fee_rate),
+                })
+        }))
+    }
+
+    fn sign_deposit_tx(&self, trade: &Trade, prepared_deposit_tx: &[u8]) -> WalletFuture<Vec<u8>> {
+        let mut tx: Transaction = match consensus::deserialize(prepared_deposit_tx) {
+            Ok(tx) => tx,
+            Err(e) => return Box::new(future::err(format!("Invalid deposit tx: {}", e))),
+        };
+        if let Err(e) = check_deposit_output(trade, &tx) {
+            return Box::new(future::err(e));
+        }
+        let wallet = self.clone();
+        Box::new(self.scan().and_then(move |coins| {
+            wallet.sign(&mut tx, &coins)?;
+            Ok(consensus::serialize(&tx))
+        }))
+    }
+
+    fn prepare_deposit_tx(
+        &self,
+        trade: &Trade,
+        taker: &TakerFunding,
+    ) -> WalletFuture<MakerDeposit> {
+        let mut input = Vec::new();
+        for raw in taker.inputs.iter() {
+            match consensus::deserialize::<Transaction>(&raw.parent_transaction) {
+                Ok(parent) => input.push(tx_in(OutPoint::new(parent.txid(), raw.index as u32))),
+                Err(e) => return Box::new(future::err(format!("Invalid taker input: {}", e))),
+            }
+        }
+        let mut output = vec![];
+        if let Some(address) = &taker.change_output_address {
+            match Address::from_str(address) {
+                Ok(address) => output.push(TxOut {
+                    value: taker.change_output_value,
+                    script_pubkey: address.script_pubkey(),
+                }),
+                Err(e) => return Box::new(future::err(format!("Invalid change address: {}", e))),
+            }
+        }
+        let taker_pub_key = taker.multi_sig_pub_key.clone();
+        let deposit_value = deposit_output_value(trade, taker.tx_fee);
+        let we_buy = trade.direction() == OfferDirection::Buy;
+        let own_deposit = deposit_contribution(trade, taker.tx_fee, false);
+        let wallet = self.clone();
+        Box::new(
+            self.verify_taker_fee(trade, taker)
+                .and_then({
+                    let wallet = wallet.clone();
+                    move |_| wallet.reserve(own_deposit)
+                })
+                .and_then(move |reserved| {
+                    let keys = wallet.trade_keys();
+                    let (buyer, seller) = if we_buy {
+                        (&keys.multi_sig_pub_key, &taker_pub_key)
+                    } else {
+                        (&taker_pub_key, &keys.multi_sig_pub_key)
+                    };
+                    output.insert(
+                        0,
+                        TxOut {
+                            value: deposit_value,
+                            script_pubkey: tx::multi_sig_script(buyer, seller).to_v0_p2wsh(),
+                        },
+                    );
+                    for raw in reserved.inputs.iter() {
+                        let parent: Transaction = consensus::deserialize(&raw.parent_transaction)
+                            .map_err(|e| e.to_string())?;
+                        input.push(tx_in(OutPoint::new(parent.txid(), raw.index as u32)));
+                    }
+                    if let Some(address) = &reserved.change_output_address {
+                        let address = Address::from_str(address).map_err(|e| e.to_string())?;
+                        output.push(TxOut {
+                            value: reserved.change_output_value,
+                            script_pubkey: address.script_pubkey(),
+                        });
+                    }
+                    let tx = Transaction {
+                        version: 2,
+                        lock_time: 0,
+                        input,
+                        output,
+                    };
+                    Ok(MakerDeposit {
+                        prepared_deposit_tx: consensus::serialize(&tx),
+                        inputs: reserved.inputs,
+                        multi_sig_pub_key: keys.multi_sig_pub_key,
+                        payout_address: keys.payout_address,
+                    })
+                }),
+        )
+    }
+
+    fn publish_deposit_tx(&self, _: &Trade, signed_deposit_tx: &[u8]) -> WalletFuture<Vec<u8>> {
+        let mut tx: Transaction = match consensus::deserialize(signed_deposit_tx) {
+            Ok(tx) => tx,
+            Err(e) => return Box::new(future::err(format!("Invalid deposit tx: {}", e))),
+        };
+        let wallet = self.clone();
+        Box::new(
+            self.scan()
+                .and_then(move |coins| {
+                    wallet.sign(&mut tx, &coins)?;
+                    Ok((wallet, tx))
+                })
+                .and_then(|(wallet, tx)| {
+                    wallet
+                        .backend
+                        .broadcast(&tx)
+                        .map(move |_| consensus::serialize(&tx))
+                }),
+        )
+    }
+
+    fn sign_payout_tx(&self, trade: &Trade, payout_tx: &[u8]) -> WalletFuture<Vec<u8>> {
+        let (tx, script, value) = match payout_to_sign(trade, payout_tx) {
+            Ok(payout) => payout,
+            Err(e) => return Box::new(future::err(e)),
+        };
+        let index = match trade
+            .multi_sig_pub_key
+            .as_ref()
+            .and_then(|pub_key| self.key_index(pub_key))
+        {
+            Some(index) => index,
+            None => return Box::new(future::err("Our multisig key isn't in the wallet".into())),
+        };
+        let key = self.keys.private_key(Chain::External, index);
+        let sighash = tx::payout_sighash(&tx, &script, value);
+        Box::new(future::ok(
+            self.keys
+                .secp()
+                .sign(&sighash, &key.key)
+                .serialize_der()
+                .to_vec(),
+        ))
+    }
+
+    fn publish_tx(&self, tx: &[u8]) -> WalletFuture<String> {
+        match consensus::deserialize(tx) {
+            Ok(tx) => self.backend.broadcast(&tx),
+            Err(e) => Box::new(future::err(format!("Invalid tx: {}", e))),
+        }
+    }
+
+    fn block_height(&self) -> WalletFuture<u32> {
+        self.backend.tip_height()
+    }
+
+    fn find_payout(&self, trade: &Trade) -> WalletFuture<Option<String>> {
+        let deposit_tx_id = match trade.deposit_tx_id.as_ref() {
+            Some(id) => id,
+            None => return Box::new(future::ok(None)),
+        };
+        self.backend.spending_tx(deposit_tx_id, 0)
+    }
+
+    fn balance(&self) -> WalletFuture<u64> {
+        Box::new(
+            self.spendable()
+                .map(|coins| coins.iter().map(|coin| coin.value).sum()),
+        )
+    }
+
+    fn fee_rate(&self) -> WalletFuture<u64> {
+        let wallet = self.clone();
+        fee_rate(&self.fee_rates, self.network, move || {
+            wallet.estimate_fee_rate()
+        })
+    }
+
+    fn receive_address(&self) -> WalletFuture<String> {
+        let wallet = self.clone();
+        Box::new(self.scan_chain(Chain::External).map(move |_| {
+            let index = wallet.fresh_index(Chain::External);
+            wallet.keys.address(Chain::External, index).to_string()
+        }))
+    }
+}
+
+/// Largest coins first until `value` and the fee of a tx with `outputs` plus change are covered.
+fn select(
+    mut coins: Vec<Coin>,
+    value: u64,
+    fee_rate: u64,
+    outputs: usize,
+) -> Result<Vec<Coin>, String> {
+    coins.sort_by_key(|coin| Reverse(coin.value));
+    let available: u64 = coins.iter().map(|coin| coin.value).sum();
+    let mut selected = Vec::new();
+    let mut total = 0;
+    for coin in coins {
+        if total >= value + fee_rate * vsize(selected.len(), outputs) {
+            break;
+        }
+        total += coin.value;
+        selected.push(coin);
+    }
+    if total < value + fee_rate * vsize(selected.len(), outputs) {
+        return Err(format!(
+            "Insufficient funds: {} sat needed, {} sat available",
+            value, available
+        ));
+    }
+    Ok(selected)
+}
+
+fn vsize(inputs: usize, outputs: usize) -> u64 {
+    TX_OVERHEAD_VSIZE + inputs as u64 * INPUT_VSIZE + outputs as u64 * OUTPUT_VSIZE
+}
+
+fn tx_in(previous_output: OutPoint) -> TxIn {
+    TxIn {
+        previous_output,
+        script_sig: Script::new(),
+        sequence: 0xFFFF_FFFF,
+        witness: Vec::new(),
+    }
+}
@@ -0,0 +1,174 @@
+use super::backend::{ChainBackend, Utxo};
+use crate::{domain::trade::wallet::WalletFuture, prelude::*};
+use bitcoin::{consensus, Address, Transaction};
+use reqwest::{
+    r#async::{Client, Response},
+    Proxy, Url,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::collections::HashMap;
+
+#[derive(Deserialize)]
+struct AddressStats {
+    chain_stats: TxoStats,
+    mempool_stats: TxoStats,
+}
+impl AddressStats {
+    fn used(&self) -> bool {
+        self.chain_stats.funded_txo_count + self.mempool_stats.funded_txo_count > 0
+    }
+}
+
+#[derive(Deserialize)]
+struct TxoStats {
+    funded_txo_count: u32,
+}
+
+#[derive(Deserialize)]
+struct EsploraUtxo {
+    txid: String,
+    vout: u32,
+    value: u64,
+}
+
+#[derive(Deserialize)]
+struct Outspend {
+    spent: bool,
+    txid: Option<String>,
+}
+
+/// Client of the REST api of an Esplora server (electrs / blockstream.info).
+#[derive(Clone)]
+pub struct Esplora {
+    client: Client,
+    url: Url,
+}
+
+impl Esplora {
+    pub fn new(url: Url, proxy_port: Option<u16>) -> Self {
+        let client = if let Some(proxy_port) = proxy_port {
+            Client::builder()
+                .proxy(
+                    Proxy::http(&format!("socks5h://127.0.0.1:{}", proxy_port))
+                        .expect("Couldn't set proxy"),
+                )
+                .build()
+                .expect("Couldn't create client")
+        } else {
+            Client::new()
+        };
+        Self { client, url }
+    }
+
+    fn endpoint(&self, path: &str) -> String {
+        format!("{}/{}", self.url.as_str().trim_end_matches('/'), path)
+    }
+
+    fn get(&self, path: String) -> impl Future<Item = Response, Error = String> {
+        self.client
+            .get(&self.endpoint(&path))
+            .send()
+            .and_then(Response::error_for_status)
+            .map_err(move |e| format!("Esplora request {} failed: {}", path, e))
+    }
+
+    fn get_json<T: DeserializeOwned + 'static>(&self, path: String) -> WalletFuture<T> {
+        Box::new(self.get(path).and_then(|mut response| {
+            response
+                .json()
+                .map_err(|e| format!("Invalid response from Esplora: {}", e))
+        }))
+    }
+
+    fn get_text(&self, path: String) -> WalletFuture<String> {
+        Box::new(self.get(path).and_then(|mut response| {
+            response
+                .text()
+                .map_err(|e| format!("Invalid response from Esplora: {}", e))
+        }))
+    }
+}
+
+impl ChainBackend for Esplora {
+    fn fee_rate(&self, target: u32) -> WalletFuture<Option<f64>> {
+        Box::new(self.get_json("fee-estimates".into()).map(
+            move |estimates: HashMap<String, f64>| estimates.get(&target.to_string()).cloned(),
+        ))
+    }
+
+    fn tip_height(&self) -> WalletFuture<u32> {
+        Box::new(
+            self.get_text("blocks/tip/height".into())
+                .and_then(|height| {
+                    height
+                        .trim()
+                        .parse()
+                        .map_err(|e| format!("Invalid block height from Esplora: {}", e))
+                }),
+        )
+    }
+
+    fn address_used(&self, address: &Address) -> WalletFuture<bool> {
+        Box::new(
+            self.get_json(format!("address/{}", address))
+                .map(|stats: AddressStats| stats.used()),
+        )
+    }
+
+    fn utxos(&self, address: &Address) -> WalletFuture<Vec<Utxo>> {
+        Box::new(self.get_json(format!("address/{}/utxo", address)).map(
+            |utxos: Vec<EsploraUtxo>| {
+                utxos
+                    .into_iter()
+                    .map(|utxo| Utxo {
+                        txid: utxo.txid,
+                        vout: utxo.vout,
+                        value: utxo.value,
+                    })
+                    .collect()
+            },
+        ))
+    }
+
+    fn raw_tx(&self, txid: &str) -> WalletFuture<Transaction> {
+        Box::new(self.get_text(format!("tx/{}/hex", txid)).and_then(|hex| {
+            let bytes = Vec::from_hex(hex.trim()).map_err(|e| e.to_string())?;
+            consensus::deserialize(&bytes).map_err(|e| e.to_string())
+        }))
+    }
+
+    fn spending_tx(&self, txid: &str, vout: u32) -> WalletFuture<Option<String>> {
+        Box::new(self.get_json(format!("tx/{}/outspend/{}", txid, vout)).map(
+            |outspend: Outspend| {
+                if outspend.spent {
+                    outspend.txid
+                } else {
+                    None
+                }
+            },
+        ))
+    }
+
+    fn broadcast(&self, tx: &Transaction) -> WalletFuture<String> {
+        Box::new(
+            self.client
+                .post(&self.endpoint("tx"))
+                .body(consensus::serialize(tx).to_hex())
+                .send()
+                .map_err(|e| format!("Couldn't broadcast tx: {}", e))
+                .and_then(|mut response| {
+                    let status = response.status();
+                    response
+                        .text()
+                        .map_err(|e| format!("Couldn't broadcast tx: {}", e))
+                        .and_then(move |text| {
+                            if status.is_success() {
+                                Ok(text)
+                            } else {
+                                Err(format!("Tx was rejected: {}", text))
+                            }
+                        })
+                }),
+        )
+    }
+}
@@ -0,0 +1,321 @@
+use super::bitcoin_network;
+use crate::{bisq::constants::BaseCurrencyNetwork, prelude::ToHex};
+use bitcoin::{
+    secp256k1::{All, Secp256k1},
+    util::{
+        bip32::{ChildNumber, ExtendedPrivKey, ExtendedPubKey},
+        key::{PrivateKey, PublicKey},
+    },
+    Address,
+};
+use openssl::{
+    hash::MessageDigest,
+    pkcs5::pbkdf2_hmac,
+    rand::rand_bytes,
+    symm::{decrypt_aead, encrypt_aead, Cipher},
+};
+use std::{fs, io, path::Path};
+
+const SEED_FILE: &str = "seed";
+/// The output descriptors of the receive and change chain, for watch-only
+/// wallets like bitcoind's `importdescriptors`
+const DESCRIPTORS_FILE: &str = "descriptors";
+const SEED_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+const IV_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const KDF_ITERATIONS: usize = 100_000;
+
+/// Receive and change chains of the BIP84 account.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Chain {
+    External = 0,
+    Internal = 1,
+}
+
+/// The HD keys of the embedded wallet, derived like BIP84 (native segwit)
+/// from a seed that is kept encrypted with the wallet password.
+pub struct WalletKeys {
+    account: ExtendedPrivKey,
+    /// `[fingerprint/84'/coin'/0']` of the account key
+    origin: String,
+    network: bitcoin::Network,
+    secp: Secp256k1<All>,
+}
+
+impl WalletKeys {
+    pub fn load_or_generate(
+        dir: &Path,
+        password: &str,
+        network: BaseCurrencyNetwork,
+    ) -> io::Result<Self> {
+        let path = dir.join(SEED_FILE);
+        let seed = if path.exists() {
+            decrypt_seed(&fs::read(path)?, password)?
+        } else {
+            info!("Generating new wallet seed in {:?}", dir);
+            let mut seed = vec![0; SEED_LEN];
+            rand_bytes(&mut seed).map_err(to_io)?;
+            fs::create_dir_all(dir)?;
+            fs::write(path, encrypt_seed(&seed, password)?)?;
+            seed
+        };
+        let keys = Self::from_seed(&seed, network)?;
+        let descriptors_path = dir.join(DESCRIPTORS_FILE);
+        if !descriptors_path.exists() {
+            fs::write(descriptors_path, keys.descriptors().join("\n") + "\n")?;
+        }
+        Ok(keys)
+    }
+
+    fn from_seed(seed: &[u8], network: BaseCurrencyNetwork) -> io::Result<Self> {
+        let network = bitcoin_network(network);
+        let secp = Secp256k1::new();
+        let coin_type = match network {
+            bitcoin::Network::Bitcoin => 0,
+            _ => 1,
+        };
+        let path = [
+            ChildNumber::from_hardened_idx(84).expect("Valid index"),
+            ChildNumber::from_hardened_idx(coin_type).expect("Valid index"),
+            ChildNumber::from_hardened_idx(0).expect("Valid index"),
+        ];
+        let master = ExtendedPrivKey::new_master(network, seed).map_err(to_io)?;
+        let account = master.derive_priv(&secp, &path).map_err(to_io)?;
+        let origin = format!(
+            "[{}/84'/{}'/0']",
+            master.fingerprint(&secp)[..].to_hex(),
+            coin_type
+        );
+        Ok(Self {
+            account,
+            origin,
+            network,
+            secp,
+        })
+    }
+
+    pub fn secp(&self) -> &Secp256k1<All> {
+        &self.secp
+    }
+
+    pub fn private_key(&self, chain: Chain, index: u32) -> PrivateKey {
+        let path = [
+            ChildNumber::from_normal_idx(chain as u32).expect("Valid index"),
+            ChildNumber::from_normal_idx(index).expect("Valid index"),
+        ];
+        self.account
+            .derive_priv(&self.secp, &path)
+            .expect("Normal derivation can't fail")
+            .private_key
+    }
+
+    pub fn public_key(&self, chain: Chain, index: u32) -> PublicKey {
+        self.private_key(chain, index).public_key(&self.secp)
+    }
+
+    pub fn address(&self, chain: Chain, index: u32) -> Address {
+        Address::p2wpkh(&self.public_key(chain, index), self.network)
+    }
+
+    /// `wpkh` descriptors of the receive and change chain, with checksum.
+    pub fn descriptors(&self) -> Vec<String> {
+        let xpub = ExtendedPubKey::from_private(&self.secp, &self.account);
+        [Chain::External, Chain::Internal]
+            .iter()
+            .map(|chain| {
+                let descriptor = format!("wpkh({}{}/{}/*)", self.origin, xpub, *chain as u32);
+                let checksum = descriptor_checksum(&descriptor);
+                format!("{}#{}", descriptor, checksum)
+            })
+            .collect()
+    }
+}
+
+const DESCRIPTOR_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}\
+                                  IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~\
+                                  ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+fn polymod(checksum: u64, value: u64) -> u64 {
+    let top = checksum >> 35;
+    let mut checksum = ((checksum & 0x7_ffff_ffff) << 5) ^ value;
+    for (bit, generator) in [
+        0xf5_dee5_1989,
+        0xa9_fdca_3312,
+        0x1b_ab10_e32d,
+        0x37_06b1_677a,
+        0x64_4d62_6ffd,
+    ]
+    .iter()
+    .enumerate()
+    {
+        if top >> bit & 1 == 1 {
+            checksum ^= generator;
+        }
+    }
+    checksum
+}
+
+/// The checksum of BIP 380 that bitcoind expects after the `#`.
+fn descriptor_checksum(descriptor: &str) -> String {
+    let mut checksum = 1;
+    let mut groups = Vec::new();
+    for c in descriptor.chars() {
+        let position = DESCRIPTOR_CHARSET.find(c).expect("Descriptors are ASCII") as u64;
+        checksum = polymod(checksum, position & 31);
+        groups.push(position >> 5);
+        if groups.len() == 3 {
+            checksum = polymod(checksum, groups[0] * 9 + groups[1] * 3 + groups[2]);
+            groups.clear();
+        }
+    }
+    if !groups.is_empty() {
+        checksum = polymod(
+            checksum,
+            groups.iter().fold(0, |group, class| group * 3 + class),
+        );
+    }
+    for _ in 0..8 {
+        checksum = polymod(checksum, 0);
+    }
+    checksum ^= 1;
+    (0..8)
+        .map(|i| CHECKSUM_CHARSET[(checksum >> (5 * (7 - i)) & 31) as usize] as char)
+        .collect()
+}
+
+// salt | iv | tag | encrypted seed
+fn encrypt_seed(seed: &[u8], password: &str) -> io::Result<Vec<u8>> {
+    let mut salt = [0; SALT_LEN];
+    let mut iv = [0; IV_LEN];
+    rand_bytes(&mut salt).map_err(to_io)?;
+    rand_bytes(&mut iv).map_err(to_io)?;
+    let key = derive_key(password, &salt)?;
+    let mut tag = [0; TAG_LEN];
+    let encrypted =
+        encrypt_aead(Cipher::aes_256_gcm(), &key, Some(&iv), &[], seed, &mut tag).map_err(to_io)?;
+    Ok([&salt[..], &iv, &tag, &encrypted].concat())
+}
+
+fn decrypt_seed(data: &[u8], password: &str) -> io::Result<Vec<u8>> {
+    if data.len() != SALT_LEN + IV_LEN + TAG_LEN + SEED_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Wallet seed file is corrupt",
+        ));
+    }
+    let (salt, rest) = data.split_at(SALT_LEN);
+    let (iv, rest) = rest.split_at(IV_LEN);
+    let (tag, encrypted) = rest.split_at(TAG_LEN);
+    let key = derive_key(password, salt)?;
+    decrypt_aead(Cipher::aes_256_gcm(), &key, Some(iv), &[], encrypted, tag)
+        .map_err(|_| io::Error::new(io::ErrorKind::PermissionDenied, "Wrong wallet password"))
+}
+
+fn derive_key(password: &str, salt: &[u8]) -> io::Result<[u8; 32]> {
+    let mut key = [0; 32];
+    pbkdf2_hmac(
+        password.as_bytes(),
+        salt,
+        KDF_ITERATIONS,
+        MessageDigest::sha256(),
+        &mut key,
+    )
+    .map_err(to_io)?;
+    Ok(key)
+}
+
+fn to_io<E: ToString>(e: E) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    #[test]
+    fn seed_encryption() {
+        let dir = env::temp_dir().join(format!("risq-wallet-{}", process::id()));
+        let keys =
+            WalletKeys::load_or_generate(&dir, "secret", BaseCurrencyNetwork::BtcRegtest).unwrap();
+        let loaded =
+            WalletKeys::load_or_generate(&dir, "secret", BaseCurrencyNetwork::BtcRegtest).unwrap();
+        let wrong = WalletKeys::load_or_generate(&dir, "guess", BaseCurrencyNetwork::BtcRegtest);
+        fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(
+            keys.address(Chain::External, 0),
+            loaded.address(Chain::External, 0)
+        );
+        assert_ne!(
+            keys.address(Chain::External, 0),
+            keys.address(Chain::Internal, 0)
+        );
+        assert!(keys
+            .address(Chain::External, 0)
+            .to_string()
+            .starts_with("bcrt1"));
+        assert_eq!(
+            wrong.err().map(|e| e.kind()),
+            Some(io::ErrorKind::PermissionDenied)
+        );
+    }
+
+    #[test]
+    fn descriptors_with_checksum() {
+        assert_eq!(descriptor_checksum("raw(deadbeef)"), "89f8spxm");
+        assert_eq!(
+            descriptor_checksum("addr(mkmZxiEcEd8ZqjQWVZuC6so5dFMKEFpN2j)"),
+            "02wpgw69"
+        );
+        let keys = WalletKeys::from_seed(&[1; SEED_LEN], BaseCurrencyNetwork::BtcRegtest).unwrap();
+        let descriptors = keys.descriptors();
+        assert!(descriptors[0].starts_with("wpkh(["));
+        assert!(descriptors[0].contains("/84'/1'/0']tpub"));
+        assert!(descriptors[1].contains("/1/*)#"));
+    }
+
+    // https://github.com/bitcoin/bips/blob/master/bip-0084.mediawiki#test-vectors
+    #[test]
+    fn bip84_test_vectors() {
+        use crate::prelude::FromHex;
+
+        // The seed of the mnemonic "abandon abandon ... about" without passphrase
+        let seed = Vec::<u8>::from_hex(
+            "5eb00bbddcf069084889a8ab9155568165f5c453ccb85e70811aaed6f6da5fc1\
+             9a5ac40b389cd370d086206dec8aa6c43daea6690f20ad3d8d48b2d2ce9e38e4",
+        )
+        .unwrap();
+        let keys = WalletKeys::from_seed(&seed, BaseCurrencyNetwork::BtcMainnet).unwrap();
+        assert_eq!(keys.origin, "[73c5da0a/84'/0'/0']");
+        for (chain, index, private_key, public_key, address) in &[
+            (
+                Chain::External,
+                0,
+                "KyZpNDKnfs94vbrwhJneDi77V6jF64PWPF8x5cdJb8ifgg2DUc9d",
+                "0330d54fd0dd420a6e5f8d3624f5f3482cae350f79d5f0753bf5beef9c2d91af3c",
+                "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu",
+            ),
+            (
+                Chain::External,
+                1,
+                "Kxpf5b8p3qX56DKEe5NqWbNUP9MnqoRFzZwHRtsFqhzuvUJsYZCy",
+                "03e775fd51f0dfb8cd865d9ff1cca2a158cf651fe997fdc9fee9c1d3b5e995ea77",
+                "bc1qnjg0jd8228aq7egyzacy8cys3knf9xvrerkf9g",
+            ),
+            (
+                Chain::Internal,
+                0,
+                "KxuoxufJL5csa1Wieb2kp29VNdn92Us8CoaUG3aGtPtcF3AzeXvF",
+                "03025324888e429ab8e3dbaf1f7802648b9cd01e9b418485c5fa4c1b9b5700e1a6",
+                "bc1q8c6fshw2dlwun7ekn9qwf37cu2rn755upcp6el",
+            ),
+        ] {
+            assert_eq!(keys.private_key(*chain, *index).to_wif(), *private_key);
+            assert_eq!(keys.public_key(*chain, *index).to_string(), *public_key);
+            assert_eq!(keys.address(*chain, *index).to_string(), *address);
+        }
+    }
+}
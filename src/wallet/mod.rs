@@ -0,0 +1,205 @@
+mod backend;
+mod bitcoind;
+mod electrum;
+mod embedded;
+mod esplora;
+mod keys;
+pub mod tx;
+
+pub use bitcoind::{BitcoindAuth, BitcoindWallet};
+pub use embedded::EmbeddedWallet;
+
+use crate::{
+    bisq::{
+        constants::BaseCurrencyNetwork,
+        payload::{NodeAddress, RawTransactionInput},
+    },
+    domain::{
+        fees::FeeRates,
+        format::BTC_PRECISION,
+        offer::OfferDirection,
+        trade::{
+            wallet::{TradeWallet, WalletFuture},
+            Trade,
+        },
+        trade_fee::{self, FeeCurrency},
+    },
+    prelude::{future, Future},
+};
+use backend::ChainBackend;
+use bitcoin::{consensus, Script, Transaction};
+use electrum::Electrum;
+use esplora::Esplora;
+use reqwest::Url;
+use std::{io, path::PathBuf, sync::Arc};
+
+/// Roughly the size of a deposit or payout tx, used to turn fee rates into tx fees.
+const TRADE_TX_VSIZE: u64 = 320;
+/// Used when neither the fee service nor the backend can estimate a fee rate.
+const FALLBACK_FEE_RATE: u64 = 20;
+/// Confirmation target of the fee estimation.
+const FEE_TARGET_BLOCKS: u32 = 6;
+const DUST_LIMIT: u64 = 546;
+
+/// Inputs that are locked in the wallet until they are spent by the deposit tx.
+struct Reserved {
+    inputs: Vec<RawTransactionInput>,
+    change_output_value: u64,
+    change_output_address: Option<String>,
+}
+
+/// Our keys and addresses of a trade.
+struct TradeKeys {
+    multi_sig_pub_key: Vec<u8>,
+    payout_address: String,
+}
+
+#[derive(Clone)]
+pub enum WalletConfig {
+    Bitcoind {
+        url: Url,
+        auth: BitcoindAuth,
+    },
+    /// Keys are kept encrypted in `dir`, coins are looked up via `chain`
+    Embedded {
+        dir: PathBuf,
+        password: String,
+        chain: ChainSource,
+    },
+}
+
+/// The server the embedded wallet looks up its coins on.
+#[derive(Clone)]
+pub enum ChainSource {
+    /// The REST api of electrs or blockstream.info
+    Esplora(Url),
+    /// An Electrum server speaking plain TCP, eg. `electrum.example:50001`
+    Electrum(NodeAddress),
+}
+
+pub fn open(
+    config: WalletConfig,
+    network: BaseCurrencyNetwork,
+    proxy_port: Option<u16>,
+    fee_rates: FeeRates,
+) -> io::Result<Arc<dyn TradeWallet>> {
+    Ok(match config {
+        WalletConfig::Bitcoind { url, auth } => {
+            Arc::new(BitcoindWallet::new(url, auth, network, fee_rates))
+        }
+        WalletConfig::Embedded {
+            dir,
+            password,
+            chain,
+        } => {
+            let backend: Arc<dyn ChainBackend> = match chain {
+                ChainSource::Esplora(url) => Arc::new(Esplora::new(url, proxy_port)),
+                ChainSource::Electrum(server) => Arc::new(Electrum::new(server, proxy_port)),
+            };
+            Arc::new(EmbeddedWallet::open(
+                &dir, &password, backend, network, fee_rates,
+            )?)
+        }
+    })
+}
+
+/// Esplora servers of blockstream.info, reachable without TLS via tor.
+/// Without tor a local electrs instance is expected.
+pub fn default_esplora_url(network: BaseCurrencyNetwork, use_tor_proxy: bool) -> &'static str {
+    match (network, use_tor_proxy) {
+        (BaseCurrencyNetwork::BtcMainnet, true) => {
+            "http://explorerzydxu5ecjrkwceayqybizmpjjznk5izmitf2modhcusuqlid.onion/api"
+        }
+        (BaseCurrencyNetwork::BtcTestnet, true) => {
+            "http://explorerzydxu5ecjrkwceayqybizmpjjznk5izmitf2modhcusuqlid.onion/testnet/api"
+        }
+        (BaseCurrencyNetwork::BtcMainnet, false) => "http://127.0.0.1:3000",
+        (BaseCurrencyNetwork::BtcTestnet, false) => "http://127.0.0.1:3001",
+        (BaseCurrencyNetwork::BtcRegtest, _) => "http://127.0.0.1:3002",
+    }
+}
+
+fn bitcoin_network(network: BaseCurrencyNetwork) -> bitcoin::Network {
+    match network {
+        BaseCurrencyNetwork::BtcMainnet => bitcoin::Network::Bitcoin,
+        BaseCurrencyNetwork::BtcTestnet => bitcoin::Network::Testnet,
+        BaseCurrencyNetwork::BtcRegtest => bitcoin::Network::Regtest,
+    }
+}
+
+fn taker_fee(trade: &Trade) -> u64 {
+    let amount = trade.amount.with_precision(BTC_PRECISION).base_amount();
+    trade_fee::taker_fee(amount, FeeCurrency::Btc)
+}
+
+/// The rate of the fee service if it is known, otherwise the estimate of
+/// the wallet backend.
+fn fee_rate(
+    fee_rates: &FeeRates,
+    network: BaseCurrencyNetwork,
+    estimate: impl FnOnce() -> WalletFuture<Option<u64>>,
+) -> WalletFuture<u64> {
+    match fee_rates.current(network) {
+        Some(rate) => Box::new(future::ok(rate.sats_per_vbyte)),
+        None => Box::new(estimate().map(|rate| rate.unwrap_or(FALLBACK_FEE_RATE))),
+    }
+}
+
+fn tx_fee(sats_per_vbyte: u64) -> u64 {
+    sats_per_vbyte * TRADE_TX_VSIZE
+}
+
+/// What we lock in the deposit tx: our security deposit, the trade amount
+/// when we sell and, as taker, the mining fees of the deposit and payout tx.
+fn deposit_contribution(trade: &Trade, tx_fee: u64, taker: bool) -> u64 {
+    let payload = &trade.offer.payload;
+    let own = match trade.direction() {
+        OfferDirection::Buy => payload.buyer_security_deposit as u64,
+        OfferDirection::Sell => {
+            payload.seller_security_deposit as u64
+                + trade.amount.with_precision(BTC_PRECISION).base_amount()
+        }
+    };
+    if taker {
+        own + 2 * tx_fee
+    } else {
+        own
+    }
+}
+
+/// The 2-of-2 output of the deposit tx, which keeps the payout tx fee.
+fn deposit_output_value(trade: &Trade, tx_fee: u64) -> u64 {
+    let payload = &trade.offer.payload;
+    trade.amount.with_precision(BTC_PRECISION).base_amount()
+        + payload.buyer_security_deposit as u64
+        + payload.seller_security_deposit as u64
+        + tx_fee
+}
+
+/// The maker decides about the mining fee, but has to lock the whole trade
+/// in the 2-of-2 output of our keys.
+fn check_deposit_output(trade: &Trade, deposit_tx: &Transaction) -> Result<(), String> {
+    let output = deposit_tx
+        .output
+        .get(tx::DEPOSIT_OUTPUT as usize)
+        .ok_or("Deposit tx has no 2-of-2 output")?;
+    if output.value < deposit_output_value(trade, 0) {
+        return Err("Deposit tx doesn't lock the trade amount and deposits".into());
+    }
+    if output.script_pubkey != tx::trade_script(trade)?.to_v0_p2wsh() {
+        return Err("Deposit tx doesn't pay to our 2-of-2 output".into());
+    }
+    Ok(())
+}
+
+/// The payout tx we are asked to sign, which has to spend the deposit of
+/// the trade, with the script and value of the deposit output.
+fn payout_to_sign(trade: &Trade, payout_tx: &[u8]) -> Result<(Transaction, Script, u64), String> {
+    let tx: Transaction =
+        consensus::deserialize(payout_tx).map_err(|e| format!("Invalid payout tx: {}", e))?;
+    let (deposit, value) = tx::deposit_output(trade)?;
+    if tx.input.len() != 1 || tx.input[0].previous_output != deposit {
+        return Err("Payout tx doesn't spend the deposit".into());
+    }
+    Ok((tx, tx::trade_script(trade)?, value))
+}
@@ -0,0 +1,327 @@
+//! The 2-of-2 multisig transactions both trade roles have to agree on.
+//! Signatures are exchanged DER encoded without the sighash type, like
+//! bisq does.
+
+use crate::{
+    bisq::constants::{self, BaseCurrencyNetwork},
+    domain::{format::BTC_PRECISION, offer::OfferDirection, trade::Trade},
+};
+use bitcoin::{
+    blockdata::{
+        opcodes::all::OP_CHECKMULTISIG,
+        script::Builder,
+        transaction::{SigHashType, TxIn, TxOut},
+    },
+    consensus,
+    secp256k1::{self, Secp256k1, Signature, Verification},
+    util::{bip143::SighashComponents, key::PublicKey},
+    Address, OutPoint, Script, Transaction,
+};
+use std::str::FromStr;
+
+/// Index of the 2-of-2 output in the deposit tx.
+pub const DEPOSIT_OUTPUT: u32 = 0;
+/// How far the lock time of the delayed payout tx may be off from what we
+/// expect, the traders' views of the chain tip can differ by a few blocks.
+pub const LOCK_TIME_TOLERANCE: u32 = 10;
+// Lock time 0 and this sequence make a tx final right away
+const FINAL_SEQUENCE: u32 = 0xFFFF_FFFF;
+const BLOCKS_PER_DAY: u32 = 144;
+
+/// Where the delayed payout tx sends the deposit, the same DAO address
+/// that receives the trade fees.
+pub fn donation_address(network: BaseCurrencyNetwork) -> Address {
+    Address::from_str(constants::btc_fee_receiver(network)).expect("Invalid donation address")
+}
+
+/// Blocks after the trade start until the delayed payout tx can be
+/// published, like bisq's `Restrictions.getLockTime`.
+pub fn lock_time_delay(trade: &Trade, network: BaseCurrencyNetwork) -> u32 {
+    if network == BaseCurrencyNetwork::BtcRegtest {
+        5
+    } else if trade.offer.market.non_btc_side().is_crypto() {
+        10 * BLOCKS_PER_DAY
+    } else {
+        20 * BLOCKS_PER_DAY
+    }
+}
+
+/// Witness script of the deposit output. Bisq lists the seller's key first.
+pub fn multi_sig_script(buyer_pub_key: &[u8], seller_pub_key: &[u8]) -> Script {
+    Builder::new()
+        .push_int(2)
+        .push_slice(seller_pub_key)
+        .push_slice(buyer_pub_key)
+        .push_int(2)
+        .push_opcode(OP_CHECKMULTISIG)
+        .into_script()
+}
+
+/// Orders our and the peer's value of a trade as (buyer, seller).
+pub fn buyer_and_seller<T>(trade: &Trade, own: T, peer: T) -> (T, T) {
+    match trade.direction() {
+        OfferDirection::Buy => (own, peer),
+        OfferDirection::Sell => (peer, own),
+    }
+}
+
+/// The multisig keys of the trade as (buyer, seller).
+pub fn multi_sig_keys(trade: &Trade) -> Result<(&[u8], &[u8]), String> {
+    let own = trade
+        .multi_sig_pub_key
+        .as_ref()
+        .ok_or("Our multisig key is missing")?;
+    let peer = trade
+        .peer_multi_sig_pub_key
+        .as_ref()
+        .ok_or("The peer's multisig key is missing")?;
+    Ok(buyer_and_seller(trade, own, peer))
+}
+
+pub fn trade_script(trade: &Trade) -> Result<Script, String> {
+    let (buyer, seller) = multi_sig_keys(trade)?;
+    Ok(multi_sig_script(buyer, seller))
+}
+
+/// The 2-of-2 output of the published deposit tx and its value.
+pub fn deposit_output(trade: &Trade) -> Result<(OutPoint, u64), String> {
+    let deposit_tx: Transaction = consensus::deserialize(
+        trade
+            .deposit_tx
+            .as_ref()
+            .ok_or("The deposit tx is missing")?,
+    )
+    .map_err(|e| format!("Invalid deposit tx: {}", e))?;
+    let value = deposit_tx
+        .output
+        .get(DEPOSIT_OUTPUT as usize)
+        .ok_or("Deposit tx has no 2-of-2 output")?
+        .value;
+    Ok((OutPoint::new(deposit_tx.txid(), DEPOSIT_OUTPUT), value))
+}
+
+/// Payouts of a completed trade as (buyer, seller): the buyer receives the
+/// trade amount, both get their security deposit back.
+pub fn payout_amounts(trade: &Trade) -> (u64, u64) {
+    let payload = &trade.offer.payload;
+    let amount = trade.amount.with_precision(BTC_PRECISION).base_amount();
+    (
+        amount + payload.buyer_security_deposit as u64,
+        payload.seller_security_deposit as u64,
+    )
+}
+
+// Both payouts come from the deposit output, so their sum must fit into it
+fn checked_payout(
+    buyer_payout: u64,
+    seller_payout: u64,
+    deposit_value: u64,
+) -> Result<u64, String> {
+    match buyer_payout.checked_add(seller_payout) {
+        Some(total) if total <= deposit_value => Ok(total),
+        _ => Err("Payouts exceed the deposit".into()),
+    }
+}
+
+/// Spends the deposit output to the payout addresses of both traders.
+/// What the payouts leave of the deposit output is the mining fee, which
+/// the taker added when funding the deposit.
+pub fn payout_tx(
+    trade: &Trade,
+    buyer_payout: u64,
+    seller_payout: u64,
+) -> Result<Transaction, String> {
+    let (deposit, deposit_value) = deposit_output(trade)?;
+    checked_payout(buyer_payout, seller_payout, deposit_value)?;
+    let own = trade
+        .payout_address
+        .as_ref()
+        .ok_or("Our payout address is missing")?;
+    let peer = trade
+        .peer_payout_address
+        .as_ref()
+        .ok_or("The peer's payout address is missing")?;
+    let (buyer_address, seller_address) = buyer_and_seller(trade, own, peer);
+    let mut output = Vec::new();
+    for (address, value) in [
+        (buyer_address, buyer_payout),
+        (seller_address, seller_payout),
+    ]
+    .iter()
+    {
+        if *value == 0 {
+            continue;
+        }
+        let address = Address::from_str(address)
+            .map_err(|e| format!("Invalid payout address {}: {}", address, e))?;
+        output.push(TxOut {
+            value: *value,
+            script_pubkey: address.script_pubkey(),
+        });
+    }
+    Ok(Transaction {
+        version: 1,
+        lock_time: 0,
+        input: vec![TxIn {
+            previous_output: deposit,
+            script_sig: Script::new(),
+            sequence: FINAL_SEQUENCE,
+            witness: Vec::new(),
+        }],
+        output,
+    })
+}
+
+/// Sends the deposit to the donation address once `lock_time` is reached,
+/// the refund agent then pays the traders out of the DAO's funds. It pays
+/// the same mining fee as the payout tx.
+pub fn delayed_payout_tx(
+    trade: &Trade,
+    donation_address: &Address,
+    lock_time: u32,
+) -> Result<Transaction, String> {
+    let (deposit, deposit_value) = deposit_output(trade)?;
+    let (buyer_payout, seller_payout) = payout_amounts(trade);
+    let total = checked_payout(buyer_payout, seller_payout, deposit_value)?;
+    Ok(Transaction {
+        version: 1,
+        lock_time,
+        input: vec![TxIn {
+            previous_output: deposit,
+            script_sig: Script::new(),
+            sequence: FINAL_SEQUENCE - 1,
+            witness: Vec::new(),
+        }],
+        output: vec![TxOut {
+            value: total,
+            script_pubkey: donation_address.script_pubkey(),
+        }],
+    })
+}
+
+/// What both traders sign to spend the deposit output.
+pub fn payout_sighash(
+    payout_tx: &Transaction,
+    script: &Script,
+    deposit_value: u64,
+) -> secp256k1::Message {
+    let sighash =
+        SighashComponents::new(payout_tx).sighash_all(&payout_tx.input[0], script, deposit_value);
+    secp256k1::Message::from_slice(&sighash[..]).expect("Sighash is 32 bytes")
+}
+
+pub fn verify_signature<C: Verification>(
+    secp: &Secp256k1<C>,
+    sighash: &secp256k1::Message,
+    signature: &[u8],
+    pub_key: &[u8],
+) -> bool {
+    match (
+        Signature::from_der(signature),
+        PublicKey::from_slice(pub_key),
+    ) {
+        (Ok(signature), Ok(pub_key)) => secp.verify(sighash, &signature, &pub_key.key).is_ok(),
+        _ => false,
+    }
+}
+
+/// Completes the payout tx with the signatures of both traders.
+pub fn finalize_payout(
+    payout_tx: &mut Transaction,
+    script: &Script,
+    buyer_signature: &[u8],
+    seller_signature: &[u8],
+) {
+    let with_sighash_type = |signature: &[u8]| {
+        let mut signature = signature.to_vec();
+        signature.push(SigHashType::All as u8);
+        signature
+    };
+    // The empty element is popped by the off-by-one bug of OP_CHECKMULTISIG
+    payout_tx.input[0].witness = vec![
+        Vec::new(),
+        with_sighash_type(seller_signature),
+        with_sighash_type(buyer_signature),
+        script.to_bytes(),
+    ];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::secp256k1::SecretKey;
+
+    #[test]
+    fn payout_signatures() {
+        let secp = Secp256k1::new();
+        let buyer_key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let seller_key = SecretKey::from_slice(&[2; 32]).unwrap();
+        let buyer_pub = secp256k1::PublicKey::from_secret_key(&secp, &buyer_key).serialize();
+        let seller_pub = secp256k1::PublicKey::from_secret_key(&secp, &seller_key).serialize();
+        let script = multi_sig_script(&buyer_pub, &seller_pub);
+        assert_eq!(&script.as_bytes()[2..35], &seller_pub[..]);
+
+        let mut tx = Transaction {
+            version: 1,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::default(),
+                script_sig: Script::new(),
+                sequence: FINAL_SEQUENCE,
+                witness: Vec::new(),
+            }],
+            output: vec![TxOut {
+                value: 90_000,
+                script_pubkey: script.to_v0_p2wsh(),
+            }],
+        };
+        let sighash = payout_sighash(&tx, &script, 100_000);
+        let buyer_signature = secp.sign(&sighash, &buyer_key).serialize_der();
+        let seller_signature = secp.sign(&sighash, &seller_key).serialize_der();
+        assert!(verify_signature(
+            &secp,
+            &sighash,
+            &buyer_signature,
+            &buyer_pub
+        ));
+        assert!(!verify_signature(
+            &secp,
+            &sighash,
+            &buyer_signature,
+            &seller_pub
+        ));
+        assert!(!verify_signature(
+            &secp,
+            &payout_sighash(&tx, &script, 99_999),
+            &buyer_signature,
+            &buyer_pub
+        ));
+
+        finalize_payout(&mut tx, &script, &buyer_signature, &seller_signature);
+        let witness = &tx.input[0].witness;
+        assert_eq!(witness.len(), 4);
+        assert!(witness[0].is_empty());
+        assert_eq!(witness[1][..witness[1].len() - 1], seller_signature[..]);
+        assert_eq!(witness[2].last(), Some(&(SigHashType::All as u8)));
+    }
+
+    #[test]
+    fn donation_addresses() {
+        for network in [
+            BaseCurrencyNetwork::BtcMainnet,
+            BaseCurrencyNetwork::BtcTestnet,
+            BaseCurrencyNetwork::BtcRegtest,
+        ]
+        .iter()
+        {
+            assert!(!donation_address(*network).script_pubkey().is_empty());
+        }
+    }
+
+    #[test]
+    fn overflowing_payouts() {
+        assert_eq!(checked_payout(60_000, 40_000, 100_000), Ok(100_000));
+        assert!(checked_payout(60_000, 40_001, 100_000).is_err());
+        assert!(checked_payout(u64::MAX, 1, 100_000).is_err());
+    }
+}
@@ -32,3 +32,12 @@ teardown() {
   stop_node "bob"
   stop_node "alice"
 }
+
+@test "alice reports bootstrap progress" {
+  start_node "alice" 3101 3111
+
+  retry 10 1 [ "$(node_status alice '.progress')" == "100" ]
+  [ "$(node_status alice '[ .phases[].reached ] | all')" == "true" ]
+
+  stop_node "alice"
+}